@@ -372,6 +372,104 @@ fn gen_instruction_data_impl(registry: &FormatRegistry, fmt: &mut Formatter) {
     fmt.line("}");
 }
 
+/// Generate the binary format support of `InstructionData`.
+///
+/// These methods are only available with the `binary-format` feature:
+///
+/// - `pub(crate) fn encode_operands(&self, &mut enc, &pool)`
+/// - `pub(crate) fn decode_operands(opcode, &mut dec, &mut pool) -> Result<Self, DecodeError>`
+///
+/// The value operands are encoded first, followed by the immediate fields in declaration order.
+fn gen_instruction_data_binary_format(registry: &FormatRegistry, fmt: &mut Formatter) {
+    fmt.line("#[cfg(feature = \"binary-format\")]");
+    fmt.line("impl InstructionData {");
+    fmt.indent(|fmt| {
+        fmt.doc_comment("Encode the operands of this instruction, but not its opcode.");
+        fmt.line("pub(crate) fn encode_operands(&self, enc: &mut binary_format::Encoder, pool: &ir::ValueListPool) {");
+        fmt.indent(|fmt| {
+            fmt.line("match *self {");
+            fmt.indent(|fmt| {
+                for format in registry.iter() {
+                    let mut members = Vec::new();
+                    if format.typevar_operand.is_some() {
+                        members.push(if format.num_value_operands == 1 && !format.has_value_list {
+                            "ref arg"
+                        } else {
+                            "ref args"
+                        });
+                    }
+                    for field in &format.imm_fields {
+                        members.push(field.member);
+                    }
+                    members.push("..");
+                    fmtln!(fmt, "InstructionData::{} {{ {} }} => {{", format.name, members.join(", "));
+                    fmt.indent(|fmt| {
+                        if format.typevar_operand.is_some() {
+                            if format.has_value_list {
+                                fmt.line("enc.value_list(args.as_slice(pool));");
+                            } else if format.num_value_operands == 1 {
+                                fmt.line("binary_format::Encode::encode(arg, enc);");
+                            } else {
+                                for i in 0..format.num_value_operands {
+                                    fmtln!(fmt, "binary_format::Encode::encode(&args[{}], enc);", i);
+                                }
+                            }
+                        }
+                        for field in &format.imm_fields {
+                            fmtln!(fmt, "binary_format::Encode::encode(&{}, enc);", field.member);
+                        }
+                    });
+                    fmtln!(fmt, "}");
+                }
+            });
+            fmt.line("}");
+        });
+        fmt.line("}");
+        fmt.empty_line();
+
+        fmt.doc_comment(r#"
+            Decode the operands of an instruction with the given opcode.
+
+            Entity references are checked against the entity counts known to the decoder.
+        "#);
+        fmt.line("pub(crate) fn decode_operands(");
+        fmt.indent(|fmt| {
+            fmt.line("opcode: Opcode,");
+            fmt.line("dec: &mut binary_format::Decoder,");
+            fmt.line("pool: &mut ir::ValueListPool,");
+        });
+        fmt.line(") -> Result<Self, binary_format::DecodeError> {");
+        fmt.indent(|fmt| {
+            fmt.line("Ok(match opcode.format() {");
+            fmt.indent(|fmt| {
+                for format in registry.iter() {
+                    fmtln!(fmt, "InstructionFormat::{} => InstructionData::{} {{", format.name, format.name);
+                    fmt.indent(|fmt| {
+                        fmt.line("opcode,");
+                        if format.typevar_operand.is_some() {
+                            if format.has_value_list {
+                                fmt.line("args: dec.value_list(pool)?,");
+                            } else if format.num_value_operands == 1 {
+                                fmt.line("arg: binary_format::Decode::decode(dec)?,");
+                            } else {
+                                let args = vec!["binary_format::Decode::decode(dec)?"; format.num_value_operands];
+                                fmtln!(fmt, "args: [{}],", args.join(", "));
+                            }
+                        }
+                        for field in &format.imm_fields {
+                            fmtln!(fmt, "{}: binary_format::Decode::decode(dec)?,", field.member);
+                        }
+                    });
+                    fmt.line("},");
+                }
+            });
+            fmt.line("})");
+        });
+        fmt.line("}");
+    });
+    fmt.line("}");
+}
+
 fn gen_bool_accessor<T: Fn(&Instruction) -> bool>(
     all_inst: &AllInstructions,
     get_attr: T,
@@ -1075,6 +1173,8 @@ pub(crate) fn generate(
     fmt.empty_line();
     gen_instruction_data_impl(format_registry, &mut fmt);
     fmt.empty_line();
+    gen_instruction_data_binary_format(format_registry, &mut fmt);
+    fmt.empty_line();
     gen_opcodes(all_inst, format_registry, &mut fmt);
     gen_type_constraints(all_inst, &mut fmt);
     fmt.update_file(opcode_filename, out_dir)?;
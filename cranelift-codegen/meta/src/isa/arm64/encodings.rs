@@ -0,0 +1,814 @@
+use crate::cdsl::ast::{Apply, Expr, Literal, VarPool};
+use crate::cdsl::encodings::{Encoding, EncodingBuilder};
+use crate::cdsl::instructions::{
+    BoundInstruction, InstSpec, InstructionGroup, InstructionPredicate, InstructionPredicateNode,
+    InstructionPredicateRegistry,
+};
+use crate::cdsl::recipes::{EncodingRecipeNumber, Recipes};
+use crate::cdsl::types::LaneType;
+
+use crate::shared::types::Bool::B1;
+use crate::shared::types::Float::{F32, F64};
+use crate::shared::types::Int::{I16, I32, I64, I8};
+use crate::shared::Definitions as SharedDefinitions;
+
+use super::recipes::RecipeGroup;
+
+fn enc(inst: impl Into<InstSpec>, recipe: EncodingRecipeNumber, bits: u16) -> EncodingBuilder {
+    EncodingBuilder::new(inst.into(), recipe, bits)
+}
+
+pub struct PerCpuModeEncodings<'defs> {
+    pub inst_pred_reg: InstructionPredicateRegistry,
+    pub enc64: Vec<Encoding>,
+    recipes: &'defs Recipes,
+}
+
+impl<'defs> PerCpuModeEncodings<'defs> {
+    fn new(recipes: &'defs Recipes) -> Self {
+        Self {
+            inst_pred_reg: InstructionPredicateRegistry::new(),
+            enc64: Vec::new(),
+            recipes,
+        }
+    }
+    fn add64(&mut self, encoding: EncodingBuilder) {
+        self.enc64
+            .push(encoding.build(self.recipes, &mut self.inst_pred_reg));
+    }
+}
+
+// All A64 instructions are 32 bits wide. The encbits hold the fixed high bits of an instruction,
+// and the recipes fill in the register and immediate fields. The functions below extract the
+// encbits from the base encoding of an instruction as found in the architecture reference
+// manual, for the instruction classes that need it.
+
+/// Bits 31:21 of a data-processing (register) instruction.
+fn rrr_bits(base: u32) -> u16 {
+    (base >> 21) as u16
+}
+
+/// Bits 31:22 of an add/subtract immediate, bitfield or load/store unsigned offset instruction.
+fn imm_bits(base: u32) -> u16 {
+    (base >> 22) as u16
+}
+
+/// Bits 31:16 of the register-to-register instructions and branches to registers.
+fn rr_bits(base: u32) -> u16 {
+    (base >> 16) as u16
+}
+
+pub(crate) fn define<'defs>(
+    shared_defs: &'defs SharedDefinitions,
+    arm64: &InstructionGroup,
+    recipes: &'defs RecipeGroup,
+) -> PerCpuModeEncodings<'defs> {
+    let shared = &shared_defs.instructions;
+    let formats = &shared_defs.format_registry;
+
+    // Shared instructions shorthands.
+    let adjust_sp_down_imm = shared.by_name("adjust_sp_down_imm");
+    let adjust_sp_up_imm = shared.by_name("adjust_sp_up_imm");
+    let band = shared.by_name("band");
+    let band_not = shared.by_name("band_not");
+    let bconst = shared.by_name("bconst");
+    let bint = shared.by_name("bint");
+    let bitcast = shared.by_name("bitcast");
+    let bitrev = shared.by_name("bitrev");
+    let bnot = shared.by_name("bnot");
+    let bor = shared.by_name("bor");
+    let bor_not = shared.by_name("bor_not");
+    let brff = shared.by_name("brff");
+    let brif = shared.by_name("brif");
+    let brnz = shared.by_name("brnz");
+    let brz = shared.by_name("brz");
+    let bxor = shared.by_name("bxor");
+    let bxor_not = shared.by_name("bxor_not");
+    let call = shared.by_name("call");
+    let call_indirect = shared.by_name("call_indirect");
+    let ceil = shared.by_name("ceil");
+    let cls = shared.by_name("cls");
+    let clz = shared.by_name("clz");
+    let copy = shared.by_name("copy");
+    let copy_nop = shared.by_name("copy_nop");
+    let copy_special = shared.by_name("copy_special");
+    let copy_to_ssa = shared.by_name("copy_to_ssa");
+    let ctz = shared.by_name("ctz");
+    let debugtrap = shared.by_name("debugtrap");
+    let fabs = shared.by_name("fabs");
+    let fadd = shared.by_name("fadd");
+    let fcmp = shared.by_name("fcmp");
+    let fcvt_from_sint = shared.by_name("fcvt_from_sint");
+    let fcvt_from_uint = shared.by_name("fcvt_from_uint");
+    let fcvt_to_sint_sat = shared.by_name("fcvt_to_sint_sat");
+    let fcvt_to_uint_sat = shared.by_name("fcvt_to_uint_sat");
+    let fdemote = shared.by_name("fdemote");
+    let fdiv = shared.by_name("fdiv");
+    let ffcmp = shared.by_name("ffcmp");
+    let fill = shared.by_name("fill");
+    let fill_nop = shared.by_name("fill_nop");
+    let floor = shared.by_name("floor");
+    let fma = shared.by_name("fma");
+    let fmax = shared.by_name("fmax");
+    let fmin = shared.by_name("fmin");
+    let fmul = shared.by_name("fmul");
+    let fneg = shared.by_name("fneg");
+    let fpromote = shared.by_name("fpromote");
+    let fsub = shared.by_name("fsub");
+    let func_addr = shared.by_name("func_addr");
+    let iadd = shared.by_name("iadd");
+    let iadd_imm = shared.by_name("iadd_imm");
+    let icmp = shared.by_name("icmp");
+    let icmp_imm = shared.by_name("icmp_imm");
+    let iconst = shared.by_name("iconst");
+    let ifcmp = shared.by_name("ifcmp");
+    let ifcmp_imm = shared.by_name("ifcmp_imm");
+    let ifcmp_sp = shared.by_name("ifcmp_sp");
+    let imul = shared.by_name("imul");
+    let indirect_jump_table_br = shared.by_name("indirect_jump_table_br");
+    let ireduce = shared.by_name("ireduce");
+    let ishl = shared.by_name("ishl");
+    let ishl_imm = shared.by_name("ishl_imm");
+    let istore16 = shared.by_name("istore16");
+    let istore32 = shared.by_name("istore32");
+    let istore8 = shared.by_name("istore8");
+    let isub = shared.by_name("isub");
+    let jump = shared.by_name("jump");
+    let jump_table_base = shared.by_name("jump_table_base");
+    let jump_table_entry = shared.by_name("jump_table_entry");
+    let load = shared.by_name("load");
+    let nearest = shared.by_name("nearest");
+    let regfill = shared.by_name("regfill");
+    let regmove = shared.by_name("regmove");
+    let regspill = shared.by_name("regspill");
+    let return_ = shared.by_name("return");
+    let rotl_imm = shared.by_name("rotl_imm");
+    let rotr = shared.by_name("rotr");
+    let rotr_imm = shared.by_name("rotr_imm");
+    let sdiv = shared.by_name("sdiv");
+    let select = shared.by_name("select");
+    let selectif = shared.by_name("selectif");
+    let sextend = shared.by_name("sextend");
+    let sload16 = shared.by_name("sload16");
+    let sload32 = shared.by_name("sload32");
+    let sload8 = shared.by_name("sload8");
+    let smulhi = shared.by_name("smulhi");
+    let spill = shared.by_name("spill");
+    let sqrt = shared.by_name("sqrt");
+    let srem = shared.by_name("srem");
+    let sshr = shared.by_name("sshr");
+    let sshr_imm = shared.by_name("sshr_imm");
+    let stack_addr = shared.by_name("stack_addr");
+    let store = shared.by_name("store");
+    let symbol_value = shared.by_name("symbol_value");
+    let trap = shared.by_name("trap");
+    let trapif = shared.by_name("trapif");
+    let trueff = shared.by_name("trueff");
+    let trueif = shared.by_name("trueif");
+    let trunc = shared.by_name("trunc");
+    let udiv = shared.by_name("udiv");
+    let uextend = shared.by_name("uextend");
+    let uload16 = shared.by_name("uload16");
+    let uload32 = shared.by_name("uload32");
+    let uload8 = shared.by_name("uload8");
+    let umulhi = shared.by_name("umulhi");
+    let urem = shared.by_name("urem");
+    let ushr = shared.by_name("ushr");
+    let ushr_imm = shared.by_name("ushr_imm");
+
+    // arm64 specific instructions.
+    let arm64_pop = arm64.by_name("arm64_pop");
+    let arm64_pop2 = arm64.by_name("arm64_pop2");
+    let arm64_push = arm64.by_name("arm64_push");
+    let arm64_push2 = arm64.by_name("arm64_push2");
+
+    // Recipes shorthands, prefixed with r_.
+    let r_addsub_imm = recipes.by_name("addsub_imm");
+    let r_adjustsp = recipes.by_name("adjustsp");
+    let r_adjustsp_imm12 = recipes.by_name("adjustsp_imm12");
+    let r_adjustsp_imm24 = recipes.by_name("adjustsp_imm24");
+    let r_b = recipes.by_name("b");
+    let r_bcond = recipes.by_name("bcond");
+    let r_bcondf = recipes.by_name("bcondf");
+    let r_bconst = recipes.by_name("bconst");
+    let r_bfm = recipes.by_name("bfm");
+    let r_call = recipes.by_name("call");
+    let r_call_r = recipes.by_name("call_r");
+    let r_cb = recipes.by_name("cb");
+    let r_copysp = recipes.by_name("copysp");
+    let r_copytossa = recipes.by_name("copytossa");
+    let r_csel = recipes.by_name("csel");
+    let r_cset = recipes.by_name("cset");
+    let r_ctz = recipes.by_name("ctz");
+    let r_debugtrap = recipes.by_name("debugtrap");
+    let r_dp1 = recipes.by_name("dp1");
+    let r_dp2 = recipes.by_name("dp2");
+    let r_fcopy = recipes.by_name("fcopy");
+    let r_fcmp = recipes.by_name("fcmp");
+    let r_fcopytossa = recipes.by_name("fcopytossa");
+    let r_fcscc = recipes.by_name("fcscc");
+    let r_fcset = recipes.by_name("fcset");
+    let r_ffillnull = recipes.by_name("ffillnull");
+    let r_fillnull = recipes.by_name("fillnull");
+    let r_fnaddr = recipes.by_name("fnaddr");
+    let r_fp1 = recipes.by_name("fp1");
+    let r_fp2 = recipes.by_name("fp2");
+    let r_fp3 = recipes.by_name("fp3");
+    let r_fpr_gpr = recipes.by_name("fpr_gpr");
+    let r_frmov = recipes.by_name("frmov");
+    let r_fselect = recipes.by_name("fselect");
+    let r_gpr_fpr = recipes.by_name("gpr_fpr");
+    let r_gvaddr = recipes.by_name("gvaddr");
+    let r_icscc = recipes.by_name("icscc");
+    let r_icscc_imm = recipes.by_name("icscc_imm");
+    let r_indirect_jmp = recipes.by_name("indirect_jmp");
+    let r_jt_base = recipes.by_name("jt_base");
+    let r_jt_entry = recipes.by_name("jt_entry");
+    let r_movk32 = recipes.by_name("movk32");
+    let r_movk64 = recipes.by_name("movk64");
+    let r_movn = recipes.by_name("movn");
+    let r_movz = recipes.by_name("movz");
+    let r_null = recipes.by_name("null");
+    let r_rcmp = recipes.by_name("rcmp");
+    let r_rcmp_imm = recipes.by_name("rcmp_imm");
+    let r_rcmp_sp = recipes.by_name("rcmp_sp");
+    let r_rem = recipes.by_name("rem");
+    let r_ret = recipes.by_name("ret");
+    let r_rmov = recipes.by_name("rmov");
+    let r_rotate_imm = recipes.by_name("rotate_imm");
+    let r_rrr = recipes.by_name("rrr");
+    let r_rrr_zr = recipes.by_name("rrr_zr");
+    let r_rzr = recipes.by_name("rzr");
+    let r_sdiv = recipes.by_name("sdiv");
+    let r_select = recipes.by_name("select");
+    let r_shift_imm = recipes.by_name("shift_imm");
+    let r_spaddr = recipes.by_name("spaddr");
+    let r_stacknull = recipes.by_name("stacknull");
+    let r_trap = recipes.by_name("trap");
+    let r_trapif = recipes.by_name("trapif");
+    let r_udiv = recipes.by_name("udiv");
+
+    // Definitions.
+    let mut e = PerCpuModeEncodings::new(&recipes.recipes);
+
+    // Data-processing (shifted register) instructions. The 64-bit variants have the `sf` bit 31
+    // set.
+    for &(inst, base) in &[
+        (iadd, 0x0b00_0000),
+        (isub, 0x4b00_0000),
+        (band, 0x0a00_0000),
+        (bor, 0x2a00_0000),
+        (bxor, 0x4a00_0000),
+        (band_not, 0x0a20_0000),
+        (bor_not, 0x2a20_0000),
+        (bxor_not, 0x4a20_0000),
+    ] {
+        e.add64(enc(inst.bind(I32), r_rrr, rrr_bits(base)));
+        e.add64(enc(inst.bind(I64), r_rrr, rrr_bits(base | 0x8000_0000)));
+    }
+    for &(inst, base) in &[(band, 0x0a00_0000), (bor, 0x2a00_0000), (bxor, 0x4a00_0000)] {
+        e.add64(enc(inst.bind(B1), r_rrr, rrr_bits(base)));
+    }
+
+    // `mvn` is `orn` with the zero register as first operand.
+    e.add64(enc(bnot.bind(I32), r_rzr, rrr_bits(0x2a20_0000)));
+    e.add64(enc(bnot.bind(I64), r_rzr, rrr_bits(0xaa20_0000)));
+
+    // Add/subtract immediate.
+    e.add64(enc(iadd_imm.bind(I32), r_addsub_imm, imm_bits(0x1100_0000)));
+    e.add64(enc(iadd_imm.bind(I64), r_addsub_imm, imm_bits(0x9100_0000)));
+
+    // Multiplications use `madd` with the zero register as addend.
+    e.add64(enc(imul.bind(I32), r_rrr_zr, rrr_bits(0x1b00_0000)));
+    e.add64(enc(imul.bind(I64), r_rrr_zr, rrr_bits(0x9b00_0000)));
+    e.add64(enc(smulhi.bind(I64), r_rrr_zr, rrr_bits(0x9b40_0000)));
+    e.add64(enc(umulhi.bind(I64), r_rrr_zr, rrr_bits(0x9bc0_0000)));
+
+    // Data-processing (2 source): divisions and shifts by a register.
+    // The encbits are `opcode | (sf << 6)`.
+    for &(ty, sf) in &[(I32, 0), (I64, 1 << 6)] {
+        e.add64(enc(udiv.bind(ty), r_udiv, 0b000010 | sf));
+        e.add64(enc(sdiv.bind(ty), r_sdiv, 0b000011 | sf));
+        e.add64(enc(urem.bind(ty), r_rem, 0b000010 | sf));
+        e.add64(enc(srem.bind(ty), r_rem, 0b000011 | sf));
+
+        // The shift amount is taken modulo the register width, like the clif semantics. Only
+        // the low bits of the amount register are used, so any amount type works.
+        for &(inst, op) in &[
+            (ishl, 0b001000),
+            (ushr, 0b001001),
+            (sshr, 0b001010),
+            (rotr, 0b001011),
+        ] {
+            e.add64(enc(inst.bind(ty).bind(I32), r_dp2, op | sf));
+            e.add64(enc(inst.bind(ty).bind(I64), r_dp2, op | sf));
+        }
+    }
+
+    // Immediate shifts and rotates.
+    e.add64(enc(
+        ishl_imm.bind(I32),
+        r_shift_imm,
+        imm_bits(0x5300_0000) | (1 << 10),
+    ));
+    e.add64(enc(
+        ishl_imm.bind(I64),
+        r_shift_imm,
+        imm_bits(0xd340_0000) | (1 << 10),
+    ));
+    e.add64(enc(ushr_imm.bind(I32), r_shift_imm, imm_bits(0x5300_0000)));
+    e.add64(enc(ushr_imm.bind(I64), r_shift_imm, imm_bits(0xd340_0000)));
+    e.add64(enc(sshr_imm.bind(I32), r_shift_imm, imm_bits(0x1300_0000)));
+    e.add64(enc(sshr_imm.bind(I64), r_shift_imm, imm_bits(0x9340_0000)));
+    e.add64(enc(rotr_imm.bind(I32), r_rotate_imm, rrr_bits(0x1380_0000)));
+    e.add64(enc(rotr_imm.bind(I64), r_rotate_imm, rrr_bits(0x93c0_0000)));
+    e.add64(enc(
+        rotl_imm.bind(I32),
+        r_rotate_imm,
+        rrr_bits(0x1380_0000) | (1 << 11),
+    ));
+    e.add64(enc(
+        rotl_imm.bind(I64),
+        r_rotate_imm,
+        rrr_bits(0x93c0_0000) | (1 << 11),
+    ));
+
+    // Data-processing (1 source): bit counting.
+    // The encbits are `opcode | (sf << 6)`.
+    for &(ty, sf) in &[(I32, 0), (I64, 1 << 6)] {
+        e.add64(enc(bitrev.bind(ty), r_dp1, sf));
+        e.add64(enc(clz.bind(ty), r_dp1, 0b000100 | sf));
+        e.add64(enc(cls.bind(ty), r_dp1, 0b000101 | sf));
+        e.add64(enc(ctz.bind(ty), r_ctz, sf));
+    }
+
+    // Integer extensions use the `uxt*` and `sxt*` aliases of the bitfield moves.
+    for &(inst, base32, base64) in &[
+        (uextend, 0x5300_0000, 0xd340_0000),
+        (sextend, 0x1300_0000, 0x9340_0000),
+    ] {
+        e.add64(enc(
+            inst.bind(I32).bind(I8),
+            r_bfm,
+            imm_bits(base32) | (7 << 10),
+        ));
+        e.add64(enc(
+            inst.bind(I32).bind(I16),
+            r_bfm,
+            imm_bits(base32) | (15 << 10),
+        ));
+        e.add64(enc(
+            inst.bind(I64).bind(I8),
+            r_bfm,
+            imm_bits(base64) | (7 << 10),
+        ));
+        e.add64(enc(
+            inst.bind(I64).bind(I16),
+            r_bfm,
+            imm_bits(base64) | (15 << 10),
+        ));
+        e.add64(enc(
+            inst.bind(I64).bind(I32),
+            r_bfm,
+            imm_bits(base64) | (31 << 10),
+        ));
+    }
+
+    // Booleans are 0 or 1 in registers, so `bint` only needs to clear the unused bits.
+    e.add64(enc(bint.bind(I32).bind(B1), r_bfm, imm_bits(0x5300_0000)));
+    e.add64(enc(bint.bind(I64).bind(B1), r_bfm, imm_bits(0xd340_0000)));
+
+    // Reductions only reinterpret the low bits of the register.
+    for &(to, from) in &[
+        (I32, I64),
+        (I16, I64),
+        (I16, I32),
+        (I8, I64),
+        (I8, I32),
+        (I8, I16),
+    ] {
+        e.add64(enc(ireduce.bind(to).bind(from), r_null, 0));
+    }
+
+    // Integer constants. Small positive and negative numbers take a single instruction, the
+    // other constants are built 16 bits at a time.
+    e.add64(enc(iconst.bind(I32), r_movz, 0x0a5));
+    e.add64(enc(iconst.bind(I32), r_movn, 0x025));
+    e.add64(enc(iconst.bind(I32), r_movk32, 0x0a5));
+    e.add64(enc(iconst.bind(I64), r_movz, 0x1a5));
+    e.add64(enc(iconst.bind(I64), r_movn, 0x125));
+    e.add64(enc(iconst.bind(I64), r_movk64, 0x1a5));
+    e.add64(enc(bconst.bind(B1), r_bconst, 0x0a5));
+
+    // Integer comparisons are subtractions that discard their result.
+    e.add64(enc(icmp.bind(I32), r_icscc, rrr_bits(0x6b00_0000)));
+    e.add64(enc(icmp.bind(I64), r_icscc, rrr_bits(0xeb00_0000)));
+    e.add64(enc(icmp_imm.bind(I32), r_icscc_imm, imm_bits(0x7100_0000)));
+    e.add64(enc(icmp_imm.bind(I64), r_icscc_imm, imm_bits(0xf100_0000)));
+    e.add64(enc(ifcmp.bind(I32), r_rcmp, rrr_bits(0x6b00_0000)));
+    e.add64(enc(ifcmp.bind(I64), r_rcmp, rrr_bits(0xeb00_0000)));
+    e.add64(enc(ifcmp_imm.bind(B1), r_rcmp_imm, imm_bits(0x7100_0000)));
+    e.add64(enc(ifcmp_imm.bind(I32), r_rcmp_imm, imm_bits(0x7100_0000)));
+    e.add64(enc(ifcmp_imm.bind(I64), r_rcmp_imm, imm_bits(0xf100_0000)));
+    e.add64(enc(ifcmp_sp.bind(I64), r_rcmp_sp, rrr_bits(0xeb00_0000)));
+
+    e.add64(enc(trueif, r_cset, 0));
+    e.add64(enc(selectif.bind(I32), r_csel, rrr_bits(0x1a80_0000)));
+    e.add64(enc(selectif.bind(I64), r_csel, rrr_bits(0x9a80_0000)));
+
+    // Selects on a boolean or integer condition. Bit 12 of the encbits tells the recipe to
+    // compare a 64-bit condition.
+    for &(cond_ty, cond_bit) in &[
+        (LaneType::from(B1), 0),
+        (LaneType::from(I32), 0),
+        (LaneType::from(I64), 1 << 12),
+    ] {
+        e.add64(enc(
+            select.bind(I32).bind(cond_ty),
+            r_select,
+            rrr_bits(0x1a80_0000) | cond_bit,
+        ));
+        e.add64(enc(
+            select.bind(I64).bind(cond_ty),
+            r_select,
+            rrr_bits(0x9a80_0000) | cond_bit,
+        ));
+        e.add64(enc(select.bind(F32).bind(cond_ty), r_fselect, cond_bit));
+        e.add64(enc(select.bind(F64).bind(cond_ty), r_fselect, 1 | cond_bit));
+    }
+
+    // Floating point arithmetic.
+    // The encbits are `opcode | (type << 4)` for the 2 source instructions, and
+    // `opcode | (type << 6)` for the 1 source instructions.
+    for &(ty, ftype) in &[(F32, 0), (F64, 1)] {
+        for &(inst, op) in &[
+            (fmul, 0b0000),
+            (fdiv, 0b0001),
+            (fadd, 0b0010),
+            (fsub, 0b0011),
+            (fmax, 0b0100),
+            (fmin, 0b0101),
+        ] {
+            e.add64(enc(inst.bind(ty), r_fp2, op | (ftype << 4)));
+        }
+
+        for &(inst, op) in &[
+            (fabs, 0b000001),
+            (fneg, 0b000010),
+            (sqrt, 0b000011),
+            (nearest, 0b001000),
+            (ceil, 0b001001),
+            (floor, 0b001010),
+            (trunc, 0b001011),
+        ] {
+            e.add64(enc(inst.bind(ty), r_fp1, op | (ftype << 6)));
+        }
+
+        e.add64(enc(fma.bind(ty), r_fp3, ftype));
+    }
+
+    e.add64(enc(fdemote.bind(F32).bind(F64), r_fp1, 0b000100 | (1 << 6)));
+    e.add64(enc(fpromote.bind(F64).bind(F32), r_fp1, 0b000101));
+
+    // Floating point comparisons. `one` and `ueq` need two tests and are legalized.
+    {
+        let mut var_pool = VarPool::new();
+
+        // Helper that creates an instruction predicate for a floating point condition code.
+        let mut fcmp_instp = |bound_inst: &BoundInstruction,
+                              floatcc_field: &'static str|
+         -> InstructionPredicateNode {
+            let x = var_pool.create("x");
+            let y = var_pool.create("y");
+            let cc = Literal::enumerator_for(&shared_defs.imm.floatcc, floatcc_field);
+            Apply::new(
+                bound_inst.clone().into(),
+                vec![Expr::Literal(cc), Expr::Var(x), Expr::Var(y)],
+            )
+            .inst_predicate(&shared_defs.format_registry, &var_pool)
+            .unwrap()
+        };
+
+        for &(ty, ftype) in &[(F32, 0), (F64, 1)] {
+            let fcmp_ty = fcmp.bind(ty);
+            for &cond in &[
+                "ord", "uno", "eq", "ne", "lt", "le", "gt", "ge", "ult", "ule", "ugt", "uge",
+            ] {
+                e.add64(
+                    enc(fcmp_ty.clone(), r_fcscc, ftype).inst_predicate(fcmp_instp(&fcmp_ty, cond)),
+                );
+            }
+        }
+    }
+
+    // Floating point comparisons producing CPU flags, created by the post-optimization pass
+    // from an encoded `fcmp`, so the `one` and `ueq` conditions can't appear.
+    e.add64(enc(ffcmp.bind(F32), r_fcmp, 0));
+    e.add64(enc(ffcmp.bind(F64), r_fcmp, 1));
+    e.add64(enc(trueff, r_fcset, 0));
+
+    // `fmov` between a general purpose and a SIMD&FP register copies the bits unchanged.
+    e.add64(enc(
+        bitcast.bind(F32).bind(I32),
+        r_gpr_fpr,
+        rr_bits(0x1e27_0000),
+    ));
+    e.add64(enc(
+        bitcast.bind(F64).bind(I64),
+        r_gpr_fpr,
+        rr_bits(0x9e67_0000),
+    ));
+    e.add64(enc(
+        bitcast.bind(I32).bind(F32),
+        r_fpr_gpr,
+        rr_bits(0x1e26_0000),
+    ));
+    e.add64(enc(
+        bitcast.bind(I64).bind(F64),
+        r_fpr_gpr,
+        rr_bits(0x9e66_0000),
+    ));
+
+    // Conversions between integers and floats. The float to integer conversions saturate and
+    // convert NaN to 0, which matches the `_sat` variants; the trapping variants are legalized.
+    for &(float_ty, int_ty, base) in &[
+        (F32, I32, 0x1e00_0000),
+        (F64, I32, 0x1e40_0000),
+        (F32, I64, 0x9e00_0000),
+        (F64, I64, 0x9e40_0000),
+    ] {
+        let scvtf = rr_bits(base | 0x0022_0000);
+        let fcvtzs = rr_bits(base | 0x0038_0000);
+        e.add64(enc(
+            fcvt_from_sint.bind(float_ty).bind(int_ty),
+            r_gpr_fpr,
+            scvtf,
+        ));
+        e.add64(enc(
+            fcvt_from_uint.bind(float_ty).bind(int_ty),
+            r_gpr_fpr,
+            scvtf | 1,
+        ));
+        e.add64(enc(
+            fcvt_to_sint_sat.bind(int_ty).bind(float_ty),
+            r_fpr_gpr,
+            fcvtzs,
+        ));
+        e.add64(enc(
+            fcvt_to_uint_sat.bind(int_ty).bind(float_ty),
+            r_fpr_gpr,
+            fcvtzs | 1,
+        ));
+    }
+
+    // Loads and stores.
+    //
+    // The scaled unsigned offset form is tried first, then the unscaled 9-bit signed offset
+    // form, and finally the offset is built in a scratch register.
+    {
+        let f_load = formats.get(formats.by_name("Load"));
+        let f_store = formats.get(formats.by_name("Store"));
+
+        let r_ld = recipes.by_name("ld");
+        let r_ld_unscaled = recipes.by_name("ldUnscaled");
+        let r_ld_large = recipes.by_name("ldLarge");
+        let r_fld = recipes.by_name("fld");
+        let r_fld_unscaled = recipes.by_name("fldUnscaled");
+        let r_fld_large = recipes.by_name("fldLarge");
+        let r_st = recipes.by_name("st");
+        let r_st_unscaled = recipes.by_name("stUnscaled");
+        let r_st_large = recipes.by_name("stLarge");
+        let r_fst = recipes.by_name("fst");
+        let r_fst_unscaled = recipes.by_name("fstUnscaled");
+        let r_fst_large = recipes.by_name("fstLarge");
+
+        for (inst, base) in vec![
+            (load.bind(I32).bind_any(), 0xb940_0000),
+            (load.bind(I64).bind_any(), 0xf940_0000),
+            (uload8.bind(I32).bind_any(), 0x3940_0000),
+            (uload8.bind(I64).bind_any(), 0x3940_0000),
+            (sload8.bind(I32).bind_any(), 0x39c0_0000),
+            (sload8.bind(I64).bind_any(), 0x3980_0000),
+            (uload16.bind(I32).bind_any(), 0x7940_0000),
+            (uload16.bind(I64).bind_any(), 0x7940_0000),
+            (sload16.bind(I32).bind_any(), 0x79c0_0000),
+            (sload16.bind(I64).bind_any(), 0x7980_0000),
+            (uload32.bind(I64), 0xb940_0000),
+            (sload32.bind(I64), 0xb980_0000),
+        ] {
+            let bits = imm_bits(base);
+            let scale = (base >> 30) as usize;
+            e.add64(enc(inst.clone(), r_ld, bits).inst_predicate(
+                InstructionPredicate::new_is_unsigned_int(f_load, "offset", 12 + scale, scale),
+            ));
+            e.add64(enc(inst.clone(), r_ld_unscaled, bits));
+            e.add64(enc(inst, r_ld_large, bits));
+        }
+
+        for &(ty, base) in &[(F32, 0xbd40_0000), (F64, 0xfd40_0000)] {
+            let bits = imm_bits(base);
+            let scale = (base >> 30) as usize;
+            e.add64(enc(load.bind(ty).bind_any(), r_fld, bits).inst_predicate(
+                InstructionPredicate::new_is_unsigned_int(f_load, "offset", 12 + scale, scale),
+            ));
+            e.add64(enc(load.bind(ty).bind_any(), r_fld_unscaled, bits));
+            e.add64(enc(load.bind(ty).bind_any(), r_fld_large, bits));
+        }
+
+        for (inst, base) in vec![
+            (store.bind(I32).bind_any(), 0xb900_0000),
+            (store.bind(I64).bind_any(), 0xf900_0000),
+            (istore8.bind(I32).bind_any(), 0x3900_0000),
+            (istore8.bind(I64).bind_any(), 0x3900_0000),
+            (istore16.bind(I32).bind_any(), 0x7900_0000),
+            (istore16.bind(I64).bind_any(), 0x7900_0000),
+            (istore32.bind(I64).bind_any(), 0xb900_0000),
+        ] {
+            let bits = imm_bits(base);
+            let scale = (base >> 30) as usize;
+            e.add64(enc(inst.clone(), r_st, bits).inst_predicate(
+                InstructionPredicate::new_is_unsigned_int(f_store, "offset", 12 + scale, scale),
+            ));
+            e.add64(enc(inst.clone(), r_st_unscaled, bits));
+            e.add64(enc(inst, r_st_large, bits));
+        }
+
+        for &(ty, base) in &[(F32, 0xbd00_0000), (F64, 0xfd00_0000)] {
+            let bits = imm_bits(base);
+            let scale = (base >> 30) as usize;
+            e.add64(enc(store.bind(ty).bind_any(), r_fst, bits).inst_predicate(
+                InstructionPredicate::new_is_unsigned_int(f_store, "offset", 12 + scale, scale),
+            ));
+            e.add64(enc(store.bind(ty).bind_any(), r_fst_unscaled, bits));
+            e.add64(enc(store.bind(ty).bind_any(), r_fst_large, bits));
+        }
+    }
+
+    // Spills and fills. The store and load sizes match the stack slot sizes.
+    {
+        let r_spill = recipes.by_name("spill");
+        let r_fill = recipes.by_name("fill");
+        let r_regspill = recipes.by_name("regspill");
+        let r_regfill = recipes.by_name("regfill");
+        let r_fspill = recipes.by_name("fspill");
+        let r_ffill = recipes.by_name("ffill");
+        let r_fregspill = recipes.by_name("fregspill");
+        let r_fregfill = recipes.by_name("fregfill");
+
+        for &(ty, store_base, load_base) in &[
+            (LaneType::from(B1), 0x3900_0000, 0x3940_0000),
+            (LaneType::from(I8), 0x3900_0000, 0x3940_0000),
+            (LaneType::from(I16), 0x7900_0000, 0x7940_0000),
+            (LaneType::from(I32), 0xb900_0000, 0xb940_0000),
+            (LaneType::from(I64), 0xf900_0000, 0xf940_0000),
+        ] {
+            e.add64(enc(spill.bind(ty), r_spill, imm_bits(store_base)));
+            e.add64(enc(regspill.bind(ty), r_regspill, imm_bits(store_base)));
+            e.add64(enc(fill.bind(ty), r_fill, imm_bits(load_base)));
+            e.add64(enc(regfill.bind(ty), r_regfill, imm_bits(load_base)));
+        }
+
+        for &(ty, store_base, load_base) in &[
+            (F32, 0xbd00_0000, 0xbd40_0000),
+            (F64, 0xfd00_0000, 0xfd40_0000),
+        ] {
+            e.add64(enc(spill.bind(ty), r_fspill, imm_bits(store_base)));
+            e.add64(enc(regspill.bind(ty), r_fregspill, imm_bits(store_base)));
+            e.add64(enc(fill.bind(ty), r_ffill, imm_bits(load_base)));
+            e.add64(enc(regfill.bind(ty), r_fregfill, imm_bits(load_base)));
+        }
+    }
+
+    // No-op fills, created by late-stage redundant-fill removal.
+    for &ty in &[I64, I32, I16, I8] {
+        e.add64(enc(fill_nop.bind(ty), r_fillnull, 0));
+    }
+    e.add64(enc(fill_nop.bind(B1), r_fillnull, 0));
+    for &ty in &[F64, F32] {
+        e.add64(enc(fill_nop.bind(ty), r_ffillnull, 0));
+    }
+
+    // Stack-slot-to-the-same-stack-slot copy, which is guaranteed to turn into a no-op.
+    let copy_nop_types: [LaneType; 7] = [
+        I64.into(),
+        I32.into(),
+        I16.into(),
+        I8.into(),
+        B1.into(),
+        F64.into(),
+        F32.into(),
+    ];
+    for &ty in &copy_nop_types {
+        e.add64(enc(copy_nop.bind(ty), r_stacknull, 0));
+    }
+
+    // Register copies use `mov`, which is `orr` with the zero register as first operand.
+    for &ty in &[
+        LaneType::from(I8),
+        LaneType::from(I16),
+        LaneType::from(I32),
+        LaneType::from(B1),
+    ] {
+        e.add64(enc(copy.bind(ty), r_rzr, rrr_bits(0x2a00_0000)));
+        e.add64(enc(regmove.bind(ty), r_rmov, rrr_bits(0x2a00_0000)));
+        e.add64(enc(
+            copy_to_ssa.bind(ty),
+            r_copytossa,
+            rrr_bits(0x2a00_0000),
+        ));
+    }
+    e.add64(enc(copy.bind(I64), r_rzr, rrr_bits(0xaa00_0000)));
+    e.add64(enc(regmove.bind(I64), r_rmov, rrr_bits(0xaa00_0000)));
+    e.add64(enc(
+        copy_to_ssa.bind(I64),
+        r_copytossa,
+        rrr_bits(0xaa00_0000),
+    ));
+
+    // Floating point copies. The encbits are the `fmov` opcode and type of the `fp1` recipes.
+    for &(ty, ftype) in &[(F32, 0), (F64, 1)] {
+        e.add64(enc(copy.bind(ty), r_fcopy, ftype << 6));
+        e.add64(enc(regmove.bind(ty), r_frmov, ftype << 6));
+        e.add64(enc(copy_to_ssa.bind(ty), r_fcopytossa, ftype << 6));
+    }
+
+    // Copies involving the stack pointer.
+    e.add64(enc(copy_special, r_copysp, imm_bits(0x9100_0000)));
+
+    // Stack addresses and adjustments.
+    e.add64(enc(stack_addr.bind(I64), r_spaddr, imm_bits(0x9100_0000)));
+    for &(inst, base) in &[
+        (adjust_sp_down_imm, 0xd100_0000),
+        (adjust_sp_up_imm, 0x9100_0000),
+    ] {
+        e.add64(enc(inst, r_adjustsp_imm12, imm_bits(base)));
+        e.add64(enc(inst, r_adjustsp_imm24, imm_bits(base)));
+        e.add64(enc(inst, r_adjustsp, imm_bits(base)));
+    }
+
+    // Pushes and pops, used by the prologue and epilogue.
+    {
+        let r_push = recipes.by_name("push");
+        let r_pop = recipes.by_name("pop");
+        let r_push2 = recipes.by_name("push2");
+        let r_pop2 = recipes.by_name("pop2");
+        let r_fpush = recipes.by_name("fpush");
+        let r_fpop = recipes.by_name("fpop");
+        let r_fpush2 = recipes.by_name("fpush2");
+        let r_fpop2 = recipes.by_name("fpop2");
+
+        e.add64(enc(arm64_push.bind(I64), r_push, imm_bits(0xf800_0000)));
+        e.add64(enc(arm64_pop.bind(I64), r_pop, imm_bits(0xf840_0000)));
+        e.add64(enc(arm64_push2.bind(I64), r_push2, imm_bits(0xa980_0000)));
+        e.add64(enc(arm64_pop2.bind(I64), r_pop2, imm_bits(0xa8c0_0000)));
+        e.add64(enc(arm64_push.bind(F64), r_fpush, imm_bits(0xfc00_0000)));
+        e.add64(enc(arm64_pop.bind(F64), r_fpop, imm_bits(0xfc40_0000)));
+        e.add64(enc(arm64_push2.bind(F64), r_fpush2, imm_bits(0x6d80_0000)));
+        e.add64(enc(arm64_pop2.bind(F64), r_fpop2, imm_bits(0x6cc0_0000)));
+    }
+
+    // Control flow.
+    e.add64(enc(jump, r_b, 0b000101));
+    e.add64(enc(brz.bind(B1), r_cb, 0x34));
+    e.add64(enc(brz.bind(I32), r_cb, 0x34));
+    e.add64(enc(brz.bind(I64), r_cb, 0xb4));
+    e.add64(enc(brnz.bind(B1), r_cb, 0x35));
+    e.add64(enc(brnz.bind(I32), r_cb, 0x35));
+    e.add64(enc(brnz.bind(I64), r_cb, 0xb5));
+    e.add64(enc(brif, r_bcond, 0));
+    e.add64(enc(brff, r_bcondf, 0));
+
+    // `bl` only reaches +/-128 MB, so only colocated callees are called directly, and the
+    // others go through `func_addr` and `blr`.
+    e.add64(enc(call, r_call, 0b100101).inst_predicate(
+        InstructionPredicate::new_is_colocated_func(
+            formats.get(formats.by_name("Call")),
+            "func_ref",
+        ),
+    ));
+    e.add64(enc(call_indirect.bind(I64), r_call_r, rr_bits(0xd63f_0000)));
+    e.add64(enc(return_, r_ret, rr_bits(0xd65f_0000)));
+
+    e.add64(enc(func_addr.bind(I64), r_fnaddr, 0));
+    e.add64(enc(symbol_value.bind(I64), r_gvaddr, 0));
+
+    // Jump tables.
+    e.add64(enc(jump_table_base.bind(I64), r_jt_base, 0));
+    e.add64(enc(
+        jump_table_entry.bind(I64),
+        r_jt_entry,
+        imm_bits(0xb980_0000),
+    ));
+    e.add64(enc(
+        indirect_jump_table_br.bind(I64),
+        r_indirect_jmp,
+        rr_bits(0xd61f_0000),
+    ));
+
+    // Traps.
+    e.add64(enc(trap, r_trap, 0));
+    e.add64(enc(trapif, r_trapif, 0));
+    e.add64(enc(debugtrap, r_debugtrap, 0));
+
+    e
+}
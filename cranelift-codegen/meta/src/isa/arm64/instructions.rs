@@ -0,0 +1,92 @@
+#![allow(non_snake_case)]
+
+use crate::cdsl::formats::FormatRegistry;
+use crate::cdsl::instructions::{
+    AllInstructions, InstructionBuilder as Inst, InstructionGroup, InstructionGroupBuilder,
+};
+use crate::cdsl::operands::create_operand as operand;
+use crate::cdsl::typevar::{TypeSetBuilder, TypeVar};
+
+pub(crate) fn define(
+    mut all_instructions: &mut AllInstructions,
+    format_registry: &FormatRegistry,
+) -> InstructionGroup {
+    let mut ig = InstructionGroupBuilder::new(
+        "arm64",
+        "arm64 specific instruction set",
+        &mut all_instructions,
+        format_registry,
+    );
+
+    let Reg64 = &TypeVar::new(
+        "Reg64",
+        "A 64-bit integer or floating point register value",
+        TypeSetBuilder::new().ints(64..64).floats(64..64).build(),
+    );
+
+    let x = &operand("x", Reg64);
+    let y = &operand("y", Reg64);
+
+    ig.push(
+        Inst::new(
+            "arm64_push",
+            r#"
+    Pushes a value onto the stack.
+
+    Decrements the stack pointer by 16 bytes and stores the specified value
+    at the new top of the stack, keeping the stack pointer 16-byte aligned.
+    "#,
+        )
+        .operands_in(vec![x])
+        .other_side_effects(true)
+        .can_store(true),
+    );
+
+    ig.push(
+        Inst::new(
+            "arm64_pop",
+            r#"
+    Pops a value from the stack.
+
+    Loads a value from the top of the stack and then increments the stack
+    pointer by 16 bytes.
+    "#,
+        )
+        .operands_out(vec![x])
+        .other_side_effects(true)
+        .can_load(true),
+    );
+
+    ig.push(
+        Inst::new(
+            "arm64_push2",
+            r#"
+    Pushes a pair of values onto the stack.
+
+    Decrements the stack pointer by 16 bytes and stores `x` at the new top of
+    the stack with `y` immediately above it.
+    "#,
+        )
+        .operands_in(vec![x, y])
+        .other_side_effects(true)
+        .can_store(true),
+    );
+
+    ig.push(
+        Inst::new(
+            "arm64_pop2",
+            r#"
+    Pops a pair of values from the stack.
+
+    This is the inverse of `arm64_push2`: `x` is loaded from the top of the
+    stack, `y` from the word above it, and then the stack pointer is
+    incremented by 16 bytes.
+    "#,
+        )
+        .operands_out(vec![x, y])
+        .other_side_effects(true)
+        .can_load(true),
+    );
+
+    ig.build()
+}
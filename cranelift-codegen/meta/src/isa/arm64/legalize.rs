@@ -0,0 +1,204 @@
+use crate::cdsl::ast::{var, ExprBuilder, Literal};
+use crate::cdsl::xform::TransformGroupBuilder;
+use crate::shared::types::Float::{F32, F64};
+use crate::shared::types::Int::{I32, I64};
+use crate::shared::Definitions as SharedDefinitions;
+
+pub(crate) fn define(shared: &mut SharedDefinitions) {
+    let mut group = TransformGroupBuilder::new(
+        "arm64_expand",
+        r#"
+    Legalize instructions by expansion.
+
+    Use arm64-specific instructions if needed."#,
+    )
+    .isa("arm64")
+    .chain_with(shared.transform_groups.by_name("expand_flags").id);
+
+    // List of instructions.
+    let insts = &shared.instructions;
+    let band = insts.by_name("band");
+    let band_not = insts.by_name("band_not");
+    let bitcast = insts.by_name("bitcast");
+    let bor = insts.by_name("bor");
+    let fcmp = insts.by_name("fcmp");
+    let fcopysign = insts.by_name("fcopysign");
+    let fcvt_to_sint = insts.by_name("fcvt_to_sint");
+    let fcvt_to_uint = insts.by_name("fcvt_to_uint");
+    let iadd = insts.by_name("iadd");
+    let iconst = insts.by_name("iconst");
+    let imul = insts.by_name("imul");
+    let ireduce = insts.by_name("ireduce");
+    let irsub_imm = insts.by_name("irsub_imm");
+    let isub = insts.by_name("isub");
+    let popcnt = insts.by_name("popcnt");
+    let rotl = insts.by_name("rotl");
+    let rotr = insts.by_name("rotr");
+    let sextend = insts.by_name("sextend");
+    let smulhi = insts.by_name("smulhi");
+    let sshr_imm = insts.by_name("sshr_imm");
+    let uextend = insts.by_name("uextend");
+    let umulhi = insts.by_name("umulhi");
+    let ushr_imm = insts.by_name("ushr_imm");
+
+    let imm = &shared.imm;
+
+    // Conversions from float to int can trap, so the range checks are inserted before a
+    // saturating conversion.
+    group.custom_legalize(fcvt_to_sint, "expand_fcvt_to_int");
+    group.custom_legalize(fcvt_to_uint, "expand_fcvt_to_int");
+
+    let a = var("a");
+    let x = var("x");
+    let y = var("y");
+    let a1 = var("a1");
+    let a2 = var("a2");
+    let a3 = var("a3");
+    let a4 = var("a4");
+
+    // There is only a rotate right instruction, so rotate left by the negated amount. The
+    // register form only looks at the low bits of the rotate amount.
+    let imm64_0 = Literal::constant(&imm.imm64, 0);
+    group.legalize(
+        def!(a = rotl(x, y)),
+        vec![def!(a1 = irsub_imm(y, imm64_0)), def!(a = rotr(x, a1))],
+    );
+
+    // The high half of a 32-bit multiplication is computed with a 64-bit multiplication.
+    let imm64_32 = Literal::constant(&imm.imm64, 32);
+    group.legalize(
+        def!(a = umulhi.I32(x, y)),
+        vec![
+            def!(a1 = uextend.I64(x)),
+            def!(a2 = uextend.I64(y)),
+            def!(a3 = imul(a1, a2)),
+            def!(a4 = ushr_imm(a3, imm64_32)),
+            def!(a = ireduce.I32(a4)),
+        ],
+    );
+    group.legalize(
+        def!(a = smulhi.I32(x, y)),
+        vec![
+            def!(a1 = sextend.I64(x)),
+            def!(a2 = sextend.I64(y)),
+            def!(a3 = imul(a1, a2)),
+            def!(a4 = sshr_imm(a3, imm64_32)),
+            def!(a = ireduce.I32(a4)),
+        ],
+    );
+
+    // Floating point condition codes.
+    //
+    // A single `fcmp` followed by a conditional select covers all the condition codes except
+    // `one` and `ueq`, which need two tests.
+    let floatcc_eq = Literal::enumerator_for(&imm.floatcc, "eq");
+    let floatcc_ord = Literal::enumerator_for(&imm.floatcc, "ord");
+    let floatcc_ueq = Literal::enumerator_for(&imm.floatcc, "ueq");
+    let floatcc_ne = Literal::enumerator_for(&imm.floatcc, "ne");
+    let floatcc_uno = Literal::enumerator_for(&imm.floatcc, "uno");
+    let floatcc_one = Literal::enumerator_for(&imm.floatcc, "one");
+
+    group.legalize(
+        def!(a = fcmp(floatcc_one, x, y)),
+        vec![
+            def!(a1 = fcmp(floatcc_ord, x, y)),
+            def!(a2 = fcmp(floatcc_ne, x, y)),
+            def!(a = band(a1, a2)),
+        ],
+    );
+    group.legalize(
+        def!(a = fcmp(floatcc_ueq, x, y)),
+        vec![
+            def!(a1 = fcmp(floatcc_uno, x, y)),
+            def!(a2 = fcmp(floatcc_eq, x, y)),
+            def!(a = bor(a1, a2)),
+        ],
+    );
+
+    // The shared `fcopysign` expansion needs bitwise operations on floats, so do the bit
+    // manipulation in integer registers instead.
+    let b = var("b");
+    let xi = var("xi");
+    let yi = var("yi");
+    for &(ty, int_ty, sign_bit) in &[
+        (F32, I32, &Literal::constant(&imm.imm64, 0x8000_0000)),
+        (
+            F64,
+            I64,
+            &Literal::constant(&imm.imm64, 0x8000_0000_0000_0000u64 as i64),
+        ),
+    ] {
+        group.legalize(
+            def!(a = fcopysign.ty(x, y)),
+            vec![
+                def!(xi = bitcast.int_ty(x)),
+                def!(yi = bitcast.int_ty(y)),
+                def!(b = iconst.int_ty(sign_bit)),
+                def!(a1 = band_not(xi, b)),
+                def!(a2 = band(yi, b)),
+                def!(a3 = bor(a1, a2)),
+                def!(a = bitcast.ty(a3)),
+            ],
+        );
+    }
+
+    // Population count. AArch64 only has a vector `cnt` instruction, so use the usual bit
+    // twiddling sequence.
+    let v1 = var("v1");
+    let v3 = var("v3");
+    let v4 = var("v4");
+    let v5 = var("v5");
+    let v6 = var("v6");
+    let v7 = var("v7");
+    let v8 = var("v8");
+    let v9 = var("v9");
+    let v10 = var("v10");
+    let v11 = var("v11");
+    let v12 = var("v12");
+    let v13 = var("v13");
+    let v14 = var("v14");
+    let v15 = var("v15");
+    let v16 = var("v16");
+    let c77 = var("c77");
+    #[allow(non_snake_case)]
+    let c0F = var("c0F");
+    let c01 = var("c01");
+
+    let imm64_1 = Literal::constant(&imm.imm64, 1);
+    let imm64_4 = Literal::constant(&imm.imm64, 4);
+    for &(ty, mask77, mask0f, mask01, shift) in &[
+        (
+            I64,
+            0x7777_7777_7777_7777,
+            0x0F0F_0F0F_0F0F_0F0F,
+            0x0101_0101_0101_0101,
+            56,
+        ),
+        (I32, 0x7777_7777, 0x0F0F_0F0F, 0x0101_0101, 24),
+    ] {
+        group.legalize(
+            def!(v16 = popcnt.ty(v1)),
+            vec![
+                def!(v3 = ushr_imm(v1, imm64_1)),
+                def!(c77 = iconst(Literal::constant(&imm.imm64, mask77))),
+                def!(v4 = band(v3, c77)),
+                def!(v5 = isub(v1, v4)),
+                def!(v6 = ushr_imm(v4, imm64_1)),
+                def!(v7 = band(v6, c77)),
+                def!(v8 = isub(v5, v7)),
+                def!(v9 = ushr_imm(v7, imm64_1)),
+                def!(v10 = band(v9, c77)),
+                def!(v11 = isub(v8, v10)),
+                def!(v12 = ushr_imm(v11, imm64_4)),
+                def!(v13 = iadd(v11, v12)),
+                def!(c0F = iconst(Literal::constant(&imm.imm64, mask0f))),
+                def!(v14 = band(v13, c0F)),
+                def!(c01 = iconst(Literal::constant(&imm.imm64, mask01))),
+                def!(v15 = imul(v14, c01)),
+                def!(v16 = ushr_imm(v15, Literal::constant(&imm.imm64, shift))),
+            ],
+        );
+    }
+
+    group.build_and_add_to(&mut shared.transform_groups);
+}
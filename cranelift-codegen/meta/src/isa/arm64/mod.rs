@@ -1,12 +1,18 @@
 use crate::cdsl::cpu_modes::CpuMode;
-use crate::cdsl::instructions::{InstructionGroupBuilder, InstructionPredicateMap};
 use crate::cdsl::isa::TargetIsa;
-use crate::cdsl::recipes::Recipes;
 use crate::cdsl::regs::{IsaRegs, IsaRegsBuilder, RegBankBuilder, RegClassBuilder};
 use crate::cdsl::settings::{SettingGroup, SettingGroupBuilder};
 
+use crate::shared::types::Bool::B1;
+use crate::shared::types::Float::{F32, F64};
+use crate::shared::types::Int::{I16, I32, I64, I8};
 use crate::shared::Definitions as SharedDefinitions;
 
+mod encodings;
+mod instructions;
+mod legalize;
+mod recipes;
+
 fn define_settings(_shared: &SettingGroup) -> SettingGroup {
     let setting = SettingGroupBuilder::new("arm64");
     setting.build()
@@ -49,27 +55,38 @@ pub(crate) fn define(shared_defs: &mut SharedDefinitions) -> TargetIsa {
     let settings = define_settings(&shared_defs.settings);
     let regs = define_registers();
 
-    let inst_group = InstructionGroupBuilder::new(
-        "arm64",
-        "arm64 specific instruction set",
+    let inst_group = instructions::define(
         &mut shared_defs.all_instructions,
         &shared_defs.format_registry,
-    )
-    .build();
+    );
+    legalize::define(shared_defs);
 
     let mut a64 = CpuMode::new("A64");
 
-    // TODO refine these.
+    let expand_flags = shared_defs.transform_groups.by_name("expand_flags");
     let narrow = shared_defs.transform_groups.by_name("narrow");
+    let widen = shared_defs.transform_groups.by_name("widen");
+    let arm64_expand = shared_defs.transform_groups.by_name("arm64_expand");
+
+    a64.legalize_monomorphic(expand_flags);
     a64.legalize_default(narrow);
+    a64.legalize_type(B1, expand_flags);
+    a64.legalize_type(I8, widen);
+    a64.legalize_type(I16, widen);
+    a64.legalize_type(I32, arm64_expand);
+    a64.legalize_type(I64, arm64_expand);
+    a64.legalize_type(F32, arm64_expand);
+    a64.legalize_type(F64, arm64_expand);
 
-    let cpu_modes = vec![a64];
+    let recipes = recipes::define(shared_defs, &regs);
+
+    let encodings = encodings::define(shared_defs, &inst_group, &recipes);
+    a64.set_encodings(encodings.enc64);
+    let encodings_predicates = encodings.inst_pred_reg.extract();
 
-    // TODO implement arm64 recipes.
-    let recipes = Recipes::new();
+    let recipes = recipes.collect();
 
-    // TODO implement arm64 encodings and predicates.
-    let encodings_predicates = InstructionPredicateMap::new();
+    let cpu_modes = vec![a64];
 
     TargetIsa::new(
         "arm64",
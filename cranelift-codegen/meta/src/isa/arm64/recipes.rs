@@ -0,0 +1,1100 @@
+use std::collections::HashMap;
+
+use crate::cdsl::formats::FormatRegistry;
+use crate::cdsl::instructions::InstructionPredicate;
+use crate::cdsl::recipes::{
+    EncodingRecipeBuilder, EncodingRecipeNumber, OperandConstraint, Recipes, Register, Stack,
+};
+use crate::cdsl::regs::IsaRegs;
+use crate::shared::Definitions as SharedDefinitions;
+
+/// An helper to create recipes and use them when defining the ARM64 encodings.
+pub struct RecipeGroup<'formats> {
+    /// Memoized format registry, to pass it to the builders.
+    formats: &'formats FormatRegistry,
+
+    /// The actualy list of recipes explicitly created in this file.
+    pub recipes: Recipes,
+
+    /// Provides fast lookup from a name to an encoding recipe.
+    name_to_recipe: HashMap<String, EncodingRecipeNumber>,
+}
+
+impl<'formats> RecipeGroup<'formats> {
+    fn new(formats: &'formats FormatRegistry) -> Self {
+        Self {
+            formats,
+            recipes: Recipes::new(),
+            name_to_recipe: HashMap::new(),
+        }
+    }
+
+    fn push(&mut self, builder: EncodingRecipeBuilder) {
+        assert!(
+            self.name_to_recipe.get(&builder.name).is_none(),
+            format!("arm64 recipe '{}' created twice", builder.name)
+        );
+        let name = builder.name.clone();
+        let number = self.recipes.push(builder.build(self.formats));
+        self.name_to_recipe.insert(name, number);
+    }
+
+    pub fn by_name(&self, name: &str) -> EncodingRecipeNumber {
+        let number = *self
+            .name_to_recipe
+            .get(name)
+            .expect(&format!("unknown arm64 recipe name {}", name));
+        number
+    }
+
+    pub fn collect(self) -> Recipes {
+        self.recipes
+    }
+}
+
+pub(crate) fn define<'formats>(
+    shared_defs: &'formats SharedDefinitions,
+    regs: &IsaRegs,
+) -> RecipeGroup<'formats> {
+    let formats = &shared_defs.format_registry;
+
+    // Format shorthands.
+    let f_binary = formats.by_name("Binary");
+    let f_binary_imm = formats.by_name("BinaryImm");
+    let f_branch = formats.by_name("Branch");
+    let f_branch_float = formats.by_name("BranchFloat");
+    let f_branch_int = formats.by_name("BranchInt");
+    let f_branch_table_entry = formats.by_name("BranchTableEntry");
+    let f_branch_table_base = formats.by_name("BranchTableBase");
+    let f_call = formats.by_name("Call");
+    let f_call_indirect = formats.by_name("CallIndirect");
+    let f_copy_special = formats.by_name("CopySpecial");
+    let f_copy_to_ssa = formats.by_name("CopyToSsa");
+    let f_float_compare = formats.by_name("FloatCompare");
+    let f_float_cond = formats.by_name("FloatCond");
+    let f_func_addr = formats.by_name("FuncAddr");
+    let f_indirect_jump = formats.by_name("IndirectJump");
+    let f_int_compare = formats.by_name("IntCompare");
+    let f_int_compare_imm = formats.by_name("IntCompareImm");
+    let f_int_cond = formats.by_name("IntCond");
+    let f_int_cond_trap = formats.by_name("IntCondTrap");
+    let f_int_select = formats.by_name("IntSelect");
+    let f_jump = formats.by_name("Jump");
+    let f_load = formats.by_name("Load");
+    let f_multiary = formats.by_name("MultiAry");
+    let f_nullary = formats.by_name("NullAry");
+    let f_reg_fill = formats.by_name("RegFill");
+    let f_reg_spill = formats.by_name("RegSpill");
+    let f_regmove = formats.by_name("RegMove");
+    let f_stack_load = formats.by_name("StackLoad");
+    let f_store = formats.by_name("Store");
+    let f_ternary = formats.by_name("Ternary");
+    let f_trap = formats.by_name("Trap");
+    let f_unary = formats.by_name("Unary");
+    let f_unary_bool = formats.by_name("UnaryBool");
+    let f_unary_global_value = formats.by_name("UnaryGlobalValue");
+    let f_unary_imm = formats.by_name("UnaryImm");
+
+    // Register classes shorthands.
+    let gpr = regs.class_by_name("GPR");
+    let fpr = regs.class_by_name("FPR");
+    let flag = regs.class_by_name("FLAG");
+
+    // Specific register shorthands.
+    let reg_nzcv = Register::new(flag, regs.regunit_by_name(flag, "nzcv"));
+
+    // Definitions.
+    let mut recipes = RecipeGroup::new(&shared_defs.format_registry);
+
+    // Data-processing (register) instructions with an unshifted second operand.
+    // The encbits are the instruction bits 31:21.
+    recipes.push(
+        EncodingRecipeBuilder::new("rrr", f_binary, 4)
+            .operands_in(vec![gpr, gpr])
+            .operands_out(vec![gpr])
+            .clobbers_flags(false)
+            .emit("put_rrr(bits, in_reg0, in_reg1, out_reg0, sink);"),
+    );
+
+    // Multiply with the accumulator fixed to the zero register.
+    // The encbits are the instruction bits 31:21, with bit 15 (`o0`) in bit 11.
+    recipes.push(
+        EncodingRecipeBuilder::new("rrr_zr", f_binary, 4)
+            .operands_in(vec![gpr, gpr])
+            .operands_out(vec![gpr])
+            .clobbers_flags(false)
+            .emit("put_rrrr(bits, in_reg0, in_reg1, ZERO_REG, out_reg0, sink);"),
+    );
+
+    // Data-processing (1 source) instructions.
+    // The encbits are `opcode | (sf << 6)`.
+    recipes.push(
+        EncodingRecipeBuilder::new("dp1", f_unary, 4)
+            .operands_in(vec![gpr])
+            .operands_out(vec![gpr])
+            .clobbers_flags(false)
+            .emit("put_dp1(bits, in_reg0, out_reg0, sink);"),
+    );
+
+    // Count trailing zeros: reverse the bits, then count the leading zeros.
+    recipes.push(
+        EncodingRecipeBuilder::new("ctz", f_unary, 8)
+            .operands_in(vec![gpr])
+            .operands_out(vec![gpr])
+            .clobbers_flags(false)
+            .emit(
+                r#"
+                    // rbit
+                    put_dp1(bits, in_reg0, out_reg0, sink);
+                    // clz
+                    put_dp1(bits | 0b000100, out_reg0, out_reg0, sink);
+                "#,
+            ),
+    );
+
+    // Data-processing (2 source) instructions.
+    // The encbits are `opcode | (sf << 6)`.
+    recipes.push(
+        EncodingRecipeBuilder::new("dp2", f_binary, 4)
+            .operands_in(vec![gpr, gpr])
+            .operands_out(vec![gpr])
+            .clobbers_flags(false)
+            .emit("put_dp2(bits, in_reg0, in_reg1, out_reg0, sink);"),
+    );
+
+    // Unsigned division. The hardware returns zero when dividing by zero, so branch over a trap
+    // when the divisor is non-zero.
+    recipes.push(
+        EncodingRecipeBuilder::new("udiv", f_binary, 12)
+            .operands_in(vec![gpr, gpr])
+            .operands_out(vec![gpr])
+            .clobbers_flags(false)
+            .emit(
+                r#"
+                    let sf = bits >> 6;
+                    // cbnz y, 1f
+                    put_cb(0x35 | (sf << 7), in_reg1, 8, sink);
+                    sink.trap(TrapCode::IntegerDivisionByZero, func.srclocs[inst]);
+                    put_udf(sink);
+                    // 1: udiv
+                    put_dp2(bits, in_reg0, in_reg1, out_reg0, sink);
+                "#,
+            ),
+    );
+
+    // Signed division. Besides the division by zero, INT_MIN / -1 must trap too.
+    recipes.push(
+        EncodingRecipeBuilder::new("sdiv", f_binary, 32)
+            .operands_in(vec![gpr, gpr])
+            .operands_out(vec![gpr])
+            .emit(
+                r#"
+                    let sf = bits >> 6;
+                    // cbnz y, 1f
+                    put_cb(0x35 | (sf << 7), in_reg1, 8, sink);
+                    sink.trap(TrapCode::IntegerDivisionByZero, func.srclocs[inst]);
+                    put_udf(sink);
+                    // 1: cmn y, #1
+                    put_addsub_imm(0xc4 | (sf << 9), in_reg1, 1, ZERO_REG, sink);
+                    // b.ne 2f
+                    put_bcond(COND_NE, 16, sink);
+                    // cmp x, #1: this only overflows for INT_MIN.
+                    put_addsub_imm(0x1c4 | (sf << 9), in_reg0, 1, ZERO_REG, sink);
+                    // b.vc 2f
+                    put_bcond(COND_VC, 8, sink);
+                    sink.trap(TrapCode::IntegerOverflow, func.srclocs[inst]);
+                    put_udf(sink);
+                    // 2: sdiv
+                    put_dp2(bits, in_reg0, in_reg1, out_reg0, sink);
+                "#,
+            ),
+    );
+
+    // Remainders are computed from the quotient with a multiply-subtract. The quotient of
+    // INT_MIN / -1 wraps around and the remainder is 0 as expected, so only the division by zero
+    // traps.
+    recipes.push(
+        EncodingRecipeBuilder::new("rem", f_binary, 16)
+            .operands_in(vec![gpr, gpr])
+            .operands_out(vec![gpr])
+            .clobbers_flags(false)
+            .emit(
+                r#"
+                    let sf = bits >> 6;
+                    // cbnz y, 1f
+                    put_cb(0x35 | (sf << 7), in_reg1, 8, sink);
+                    sink.trap(TrapCode::IntegerDivisionByZero, func.srclocs[inst]);
+                    put_udf(sink);
+                    // 1: [us]div scratch, x, y
+                    put_dp2(bits, in_reg0, in_reg1, SCRATCH_REG, sink);
+                    // msub rd, scratch, y, x
+                    put_rrrr(0x8d8 | (sf << 10), SCRATCH_REG, in_reg1, in_reg0, out_reg0, sink);
+                "#,
+            ),
+    );
+
+    // Add/subtract (immediate). Negative immediates flip the operation.
+    // The encbits are the instruction bits 31:22.
+    let format = formats.get(f_binary_imm);
+    recipes.push(
+        EncodingRecipeBuilder::new("addsub_imm", f_binary_imm, 4)
+            .operands_in(vec![gpr])
+            .operands_out(vec![gpr])
+            .inst_predicate(InstructionPredicate::new_is_signed_int(
+                format, "imm", 12, 0,
+            ))
+            .clobbers_flags(false)
+            .emit("put_addsub_imm(bits, in_reg0, imm.into(), out_reg0, sink);"),
+    );
+
+    // Immediate shifts are aliases of the bitfield move instructions.
+    // The encbits are the instruction bits 31:22, with bit 10 set for left shifts.
+    recipes.push(
+        EncodingRecipeBuilder::new("shift_imm", f_binary_imm, 4)
+            .operands_in(vec![gpr])
+            .operands_out(vec![gpr])
+            .clobbers_flags(false)
+            .emit("put_shift_imm(bits, in_reg0, imm.into(), out_reg0, sink);"),
+    );
+
+    // Immediate rotates are aliases of `extr` with both sources set to the same register.
+    // The encbits are the instruction bits 31:21, with bit 11 set for left rotates.
+    recipes.push(
+        EncodingRecipeBuilder::new("rotate_imm", f_binary_imm, 4)
+            .operands_in(vec![gpr])
+            .operands_out(vec![gpr])
+            .clobbers_flags(false)
+            .emit("put_rotate_imm(bits, in_reg0, imm.into(), out_reg0, sink);"),
+    );
+
+    // Bitfield moves with `immr` = 0, used for extensions.
+    // The encbits are the instruction bits 31:22 | (imms << 10).
+    recipes.push(
+        EncodingRecipeBuilder::new("bfm", f_unary, 4)
+            .operands_in(vec![gpr])
+            .operands_out(vec![gpr])
+            .clobbers_flags(false)
+            .emit("put_bfm(bits, in_reg0, out_reg0, sink);"),
+    );
+
+    // No-op reinterpretation of a register, used for `ireduce`.
+    recipes.push(
+        EncodingRecipeBuilder::new("null", f_unary, 0)
+            .operands_in(vec![gpr])
+            .operands_out(vec![0])
+            .clobbers_flags(false)
+            .emit(""),
+    );
+
+    // Register instructions with the first source fixed to the zero register: `mov` and `mvn`.
+    recipes.push(
+        EncodingRecipeBuilder::new("rzr", f_unary, 4)
+            .operands_in(vec![gpr])
+            .operands_out(vec![gpr])
+            .clobbers_flags(false)
+            .emit("put_rrr(bits, ZERO_REG, in_reg0, out_reg0, sink);"),
+    );
+
+    // Same for a GPR regmove.
+    recipes.push(
+        EncodingRecipeBuilder::new("rmov", f_regmove, 4)
+            .operands_in(vec![gpr])
+            .clobbers_flags(false)
+            .emit("put_rrr(bits, ZERO_REG, src, dst, sink);"),
+    );
+
+    // Same for copy-to-SSA -- GPR regmove.
+    recipes.push(
+        EncodingRecipeBuilder::new("copytossa", f_copy_to_ssa, 4)
+            // No operands_in to mention, because a source register is specified directly.
+            .operands_out(vec![gpr])
+            .clobbers_flags(false)
+            .emit("put_rrr(bits, ZERO_REG, src, out_reg0, sink);"),
+    );
+
+    // Copies involving the stack pointer use `add rd, rn, #0` since register 31 is the zero
+    // register in the logical instructions.
+    recipes.push(
+        EncodingRecipeBuilder::new("copysp", f_copy_special, 4)
+            .clobbers_flags(false)
+            .emit("put_addsub_imm(bits, src, 0, dst, sink);"),
+    );
+
+    // Floating point register copies.
+    recipes.push(
+        EncodingRecipeBuilder::new("fcopy", f_unary, 4)
+            .operands_in(vec![fpr])
+            .operands_out(vec![fpr])
+            .clobbers_flags(false)
+            .emit("put_fp1(bits, in_reg0, out_reg0, sink);"),
+    );
+
+    recipes.push(
+        EncodingRecipeBuilder::new("frmov", f_regmove, 4)
+            .operands_in(vec![fpr])
+            .clobbers_flags(false)
+            .emit("put_fp1(bits, src, dst, sink);"),
+    );
+
+    recipes.push(
+        EncodingRecipeBuilder::new("fcopytossa", f_copy_to_ssa, 4)
+            .operands_out(vec![fpr])
+            .clobbers_flags(false)
+            .emit("put_fp1(bits, src, out_reg0, sink);"),
+    );
+
+    // Move wide immediates.
+    // The encbits are the instruction bits 31:23 of `movz`.
+    let format = formats.get(f_unary_imm);
+    recipes.push(
+        EncodingRecipeBuilder::new("movz", f_unary_imm, 4)
+            .operands_out(vec![gpr])
+            .inst_predicate(InstructionPredicate::new_is_unsigned_int(
+                format, "imm", 16, 0,
+            ))
+            .clobbers_flags(false)
+            .emit(
+                r#"
+                    let imm: i64 = imm.into();
+                    put_mov_wide(bits, imm as u32, 0, out_reg0, sink);
+                "#,
+            ),
+    );
+
+    // `movn` materializes small negative numbers. The positive ones are handled by `movz` first.
+    recipes.push(
+        EncodingRecipeBuilder::new("movn", f_unary_imm, 4)
+            .operands_out(vec![gpr])
+            .inst_predicate(InstructionPredicate::new_is_signed_int(
+                format, "imm", 17, 0,
+            ))
+            .clobbers_flags(false)
+            .emit(
+                r#"
+                    let imm: i64 = imm.into();
+                    put_mov_wide(bits, !imm as u32, 0, out_reg0, sink);
+                "#,
+            ),
+    );
+
+    // Arbitrary constants are built 16 bits at a time with a `movz` followed by `movk`s.
+    recipes.push(
+        EncodingRecipeBuilder::new("movk32", f_unary_imm, 8)
+            .operands_out(vec![gpr])
+            .clobbers_flags(false)
+            .emit(
+                r#"
+                    let imm: i64 = imm.into();
+                    put_mov_wide(bits, imm as u32, 0, out_reg0, sink);
+                    put_mov_wide(bits | MOVK_BIT, (imm >> 16) as u32, 1, out_reg0, sink);
+                "#,
+            ),
+    );
+
+    recipes.push(
+        EncodingRecipeBuilder::new("movk64", f_unary_imm, 16)
+            .operands_out(vec![gpr])
+            .clobbers_flags(false)
+            .emit(
+                r#"
+                    let imm: i64 = imm.into();
+                    put_mov_wide(bits, imm as u32, 0, out_reg0, sink);
+                    put_mov_wide(bits | MOVK_BIT, (imm >> 16) as u32, 1, out_reg0, sink);
+                    put_mov_wide(bits | MOVK_BIT, (imm >> 32) as u32, 2, out_reg0, sink);
+                    put_mov_wide(bits | MOVK_BIT, (imm >> 48) as u32, 3, out_reg0, sink);
+                "#,
+            ),
+    );
+
+    recipes.push(
+        EncodingRecipeBuilder::new("bconst", f_unary_bool, 4)
+            .operands_out(vec![gpr])
+            .clobbers_flags(false)
+            .emit("put_mov_wide(bits, imm as u32, 0, out_reg0, sink);"),
+    );
+
+    // Integer comparisons: compare and set the output register from the condition flags.
+    recipes.push(
+        EncodingRecipeBuilder::new("icscc", f_int_compare, 8)
+            .operands_in(vec![gpr, gpr])
+            .operands_out(vec![gpr])
+            .emit(
+                r#"
+                    // cmp x, y
+                    put_rrr(bits, in_reg0, in_reg1, ZERO_REG, sink);
+                    put_cset(icc2cond(cond), out_reg0, sink);
+                "#,
+            ),
+    );
+
+    let format = formats.get(f_int_compare_imm);
+    recipes.push(
+        EncodingRecipeBuilder::new("icscc_imm", f_int_compare_imm, 8)
+            .operands_in(vec![gpr])
+            .operands_out(vec![gpr])
+            .inst_predicate(InstructionPredicate::new_is_signed_int(
+                format, "imm", 12, 0,
+            ))
+            .emit(
+                r#"
+                    // cmp x, #imm
+                    put_addsub_imm(bits, in_reg0, imm.into(), ZERO_REG, sink);
+                    put_cset(icc2cond(cond), out_reg0, sink);
+                "#,
+            ),
+    );
+
+    // Comparisons producing CPU flags.
+    recipes.push(
+        EncodingRecipeBuilder::new("rcmp", f_binary, 4)
+            .operands_in(vec![gpr, gpr])
+            .operands_out(vec![reg_nzcv])
+            .emit("put_rrr(bits, in_reg0, in_reg1, ZERO_REG, sink);"),
+    );
+
+    let format = formats.get(f_binary_imm);
+    recipes.push(
+        EncodingRecipeBuilder::new("rcmp_imm", f_binary_imm, 4)
+            .operands_in(vec![gpr])
+            .operands_out(vec![reg_nzcv])
+            .inst_predicate(InstructionPredicate::new_is_signed_int(
+                format, "imm", 12, 0,
+            ))
+            .emit("put_addsub_imm(bits, in_reg0, imm.into(), ZERO_REG, sink);"),
+    );
+
+    // Compare a register with the stack pointer. The stack pointer can't be the second operand
+    // of a compare, so it is copied to the second scratch register first.
+    recipes.push(
+        EncodingRecipeBuilder::new("rcmp_sp", f_unary, 8)
+            .operands_in(vec![gpr])
+            .operands_out(vec![reg_nzcv])
+            .emit(
+                r#"
+                    // mov scratch, sp
+                    put_addsub_imm(0x244, STACK_REG, 0, SCRATCH_REG2, sink);
+                    // cmp x, scratch
+                    put_rrr(bits, in_reg0, SCRATCH_REG2, ZERO_REG, sink);
+                "#,
+            ),
+    );
+
+    // Set a register from the condition flags.
+    recipes.push(
+        EncodingRecipeBuilder::new("cset", f_int_cond, 4)
+            .operands_in(vec![reg_nzcv])
+            .operands_out(vec![gpr])
+            .clobbers_flags(false)
+            .emit("put_cset(icc2cond(cond), out_reg0, sink);"),
+    );
+
+    // Conditional select.
+    // The encbits are the instruction bits 31:21, with bit 10 (`o2`) in bit 11.
+    recipes.push(
+        EncodingRecipeBuilder::new("csel", f_int_select, 4)
+            .operands_in(vec![
+                OperandConstraint::FixedReg(reg_nzcv),
+                OperandConstraint::RegClass(gpr),
+                OperandConstraint::RegClass(gpr),
+            ])
+            .operands_out(vec![gpr])
+            .clobbers_flags(false)
+            .emit("put_csel(bits, icc2cond(cond), in_reg1, in_reg2, out_reg0, sink);"),
+    );
+
+    // Select on a boolean or an integer: compare it with zero, then do a conditional select.
+    // Bit 12 of the encbits is set when the condition is a 64-bit integer.
+    recipes.push(
+        EncodingRecipeBuilder::new("select", f_ternary, 8)
+            .operands_in(vec![gpr, gpr, gpr])
+            .operands_out(vec![gpr])
+            .emit(
+                r#"
+                    // cmp c, #0
+                    put_addsub_imm(select_cmp_bits(bits), in_reg0, 0, ZERO_REG, sink);
+                    put_csel(bits, COND_NE, in_reg1, in_reg2, out_reg0, sink);
+                "#,
+            ),
+    );
+
+    recipes.push(
+        EncodingRecipeBuilder::new("fselect", f_ternary, 8)
+            .operands_in(vec![gpr, fpr, fpr])
+            .operands_out(vec![fpr])
+            .emit(
+                r#"
+                    // cmp c, #0
+                    put_addsub_imm(select_cmp_bits(bits), in_reg0, 0, ZERO_REG, sink);
+                    put_fcsel(bits, COND_NE, in_reg1, in_reg2, out_reg0, sink);
+                "#,
+            ),
+    );
+
+    // Floating-point data-processing (2 source).
+    // The encbits are `opcode | (type << 4)`.
+    recipes.push(
+        EncodingRecipeBuilder::new("fp2", f_binary, 4)
+            .operands_in(vec![fpr, fpr])
+            .operands_out(vec![fpr])
+            .clobbers_flags(false)
+            .emit("put_fp2(bits, in_reg0, in_reg1, out_reg0, sink);"),
+    );
+
+    // Floating-point data-processing (1 source).
+    // The encbits are `opcode | (type << 6)`.
+    recipes.push(
+        EncodingRecipeBuilder::new("fp1", f_unary, 4)
+            .operands_in(vec![fpr])
+            .operands_out(vec![fpr])
+            .clobbers_flags(false)
+            .emit("put_fp1(bits, in_reg0, out_reg0, sink);"),
+    );
+
+    // Floating-point data-processing (3 source).
+    // The encbits are the floating point type.
+    recipes.push(
+        EncodingRecipeBuilder::new("fp3", f_ternary, 4)
+            .operands_in(vec![fpr, fpr, fpr])
+            .operands_out(vec![fpr])
+            .clobbers_flags(false)
+            .emit("put_fp3(bits, in_reg0, in_reg1, in_reg2, out_reg0, sink);"),
+    );
+
+    // Floating point comparisons: compare and set the output register from the condition flags.
+    // The encbits are the floating point type.
+    recipes.push(
+        EncodingRecipeBuilder::new("fcscc", f_float_compare, 8)
+            .operands_in(vec![fpr, fpr])
+            .operands_out(vec![gpr])
+            .emit(
+                r#"
+                    put_fcmp(bits, in_reg0, in_reg1, sink);
+                    put_cset(fcc2cond(cond), out_reg0, sink);
+                "#,
+            ),
+    );
+
+    // Floating point comparisons producing CPU flags.
+    recipes.push(
+        EncodingRecipeBuilder::new("fcmp", f_binary, 4)
+            .operands_in(vec![fpr, fpr])
+            .operands_out(vec![reg_nzcv])
+            .emit("put_fcmp(bits, in_reg0, in_reg1, sink);"),
+    );
+
+    recipes.push(
+        EncodingRecipeBuilder::new("fcset", f_float_cond, 4)
+            .operands_in(vec![reg_nzcv])
+            .operands_out(vec![gpr])
+            .clobbers_flags(false)
+            .emit("put_cset(fcc2cond(cond), out_reg0, sink);"),
+    );
+
+    // Conversions between integer and floating point registers.
+    // The encbits are the instruction bits 31:16.
+    recipes.push(
+        EncodingRecipeBuilder::new("gpr_fpr", f_unary, 4)
+            .operands_in(vec![gpr])
+            .operands_out(vec![fpr])
+            .clobbers_flags(false)
+            .emit("put_rr(bits, in_reg0, out_reg0, sink);"),
+    );
+
+    recipes.push(
+        EncodingRecipeBuilder::new("fpr_gpr", f_unary, 4)
+            .operands_in(vec![fpr])
+            .operands_out(vec![gpr])
+            .clobbers_flags(false)
+            .emit("put_rr(bits, in_reg0, out_reg0, sink);"),
+    );
+
+    // Loads and stores.
+    //
+    // The encbits are the instruction bits 31:22 of the unsigned offset form. The same bits are
+    // used to derive the unscaled 9-bit offset form and the register offset form used for
+    // offsets that don't fit either immediate. The latter builds the offset in the scratch
+    // register.
+    for &(name, out_class) in &[("ld", gpr), ("fld", fpr)] {
+        recipes.push(
+            EncodingRecipeBuilder::new(name, f_load, 4)
+                .operands_in(vec![gpr])
+                .operands_out(vec![out_class])
+                .clobbers_flags(false)
+                .emit(
+                    r#"
+                        if !flags.notrap() {
+                            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+                        }
+                        let offset: i32 = offset.into();
+                        put_ldst(bits, out_reg0, in_reg0, offset.into(), sink);
+                    "#,
+                ),
+        );
+    }
+
+    for &(name, out_class) in &[("ldUnscaled", gpr), ("fldUnscaled", fpr)] {
+        recipes.push(
+            EncodingRecipeBuilder::new(name, f_load, 4)
+                .operands_in(vec![gpr])
+                .operands_out(vec![out_class])
+                .inst_predicate(InstructionPredicate::new_is_signed_int(
+                    formats.get(f_load),
+                    "offset",
+                    9,
+                    0,
+                ))
+                .clobbers_flags(false)
+                .emit(
+                    r#"
+                        if !flags.notrap() {
+                            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+                        }
+                        let offset: i32 = offset.into();
+                        put_ldst_unscaled(bits, out_reg0, in_reg0, offset.into(), sink);
+                    "#,
+                ),
+        );
+    }
+
+    for &(name, out_class) in &[("ldLarge", gpr), ("fldLarge", fpr)] {
+        recipes.push(
+            EncodingRecipeBuilder::new(name, f_load, 12)
+                .operands_in(vec![gpr])
+                .operands_out(vec![out_class])
+                .clobbers_flags(false)
+                .emit(
+                    r#"
+                        let offset: i32 = offset.into();
+                        put_mov_i32(SCRATCH_REG, offset, sink);
+                        if !flags.notrap() {
+                            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+                        }
+                        put_ldst_reg(bits, out_reg0, in_reg0, SCRATCH_REG, EXTEND_SXTW, sink);
+                    "#,
+                ),
+        );
+    }
+
+    for &(name, in_class) in &[("st", gpr), ("fst", fpr)] {
+        recipes.push(
+            EncodingRecipeBuilder::new(name, f_store, 4)
+                .operands_in(vec![in_class, gpr])
+                .clobbers_flags(false)
+                .emit(
+                    r#"
+                        if !flags.notrap() {
+                            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+                        }
+                        let offset: i32 = offset.into();
+                        put_ldst(bits, in_reg0, in_reg1, offset.into(), sink);
+                    "#,
+                ),
+        );
+    }
+
+    for &(name, in_class) in &[("stUnscaled", gpr), ("fstUnscaled", fpr)] {
+        recipes.push(
+            EncodingRecipeBuilder::new(name, f_store, 4)
+                .operands_in(vec![in_class, gpr])
+                .inst_predicate(InstructionPredicate::new_is_signed_int(
+                    formats.get(f_store),
+                    "offset",
+                    9,
+                    0,
+                ))
+                .clobbers_flags(false)
+                .emit(
+                    r#"
+                        if !flags.notrap() {
+                            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+                        }
+                        let offset: i32 = offset.into();
+                        put_ldst_unscaled(bits, in_reg0, in_reg1, offset.into(), sink);
+                    "#,
+                ),
+        );
+    }
+
+    for &(name, in_class) in &[("stLarge", gpr), ("fstLarge", fpr)] {
+        recipes.push(
+            EncodingRecipeBuilder::new(name, f_store, 12)
+                .operands_in(vec![in_class, gpr])
+                .clobbers_flags(false)
+                .emit(
+                    r#"
+                        let offset: i32 = offset.into();
+                        put_mov_i32(SCRATCH_REG, offset, sink);
+                        if !flags.notrap() {
+                            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+                        }
+                        put_ldst_reg(bits, in_reg0, in_reg1, SCRATCH_REG, EXTEND_SXTW, sink);
+                    "#,
+                ),
+        );
+    }
+
+    // Stack pointer relative spills and fills. Offsets that don't fit in the scaled immediate
+    // need two more instructions to build the offset in the scratch register.
+    for &(spill, fill, regspill, regfill, class) in &[
+        ("spill", "fill", "regspill", "regfill", gpr),
+        ("fspill", "ffill", "fregspill", "fregfill", fpr),
+    ] {
+        recipes.push(
+            EncodingRecipeBuilder::new(spill, f_unary, 4)
+                .operands_in(vec![class])
+                .operands_out(vec![Stack::new(class)])
+                .clobbers_flags(false)
+                .compute_size("size_plus_maybe_offset_for_stack_slot")
+                .emit("put_ldst_sp(bits, in_reg0, out_stk0.offset, sink);"),
+        );
+
+        recipes.push(
+            EncodingRecipeBuilder::new(fill, f_unary, 4)
+                .operands_in(vec![Stack::new(class)])
+                .operands_out(vec![class])
+                .clobbers_flags(false)
+                .compute_size("size_plus_maybe_offset_for_stack_slot")
+                .emit("put_ldst_sp(bits, out_reg0, in_stk0.offset, sink);"),
+        );
+
+        recipes.push(
+            EncodingRecipeBuilder::new(regspill, f_reg_spill, 4)
+                .operands_in(vec![class])
+                .clobbers_flags(false)
+                .compute_size("size_plus_maybe_offset_for_stack_slot")
+                .emit(
+                    r#"
+                        let dst = StackRef::sp(dst, &func.stack_slots);
+                        put_ldst_sp(bits, src, dst.offset, sink);
+                    "#,
+                ),
+        );
+
+        recipes.push(
+            EncodingRecipeBuilder::new(regfill, f_reg_fill, 4)
+                .operands_in(vec![Stack::new(class)])
+                .clobbers_flags(false)
+                .compute_size("size_plus_maybe_offset_for_stack_slot")
+                .emit(
+                    r#"
+                        let src = StackRef::sp(src, &func.stack_slots);
+                        put_ldst_sp(bits, dst, src.offset, sink);
+                    "#,
+                ),
+        );
+    }
+
+    // Stack-slot to same stack-slot copy, which is guaranteed to turn into a no-op.
+    recipes.push(
+        EncodingRecipeBuilder::new("stacknull", f_unary, 0)
+            .operands_in(vec![Stack::new(gpr)])
+            .operands_out(vec![Stack::new(gpr)])
+            .clobbers_flags(false)
+            .emit(""),
+    );
+
+    // No-op fills, created by late-stage redundant-fill removal.
+    recipes.push(
+        EncodingRecipeBuilder::new("fillnull", f_unary, 0)
+            .operands_in(vec![Stack::new(gpr)])
+            .operands_out(vec![gpr])
+            .clobbers_flags(false)
+            .emit(""),
+    );
+
+    recipes.push(
+        EncodingRecipeBuilder::new("ffillnull", f_unary, 0)
+            .operands_in(vec![Stack::new(fpr)])
+            .operands_out(vec![fpr])
+            .clobbers_flags(false)
+            .emit(""),
+    );
+
+    // Address of a stack slot.
+    recipes.push(
+        EncodingRecipeBuilder::new("spaddr", f_stack_load, 4)
+            .operands_out(vec![gpr])
+            .clobbers_flags(false)
+            .compute_size("size_plus_maybe_offset_for_stack_addr")
+            .emit(
+                r#"
+                    let sp = StackRef::sp(stack_slot, &func.stack_slots);
+                    let imm: i32 = offset.into();
+                    let offset = sp.offset.checked_add(imm).unwrap();
+                    if is_unsigned_int(offset, 12, 0) {
+                        put_addsub_imm(bits, STACK_REG, offset.into(), out_reg0, sink);
+                    } else {
+                        put_mov_i32(SCRATCH_REG, offset, sink);
+                        put_addsub_ext(bits, STACK_REG, SCRATCH_REG, out_reg0, sink);
+                    }
+                "#,
+            ),
+    );
+
+    // Adjustments of the stack pointer by an immediate.
+    // The encbits are the instruction bits 31:22 of `add` or `sub` (immediate).
+    let format = formats.get(f_unary_imm);
+    recipes.push(
+        EncodingRecipeBuilder::new("adjustsp_imm12", f_unary_imm, 4)
+            .inst_predicate(InstructionPredicate::new_is_unsigned_int(
+                format, "imm", 12, 0,
+            ))
+            .clobbers_flags(false)
+            .emit("put_addsub_imm(bits, STACK_REG, imm.into(), STACK_REG, sink);"),
+    );
+
+    recipes.push(
+        EncodingRecipeBuilder::new("adjustsp_imm24", f_unary_imm, 8)
+            .inst_predicate(InstructionPredicate::new_is_unsigned_int(
+                format, "imm", 24, 0,
+            ))
+            .clobbers_flags(false)
+            .emit(
+                r#"
+                    let imm: i64 = imm.into();
+                    // The high 12 bits are shifted left by 12.
+                    put_addsub_imm(bits | 1, STACK_REG, imm >> 12, STACK_REG, sink);
+                    put_addsub_imm(bits, STACK_REG, imm & 0xfff, STACK_REG, sink);
+                "#,
+            ),
+    );
+
+    recipes.push(
+        EncodingRecipeBuilder::new("adjustsp", f_unary_imm, 12)
+            .inst_predicate(InstructionPredicate::new_is_unsigned_int(
+                format, "imm", 31, 0,
+            ))
+            .clobbers_flags(false)
+            .emit(
+                r#"
+                    let imm: i64 = imm.into();
+                    put_mov_i32(SCRATCH_REG, imm as i32, sink);
+                    put_addsub_ext(bits, STACK_REG, SCRATCH_REG, STACK_REG, sink);
+                "#,
+            ),
+    );
+
+    // Pushes and pops keep the stack pointer 16-byte aligned.
+    // The encbits are the instruction bits 31:22 of the pre-indexed store or post-indexed load.
+    for &(push, pop, push2, pop2, class) in &[
+        ("push", "pop", "push2", "pop2", gpr),
+        ("fpush", "fpop", "fpush2", "fpop2", fpr),
+    ] {
+        recipes.push(
+            EncodingRecipeBuilder::new(push, f_unary, 4)
+                .operands_in(vec![class])
+                .clobbers_flags(false)
+                .emit("put_ldst_sp_indexed(bits, in_reg0, -16, sink);"),
+        );
+
+        recipes.push(
+            EncodingRecipeBuilder::new(pop, f_nullary, 4)
+                .operands_out(vec![class])
+                .clobbers_flags(false)
+                .emit("put_ldst_sp_indexed(bits, out_reg0, 16, sink);"),
+        );
+
+        recipes.push(
+            EncodingRecipeBuilder::new(push2, f_binary, 4)
+                .operands_in(vec![class, class])
+                .clobbers_flags(false)
+                .emit("put_ldst_pair_sp(bits, in_reg0, in_reg1, -16, sink);"),
+        );
+
+        recipes.push(
+            EncodingRecipeBuilder::new(pop2, f_nullary, 4)
+                .operands_out(vec![class, class])
+                .clobbers_flags(false)
+                .emit("put_ldst_pair_sp(bits, out_reg0, out_reg1, 16, sink);"),
+        );
+    }
+
+    // Traps.
+    recipes.push(
+        EncodingRecipeBuilder::new("trap", f_trap, 4)
+            .clobbers_flags(false)
+            .emit(
+                r#"
+                    sink.trap(code, func.srclocs[inst]);
+                    put_udf(sink);
+                "#,
+            ),
+    );
+
+    // Conditional branch over a trap.
+    recipes.push(
+        EncodingRecipeBuilder::new("trapif", f_int_cond_trap, 8)
+            .operands_in(vec![reg_nzcv])
+            .clobbers_flags(false)
+            .emit(
+                r#"
+                    put_bcond(icc2cond(cond.inverse()), 8, sink);
+                    sink.trap(code, func.srclocs[inst]);
+                    put_udf(sink);
+                "#,
+            ),
+    );
+
+    recipes.push(
+        EncodingRecipeBuilder::new("debugtrap", f_nullary, 4)
+            .clobbers_flags(false)
+            .emit("put_brk(sink);"),
+    );
+
+    // Branches.
+    // The encbits are the instruction bits 31:26 for the unconditional branches, and 31:24 for
+    // the compare and branch instructions.
+    recipes.push(
+        EncodingRecipeBuilder::new("b", f_jump, 4)
+            .branch_range((0, 28))
+            .clobbers_flags(false)
+            .emit(
+                r#"
+                    let dest = i64::from(func.offsets[destination]);
+                    let disp = dest - i64::from(sink.offset());
+                    put_b(bits, disp, sink);
+                "#,
+            ),
+    );
+
+    recipes.push(
+        EncodingRecipeBuilder::new("cb", f_branch, 4)
+            .operands_in(vec![gpr])
+            .branch_range((0, 21))
+            .clobbers_flags(false)
+            .emit(
+                r#"
+                    let dest = i64::from(func.offsets[destination]);
+                    let disp = dest - i64::from(sink.offset());
+                    put_cb(bits, in_reg0, disp, sink);
+                "#,
+            ),
+    );
+
+    recipes.push(
+        EncodingRecipeBuilder::new("bcond", f_branch_int, 4)
+            .operands_in(vec![reg_nzcv])
+            .branch_range((0, 21))
+            .clobbers_flags(false)
+            .emit(
+                r#"
+                    let dest = i64::from(func.offsets[destination]);
+                    let disp = dest - i64::from(sink.offset());
+                    put_bcond(icc2cond(cond), disp, sink);
+                "#,
+            ),
+    );
+
+    recipes.push(
+        EncodingRecipeBuilder::new("bcondf", f_branch_float, 4)
+            .operands_in(vec![reg_nzcv])
+            .branch_range((0, 21))
+            .clobbers_flags(false)
+            .emit(
+                r#"
+                    let dest = i64::from(func.offsets[destination]);
+                    let disp = dest - i64::from(sink.offset());
+                    put_bcond(fcc2cond(cond), disp, sink);
+                "#,
+            ),
+    );
+
+    // Calls.
+    recipes.push(
+        EncodingRecipeBuilder::new("call", f_call, 4)
+            .clobbers_flags(false)
+            .emit(
+                r#"
+                    sink.reloc_external(Reloc::Arm64Call,
+                                        &func.dfg.ext_funcs[func_ref].name,
+                                        0);
+                    put_b(bits, 0, sink);
+                "#,
+            ),
+    );
+
+    // Indirect branches through a register.
+    // The encbits are the instruction bits 31:16.
+    recipes.push(
+        EncodingRecipeBuilder::new("call_r", f_call_indirect, 4)
+            .operands_in(vec![gpr])
+            .clobbers_flags(false)
+            .emit("put_br(bits, in_reg0, sink);"),
+    );
+
+    // The return address is provided by a special-purpose `link` return value that is added by
+    // the prologue insertion.
+    recipes.push(
+        EncodingRecipeBuilder::new("ret", f_multiary, 4)
+            .clobbers_flags(false)
+            .emit("put_br(bits, LINK_REG, sink);"),
+    );
+
+    // `adr` reaches +/-1 MB, which covers the jump tables placed after the code.
+    recipes.push(
+        EncodingRecipeBuilder::new("jt_base", f_branch_table_base, 4)
+            .operands_out(vec![gpr])
+            .clobbers_flags(false)
+            .emit(
+                r#"
+                    let dest = i64::from(func.jt_offsets[table]);
+                    let disp = dest - i64::from(sink.offset());
+                    put_adr(out_reg0, disp, sink);
+                "#,
+            ),
+    );
+
+    // Load a sign-extended 32-bit jump table entry: `ldrsw rd, [base, index, lsl #2]`.
+    recipes.push(
+        EncodingRecipeBuilder::new("jt_entry", f_branch_table_entry, 4)
+            .operands_in(vec![gpr, gpr])
+            .operands_out(vec![gpr])
+            .inst_predicate(InstructionPredicate::new_is_field_equal(
+                formats.get(f_branch_table_entry),
+                "imm",
+                "4".into(),
+            ))
+            .clobbers_flags(false)
+            .emit("put_ldst_reg(bits, out_reg0, in_reg1, in_reg0, EXTEND_LSL_SCALED, sink);"),
+    );
+
+    recipes.push(
+        EncodingRecipeBuilder::new("indirect_jmp", f_indirect_jump, 4)
+            .operands_in(vec![gpr])
+            .clobbers_flags(false)
+            .emit("put_br(bits, in_reg0, sink);"),
+    );
+
+    // Absolute addresses are loaded from a literal placed inline and skipped over with a branch.
+    recipes.push(
+        EncodingRecipeBuilder::new("fnaddr", f_func_addr, 16)
+            .operands_out(vec![gpr])
+            .clobbers_flags(false)
+            .emit(
+                r#"
+                    put_ldr_literal_skip(out_reg0, sink);
+                    sink.reloc_external(Reloc::Abs8,
+                                        &func.dfg.ext_funcs[func_ref].name,
+                                        0);
+                    sink.put8(0);
+                "#,
+            ),
+    );
+
+    recipes.push(
+        EncodingRecipeBuilder::new("gvaddr", f_unary_global_value, 16)
+            .operands_out(vec![gpr])
+            .clobbers_flags(false)
+            .emit(
+                r#"
+                    put_ldr_literal_skip(out_reg0, sink);
+                    sink.reloc_external(Reloc::Abs8,
+                                        &func.global_values[global_value].symbol_name(),
+                                        0);
+                    sink.put8(0);
+                "#,
+            ),
+    );
+
+    recipes
+}
@@ -1,10 +1,12 @@
 use crate::cdsl::ast::{Apply, Expr, Literal, VarPool};
 use crate::cdsl::encodings::{Encoding, EncodingBuilder};
 use crate::cdsl::instructions::{
-    BoundInstruction, InstSpec, InstructionPredicateNode, InstructionPredicateRegistry,
+    BoundInstruction, InstSpec, InstructionPredicate, InstructionPredicateNode,
+    InstructionPredicateRegistry,
 };
 use crate::cdsl::recipes::{EncodingRecipeNumber, Recipes};
 use crate::cdsl::settings::SettingGroup;
+use crate::cdsl::types::LaneType;
 
 use crate::shared::types::Bool::B1;
 use crate::shared::types::Float::{F32, F64};
@@ -101,6 +103,36 @@ fn lui_bits() -> u16 {
     0b01101
 }
 
+fn fload_bits(funct3: u16) -> u16 {
+    assert!(funct3 <= 0b111);
+    0b00001 | (funct3 << 5)
+}
+
+fn fstore_bits(funct3: u16) -> u16 {
+    assert!(funct3 <= 0b111);
+    0b01001 | (funct3 << 5)
+}
+
+fn system_bits(funct3: u16) -> u16 {
+    assert!(funct3 <= 0b111);
+    0b11100 | (funct3 << 5)
+}
+
+/// The encbits of the OP-FP recipes are `rm | (funct7 << 3) | (rs2 << 10)`, with funct7 made of
+/// funct5 and the format.
+fn fp_bits(funct5: u16, fmt: u16, rm: u16, rs2: u16) -> u16 {
+    assert!(funct5 <= 0b11111);
+    assert!(fmt <= 0b11);
+    assert!(rm <= 0b111);
+    assert!(rs2 <= 0b11111);
+    rm | (((funct5 << 2) | fmt) << 3) | (rs2 << 10)
+}
+
+fn fmadd_bits(fmt: u16) -> u16 {
+    assert!(fmt <= 0b11);
+    0b10000 | (fmt << 8)
+}
+
 pub(crate) fn define<'defs>(
     shared_defs: &'defs SharedDefinitions,
     isa_settings: &SettingGroup,
@@ -109,8 +141,14 @@ pub(crate) fn define<'defs>(
     // Instructions shorthands.
     let shared = &shared_defs.instructions;
 
+    let adjust_sp_down_imm = shared.by_name("adjust_sp_down_imm");
+    let adjust_sp_up_imm = shared.by_name("adjust_sp_up_imm");
     let band = shared.by_name("band");
     let band_imm = shared.by_name("band_imm");
+    let bconst = shared.by_name("bconst");
+    let bint = shared.by_name("bint");
+    let bitcast = shared.by_name("bitcast");
+    let bnot = shared.by_name("bnot");
     let bor = shared.by_name("bor");
     let bor_imm = shared.by_name("bor_imm");
     let br_icmp = shared.by_name("br_icmp");
@@ -123,49 +161,149 @@ pub(crate) fn define<'defs>(
     let copy = shared.by_name("copy");
     let copy_nop = shared.by_name("copy_nop");
     let copy_to_ssa = shared.by_name("copy_to_ssa");
+    let debugtrap = shared.by_name("debugtrap");
+    let fabs = shared.by_name("fabs");
+    let fadd = shared.by_name("fadd");
+    let fcmp = shared.by_name("fcmp");
+    let fcopysign = shared.by_name("fcopysign");
+    let fcvt_from_sint = shared.by_name("fcvt_from_sint");
+    let fcvt_from_uint = shared.by_name("fcvt_from_uint");
+    let fcvt_to_sint_sat = shared.by_name("fcvt_to_sint_sat");
+    let fcvt_to_uint_sat = shared.by_name("fcvt_to_uint_sat");
+    let fdemote = shared.by_name("fdemote");
+    let fdiv = shared.by_name("fdiv");
     let fill = shared.by_name("fill");
     let fill_nop = shared.by_name("fill_nop");
+    let fma = shared.by_name("fma");
+    let fmul = shared.by_name("fmul");
+    let fneg = shared.by_name("fneg");
+    let fpromote = shared.by_name("fpromote");
+    let fsub = shared.by_name("fsub");
+    let func_addr = shared.by_name("func_addr");
     let iadd = shared.by_name("iadd");
     let iadd_imm = shared.by_name("iadd_imm");
     let iconst = shared.by_name("iconst");
     let icmp = shared.by_name("icmp");
     let icmp_imm = shared.by_name("icmp_imm");
     let imul = shared.by_name("imul");
+    let indirect_jump_table_br = shared.by_name("indirect_jump_table_br");
+    let ireduce = shared.by_name("ireduce");
     let ishl = shared.by_name("ishl");
     let ishl_imm = shared.by_name("ishl_imm");
+    let istore16 = shared.by_name("istore16");
+    let istore32 = shared.by_name("istore32");
+    let istore8 = shared.by_name("istore8");
     let isub = shared.by_name("isub");
     let jump = shared.by_name("jump");
+    let jump_table_base = shared.by_name("jump_table_base");
+    let jump_table_entry = shared.by_name("jump_table_entry");
+    let load = shared.by_name("load");
+    let regfill = shared.by_name("regfill");
     let regmove = shared.by_name("regmove");
+    let regspill = shared.by_name("regspill");
+    let sdiv = shared.by_name("sdiv");
+    let sextend = shared.by_name("sextend");
+    let sload16 = shared.by_name("sload16");
+    let sload32 = shared.by_name("sload32");
+    let sload8 = shared.by_name("sload8");
+    let smulhi = shared.by_name("smulhi");
     let spill = shared.by_name("spill");
+    let sqrt = shared.by_name("sqrt");
+    let srem = shared.by_name("srem");
     let sshr = shared.by_name("sshr");
     let sshr_imm = shared.by_name("sshr_imm");
+    let stack_addr = shared.by_name("stack_addr");
+    let stack_load = shared.by_name("stack_load");
+    let stack_store = shared.by_name("stack_store");
+    let store = shared.by_name("store");
+    let symbol_value = shared.by_name("symbol_value");
+    let trap = shared.by_name("trap");
+    let udiv = shared.by_name("udiv");
+    let uextend = shared.by_name("uextend");
+    let uload16 = shared.by_name("uload16");
+    let uload32 = shared.by_name("uload32");
+    let uload8 = shared.by_name("uload8");
+    let umulhi = shared.by_name("umulhi");
+    let urem = shared.by_name("urem");
     let ushr = shared.by_name("ushr");
     let ushr_imm = shared.by_name("ushr_imm");
     let return_ = shared.by_name("return");
 
     // Recipes shorthands, prefixed with r_.
     let r_copytossa = recipes.by_name("copytossa");
+    let r_fcopytossa = recipes.by_name("fcopytossa");
+    let r_ffillnull = recipes.by_name("ffillnull");
     let r_fillnull = recipes.by_name("fillnull");
+    let r_fp_fi = recipes.by_name("FPfi");
+    let r_fp_rfi = recipes.by_name("FPrfi");
+    let r_fp_rsp = recipes.by_name("FPrsp");
+    let r_fp_sl = recipes.by_name("FPsl");
+    let r_fp_sp = recipes.by_name("FPsp");
+    let r_fp_ss = recipes.by_name("FPss");
+    let r_gp_fi = recipes.by_name("GPfi");
+    let r_gp_rfi = recipes.by_name("GPrfi");
+    let r_gp_rsp = recipes.by_name("GPrsp");
+    let r_gp_sl = recipes.by_name("GPsl");
+    let r_gp_sp = recipes.by_name("GPsp");
+    let r_gp_ss = recipes.by_name("GPss");
+    let r_iadjsp_down = recipes.by_name("IadjspDown");
+    let r_iadjsp_up = recipes.by_name("IadjspUp");
+    let r_ibconst = recipes.by_name("Ibconst");
+    let r_ibnot = recipes.by_name("Ibnot");
     let r_icall = recipes.by_name("Icall");
     let r_icopy = recipes.by_name("Icopy");
+    let r_iebreak = recipes.by_name("Iebreak");
+    let r_ifload = recipes.by_name("Ifload");
+    let r_ifload_large = recipes.by_name("IfloadLarge");
     let r_ii = recipes.by_name("Ii");
     let r_iicmp = recipes.by_name("Iicmp");
+    let r_ijmp = recipes.by_name("Ijmp");
+    let r_ili = recipes.by_name("Ili");
+    let r_iload = recipes.by_name("Iload");
+    let r_iload_large = recipes.by_name("IloadLarge");
     let r_iret = recipes.by_name("Iret");
     let r_irmov = recipes.by_name("Irmov");
+    let r_ispaddr = recipes.by_name("Ispaddr");
+    let r_itrap = recipes.by_name("Itrap");
     let r_iz = recipes.by_name("Iz");
-    let r_gp_sp = recipes.by_name("GPsp");
-    let r_gp_fi = recipes.by_name("GPfi");
+    let r_null = recipes.by_name("null");
     let r_r = recipes.by_name("R");
+    let r_r4 = recipes.by_name("R4");
+    let r_radjsp = recipes.by_name("Radjsp");
+    let r_rdiv = recipes.by_name("Rdiv");
+    let r_rext = recipes.by_name("Rext");
+    let r_rfcmp = recipes.by_name("Rfcmp");
+    let r_rfcvt = recipes.by_name("Rfcvt");
+    let r_rfp = recipes.by_name("Rfp");
+    let r_rfp1 = recipes.by_name("Rfp1");
+    let r_rfpgp = recipes.by_name("Rfpgp");
+    let r_rfrmov = recipes.by_name("Rfrmov");
+    let r_rfsgn = recipes.by_name("Rfsgn");
+    let r_rgpfp = recipes.by_name("Rgpfp");
     let r_ricmp = recipes.by_name("Ricmp");
+    let r_rjtentry = recipes.by_name("Rjtentry");
+    let r_rsdiv = recipes.by_name("Rsdiv");
     let r_rshamt = recipes.by_name("Rshamt");
     let r_sb = recipes.by_name("SB");
     let r_sb_zero = recipes.by_name("SBzero");
+    let r_sfstore = recipes.by_name("Sfstore");
+    let r_sfstore_large = recipes.by_name("SfstoreLarge");
+    let r_sstore = recipes.by_name("Sstore");
+    let r_sstore_large = recipes.by_name("SstoreLarge");
     let r_stacknull = recipes.by_name("stacknull");
     let r_u = recipes.by_name("U");
+    let r_ufnaddr4 = recipes.by_name("Ufnaddr4");
+    let r_ufnaddr8 = recipes.by_name("Ufnaddr8");
+    let r_ugvaddr4 = recipes.by_name("Ugvaddr4");
+    let r_ugvaddr8 = recipes.by_name("Ugvaddr8");
     let r_uj = recipes.by_name("UJ");
     let r_uj_call = recipes.by_name("UJcall");
+    let r_ujtbase = recipes.by_name("Ujtbase");
 
     // Predicates shorthands.
+    let has_scratch = isa_settings.predicate_by_name("has_scratch");
+    let use_d = isa_settings.predicate_by_name("use_d");
+    let use_f = isa_settings.predicate_by_name("use_f");
     let use_m = isa_settings.predicate_by_name("use_m");
 
     // Definitions.
@@ -195,6 +333,30 @@ pub(crate) fn define<'defs>(
     // There are no andiw/oriw/xoriw variations.
     e.add64(enc(iadd_imm.bind(I32), r_ii, opimm32_bits(0b000, 0)));
 
+    // 32-bit values are kept sign-extended in RV64 registers, which the 64-bit bitwise
+    // operations preserve.
+    for &(inst, inst_imm, f3) in &[
+        (bxor, bxor_imm, 0b100),
+        (bor, bor_imm, 0b110),
+        (band, band_imm, 0b111),
+    ] {
+        e.add64(enc(inst.bind(I32), r_r, op_bits(f3, 0b0000000)));
+        e.add64(enc(inst_imm.bind(I32), r_ii, opimm_bits(f3, 0)));
+    }
+
+    // Booleans are 0 or 1 in a register.
+    for &(inst, f3) in &[(bxor, 0b100), (bor, 0b110), (band, 0b111)] {
+        e.add32(enc(inst.bind(B1), r_r, op_bits(f3, 0b0000000)));
+        e.add64(enc(inst.bind(B1), r_r, op_bits(f3, 0b0000000)));
+    }
+    e.add32(enc(bnot.bind(B1), r_ibnot, opimm_bits(0b100, 0)));
+    e.add64(enc(bnot.bind(B1), r_ibnot, opimm_bits(0b100, 0)));
+    e.add32(enc(bconst.bind(B1), r_ibconst, opimm_bits(0b000, 0)));
+    e.add64(enc(bconst.bind(B1), r_ibconst, opimm_bits(0b000, 0)));
+    e.add32(enc(bint.bind(I32).bind(B1), r_icopy, opimm_bits(0b000, 0)));
+    e.add64(enc(bint.bind(I32).bind(B1), r_icopy, opimm_bits(0b000, 0)));
+    e.add64(enc(bint.bind(I64).bind(B1), r_icopy, opimm_bits(0b000, 0)));
+
     // Use iadd_imm with %x0 to materialize constants.
     e.add32(enc(iconst.bind(I32), r_iz, opimm_bits(0b0, 0)));
     e.add64(enc(iconst.bind(I32), r_iz, opimm_bits(0b0, 0)));
@@ -220,7 +382,7 @@ pub(crate) fn define<'defs>(
     }
 
     // Signed and unsigned integer 'less than'. There are no 'w' variants for comparing 32-bit
-    // numbers in RV64.
+    // numbers in RV64, but 32-bit values are kept sign-extended so the full comparison works.
     {
         let mut var_pool = VarPool::new();
 
@@ -245,6 +407,10 @@ pub(crate) fn define<'defs>(
             enc(icmp_i32.clone(), r_ricmp, op_bits(0b010, 0b0000000))
                 .inst_predicate(icmp_instp(&icmp_i32, "slt")),
         );
+        e.add64(
+            enc(icmp_i32.clone(), r_ricmp, op_bits(0b010, 0b0000000))
+                .inst_predicate(icmp_instp(&icmp_i32, "slt")),
+        );
         e.add64(
             enc(icmp_i64.clone(), r_ricmp, op_bits(0b010, 0b0000000))
                 .inst_predicate(icmp_instp(&icmp_i64, "slt")),
@@ -254,6 +420,10 @@ pub(crate) fn define<'defs>(
             enc(icmp_i32.clone(), r_ricmp, op_bits(0b011, 0b0000000))
                 .inst_predicate(icmp_instp(&icmp_i32, "ult")),
         );
+        e.add64(
+            enc(icmp_i32.clone(), r_ricmp, op_bits(0b011, 0b0000000))
+                .inst_predicate(icmp_instp(&icmp_i32, "ult")),
+        );
         e.add64(
             enc(icmp_i64.clone(), r_ricmp, op_bits(0b011, 0b0000000))
                 .inst_predicate(icmp_instp(&icmp_i64, "ult")),
@@ -266,6 +436,10 @@ pub(crate) fn define<'defs>(
             enc(icmp_i32.clone(), r_iicmp, opimm_bits(0b010, 0))
                 .inst_predicate(icmp_instp(&icmp_i32, "slt")),
         );
+        e.add64(
+            enc(icmp_i32.clone(), r_iicmp, opimm_bits(0b010, 0))
+                .inst_predicate(icmp_instp(&icmp_i32, "slt")),
+        );
         e.add64(
             enc(icmp_i64.clone(), r_iicmp, opimm_bits(0b010, 0))
                 .inst_predicate(icmp_instp(&icmp_i64, "slt")),
@@ -275,6 +449,10 @@ pub(crate) fn define<'defs>(
             enc(icmp_i32.clone(), r_iicmp, opimm_bits(0b011, 0))
                 .inst_predicate(icmp_instp(&icmp_i32, "ult")),
         );
+        e.add64(
+            enc(icmp_i32.clone(), r_iicmp, opimm_bits(0b011, 0))
+                .inst_predicate(icmp_instp(&icmp_i32, "ult")),
+        );
         e.add64(
             enc(icmp_i64.clone(), r_iicmp, opimm_bits(0b011, 0))
                 .inst_predicate(icmp_instp(&icmp_i64, "ult")),
@@ -286,19 +464,79 @@ pub(crate) fn define<'defs>(
     e.add64(enc(iconst.bind(I32), r_u, lui_bits()));
     e.add64(enc(iconst.bind(I64), r_u, lui_bits()));
 
+    // Other integer constants need a sequence of instructions.
+    e.add32(enc(iconst.bind(I32), r_ili, 0b10));
+    e.add64(enc(iconst.bind(I32), r_ili, 0b11));
+    e.add64(enc(iconst.bind(I64), r_ili, 0b01));
+
     // "M" Standard Extension for Integer Multiplication and Division.
     // Gated by the `use_m` flag.
     e.add32(enc(imul.bind(I32), r_r, op_bits(0b000, 0b00000001)).isa_predicate(use_m));
     e.add64(enc(imul.bind(I64), r_r, op_bits(0b000, 0b00000001)).isa_predicate(use_m));
     e.add64(enc(imul.bind(I32), r_r, op32_bits(0b000, 0b00000001)).isa_predicate(use_m));
+    e.add32(enc(smulhi.bind(I32), r_r, op_bits(0b001, 0b00000001)).isa_predicate(use_m));
+    e.add32(enc(umulhi.bind(I32), r_r, op_bits(0b011, 0b00000001)).isa_predicate(use_m));
+    e.add64(enc(smulhi.bind(I64), r_r, op_bits(0b001, 0b00000001)).isa_predicate(use_m));
+    e.add64(enc(umulhi.bind(I64), r_r, op_bits(0b011, 0b00000001)).isa_predicate(use_m));
+
+    // The division instructions don't trap, so the recipes test the operands first.
+    for &(inst, f3) in &[(udiv, 0b101), (srem, 0b110), (urem, 0b111)] {
+        e.add32(enc(inst.bind(I32), r_rdiv, op_bits(f3, 0b00000001)).isa_predicate(use_m));
+        e.add64(enc(inst.bind(I64), r_rdiv, op_bits(f3, 0b00000001)).isa_predicate(use_m));
+        e.add64(enc(inst.bind(I32), r_rdiv, op32_bits(f3, 0b00000001)).isa_predicate(use_m));
+    }
+    e.add32(enc(sdiv.bind(I32), r_rsdiv, op_bits(0b100, 0b00000001)).isa_predicate(use_m));
+    e.add64(enc(sdiv.bind(I64), r_rsdiv, op_bits(0b100, 0b00000001)).isa_predicate(use_m));
+    e.add64(enc(sdiv.bind(I32), r_rsdiv, op32_bits(0b100, 0b00000001)).isa_predicate(use_m));
+
+    // Integer extensions shift the value to the top of the register and back. The encbits are the
+    // shift amount, with bit 6 set for a sign extension.
+    for &(inst, sign) in &[(uextend, 0), (sextend, 0x40)] {
+        for &(to, from, shift32, shift64) in &[(I32, I8, 24, 56), (I32, I16, 16, 48)] {
+            e.add32(enc(inst.bind(to).bind(from), r_rext, shift32 | sign));
+            e.add64(enc(inst.bind(to).bind(from), r_rext, shift64 | sign));
+        }
+        e.add64(enc(inst.bind(I64).bind(I8), r_rext, 56 | sign));
+        e.add64(enc(inst.bind(I64).bind(I16), r_rext, 48 | sign));
+    }
+    e.add64(enc(uextend.bind(I64).bind(I32), r_rext, 32));
+    // `addiw` sign-extends the low 32 bits.
+    e.add64(enc(
+        sextend.bind(I64).bind(I32),
+        r_icopy,
+        opimm32_bits(0b000, 0),
+    ));
+    e.add64(enc(
+        ireduce.bind(I32).bind(I64),
+        r_icopy,
+        opimm32_bits(0b000, 0),
+    ));
+
+    // Reductions to types narrower than 32 bits only reinterpret the low bits of the register.
+    for &(to, from) in &[(I16, I32), (I8, I32), (I8, I16)] {
+        e.add32(enc(ireduce.bind(to).bind(from), r_null, 0));
+        e.add64(enc(ireduce.bind(to).bind(from), r_null, 0));
+    }
+    e.add64(enc(ireduce.bind(I16).bind(I64), r_null, 0));
+    e.add64(enc(ireduce.bind(I8).bind(I64), r_null, 0));
 
     // Control flow.
 
     // Unconditional branches.
     e.add32(enc(jump, r_uj, jal_bits()));
     e.add64(enc(jump, r_uj, jal_bits()));
-    e.add32(enc(call, r_uj_call, jal_bits()));
-    e.add64(enc(call, r_uj_call, jal_bits()));
+    // Direct calls can only reach colocated functions; the others are legalized to indirect
+    // calls.
+    let call_instp = || {
+        InstructionPredicate::new_is_colocated_func(
+            shared_defs
+                .format_registry
+                .get(shared_defs.format_registry.by_name("Call")),
+            "func_ref",
+        )
+    };
+    e.add32(enc(call, r_uj_call, jal_bits()).inst_predicate(call_instp()));
+    e.add64(enc(call, r_uj_call, jal_bits()).inst_predicate(call_instp()));
 
     // Conditional branches.
     {
@@ -341,6 +579,10 @@ pub(crate) fn define<'defs>(
                 enc(br_icmp_i32.clone(), r_sb, branch_bits(f3))
                     .inst_predicate(br_icmp_instp(&br_icmp_i32, cond)),
             );
+            e.add64(
+                enc(br_icmp_i32.clone(), r_sb, branch_bits(f3))
+                    .inst_predicate(br_icmp_instp(&br_icmp_i32, cond)),
+            );
             e.add64(
                 enc(br_icmp_i64.clone(), r_sb, branch_bits(f3))
                     .inst_predicate(br_icmp_instp(&br_icmp_i64, cond)),
@@ -350,6 +592,7 @@ pub(crate) fn define<'defs>(
 
     for &(inst, f3) in &[(brz, 0b000), (brnz, 0b001)] {
         e.add32(enc(inst.bind(I32), r_sb_zero, branch_bits(f3)));
+        e.add64(enc(inst.bind(I32), r_sb_zero, branch_bits(f3)));
         e.add64(enc(inst.bind(I64), r_sb_zero, branch_bits(f3)));
         e.add32(enc(inst.bind(B1), r_sb_zero, branch_bits(f3)));
         e.add64(enc(inst.bind(B1), r_sb_zero, branch_bits(f3)));
@@ -363,21 +606,76 @@ pub(crate) fn define<'defs>(
     e.add32(enc(call_indirect.bind(I32), r_icall, jalr_bits()));
     e.add64(enc(call_indirect.bind(I64), r_icall, jalr_bits()));
 
-    // Spill and fill.
-    e.add32(enc(spill.bind(I32), r_gp_sp, store_bits(0b010)));
-    e.add64(enc(spill.bind(I32), r_gp_sp, store_bits(0b010)));
+    // Spill and fill. The store and load sizes match the stack slot sizes.
+    for &(ty, store_f3, load_f3) in &[
+        (LaneType::from(I8), 0b000, 0b100),
+        (LaneType::from(I16), 0b001, 0b101),
+        (LaneType::from(I32), 0b010, 0b010),
+        (LaneType::from(B1), 0b000, 0b100),
+    ] {
+        e.add32(enc(spill.bind(ty), r_gp_sp, store_bits(store_f3)));
+        e.add64(enc(spill.bind(ty), r_gp_sp, store_bits(store_f3)));
+        e.add32(enc(fill.bind(ty), r_gp_fi, load_bits(load_f3)));
+        e.add64(enc(fill.bind(ty), r_gp_fi, load_bits(load_f3)));
+        e.add32(enc(regspill.bind(ty), r_gp_rsp, store_bits(store_f3)));
+        e.add64(enc(regspill.bind(ty), r_gp_rsp, store_bits(store_f3)));
+        e.add32(enc(regfill.bind(ty), r_gp_rfi, load_bits(load_f3)));
+        e.add64(enc(regfill.bind(ty), r_gp_rfi, load_bits(load_f3)));
+    }
     e.add64(enc(spill.bind(I64), r_gp_sp, store_bits(0b011)));
-    e.add32(enc(fill.bind(I32), r_gp_fi, load_bits(0b010)));
-    e.add64(enc(fill.bind(I32), r_gp_fi, load_bits(0b010)));
     e.add64(enc(fill.bind(I64), r_gp_fi, load_bits(0b011)));
+    e.add64(enc(regspill.bind(I64), r_gp_rsp, store_bits(0b011)));
+    e.add64(enc(regfill.bind(I64), r_gp_rfi, load_bits(0b011)));
+
+    for &(ty, f3, isap) in &[(F32, 0b010, use_f), (F64, 0b011, use_d)] {
+        e.add32(enc(spill.bind(ty), r_fp_sp, fstore_bits(f3)).isa_predicate(isap));
+        e.add64(enc(spill.bind(ty), r_fp_sp, fstore_bits(f3)).isa_predicate(isap));
+        e.add32(enc(fill.bind(ty), r_fp_fi, fload_bits(f3)).isa_predicate(isap));
+        e.add64(enc(fill.bind(ty), r_fp_fi, fload_bits(f3)).isa_predicate(isap));
+        e.add32(enc(regspill.bind(ty), r_fp_rsp, fstore_bits(f3)).isa_predicate(isap));
+        e.add64(enc(regspill.bind(ty), r_fp_rsp, fstore_bits(f3)).isa_predicate(isap));
+        e.add32(enc(regfill.bind(ty), r_fp_rfi, fload_bits(f3)).isa_predicate(isap));
+        e.add64(enc(regfill.bind(ty), r_fp_rfi, fload_bits(f3)).isa_predicate(isap));
+    }
 
     // No-op fills, created by late-stage redundant-fill removal.
-    for &ty in &[I64, I32] {
+    for &ty in &[I64, I32, I16, I8] {
         e.add64(enc(fill_nop.bind(ty), r_fillnull, 0));
         e.add32(enc(fill_nop.bind(ty), r_fillnull, 0));
     }
     e.add64(enc(fill_nop.bind(B1), r_fillnull, 0));
     e.add32(enc(fill_nop.bind(B1), r_fillnull, 0));
+    for &ty in &[F64, F32] {
+        e.add64(enc(fill_nop.bind(ty), r_ffillnull, 0));
+        e.add32(enc(fill_nop.bind(ty), r_ffillnull, 0));
+    }
+
+    // Direct stack slot accesses and addresses.
+    e.add32(enc(stack_store.bind(I32), r_gp_ss, store_bits(0b010)));
+    e.add64(enc(stack_store.bind(I32), r_gp_ss, store_bits(0b010)));
+    e.add64(enc(stack_store.bind(I64), r_gp_ss, store_bits(0b011)));
+    e.add32(enc(stack_load.bind(I32), r_gp_sl, load_bits(0b010)));
+    e.add64(enc(stack_load.bind(I32), r_gp_sl, load_bits(0b010)));
+    e.add64(enc(stack_load.bind(I64), r_gp_sl, load_bits(0b011)));
+    for &(ty, f3, isap) in &[(F32, 0b010, use_f), (F64, 0b011, use_d)] {
+        e.add32(enc(stack_store.bind(ty), r_fp_ss, fstore_bits(f3)).isa_predicate(isap));
+        e.add64(enc(stack_store.bind(ty), r_fp_ss, fstore_bits(f3)).isa_predicate(isap));
+        e.add32(enc(stack_load.bind(ty), r_fp_sl, fload_bits(f3)).isa_predicate(isap));
+        e.add64(enc(stack_load.bind(ty), r_fp_sl, fload_bits(f3)).isa_predicate(isap));
+    }
+    e.add32(enc(stack_addr.bind(I32), r_ispaddr, opimm_bits(0b000, 0)));
+    e.add64(enc(stack_addr.bind(I64), r_ispaddr, opimm_bits(0b000, 0)));
+
+    // Stack pointer adjustments.
+    for &(inst, r_small, sub) in &[
+        (adjust_sp_down_imm, r_iadjsp_down, 0b0100000),
+        (adjust_sp_up_imm, r_iadjsp_up, 0b0000000),
+    ] {
+        e.add32(enc(inst, r_small, opimm_bits(0b000, 0)));
+        e.add64(enc(inst, r_small, opimm_bits(0b000, 0)));
+        e.add32(enc(inst, r_radjsp, op_bits(0b000, sub)).isa_predicate(has_scratch));
+        e.add64(enc(inst, r_radjsp, op_bits(0b000, sub)));
+    }
 
     // Register copies.
     e.add32(enc(copy.bind(I32), r_icopy, opimm_bits(0b000, 0)));
@@ -388,10 +686,23 @@ pub(crate) fn define<'defs>(
     e.add64(enc(regmove.bind(I64), r_irmov, opimm_bits(0b000, 0)));
     e.add64(enc(regmove.bind(I32), r_irmov, opimm32_bits(0b000, 0)));
 
-    e.add32(enc(copy.bind(B1), r_icopy, opimm_bits(0b000, 0)));
-    e.add64(enc(copy.bind(B1), r_icopy, opimm_bits(0b000, 0)));
-    e.add32(enc(regmove.bind(B1), r_irmov, opimm_bits(0b000, 0)));
-    e.add64(enc(regmove.bind(B1), r_irmov, opimm_bits(0b000, 0)));
+    for &ty in &[LaneType::from(B1), LaneType::from(I8), LaneType::from(I16)] {
+        e.add32(enc(copy.bind(ty), r_icopy, opimm_bits(0b000, 0)));
+        e.add64(enc(copy.bind(ty), r_icopy, opimm_bits(0b000, 0)));
+        e.add32(enc(regmove.bind(ty), r_irmov, opimm_bits(0b000, 0)));
+        e.add64(enc(regmove.bind(ty), r_irmov, opimm_bits(0b000, 0)));
+    }
+
+    // Float copies are `fsgnj` with the same source register twice.
+    for &(ty, fmt, isap) in &[(F32, 0b00, use_f), (F64, 0b01, use_d)] {
+        let bits = fp_bits(0b00100, fmt, 0b000, 0);
+        e.add32(enc(copy.bind(ty), r_rfsgn, bits).isa_predicate(isap));
+        e.add64(enc(copy.bind(ty), r_rfsgn, bits).isa_predicate(isap));
+        e.add32(enc(regmove.bind(ty), r_rfrmov, bits).isa_predicate(isap));
+        e.add64(enc(regmove.bind(ty), r_rfrmov, bits).isa_predicate(isap));
+        e.add32(enc(copy_to_ssa.bind(ty), r_fcopytossa, bits).isa_predicate(isap));
+        e.add64(enc(copy_to_ssa.bind(ty), r_fcopytossa, bits).isa_predicate(isap));
+    }
 
     // Stack-slot-to-the-same-stack-slot copy, which is guaranteed to turn
     // into a no-op.
@@ -434,5 +745,232 @@ pub(crate) fn define<'defs>(
         opimm_bits(0b000, 0),
     ));
 
+    // Loads and stores. Offsets that don't fit in 12 bits are added to the base address in %x31,
+    // which RV32E doesn't have.
+    for (inst, bits, rv32) in vec![
+        (load.bind(I32).bind_any(), load_bits(0b010), true),
+        (uload8.bind(I32).bind_any(), load_bits(0b100), true),
+        (sload8.bind(I32).bind_any(), load_bits(0b000), true),
+        (uload16.bind(I32).bind_any(), load_bits(0b101), true),
+        (sload16.bind(I32).bind_any(), load_bits(0b001), true),
+        (load.bind(I64).bind_any(), load_bits(0b011), false),
+        (uload8.bind(I64).bind_any(), load_bits(0b100), false),
+        (sload8.bind(I64).bind_any(), load_bits(0b000), false),
+        (uload16.bind(I64).bind_any(), load_bits(0b101), false),
+        (sload16.bind(I64).bind_any(), load_bits(0b001), false),
+        (uload32.bind(I64), load_bits(0b110), false),
+        (sload32.bind(I64), load_bits(0b010), false),
+    ] {
+        if rv32 {
+            e.add32(enc(inst.clone(), r_iload, bits));
+            e.add32(enc(inst.clone(), r_iload_large, bits).isa_predicate(has_scratch));
+        }
+        e.add64(enc(inst.clone(), r_iload, bits));
+        e.add64(enc(inst, r_iload_large, bits));
+    }
+
+    for (inst, bits, rv32) in vec![
+        (store.bind(I32).bind_any(), store_bits(0b010), true),
+        (istore8.bind(I32).bind_any(), store_bits(0b000), true),
+        (istore16.bind(I32).bind_any(), store_bits(0b001), true),
+        (store.bind(I64).bind_any(), store_bits(0b011), false),
+        (istore8.bind(I64).bind_any(), store_bits(0b000), false),
+        (istore16.bind(I64).bind_any(), store_bits(0b001), false),
+        (istore32.bind(I64).bind_any(), store_bits(0b010), false),
+    ] {
+        if rv32 {
+            e.add32(enc(inst.clone(), r_sstore, bits));
+            e.add32(enc(inst.clone(), r_sstore_large, bits).isa_predicate(has_scratch));
+        }
+        e.add64(enc(inst.clone(), r_sstore, bits));
+        e.add64(enc(inst, r_sstore_large, bits));
+    }
+
+    for &(ty, f3, isap) in &[(F32, 0b010, use_f), (F64, 0b011, use_d)] {
+        let load_ty = load.bind(ty).bind_any();
+        let store_ty = store.bind(ty).bind_any();
+        e.add32(enc(load_ty.clone(), r_ifload, fload_bits(f3)).isa_predicate(isap));
+        e.add32(enc(load_ty.clone(), r_ifload_large, fload_bits(f3)).isa_predicate(isap));
+        e.add64(enc(load_ty.clone(), r_ifload, fload_bits(f3)).isa_predicate(isap));
+        e.add64(enc(load_ty, r_ifload_large, fload_bits(f3)).isa_predicate(isap));
+        e.add32(enc(store_ty.clone(), r_sfstore, fstore_bits(f3)).isa_predicate(isap));
+        e.add32(enc(store_ty.clone(), r_sfstore_large, fstore_bits(f3)).isa_predicate(isap));
+        e.add64(enc(store_ty.clone(), r_sfstore, fstore_bits(f3)).isa_predicate(isap));
+        e.add64(enc(store_ty, r_sfstore_large, fstore_bits(f3)).isa_predicate(isap));
+    }
+
+    // "F" and "D" Standard Extensions for floating point, gated by the `use_f` and `use_d` flags.
+    // The arithmetic instructions use round to nearest, ties to even.
+    for &(ty, fmt, isap) in &[(F32, 0b00, use_f), (F64, 0b01, use_d)] {
+        for &(inst, funct5) in &[
+            (fadd, 0b00000),
+            (fsub, 0b00001),
+            (fmul, 0b00010),
+            (fdiv, 0b00011),
+        ] {
+            e.add32(enc(inst.bind(ty), r_rfp, fp_bits(funct5, fmt, 0b000, 0)).isa_predicate(isap));
+            e.add64(enc(inst.bind(ty), r_rfp, fp_bits(funct5, fmt, 0b000, 0)).isa_predicate(isap));
+        }
+        let bits = fp_bits(0b01011, fmt, 0b000, 0);
+        e.add32(enc(sqrt.bind(ty), r_rfp1, bits).isa_predicate(isap));
+        e.add64(enc(sqrt.bind(ty), r_rfp1, bits).isa_predicate(isap));
+        e.add32(enc(fma.bind(ty), r_r4, fmadd_bits(fmt)).isa_predicate(isap));
+        e.add64(enc(fma.bind(ty), r_r4, fmadd_bits(fmt)).isa_predicate(isap));
+
+        // Sign injection.
+        let bits = fp_bits(0b00100, fmt, 0b000, 0);
+        e.add32(enc(fcopysign.bind(ty), r_rfp, bits).isa_predicate(isap));
+        e.add64(enc(fcopysign.bind(ty), r_rfp, bits).isa_predicate(isap));
+        let bits = fp_bits(0b00100, fmt, 0b001, 0);
+        e.add32(enc(fneg.bind(ty), r_rfsgn, bits).isa_predicate(isap));
+        e.add64(enc(fneg.bind(ty), r_rfsgn, bits).isa_predicate(isap));
+        let bits = fp_bits(0b00100, fmt, 0b010, 0);
+        e.add32(enc(fabs.bind(ty), r_rfsgn, bits).isa_predicate(isap));
+        e.add64(enc(fabs.bind(ty), r_rfsgn, bits).isa_predicate(isap));
+    }
+
+    let bits = fp_bits(0b01000, 0b00, 0b000, 1);
+    e.add32(enc(fdemote.bind(F32).bind(F64), r_rfp1, bits).isa_predicate(use_d));
+    e.add64(enc(fdemote.bind(F32).bind(F64), r_rfp1, bits).isa_predicate(use_d));
+    let bits = fp_bits(0b01000, 0b01, 0b000, 0);
+    e.add32(enc(fpromote.bind(F64).bind(F32), r_rfp1, bits).isa_predicate(use_d));
+    e.add64(enc(fpromote.bind(F64).bind(F32), r_rfp1, bits).isa_predicate(use_d));
+
+    // Floating point comparisons. The other conditions are legalized.
+    {
+        let mut var_pool = VarPool::new();
+
+        // Helper that creates an instruction predicate for a floating point condition code.
+        let mut fcmp_instp = |bound_inst: &BoundInstruction,
+                              floatcc_field: &'static str|
+         -> InstructionPredicateNode {
+            let x = var_pool.create("x");
+            let y = var_pool.create("y");
+            let cc = Literal::enumerator_for(&shared_defs.imm.floatcc, floatcc_field);
+            Apply::new(
+                bound_inst.clone().into(),
+                vec![Expr::Literal(cc), Expr::Var(x), Expr::Var(y)],
+            )
+            .inst_predicate(&shared_defs.format_registry, &var_pool)
+            .unwrap()
+        };
+
+        for &(ty, fmt, isap) in &[(F32, 0b00, use_f), (F64, 0b01, use_d)] {
+            let fcmp_ty = fcmp.bind(ty);
+            for &(cond, rm) in &[("eq", 0b010), ("lt", 0b001), ("le", 0b000)] {
+                let bits = fp_bits(0b10100, fmt, rm, 0);
+                e.add32(
+                    enc(fcmp_ty.clone(), r_rfcmp, bits)
+                        .inst_predicate(fcmp_instp(&fcmp_ty, cond))
+                        .isa_predicate(isap),
+                );
+                e.add64(
+                    enc(fcmp_ty.clone(), r_rfcmp, bits)
+                        .inst_predicate(fcmp_instp(&fcmp_ty, cond))
+                        .isa_predicate(isap),
+                );
+            }
+        }
+    }
+
+    // `fmv.w.x` and `fmv.x.w`, plus `fmv.d.x` and `fmv.x.d` on RV64D, copy raw bits between the
+    // x and f registers.
+    e.add32(
+        enc(
+            bitcast.bind(F32).bind(I32),
+            r_rgpfp,
+            fp_bits(0b11110, 0b00, 0b000, 0),
+        )
+        .isa_predicate(use_f),
+    );
+    e.add64(
+        enc(
+            bitcast.bind(F32).bind(I32),
+            r_rgpfp,
+            fp_bits(0b11110, 0b00, 0b000, 0),
+        )
+        .isa_predicate(use_f),
+    );
+    e.add32(
+        enc(
+            bitcast.bind(I32).bind(F32),
+            r_rfpgp,
+            fp_bits(0b11100, 0b00, 0b000, 0),
+        )
+        .isa_predicate(use_f),
+    );
+    e.add64(
+        enc(
+            bitcast.bind(I32).bind(F32),
+            r_rfpgp,
+            fp_bits(0b11100, 0b00, 0b000, 0),
+        )
+        .isa_predicate(use_f),
+    );
+    e.add64(
+        enc(
+            bitcast.bind(F64).bind(I64),
+            r_rgpfp,
+            fp_bits(0b11110, 0b01, 0b000, 0),
+        )
+        .isa_predicate(use_d),
+    );
+    e.add64(
+        enc(
+            bitcast.bind(I64).bind(F64),
+            r_rfpgp,
+            fp_bits(0b11100, 0b01, 0b000, 0),
+        )
+        .isa_predicate(use_d),
+    );
+
+    // Conversions between integers and floats. The rs2 field selects the integer type: 0 for
+    // `w`, 1 for `wu`, 2 for `l` and 3 for `lu`. Conversions to integers round towards zero.
+    for &(float_ty, fmt, isap) in &[(F32, 0b00, use_f), (F64, 0b01, use_d)] {
+        for &(int_ty, signed_rs2, rv32) in &[(I32, 0, true), (I64, 2, false)] {
+            for &(from_int, to_int, rs2) in &[
+                (fcvt_from_sint, fcvt_to_sint_sat, signed_rs2),
+                (fcvt_from_uint, fcvt_to_uint_sat, signed_rs2 + 1),
+            ] {
+                let from_bits = fp_bits(0b11010, fmt, 0b000, rs2);
+                let to_bits = fp_bits(0b11000, fmt, 0b001, rs2);
+                let from_int = from_int.bind(float_ty).bind(int_ty);
+                let to_int = to_int.bind(int_ty).bind(float_ty);
+                if rv32 {
+                    e.add32(enc(from_int.clone(), r_rgpfp, from_bits).isa_predicate(isap));
+                    e.add32(enc(to_int.clone(), r_rfcvt, to_bits).isa_predicate(isap));
+                }
+                e.add64(enc(from_int, r_rgpfp, from_bits).isa_predicate(isap));
+                e.add64(enc(to_int, r_rfcvt, to_bits).isa_predicate(isap));
+            }
+        }
+    }
+
+    // Jump tables.
+    e.add32(enc(jump_table_base.bind(I32), r_ujtbase, 0));
+    e.add64(enc(jump_table_base.bind(I64), r_ujtbase, 0));
+    e.add32(
+        enc(jump_table_entry.bind(I32), r_rjtentry, load_bits(0b010)).isa_predicate(has_scratch),
+    );
+    e.add64(enc(
+        jump_table_entry.bind(I64),
+        r_rjtentry,
+        load_bits(0b010),
+    ));
+    e.add32(enc(indirect_jump_table_br.bind(I32), r_ijmp, jalr_bits()));
+    e.add64(enc(indirect_jump_table_br.bind(I64), r_ijmp, jalr_bits()));
+
+    // Absolute addresses of functions and symbols.
+    e.add32(enc(func_addr.bind(I32), r_ufnaddr4, load_bits(0b010)));
+    e.add64(enc(func_addr.bind(I64), r_ufnaddr8, load_bits(0b011)));
+    e.add32(enc(symbol_value.bind(I32), r_ugvaddr4, load_bits(0b010)));
+    e.add64(enc(symbol_value.bind(I64), r_ugvaddr8, load_bits(0b011)));
+
+    // Traps.
+    e.add32(enc(trap, r_itrap, 0));
+    e.add64(enc(trap, r_itrap, 0));
+    e.add32(enc(debugtrap, r_iebreak, system_bits(0b000)));
+    e.add64(enc(debugtrap, r_iebreak, system_bits(0b000)));
+
     e
 }
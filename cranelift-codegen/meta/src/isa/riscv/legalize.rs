@@ -0,0 +1,330 @@
+use crate::cdsl::ast::{var, ExprBuilder, Literal};
+use crate::cdsl::xform::TransformGroupBuilder;
+use crate::shared::types::Int::{self, I32, I64};
+use crate::shared::Definitions as SharedDefinitions;
+
+pub(crate) fn define(shared: &mut SharedDefinitions) {
+    let mut group = TransformGroupBuilder::new(
+        "riscv_expand",
+        r#"
+    Legalize instructions by expansion.
+
+    Use RISC-V specific patterns for the conditions that have no instruction."#,
+    )
+    .isa("riscv")
+    .chain_with(shared.transform_groups.by_name("expand").id);
+
+    // List of instructions.
+    let insts = &shared.instructions;
+    let band = insts.by_name("band");
+    let band_not = insts.by_name("band_not");
+    let bnot = insts.by_name("bnot");
+    let bor = insts.by_name("bor");
+    let bxor = insts.by_name("bxor");
+    let clz = insts.by_name("clz");
+    let ctz = insts.by_name("ctz");
+    let fcmp = insts.by_name("fcmp");
+    let fcvt_to_sint = insts.by_name("fcvt_to_sint");
+    let fcvt_to_uint = insts.by_name("fcvt_to_uint");
+    let iadd = insts.by_name("iadd");
+    let iadd_imm = insts.by_name("iadd_imm");
+    let icmp = insts.by_name("icmp");
+    let icmp_imm = insts.by_name("icmp_imm");
+    let iconst = insts.by_name("iconst");
+    let imul = insts.by_name("imul");
+    let ireduce = insts.by_name("ireduce");
+    let irsub_imm = insts.by_name("irsub_imm");
+    let ishl = insts.by_name("ishl");
+    let isub = insts.by_name("isub");
+    let popcnt = insts.by_name("popcnt");
+    let rotl = insts.by_name("rotl");
+    let rotr = insts.by_name("rotr");
+    let sextend = insts.by_name("sextend");
+    let smulhi = insts.by_name("smulhi");
+    let sshr_imm = insts.by_name("sshr_imm");
+    let uextend = insts.by_name("uextend");
+    let umulhi = insts.by_name("umulhi");
+    let ushr = insts.by_name("ushr");
+    let ushr_imm = insts.by_name("ushr_imm");
+
+    let imm = &shared.imm;
+
+    // Conversions from float to int can trap, so the range checks are inserted before a
+    // saturating conversion.
+    group.custom_legalize(fcvt_to_sint, "expand_fcvt_to_int");
+    group.custom_legalize(fcvt_to_uint, "expand_fcvt_to_int");
+
+    let a = var("a");
+    let x = var("x");
+    let y = var("y");
+    let a1 = var("a1");
+    let a2 = var("a2");
+    let a3 = var("a3");
+    let a4 = var("a4");
+
+    // Integer conditions. Only `slt` and `sltu` exist, so swap the operands or negate the
+    // result for the other orderings. Equality is tested on the `xor` of the operands.
+    let intcc_eq = Literal::enumerator_for(&imm.intcc, "eq");
+    let intcc_ne = Literal::enumerator_for(&imm.intcc, "ne");
+    let intcc_slt = Literal::enumerator_for(&imm.intcc, "slt");
+    let intcc_sge = Literal::enumerator_for(&imm.intcc, "sge");
+    let intcc_sgt = Literal::enumerator_for(&imm.intcc, "sgt");
+    let intcc_sle = Literal::enumerator_for(&imm.intcc, "sle");
+    let intcc_ult = Literal::enumerator_for(&imm.intcc, "ult");
+    let intcc_uge = Literal::enumerator_for(&imm.intcc, "uge");
+    let intcc_ugt = Literal::enumerator_for(&imm.intcc, "ugt");
+    let intcc_ule = Literal::enumerator_for(&imm.intcc, "ule");
+
+    let imm64_1 = Literal::constant(&imm.imm64, 1);
+    for &ty in &[I32, I64] {
+        group.legalize(
+            def!(a = icmp.ty(intcc_eq, x, y)),
+            vec![
+                def!(a1 = bxor(x, y)),
+                def!(a = icmp_imm(intcc_ult, a1, imm64_1)),
+            ],
+        );
+        group.legalize(
+            def!(a = icmp.ty(intcc_ne, x, y)),
+            vec![
+                def!(a1 = bxor(x, y)),
+                def!(a2 = icmp_imm(intcc_ult, a1, imm64_1)),
+                def!(a = bnot(a2)),
+            ],
+        );
+    }
+
+    for &(cc, swapped_cc) in &[(&intcc_sgt, &intcc_slt), (&intcc_ugt, &intcc_ult)] {
+        group.legalize(
+            def!(a = icmp(cc, x, y)),
+            vec![def!(a = icmp(swapped_cc, y, x))],
+        );
+    }
+
+    for &(cc, inverse_cc) in &[(&intcc_sge, &intcc_slt), (&intcc_uge, &intcc_ult)] {
+        group.legalize(
+            def!(a = icmp(cc, x, y)),
+            vec![def!(a1 = icmp(inverse_cc, x, y)), def!(a = bnot(a1))],
+        );
+    }
+
+    for &(cc, inverse_cc) in &[(&intcc_sle, &intcc_slt), (&intcc_ule, &intcc_ult)] {
+        group.legalize(
+            def!(a = icmp(cc, x, y)),
+            vec![def!(a1 = icmp(inverse_cc, y, x)), def!(a = bnot(a1))],
+        );
+    }
+
+    // Floating point conditions. Only `feq`, `flt` and `fle` exist, and they are false for
+    // unordered operands.
+    let floatcc_ord = Literal::enumerator_for(&imm.floatcc, "ord");
+    let floatcc_uno = Literal::enumerator_for(&imm.floatcc, "uno");
+    let floatcc_eq = Literal::enumerator_for(&imm.floatcc, "eq");
+    let floatcc_ne = Literal::enumerator_for(&imm.floatcc, "ne");
+    let floatcc_one = Literal::enumerator_for(&imm.floatcc, "one");
+    let floatcc_ueq = Literal::enumerator_for(&imm.floatcc, "ueq");
+    let floatcc_lt = Literal::enumerator_for(&imm.floatcc, "lt");
+    let floatcc_le = Literal::enumerator_for(&imm.floatcc, "le");
+    let floatcc_gt = Literal::enumerator_for(&imm.floatcc, "gt");
+    let floatcc_ge = Literal::enumerator_for(&imm.floatcc, "ge");
+    let floatcc_ult = Literal::enumerator_for(&imm.floatcc, "ult");
+    let floatcc_ule = Literal::enumerator_for(&imm.floatcc, "ule");
+    let floatcc_ugt = Literal::enumerator_for(&imm.floatcc, "ugt");
+    let floatcc_uge = Literal::enumerator_for(&imm.floatcc, "uge");
+
+    group.legalize(
+        def!(a = fcmp(floatcc_gt, x, y)),
+        vec![def!(a = fcmp(floatcc_lt, y, x))],
+    );
+    group.legalize(
+        def!(a = fcmp(floatcc_ge, x, y)),
+        vec![def!(a = fcmp(floatcc_le, y, x))],
+    );
+    group.legalize(
+        def!(a = fcmp(floatcc_ord, x, y)),
+        vec![
+            def!(a1 = fcmp(floatcc_eq, x, x)),
+            def!(a2 = fcmp(floatcc_eq, y, y)),
+            def!(a = band(a1, a2)),
+        ],
+    );
+    group.legalize(
+        def!(a = fcmp(floatcc_one, x, y)),
+        vec![
+            def!(a1 = fcmp(floatcc_lt, x, y)),
+            def!(a2 = fcmp(floatcc_lt, y, x)),
+            def!(a = bor(a1, a2)),
+        ],
+    );
+
+    // The remaining conditions are the negations of the ones above.
+    for &(cc, inverse_cc) in &[
+        (&floatcc_uno, &floatcc_ord),
+        (&floatcc_ne, &floatcc_eq),
+        (&floatcc_ueq, &floatcc_one),
+        (&floatcc_ult, &floatcc_ge),
+        (&floatcc_ule, &floatcc_gt),
+        (&floatcc_ugt, &floatcc_le),
+        (&floatcc_uge, &floatcc_lt),
+    ] {
+        group.legalize(
+            def!(a = fcmp(cc, x, y)),
+            vec![def!(a1 = fcmp(inverse_cc, x, y)), def!(a = bnot(a1))],
+        );
+    }
+
+    // There are no rotate instructions, so combine two shifts. The shift instructions only look
+    // at the low bits of the shift amount, so the negated amount shifts by `bits - y`.
+    let imm64_0 = Literal::constant(&imm.imm64, 0);
+    group.legalize(
+        def!(a = rotl(x, y)),
+        vec![
+            def!(a1 = irsub_imm(y, imm64_0)),
+            def!(a2 = ishl(x, y)),
+            def!(a3 = ushr(x, a1)),
+            def!(a = bor(a2, a3)),
+        ],
+    );
+    group.legalize(
+        def!(a = rotr(x, y)),
+        vec![
+            def!(a1 = irsub_imm(y, imm64_0)),
+            def!(a2 = ushr(x, y)),
+            def!(a3 = ishl(x, a1)),
+            def!(a = bor(a2, a3)),
+        ],
+    );
+
+    // The high half of a 32-bit multiplication is computed with a 64-bit multiplication.
+    let imm64_32 = Literal::constant(&imm.imm64, 32);
+    group.legalize(
+        def!(a = umulhi.I32(x, y)),
+        vec![
+            def!(a1 = uextend.I64(x)),
+            def!(a2 = uextend.I64(y)),
+            def!(a3 = imul(a1, a2)),
+            def!(a4 = ushr_imm(a3, imm64_32)),
+            def!(a = ireduce.I32(a4)),
+        ],
+    );
+    group.legalize(
+        def!(a = smulhi.I32(x, y)),
+        vec![
+            def!(a1 = sextend.I64(x)),
+            def!(a2 = sextend.I64(y)),
+            def!(a3 = imul(a1, a2)),
+            def!(a4 = sshr_imm(a3, imm64_32)),
+            def!(a = ireduce.I32(a4)),
+        ],
+    );
+
+    // Population count with the usual bit twiddling sequence, since the base ISA has no
+    // instruction for it.
+    let v1 = var("v1");
+    let v3 = var("v3");
+    let v4 = var("v4");
+    let v5 = var("v5");
+    let v6 = var("v6");
+    let v7 = var("v7");
+    let v8 = var("v8");
+    let v9 = var("v9");
+    let v10 = var("v10");
+    let v11 = var("v11");
+    let v12 = var("v12");
+    let v13 = var("v13");
+    let v14 = var("v14");
+    let v15 = var("v15");
+    let v16 = var("v16");
+    let c77 = var("c77");
+    #[allow(non_snake_case)]
+    let c0F = var("c0F");
+    let c01 = var("c01");
+
+    let imm64_4 = Literal::constant(&imm.imm64, 4);
+    for &(ty, mask77, mask0f, mask01, shift) in &[
+        (
+            I64,
+            0x7777_7777_7777_7777,
+            0x0F0F_0F0F_0F0F_0F0F,
+            0x0101_0101_0101_0101,
+            56,
+        ),
+        (I32, 0x7777_7777, 0x0F0F_0F0F, 0x0101_0101, 24),
+    ] {
+        group.legalize(
+            def!(v16 = popcnt.ty(v1)),
+            vec![
+                def!(v3 = ushr_imm(v1, imm64_1)),
+                def!(c77 = iconst(Literal::constant(&imm.imm64, mask77))),
+                def!(v4 = band(v3, c77)),
+                def!(v5 = isub(v1, v4)),
+                def!(v6 = ushr_imm(v4, imm64_1)),
+                def!(v7 = band(v6, c77)),
+                def!(v8 = isub(v5, v7)),
+                def!(v9 = ushr_imm(v7, imm64_1)),
+                def!(v10 = band(v9, c77)),
+                def!(v11 = isub(v8, v10)),
+                def!(v12 = ushr_imm(v11, imm64_4)),
+                def!(v13 = iadd(v11, v12)),
+                def!(c0F = iconst(Literal::constant(&imm.imm64, mask0f))),
+                def!(v14 = band(v13, c0F)),
+                def!(c01 = iconst(Literal::constant(&imm.imm64, mask01))),
+                def!(v15 = imul(v14, c01)),
+                def!(v16 = ushr_imm(v15, Literal::constant(&imm.imm64, shift))),
+            ],
+        );
+    }
+
+    // Count trailing zeros as the population count of the mask of the trailing zeros.
+    let imm64_minus_1 = Literal::constant(&imm.imm64, -1);
+    group.legalize(
+        def!(a = ctz(x)),
+        vec![
+            def!(a1 = iadd_imm(x, imm64_minus_1)),
+            def!(a2 = band_not(a1, x)),
+            def!(a = popcnt(a2)),
+        ],
+    );
+
+    // Count leading zeros by smearing the highest set bit to the right, and counting the
+    // remaining zeros.
+    let s1 = var("s1");
+    let s2 = var("s2");
+    let s3 = var("s3");
+    let s4 = var("s4");
+    let s5 = var("s5");
+    let s6 = var("s6");
+    let t1 = var("t1");
+    let t2 = var("t2");
+    let t3 = var("t3");
+    let t4 = var("t4");
+    let t5 = var("t5");
+    let t6 = var("t6");
+    let smear = |ty: Int| {
+        vec![
+            def!(t1 = ushr_imm.ty(x, imm64_1)),
+            def!(s1 = bor(x, t1)),
+            def!(t2 = ushr_imm.ty(s1, Literal::constant(&imm.imm64, 2))),
+            def!(s2 = bor(s1, t2)),
+            def!(t3 = ushr_imm.ty(s2, imm64_4)),
+            def!(s3 = bor(s2, t3)),
+            def!(t4 = ushr_imm.ty(s3, Literal::constant(&imm.imm64, 8))),
+            def!(s4 = bor(s3, t4)),
+            def!(t5 = ushr_imm.ty(s4, Literal::constant(&imm.imm64, 16))),
+            def!(s5 = bor(s4, t5)),
+        ]
+    };
+    let mut clz_i32 = smear(I32);
+    clz_i32.push(def!(a1 = bnot(s5)));
+    clz_i32.push(def!(a = popcnt(a1)));
+    group.legalize(def!(a = clz.I32(x)), clz_i32);
+
+    let mut clz_i64 = smear(I64);
+    clz_i64.push(def!(t6 = ushr_imm.I64(s5, imm64_32)));
+    clz_i64.push(def!(s6 = bor(s5, t6)));
+    clz_i64.push(def!(a1 = bnot(s6)));
+    clz_i64.push(def!(a = popcnt(a1)));
+    group.legalize(def!(a = clz.I64(x)), clz_i64);
+
+    group.build_and_add_to(&mut shared.transform_groups);
+}
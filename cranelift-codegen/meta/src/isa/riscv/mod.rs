@@ -4,11 +4,13 @@ use crate::cdsl::isa::TargetIsa;
 use crate::cdsl::regs::{IsaRegs, IsaRegsBuilder, RegBankBuilder, RegClassBuilder};
 use crate::cdsl::settings::{PredicateNode, SettingGroup, SettingGroupBuilder};
 
+use crate::shared::types::Bool::B1;
 use crate::shared::types::Float::{F32, F64};
-use crate::shared::types::Int::{I32, I64};
+use crate::shared::types::Int::{I16, I32, I64, I8};
 use crate::shared::Definitions as SharedDefinitions;
 
 mod encodings;
+mod legalize;
 mod recipes;
 
 fn define_settings(shared: &SettingGroup) -> SettingGroup {
@@ -41,7 +43,7 @@ fn define_settings(shared: &SettingGroup) -> SettingGroup {
         true,
     );
 
-    setting.add_bool(
+    let enable_e = setting.add_bool(
         "enable_e",
         "Enable the 'RV32E' instruction set with only 16 registers",
         false,
@@ -55,6 +57,8 @@ fn define_settings(shared: &SettingGroup) -> SettingGroup {
     setting.add_predicate("use_a", predicate!(supports_a && shared_enable_atomics));
     setting.add_predicate("use_f", predicate!(supports_f && shared_enable_float));
     setting.add_predicate("use_d", predicate!(supports_d && shared_enable_float));
+    // Large memory offsets are computed in %x31, which RV32E doesn't have.
+    setting.add_predicate("has_scratch", predicate!(!enable_e));
     setting.add_predicate(
         "full_float",
         predicate!(shared_enable_simd && supports_f && supports_d),
@@ -96,6 +100,7 @@ pub(crate) fn define(shared_defs: &mut SharedDefinitions) -> TargetIsa {
         &shared_defs.format_registry,
     )
     .build();
+    legalize::define(shared_defs);
 
     // CPU modes for 32-bit and 64-bit operation.
     let mut rv_32 = CpuMode::new("RV32");
@@ -103,18 +108,26 @@ pub(crate) fn define(shared_defs: &mut SharedDefinitions) -> TargetIsa {
 
     let expand = shared_defs.transform_groups.by_name("expand");
     let narrow = shared_defs.transform_groups.by_name("narrow");
+    let widen = shared_defs.transform_groups.by_name("widen");
+    let riscv_expand = shared_defs.transform_groups.by_name("riscv_expand");
     rv_32.legalize_monomorphic(expand);
     rv_32.legalize_default(narrow);
-    rv_32.legalize_type(I32, expand);
-    rv_32.legalize_type(F32, expand);
-    rv_32.legalize_type(F64, expand);
+    rv_32.legalize_type(B1, expand);
+    rv_32.legalize_type(I8, widen);
+    rv_32.legalize_type(I16, widen);
+    rv_32.legalize_type(I32, riscv_expand);
+    rv_32.legalize_type(F32, riscv_expand);
+    rv_32.legalize_type(F64, riscv_expand);
 
     rv_64.legalize_monomorphic(expand);
     rv_64.legalize_default(narrow);
-    rv_64.legalize_type(I32, expand);
-    rv_64.legalize_type(I64, expand);
-    rv_64.legalize_type(F32, expand);
-    rv_64.legalize_type(F64, expand);
+    rv_64.legalize_type(B1, expand);
+    rv_64.legalize_type(I8, widen);
+    rv_64.legalize_type(I16, widen);
+    rv_64.legalize_type(I32, riscv_expand);
+    rv_64.legalize_type(I64, riscv_expand);
+    rv_64.legalize_type(F32, riscv_expand);
+    rv_64.legalize_type(F64, riscv_expand);
 
     let recipes = recipes::define(shared_defs, &regs);
 
@@ -61,19 +61,36 @@ pub(crate) fn define<'formats>(
     let f_binary_imm = formats.by_name("BinaryImm");
     let f_branch = formats.by_name("Branch");
     let f_branch_icmp = formats.by_name("BranchIcmp");
+    let f_branch_table_base = formats.by_name("BranchTableBase");
+    let f_branch_table_entry = formats.by_name("BranchTableEntry");
     let f_call = formats.by_name("Call");
     let f_call_indirect = formats.by_name("CallIndirect");
     let f_copy_to_ssa = formats.by_name("CopyToSsa");
+    let f_float_compare = formats.by_name("FloatCompare");
+    let f_func_addr = formats.by_name("FuncAddr");
+    let f_indirect_jump = formats.by_name("IndirectJump");
     let f_int_compare = formats.by_name("IntCompare");
     let f_int_compare_imm = formats.by_name("IntCompareImm");
     let f_jump = formats.by_name("Jump");
+    let f_load = formats.by_name("Load");
     let f_multiary = formats.by_name("MultiAry");
+    let f_nullary = formats.by_name("NullAry");
+    let f_reg_fill = formats.by_name("RegFill");
+    let f_reg_spill = formats.by_name("RegSpill");
     let f_regmove = formats.by_name("RegMove");
+    let f_stack_load = formats.by_name("StackLoad");
+    let f_stack_store = formats.by_name("StackStore");
+    let f_store = formats.by_name("Store");
+    let f_ternary = formats.by_name("Ternary");
+    let f_trap = formats.by_name("Trap");
     let f_unary = formats.by_name("Unary");
+    let f_unary_bool = formats.by_name("UnaryBool");
+    let f_unary_global_value = formats.by_name("UnaryGlobalValue");
     let f_unary_imm = formats.by_name("UnaryImm");
 
     // Register classes shorthands.
     let gpr = regs.class_by_name("GPR");
+    let fpr = regs.class_by_name("FPR");
 
     // Definitions.
     let mut recipes = RecipeGroup::new(&shared_defs.format_registry);
@@ -256,20 +273,245 @@ pub(crate) fn define<'formats>(
             ),
     );
 
-    // Spill of a GPR.
+    // Constants that don't fit in a single instruction are built with a `lui`, `addi(w)` and
+    // `slli` sequence. The encbits are 1 in RV64 mode, and 2 when the constant is a 32-bit value.
     recipes.push(
-        EncodingRecipeBuilder::new("GPsp", f_unary, 4)
+        EncodingRecipeBuilder::new("Ili", f_unary_imm, 4)
+            .operands_out(vec![gpr])
+            .compute_size("size_for_li")
+            .emit("put_li(bits, imm.into(), out_reg0, sink);"),
+    );
+
+    // Boolean constants are materialized as `addi rd, %x0, imm`.
+    recipes.push(
+        EncodingRecipeBuilder::new("Ibconst", f_unary_bool, 4)
+            .operands_out(vec![gpr])
+            .emit("put_i(bits, 0, imm.into(), out_reg0, sink);"),
+    );
+
+    // Boolean negation is `xori rd, rs, 1`.
+    recipes.push(
+        EncodingRecipeBuilder::new("Ibnot", f_unary, 4)
             .operands_in(vec![gpr])
-            .operands_out(vec![Stack::new(gpr)])
-            .emit("unimplemented!();"),
+            .operands_out(vec![gpr])
+            .emit("put_i(bits, in_reg0, 1, out_reg0, sink);"),
     );
 
-    // Fill of a GPR.
+    // Integer extensions are a pair of shifts. The encbits are the shift amount, with bit 6 set
+    // for a sign extension.
     recipes.push(
-        EncodingRecipeBuilder::new("GPfi", f_unary, 4)
-            .operands_in(vec![Stack::new(gpr)])
+        EncodingRecipeBuilder::new("Rext", f_unary, 8)
+            .operands_in(vec![gpr])
+            .operands_out(vec![gpr])
+            .emit("put_extend(bits, in_reg0, out_reg0, sink);"),
+    );
+
+    // No-op reinterpretation of a register, used for `ireduce` to types narrower than 32 bits.
+    recipes.push(
+        EncodingRecipeBuilder::new("null", f_unary, 0)
+            .operands_in(vec![gpr])
+            .operands_out(vec![0])
+            .emit(""),
+    );
+
+    // Division and remainder instructions, which don't trap on a zero divisor by themselves.
+    recipes.push(
+        EncodingRecipeBuilder::new("Rdiv", f_binary, 12)
+            .operands_in(vec![gpr, gpr])
+            .operands_out(vec![gpr])
+            .emit(
+                r#"
+                    put_trapz(in_reg1, TrapCode::IntegerDivisionByZero, func.srclocs[inst], sink);
+                    put_r(bits, in_reg0, in_reg1, out_reg0, sink);
+                "#,
+            ),
+    );
+
+    // Signed division also traps when dividing INT_MIN by -1.
+    recipes.push(
+        EncodingRecipeBuilder::new("Rsdiv", f_binary, 32)
+            .operands_in(vec![gpr, gpr])
+            .operands_out(vec![gpr])
+            .emit(
+                r#"
+                    put_trapz(in_reg1, TrapCode::IntegerDivisionByZero, func.srclocs[inst], sink);
+                    put_sdiv_overflow_check(func.dfg.ctrl_typevar(inst), in_reg0, in_reg1,
+                                            func.srclocs[inst], sink);
+                    put_r(bits, in_reg0, in_reg1, out_reg0, sink);
+                "#,
+            ),
+    );
+
+    // Loads and stores with a 12-bit signed offset. Larger offsets are added to the base address
+    // in the %x31 scratch register first.
+    let format = formats.get(f_load);
+    for &(name, large_name, out_class) in
+        &[("Iload", "IloadLarge", gpr), ("Ifload", "IfloadLarge", fpr)]
+    {
+        recipes.push(
+            EncodingRecipeBuilder::new(name, f_load, 4)
+                .operands_in(vec![gpr])
+                .operands_out(vec![out_class])
+                .inst_predicate(InstructionPredicate::new_is_signed_int(
+                    format, "offset", 12, 0,
+                ))
+                .emit(
+                    r#"
+                        if !flags.notrap() {
+                            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+                        }
+                        let offset: i32 = offset.into();
+                        put_i(bits, in_reg0, offset.into(), out_reg0, sink);
+                    "#,
+                ),
+        );
+
+        recipes.push(
+            EncodingRecipeBuilder::new(large_name, f_load, 12)
+                .operands_in(vec![gpr])
+                .operands_out(vec![out_class])
+                .emit(
+                    r#"
+                        let offset: i32 = offset.into();
+                        let lo = put_large_offset(in_reg0, offset, sink);
+                        if !flags.notrap() {
+                            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+                        }
+                        put_i(bits, SCRATCH_REG, lo, out_reg0, sink);
+                    "#,
+                ),
+        );
+    }
+
+    let format = formats.get(f_store);
+    for &(name, large_name, in_class) in &[
+        ("Sstore", "SstoreLarge", gpr),
+        ("Sfstore", "SfstoreLarge", fpr),
+    ] {
+        recipes.push(
+            EncodingRecipeBuilder::new(name, f_store, 4)
+                .operands_in(vec![in_class, gpr])
+                .inst_predicate(InstructionPredicate::new_is_signed_int(
+                    format, "offset", 12, 0,
+                ))
+                .emit(
+                    r#"
+                        if !flags.notrap() {
+                            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+                        }
+                        let offset: i32 = offset.into();
+                        put_s(bits, in_reg1, in_reg0, offset.into(), sink);
+                    "#,
+                ),
+        );
+
+        recipes.push(
+            EncodingRecipeBuilder::new(large_name, f_store, 12)
+                .operands_in(vec![in_class, gpr])
+                .emit(
+                    r#"
+                        let offset: i32 = offset.into();
+                        let lo = put_large_offset(in_reg1, offset, sink);
+                        if !flags.notrap() {
+                            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+                        }
+                        put_s(bits, SCRATCH_REG, in_reg0, lo, sink);
+                    "#,
+                ),
+        );
+    }
+
+    // Stack pointer relative spills, fills and stack slot accesses. Offsets that don't fit in the
+    // 12-bit immediate need two more instructions to compute the address in %x31.
+    for &(spill, fill, regspill, regfill, stack_store, stack_load, class) in &[
+        ("GPsp", "GPfi", "GPrsp", "GPrfi", "GPss", "GPsl", gpr),
+        ("FPsp", "FPfi", "FPrsp", "FPrfi", "FPss", "FPsl", fpr),
+    ] {
+        recipes.push(
+            EncodingRecipeBuilder::new(spill, f_unary, 4)
+                .operands_in(vec![class])
+                .operands_out(vec![Stack::new(class)])
+                .compute_size("size_plus_maybe_offset_for_stack_slot")
+                .emit("put_sp_store(bits, in_reg0, out_stk0.offset, sink);"),
+        );
+
+        recipes.push(
+            EncodingRecipeBuilder::new(fill, f_unary, 4)
+                .operands_in(vec![Stack::new(class)])
+                .operands_out(vec![class])
+                .compute_size("size_plus_maybe_offset_for_stack_slot")
+                .emit("put_sp_load(bits, out_reg0, in_stk0.offset, sink);"),
+        );
+
+        recipes.push(
+            EncodingRecipeBuilder::new(regspill, f_reg_spill, 4)
+                .operands_in(vec![class])
+                .compute_size("size_plus_maybe_offset_for_stack_slot")
+                .emit(
+                    r#"
+                        let dst = StackRef::sp(dst, &func.stack_slots);
+                        put_sp_store(bits, src, dst.offset, sink);
+                    "#,
+                ),
+        );
+
+        recipes.push(
+            EncodingRecipeBuilder::new(regfill, f_reg_fill, 4)
+                .operands_in(vec![Stack::new(class)])
+                .compute_size("size_plus_maybe_offset_for_stack_slot")
+                .emit(
+                    r#"
+                        let src = StackRef::sp(src, &func.stack_slots);
+                        put_sp_load(bits, dst, src.offset, sink);
+                    "#,
+                ),
+        );
+
+        recipes.push(
+            EncodingRecipeBuilder::new(stack_store, f_stack_store, 4)
+                .operands_in(vec![class])
+                .compute_size("size_plus_maybe_offset_for_stack_slot")
+                .emit(
+                    r#"
+                        let sp = StackRef::sp(stack_slot, &func.stack_slots);
+                        let offset: i32 = offset.into();
+                        put_sp_store(bits, in_reg0, sp.offset.checked_add(offset).unwrap(), sink);
+                    "#,
+                ),
+        );
+
+        recipes.push(
+            EncodingRecipeBuilder::new(stack_load, f_stack_load, 4)
+                .operands_out(vec![class])
+                .compute_size("size_plus_maybe_offset_for_stack_slot")
+                .emit(
+                    r#"
+                        let sp = StackRef::sp(stack_slot, &func.stack_slots);
+                        let offset: i32 = offset.into();
+                        put_sp_load(bits, out_reg0, sp.offset.checked_add(offset).unwrap(), sink);
+                    "#,
+                ),
+        );
+    }
+
+    // Address of a stack slot.
+    recipes.push(
+        EncodingRecipeBuilder::new("Ispaddr", f_stack_load, 4)
             .operands_out(vec![gpr])
-            .emit("unimplemented!();"),
+            .compute_size("size_plus_maybe_offset_for_stack_slot")
+            .emit(
+                r#"
+                    let sp = StackRef::sp(stack_slot, &func.stack_slots);
+                    let offset: i32 = offset.into();
+                    let offset = sp.offset.checked_add(offset).unwrap();
+                    if is_signed_int(offset, 12, 0) {
+                        put_i(bits, STACK_REG, offset.into(), out_reg0, sink);
+                    } else {
+                        let lo = put_large_offset(STACK_REG, offset, sink);
+                        put_i(bits, SCRATCH_REG, lo, out_reg0, sink);
+                    }
+                "#,
+            ),
     );
 
     // Stack-slot to same stack-slot copy, which is guaranteed to turn into a no-op.
@@ -289,5 +531,239 @@ pub(crate) fn define<'formats>(
             .emit(""),
     );
 
+    recipes.push(
+        EncodingRecipeBuilder::new("ffillnull", f_unary, 0)
+            .operands_in(vec![Stack::new(fpr)])
+            .operands_out(vec![fpr])
+            .clobbers_flags(false)
+            .emit(""),
+    );
+
+    // Adjustments of the stack pointer by an immediate, with an `addi` when the immediate fits.
+    let format = formats.get(f_unary_imm);
+    recipes.push(
+        EncodingRecipeBuilder::new("IadjspDown", f_unary_imm, 4)
+            .inst_predicate(InstructionPredicate::new_is_signed_int(
+                format, "imm", 12, 0,
+            ))
+            .emit(
+                r#"
+                    let imm: i64 = imm.into();
+                    put_i(bits, STACK_REG, -imm, STACK_REG, sink);
+                "#,
+            ),
+    );
+
+    recipes.push(
+        EncodingRecipeBuilder::new("IadjspUp", f_unary_imm, 4)
+            .inst_predicate(InstructionPredicate::new_is_signed_int(
+                format, "imm", 12, 0,
+            ))
+            .emit("put_i(bits, STACK_REG, imm.into(), STACK_REG, sink);"),
+    );
+
+    // Larger adjustments build the immediate in %x31 and `add` or `sub` it.
+    recipes.push(
+        EncodingRecipeBuilder::new("Radjsp", f_unary_imm, 12)
+            .inst_predicate(InstructionPredicate::new_is_unsigned_int(
+                format, "imm", 30, 0,
+            ))
+            .emit(
+                r#"
+                    let imm: i64 = imm.into();
+                    put_li32(SCRATCH_REG, imm as i32, sink);
+                    put_r(bits, STACK_REG, SCRATCH_REG, STACK_REG, sink);
+                "#,
+            ),
+    );
+
+    // Floating point instructions.
+    //
+    // The encbits of the OP-FP instructions are `rm | (funct7 << 3) | (rs2 << 10)`. The rs2 bits
+    // provide the fixed rs2 field of unary instructions.
+    recipes.push(
+        EncodingRecipeBuilder::new("Rfp", f_binary, 4)
+            .operands_in(vec![fpr, fpr])
+            .operands_out(vec![fpr])
+            .emit("put_fp(bits, in_reg0, in_reg1, out_reg0, sink);"),
+    );
+
+    recipes.push(
+        EncodingRecipeBuilder::new("Rfp1", f_unary, 4)
+            .operands_in(vec![fpr])
+            .operands_out(vec![fpr])
+            .emit("put_fp(bits, in_reg0, 0, out_reg0, sink);"),
+    );
+
+    // Sign injection with the same register as both sources: copies, `fneg` and `fabs`.
+    recipes.push(
+        EncodingRecipeBuilder::new("Rfsgn", f_unary, 4)
+            .operands_in(vec![fpr])
+            .operands_out(vec![fpr])
+            .emit("put_fp(bits, in_reg0, in_reg0, out_reg0, sink);"),
+    );
+
+    recipes.push(
+        EncodingRecipeBuilder::new("Rfrmov", f_regmove, 4)
+            .operands_in(vec![fpr])
+            .emit("put_fp(bits, src, src, dst, sink);"),
+    );
+
+    recipes.push(
+        EncodingRecipeBuilder::new("fcopytossa", f_copy_to_ssa, 4)
+            .operands_out(vec![fpr])
+            .emit("put_fp(bits, src, src, out_reg0, sink);"),
+    );
+
+    // Float comparisons write 0 or 1 to an integer register.
+    recipes.push(
+        EncodingRecipeBuilder::new("Rfcmp", f_float_compare, 4)
+            .operands_in(vec![fpr, fpr])
+            .operands_out(vec![gpr])
+            .emit("put_fp(bits, in_reg0, in_reg1, out_reg0, sink);"),
+    );
+
+    // Moves and conversions between the register banks.
+    recipes.push(
+        EncodingRecipeBuilder::new("Rfpgp", f_unary, 4)
+            .operands_in(vec![fpr])
+            .operands_out(vec![gpr])
+            .emit("put_fp(bits, in_reg0, 0, out_reg0, sink);"),
+    );
+
+    recipes.push(
+        EncodingRecipeBuilder::new("Rgpfp", f_unary, 4)
+            .operands_in(vec![gpr])
+            .operands_out(vec![fpr])
+            .emit("put_fp(bits, in_reg0, 0, out_reg0, sink);"),
+    );
+
+    // Saturating conversions to integers. The `fcvt` instructions saturate out of range values,
+    // but convert NaN to the maximum integer, so the result is masked with `feq x, x`.
+    recipes.push(
+        EncodingRecipeBuilder::new("Rfcvt", f_unary, 16)
+            .operands_in(vec![fpr])
+            .operands_out(vec![gpr])
+            .emit("put_fcvt_sat(bits, in_reg0, out_reg0, sink);"),
+    );
+
+    // R4-type fused multiply-add.
+    // The encbits are `opcode[6:2] | (rm << 5) | (fmt << 8)`.
+    recipes.push(
+        EncodingRecipeBuilder::new("R4", f_ternary, 4)
+            .operands_in(vec![fpr, fpr, fpr])
+            .operands_out(vec![fpr])
+            .emit("put_r4(bits, in_reg0, in_reg1, in_reg2, out_reg0, sink);"),
+    );
+
+    // Traps are the canonical illegal instruction `unimp`.
+    recipes.push(EncodingRecipeBuilder::new("Itrap", f_trap, 4).emit(
+        r#"
+                    sink.trap(code, func.srclocs[inst]);
+                    put_unimp(sink);
+                "#,
+    ));
+
+    recipes.push(
+        EncodingRecipeBuilder::new("Iebreak", f_nullary, 4).emit("put_i(bits, 0, 1, 0, sink);"),
+    );
+
+    // The jump table base is materialized with `auipc` followed by `addi`, since the tables
+    // follow the code at a known offset.
+    recipes.push(
+        EncodingRecipeBuilder::new("Ujtbase", f_branch_table_base, 8)
+            .operands_out(vec![gpr])
+            .emit(
+                r#"
+                    let dest = i64::from(func.jt_offsets[table]);
+                    let disp = dest - i64::from(sink.offset());
+                    put_pcrel_addr(out_reg0, disp, sink);
+                "#,
+            ),
+    );
+
+    // Load a sign-extended 32-bit jump table entry through %x31.
+    recipes.push(
+        EncodingRecipeBuilder::new("Rjtentry", f_branch_table_entry, 12)
+            .operands_in(vec![gpr, gpr])
+            .operands_out(vec![gpr])
+            .inst_predicate(InstructionPredicate::new_is_field_equal(
+                formats.get(f_branch_table_entry),
+                "imm",
+                "4".into(),
+            ))
+            .emit(
+                r#"
+                    put_rshamt(SLLI_BITS, in_reg0, 2, SCRATCH_REG, sink);
+                    put_r(ADD_BITS, SCRATCH_REG, in_reg1, SCRATCH_REG, sink);
+                    put_i(bits, SCRATCH_REG, 0, out_reg0, sink);
+                "#,
+            ),
+    );
+
+    recipes.push(
+        EncodingRecipeBuilder::new("Ijmp", f_indirect_jump, 4)
+            .operands_in(vec![gpr])
+            .emit("put_i(bits, in_reg0, 0, 0, sink);"),
+    );
+
+    // Absolute addresses are loaded from a literal placed inline and skipped over with a jump.
+    // The encoding bits are those of the `lw` or `ld` loading the literal.
+    recipes.push(
+        EncodingRecipeBuilder::new("Ufnaddr4", f_func_addr, 16)
+            .operands_out(vec![gpr])
+            .emit(
+                r#"
+                    put_literal_load(bits, 4, out_reg0, sink);
+                    sink.reloc_external(Reloc::Abs4,
+                                        &func.dfg.ext_funcs[func_ref].name,
+                                        0);
+                    sink.put4(0);
+                "#,
+            ),
+    );
+
+    recipes.push(
+        EncodingRecipeBuilder::new("Ufnaddr8", f_func_addr, 20)
+            .operands_out(vec![gpr])
+            .emit(
+                r#"
+                    put_literal_load(bits, 8, out_reg0, sink);
+                    sink.reloc_external(Reloc::Abs8,
+                                        &func.dfg.ext_funcs[func_ref].name,
+                                        0);
+                    sink.put8(0);
+                "#,
+            ),
+    );
+
+    recipes.push(
+        EncodingRecipeBuilder::new("Ugvaddr4", f_unary_global_value, 16)
+            .operands_out(vec![gpr])
+            .emit(
+                r#"
+                    put_literal_load(bits, 4, out_reg0, sink);
+                    sink.reloc_external(Reloc::Abs4,
+                                        &func.global_values[global_value].symbol_name(),
+                                        0);
+                    sink.put4(0);
+                "#,
+            ),
+    );
+
+    recipes.push(
+        EncodingRecipeBuilder::new("Ugvaddr8", f_unary_global_value, 20)
+            .operands_out(vec![gpr])
+            .emit(
+                r#"
+                    put_literal_load(bits, 8, out_reg0, sink);
+                    sink.reloc_external(Reloc::Abs8,
+                                        &func.global_values[global_value].symbol_name(),
+                                        0);
+                    sink.put8(0);
+                "#,
+            ),
+    );
+
     recipes
 }
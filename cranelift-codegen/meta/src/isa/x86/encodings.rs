@@ -2,6 +2,7 @@
 
 use std::collections::HashMap;
 
+use crate::cdsl::ast::Literal;
 use crate::cdsl::encodings::{Encoding, EncodingBuilder};
 use crate::cdsl::instructions::{
     BoundInstruction, InstSpec, Instruction, InstructionGroup, InstructionPredicate,
@@ -331,6 +332,11 @@ pub(crate) fn define(
     let adjust_sp_down = shared.by_name("adjust_sp_down");
     let adjust_sp_down_imm = shared.by_name("adjust_sp_down_imm");
     let adjust_sp_up_imm = shared.by_name("adjust_sp_up_imm");
+    let atomic_cas = shared.by_name("atomic_cas");
+    let atomic_load = shared.by_name("atomic_load");
+    let atomic_rmw_add = shared.by_name("atomic_rmw_add");
+    let atomic_rmw_xchg = shared.by_name("atomic_rmw_xchg");
+    let atomic_store = shared.by_name("atomic_store");
     let band = shared.by_name("band");
     let band_imm = shared.by_name("band_imm");
     let band_not = shared.by_name("band_not");
@@ -357,6 +363,7 @@ pub(crate) fn define(
     let ctz = shared.by_name("ctz");
     let debugtrap = shared.by_name("debugtrap");
     let extractlane = shared.by_name("extractlane");
+    let fence = shared.by_name("fence");
     let f32const = shared.by_name("f32const");
     let f64const = shared.by_name("f64const");
     let fadd = shared.by_name("fadd");
@@ -418,6 +425,7 @@ pub(crate) fn define(
     let rotl_imm = shared.by_name("rotl_imm");
     let rotr = shared.by_name("rotr");
     let rotr_imm = shared.by_name("rotr_imm");
+    let sadd_sat = shared.by_name("sadd_sat");
     let safepoint = shared.by_name("safepoint");
     let scalar_to_vector = shared.by_name("scalar_to_vector");
     let selectif = shared.by_name("selectif");
@@ -431,6 +439,7 @@ pub(crate) fn define(
     let sload8_complex = shared.by_name("sload8_complex");
     let spill = shared.by_name("spill");
     let sqrt = shared.by_name("sqrt");
+    let ssub_sat = shared.by_name("ssub_sat");
     let sshr = shared.by_name("sshr");
     let sshr_imm = shared.by_name("sshr_imm");
     let stack_addr = shared.by_name("stack_addr");
@@ -444,6 +453,7 @@ pub(crate) fn define(
     let trueff = shared.by_name("trueff");
     let trueif = shared.by_name("trueif");
     let trunc = shared.by_name("trunc");
+    let uadd_sat = shared.by_name("uadd_sat");
     let uextend = shared.by_name("uextend");
     let uload16 = shared.by_name("uload16");
     let uload16_complex = shared.by_name("uload16_complex");
@@ -453,6 +463,7 @@ pub(crate) fn define(
     let uload8_complex = shared.by_name("uload8_complex");
     let ushr = shared.by_name("ushr");
     let ushr_imm = shared.by_name("ushr_imm");
+    let usub_sat = shared.by_name("usub_sat");
     let vconst = shared.by_name("vconst");
     let x86_bsf = x86.by_name("x86_bsf");
     let x86_bsr = x86.by_name("x86_bsr");
@@ -462,6 +473,10 @@ pub(crate) fn define(
     let x86_pop = x86.by_name("x86_pop");
     let x86_pshufd = x86.by_name("x86_pshufd");
     let x86_pshufb = x86.by_name("x86_pshufb");
+    let x86_psll = x86.by_name("x86_psll");
+    let x86_psra = x86.by_name("x86_psra");
+    let x86_psrl = x86.by_name("x86_psrl");
+    let x86_ptest = x86.by_name("x86_ptest");
     let x86_push = x86.by_name("x86_push");
     let x86_sdivmodx = x86.by_name("x86_sdivmodx");
     let x86_smulx = x86.by_name("x86_smulx");
@@ -524,6 +539,7 @@ pub(crate) fn define(
     let rec_gvaddr4 = r.template("gvaddr4");
     let rec_gvaddr8 = r.template("gvaddr8");
     let rec_icscc = r.template("icscc");
+    let rec_icscc_fpr = r.template("icscc_fpr");
     let rec_icscc_ib = r.template("icscc_ib");
     let rec_icscc_id = r.template("icscc_id");
     let rec_indirect_jmp = r.template("indirect_jmp");
@@ -534,6 +550,11 @@ pub(crate) fn define(
     let rec_jt_entry = r.template("jt_entry");
     let rec_ld = r.template("ld");
     let rec_ldDisp32 = r.template("ldDisp32");
+    let rec_lockCmpxchg = r.template("lockCmpxchg");
+    let rec_lockCmpxchg_abcd = r.template("lockCmpxchg_abcd");
+    let rec_lockRmw = r.template("lockRmw");
+    let rec_lockRmw_abcd = r.template("lockRmw_abcd");
+    let rec_mfence = r.recipe("mfence");
     let rec_ldDisp8 = r.template("ldDisp8");
     let rec_ldWithIndex = r.template("ldWithIndex");
     let rec_ldWithIndexDisp32 = r.template("ldWithIndexDisp32");
@@ -542,6 +563,7 @@ pub(crate) fn define(
     let rec_null = r.recipe("null");
     let rec_null_fpr = r.recipe("null_fpr");
     let rec_pcrel_fnaddr8 = r.template("pcrel_fnaddr8");
+    let rec_pfcmp = r.template("pfcmp");
     let rec_pcrel_gvaddr8 = r.template("pcrel_gvaddr8");
     let rec_popq = r.template("popq");
     let rec_pu_id = r.template("pu_id");
@@ -580,6 +602,8 @@ pub(crate) fn define(
     let rec_stacknull = r.recipe("stacknull");
     let rec_stDisp32 = r.template("stDisp32");
     let rec_stDisp32_abcd = r.template("stDisp32_abcd");
+    let rec_stFence = r.template("stFence");
+    let rec_stFence_abcd = r.template("stFence_abcd");
     let rec_stDisp8 = r.template("stDisp8");
     let rec_stDisp8_abcd = r.template("stDisp8_abcd");
     let rec_stWithIndex = r.template("stWithIndex");
@@ -617,8 +641,11 @@ pub(crate) fn define(
     let use_lzcnt = settings.predicate_by_name("use_lzcnt");
     let use_bmi1 = settings.predicate_by_name("use_bmi1");
     let use_sse41 = settings.predicate_by_name("use_sse41");
+    let use_sse2_simd = settings.predicate_by_name("use_sse2_simd");
     let use_ssse3_simd = settings.predicate_by_name("use_ssse3_simd");
     let use_sse41_simd = settings.predicate_by_name("use_sse41_simd");
+    let use_sse42_simd = settings.predicate_by_name("use_sse42_simd");
+    let use_atomics = settings.predicate_by_name("use_atomics");
 
     // Definitions.
     let mut e = PerCpuModeEncodings::new();
@@ -965,6 +992,104 @@ pub(crate) fn define(
         e.enc_i32_i64_ld_st(sload8, true, recipe.opcodes(vec![0x0f, 0xbe]));
     }
 
+    // Atomic memory accesses. Naturally aligned loads and stores are atomic on x86; stores are
+    // followed by an `mfence` to make them sequentially consistent.
+    for recipe in &[rec_ld, rec_ldDisp8, rec_ldDisp32] {
+        for (ty, op) in vec![
+            (I8, vec![0x0f, 0xb6]),
+            (I16, vec![0x0f, 0xb7]),
+            (I32, vec![0x8b]),
+        ] {
+            e.enc_both_isap(
+                atomic_load.bind(ty).bind_any(),
+                recipe.opcodes(op),
+                use_atomics,
+            );
+        }
+        e.enc64_isap(
+            atomic_load.bind(I64).bind_any(),
+            recipe.opcodes(vec![0x8b]).rex().w(),
+            use_atomics,
+        );
+    }
+
+    // Byte accesses use the *_abcd recipes, which fall back to the plain recipes when a REX
+    // prefix is applied.
+    e.enc_both_isap(
+        atomic_store.bind(I8).bind_any(),
+        rec_stFence_abcd.opcodes(vec![0x88]),
+        use_atomics,
+    );
+    e.enc_both_isap(
+        atomic_store.bind(I16).bind_any(),
+        rec_stFence.opcodes(vec![0x66, 0x89]),
+        use_atomics,
+    );
+    e.enc_both_isap(
+        atomic_store.bind(I32).bind_any(),
+        rec_stFence.opcodes(vec![0x89]),
+        use_atomics,
+    );
+    e.enc64_isap(
+        atomic_store.bind(I64).bind_any(),
+        rec_stFence.opcodes(vec![0x89]).rex().w(),
+        use_atomics,
+    );
+
+    // `lock xadd` and `xchg`. The remaining read-modify-write operations are expanded into a
+    // `lock cmpxchg` loop.
+    for (inst, byte_op, op) in vec![
+        (atomic_rmw_add, vec![0x0f, 0xc0], vec![0x0f, 0xc1]),
+        (atomic_rmw_xchg, vec![0x86], vec![0x87]),
+    ] {
+        e.enc_both_isap(
+            inst.bind(I8).bind_any(),
+            rec_lockRmw_abcd.opcodes(byte_op),
+            use_atomics,
+        );
+        let mut op16 = vec![0x66];
+        op16.extend(op.iter());
+        e.enc_both_isap(
+            inst.bind(I16).bind_any(),
+            rec_lockRmw.opcodes(op16),
+            use_atomics,
+        );
+        e.enc_both_isap(
+            inst.bind(I32).bind_any(),
+            rec_lockRmw.opcodes(op.clone()),
+            use_atomics,
+        );
+        e.enc64_isap(
+            inst.bind(I64).bind_any(),
+            rec_lockRmw.opcodes(op.clone()).rex().w(),
+            use_atomics,
+        );
+    }
+
+    e.enc_both_isap(
+        atomic_cas.bind(I8).bind_any(),
+        rec_lockCmpxchg_abcd.opcodes(vec![0x0f, 0xb0]),
+        use_atomics,
+    );
+    e.enc_both_isap(
+        atomic_cas.bind(I16).bind_any(),
+        rec_lockCmpxchg.opcodes(vec![0x66, 0x0f, 0xb1]),
+        use_atomics,
+    );
+    e.enc_both_isap(
+        atomic_cas.bind(I32).bind_any(),
+        rec_lockCmpxchg.opcodes(vec![0x0f, 0xb1]),
+        use_atomics,
+    );
+    e.enc64_isap(
+        atomic_cas.bind(I64).bind_any(),
+        rec_lockCmpxchg.opcodes(vec![0x0f, 0xb1]).rex().w(),
+        use_atomics,
+    );
+
+    e.enc32_rec(fence, rec_mfence, 0);
+    e.enc64_rec(fence, rec_mfence, 0);
+
     e.enc_i32_i64(fill, rec_fillSib32.opcodes(vec![0x8b]));
     e.enc_i32_i64(regfill, rec_regfill32.opcodes(vec![0x8b]));
     e.enc_r32_r64_rex_only(fill, rec_fillSib32.opcodes(vec![0x8b]));
@@ -1842,6 +1967,182 @@ pub(crate) fn define(
         e.enc_32_64_maybe_isap(instruction, template, None); // from SSE
     }
 
+    // SIMD loads and stores use the unaligned MOVUPS; wasm does not guarantee that vector
+    // accesses are aligned.
+    for ty in ValueType::all_lane_types().filter(allowed_simd_type) {
+        let vload = load.bind_vector_from_lane(ty, sse_vector_size).bind_any();
+        e.enc_both(vload.clone(), rec_fld.opcodes(vec![0x0f, 0x10]));
+        e.enc_both(vload.clone(), rec_fldDisp8.opcodes(vec![0x0f, 0x10]));
+        e.enc_both(vload, rec_fldDisp32.opcodes(vec![0x0f, 0x10]));
+
+        let vload_complex = load_complex.bind_vector_from_lane(ty, sse_vector_size);
+        e.enc_both(
+            vload_complex.clone(),
+            rec_fldWithIndex.opcodes(vec![0x0f, 0x10]),
+        );
+        e.enc_both(
+            vload_complex.clone(),
+            rec_fldWithIndexDisp8.opcodes(vec![0x0f, 0x10]),
+        );
+        e.enc_both(
+            vload_complex,
+            rec_fldWithIndexDisp32.opcodes(vec![0x0f, 0x10]),
+        );
+
+        let vstore = store.bind_vector_from_lane(ty, sse_vector_size).bind_any();
+        e.enc_both(vstore.clone(), rec_fst.opcodes(vec![0x0f, 0x11]));
+        e.enc_both(vstore.clone(), rec_fstDisp8.opcodes(vec![0x0f, 0x11]));
+        e.enc_both(vstore, rec_fstDisp32.opcodes(vec![0x0f, 0x11]));
+
+        let vstore_complex = store_complex.bind_vector_from_lane(ty, sse_vector_size);
+        e.enc_both(
+            vstore_complex.clone(),
+            rec_fstWithIndex.opcodes(vec![0x0f, 0x11]),
+        );
+        e.enc_both(
+            vstore_complex.clone(),
+            rec_fstWithIndexDisp8.opcodes(vec![0x0f, 0x11]),
+        );
+        e.enc_both(
+            vstore_complex,
+            rec_fstWithIndexDisp32.opcodes(vec![0x0f, 0x11]),
+        );
+
+        // Spills, fills and register copies, needed by the register allocator.
+        let vfill = fill.bind_vector_from_lane(ty, sse_vector_size);
+        e.enc_both(vfill, rec_ffillSib32.opcodes(vec![0x0f, 0x10]));
+        let vregfill = regfill.bind_vector_from_lane(ty, sse_vector_size);
+        e.enc_both(vregfill, rec_fregfill32.opcodes(vec![0x0f, 0x10]));
+        let vspill = spill.bind_vector_from_lane(ty, sse_vector_size);
+        e.enc_both(vspill, rec_fspillSib32.opcodes(vec![0x0f, 0x11]));
+        let vregspill = regspill.bind_vector_from_lane(ty, sse_vector_size);
+        e.enc_both(vregspill, rec_fregspill32.opcodes(vec![0x0f, 0x11]));
+
+        // MOVAPS
+        let vcopy = copy.bind_vector_from_lane(ty, sse_vector_size);
+        e.enc_both(vcopy, rec_furm.opcodes(vec![0x0f, 0x28]));
+        let vregmove = regmove.bind_vector_from_lane(ty, sse_vector_size);
+        e.enc32(vregmove.clone(), rec_frmov.opcodes(vec![0x0f, 0x28]));
+        e.enc64(vregmove, rec_frmov.opcodes(vec![0x0f, 0x28]).rex());
+    }
+
+    // SIMD integer arithmetic, indexed by lane width.
+    let mut simd_int_binops: Vec<(&Instruction, u16, Vec<u8>, SettingPredicateNumber)> = vec![
+        (iadd, 8, vec![0x66, 0x0f, 0xfc], use_sse2_simd), // PADDB
+        (iadd, 16, vec![0x66, 0x0f, 0xfd], use_sse2_simd), // PADDW
+        (iadd, 32, vec![0x66, 0x0f, 0xfe], use_sse2_simd), // PADDD
+        (iadd, 64, vec![0x66, 0x0f, 0xd4], use_sse2_simd), // PADDQ
+        (isub, 8, vec![0x66, 0x0f, 0xf8], use_sse2_simd), // PSUBB
+        (isub, 16, vec![0x66, 0x0f, 0xf9], use_sse2_simd), // PSUBW
+        (isub, 32, vec![0x66, 0x0f, 0xfa], use_sse2_simd), // PSUBD
+        (isub, 64, vec![0x66, 0x0f, 0xfb], use_sse2_simd), // PSUBQ
+        (imul, 16, vec![0x66, 0x0f, 0xd5], use_sse2_simd), // PMULLW
+        (imul, 32, vec![0x66, 0x0f, 0x38, 0x40], use_sse41_simd), // PMULLD
+        (sadd_sat, 8, vec![0x66, 0x0f, 0xec], use_sse2_simd), // PADDSB
+        (sadd_sat, 16, vec![0x66, 0x0f, 0xed], use_sse2_simd), // PADDSW
+        (uadd_sat, 8, vec![0x66, 0x0f, 0xdc], use_sse2_simd), // PADDUSB
+        (uadd_sat, 16, vec![0x66, 0x0f, 0xdd], use_sse2_simd), // PADDUSW
+        (ssub_sat, 8, vec![0x66, 0x0f, 0xe8], use_sse2_simd), // PSUBSB
+        (ssub_sat, 16, vec![0x66, 0x0f, 0xe9], use_sse2_simd), // PSUBSW
+        (usub_sat, 8, vec![0x66, 0x0f, 0xd8], use_sse2_simd), // PSUBUSB
+        (usub_sat, 16, vec![0x66, 0x0f, 0xd9], use_sse2_simd), // PSUBUSW
+    ];
+
+    // SIMD shifts by a count held in the low 64 bits of an XMM register.
+    simd_int_binops.extend(vec![
+        (x86_psll, 16, vec![0x66, 0x0f, 0xf1], use_sse2_simd), // PSLLW
+        (x86_psll, 32, vec![0x66, 0x0f, 0xf2], use_sse2_simd), // PSLLD
+        (x86_psll, 64, vec![0x66, 0x0f, 0xf3], use_sse2_simd), // PSLLQ
+        (x86_psrl, 16, vec![0x66, 0x0f, 0xd1], use_sse2_simd), // PSRLW
+        (x86_psrl, 32, vec![0x66, 0x0f, 0xd2], use_sse2_simd), // PSRLD
+        (x86_psrl, 64, vec![0x66, 0x0f, 0xd3], use_sse2_simd), // PSRLQ
+        (x86_psra, 16, vec![0x66, 0x0f, 0xe1], use_sse2_simd), // PSRAW
+        (x86_psra, 32, vec![0x66, 0x0f, 0xe2], use_sse2_simd), // PSRAD
+    ]);
+
+    for (inst, lane_bits, opcodes, isap) in simd_int_binops {
+        let ty = LaneType::int_from_bits(lane_bits);
+        let instruction = inst.bind_vector_from_lane(ty, sse_vector_size);
+        let template = rec_fa.nonrex().opcodes(opcodes);
+        e.enc_32_64_maybe_isap(instruction, template, Some(isap));
+    }
+
+    // SIMD bitwise operations: PAND, POR and PXOR work on any vector type.
+    for ty in ValueType::all_lane_types().filter(allowed_simd_type) {
+        for &(inst, opcode) in &[(band, 0xdb), (bor, 0xeb), (bxor, 0xef)] {
+            let instruction = inst.bind_vector_from_lane(ty, sse_vector_size);
+            let template = rec_fa.nonrex().opcodes(vec![0x66, 0x0f, opcode]);
+            e.enc_32_64_maybe_isap(instruction, template, Some(use_sse2_simd));
+        }
+        // PANDN computes `~x&y` while `band_not(x, y)` is `x&~y`, so use the swapped recipe.
+        let instruction = band_not.bind_vector_from_lane(ty, sse_vector_size);
+        let template = rec_fax.nonrex().opcodes(vec![0x66, 0x0f, 0xdf]);
+        e.enc_32_64_maybe_isap(instruction, template, Some(use_sse2_simd));
+    }
+
+    // SIMD integer comparisons; the other condition codes are legalized in terms of these two.
+    let f_int_compare = formats.get(formats.by_name("IntCompare"));
+    let intcc_eq = Literal::enumerator_for(&shared_defs.imm.intcc, "eq");
+    let intcc_sgt = Literal::enumerator_for(&shared_defs.imm.intcc, "sgt");
+    for &(cc, lane_bits, ref opcodes, isap) in &[
+        (&intcc_eq, 8, vec![0x66, 0x0f, 0x74], use_sse2_simd), // PCMPEQB
+        (&intcc_eq, 16, vec![0x66, 0x0f, 0x75], use_sse2_simd), // PCMPEQW
+        (&intcc_eq, 32, vec![0x66, 0x0f, 0x76], use_sse2_simd), // PCMPEQD
+        (&intcc_eq, 64, vec![0x66, 0x0f, 0x38, 0x29], use_sse41_simd), // PCMPEQQ
+        (&intcc_sgt, 8, vec![0x66, 0x0f, 0x64], use_sse2_simd), // PCMPGTB
+        (&intcc_sgt, 16, vec![0x66, 0x0f, 0x65], use_sse2_simd), // PCMPGTW
+        (&intcc_sgt, 32, vec![0x66, 0x0f, 0x66], use_sse2_simd), // PCMPGTD
+        (&intcc_sgt, 64, vec![0x66, 0x0f, 0x38, 0x37], use_sse42_simd), // PCMPGTQ
+    ] {
+        let ty = LaneType::int_from_bits(lane_bits);
+        let instruction = icmp.bind_vector_from_lane(ty, sse_vector_size);
+        let template = rec_icscc_fpr.nonrex().opcodes(opcodes.clone());
+        let instp =
+            InstructionPredicate::new_is_field_equal(f_int_compare, "cond", cc.to_rust_code());
+        e.enc32_func(instruction.clone(), template.clone(), |builder| {
+            builder.isa_predicate(isap).inst_predicate(instp.clone())
+        });
+        e.enc64_func(instruction, template, |builder| {
+            builder.isa_predicate(isap).inst_predicate(instp)
+        });
+    }
+
+    // PTEST, used to legalize `vany_true` and `vall_true`.
+    for ty in ValueType::all_lane_types().filter(allowed_simd_type) {
+        let instruction = x86_ptest.bind_vector_from_lane(ty, sse_vector_size);
+        let template = rec_fcmp.nonrex().opcodes(vec![0x66, 0x0f, 0x38, 0x17]);
+        e.enc_32_64_maybe_isap(instruction, template, Some(use_sse41_simd));
+    }
+
+    // SIMD float arithmetic.
+    for &(ty, prefix) in &[(F32, None), (F64, Some(0x66))] {
+        let with_prefix =
+            |opcodes: &[u8]| -> Vec<u8> { prefix.iter().chain(opcodes.iter()).cloned().collect() };
+
+        for &(inst, opcode) in &[(fadd, 0x58), (fsub, 0x5c), (fmul, 0x59), (fdiv, 0x5e)] {
+            let instruction = inst.bind_vector_from_lane(ty, sse_vector_size);
+            let template = rec_fa.nonrex().opcodes(with_prefix(&[0x0f, opcode]));
+            e.enc_32_64_maybe_isap(instruction, template, Some(use_sse2_simd));
+        }
+
+        // SQRTPS, SQRTPD
+        let instruction = sqrt.bind_vector_from_lane(ty, sse_vector_size);
+        let template = rec_furm.nonrex().opcodes(with_prefix(&[0x0f, 0x51]));
+        e.enc_32_64_maybe_isap(instruction, template, Some(use_sse2_simd));
+
+        // CMPPS, CMPPD
+        let instruction = fcmp.bind_vector_from_lane(ty, sse_vector_size);
+        let template = rec_pfcmp.nonrex().opcodes(with_prefix(&[0x0f, 0xc2]));
+        e.enc_32_64_maybe_isap(instruction, template, Some(use_sse2_simd));
+    }
+
+    // CVTDQ2PS
+    let instruction = fcvt_from_sint
+        .bind_vector_from_lane(F32, sse_vector_size)
+        .bind_vector_from_lane(I32, sse_vector_size);
+    let template = rec_furm.nonrex().opcodes(vec![0x0f, 0x5b]);
+    e.enc_32_64_maybe_isap(instruction, template, Some(use_sse2_simd));
+
     // Reference type instructions
 
     // Null references implemented as iconst 0.
@@ -5,7 +5,7 @@ use crate::cdsl::instructions::{
     AllInstructions, InstructionBuilder as Inst, InstructionGroup, InstructionGroupBuilder,
 };
 use crate::cdsl::operands::{create_operand as operand, create_operand_doc as operand_doc};
-use crate::cdsl::types::ValueType;
+use crate::cdsl::types::{LaneType, ValueType};
 use crate::cdsl::typevar::{Interval, TypeSetBuilder, TypeVar};
 use crate::shared::immediates::Immediates;
 use crate::shared::types;
@@ -291,5 +291,77 @@ pub(crate) fn define(
         .operands_out(vec![a]),
     );
 
+    let IxN = &TypeVar::new(
+        "IxN",
+        "A SIMD vector type containing integers",
+        TypeSetBuilder::new()
+            .ints(Interval::All)
+            .simd_lanes(Interval::All)
+            .includes_scalars(false)
+            .build(),
+    );
+    let I64x2: &TypeVar = &LaneType::from(types::Int::I64).by(2).into();
+    let x = &operand_doc("x", IxN, "Vector value to shift");
+    let y = &operand_doc(
+        "y",
+        I64x2,
+        "Number of bits to shift, held in the low 64 bits",
+    );
+    let a = &operand("a", IxN);
+
+    ig.push(
+        Inst::new(
+            "x86_psll",
+            r#"
+    Shift Packed Data Left Logical -- shifts each lane of ``x`` left by the count held in the low
+    64 bits of ``y``; lanes are cleared if the count is larger than the lane width.
+    "#,
+        )
+        .operands_in(vec![x, y])
+        .operands_out(vec![a]),
+    );
+
+    ig.push(
+        Inst::new(
+            "x86_psrl",
+            r#"
+    Shift Packed Data Right Logical -- shifts each lane of ``x`` right by the count held in the low
+    64 bits of ``y``, shifting in zeroes; lanes are cleared if the count is larger than the lane
+    width.
+    "#,
+        )
+        .operands_in(vec![x, y])
+        .operands_out(vec![a]),
+    );
+
+    ig.push(
+        Inst::new(
+            "x86_psra",
+            r#"
+    Shift Packed Data Right Arithmetic -- shifts each lane of ``x`` right by the count held in the
+    low 64 bits of ``y``, shifting in sign bits; lanes are filled with their sign bit if the count
+    is larger than the lane width.
+    "#,
+        )
+        .operands_in(vec![x, y])
+        .operands_out(vec![a]),
+    );
+
+    let x = &operand_doc("x", TxN, "A vector value (i.e. held in an XMM register)");
+    let y = &operand_doc("y", TxN, "A vector value (i.e. held in an XMM register)");
+    let f = &operand("f", iflags);
+
+    ig.push(
+        Inst::new(
+            "x86_ptest",
+            r#"
+    Logical Compare -- sets the ZF flag if the bitwise AND of ``x`` and ``y`` is all zeroes and
+    the CF flag if the bitwise AND of ``y`` and the complement of ``x`` is all zeroes.
+    "#,
+        )
+        .operands_in(vec![x, y])
+        .operands_out(vec![f]),
+    );
+
     ig.build()
 }
@@ -2,8 +2,9 @@ use crate::cdsl::ast::{var, ExprBuilder, Literal};
 use crate::cdsl::instructions::InstructionGroup;
 use crate::cdsl::types::ValueType;
 use crate::cdsl::xform::TransformGroupBuilder;
-use crate::shared::types::Float::F64;
-use crate::shared::types::Int::{I32, I64};
+use crate::shared::types::Bool::{B16, B32};
+use crate::shared::types::Float::{F32, F64};
+use crate::shared::types::Int::{I16, I32, I64, I8};
 use crate::shared::Definitions as SharedDefinitions;
 
 pub(crate) fn define(shared: &mut SharedDefinitions, x86_instructions: &InstructionGroup) {
@@ -20,11 +21,15 @@ pub(crate) fn define(shared: &mut SharedDefinitions, x86_instructions: &Instruct
     // List of instructions.
     let insts = &shared.instructions;
     let band = insts.by_name("band");
+    let band_imm = insts.by_name("band_imm");
     let bitcast = insts.by_name("bitcast");
+    let bnot = insts.by_name("bnot");
     let bor = insts.by_name("bor");
+    let bxor = insts.by_name("bxor");
     let clz = insts.by_name("clz");
     let ctz = insts.by_name("ctz");
     let f64const = insts.by_name("f64const");
+    let fabs = insts.by_name("fabs");
     let fcmp = insts.by_name("fcmp");
     let fcvt_from_uint = insts.by_name("fcvt_from_uint");
     let fcvt_to_sint = insts.by_name("fcvt_to_sint");
@@ -33,28 +38,44 @@ pub(crate) fn define(shared: &mut SharedDefinitions, x86_instructions: &Instruct
     let fcvt_to_uint_sat = insts.by_name("fcvt_to_uint_sat");
     let fmax = insts.by_name("fmax");
     let fmin = insts.by_name("fmin");
+    let fneg = insts.by_name("fneg");
     let iadd = insts.by_name("iadd");
     let iconst = insts.by_name("iconst");
+    let icmp = insts.by_name("icmp");
     let imul = insts.by_name("imul");
     let insertlane = insts.by_name("insertlane");
+    let ishl = insts.by_name("ishl");
+    let ishl_imm = insts.by_name("ishl_imm");
     let isub = insts.by_name("isub");
     let popcnt = insts.by_name("popcnt");
     let raw_bitcast = insts.by_name("raw_bitcast");
     let scalar_to_vector = insts.by_name("scalar_to_vector");
     let sdiv = insts.by_name("sdiv");
     let selectif = insts.by_name("selectif");
+    let shuffle = insts.by_name("shuffle");
     let smulhi = insts.by_name("smulhi");
     let splat = insts.by_name("splat");
     let srem = insts.by_name("srem");
+    let sshr = insts.by_name("sshr");
+    let sshr_imm = insts.by_name("sshr_imm");
+    let swizzle = insts.by_name("swizzle");
+    let trueif = insts.by_name("trueif");
     let udiv = insts.by_name("udiv");
     let umulhi = insts.by_name("umulhi");
+    let ushr = insts.by_name("ushr");
     let ushr_imm = insts.by_name("ushr_imm");
     let urem = insts.by_name("urem");
+    let vall_true = insts.by_name("vall_true");
+    let vany_true = insts.by_name("vany_true");
 
     let x86_bsf = x86_instructions.by_name("x86_bsf");
     let x86_bsr = x86_instructions.by_name("x86_bsr");
     let x86_pshufb = x86_instructions.by_name("x86_pshufb");
     let x86_pshufd = x86_instructions.by_name("x86_pshufd");
+    let x86_psll = x86_instructions.by_name("x86_psll");
+    let x86_psra = x86_instructions.by_name("x86_psra");
+    let x86_psrl = x86_instructions.by_name("x86_psrl");
+    let x86_ptest = x86_instructions.by_name("x86_ptest");
     let x86_umulx = x86_instructions.by_name("x86_umulx");
     let x86_smulx = x86_instructions.by_name("x86_smulx");
 
@@ -378,5 +399,151 @@ pub(crate) fn define(shared: &mut SharedDefinitions, x86_instructions: &Instruct
         );
     }
 
+    // SIMD shuffles and integer comparisons need vector constants, so they are expanded by custom
+    // code.
+    narrow.custom_legalize(shuffle, "expand_shuffle");
+    narrow.custom_legalize(swizzle, "expand_swizzle");
+    narrow.custom_legalize(icmp, "expand_simd_icmp");
+
+    // SIMD shifts: move the masked shift amount into the low 64 bits of an XMM register, where
+    // the PSLL*, PSRL* and PSRA* instructions expect it.
+    for &(ty, lane_bits, shifts) in &[
+        (
+            I16,
+            16,
+            &[
+                (ishl, ishl_imm, x86_psll),
+                (ushr, ushr_imm, x86_psrl),
+                (sshr, sshr_imm, x86_psra),
+            ][..],
+        ),
+        (
+            I32,
+            32,
+            &[
+                (ishl, ishl_imm, x86_psll),
+                (ushr, ushr_imm, x86_psrl),
+                (sshr, sshr_imm, x86_psra),
+            ][..],
+        ),
+        (
+            I64,
+            64,
+            &[(ishl, ishl_imm, x86_psll), (ushr, ushr_imm, x86_psrl)][..],
+        ),
+    ] {
+        let lane_mask = Literal::constant(&imm.imm64, lane_bits - 1);
+        for &(shift, shift_imm, x86_shift) in shifts {
+            // The preopt pass folds constant shift amounts into the `_imm` forms.
+            let shift_imm = shift_imm.bind_vector_from_lane(ty, sse_vector_size);
+            narrow.legalize(
+                def!(a = shift_imm(x, y)),
+                vec![def!(a1 = iconst.I32(y)), def!(a = shift(x, a1))],
+            );
+
+            let shift = shift.bind_vector_from_lane(ty, sse_vector_size).bind(I32);
+            let scalar_to_vector_i32x4 =
+                scalar_to_vector.bind_vector_from_lane(I32, sse_vector_size);
+            let raw_bitcast_i32x4_to_i64x2 = raw_bitcast
+                .bind_vector_from_lane(I64, sse_vector_size)
+                .bind_vector_from_lane(I32, sse_vector_size);
+            narrow.legalize(
+                def!(a = shift(x, y)),
+                vec![
+                    def!(b = band_imm(y, lane_mask)), // wasm and CLIF shift amounts wrap around
+                    def!(c = scalar_to_vector_i32x4(b)), // MOVD zeroes the upper bits of the register
+                    def!(d = raw_bitcast_i32x4_to_i64x2(c)),
+                    def!(a = x86_shift(x, d)),
+                ],
+            );
+        }
+    }
+
+    // SIMD float comparisons: CMPPS and CMPPD only support some of the condition codes.
+    for &ty in &[F32, F64] {
+        for &(cc, rev_cc) in &[
+            (floatcc_gt, floatcc_lt),
+            (floatcc_ge, floatcc_le),
+            (floatcc_ult, floatcc_ugt),
+            (floatcc_ule, floatcc_uge),
+        ] {
+            let fcmp_vector = fcmp.bind_vector_from_lane(ty, sse_vector_size);
+            narrow.legalize(
+                def!(a = fcmp_vector(cc, x, y)),
+                vec![def!(a = fcmp(rev_cc, y, x))],
+            );
+        }
+        let fcmp_vector = fcmp.bind_vector_from_lane(ty, sse_vector_size);
+        narrow.legalize(
+            def!(a = fcmp_vector(floatcc_one, x, y)),
+            vec![
+                def!(b = fcmp(floatcc_ord, x, y)),
+                def!(c = fcmp(floatcc_ne, x, y)),
+                def!(a = band(b, c)),
+            ],
+        );
+        let fcmp_vector = fcmp.bind_vector_from_lane(ty, sse_vector_size);
+        narrow.legalize(
+            def!(a = fcmp_vector(floatcc_ueq, x, y)),
+            vec![
+                def!(b = fcmp(floatcc_uno, x, y)),
+                def!(c = fcmp(floatcc_eq, x, y)),
+                def!(a = bor(b, c)),
+            ],
+        );
+    }
+
+    // SIMD vector reductions using PTEST, which sets ZF when the AND of its operands is zero.
+    let intcc_ne = Literal::enumerator_for(&imm.intcc, "ne");
+    for &ty in &[I8, I16, I32, I64] {
+        let vany_true = vany_true.bind_vector_from_lane(ty, sse_vector_size);
+        narrow.legalize(
+            def!(y = vany_true(x)),
+            vec![def!(a = x86_ptest(x, x)), def!(y = trueif(intcc_ne, a))],
+        );
+
+        // All lanes are true if none of the lanes compares equal to zero.
+        let vall_true = vall_true.bind_vector_from_lane(ty, sse_vector_size);
+        narrow.legalize(
+            def!(y = vall_true(x)),
+            vec![
+                def!(b = bxor(x, x)),
+                def!(c = icmp(intcc_eq, x, b)),
+                def!(d = x86_ptest(c, c)),
+                def!(y = trueif(intcc_eq, d)),
+            ],
+        );
+    }
+
+    // SIMD bnot: comparing a register with itself produces the all-ones mask to XOR with. The
+    // comparison uses a lane width different from `ty` so that both bitcasts change the type.
+    for ty in ValueType::all_lane_types().filter(|t| t.lane_bits() >= 8 && t.lane_bits() <= 64) {
+        let (cmp_ty, cmp_bool_ty) = if ty.lane_bits() == 16 {
+            (I32, B32)
+        } else {
+            (I16, B16)
+        };
+        let bnot = bnot.bind_vector_from_lane(ty, sse_vector_size);
+        let raw_bitcast_to_cmp_ty = raw_bitcast
+            .bind_vector_from_lane(cmp_ty, sse_vector_size)
+            .bind_vector_from_lane(ty, sse_vector_size);
+        let raw_bitcast_from_cmp_bool_ty = raw_bitcast
+            .bind_vector_from_lane(ty, sse_vector_size)
+            .bind_vector_from_lane(cmp_bool_ty, sse_vector_size);
+        narrow.legalize(
+            def!(a = bnot(x)),
+            vec![
+                def!(b = raw_bitcast_to_cmp_ty(x)),
+                def!(c = icmp(intcc_eq, b, b)),
+                def!(d = raw_bitcast_from_cmp_bool_ty(c)),
+                def!(a = bxor(x, d)),
+            ],
+        );
+    }
+
+    // SIMD fneg and fabs flip or clear the sign bits using a vector constant.
+    narrow.custom_legalize(fneg, "expand_simd_fneg");
+    narrow.custom_legalize(fabs, "expand_simd_fabs");
+
     narrow.build_and_add_to(&mut shared.transform_groups);
 }
@@ -358,6 +358,7 @@ pub(crate) fn define<'shared>(
     let stack_fpr32 = Stack::new(fpr);
 
     // Format shorthands, prefixed with f_.
+    let f_atomic_cas = formats.by_name("AtomicCas");
     let f_binary = formats.by_name("Binary");
     let f_binary_imm = formats.by_name("BinaryImm");
     let f_branch = formats.by_name("Branch");
@@ -1779,6 +1780,173 @@ pub(crate) fn define<'shared>(
         );
     }
 
+    {
+        // Atomic memory accesses.
+        //
+        // These always address memory with a SIB byte and an explicit displacement so the size of
+        // the encoding doesn't depend on the base register.
+
+        // XX /r register-indirect store with 32-bit offset, followed by an `mfence`.
+        let st_fence = recipes.add_template_recipe(
+            EncodingRecipeBuilder::new("stFence", f_store, 9)
+                .operands_in(vec![gpr, gpr])
+                .clobbers_flags(false)
+                .emit(
+                    r#"
+                        if !flags.notrap() {
+                            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+                        }
+                        {{PUT_OP}}(bits, rex2(in_reg1, in_reg0), sink);
+                        modrm_sib_disp32(in_reg0, sink);
+                        sib_noindex(in_reg1, sink);
+                        let offset: i32 = offset.into();
+                        sink.put4(offset as u32);
+                        sink.put1(0x0f);
+                        sink.put1(0xae);
+                        sink.put1(0xf0);
+                    "#,
+                ),
+        );
+
+        // XX /r register-indirect store with 32-bit offset, followed by an `mfence`.
+        // Only ABCD allowed for stored value. This is for byte stores with no REX.
+        recipes.add_template(
+            Template::new(
+                EncodingRecipeBuilder::new("stFence_abcd", f_store, 9)
+                    .operands_in(vec![abcd, gpr])
+                    .clobbers_flags(false)
+                    .emit(
+                        r#"
+                        if !flags.notrap() {
+                            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+                        }
+                        {{PUT_OP}}(bits, rex2(in_reg1, in_reg0), sink);
+                        modrm_sib_disp32(in_reg0, sink);
+                        sib_noindex(in_reg1, sink);
+                        let offset: i32 = offset.into();
+                        sink.put4(offset as u32);
+                        sink.put1(0x0f);
+                        sink.put1(0xae);
+                        sink.put1(0xf0);
+                    "#,
+                    ),
+                formats,
+                regs,
+            )
+            .when_prefixed(st_fence),
+        );
+
+        // LOCK XX /r read-modify-write with 32-bit offset. The register operand receives the old
+        // value from memory.
+        let lock_rmw = recipes.add_template_recipe(
+            EncodingRecipeBuilder::new("lockRmw", f_store, 7)
+                .operands_in(vec![gpr, gpr])
+                .operands_out(vec![0])
+                .emit(
+                    r#"
+                        if !flags.notrap() {
+                            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+                        }
+                        sink.put1(0xf0);
+                        {{PUT_OP}}(bits, rex2(in_reg1, in_reg0), sink);
+                        modrm_sib_disp32(in_reg0, sink);
+                        sib_noindex(in_reg1, sink);
+                        let offset: i32 = offset.into();
+                        sink.put4(offset as u32);
+                    "#,
+                ),
+        );
+
+        // LOCK XX /r read-modify-write with 32-bit offset.
+        // Only ABCD allowed for the register operand. This is for byte accesses with no REX.
+        recipes.add_template(
+            Template::new(
+                EncodingRecipeBuilder::new("lockRmw_abcd", f_store, 7)
+                    .operands_in(vec![abcd, gpr])
+                    .operands_out(vec![0])
+                    .emit(
+                        r#"
+                        if !flags.notrap() {
+                            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+                        }
+                        sink.put1(0xf0);
+                        {{PUT_OP}}(bits, rex2(in_reg1, in_reg0), sink);
+                        modrm_sib_disp32(in_reg0, sink);
+                        sib_noindex(in_reg1, sink);
+                        let offset: i32 = offset.into();
+                        sink.put4(offset as u32);
+                    "#,
+                    ),
+                formats,
+                regs,
+            )
+            .when_prefixed(lock_rmw),
+        );
+
+        // LOCK CMPXCHG: the expected value is in %rax, which also receives the old value.
+        let lock_cmpxchg = recipes.add_template_recipe(
+            EncodingRecipeBuilder::new("lockCmpxchg", f_atomic_cas, 4)
+                .operands_in(vec![
+                    OperandConstraint::FixedReg(reg_rax),
+                    OperandConstraint::RegClass(gpr),
+                    OperandConstraint::RegClass(gpr),
+                ])
+                .operands_out(vec![reg_rax])
+                .emit(
+                    r#"
+                        if !flags.notrap() {
+                            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+                        }
+                        sink.put1(0xf0);
+                        {{PUT_OP}}(bits, rex2(in_reg2, in_reg1), sink);
+                        modrm_sib_disp8(in_reg1, sink);
+                        sib_noindex(in_reg2, sink);
+                        sink.put1(0);
+                    "#,
+                ),
+        );
+
+        // LOCK CMPXCHG with the replacement value in ABCD. This is for byte accesses with no REX.
+        recipes.add_template(
+            Template::new(
+                EncodingRecipeBuilder::new("lockCmpxchg_abcd", f_atomic_cas, 4)
+                    .operands_in(vec![
+                        OperandConstraint::FixedReg(reg_rax),
+                        OperandConstraint::RegClass(abcd),
+                        OperandConstraint::RegClass(gpr),
+                    ])
+                    .operands_out(vec![reg_rax])
+                    .emit(
+                        r#"
+                        if !flags.notrap() {
+                            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+                        }
+                        sink.put1(0xf0);
+                        {{PUT_OP}}(bits, rex2(in_reg2, in_reg1), sink);
+                        modrm_sib_disp8(in_reg1, sink);
+                        sib_noindex(in_reg2, sink);
+                        sink.put1(0);
+                    "#,
+                    ),
+                formats,
+                regs,
+            )
+            .when_prefixed(lock_cmpxchg),
+        );
+
+        recipes.add_recipe(
+            EncodingRecipeBuilder::new("mfence", f_nullary, 3)
+                .clobbers_flags(false)
+                .emit(
+                    r#"
+                        sink.put1(0x0f);
+                        sink.put1(0xae);
+                        sink.put1(0xf0);
+                    "#,
+                ),
+        );
+    }
+
     // Unary spill with SIB and 32-bit displacement.
     recipes.add_template_recipe(
         EncodingRecipeBuilder::new("spillSib32", f_unary, 6)
@@ -3006,6 +3174,60 @@ pub(crate) fn define<'shared>(
             ),
     );
 
+    // XX /r, RM form. Compare two vectors lane-wise; the boolean vector result is written over
+    // the first operand. The condition code is fixed by the opcode, so the encodings using this
+    // recipe have to check it with an instruction predicate.
+    recipes.add_template_recipe(
+        EncodingRecipeBuilder::new("icscc_fpr", f_int_compare, 1)
+            .operands_in(vec![fpr, fpr])
+            .operands_out(vec![0])
+            .emit(
+                r#"
+                    {{PUT_OP}}(bits, rex2(in_reg1, in_reg0), sink);
+                    modrm_rr(in_reg1, in_reg0, sink);
+                "#,
+            ),
+    );
+
+    // XX /r ib, RM form. Compare two float vectors lane-wise with CMPPS/CMPPD; the immediate
+    // byte selects the comparison predicate.
+    {
+        let pfcmp_floatccs: Vec<Literal> = ["eq", "lt", "le", "uno", "ne", "uge", "ugt", "ord"]
+            .iter()
+            .map(|name| Literal::enumerator_for(floatcc, name))
+            .collect();
+        recipes.add_template_recipe(
+            EncodingRecipeBuilder::new("pfcmp", f_float_compare, 2)
+                .operands_in(vec![fpr, fpr])
+                .operands_out(vec![0])
+                .inst_predicate(supported_floatccs_predicate(
+                    &pfcmp_floatccs,
+                    formats.get(f_float_compare),
+                ))
+                .emit(
+                    r#"
+                    // Comparison instruction.
+                    {{PUT_OP}}(bits, rex2(in_reg1, in_reg0), sink);
+                    modrm_rr(in_reg1, in_reg0, sink);
+                    // Add immediate byte indicating what type of comparison.
+                    use crate::ir::condcodes::FloatCC::*;
+                    let imm = match cond {
+                        Equal                         => 0x00,
+                        LessThan                      => 0x01,
+                        LessThanOrEqual               => 0x02,
+                        Unordered                     => 0x03,
+                        NotEqual                      => 0x04,
+                        UnorderedOrGreaterThanOrEqual => 0x05,
+                        UnorderedOrGreaterThan        => 0x06,
+                        Ordered                       => 0x07,
+                        _ => panic!("{} not supported by pfcmp", cond),
+                    };
+                    sink.put1(imm);
+                "#,
+                ),
+        );
+    }
+
     recipes.add_template_recipe(
         EncodingRecipeBuilder::new("is_zero", f_unary, 2 + 2)
             .operands_in(vec![gpr])
@@ -36,6 +36,9 @@ pub fn define(shared: &SettingGroup) -> SettingGroup {
     settings.add_predicate("use_sse41", predicate!(has_sse41));
     settings.add_predicate("use_sse42", predicate!(has_sse41 && has_sse42));
 
+    // SSE2 is part of the x86-64 baseline, so vector instructions from SSE2 only depend on the
+    // shared `enable_simd` setting.
+    settings.add_predicate("use_sse2_simd", predicate!(shared_enable_simd));
     settings.add_predicate(
         "use_ssse3_simd",
         predicate!(shared_enable_simd && has_ssse3),
@@ -71,6 +74,9 @@ pub fn define(shared: &SettingGroup) -> SettingGroup {
         predicate!(!allones_funcaddrs && !is_pic),
     );
 
+    let enable_atomics = shared.get_bool("enable_atomics");
+    settings.add_predicate("use_atomics", predicate!(enable_atomics));
+
     // Presets corresponding to x86 CPUs.
 
     settings.add_preset("baseline", preset!());
@@ -43,6 +43,12 @@ pub(crate) fn define(imm: &Immediates, entities: &EntityRefs) -> FormatRegistry
             .value()
             .imm_with_name("lane", &imm.uimm8),
     );
+    registry.insert(
+        Builder::new("Shuffle")
+            .value()
+            .value()
+            .imm_with_name("mask", &imm.uimm128),
+    );
 
     registry.insert(Builder::new("IntCompare").imm(&imm.intcc).value().value());
     registry.insert(
@@ -148,6 +154,13 @@ pub(crate) fn define(imm: &Immediates, entities: &EntityRefs) -> FormatRegistry
             .varargs()
             .imm(&imm.offset32),
     );
+    registry.insert(
+        Builder::new("AtomicCas")
+            .imm(&imm.memflags)
+            .value()
+            .value()
+            .value(),
+    );
     registry.insert(
         Builder::new("StackLoad")
             .imm(&entities.stack_slot)
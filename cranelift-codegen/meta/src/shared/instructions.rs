@@ -855,6 +855,175 @@ pub(crate) fn define(
         .can_store(true),
     );
 
+    let AtomicMem = &TypeVar::new(
+        "AtomicMem",
+        "An integer type that can be accessed atomically",
+        TypeSetBuilder::new().ints(8..64).build(),
+    );
+
+    let x = &operand_doc("x", AtomicMem, "Value to be stored");
+    let a = &operand_doc("a", AtomicMem, "Value loaded");
+    let expected = &operand_doc("expected", AtomicMem, "Value expected in memory");
+    let replacement = &operand_doc("replacement", AtomicMem, "Value to be stored");
+
+    ig.push(
+        Inst::new(
+            "atomic_load",
+            r#"
+        Atomically load from memory at ``p + Offset``.
+
+        The load is sequentially consistent with respect to all other atomic
+        memory accesses and ``fence`` instructions.
+        "#,
+        )
+        .operands_in(vec![MemFlags, p, Offset])
+        .operands_out(vec![a])
+        .can_load(true)
+        .other_side_effects(true),
+    );
+
+    ig.push(
+        Inst::new(
+            "atomic_store",
+            r#"
+        Atomically store ``x`` to memory at ``p + Offset``.
+
+        The store is sequentially consistent with respect to all other atomic
+        memory accesses and ``fence`` instructions.
+        "#,
+        )
+        .operands_in(vec![MemFlags, x, p, Offset])
+        .can_store(true)
+        .other_side_effects(true),
+    );
+
+    ig.push(
+        Inst::new(
+            "atomic_rmw_add",
+            r#"
+        Atomically replace the value ``old`` at ``p + Offset`` with ``old + x``.
+
+        The result is ``old``, the value in memory before the update.
+        "#,
+        )
+        .operands_in(vec![MemFlags, x, p, Offset])
+        .operands_out(vec![a])
+        .can_load(true)
+        .can_store(true)
+        .other_side_effects(true),
+    );
+
+    ig.push(
+        Inst::new(
+            "atomic_rmw_sub",
+            r#"
+        Atomically replace the value ``old`` at ``p + Offset`` with ``old - x``.
+
+        The result is ``old``, the value in memory before the update.
+        "#,
+        )
+        .operands_in(vec![MemFlags, x, p, Offset])
+        .operands_out(vec![a])
+        .can_load(true)
+        .can_store(true)
+        .other_side_effects(true),
+    );
+
+    ig.push(
+        Inst::new(
+            "atomic_rmw_and",
+            r#"
+        Atomically replace the value ``old`` at ``p + Offset`` with ``old & x``.
+
+        The result is ``old``, the value in memory before the update.
+        "#,
+        )
+        .operands_in(vec![MemFlags, x, p, Offset])
+        .operands_out(vec![a])
+        .can_load(true)
+        .can_store(true)
+        .other_side_effects(true),
+    );
+
+    ig.push(
+        Inst::new(
+            "atomic_rmw_or",
+            r#"
+        Atomically replace the value ``old`` at ``p + Offset`` with ``old | x``.
+
+        The result is ``old``, the value in memory before the update.
+        "#,
+        )
+        .operands_in(vec![MemFlags, x, p, Offset])
+        .operands_out(vec![a])
+        .can_load(true)
+        .can_store(true)
+        .other_side_effects(true),
+    );
+
+    ig.push(
+        Inst::new(
+            "atomic_rmw_xor",
+            r#"
+        Atomically replace the value ``old`` at ``p + Offset`` with ``old ^ x``.
+
+        The result is ``old``, the value in memory before the update.
+        "#,
+        )
+        .operands_in(vec![MemFlags, x, p, Offset])
+        .operands_out(vec![a])
+        .can_load(true)
+        .can_store(true)
+        .other_side_effects(true),
+    );
+
+    ig.push(
+        Inst::new(
+            "atomic_rmw_xchg",
+            r#"
+        Atomically replace the value ``old`` at ``p + Offset`` with ``x``.
+
+        The result is ``old``, the value in memory before the update.
+        "#,
+        )
+        .operands_in(vec![MemFlags, x, p, Offset])
+        .operands_out(vec![a])
+        .can_load(true)
+        .can_store(true)
+        .other_side_effects(true),
+    );
+
+    ig.push(
+        Inst::new(
+            "atomic_cas",
+            r#"
+        Atomically compare the value at ``p`` with ``expected`` and, if they
+        are equal, replace it with ``replacement``.
+
+        The result is the value in memory before the operation, whether or not
+        the replacement took place.
+        "#,
+        )
+        .operands_in(vec![MemFlags, expected, replacement, p])
+        .operands_out(vec![a])
+        .can_load(true)
+        .can_store(true)
+        .other_side_effects(true),
+    );
+
+    ig.push(
+        Inst::new(
+            "fence",
+            r#"
+        A sequentially consistent memory fence.
+
+        No memory access may be reordered across a fence, and the fence is
+        totally ordered with all atomic memory accesses.
+        "#,
+        )
+        .other_side_effects(true),
+    );
+
     let x = &operand_doc("x", Mem, "Value to be stored");
     let a = &operand_doc("a", Mem, "Value loaded");
     let Offset = &operand_doc("Offset", &imm.offset32, "In-bounds offset into stack slot");
@@ -1544,6 +1713,88 @@ pub(crate) fn define(
         .operands_out(vec![a]),
     );
 
+    let Tx16 = &TypeVar::new(
+        "Tx16",
+        "A SIMD vector with exactly 16 lanes of 8-bit values",
+        TypeSetBuilder::new()
+            .ints(8..8)
+            .bools(8..8)
+            .simd_lanes(16..16)
+            .includes_scalars(false)
+            .build(),
+    );
+    let x = &operand_doc("x", Tx16, "Vector providing lanes 0 to 15");
+    let y = &operand_doc("y", Tx16, "Vector providing lanes 16 to 31");
+    let mask = &operand_doc(
+        "mask",
+        &imm.uimm128,
+        "Lane selection, one byte per result lane",
+    );
+    let a = &operand("a", Tx16);
+
+    ig.push(
+        Inst::new(
+            "shuffle",
+            r#"
+        Vector shuffle.
+
+        Build a new vector by selecting lanes from ``x`` and ``y``. Byte ``i``
+        of the constant ``mask`` holds the index of the lane placed in lane
+        ``i`` of ``a``: indices 0 to 15 select lanes of ``x`` and indices 16 to
+        31 select lanes of ``y``. All other indices are invalid.
+        "#,
+        )
+        .operands_in(vec![x, y, mask])
+        .operands_out(vec![a]),
+    );
+
+    let x = &operand_doc("x", Tx16, "Vector to select lanes from");
+    let y = &operand_doc("y", Tx16, "Vector of lane indices");
+
+    ig.push(
+        Inst::new(
+            "swizzle",
+            r#"
+        Vector swizzle.
+
+        Build a new vector by selecting lanes of ``x`` with the lane indices
+        held in ``y``. Lane ``i`` of ``a`` is lane ``y[i]`` of ``x``, or zero
+        if ``y[i]`` is out of range.
+        "#,
+        )
+        .operands_in(vec![x, y])
+        .operands_out(vec![a]),
+    );
+
+    let s = &operand("s", b1);
+    let a = &operand("a", TxN);
+
+    ig.push(
+        Inst::new(
+            "vany_true",
+            r#"
+        Reduce a vector to a scalar boolean.
+
+        Return a scalar boolean true if any lane in ``a`` is non-zero, false otherwise.
+        "#,
+        )
+        .operands_in(vec![a])
+        .operands_out(vec![s]),
+    );
+
+    ig.push(
+        Inst::new(
+            "vall_true",
+            r#"
+        Reduce a vector to a scalar boolean.
+
+        Return a scalar boolean true if all lanes in ``a`` are non-zero, false otherwise.
+        "#,
+        )
+        .operands_in(vec![a])
+        .operands_out(vec![s]),
+    );
+
     let a = &operand("a", &Int.as_bool());
     let Cond = &operand("Cond", &imm.intcc);
     let x = &operand("x", Int);
@@ -1662,6 +1913,87 @@ pub(crate) fn define(
         .operands_out(vec![a]),
     );
 
+    let IxN = &TypeVar::new(
+        "IxN",
+        "A SIMD vector type containing integers",
+        TypeSetBuilder::new()
+            .ints(Interval::All)
+            .simd_lanes(Interval::All)
+            .includes_scalars(false)
+            .build(),
+    );
+    let a = &operand("a", IxN);
+    let x = &operand("x", IxN);
+    let y = &operand("y", IxN);
+
+    ig.push(
+        Inst::new(
+            "uadd_sat",
+            r#"
+        Add with unsigned saturation.
+
+        This is similar to `iadd` but the operands are interpreted as unsigned
+        integers and their summed result, instead of wrapping, will be
+        saturated to the highest unsigned integer for the controlling type
+        (e.g. `0xFF` for i8).
+        "#,
+        )
+        .operands_in(vec![x, y])
+        .operands_out(vec![a]),
+    );
+
+    ig.push(
+        Inst::new(
+            "sadd_sat",
+            r#"
+        Add with signed saturation.
+
+        This is similar to `iadd` but the operands are interpreted as signed
+        integers and their summed result, instead of wrapping, will be
+        saturated to the lowest or highest signed integer for the controlling
+        type (e.g. `0x80` or `0x7F` for i8).
+        "#,
+        )
+        .operands_in(vec![x, y])
+        .operands_out(vec![a]),
+    );
+
+    ig.push(
+        Inst::new(
+            "usub_sat",
+            r#"
+        Subtract with unsigned saturation.
+
+        This is similar to `isub` but the operands are interpreted as unsigned
+        integers and their difference, instead of wrapping, will be saturated
+        to the lowest unsigned integer for the controlling type (e.g. `0x00`
+        for i8).
+        "#,
+        )
+        .operands_in(vec![x, y])
+        .operands_out(vec![a]),
+    );
+
+    ig.push(
+        Inst::new(
+            "ssub_sat",
+            r#"
+        Subtract with signed saturation.
+
+        This is similar to `isub` but the operands are interpreted as signed
+        integers and their difference, instead of wrapping, will be saturated
+        to the lowest or highest signed integer for the controlling type
+        (e.g. `0x80` or `0x7F` for i8).
+        "#,
+        )
+        .operands_in(vec![x, y])
+        .operands_out(vec![a]),
+    );
+
+    let a = &operand("a", Int);
+    let x = &operand("x", Int);
+    let y = &operand("y", Int);
+
     ig.push(
         Inst::new(
             "imul",
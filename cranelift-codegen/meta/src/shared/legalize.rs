@@ -140,6 +140,19 @@ pub(crate) fn define(insts: &InstructionGroup, imm: &Immediates) -> TransformGro
     expand.custom_legalize(insts.by_name("stack_load"), "expand_stack_load");
     expand.custom_legalize(insts.by_name("stack_store"), "expand_stack_store");
 
+    // Custom expansions for atomic read-modify-write operations without a native encoding.
+    for &name in &[
+        "atomic_rmw_add",
+        "atomic_rmw_sub",
+        "atomic_rmw_and",
+        "atomic_rmw_or",
+        "atomic_rmw_xor",
+        "atomic_rmw_xchg",
+    ] {
+        expand.custom_legalize(insts.by_name(name), "expand_atomic_rmw");
+        widen.custom_legalize(insts.by_name(name), "expand_atomic_rmw");
+    }
+
     // List of variables to reuse in patterns.
     let x = var("x");
     let y = var("y");
@@ -15,6 +15,19 @@ pub fn define() -> SettingGroup {
         vec!["default", "best", "fastest"],
     );
 
+    settings.add_enum(
+        "loop_opts",
+        r#"
+        Loop restructuring optimizations, run when opt_level is best:
+
+        - none: Don't restructure loops.
+        - unroll: Unroll loops with a trip count known at compile time.
+        - unswitch: Hoist loop-invariant conditional branches out of loops.
+        - all: Unswitch, then unroll loops.
+        "#,
+        vec!["none", "unroll", "unswitch", "all"],
+    );
+
     settings.add_bool(
         "enable_verifier",
         r#"
@@ -0,0 +1,853 @@
+//! Decoding functions from the binary format.
+
+use super::{
+    ARGUMENT_PURPOSES, CALL_CONVS, FLOAT_CCS, INT_CCS, LANE_TYPES, MAGIC, MAX_SPARSE_ENTITIES,
+    STACK_SLOT_KINDS, TRAP_CODES, VERSION,
+};
+use crate::binemit::CodeInfo;
+use crate::entity::EntityRef;
+use crate::ir::condcodes::{FloatCC, IntCC};
+use crate::ir::immediates::{Ieee32, Ieee64, Imm64, Offset32, Uimm32, Uimm64};
+use crate::ir::{
+    AbiParam, ArgumentExtension, ArgumentLoc, Constant, Ebb, ExtFuncData, ExternalName, FuncRef,
+    Function, GlobalValue, GlobalValueData, Heap, HeapData, HeapStyle, InstructionData, JumpTable,
+    JumpTableData, LibCall, MemFlags, Opcode, SigRef, Signature, SourceLoc, StackSlot,
+    StackSlotData, Table, TableData, TrapCode, Type, Value, ValueList, ValueListPool, ValueLoc,
+};
+use crate::isa::{CallConv, Encoding};
+use crate::packed_option::ReservedValue;
+use crate::regalloc::RegDiversions;
+use core::str::{self, FromStr};
+use core::u16;
+use failure_derive::Fail;
+use std::vec::Vec;
+
+/// An error encountered while decoding a function.
+#[derive(Fail, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The bytes don't start like an encoded function.
+    #[fail(display = "not a function in the binary format")]
+    BadMagic,
+
+    /// The function was encoded with a different version of the binary format.
+    #[fail(display = "unsupported binary format version {}", _0)]
+    UnsupportedVersion(u32),
+
+    /// The bytes end in the middle of the function.
+    #[fail(display = "unexpected end of data at offset {}", _0)]
+    UnexpectedEnd(usize),
+
+    /// There are bytes left after the end of the function.
+    #[fail(display = "{} bytes after the end of the function", _0)]
+    TrailingData(usize),
+
+    /// The bytes starting at `offset` don't encode a valid `what`.
+    #[fail(display = "invalid {} at offset {}", what, offset)]
+    Invalid {
+        /// What was being decoded.
+        what: &'static str,
+        /// The offset of the invalid item in the bytes.
+        offset: usize,
+    },
+}
+
+/// Decode a function encoded by `encode_function`, along with its `CodeInfo` if it was encoded.
+pub fn decode_function(bytes: &[u8]) -> Result<(Function, Option<CodeInfo>), DecodeError> {
+    if !bytes.starts_with(&MAGIC) {
+        return Err(DecodeError::BadMagic);
+    }
+    let mut dec = Decoder::new(bytes);
+    dec.pos = MAGIC.len();
+    let version = dec.u32()?;
+    if version != VERSION {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+
+    let mut func = Function::new();
+    decode_preamble(&mut func, &mut dec)?;
+    decode_body(&mut func, &mut dec)?;
+    decode_compilation(&mut func, &mut dec)?;
+    let info = Decode::decode(&mut dec)?;
+    dec.finish()?;
+    Ok((func, info))
+}
+
+/// Decode the name and signature of `func`, and all the entities declared before its body.
+fn decode_preamble(func: &mut Function, dec: &mut Decoder) -> Result<(), DecodeError> {
+    func.name = Decode::decode(dec)?;
+    func.signature = Decode::decode(dec)?;
+
+    dec.num_stack_slots = dec.len("stack slot count")?;
+    for _ in 0..dec.num_stack_slots {
+        func.stack_slots.push(StackSlotData {
+            kind: dec.table(&STACK_SLOT_KINDS, "stack slot kind")?,
+            size: dec.u32()?,
+            offset: Decode::decode(dec)?,
+        });
+    }
+    func.stack_slots.frame_size = Decode::decode(dec)?;
+
+    // Global values can refer to each other in any order.
+    dec.num_global_values = dec.len("global value count")?;
+    for _ in 0..dec.num_global_values {
+        let offset = dec.pos;
+        let gv = match dec.byte()? {
+            0 => GlobalValueData::VMContext,
+            1 => GlobalValueData::Load {
+                base: dec.reference()?,
+                offset: Decode::decode(dec)?,
+                global_type: dec.valid_type()?,
+                readonly: Decode::decode(dec)?,
+            },
+            2 => GlobalValueData::IAddImm {
+                base: dec.reference()?,
+                offset: Decode::decode(dec)?,
+                global_type: dec.valid_type()?,
+            },
+            3 => GlobalValueData::Symbol {
+                name: Decode::decode(dec)?,
+                offset: Decode::decode(dec)?,
+                colocated: Decode::decode(dec)?,
+            },
+            _ => return Err(dec.invalid("global value", offset)),
+        };
+        func.create_global_value(gv);
+    }
+
+    dec.num_heaps = dec.len("heap count")?;
+    for _ in 0..dec.num_heaps {
+        let base = dec.reference()?;
+        let min_size = Decode::decode(dec)?;
+        let offset_guard_size = Decode::decode(dec)?;
+        let offset = dec.pos;
+        let style = match dec.byte()? {
+            0 => HeapStyle::Dynamic {
+                bound_gv: dec.reference()?,
+            },
+            1 => HeapStyle::Static {
+                bound: Decode::decode(dec)?,
+            },
+            _ => return Err(dec.invalid("heap style", offset)),
+        };
+        func.create_heap(HeapData {
+            base,
+            min_size,
+            offset_guard_size,
+            style,
+            index_type: Decode::decode(dec)?,
+        });
+    }
+
+    dec.num_tables = dec.len("table count")?;
+    for _ in 0..dec.num_tables {
+        func.create_table(TableData {
+            base_gv: dec.reference()?,
+            min_size: Decode::decode(dec)?,
+            bound_gv: dec.reference()?,
+            element_size: Decode::decode(dec)?,
+            index_type: Decode::decode(dec)?,
+        });
+    }
+
+    dec.num_sig_refs = dec.len("signature count")?;
+    for _ in 0..dec.num_sig_refs {
+        let sig = Decode::decode(dec)?;
+        func.import_signature(sig);
+    }
+
+    dec.num_func_refs = dec.len("external function count")?;
+    for _ in 0..dec.num_func_refs {
+        func.import_function(ExtFuncData {
+            name: Decode::decode(dec)?,
+            signature: dec.reference()?,
+            colocated: Decode::decode(dec)?,
+        });
+    }
+
+    dec.num_ebbs = dec.sparse_count("EBB count")?;
+    for _ in 0..dec.num_ebbs {
+        func.dfg.make_ebb();
+    }
+
+    dec.num_jump_tables = dec.len("jump table count")?;
+    for _ in 0..dec.num_jump_tables {
+        let mut jt = JumpTableData::new();
+        for _ in 0..dec.len("jump table length")? {
+            jt.push_entry(Decode::decode(dec)?);
+        }
+        func.create_jump_table(jt);
+    }
+
+    let num_constants = dec.len("constant count")?;
+    for index in 0..num_constants {
+        let offset = dec.pos;
+        let data = dec.bytes()?.to_vec();
+        let constant = func.dfg.constants.insert(data);
+        if constant.index() != index {
+            return Err(dec.invalid("duplicate constant", offset));
+        }
+        if let Some(constant_offset) = Decode::decode(dec)? {
+            func.dfg.constants.set_offset(constant, constant_offset);
+        }
+    }
+    dec.num_constants = num_constants;
+    Ok(())
+}
+
+/// Decode the values, instructions and layout of `func`.
+fn decode_body(func: &mut Function, dec: &mut Decoder) -> Result<(), DecodeError> {
+    // Values which aren't defined in the layout are left invalid.
+    dec.num_values = dec.sparse_count("value count")?;
+    for _ in 0..dec.num_values {
+        func.dfg.make_invalid_value_for_parser();
+    }
+    let mut defined = vec![false; dec.num_values];
+
+    let num_opcodes = dec.len("opcode count")?;
+    let mut opcodes = Vec::with_capacity(num_opcodes);
+    for _ in 0..num_opcodes {
+        opcodes.push(Opcode::decode(dec)?);
+    }
+
+    // The offsets of the instructions, to report the use of undefined values.
+    let mut inst_offsets = Vec::new();
+    for _ in 0..dec.len("EBB count")? {
+        let offset = dec.pos;
+        let ebb = Ebb::decode(dec)?;
+        if func.layout.is_ebb_inserted(ebb) {
+            return Err(dec.invalid("EBB inserted twice", offset));
+        }
+        func.layout.append_ebb(ebb);
+
+        let offset = dec.pos;
+        let num_params = dec.len("EBB parameter count")?;
+        if num_params > usize::from(u16::MAX) {
+            return Err(dec.invalid("EBB parameter count", offset));
+        }
+        for _ in 0..num_params {
+            let param = dec.definition(&mut defined)?;
+            let ty = dec.valid_type()?;
+            func.dfg.append_ebb_param_for_parser(ebb, ty, param);
+        }
+
+        for _ in 0..dec.len("instruction count")? {
+            let offset = dec.pos;
+            inst_offsets.push(offset);
+            let opcode = opcodes[dec.index(opcodes.len(), "opcode")?];
+            let data = InstructionData::decode_operands(opcode, dec, &mut func.dfg.value_lists)?;
+            let constraints = opcode.constraints();
+            if data.arguments(&func.dfg.value_lists).len() < constraints.num_fixed_value_arguments()
+            {
+                return Err(dec.invalid("instruction arguments", offset));
+            }
+            if let InstructionData::Call { func_ref, .. } = data {
+                let sig = func.dfg.ext_funcs[func_ref].signature;
+                if !func.dfg.signatures.is_valid(sig) {
+                    return Err(dec.invalid("call to a function without a signature", offset));
+                }
+            }
+            let inst = func.dfg.make_inst(data);
+            func.layout.append_inst(inst, ebb);
+
+            let num_results = match func.dfg.call_signature(inst) {
+                Some(sig) => func.dfg.signatures[sig].returns.len(),
+                None => constraints.num_fixed_results(),
+            };
+            let offset = dec.pos;
+            if dec.len("result count")? != num_results || num_results > usize::from(u16::MAX) {
+                return Err(dec.invalid("result count", offset));
+            }
+            for _ in 0..num_results {
+                let result = dec.definition(&mut defined)?;
+                let ty = dec.valid_type()?;
+                func.dfg.set_value_type_for_parser(result, ty);
+                func.dfg.attach_result(inst, result);
+            }
+
+            let offset = dec.pos;
+            let recipe = dec.u64()?;
+            if recipe != 0 {
+                if recipe > u64::from(u16::MAX) {
+                    return Err(dec.invalid("encoding", offset));
+                }
+                let bits = Decode::decode(dec)?;
+                func.encodings[inst] = Encoding::new(recipe as u16 - 1, bits);
+            }
+            let srcloc: SourceLoc = Decode::decode(dec)?;
+            if !srcloc.is_default() {
+                func.srclocs[inst] = srcloc;
+            }
+        }
+    }
+
+    // Check that the aliases resolve to values defined in the layout before creating them, since
+    // the data flow graph expects them to.
+    let mut aliases = Vec::new();
+    let mut alias_originals = vec![None; dec.num_values];
+    for _ in 0..dec.len("alias count")? {
+        let value = dec.definition(&mut defined)?;
+        let offset = dec.pos;
+        let original = Value::decode(dec)?;
+        alias_originals[value.index()] = Some(original);
+        aliases.push((value, original, offset));
+    }
+    for &(_, original, offset) in &aliases {
+        let mut resolved = original;
+        for _ in 0..aliases.len() {
+            match alias_originals[resolved.index()] {
+                Some(next) => resolved = next,
+                None => break,
+            }
+        }
+        if alias_originals[resolved.index()].is_some() {
+            return Err(dec.invalid("alias cycle", offset));
+        }
+        if !defined[resolved.index()] {
+            return Err(dec.invalid("alias of an undefined value", offset));
+        }
+    }
+    for &(value, original, _) in &aliases {
+        func.dfg.make_value_alias_for_serialization(original, value);
+    }
+    for &(value, _, _) in &aliases {
+        func.dfg.set_alias_type_for_parser(value);
+    }
+
+    for (ebb_inst, offset) in func
+        .layout
+        .ebbs()
+        .flat_map(|ebb| func.layout.ebb_insts(ebb))
+        .zip(inst_offsets)
+    {
+        if func
+            .dfg
+            .inst_args(ebb_inst)
+            .iter()
+            .any(|arg| !defined[arg.index()])
+        {
+            return Err(dec.invalid("use of an undefined value", offset));
+        }
+    }
+    Ok(())
+}
+
+/// Decode the results of compiling `func` which aren't part of its instructions.
+fn decode_compilation(func: &mut Function, dec: &mut Decoder) -> Result<(), DecodeError> {
+    for _ in 0..dec.len("value location count")? {
+        let value = Value::decode(dec)?;
+        func.locations[value] = Decode::decode(dec)?;
+    }
+
+    for _ in 0..dec.len("entry diversion count")? {
+        let offset = dec.pos;
+        let ebb = Ebb::decode(dec)?;
+        if func.entry_diversions.get(ebb).is_some() {
+            return Err(dec.invalid("entry diversions recorded twice", offset));
+        }
+        let mut divert = RegDiversions::new();
+        for _ in 0..dec.len("diversion count")? {
+            let offset = dec.pos;
+            let value = Value::decode(dec)?;
+            if divert.diversion(value).is_some() {
+                return Err(dec.invalid("diversion", offset));
+            }
+            match (ValueLoc::decode(dec)?, ValueLoc::decode(dec)?) {
+                (ValueLoc::Reg(from), ValueLoc::Reg(to)) => divert.regmove(value, from, to),
+                (ValueLoc::Reg(from), ValueLoc::Stack(to)) => divert.regspill(value, from, to),
+                (ValueLoc::Stack(from), ValueLoc::Reg(to)) => divert.regfill(value, from, to),
+                _ => return Err(dec.invalid("diversion", offset)),
+            }
+        }
+        divert.save_for_ebb(&mut func.entry_diversions, ebb);
+    }
+
+    for _ in 0..dec.len("EBB offset count")? {
+        let ebb = Ebb::decode(dec)?;
+        func.offsets[ebb] = dec.u32()?;
+    }
+
+    for _ in 0..dec.len("jump table offset count")? {
+        let jt = JumpTable::decode(dec)?;
+        func.jt_offsets[jt] = dec.u32()?;
+    }
+    Ok(())
+}
+
+/// A cursor over the bytes of an encoded function.
+///
+/// The decoder keeps track of the number of entities decoded so far, so entity references can be
+/// checked as they are decoded.
+pub(crate) struct Decoder<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    num_values: usize,
+    num_ebbs: usize,
+    num_stack_slots: usize,
+    num_global_values: usize,
+    num_heaps: usize,
+    num_tables: usize,
+    num_sig_refs: usize,
+    num_func_refs: usize,
+    num_jump_tables: usize,
+    num_constants: usize,
+}
+
+impl<'a> Decoder<'a> {
+    /// Create a decoder reading `bytes` from the start, which doesn't know about any entities.
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            pos: 0,
+            num_values: 0,
+            num_ebbs: 0,
+            num_stack_slots: 0,
+            num_global_values: 0,
+            num_heaps: 0,
+            num_tables: 0,
+            num_sig_refs: 0,
+            num_func_refs: 0,
+            num_jump_tables: 0,
+            num_constants: 0,
+        }
+    }
+
+    /// Check that all the bytes have been decoded.
+    pub fn finish(&self) -> Result<(), DecodeError> {
+        if self.pos != self.bytes.len() {
+            return Err(DecodeError::TrailingData(self.bytes.len() - self.pos));
+        }
+        Ok(())
+    }
+
+    /// Get the offset of the next byte to decode.
+    pub fn offset(&self) -> usize {
+        self.pos
+    }
+
+    /// Make an error for an invalid `what` starting at `offset`.
+    pub fn invalid(&self, what: &'static str, offset: usize) -> DecodeError {
+        DecodeError::Invalid { what, offset }
+    }
+
+    /// Decode the next `len` bytes.
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if len > self.bytes.len() - self.pos {
+            return Err(DecodeError::UnexpectedEnd(self.bytes.len()));
+        }
+        let bytes = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    /// Decode a single byte.
+    pub fn byte(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// Decode a LEB128 variable-length unsigned integer.
+    pub fn u64(&mut self) -> Result<u64, DecodeError> {
+        let offset = self.pos;
+        let mut x = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            let bits = u64::from(byte & 0x7f);
+            if bits << shift >> shift != bits {
+                break;
+            }
+            x |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(x);
+            }
+        }
+        Err(self.invalid("integer", offset))
+    }
+
+    /// Decode an unsigned integer.
+    pub fn u32(&mut self) -> Result<u32, DecodeError> {
+        let offset = self.pos;
+        let x = self.u64()?;
+        if x > u64::from(u32::max_value()) {
+            return Err(self.invalid("integer", offset));
+        }
+        Ok(x as u32)
+    }
+
+    /// Decode a zigzag-encoded signed integer.
+    pub fn i64(&mut self) -> Result<i64, DecodeError> {
+        let x = self.u64()?;
+        Ok((x >> 1) as i64 ^ -((x & 1) as i64))
+    }
+
+    /// Decode an index which must be less than `count`.
+    pub fn index(&mut self, count: usize, what: &'static str) -> Result<usize, DecodeError> {
+        let offset = self.pos;
+        let index = self.u64()?;
+        if index >= count as u64 {
+            return Err(self.invalid(what, offset));
+        }
+        Ok(index as usize)
+    }
+
+    /// Decode the number of following items, each of which takes at least a byte.
+    pub fn len(&mut self, what: &'static str) -> Result<usize, DecodeError> {
+        let remaining = self.bytes.len() - self.pos;
+        self.index(remaining + 1, what)
+    }
+
+    /// Decode the number of entities which can be numbered sparsely.
+    fn sparse_count(&mut self, what: &'static str) -> Result<usize, DecodeError> {
+        self.index(MAX_SPARSE_ENTITIES as usize + 1, what)
+    }
+
+    /// Decode a sequence of bytes preceded by its length.
+    pub fn bytes(&mut self) -> Result<&'a [u8], DecodeError> {
+        let len = self.len("length")?;
+        self.take(len)
+    }
+
+    /// Decode a string preceded by its length.
+    pub fn str(&mut self) -> Result<&'a str, DecodeError> {
+        let offset = self.pos;
+        let bytes = self.bytes()?;
+        str::from_utf8(bytes).map_err(|_| self.invalid("string", offset))
+    }
+
+    /// Decode an item of `table` from its index.
+    pub fn table<T: Copy>(&mut self, table: &[T], what: &'static str) -> Result<T, DecodeError> {
+        Ok(table[self.index(table.len(), what)?])
+    }
+
+    /// Decode a type which isn't the invalid type.
+    fn valid_type(&mut self) -> Result<Type, DecodeError> {
+        let offset = self.pos;
+        let ty = Type::decode(self)?;
+        if ty.is_invalid() {
+            return Err(self.invalid("type", offset));
+        }
+        Ok(ty)
+    }
+
+    /// Decode a reference between entities of the preamble, which may be reserved.
+    fn reference<E: EntityRef + ReservedValue>(&mut self) -> Result<E, DecodeError> {
+        match self.u32()? {
+            0 => Ok(E::reserved_value()),
+            index => Ok(E::new(index as usize - 1)),
+        }
+    }
+
+    /// Decode a value list preceded by its length.
+    pub fn value_list(&mut self, pool: &mut ValueListPool) -> Result<ValueList, DecodeError> {
+        let mut values = Vec::new();
+        for _ in 0..self.len("value list length")? {
+            values.push(Value::decode(self)?);
+        }
+        Ok(ValueList::from_slice(&values, pool))
+    }
+
+    /// Decode the definition of a value, which must not have been defined yet.
+    fn definition(&mut self, defined: &mut [bool]) -> Result<Value, DecodeError> {
+        let offset = self.pos;
+        let value = Value::decode(self)?;
+        if defined[value.index()] {
+            return Err(self.invalid("value defined twice", offset));
+        }
+        defined[value.index()] = true;
+        Ok(value)
+    }
+}
+
+/// Something which can be decoded from the binary format.
+pub(crate) trait Decode: Sized {
+    /// Decode the next `Self` from `dec`.
+    fn decode(dec: &mut Decoder) -> Result<Self, DecodeError>;
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        let offset = dec.pos;
+        match dec.byte()? {
+            0 => Ok(None),
+            1 => Ok(Some(T::decode(dec)?)),
+            _ => Err(dec.invalid("option", offset)),
+        }
+    }
+}
+
+impl Decode for bool {
+    fn decode(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        let offset = dec.pos;
+        match dec.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(dec.invalid("boolean", offset)),
+        }
+    }
+}
+
+impl Decode for u8 {
+    fn decode(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        dec.byte()
+    }
+}
+
+impl Decode for u16 {
+    fn decode(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        let offset = dec.pos;
+        let x = dec.u64()?;
+        if x > u64::from(u16::MAX) {
+            return Err(dec.invalid("integer", offset));
+        }
+        Ok(x as u16)
+    }
+}
+
+impl Decode for u32 {
+    fn decode(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        dec.u32()
+    }
+}
+
+impl Decode for i32 {
+    fn decode(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        let offset = dec.pos;
+        let x = dec.i64()?;
+        if x < i64::from(i32::min_value()) || x > i64::from(i32::max_value()) {
+            return Err(dec.invalid("integer", offset));
+        }
+        Ok(x as i32)
+    }
+}
+
+impl Decode for Imm64 {
+    fn decode(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        Ok(Imm64::new(dec.i64()?))
+    }
+}
+
+impl Decode for Uimm64 {
+    fn decode(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        Ok(Uimm64::new(dec.u64()?))
+    }
+}
+
+impl Decode for Uimm32 {
+    fn decode(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        Ok(Uimm32::from(dec.u32()?))
+    }
+}
+
+impl Decode for Offset32 {
+    fn decode(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        Ok(Offset32::new(i32::decode(dec)?))
+    }
+}
+
+impl Decode for Ieee32 {
+    fn decode(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        let mut bits = [0; 4];
+        bits.copy_from_slice(dec.take(4)?);
+        Ok(Ieee32::with_bits(u32::from_le_bytes(bits)))
+    }
+}
+
+impl Decode for Ieee64 {
+    fn decode(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        let mut bits = [0; 8];
+        bits.copy_from_slice(dec.take(8)?);
+        Ok(Ieee64::with_bits(u64::from_le_bytes(bits)))
+    }
+}
+
+impl Decode for IntCC {
+    fn decode(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        dec.table(&INT_CCS, "integer condition code")
+    }
+}
+
+impl Decode for FloatCC {
+    fn decode(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        dec.table(&FLOAT_CCS, "floating point condition code")
+    }
+}
+
+impl Decode for TrapCode {
+    fn decode(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        let index = dec.index(TRAP_CODES.len() + usize::from(u16::MAX) + 1, "trap code")?;
+        Ok(match index.checked_sub(TRAP_CODES.len()) {
+            Some(code) => TrapCode::User(code as u16),
+            None => TRAP_CODES[index],
+        })
+    }
+}
+
+impl Decode for MemFlags {
+    fn decode(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        let offset = dec.pos;
+        let bits = dec.byte()?;
+        if bits > 0b111 {
+            return Err(dec.invalid("memory flags", offset));
+        }
+        let mut flags = MemFlags::new();
+        if bits & 1 != 0 {
+            flags.set_notrap();
+        }
+        if bits & 2 != 0 {
+            flags.set_aligned();
+        }
+        if bits & 4 != 0 {
+            flags.set_readonly();
+        }
+        Ok(flags)
+    }
+}
+
+impl Decode for Type {
+    fn decode(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        let offset = dec.pos;
+        let index = dec.u64()?;
+        let lane_type = LANE_TYPES[(index % LANE_TYPES.len() as u64) as usize];
+        let log2_lane_count = index / LANE_TYPES.len() as u64;
+        if log2_lane_count == 0 {
+            return Ok(lane_type);
+        }
+        if !lane_type.is_lane() || log2_lane_count >= 16 {
+            return Err(dec.invalid("type", offset));
+        }
+        lane_type
+            .by(1 << log2_lane_count)
+            .ok_or_else(|| dec.invalid("type", offset))
+    }
+}
+
+impl Decode for SourceLoc {
+    fn decode(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        Ok(SourceLoc::new(dec.u32()?.wrapping_sub(1)))
+    }
+}
+
+impl Decode for ExternalName {
+    fn decode(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        let offset = dec.pos;
+        match dec.byte()? {
+            0 => Ok(ExternalName::user(dec.u32()?, dec.u32()?)),
+            1 => {
+                // Test case names are truncated when they're too long.
+                let name = dec.bytes()?;
+                let testcase = ExternalName::testcase(name);
+                match testcase {
+                    ExternalName::TestCase { length, .. } if usize::from(length) == name.len() => {
+                        Ok(testcase)
+                    }
+                    _ => Err(dec.invalid("test case name", offset)),
+                }
+            }
+            2 => LibCall::from_str(dec.str()?)
+                .map(ExternalName::LibCall)
+                .map_err(|_| dec.invalid("library call", offset)),
+            _ => Err(dec.invalid("external name", offset)),
+        }
+    }
+}
+
+impl Decode for CallConv {
+    fn decode(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        dec.table(&CALL_CONVS, "calling convention")
+    }
+}
+
+impl Decode for AbiParam {
+    fn decode(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        let mut param = AbiParam::new(dec.valid_type()?);
+        param.purpose = dec.table(&ARGUMENT_PURPOSES, "argument purpose")?;
+        param.extension = dec.table(
+            &[
+                ArgumentExtension::None,
+                ArgumentExtension::Uext,
+                ArgumentExtension::Sext,
+            ],
+            "argument extension",
+        )?;
+        let offset = dec.pos;
+        param.location = match dec.byte()? {
+            0 => ArgumentLoc::Unassigned,
+            1 => ArgumentLoc::Reg(Decode::decode(dec)?),
+            2 => ArgumentLoc::Stack(Decode::decode(dec)?),
+            _ => return Err(dec.invalid("argument location", offset)),
+        };
+        Ok(param)
+    }
+}
+
+impl Decode for Signature {
+    fn decode(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        let mut params = Vec::new();
+        for _ in 0..dec.len("parameter count")? {
+            params.push(Decode::decode(dec)?);
+        }
+        let mut returns = Vec::new();
+        for _ in 0..dec.len("return count")? {
+            returns.push(Decode::decode(dec)?);
+        }
+        let mut sig = Signature::new(Decode::decode(dec)?);
+        sig.params = params;
+        sig.returns = returns;
+        Ok(sig)
+    }
+}
+
+impl Decode for ValueLoc {
+    fn decode(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        let offset = dec.pos;
+        match dec.byte()? {
+            0 => Ok(ValueLoc::Unassigned),
+            1 => Ok(ValueLoc::Reg(Decode::decode(dec)?)),
+            2 => Ok(ValueLoc::Stack(Decode::decode(dec)?)),
+            _ => Err(dec.invalid("value location", offset)),
+        }
+    }
+}
+
+impl Decode for CodeInfo {
+    fn decode(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        Ok(CodeInfo {
+            code_size: dec.u32()?,
+            jumptables_size: dec.u32()?,
+            rodata_size: dec.u32()?,
+            total_size: dec.u32()?,
+        })
+    }
+}
+
+impl Decode for Opcode {
+    fn decode(dec: &mut Decoder) -> Result<Self, DecodeError> {
+        let offset = dec.pos;
+        Opcode::from_str(dec.str()?).map_err(|_| dec.invalid("opcode", offset))
+    }
+}
+
+/// Entity references must refer to an entity which has already been counted.
+macro_rules! decode_entities {
+    ($($entity:ident: $count:ident, $what:expr;)*) => {
+        $(
+            impl Decode for $entity {
+                fn decode(dec: &mut Decoder) -> Result<Self, DecodeError> {
+                    let count = dec.$count;
+                    Ok($entity::new(dec.index(count, $what)?))
+                }
+            }
+        )*
+    };
+}
+
+decode_entities! {
+    Value: num_values, "value reference";
+    Ebb: num_ebbs, "EBB reference";
+    StackSlot: num_stack_slots, "stack slot reference";
+    GlobalValue: num_global_values, "global value reference";
+    Heap: num_heaps, "heap reference";
+    Table: num_tables, "table reference";
+    SigRef: num_sig_refs, "signature reference";
+    FuncRef: num_func_refs, "function reference";
+    JumpTable: num_jump_tables, "jump table reference";
+    Constant: num_constants, "constant reference";
+}
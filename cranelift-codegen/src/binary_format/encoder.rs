@@ -0,0 +1,615 @@
+//! Encoding functions in the binary format.
+
+use super::{
+    ARGUMENT_PURPOSES, CALL_CONVS, FLOAT_CCS, INT_CCS, LANE_TYPES, MAGIC, STACK_SLOT_KINDS,
+    TRAP_CODES, VERSION,
+};
+use crate::binemit::CodeInfo;
+use crate::entity::EntityRef;
+use crate::fx::FxHashMap;
+use crate::ir::condcodes::{FloatCC, IntCC};
+use crate::ir::immediates::{Ieee32, Ieee64, Imm64, Offset32, Uimm32, Uimm64};
+use crate::ir::{
+    AbiParam, ArgumentExtension, ArgumentLoc, Constant, Ebb, ExternalName, FuncRef, Function,
+    GlobalValue, GlobalValueData, Heap, HeapStyle, JumpTable, MemFlags, Opcode, SigRef, Signature,
+    SourceLoc, StackSlot, Table, TrapCode, Type, Value, ValueLoc,
+};
+use crate::isa::CallConv;
+use crate::packed_option::ReservedValue;
+use std::string::ToString;
+use std::vec::Vec;
+
+/// Encode `func` in the binary format, along with the `CodeInfo` returned when compiling it.
+pub fn encode_function(func: &Function, info: Option<&CodeInfo>) -> Vec<u8> {
+    let mut enc = Encoder::new();
+    enc.bytes.extend_from_slice(&MAGIC);
+    enc.u32(VERSION);
+    encode_preamble(func, &mut enc);
+    encode_body(func, &mut enc);
+    encode_compilation(func, &mut enc);
+    info.cloned().encode(&mut enc);
+    enc.into_bytes()
+}
+
+/// Encode the name and signature of `func`, and all the entities declared before its body.
+fn encode_preamble(func: &Function, enc: &mut Encoder) {
+    func.name.encode(enc);
+    func.signature.encode(enc);
+
+    enc.len(func.stack_slots.keys().len());
+    for slot in func.stack_slots.values() {
+        enc.table(&STACK_SLOT_KINDS, slot.kind);
+        enc.u32(slot.size);
+        slot.offset.encode(enc);
+    }
+    func.stack_slots.frame_size.encode(enc);
+
+    enc.len(func.global_values.len());
+    for gv in func.global_values.values() {
+        match *gv {
+            GlobalValueData::VMContext => enc.byte(0),
+            GlobalValueData::Load {
+                base,
+                offset,
+                global_type,
+                readonly,
+            } => {
+                enc.byte(1);
+                enc.reference(base);
+                offset.encode(enc);
+                global_type.encode(enc);
+                readonly.encode(enc);
+            }
+            GlobalValueData::IAddImm {
+                base,
+                offset,
+                global_type,
+            } => {
+                enc.byte(2);
+                enc.reference(base);
+                offset.encode(enc);
+                global_type.encode(enc);
+            }
+            GlobalValueData::Symbol {
+                ref name,
+                offset,
+                colocated,
+            } => {
+                enc.byte(3);
+                name.encode(enc);
+                offset.encode(enc);
+                colocated.encode(enc);
+            }
+        }
+    }
+
+    enc.len(func.heaps.len());
+    for heap in func.heaps.values() {
+        enc.reference(heap.base);
+        heap.min_size.encode(enc);
+        heap.offset_guard_size.encode(enc);
+        match heap.style {
+            HeapStyle::Dynamic { bound_gv } => {
+                enc.byte(0);
+                enc.reference(bound_gv);
+            }
+            HeapStyle::Static { bound } => {
+                enc.byte(1);
+                bound.encode(enc);
+            }
+        }
+        heap.index_type.encode(enc);
+    }
+
+    enc.len(func.tables.len());
+    for table in func.tables.values() {
+        enc.reference(table.base_gv);
+        table.min_size.encode(enc);
+        enc.reference(table.bound_gv);
+        table.element_size.encode(enc);
+        table.index_type.encode(enc);
+    }
+
+    enc.len(func.dfg.signatures.len());
+    for sig in func.dfg.signatures.values() {
+        sig.encode(enc);
+    }
+
+    enc.len(func.dfg.ext_funcs.len());
+    for ext_func in func.dfg.ext_funcs.values() {
+        ext_func.name.encode(enc);
+        enc.reference(ext_func.signature);
+        ext_func.colocated.encode(enc);
+    }
+
+    enc.len(func.dfg.num_ebbs());
+
+    enc.len(func.jump_tables.len());
+    for jt in func.jump_tables.values() {
+        enc.len(jt.len());
+        for ebb in jt.iter() {
+            ebb.encode(enc);
+        }
+    }
+
+    enc.len(func.dfg.constants.len());
+    for (_, data, offset) in func.dfg.constants.iter_with_offsets() {
+        enc.bytes(data);
+        offset.encode(enc);
+    }
+}
+
+/// Encode the values, instructions and layout of `func`.
+fn encode_body(func: &Function, enc: &mut Encoder) {
+    let dfg = &func.dfg;
+    enc.len(dfg.num_values());
+
+    // Only the values defined in the layout are encoded, so find them first, along with the
+    // opcodes used.
+    let mut live = vec![false; dfg.num_values()];
+    let mut opcodes = Vec::new();
+    let mut opcode_numbers = FxHashMap::default();
+    for ebb in func.layout.ebbs() {
+        for &param in dfg.ebb_params(ebb) {
+            live[param.index()] = true;
+        }
+        for inst in func.layout.ebb_insts(ebb) {
+            for &result in dfg.inst_results(inst) {
+                live[result.index()] = true;
+            }
+            let opcode = dfg[inst].opcode();
+            opcode_numbers.entry(opcode).or_insert_with(|| {
+                opcodes.push(opcode);
+                opcodes.len() - 1
+            });
+        }
+    }
+
+    enc.len(opcodes.len());
+    for opcode in opcodes {
+        opcode.encode(enc);
+    }
+
+    enc.len(func.layout.ebbs().count());
+    for ebb in func.layout.ebbs() {
+        ebb.encode(enc);
+        let params = dfg.ebb_params(ebb);
+        enc.len(params.len());
+        for &param in params {
+            param.encode(enc);
+            dfg.value_type(param).encode(enc);
+        }
+
+        enc.len(func.layout.ebb_insts(ebb).count());
+        for inst in func.layout.ebb_insts(ebb) {
+            let data = &dfg[inst];
+            enc.len(opcode_numbers[&data.opcode()]);
+            data.encode_operands(enc, &dfg.value_lists);
+
+            let results = dfg.inst_results(inst);
+            enc.len(results.len());
+            for &result in results {
+                result.encode(enc);
+                dfg.value_type(result).encode(enc);
+            }
+
+            let encoding = func.encodings[inst];
+            if encoding.is_legal() {
+                enc.len(encoding.recipe() + 1);
+                enc.u32(encoding.bits().into());
+            } else {
+                enc.byte(0);
+            }
+            func.srclocs[inst].encode(enc);
+        }
+    }
+
+    // Encode the aliases which resolve to a value defined in the layout. The others can't be
+    // used by the instructions in the layout.
+    let mut aliases = Vec::new();
+    for value in (0..dfg.num_values()).map(Value::new) {
+        if live[value.index()] {
+            continue;
+        }
+        let original = match dfg.value_alias_dest_for_serialization(value) {
+            Some(original) if original != Value::reserved_value() => original,
+            _ => continue,
+        };
+        let mut resolved = original;
+        for _ in 0..dfg.num_values() {
+            match dfg.value_alias_dest_for_serialization(resolved) {
+                Some(next) if !live[resolved.index()] && next != Value::reserved_value() => {
+                    resolved = next
+                }
+                _ => break,
+            }
+        }
+        if live[resolved.index()] {
+            aliases.push((value, original));
+        }
+    }
+    enc.len(aliases.len());
+    for (value, original) in aliases {
+        value.encode(enc);
+        original.encode(enc);
+    }
+}
+
+/// Encode the results of compiling `func` which aren't part of its instructions.
+fn encode_compilation(func: &Function, enc: &mut Encoder) {
+    let locations: Vec<_> = func
+        .locations
+        .iter()
+        .filter(|&(_, loc)| loc.is_assigned())
+        .collect();
+    enc.len(locations.len());
+    for (value, loc) in locations {
+        value.encode(enc);
+        loc.encode(enc);
+    }
+
+    let entry_diversions: Vec<_> = func
+        .layout
+        .ebbs()
+        .filter_map(|ebb| Some((ebb, func.entry_diversions.get(ebb)?)))
+        .collect();
+    enc.len(entry_diversions.len());
+    for (ebb, divert) in entry_diversions {
+        ebb.encode(enc);
+        let mut diversions: Vec<_> = divert.iter().collect();
+        diversions.sort_by_key(|&(&value, _)| value);
+        enc.len(diversions.len());
+        for (value, diversion) in diversions {
+            value.encode(enc);
+            diversion.from.encode(enc);
+            diversion.to.encode(enc);
+        }
+    }
+
+    // The offsets are encoded even when they're zero, since an empty map of EBB offsets means
+    // that the code layout hasn't been computed.
+    enc.len(func.offsets.keys().count());
+    for (ebb, &offset) in func.offsets.iter() {
+        ebb.encode(enc);
+        enc.u32(offset);
+    }
+
+    enc.len(func.jt_offsets.keys().count());
+    for (jt, &offset) in func.jt_offsets.iter() {
+        jt.encode(enc);
+        enc.u32(offset);
+    }
+}
+
+/// A buffer for the bytes of an encoded function.
+pub(crate) struct Encoder {
+    bytes: Vec<u8>,
+}
+
+impl Encoder {
+    /// Create an empty encoder.
+    pub fn new() -> Self {
+        Self { bytes: Vec::new() }
+    }
+
+    /// Get the bytes encoded so far.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// Encode a single byte.
+    pub fn byte(&mut self, byte: u8) {
+        self.bytes.push(byte);
+    }
+
+    /// Encode an unsigned integer as a LEB128 variable-length integer.
+    pub fn u64(&mut self, mut x: u64) {
+        while x >= 0x80 {
+            self.byte(x as u8 | 0x80);
+            x >>= 7;
+        }
+        self.byte(x as u8);
+    }
+
+    /// Encode an unsigned integer.
+    pub fn u32(&mut self, x: u32) {
+        self.u64(x.into());
+    }
+
+    /// Encode a signed integer, zigzag-encoded so small negative numbers are short too.
+    pub fn i64(&mut self, x: i64) {
+        self.u64(((x << 1) ^ (x >> 63)) as u64);
+    }
+
+    /// Encode a length or index.
+    pub fn len(&mut self, len: usize) {
+        self.u64(len as u64);
+    }
+
+    /// Encode a sequence of bytes, preceded by its length.
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.len(bytes.len());
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    /// Encode a string, preceded by its length.
+    pub fn str(&mut self, s: &str) {
+        self.bytes(s.as_bytes());
+    }
+
+    /// Encode `x` as its index in `table`.
+    pub fn table<T: PartialEq>(&mut self, table: &[T], x: T) {
+        let index = table
+            .iter()
+            .position(|t| *t == x)
+            .expect("missing from the binary format tables");
+        self.len(index);
+    }
+
+    /// Encode a reference between entities of the preamble, which may be reserved.
+    pub fn reference<E: EntityRef + ReservedValue + PartialEq>(&mut self, entity: E) {
+        if entity == E::reserved_value() {
+            self.byte(0);
+        } else {
+            self.len(entity.index() + 1);
+        }
+    }
+
+    /// Encode the values of a value list, preceded by their number.
+    pub fn value_list(&mut self, values: &[Value]) {
+        self.len(values.len());
+        for value in values {
+            value.encode(self);
+        }
+    }
+}
+
+/// Something which can be encoded in the binary format.
+pub(crate) trait Encode {
+    /// Append the encoding of `self` to `enc`.
+    fn encode(&self, enc: &mut Encoder);
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, enc: &mut Encoder) {
+        match *self {
+            None => enc.byte(0),
+            Some(ref x) => {
+                enc.byte(1);
+                x.encode(enc);
+            }
+        }
+    }
+}
+
+impl Encode for bool {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.byte(*self as u8);
+    }
+}
+
+impl Encode for u8 {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.byte(*self);
+    }
+}
+
+impl Encode for u16 {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.u32((*self).into());
+    }
+}
+
+impl Encode for u32 {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.u32(*self);
+    }
+}
+
+impl Encode for i32 {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.i64((*self).into());
+    }
+}
+
+impl Encode for Imm64 {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.i64((*self).into());
+    }
+}
+
+impl Encode for Uimm64 {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.u64((*self).into());
+    }
+}
+
+impl Encode for Uimm32 {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.u32((*self).into());
+    }
+}
+
+impl Encode for Offset32 {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.i64((*self).into());
+    }
+}
+
+impl Encode for Ieee32 {
+    fn encode(&self, enc: &mut Encoder) {
+        // Floating point numbers rarely have small bit patterns, so they are stored as is.
+        enc.bytes.extend_from_slice(&self.bits().to_le_bytes());
+    }
+}
+
+impl Encode for Ieee64 {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.bytes.extend_from_slice(&self.bits().to_le_bytes());
+    }
+}
+
+impl Encode for IntCC {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.table(&INT_CCS, *self);
+    }
+}
+
+impl Encode for FloatCC {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.table(&FLOAT_CCS, *self);
+    }
+}
+
+impl Encode for TrapCode {
+    fn encode(&self, enc: &mut Encoder) {
+        match *self {
+            TrapCode::User(code) => enc.len(TRAP_CODES.len() + usize::from(code)),
+            code => enc.table(&TRAP_CODES, code),
+        }
+    }
+}
+
+impl Encode for MemFlags {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.byte(self.notrap() as u8 | (self.aligned() as u8) << 1 | (self.readonly() as u8) << 2);
+    }
+}
+
+impl Encode for Type {
+    fn encode(&self, enc: &mut Encoder) {
+        // The lane type goes in the low bits, and the log2 of the lane count in the high bits.
+        let lane_type = LANE_TYPES
+            .iter()
+            .position(|&t| t == self.lane_type())
+            .expect("missing from the binary format tables");
+        enc.len(lane_type + LANE_TYPES.len() * usize::from(self.log2_lane_count()));
+    }
+}
+
+impl Encode for SourceLoc {
+    fn encode(&self, enc: &mut Encoder) {
+        // The default source location is all ones, so this makes it a single byte.
+        enc.u32(self.bits().wrapping_add(1));
+    }
+}
+
+impl Encode for ExternalName {
+    fn encode(&self, enc: &mut Encoder) {
+        match *self {
+            ExternalName::User { namespace, index } => {
+                enc.byte(0);
+                enc.u32(namespace);
+                enc.u32(index);
+            }
+            ExternalName::TestCase { length, ascii } => {
+                enc.byte(1);
+                enc.bytes(&ascii[..usize::from(length)]);
+            }
+            ExternalName::LibCall(libcall) => {
+                enc.byte(2);
+                enc.str(&libcall.to_string());
+            }
+        }
+    }
+}
+
+impl Encode for CallConv {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.table(&CALL_CONVS, *self);
+    }
+}
+
+impl Encode for AbiParam {
+    fn encode(&self, enc: &mut Encoder) {
+        self.value_type.encode(enc);
+        enc.table(&ARGUMENT_PURPOSES, self.purpose);
+        enc.byte(match self.extension {
+            ArgumentExtension::None => 0,
+            ArgumentExtension::Uext => 1,
+            ArgumentExtension::Sext => 2,
+        });
+        match self.location {
+            ArgumentLoc::Unassigned => enc.byte(0),
+            ArgumentLoc::Reg(reg) => {
+                enc.byte(1);
+                reg.encode(enc);
+            }
+            ArgumentLoc::Stack(offset) => {
+                enc.byte(2);
+                offset.encode(enc);
+            }
+        }
+    }
+}
+
+impl Encode for Signature {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.len(self.params.len());
+        for param in &self.params {
+            param.encode(enc);
+        }
+        enc.len(self.returns.len());
+        for ret in &self.returns {
+            ret.encode(enc);
+        }
+        self.call_conv.encode(enc);
+    }
+}
+
+impl Encode for ValueLoc {
+    fn encode(&self, enc: &mut Encoder) {
+        match *self {
+            ValueLoc::Unassigned => enc.byte(0),
+            ValueLoc::Reg(reg) => {
+                enc.byte(1);
+                reg.encode(enc);
+            }
+            ValueLoc::Stack(ss) => {
+                enc.byte(2);
+                ss.encode(enc);
+            }
+        }
+    }
+}
+
+impl Encode for CodeInfo {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.u32(self.code_size);
+        enc.u32(self.jumptables_size);
+        enc.u32(self.rodata_size);
+        enc.u32(self.total_size);
+    }
+}
+
+impl Encode for Opcode {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.str(&self.to_string());
+    }
+}
+
+/// Entity references are encoded as their index.
+macro_rules! encode_entities {
+    ($($entity:ident),*) => {
+        $(
+            impl Encode for $entity {
+                fn encode(&self, enc: &mut Encoder) {
+                    enc.len(self.index());
+                }
+            }
+        )*
+    };
+}
+
+encode_entities!(
+    Value,
+    Ebb,
+    StackSlot,
+    GlobalValue,
+    Heap,
+    Table,
+    SigRef,
+    FuncRef,
+    JumpTable,
+    Constant
+);
@@ -0,0 +1,375 @@
+//! A compact binary format for Cranelift IR functions.
+//!
+//! The `binary_format` module converts an IR `Function` to a versioned sequence of bytes and back,
+//! which is much faster than writing and parsing the textual form. Besides the IR itself, the
+//! encoding includes the results of compilation: instruction encodings, value locations, entry
+//! diversions, EBB and jump table offsets, constant offsets, and optionally the `CodeInfo`
+//! returned by `Context::compile`.
+//!
+//! The decoder accepts arbitrary bytes: malformed input is reported as a `DecodeError`, and a
+//! successfully decoded function can be printed and verified. Encodings and register units can't
+//! be checked without a `TargetIsa`, so functions from untrusted sources should still be verified
+//! before being compiled or emitted.
+//!
+//! Some details of a function aren't preserved:
+//!
+//! - Instructions are numbered in layout order, and instructions which aren't inserted in the
+//!   layout are dropped, as are the values they define.
+//! - Values which are neither defined in the layout nor aliases of such values are left invalid.
+//! - The lists of outgoing argument and emergency stack slots used by `StackSlots` to reuse slots
+//!   are not rebuilt, like with the text format.
+//! - Value labels are dropped.
+//!
+//! Like in the text format, the references between the global values, heaps, tables, signatures
+//! and external functions aren't checked by the decoder, which leaves that to the verifier. All the
+//! other entity references must refer to existing entities.
+
+mod decoder;
+mod encoder;
+
+pub use self::decoder::{decode_function, DecodeError};
+pub(crate) use self::decoder::{Decode, Decoder};
+pub use self::encoder::encode_function;
+pub(crate) use self::encoder::{Encode, Encoder};
+
+use crate::ir::condcodes::{FloatCC, IntCC};
+use crate::ir::{types, ArgumentPurpose, StackSlotKind, TrapCode, Type};
+use crate::isa::CallConv;
+
+/// The bytes starting every encoded function.
+const MAGIC: [u8; 4] = *b"\0clf";
+
+/// The version of the binary format.
+///
+/// This must be bumped whenever the encoding changes, including when the fields of an instruction
+/// format change.
+pub const VERSION: u32 = 2;
+
+/// The maximum number of values or EBBs of a decoded function.
+///
+/// These entities can be numbered sparsely, so their numbers aren't limited by the size of the
+/// input like the other entities.
+const MAX_SPARSE_ENTITIES: u32 = 1 << 24;
+
+/// The scalar types, whose index in this table is encoded along with the number of lanes.
+///
+/// The invalid type is used by the placeholders padding the heaps and tables of functions parsed
+/// from the text format.
+const LANE_TYPES: [Type; 18] = [
+    types::INVALID,
+    types::IFLAGS,
+    types::FFLAGS,
+    types::B1,
+    types::B8,
+    types::B16,
+    types::B32,
+    types::B64,
+    types::B128,
+    types::I8,
+    types::I16,
+    types::I32,
+    types::I64,
+    types::I128,
+    types::F32,
+    types::F64,
+    types::R32,
+    types::R64,
+];
+
+const INT_CCS: [IntCC; 10] = [
+    IntCC::Equal,
+    IntCC::NotEqual,
+    IntCC::SignedLessThan,
+    IntCC::SignedGreaterThanOrEqual,
+    IntCC::SignedGreaterThan,
+    IntCC::SignedLessThanOrEqual,
+    IntCC::UnsignedLessThan,
+    IntCC::UnsignedGreaterThanOrEqual,
+    IntCC::UnsignedGreaterThan,
+    IntCC::UnsignedLessThanOrEqual,
+];
+
+const FLOAT_CCS: [FloatCC; 14] = [
+    FloatCC::Ordered,
+    FloatCC::Unordered,
+    FloatCC::Equal,
+    FloatCC::NotEqual,
+    FloatCC::OrderedNotEqual,
+    FloatCC::UnorderedOrEqual,
+    FloatCC::LessThan,
+    FloatCC::LessThanOrEqual,
+    FloatCC::GreaterThan,
+    FloatCC::GreaterThanOrEqual,
+    FloatCC::UnorderedOrLessThan,
+    FloatCC::UnorderedOrLessThanOrEqual,
+    FloatCC::UnorderedOrGreaterThan,
+    FloatCC::UnorderedOrGreaterThanOrEqual,
+];
+
+/// The trap codes other than `TrapCode::User`, which are encoded after these.
+const TRAP_CODES: [TrapCode; 12] = [
+    TrapCode::StackOverflow,
+    TrapCode::HeapOutOfBounds,
+    TrapCode::HeapMisaligned,
+    TrapCode::TableOutOfBounds,
+    TrapCode::OutOfBounds,
+    TrapCode::IndirectCallToNull,
+    TrapCode::BadSignature,
+    TrapCode::IntegerOverflow,
+    TrapCode::IntegerDivisionByZero,
+    TrapCode::BadConversionToInteger,
+    TrapCode::UnreachableCodeReached,
+    TrapCode::Interrupt,
+];
+
+const ARGUMENT_PURPOSES: [ArgumentPurpose; 8] = [
+    ArgumentPurpose::Normal,
+    ArgumentPurpose::StructReturn,
+    ArgumentPurpose::Link,
+    ArgumentPurpose::FramePointer,
+    ArgumentPurpose::CalleeSaved,
+    ArgumentPurpose::VMContext,
+    ArgumentPurpose::SignatureId,
+    ArgumentPurpose::StackLimit,
+];
+
+const CALL_CONVS: [CallConv; 7] = [
+    CallConv::Fast,
+    CallConv::Cold,
+    CallConv::SystemV,
+    CallConv::WindowsFastcall,
+    CallConv::BaldrdashSystemV,
+    CallConv::BaldrdashWindows,
+    CallConv::Probestack,
+];
+
+const STACK_SLOT_KINDS: [StackSlotKind; 5] = [
+    StackSlotKind::SpillSlot,
+    StackSlotKind::ExplicitSlot,
+    StackSlotKind::IncomingArg,
+    StackSlotKind::OutgoingArg,
+    StackSlotKind::EmergencySlot,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::binemit::CodeInfo;
+    use crate::cursor::{Cursor, FuncCursor};
+    use crate::ir::immediates::{Ieee64, Imm64, Offset32, Uimm64};
+    use crate::ir::{
+        AbiParam, ExtFuncData, ExternalName, Function, GlobalValueData, HeapData, HeapStyle,
+        InstBuilder, JumpTableData, MemFlags, Signature, SourceLoc, StackSlotData, ValueLoc,
+    };
+    use crate::isa::{Encoding, RegUnit};
+    use crate::verifier::verify_function;
+    use std::string::{String, ToString};
+    use std::vec::Vec;
+
+    /// Build a function using most of the parts of the IR.
+    fn sample_function() -> Function {
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params.push(AbiParam::new(types::I64));
+        sig.params
+            .push(AbiParam::special(types::I64, ArgumentPurpose::VMContext));
+        sig.returns.push(AbiParam::new(types::I32).uext());
+        let mut func = Function::with_name_signature(ExternalName::user(1, 7), sig);
+
+        let ss0 = func.create_stack_slot(StackSlotData::new(StackSlotKind::ExplicitSlot, 16));
+        let vmctx = func.create_global_value(GlobalValueData::VMContext);
+        let base = func.create_global_value(GlobalValueData::Load {
+            base: vmctx,
+            offset: Offset32::new(8),
+            global_type: types::I64,
+            readonly: true,
+        });
+        let heap = func.create_heap(HeapData {
+            base,
+            min_size: Uimm64::new(0x1_0000),
+            offset_guard_size: Uimm64::new(0x8000_0000),
+            style: HeapStyle::Static {
+                bound: Uimm64::new(0x1_0000_0000),
+            },
+            index_type: types::I32,
+        });
+        let mut callee_sig = Signature::new(CallConv::Fast);
+        callee_sig.params.push(AbiParam::new(types::F64));
+        callee_sig.returns.push(AbiParam::new(types::I32));
+        let callee_sig = func.import_signature(callee_sig);
+        let callee = func.import_function(ExtFuncData {
+            name: ExternalName::testcase("callee"),
+            signature: callee_sig,
+            colocated: true,
+        });
+        let vector = func.dfg.constants.insert(vec![1; 16]);
+
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let ebb2 = func.dfg.make_ebb();
+        let mut jt = JumpTableData::new();
+        jt.push_entry(ebb1);
+        jt.push_entry(ebb1);
+        let jt = func.create_jump_table(jt);
+
+        let mut pos = FuncCursor::new(&mut func);
+        pos.insert_ebb(ebb0);
+        let index = pos.func.dfg.append_ebb_param(ebb0, types::I64);
+        pos.func.dfg.append_ebb_param(ebb0, types::I64);
+        pos.set_srcloc(SourceLoc::new(0x10));
+        let index = pos.ins().ireduce(types::I32, index);
+        let addr = pos.ins().heap_addr(types::I64, heap, index, 4);
+        let loaded = pos.ins().load(types::I32, MemFlags::trusted(), addr, 0);
+        pos.ins().stack_store(loaded, ss0, 4);
+        let f = pos.ins().f64const(Ieee64::with_float(1.5));
+        let call = pos.ins().call(callee, &[f]);
+        let result = pos.func.dfg.inst_results(call)[0];
+        let cmp = pos
+            .ins()
+            .icmp_imm(IntCC::SignedLessThan, result, Imm64::new(-3));
+        pos.ins().vconst(types::I8X16, vector);
+        pos.ins().trapnz(cmp, TrapCode::User(42));
+        pos.ins().br_table(result, ebb1, jt);
+
+        pos.insert_ebb(ebb1);
+        pos.ins().jump(ebb2, &[result]);
+
+        pos.insert_ebb(ebb2);
+        let param = pos.func.dfg.append_ebb_param(ebb2, types::I32);
+        let one = pos.ins().iconst(types::I32, 1);
+        let sum = pos.ins().iadd(param, one);
+
+        // Replace the addition with an alias, like the optimizations do.
+        let add = pos.func.dfg.value_def(sum).unwrap_inst();
+        pos.func.dfg.clear_results(add);
+        pos.func.dfg.change_to_alias(sum, param);
+        pos.func.layout.remove_inst(add);
+        pos.ins().return_(&[sum]);
+        func
+    }
+
+    /// The textual form of `func`, which covers everything that can be printed without an ISA.
+    fn text(func: &Function) -> String {
+        func.display(None).to_string()
+    }
+
+    #[test]
+    fn round_trip() {
+        let func = sample_function();
+        verify_function(
+            &func,
+            &crate::settings::Flags::new(crate::settings::builder()),
+        )
+        .expect("the sample function should verify");
+        let bytes = encode_function(&func, None);
+        let (decoded, info) = decode_function(&bytes).unwrap();
+        assert_eq!(text(&decoded), text(&func));
+        assert_eq!(decoded.signature, func.signature);
+        assert!(info.is_none());
+
+        // The encoding is deterministic.
+        assert_eq!(encode_function(&decoded, None), bytes);
+    }
+
+    #[test]
+    fn compilation_results() {
+        let mut func = sample_function();
+
+        // Pretend the function was compiled.
+        let insts: Vec<_> = func
+            .layout
+            .ebbs()
+            .flat_map(|ebb| func.layout.ebb_insts(ebb).collect::<Vec<_>>())
+            .collect();
+        for (i, &inst) in insts.iter().enumerate() {
+            func.encodings[inst] = Encoding::new(i as u16, 0x0f);
+        }
+        let ebb0 = func.layout.entry_block().unwrap();
+        let index = func.dfg.ebb_params(ebb0)[0];
+        func.locations[index] = ValueLoc::Reg(3 as RegUnit);
+        func.locations[func.dfg.ebb_params(ebb0)[1]] =
+            ValueLoc::Stack(func.stack_slots.keys().next().unwrap());
+        func.offsets[ebb0] = 0;
+        let ebb2 = func.layout.last_ebb().unwrap();
+        func.offsets[ebb2] = 0x40;
+        func.stack_slots.frame_size = Some(32);
+        let info = CodeInfo {
+            code_size: 0x48,
+            jumptables_size: 8,
+            rodata_size: 16,
+            total_size: 0x60,
+        };
+
+        let bytes = encode_function(&func, Some(&info));
+        let (decoded, decoded_info) = decode_function(&bytes).unwrap();
+        assert_eq!(text(&decoded), text(&func));
+        assert_eq!(decoded_info, Some(info));
+        for (old, new) in insts.iter().zip(
+            decoded
+                .layout
+                .ebbs()
+                .flat_map(|ebb| decoded.layout.ebb_insts(ebb).collect::<Vec<_>>()),
+        ) {
+            assert_eq!(decoded.encodings[new], func.encodings[*old]);
+        }
+        assert_eq!(decoded.locations[index], ValueLoc::Reg(3));
+        assert_eq!(decoded.offsets[ebb0], 0);
+        assert_eq!(decoded.offsets[ebb2], 0x40);
+        assert_eq!(decoded.offsets.keys().count(), func.offsets.keys().count());
+        assert_eq!(decoded.stack_slots.frame_size, Some(32));
+        assert_eq!(encode_function(&decoded, Some(&info)), bytes);
+    }
+
+    #[test]
+    fn bad_header() {
+        let bytes = encode_function(&sample_function(), None);
+        assert_eq!(decode_function(&[]).unwrap_err(), DecodeError::BadMagic);
+        assert_eq!(
+            decode_function(&bytes[1..]).unwrap_err(),
+            DecodeError::BadMagic
+        );
+
+        let mut future = bytes.clone();
+        future[MAGIC.len()] = 0x7f;
+        assert_eq!(
+            decode_function(&future).unwrap_err(),
+            DecodeError::UnsupportedVersion(0x7f)
+        );
+
+        // Version 1 dropped the EBB and jump table offsets that were zero.
+        let mut old = bytes.clone();
+        old[MAGIC.len()] = 1;
+        assert_eq!(
+            decode_function(&old).unwrap_err(),
+            DecodeError::UnsupportedVersion(1)
+        );
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(
+            decode_function(&trailing).unwrap_err(),
+            DecodeError::TrailingData(1)
+        );
+    }
+
+    #[test]
+    fn truncated_and_corrupted() {
+        let bytes = encode_function(&sample_function(), None);
+        for len in 0..bytes.len() {
+            assert!(decode_function(&bytes[..len]).is_err());
+        }
+
+        // Flipping bytes must never panic, and any function decoded must print and survive
+        // another round trip.
+        for i in MAGIC.len()..bytes.len() {
+            for &flip in &[0x01, 0x80, 0xff] {
+                let mut corrupted = bytes.clone();
+                corrupted[i] ^= flip;
+                if let Ok((func, info)) = decode_function(&corrupted) {
+                    let (decoded, _) = decode_function(&encode_function(&func, info.as_ref()))
+                        .expect("a decoded function should encode correctly");
+                    assert_eq!(text(&decoded), text(&func));
+                }
+            }
+        }
+    }
+}
@@ -80,7 +80,7 @@ impl fmt::Display for Reloc {
 /// The code starts at offset 0 and is followed optionally by relocatable jump tables and copyable
 /// (raw binary) read-only data.  Any padding between sections is always part of the section that
 /// precedes the boundary between the sections.
-#[derive(PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct CodeInfo {
     /// Number of bytes of machine code (the code starts at offset 0).
     pub code_size: CodeOffset,
@@ -155,6 +155,41 @@ pub trait CodeSink {
     fn add_stackmap(&mut self, _: &[Value], _: &Function, _: &dyn TargetIsa);
 }
 
+/// Type of the frame unwind information.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FrameUnwindKind {
+    /// Windows fastcall unwinding (as in .pdata).
+    Fastcall,
+    /// FDE entry for libunwind (similar to .eh_frame format).
+    Libunwind,
+}
+
+/// Offset in frame unwind information buffer.
+pub type FrameUnwindOffset = usize;
+
+/// Sink for frame unwind information.
+///
+/// The sink receives the bytes of the unwind information for one function. Relocations refer to
+/// the start of the function's machine code, which is only known once it has been placed in
+/// memory or in an object file.
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::len_without_is_empty))]
+pub trait FrameUnwindSink {
+    /// Get the current position.
+    fn len(&self) -> FrameUnwindOffset;
+
+    /// Add bytes to the unwind information.
+    fn bytes(&mut self, _: &[u8]);
+
+    /// Reserve space for `_len` more bytes.
+    fn reserve(&mut self, _len: usize) {}
+
+    /// Add a relocation to the start of the function at `_offset` in the unwind information.
+    fn reloc(&mut self, _: Reloc, _: FrameUnwindOffset);
+
+    /// Specify the offset of the entry (e.g. the FDE) the system unwinder should be given.
+    fn set_entry_offset(&mut self, _: FrameUnwindOffset);
+}
+
 /// Report a bad encoding error.
 #[cold]
 pub fn bad_encoding(func: &Function, inst: Inst) -> ! {
@@ -75,6 +75,18 @@ impl Stackmap {
         Self { bitmap }
     }
 
+    /// Create a stackmap from the words of its bitmap.
+    #[cfg(feature = "cache")]
+    pub(crate) fn from_bitmap(bitmap: Vec<BitSet<u32>>) -> Self {
+        Self { bitmap }
+    }
+
+    /// Get the words of the bitmap.
+    #[cfg(feature = "cache")]
+    pub(crate) fn bitmap(&self) -> &[BitSet<u32>] {
+        &self.bitmap
+    }
+
     /// Returns a specified bit.
     pub fn get_bit(&self, bit_index: usize) -> bool {
         assert!(bit_index < 32 * self.bitmap.len());
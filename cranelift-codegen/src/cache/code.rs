@@ -0,0 +1,266 @@
+//! The machine code of cached functions.
+
+use crate::binary_format::{Decode, DecodeError, Decoder, Encode, Encoder};
+use crate::binemit::{
+    Addend, CodeInfo, CodeOffset, Reloc, RelocSink, Stackmap, StackmapSink, TrapSink,
+};
+use crate::bitset::BitSet;
+use crate::entity::EntityRef;
+use crate::ir::{ConstantOffset, ExternalName, JumpTable, SourceLoc, TrapCode};
+use core::ptr;
+use std::vec::Vec;
+
+const RELOCS: [Reloc; 10] = [
+    Reloc::Abs4,
+    Reloc::Abs8,
+    Reloc::X86PCRel4,
+    Reloc::X86PCRelRodata4,
+    Reloc::X86CallPCRel4,
+    Reloc::X86CallPLTRel4,
+    Reloc::X86GOTPCRel4,
+    Reloc::Arm32Call,
+    Reloc::Arm64Call,
+    Reloc::RiscvCall,
+];
+
+/// The target of a relocation, as passed to a `RelocSink`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum RelocTarget {
+    Ebb(CodeOffset),
+    External(ExternalName, Addend),
+    Constant(ConstantOffset),
+    JumpTable(JumpTable),
+}
+
+/// The machine code emitted for a function, along with everything reported to the sinks while
+/// emitting it.
+#[derive(Clone, Debug)]
+pub struct CachedCode {
+    info: CodeInfo,
+    code: Vec<u8>,
+    relocs: Vec<(CodeOffset, Reloc, RelocTarget)>,
+    traps: Vec<(CodeOffset, SourceLoc, TrapCode)>,
+    stackmaps: Vec<(CodeOffset, Stackmap)>,
+}
+
+impl CachedCode {
+    /// Create an empty `CachedCode` for a function whose emission is described by `info`, to be
+    /// filled in by emitting the function with `self` as the sinks.
+    pub(crate) fn new(info: CodeInfo) -> Self {
+        Self {
+            info,
+            code: vec![0; info.total_size as usize],
+            relocs: Vec::new(),
+            traps: Vec::new(),
+            stackmaps: Vec::new(),
+        }
+    }
+
+    /// Get information about the code and read-only data.
+    pub fn info(&self) -> CodeInfo {
+        self.info
+    }
+
+    /// Get the code and read-only data.
+    pub fn code(&self) -> &[u8] {
+        &self.code
+    }
+
+    /// Split `self` into the code buffer and sinks recording what's emitted into it.
+    pub(crate) fn recorders<'a>(
+        &'a mut self,
+    ) -> (
+        *mut u8,
+        RelocRecorder<'a>,
+        TrapRecorder<'a>,
+        StackmapRecorder<'a>,
+    ) {
+        (
+            self.code.as_mut_ptr(),
+            RelocRecorder(&mut self.relocs),
+            TrapRecorder(&mut self.traps),
+            StackmapRecorder(&mut self.stackmaps),
+        )
+    }
+
+    /// Copy the code to `mem`, and report the relocations, traps and stackmaps to the sinks in
+    /// the order in which they were emitted.
+    ///
+    /// This function is unsafe since it doesn't check that `mem` points to at least
+    /// `info().total_size` bytes of writable memory.
+    pub unsafe fn replay(
+        &self,
+        mem: *mut u8,
+        relocs: &mut dyn RelocSink,
+        traps: &mut dyn TrapSink,
+        stackmaps: &mut dyn StackmapSink,
+    ) -> CodeInfo {
+        ptr::copy_nonoverlapping(self.code.as_ptr(), mem, self.code.len());
+        for &(offset, reloc, ref target) in &self.relocs {
+            match *target {
+                RelocTarget::Ebb(ebb_offset) => relocs.reloc_ebb(offset, reloc, ebb_offset),
+                RelocTarget::External(ref name, addend) => {
+                    relocs.reloc_external(offset, reloc, name, addend)
+                }
+                RelocTarget::Constant(constant_offset) => {
+                    relocs.reloc_constant(offset, reloc, constant_offset)
+                }
+                RelocTarget::JumpTable(jt) => relocs.reloc_jt(offset, reloc, jt),
+            }
+        }
+        for &(offset, srcloc, code) in &self.traps {
+            traps.trap(offset, srcloc, code);
+        }
+        for &(offset, ref stackmap) in &self.stackmaps {
+            stackmaps.add_stackmap(offset, stackmap.clone());
+        }
+        self.info
+    }
+
+    /// Encode `self`.
+    pub(crate) fn encode(&self, enc: &mut Encoder) {
+        self.info.encode(enc);
+        enc.bytes(&self.code);
+
+        enc.len(self.relocs.len());
+        for &(offset, reloc, ref target) in &self.relocs {
+            enc.u32(offset);
+            enc.table(&RELOCS, reloc);
+            match *target {
+                RelocTarget::Ebb(ebb_offset) => {
+                    enc.byte(0);
+                    enc.u32(ebb_offset);
+                }
+                RelocTarget::External(ref name, addend) => {
+                    enc.byte(1);
+                    name.encode(enc);
+                    enc.i64(addend);
+                }
+                RelocTarget::Constant(constant_offset) => {
+                    enc.byte(2);
+                    enc.u32(constant_offset);
+                }
+                RelocTarget::JumpTable(jt) => {
+                    enc.byte(3);
+                    enc.len(jt.index());
+                }
+            }
+        }
+
+        enc.len(self.traps.len());
+        for &(offset, srcloc, code) in &self.traps {
+            enc.u32(offset);
+            srcloc.encode(enc);
+            code.encode(enc);
+        }
+
+        enc.len(self.stackmaps.len());
+        for &(offset, ref stackmap) in &self.stackmaps {
+            enc.u32(offset);
+            let bitmap = stackmap.bitmap();
+            enc.len(bitmap.len());
+            for word in bitmap {
+                enc.u32(word.0);
+            }
+        }
+    }
+
+    /// Decode the code of a function with `num_jump_tables` jump tables.
+    pub(crate) fn decode(dec: &mut Decoder, num_jump_tables: usize) -> Result<Self, DecodeError> {
+        let offset = dec.offset();
+        let info = CodeInfo::decode(dec)?;
+        let code = dec.bytes()?.to_vec();
+        if code.len() != info.total_size as usize
+            || info.code_size + info.jumptables_size + info.rodata_size != info.total_size
+        {
+            return Err(dec.invalid("code size", offset));
+        }
+
+        let mut relocs = Vec::new();
+        for _ in 0..dec.len("relocation count")? {
+            let offset = dec.u32()?;
+            let reloc = dec.table(&RELOCS, "relocation kind")?;
+            let kind_offset = dec.offset();
+            let target = match dec.byte()? {
+                0 => RelocTarget::Ebb(dec.u32()?),
+                1 => RelocTarget::External(ExternalName::decode(dec)?, dec.i64()?),
+                2 => RelocTarget::Constant(dec.u32()?),
+                3 => RelocTarget::JumpTable(JumpTable::new(
+                    dec.index(num_jump_tables, "jump table reference")?,
+                )),
+                _ => return Err(dec.invalid("relocation", kind_offset)),
+            };
+            relocs.push((offset, reloc, target));
+        }
+
+        let mut traps = Vec::new();
+        for _ in 0..dec.len("trap count")? {
+            traps.push((dec.u32()?, SourceLoc::decode(dec)?, TrapCode::decode(dec)?));
+        }
+
+        let mut stackmaps = Vec::new();
+        for _ in 0..dec.len("stackmap count")? {
+            let offset = dec.u32()?;
+            let mut bitmap = Vec::new();
+            for _ in 0..dec.len("stackmap length")? {
+                bitmap.push(BitSet(dec.u32()?));
+            }
+            stackmaps.push((offset, Stackmap::from_bitmap(bitmap)));
+        }
+
+        Ok(Self {
+            info,
+            code,
+            relocs,
+            traps,
+            stackmaps,
+        })
+    }
+}
+
+/// A `RelocSink` recording the relocations of a `CachedCode`.
+pub(crate) struct RelocRecorder<'a>(&'a mut Vec<(CodeOffset, Reloc, RelocTarget)>);
+
+impl<'a> RelocSink for RelocRecorder<'a> {
+    fn reloc_ebb(&mut self, offset: CodeOffset, reloc: Reloc, ebb_offset: CodeOffset) {
+        self.0.push((offset, reloc, RelocTarget::Ebb(ebb_offset)));
+    }
+
+    fn reloc_external(
+        &mut self,
+        offset: CodeOffset,
+        reloc: Reloc,
+        name: &ExternalName,
+        addend: Addend,
+    ) {
+        self.0
+            .push((offset, reloc, RelocTarget::External(name.clone(), addend)));
+    }
+
+    fn reloc_constant(&mut self, offset: CodeOffset, reloc: Reloc, constant: ConstantOffset) {
+        self.0
+            .push((offset, reloc, RelocTarget::Constant(constant)));
+    }
+
+    fn reloc_jt(&mut self, offset: CodeOffset, reloc: Reloc, jt: JumpTable) {
+        self.0.push((offset, reloc, RelocTarget::JumpTable(jt)));
+    }
+}
+
+/// A `TrapSink` recording the traps of a `CachedCode`.
+pub(crate) struct TrapRecorder<'a>(&'a mut Vec<(CodeOffset, SourceLoc, TrapCode)>);
+
+impl<'a> TrapSink for TrapRecorder<'a> {
+    fn trap(&mut self, offset: CodeOffset, srcloc: SourceLoc, code: TrapCode) {
+        self.0.push((offset, srcloc, code));
+    }
+}
+
+/// A `StackmapSink` recording the stackmaps of a `CachedCode`.
+pub(crate) struct StackmapRecorder<'a>(&'a mut Vec<(CodeOffset, Stackmap)>);
+
+impl<'a> StackmapSink for StackmapRecorder<'a> {
+    fn add_stackmap(&mut self, offset: CodeOffset, stackmap: Stackmap) {
+        self.0.push((offset, stackmap));
+    }
+}
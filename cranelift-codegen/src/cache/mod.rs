@@ -0,0 +1,413 @@
+//! Caching compiled functions.
+//!
+//! Compiling the same function for the same target twice produces the same code, so a
+//! `CompilationCache` can skip `Context::compile` for functions which have been compiled before.
+//! Entries are keyed on the function, the target ISA, and all the shared and ISA-specific flags.
+//! They hold the compiled function in the binary format, along with the machine code emitted for
+//! it, and the relocations, traps and stackmaps reported while emitting it.
+//!
+//! After `Context::compile_with_cache`, the context holds the compiled function whether it came
+//! from the cache or not, and `Context::emit_to_memory` replays the recorded code to the sinks
+//! instead of emitting it again.
+//!
+//! Entries are kept in a pluggable `CacheStore`: `MemoryCacheStore` keeps them for the lifetime of
+//! the process, and `DirectoryCacheStore` persists them in a directory between runs.
+
+mod code;
+mod store;
+
+pub use self::code::CachedCode;
+pub use self::store::{CacheStore, DirectoryCacheStore, MemoryCacheStore};
+
+use crate::binary_format::{decode_function, encode_function, DecodeError, Decoder, Encoder};
+use crate::binemit::CodeInfo;
+use crate::ir::Function;
+use crate::isa::TargetIsa;
+use crate::result::CodegenResult;
+use crate::Context;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use log::{debug, warn};
+use std::boxed::Box;
+use std::io;
+use std::path::PathBuf;
+use std::vec::Vec;
+
+/// The key identifying a function compiled for a particular target.
+///
+/// The key contains the function in the binary format along with a description of the target,
+/// so two keys are only equal when they compile to the same code. Its 64-bit hash is stable
+/// between runs, so stores can use it to name their entries.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    bytes: Vec<u8>,
+    hash: u64,
+}
+
+impl CacheKey {
+    /// Make the key of `func` compiled for `isa`.
+    pub fn new(func: &Function, isa: &dyn TargetIsa) -> Self {
+        let mut bytes = format!(
+            "cranelift {}\n{} {}\n{}\n",
+            crate::VERSION,
+            isa.name(),
+            isa.triple(),
+            isa
+        )
+        .into_bytes();
+        bytes.extend_from_slice(&encode_function(func, None));
+
+        // 64-bit FNV-1a.
+        let mut hash = 0xcbf2_9ce4_8422_2325u64;
+        for &byte in &bytes {
+            hash = (hash ^ u64::from(byte)).wrapping_mul(0x100_0000_01b3);
+        }
+        Self { bytes, hash }
+    }
+
+    /// Get the bytes of the key.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Get the hash of the key.
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+}
+
+/// A cache of compiled functions, backed by a `CacheStore`.
+///
+/// The cache can be shared by several threads compiling functions.
+pub struct CompilationCache {
+    store: Box<dyn CacheStore>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl CompilationCache {
+    /// Create a cache keeping its entries in `store`.
+    pub fn new(store: Box<dyn CacheStore>) -> Self {
+        Self {
+            store,
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        }
+    }
+
+    /// Create a cache keeping its entries in memory.
+    pub fn in_memory() -> Self {
+        Self::new(Box::new(MemoryCacheStore::new()))
+    }
+
+    /// Create a cache keeping its entries in the directory `dir`, which is created if needed.
+    pub fn in_directory<P: Into<PathBuf>>(dir: P) -> io::Result<Self> {
+        Ok(Self::new(Box::new(DirectoryCacheStore::new(dir)?)))
+    }
+
+    /// Get the number of compilations which were found in the cache.
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Get the number of compilations which weren't found in the cache.
+    pub fn misses(&self) -> usize {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Look up the compiled function and code stored for `key`.
+    ///
+    /// Entries which can't be decoded, e.g. because they were written by another version of
+    /// Cranelift, are ignored.
+    fn get(&self, key: &CacheKey) -> Option<(Function, CachedCode)> {
+        let entry = self.store.get(key)?;
+        match decode_entry(&entry) {
+            Ok(found) => Some(found),
+            Err(e) => {
+                warn!("ignoring invalid cache entry {:016x}: {}", key.hash(), e);
+                None
+            }
+        }
+    }
+
+    /// Store the compiled function `func` and its `code` for `key`.
+    fn insert(&self, key: &CacheKey, func: &Function, code: &CachedCode) {
+        let mut enc = Encoder::new();
+        enc.bytes(&encode_function(func, Some(&code.info())));
+        code.encode(&mut enc);
+        self.store.insert(key, &enc.into_bytes());
+    }
+}
+
+fn decode_entry(entry: &[u8]) -> Result<(Function, CachedCode), DecodeError> {
+    let mut dec = Decoder::new(entry);
+    let (func, _) = decode_function(dec.bytes()?)?;
+    let code = CachedCode::decode(&mut dec, func.jump_tables.len())?;
+    dec.finish()?;
+    Ok((func, code))
+}
+
+/// Compile the function in `ctx` for `isa`, or take it from `cache`.
+///
+/// Value labels aren't preserved by the binary format, so functions which have some are always
+/// compiled, and never cached.
+pub(crate) fn compile(
+    ctx: &mut Context,
+    isa: &dyn TargetIsa,
+    cache: &CompilationCache,
+) -> CodegenResult<CodeInfo> {
+    if ctx.func.dfg.values_labels.is_some() {
+        return ctx.compile(isa);
+    }
+
+    let key = CacheKey::new(&ctx.func, isa);
+    if let Some((func, code)) = cache.get(&key) {
+        debug!("found {} in the compilation cache", func.name);
+        cache.hits.fetch_add(1, Ordering::Relaxed);
+        ctx.clear();
+        ctx.func = func;
+        ctx.flowgraph();
+        let info = code.info();
+        ctx.cached_code = Some(code);
+        return Ok(info);
+    }
+
+    cache.misses.fetch_add(1, Ordering::Relaxed);
+    let info = ctx.compile(isa)?;
+    let mut code = CachedCode::new(info);
+    {
+        let (mem, mut relocs, mut traps, mut stackmaps) = code.recorders();
+        unsafe { ctx.emit_to_memory(isa, mem, &mut relocs, &mut traps, &mut stackmaps) };
+    }
+    cache.insert(&key, &ctx.func, &code);
+    ctx.cached_code = Some(code);
+    Ok(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::binemit::{Addend, CodeOffset, Reloc, RelocSink, Stackmap, StackmapSink, TrapSink};
+    use crate::cursor::{Cursor, FuncCursor};
+    use crate::ir::{
+        types, AbiParam, ConstantOffset, ExtFuncData, ExternalName, InstBuilder, JumpTable,
+        Signature, SourceLoc, TrapCode,
+    };
+    use crate::isa::{self, CallConv};
+    use crate::settings::{self, Configurable};
+    use core::str::FromStr;
+    use std::fs;
+    use std::string::{String, ToString};
+    use target_lexicon::triple;
+
+    fn x86_64(opt_level: &str) -> Option<Box<dyn TargetIsa>> {
+        let mut flags = settings::builder();
+        flags.set("opt_level", opt_level).unwrap();
+        isa::lookup(triple!("x86_64"))
+            .ok()
+            .map(|b| b.finish(settings::Flags::new(flags)))
+    }
+
+    /// A function with a call, a division which can trap, and a trap.
+    fn sample_function() -> Function {
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params.push(AbiParam::new(types::I32));
+        sig.params.push(AbiParam::new(types::I32));
+        sig.returns.push(AbiParam::new(types::I32));
+        let mut func = Function::with_name_signature(ExternalName::user(0, 3), sig.clone());
+        let callee_sig = func.import_signature(sig);
+        let callee = func.import_function(ExtFuncData {
+            name: ExternalName::user(0, 4),
+            signature: callee_sig,
+            colocated: false,
+        });
+
+        let ebb0 = func.dfg.make_ebb();
+        let mut pos = FuncCursor::new(&mut func);
+        pos.insert_ebb(ebb0);
+        let x = pos.func.dfg.append_ebb_param(ebb0, types::I32);
+        let y = pos.func.dfg.append_ebb_param(ebb0, types::I32);
+        pos.set_srcloc(SourceLoc::new(7));
+        let quotient = pos.ins().udiv(x, y);
+        pos.ins().trapz(quotient, TrapCode::User(3));
+        let call = pos.ins().call(callee, &[quotient, y]);
+        let result = pos.func.dfg.inst_results(call)[0];
+        pos.ins().return_(&[result]);
+        func
+    }
+
+    /// A sink logging everything reported to it.
+    #[derive(Default)]
+    struct Log(Vec<String>);
+
+    impl RelocSink for Log {
+        fn reloc_ebb(&mut self, offset: CodeOffset, reloc: Reloc, ebb_offset: CodeOffset) {
+            self.0
+                .push(format!("{}: {} ebb {}", offset, reloc, ebb_offset));
+        }
+        fn reloc_external(
+            &mut self,
+            offset: CodeOffset,
+            reloc: Reloc,
+            name: &ExternalName,
+            a: Addend,
+        ) {
+            self.0
+                .push(format!("{}: {} {}{:+}", offset, reloc, name, a));
+        }
+        fn reloc_constant(&mut self, offset: CodeOffset, reloc: Reloc, constant: ConstantOffset) {
+            self.0
+                .push(format!("{}: {} constant {}", offset, reloc, constant));
+        }
+        fn reloc_jt(&mut self, offset: CodeOffset, reloc: Reloc, jt: JumpTable) {
+            self.0.push(format!("{}: {} {}", offset, reloc, jt));
+        }
+    }
+
+    impl TrapSink for Log {
+        fn trap(&mut self, offset: CodeOffset, srcloc: SourceLoc, code: TrapCode) {
+            self.0
+                .push(format!("{}: trap {} at {}", offset, code, srcloc));
+        }
+    }
+
+    impl StackmapSink for Log {
+        fn add_stackmap(&mut self, offset: CodeOffset, _: Stackmap) {
+            self.0.push(format!("{}: stackmap", offset));
+        }
+    }
+
+    /// Emit the function compiled in `ctx`, returning the code and what was reported to the sinks.
+    fn emit(ctx: &Context, isa: &dyn TargetIsa, info: CodeInfo) -> (Vec<u8>, Vec<String>) {
+        let mut code = vec![0; info.total_size as usize];
+        let mut relocs = Log::default();
+        let mut traps = Log::default();
+        let mut stackmaps = Log::default();
+        let emitted = unsafe {
+            ctx.emit_to_memory(
+                isa,
+                code.as_mut_ptr(),
+                &mut relocs,
+                &mut traps,
+                &mut stackmaps,
+            )
+        };
+        assert_eq!(emitted, info);
+        let mut log = relocs.0;
+        log.extend(traps.0);
+        log.extend(stackmaps.0);
+        (code, log)
+    }
+
+    #[test]
+    fn memory_cache() {
+        let isa = match x86_64("best") {
+            Some(isa) => isa,
+            None => return,
+        };
+        let cache = CompilationCache::in_memory();
+
+        let mut uncached = Context::for_function(sample_function());
+        let info = uncached.compile(&*isa).unwrap();
+        let expected = emit(&uncached, &*isa, info);
+        assert!(expected.1.iter().any(|line| line.contains("Abs8 u0:4")));
+        assert!(expected.1.iter().any(|line| line.contains("trap user3")));
+
+        let mut ctx = Context::for_function(sample_function());
+        assert_eq!(ctx.compile_with_cache(&*isa, &cache).unwrap(), info);
+        assert_eq!((cache.hits(), cache.misses()), (0, 1));
+        assert_eq!(emit(&ctx, &*isa, info), expected);
+
+        // The context is reused, like when compiling many functions.
+        ctx.clear();
+        ctx.func = sample_function();
+        assert_eq!(ctx.compile_with_cache(&*isa, &cache).unwrap(), info);
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+        assert_eq!(emit(&ctx, &*isa, info), expected);
+        assert_eq!(
+            ctx.func.display(&*isa).to_string(),
+            uncached.func.display(&*isa).to_string()
+        );
+
+        // Different flags make for a different key.
+        let fastest = x86_64("fastest").unwrap();
+        let mut ctx = Context::for_function(sample_function());
+        ctx.compile_with_cache(&*fastest, &cache).unwrap();
+        assert_eq!((cache.hits(), cache.misses()), (1, 2));
+    }
+
+    #[test]
+    fn directory_cache() {
+        let isa = match x86_64("best") {
+            Some(isa) => isa,
+            None => return,
+        };
+        let dir = std::env::temp_dir().join(format!("cranelift-cache-test-{}", std::process::id()));
+
+        let cache = CompilationCache::in_directory(&dir).unwrap();
+        let mut ctx = Context::for_function(sample_function());
+        let info = ctx.compile_with_cache(&*isa, &cache).unwrap();
+        let expected = emit(&ctx, &*isa, info);
+        assert_eq!(cache.misses(), 1);
+
+        // Another cache using the same directory finds the function, like in another run.
+        let cache = CompilationCache::in_directory(&dir).unwrap();
+        let mut ctx = Context::for_function(sample_function());
+        assert_eq!(ctx.compile_with_cache(&*isa, &cache).unwrap(), info);
+        assert_eq!((cache.hits(), cache.misses()), (1, 0));
+        assert_eq!(emit(&ctx, &*isa, info), expected);
+
+        // Corrupted entries are recompiled.
+        for entry in fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            let mut bytes = fs::read(&path).unwrap();
+            let len = bytes.len();
+            bytes.truncate(len - 3);
+            fs::write(&path, bytes).unwrap();
+        }
+        let mut ctx = Context::for_function(sample_function());
+        assert_eq!(ctx.compile_with_cache(&*isa, &cache).unwrap(), info);
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+        assert_eq!(emit(&ctx, &*isa, info), expected);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn concurrent_directory_writes() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let dir = std::env::temp_dir().join(format!(
+            "cranelift-cache-threads-test-{}",
+            std::process::id()
+        ));
+        let store = Arc::new(DirectoryCacheStore::new(&dir).unwrap());
+        let key = CacheKey {
+            bytes: b"key".to_vec(),
+            hash: 1,
+        };
+
+        // Threads writing the same key don't tear each other's entries.
+        let threads: Vec<_> = (0..8u8)
+            .map(|i| {
+                let store = Arc::clone(&store);
+                let key = key.clone();
+                thread::spawn(move || {
+                    for _ in 0..20 {
+                        store.insert(&key, &[i; 0x1_0000]);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let entry = store.get(&key).unwrap();
+        assert_eq!(entry.len(), 0x1_0000);
+        assert!(entry.iter().all(|&byte| byte == entry[0]));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
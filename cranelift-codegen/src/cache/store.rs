@@ -0,0 +1,135 @@
+//! Stores for the entries of a compilation cache.
+
+use super::CacheKey;
+use log::warn;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::vec::Vec;
+
+/// A place to keep the entries of a `CompilationCache`.
+///
+/// Entries are opaque bytes. A store may drop entries at any time, so losing or failing to write
+/// an entry only costs a recompilation. Stores are shared between compilation threads.
+pub trait CacheStore: Send + Sync {
+    /// Get the entry stored for `key`, if any.
+    fn get(&self, key: &CacheKey) -> Option<Vec<u8>>;
+
+    /// Store `entry` for `key`, replacing any previous entry.
+    fn insert(&self, key: &CacheKey, entry: &[u8]);
+}
+
+/// A `CacheStore` keeping the entries in memory, for the lifetime of the store.
+#[derive(Default)]
+pub struct MemoryCacheStore {
+    entries: Mutex<HashMap<CacheKey, Vec<u8>>>,
+}
+
+impl MemoryCacheStore {
+    /// Create an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the number of entries in the store.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    /// Check if the store has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.lock().unwrap().is_empty()
+    }
+}
+
+impl CacheStore for MemoryCacheStore {
+    fn get(&self, key: &CacheKey) -> Option<Vec<u8>> {
+        self.entries.lock().unwrap().get(key).cloned()
+    }
+
+    fn insert(&self, key: &CacheKey, entry: &[u8]) {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(key.clone(), entry.to_vec());
+    }
+}
+
+/// A `CacheStore` keeping each entry in a file of a directory, so it persists between runs.
+///
+/// Files are named after the hash of their key, and also contain the whole key, which is checked
+/// when the entry is read back. Entries are written to a temporary file first, and then renamed,
+/// so several threads and processes can share a directory.
+pub struct DirectoryCacheStore {
+    dir: PathBuf,
+    /// The number of temporary files created so far, which makes their names unique within the
+    /// process.
+    tmp_files: AtomicUsize,
+}
+
+impl DirectoryCacheStore {
+    /// Create a store using the directory `dir`, creating it if needed.
+    pub fn new<P: Into<PathBuf>>(dir: P) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            tmp_files: AtomicUsize::new(0),
+        })
+    }
+
+    /// Get the directory containing the entries.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, key: &CacheKey) -> PathBuf {
+        self.dir.join(format!("{:016x}.clc", key.hash()))
+    }
+
+    fn write(&self, path: &Path, key: &CacheKey, entry: &[u8]) -> io::Result<()> {
+        let n = self.tmp_files.fetch_add(1, Ordering::Relaxed);
+        let tmp = path.with_extension(format!("tmp{}-{}", process::id(), n));
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp)?;
+        let key_len = key.as_bytes().len() as u64;
+        file.write_all(&key_len.to_le_bytes())?;
+        file.write_all(key.as_bytes())?;
+        file.write_all(entry)?;
+        drop(file);
+        fs::rename(&tmp, path).map_err(|e| {
+            let _ = fs::remove_file(&tmp);
+            e
+        })
+    }
+}
+
+impl CacheStore for DirectoryCacheStore {
+    fn get(&self, key: &CacheKey) -> Option<Vec<u8>> {
+        let mut bytes = fs::read(self.path(key)).ok()?;
+        if bytes.len() < 8 {
+            return None;
+        }
+        let mut key_len = [0; 8];
+        key_len.copy_from_slice(&bytes[..8]);
+        let key_end = u64::from_le_bytes(key_len).checked_add(8)?;
+        if key_end > bytes.len() as u64 || &bytes[8..key_end as usize] != key.as_bytes() {
+            // Another key with the same hash.
+            return None;
+        }
+        Some(bytes.split_off(key_end as usize))
+    }
+
+    fn insert(&self, key: &CacheKey, entry: &[u8]) {
+        let path = self.path(key);
+        if let Err(e) = self.write(&path, key, entry) {
+            warn!("failed to write cache entry {}: {}", path.display(), e);
+        }
+    }
+}
@@ -10,23 +10,29 @@
 //! single ISA instance.
 
 use crate::binemit::{
-    relax_branches, shrink_instructions, CodeInfo, MemoryCodeSink, RelocSink, StackmapSink,
-    TrapSink,
+    relax_branches, shrink_instructions, CodeInfo, FrameUnwindKind, FrameUnwindSink,
+    MemoryCodeSink, RelocSink, StackmapSink, TrapSink,
 };
+#[cfg(feature = "cache")]
+use crate::cache::{self, CachedCode, CompilationCache};
 use crate::dce::do_dce;
 use crate::dominator_tree::DominatorTree;
 use crate::flowgraph::ControlFlowGraph;
-use crate::ir::Function;
+use crate::inline::do_inlining;
+use crate::ir::{ExternalName, Function};
 use crate::isa::TargetIsa;
 use crate::legalize_function;
 use crate::licm::do_licm;
 use crate::loop_analysis::LoopAnalysis;
+use crate::loop_unroll::do_loop_unroll;
+use crate::loop_unswitch::do_loop_unswitch;
 use crate::nan_canonicalization::do_nan_canonicalization;
 use crate::postopt::do_postopt;
+use crate::redundant_load_elim::do_redundant_load_elim;
 use crate::redundant_reload_remover::RedundantReloadRemover;
 use crate::regalloc;
 use crate::result::CodegenResult;
-use crate::settings::{FlagsOrIsa, OptLevel};
+use crate::settings::{FlagsOrIsa, LoopOpts, OptLevel};
 use crate::simple_gvn::do_simple_gvn;
 use crate::simple_preopt::do_preopt;
 use crate::timing;
@@ -54,6 +60,10 @@ pub struct Context {
 
     /// Redundant-reload remover context.
     pub redundant_reload_remover: RedundantReloadRemover,
+
+    /// The code of `func` recorded by `compile_with_cache`, which is replayed when emitting it.
+    #[cfg(feature = "cache")]
+    pub(crate) cached_code: Option<CachedCode>,
 }
 
 impl Context {
@@ -77,6 +87,8 @@ impl Context {
             regalloc: regalloc::Context::new(),
             loop_analysis: LoopAnalysis::new(),
             redundant_reload_remover: RedundantReloadRemover::new(),
+            #[cfg(feature = "cache")]
+            cached_code: None,
         }
     }
 
@@ -88,6 +100,10 @@ impl Context {
         self.regalloc.clear();
         self.loop_analysis.clear();
         self.redundant_reload_remover.clear();
+        #[cfg(feature = "cache")]
+        {
+            self.cached_code = None;
+        }
     }
 
     /// Compile the function, and emit machine code into a `Vec<u8>`.
@@ -128,6 +144,10 @@ impl Context {
     /// Returns information about the function's code and read-only data.
     pub fn compile(&mut self, isa: &dyn TargetIsa) -> CodegenResult<CodeInfo> {
         let _tt = timing::compile();
+        #[cfg(feature = "cache")]
+        {
+            self.cached_code = None;
+        }
         self.verify_if(isa)?;
 
         self.compute_cfg();
@@ -137,6 +157,17 @@ impl Context {
         if isa.flags().enable_nan_canonicalization() {
             self.canonicalize_nans(isa)?;
         }
+        let loop_opts = isa.flags().loop_opts();
+        if isa.flags().opt_level() == OptLevel::Best && loop_opts != LoopOpts::None {
+            self.compute_domtree();
+            self.compute_loop_analysis();
+            if loop_opts != LoopOpts::Unroll {
+                self.loop_unswitch(isa)?;
+            }
+            if loop_opts != LoopOpts::Unswitch {
+                self.loop_unroll(isa)?;
+            }
+        }
         self.legalize(isa)?;
         if isa.flags().opt_level() != OptLevel::Fastest {
             self.postopt(isa)?;
@@ -146,6 +177,7 @@ impl Context {
             self.compute_loop_analysis();
             self.licm(isa)?;
             self.simple_gvn(isa)?;
+            self.redundant_load_elim(isa)?;
         }
         self.compute_domtree();
         self.eliminate_unreachable_code(isa)?;
@@ -161,12 +193,38 @@ impl Context {
         self.relax_branches(isa)
     }
 
+    /// Compile the function, or find it in `cache` if it has been compiled before with the same
+    /// ISA and flags.
+    ///
+    /// Either way, the context holds the compiled function afterwards, and the code emitted for
+    /// it is recorded, so `emit_to_memory` replays it instead of emitting the function again.
+    /// Functions with value labels are always compiled with `compile`, since the labels aren't
+    /// cached.
+    ///
+    /// Returns information about the function's code and read-only data.
+    #[cfg(feature = "cache")]
+    pub fn compile_with_cache(
+        &mut self,
+        isa: &dyn TargetIsa,
+        cache: &CompilationCache,
+    ) -> CodegenResult<CodeInfo> {
+        cache::compile(self, isa, cache)
+    }
+
+    /// Get the code recorded by `compile_with_cache`, if the function was compiled with it.
+    #[cfg(feature = "cache")]
+    pub fn cached_code(&self) -> Option<&CachedCode> {
+        self.cached_code.as_ref()
+    }
+
     /// Emit machine code directly into raw memory.
     ///
     /// Write all of the function's machine code to the memory at `mem`. The size of the machine
     /// code is returned by `compile` above.
     ///
     /// The machine code is not relocated. Instead, any relocations are emitted into `relocs`.
+    /// If the function was compiled by `compile_with_cache`, the recorded code and relocations
+    /// are replayed.
     ///
     /// This function is unsafe since it does not perform bounds checking on the memory buffer,
     /// and it can't guarantee that the `mem` pointer is valid.
@@ -181,11 +239,32 @@ impl Context {
         stackmaps: &mut dyn StackmapSink,
     ) -> CodeInfo {
         let _tt = timing::binemit();
+        #[cfg(feature = "cache")]
+        {
+            if let Some(ref code) = self.cached_code {
+                return code.replay(mem, relocs, traps, stackmaps);
+            }
+        }
         let mut sink = MemoryCodeSink::new(mem, relocs, traps, stackmaps);
         isa.emit_function_to_memory(&self.func, &mut sink);
         sink.info
     }
 
+    /// Emit unwind information.
+    ///
+    /// Requires that the function layout be calculated (see `relax_branches`).
+    ///
+    /// Only some calling conventions (e.g. Windows fastcall) will have unwind information.
+    /// This is a no-op if the function has no unwind information.
+    pub fn emit_unwind_info(
+        &self,
+        isa: &dyn TargetIsa,
+        kind: FrameUnwindKind,
+        sink: &mut dyn FrameUnwindSink,
+    ) {
+        isa.emit_unwind_info(&self.func, kind, sink);
+    }
+
     /// Run the verifier on the function.
     ///
     /// Also check that the dominator tree and control flow graph are consistent with the function.
@@ -236,6 +315,23 @@ impl Context {
         Ok(())
     }
 
+    /// Inline the functions called by the function, as found by `lookup`.
+    ///
+    /// This must run before legalization. Calls whose callee isn't returned by `lookup`, or
+    /// can't be inlined, are left alone.
+    pub fn inline<'a, 'f, FOI, F>(&mut self, fisa: FOI, lookup: F) -> CodegenResult<()>
+    where
+        FOI: Into<FlagsOrIsa<'a>>,
+        F: FnMut(&ExternalName) -> Option<&'f Function>,
+    {
+        // Inlining invalidates the control flow graph and everything derived from it.
+        self.cfg.clear();
+        self.domtree.clear();
+        self.loop_analysis.clear();
+        do_inlining(&mut self.func, lookup);
+        self.verify_if(fisa)
+    }
+
     /// Perform pre-legalization rewrites on the function.
     pub fn preopt(&mut self, isa: &dyn TargetIsa) -> CodegenResult<()> {
         do_preopt(&mut self.func, &mut self.cfg);
@@ -294,6 +390,15 @@ impl Context {
         self.verify_if(fisa)
     }
 
+    /// Perform redundant load elimination and store-to-load forwarding on the function.
+    pub fn redundant_load_elim<'a, FOI: Into<FlagsOrIsa<'a>>>(
+        &mut self,
+        fisa: FOI,
+    ) -> CodegenResult<()> {
+        do_redundant_load_elim(&mut self.func, &self.cfg, &self.domtree);
+        self.verify_if(fisa)
+    }
+
     /// Perform LICM on the function.
     pub fn licm(&mut self, isa: &dyn TargetIsa) -> CodegenResult<()> {
         do_licm(
@@ -306,6 +411,28 @@ impl Context {
         self.verify_if(isa)
     }
 
+    /// Perform loop unswitching on the function.
+    pub fn loop_unswitch<'a, FOI: Into<FlagsOrIsa<'a>>>(&mut self, fisa: FOI) -> CodegenResult<()> {
+        do_loop_unswitch(
+            &mut self.func,
+            &mut self.cfg,
+            &mut self.domtree,
+            &mut self.loop_analysis,
+        );
+        self.verify_if(fisa)
+    }
+
+    /// Perform loop unrolling on the function.
+    pub fn loop_unroll<'a, FOI: Into<FlagsOrIsa<'a>>>(&mut self, fisa: FOI) -> CodegenResult<()> {
+        do_loop_unroll(
+            &mut self.func,
+            &mut self.cfg,
+            &mut self.domtree,
+            &mut self.loop_analysis,
+        );
+        self.verify_if(fisa)
+    }
+
     /// Perform unreachable code elimination.
     pub fn eliminate_unreachable_code<'a, FOI>(&mut self, fisa: FOI) -> CodegenResult<()>
     where
@@ -0,0 +1,805 @@
+//! DWARF debug information for compiled functions.
+//!
+//! The source locations of a function's instructions are turned into a `.debug_line` line
+//! program, and the value label ranges computed by `Context::build_value_labels_ranges` into
+//! variables described by `.debug_loc` location lists. The sections are encoded by hand in the
+//! 32-bit DWARF 4 format, and refer to the functions and to each other through relocations that
+//! must be applied by the object file writer.
+//!
+//! Source locations are opaque to Cranelift, so the frontend is expected to set them to line
+//! numbers in the source file of the compilation unit.
+
+use crate::binemit::CodeOffset;
+use crate::entity::EntityRef;
+use crate::ir::{Function, StackSlotKind, Type, ValueLabel, ValueLabelAssignments, ValueLoc};
+use crate::isa::TargetIsa;
+use crate::value_label::ValueLabelsRanges;
+use std::collections::HashMap;
+use std::string::{String, ToString};
+use std::vec::Vec;
+
+/// DWARF tags.
+const DW_TAG_COMPILE_UNIT: u8 = 0x11;
+const DW_TAG_BASE_TYPE: u8 = 0x24;
+const DW_TAG_SUBPROGRAM: u8 = 0x2e;
+const DW_TAG_VARIABLE: u8 = 0x34;
+
+/// DWARF attributes.
+const DW_AT_LOCATION: u8 = 0x02;
+const DW_AT_NAME: u8 = 0x03;
+const DW_AT_BYTE_SIZE: u8 = 0x0b;
+const DW_AT_STMT_LIST: u8 = 0x10;
+const DW_AT_LOW_PC: u8 = 0x11;
+const DW_AT_HIGH_PC: u8 = 0x12;
+const DW_AT_COMP_DIR: u8 = 0x1b;
+const DW_AT_PRODUCER: u8 = 0x25;
+const DW_AT_ENCODING: u8 = 0x3e;
+const DW_AT_FRAME_BASE: u8 = 0x40;
+const DW_AT_TYPE: u8 = 0x49;
+const DW_AT_RANGES: u8 = 0x55;
+
+/// DWARF attribute forms.
+const DW_FORM_ADDR: u8 = 0x01;
+const DW_FORM_DATA4: u8 = 0x06;
+const DW_FORM_STRING: u8 = 0x08;
+const DW_FORM_DATA1: u8 = 0x0b;
+const DW_FORM_REF4: u8 = 0x13;
+const DW_FORM_SEC_OFFSET: u8 = 0x17;
+const DW_FORM_EXPRLOC: u8 = 0x18;
+
+/// DWARF base type encodings.
+const DW_ATE_BOOLEAN: u8 = 0x02;
+const DW_ATE_FLOAT: u8 = 0x04;
+const DW_ATE_SIGNED: u8 = 0x05;
+
+/// DWARF location expression operations.
+const DW_OP_REG0: u8 = 0x50;
+const DW_OP_REGX: u8 = 0x90;
+const DW_OP_FBREG: u8 = 0x91;
+const DW_OP_CALL_FRAME_CFA: u8 = 0x9c;
+
+/// DWARF line number program opcodes.
+const DW_LNS_COPY: u8 = 0x01;
+const DW_LNS_ADVANCE_PC: u8 = 0x02;
+const DW_LNS_ADVANCE_LINE: u8 = 0x03;
+const DW_LNE_END_SEQUENCE: u8 = 0x01;
+const DW_LNE_SET_ADDRESS: u8 = 0x02;
+
+/// Number of operands of the standard opcodes, from `DW_LNS_copy` to `DW_LNS_set_isa`.
+const STANDARD_OPCODE_LENGTHS: [u8; 12] = [0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1];
+
+/// Abbreviation codes of the debugging information entries.
+const ABBREV_COMPILE_UNIT: u8 = 1;
+const ABBREV_SUBPROGRAM: u8 = 2;
+const ABBREV_BASE_TYPE: u8 = 3;
+const ABBREV_VARIABLE: u8 = 4;
+const ABBREV_UNTYPED_VARIABLE: u8 = 5;
+
+/// An abbreviation: its code, tag, whether it has children, and its attributes and their forms.
+type Abbrev = (u8, u8, bool, &'static [(u8, u8)]);
+
+/// The abbreviations of all the entries.
+const ABBREVS: [Abbrev; 5] = [
+    (
+        ABBREV_COMPILE_UNIT,
+        DW_TAG_COMPILE_UNIT,
+        true,
+        &[
+            (DW_AT_PRODUCER, DW_FORM_STRING),
+            (DW_AT_NAME, DW_FORM_STRING),
+            (DW_AT_COMP_DIR, DW_FORM_STRING),
+            (DW_AT_STMT_LIST, DW_FORM_SEC_OFFSET),
+            (DW_AT_LOW_PC, DW_FORM_ADDR),
+            (DW_AT_RANGES, DW_FORM_SEC_OFFSET),
+        ],
+    ),
+    (
+        ABBREV_SUBPROGRAM,
+        DW_TAG_SUBPROGRAM,
+        true,
+        &[
+            (DW_AT_NAME, DW_FORM_STRING),
+            (DW_AT_LOW_PC, DW_FORM_ADDR),
+            (DW_AT_HIGH_PC, DW_FORM_DATA4),
+            (DW_AT_FRAME_BASE, DW_FORM_EXPRLOC),
+        ],
+    ),
+    (
+        ABBREV_BASE_TYPE,
+        DW_TAG_BASE_TYPE,
+        false,
+        &[
+            (DW_AT_NAME, DW_FORM_STRING),
+            (DW_AT_ENCODING, DW_FORM_DATA1),
+            (DW_AT_BYTE_SIZE, DW_FORM_DATA1),
+        ],
+    ),
+    (
+        ABBREV_VARIABLE,
+        DW_TAG_VARIABLE,
+        false,
+        &[
+            (DW_AT_NAME, DW_FORM_STRING),
+            (DW_AT_TYPE, DW_FORM_REF4),
+            (DW_AT_LOCATION, DW_FORM_SEC_OFFSET),
+        ],
+    ),
+    (
+        ABBREV_UNTYPED_VARIABLE,
+        DW_TAG_VARIABLE,
+        false,
+        &[
+            (DW_AT_NAME, DW_FORM_STRING),
+            (DW_AT_LOCATION, DW_FORM_SEC_OFFSET),
+        ],
+    ),
+];
+
+/// The DWARF debug sections emitted for a compilation unit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DwarfSectionId {
+    /// `.debug_abbrev`: abbreviations of the debugging information entries.
+    DebugAbbrev,
+    /// `.debug_info`: the compilation unit, its functions and their variables.
+    DebugInfo,
+    /// `.debug_line`: line number programs.
+    DebugLine,
+    /// `.debug_loc`: variable location lists.
+    DebugLoc,
+    /// `.debug_ranges`: address ranges of the compilation unit.
+    DebugRanges,
+}
+
+impl DwarfSectionId {
+    /// Get the name of the section in an ELF object file.
+    pub fn name(self) -> &'static str {
+        match self {
+            DwarfSectionId::DebugAbbrev => ".debug_abbrev",
+            DwarfSectionId::DebugInfo => ".debug_info",
+            DwarfSectionId::DebugLine => ".debug_line",
+            DwarfSectionId::DebugLoc => ".debug_loc",
+            DwarfSectionId::DebugRanges => ".debug_ranges",
+        }
+    }
+}
+
+/// The target of a relocation in a debug section.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DwarfRelocTarget {
+    /// The address of a function, by symbol name.
+    Symbol(String),
+    /// An offset into another debug section.
+    Section(DwarfSectionId),
+}
+
+/// A relocation in a debug section.
+///
+/// The addend is also stored in the section contents, for object formats with implicit addends.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DwarfReloc {
+    /// Offset of the relocated field in the section.
+    pub offset: u32,
+    /// Size of the relocated field in bytes.
+    pub size: u8,
+    /// What the field refers to.
+    pub target: DwarfRelocTarget,
+    /// Value added to the address of the target.
+    pub addend: i64,
+}
+
+/// The contents of a debug section.
+#[derive(Clone, Debug)]
+pub struct DwarfSection {
+    /// Which section this is.
+    pub id: DwarfSectionId,
+    /// The bytes of the section.
+    pub data: Vec<u8>,
+    /// The relocations to apply to `data`.
+    pub relocs: Vec<DwarfReloc>,
+}
+
+/// A variable, with its DWARF location expression in each of its ranges.
+struct Variable {
+    name: String,
+    ty: Option<Type>,
+    locations: Vec<(CodeOffset, CodeOffset, Vec<u8>)>,
+}
+
+/// The debug information of a single compiled function.
+pub struct FunctionDebugInfo {
+    name: String,
+    code_size: CodeOffset,
+    /// Code offsets where the line changes, and the new line.
+    lines: Vec<(CodeOffset, u32)>,
+    variables: Vec<Variable>,
+}
+
+impl FunctionDebugInfo {
+    /// Collect the debug information of `func`, which has been compiled for `isa`.
+    ///
+    /// `name` is the symbol of the function, `code_size` is the size of its code as returned by
+    /// the compilation in `CodeInfo`, and `ranges` are its value label ranges. Each value label
+    /// becomes a variable named by `label_name`.
+    pub fn new(
+        name: &str,
+        func: &Function,
+        isa: &dyn TargetIsa,
+        code_size: CodeOffset,
+        ranges: &ValueLabelsRanges,
+        label_name: &dyn Fn(ValueLabel) -> String,
+    ) -> Self {
+        let encinfo = isa.encoding_info();
+        let mut lines = Vec::new();
+        let mut last_line = None;
+        for ebb in func.layout.ebbs() {
+            for (offset, inst, _) in func.inst_offsets(ebb, &encinfo) {
+                let srcloc = func.srclocs[inst];
+                if srcloc.is_default() || last_line == Some(srcloc.bits()) {
+                    continue;
+                }
+                lines.push((offset, srcloc.bits()));
+                last_line = Some(srcloc.bits());
+            }
+        }
+
+        let label_types = value_label_types(func);
+        let mut labels: Vec<_> = ranges.keys().cloned().collect();
+        labels.sort_by_key(|label| label.index());
+        let mut variables = Vec::new();
+        for label in labels {
+            let locations: Vec<_> = ranges[&label]
+                .iter()
+                .filter(|range| range.start < range.end)
+                .filter_map(|range| {
+                    location_expression(func, isa, range.loc)
+                        .map(|expr| (range.start, range.end, expr))
+                })
+                .collect();
+            if locations.is_empty() {
+                continue;
+            }
+            variables.push(Variable {
+                name: label_name(label),
+                ty: label_types.get(&label).cloned(),
+                locations,
+            });
+        }
+
+        Self {
+            name: name.to_string(),
+            code_size,
+            lines,
+            variables,
+        }
+    }
+}
+
+/// Find the type of the values carrying each value label.
+fn value_label_types(func: &Function) -> HashMap<ValueLabel, Type> {
+    let mut types = HashMap::new();
+    if let Some(ref values_labels) = func.dfg.values_labels {
+        for (&value, assigns) in values_labels {
+            if let ValueLabelAssignments::Starts(ref starts) = *assigns {
+                for start in starts {
+                    types
+                        .entry(start.label)
+                        .or_insert_with(|| func.dfg.value_type(value));
+                }
+            }
+        }
+    }
+    types
+}
+
+/// Get the DWARF expression describing `loc`, if it can be described.
+///
+/// Stack slots are described relative to the frame base, which is the canonical frame address.
+/// That is where the stack slot offsets are based: the stack pointer in the caller before the
+/// call.
+fn location_expression(func: &Function, isa: &dyn TargetIsa, loc: ValueLoc) -> Option<Vec<u8>> {
+    let mut expr = Vec::new();
+    match loc {
+        ValueLoc::Reg(reg) => {
+            let reg = isa.map_dwarf_register(reg)?;
+            if reg < 32 {
+                expr.push(DW_OP_REG0 + reg as u8);
+            } else {
+                expr.push(DW_OP_REGX);
+                put_uleb128(&mut expr, reg.into());
+            }
+        }
+        ValueLoc::Stack(ss) => {
+            let slot = &func.stack_slots[ss];
+            if slot.kind == StackSlotKind::OutgoingArg {
+                return None;
+            }
+            expr.push(DW_OP_FBREG);
+            put_sleb128(&mut expr, slot.offset?.into());
+        }
+        ValueLoc::Unassigned => return None,
+    }
+    Some(expr)
+}
+
+/// Get the DWARF encoding of a base type, if `ty` is a scalar.
+fn base_type_encoding(ty: Type) -> Option<u8> {
+    if ty.is_int() {
+        Some(DW_ATE_SIGNED)
+    } else if ty.is_float() {
+        Some(DW_ATE_FLOAT)
+    } else if ty.is_bool() {
+        Some(DW_ATE_BOOLEAN)
+    } else {
+        None
+    }
+}
+
+fn put_uleb128(buf: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf.push(byte);
+            return;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+fn put_sleb128(buf: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            buf.push(byte);
+            return;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+/// A debug section being written. All values are little-endian.
+struct SectionWriter {
+    id: DwarfSectionId,
+    data: Vec<u8>,
+    relocs: Vec<DwarfReloc>,
+}
+
+impl SectionWriter {
+    fn new(id: DwarfSectionId) -> Self {
+        Self {
+            id,
+            data: Vec::new(),
+            relocs: Vec::new(),
+        }
+    }
+
+    fn len(&self) -> u32 {
+        self.data.len() as u32
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn uleb128(&mut self, value: u64) {
+        put_uleb128(&mut self.data, value);
+    }
+
+    fn sleb128(&mut self, value: i64) {
+        put_sleb128(&mut self.data, value);
+    }
+
+    fn string(&mut self, value: &str) {
+        self.data.extend_from_slice(value.as_bytes());
+        self.data.push(0);
+    }
+
+    /// Write a field of `size` bytes holding `value`.
+    fn sized(&mut self, size: u8, value: u64) {
+        self.data
+            .extend_from_slice(&value.to_le_bytes()[..size as usize]);
+    }
+
+    /// Write a relocated field of `size` bytes.
+    fn reloc(&mut self, size: u8, target: DwarfRelocTarget, addend: i64) {
+        self.relocs.push(DwarfReloc {
+            offset: self.len(),
+            size,
+            target,
+            addend,
+        });
+        self.sized(size, addend as u64);
+    }
+
+    /// Write the address `offset` bytes into the function `symbol`.
+    fn address(&mut self, size: u8, symbol: &str, offset: CodeOffset) {
+        self.reloc(
+            size,
+            DwarfRelocTarget::Symbol(symbol.to_string()),
+            offset.into(),
+        );
+    }
+
+    /// Write a 4-byte offset into the section `id`.
+    fn section_offset(&mut self, id: DwarfSectionId, offset: u32) {
+        self.reloc(4, DwarfRelocTarget::Section(id), offset.into());
+    }
+
+    /// Fill in the 4-byte length field at `start` with the size of what follows it.
+    fn patch_length(&mut self, start: u32) {
+        let length = self.len() - start - 4;
+        self.data[start as usize..start as usize + 4].copy_from_slice(&length.to_le_bytes());
+    }
+
+    fn finish(self) -> DwarfSection {
+        DwarfSection {
+            id: self.id,
+            data: self.data,
+            relocs: self.relocs,
+        }
+    }
+}
+
+/// The debug information of a compilation unit: a source file and the functions compiled from
+/// it.
+pub struct DebugInfo {
+    file_name: String,
+    comp_dir: String,
+    address_size: u8,
+    functions: Vec<FunctionDebugInfo>,
+}
+
+impl DebugInfo {
+    /// Create the debug information for the source file `file_name` in the directory `comp_dir`,
+    /// compiled for `isa`.
+    pub fn new(isa: &dyn TargetIsa, file_name: &str, comp_dir: &str) -> Self {
+        Self {
+            file_name: file_name.to_string(),
+            comp_dir: comp_dir.to_string(),
+            address_size: isa.pointer_bytes(),
+            functions: Vec::new(),
+        }
+    }
+
+    /// Add a compiled function to the compilation unit.
+    pub fn add_function(&mut self, function: FunctionDebugInfo) {
+        self.functions.push(function);
+    }
+
+    /// Is the compilation unit empty?
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty()
+    }
+
+    /// Encode the debug sections.
+    pub fn emit(&self) -> Vec<DwarfSection> {
+        let line = self.emit_line();
+        let ranges = self.emit_ranges();
+        let (loc, loc_offsets) = self.emit_loc();
+        let info = self.emit_info(&loc_offsets);
+        vec![
+            self.emit_abbrev(),
+            info.finish(),
+            line.finish(),
+            loc.finish(),
+            ranges.finish(),
+        ]
+    }
+
+    fn emit_abbrev(&self) -> DwarfSection {
+        let mut w = SectionWriter::new(DwarfSectionId::DebugAbbrev);
+        for &(code, tag, has_children, attrs) in ABBREVS.iter() {
+            w.uleb128(code.into());
+            w.uleb128(tag.into());
+            w.u8(has_children as u8);
+            for &(name, form) in attrs {
+                w.uleb128(name.into());
+                w.uleb128(form.into());
+            }
+            w.u8(0);
+            w.u8(0);
+        }
+        w.u8(0);
+        w.finish()
+    }
+
+    fn emit_info(&self, loc_offsets: &[Vec<u32>]) -> SectionWriter {
+        let mut w = SectionWriter::new(DwarfSectionId::DebugInfo);
+        w.u32(0);
+        w.u16(4); // Version.
+        w.section_offset(DwarfSectionId::DebugAbbrev, 0);
+        w.u8(self.address_size);
+
+        // The compilation unit covers the ranges of its functions, relative to a zero base.
+        w.uleb128(ABBREV_COMPILE_UNIT.into());
+        w.string(&format!("Cranelift {}", crate::VERSION));
+        w.string(&self.file_name);
+        w.string(&self.comp_dir);
+        w.section_offset(DwarfSectionId::DebugLine, 0);
+        w.sized(self.address_size, 0);
+        w.section_offset(DwarfSectionId::DebugRanges, 0);
+
+        // The base types of the variables, referred to by their offset in the unit.
+        let mut base_types: Vec<(Type, u32)> = Vec::new();
+        for var in self.functions.iter().flat_map(|f| f.variables.iter()) {
+            let ty = match var.ty {
+                Some(ty) => ty,
+                None => continue,
+            };
+            let encoding = match base_type_encoding(ty) {
+                Some(encoding) => encoding,
+                None => continue,
+            };
+            if base_types.iter().any(|&(t, _)| t == ty) {
+                continue;
+            }
+            base_types.push((ty, w.len()));
+            w.uleb128(ABBREV_BASE_TYPE.into());
+            w.string(&ty.to_string());
+            w.u8(encoding);
+            w.u8(ty.bytes() as u8);
+        }
+
+        for (func, loc_offsets) in self.functions.iter().zip(loc_offsets) {
+            w.uleb128(ABBREV_SUBPROGRAM.into());
+            w.string(&func.name);
+            w.address(self.address_size, &func.name, 0);
+            w.u32(func.code_size);
+            w.uleb128(1);
+            w.u8(DW_OP_CALL_FRAME_CFA);
+
+            for (var, &loc_offset) in func.variables.iter().zip(loc_offsets) {
+                let type_offset = var.ty.and_then(|ty| {
+                    base_types
+                        .iter()
+                        .find(|&&(t, _)| t == ty)
+                        .map(|&(_, offset)| offset)
+                });
+                match type_offset {
+                    Some(offset) => {
+                        w.uleb128(ABBREV_VARIABLE.into());
+                        w.string(&var.name);
+                        w.u32(offset);
+                    }
+                    None => {
+                        w.uleb128(ABBREV_UNTYPED_VARIABLE.into());
+                        w.string(&var.name);
+                    }
+                }
+                w.section_offset(DwarfSectionId::DebugLoc, loc_offset);
+            }
+            w.u8(0);
+        }
+        w.u8(0);
+
+        w.patch_length(0);
+        w
+    }
+
+    fn emit_line(&self) -> SectionWriter {
+        let mut w = SectionWriter::new(DwarfSectionId::DebugLine);
+        w.u32(0);
+        w.u16(4); // Version.
+        let header_length = w.len();
+        w.u32(0);
+        w.u8(1); // Minimum instruction length.
+        w.u8(1); // Maximum operations per instruction.
+        w.u8(1); // Default `is_stmt`.
+        w.u8(-5i8 as u8); // Line base.
+        w.u8(14); // Line range.
+        w.u8(STANDARD_OPCODE_LENGTHS.len() as u8 + 1); // Opcode base.
+        for &length in STANDARD_OPCODE_LENGTHS.iter() {
+            w.u8(length);
+        }
+        // No include directories, and the source file in the compilation directory.
+        w.u8(0);
+        w.string(&self.file_name);
+        w.uleb128(0);
+        w.uleb128(0);
+        w.uleb128(0);
+        w.u8(0);
+        w.patch_length(header_length);
+
+        // Each function is a separate sequence, using only standard opcodes.
+        for func in &self.functions {
+            w.u8(0);
+            w.uleb128(1 + u64::from(self.address_size));
+            w.u8(DW_LNE_SET_ADDRESS);
+            w.address(self.address_size, &func.name, 0);
+
+            let mut address = 0;
+            let mut line = 1;
+            for &(offset, new_line) in &func.lines {
+                if offset > address {
+                    w.u8(DW_LNS_ADVANCE_PC);
+                    w.uleb128((offset - address).into());
+                    address = offset;
+                }
+                if new_line != line {
+                    w.u8(DW_LNS_ADVANCE_LINE);
+                    w.sleb128(i64::from(new_line) - i64::from(line));
+                    line = new_line;
+                }
+                w.u8(DW_LNS_COPY);
+            }
+
+            if func.code_size > address {
+                w.u8(DW_LNS_ADVANCE_PC);
+                w.uleb128((func.code_size - address).into());
+            }
+            w.u8(0);
+            w.uleb128(1);
+            w.u8(DW_LNE_END_SEQUENCE);
+        }
+
+        w.patch_length(0);
+        w
+    }
+
+    fn emit_ranges(&self) -> SectionWriter {
+        let mut w = SectionWriter::new(DwarfSectionId::DebugRanges);
+        for func in &self.functions {
+            w.address(self.address_size, &func.name, 0);
+            w.address(self.address_size, &func.name, func.code_size);
+        }
+        w.sized(self.address_size, 0);
+        w.sized(self.address_size, 0);
+        w
+    }
+
+    /// Emit the location lists, and return the offset of the list of each variable.
+    fn emit_loc(&self) -> (SectionWriter, Vec<Vec<u32>>) {
+        let mut w = SectionWriter::new(DwarfSectionId::DebugLoc);
+        let mut offsets = Vec::new();
+        for func in &self.functions {
+            let mut func_offsets = Vec::new();
+            for var in &func.variables {
+                func_offsets.push(w.len());
+                for &(start, end, ref expr) in &var.locations {
+                    w.address(self.address_size, &func.name, start);
+                    w.address(self.address_size, &func.name, end);
+                    w.u16(expr.len() as u16);
+                    w.data.extend_from_slice(expr);
+                }
+                w.sized(self.address_size, 0);
+                w.sized(self.address_size, 0);
+            }
+            offsets.push(func_offsets);
+        }
+        (w, offsets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::types;
+
+    fn debug_info() -> DebugInfo {
+        DebugInfo {
+            file_name: "test.c".to_string(),
+            comp_dir: "/tmp".to_string(),
+            address_size: 8,
+            functions: vec![FunctionDebugInfo {
+                name: "f".to_string(),
+                code_size: 0x100,
+                lines: vec![(0, 3), (4, 5), (0x90, 4)],
+                variables: vec![
+                    Variable {
+                        name: "x".to_string(),
+                        ty: Some(types::I32),
+                        locations: vec![(4, 8, vec![DW_OP_REG0]), (8, 12, vec![DW_OP_FBREG, 0x70])],
+                    },
+                    Variable {
+                        name: "v".to_string(),
+                        ty: Some(types::I32X4),
+                        locations: vec![(0, 4, vec![DW_OP_REG0 + 17])],
+                    },
+                ],
+            }],
+        }
+    }
+
+    fn section(sections: &[DwarfSection], id: DwarfSectionId) -> &DwarfSection {
+        sections.iter().find(|s| s.id == id).unwrap()
+    }
+
+    #[test]
+    fn leb128() {
+        let mut buf = Vec::new();
+        put_uleb128(&mut buf, 624_485);
+        put_sleb128(&mut buf, -123_456);
+        put_sleb128(&mut buf, 63);
+        put_sleb128(&mut buf, 64);
+        assert_eq!(buf, [0xe5, 0x8e, 0x26, 0xc0, 0xbb, 0x78, 0x3f, 0xc0, 0x00]);
+    }
+
+    #[test]
+    fn line_program() {
+        let sections = debug_info().emit();
+        let line = section(&sections, DwarfSectionId::DebugLine);
+        let program_start = 4 + 2 + 4 + 18 + 1 + "test.c".len() + 1 + 3 + 1;
+        assert_eq!(
+            &line.data[program_start..],
+            &[
+                // DW_LNE_set_address f
+                0,
+                9,
+                DW_LNE_SET_ADDRESS,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                // Line 3 at 0.
+                DW_LNS_ADVANCE_LINE,
+                2,
+                DW_LNS_COPY,
+                // Line 5 at 4.
+                DW_LNS_ADVANCE_PC,
+                4,
+                DW_LNS_ADVANCE_LINE,
+                2,
+                DW_LNS_COPY,
+                // Line 4 at 0x90.
+                DW_LNS_ADVANCE_PC,
+                0x8c,
+                0x01,
+                DW_LNS_ADVANCE_LINE,
+                0x7f,
+                DW_LNS_COPY,
+                // End of the sequence at 0x100.
+                DW_LNS_ADVANCE_PC,
+                0x70,
+                0,
+                1,
+                DW_LNE_END_SEQUENCE,
+            ][..]
+        );
+        assert_eq!(
+            line.relocs,
+            [DwarfReloc {
+                offset: program_start as u32 + 3,
+                size: 8,
+                target: DwarfRelocTarget::Symbol("f".to_string()),
+                addend: 0,
+            }]
+        );
+    }
+
+    #[test]
+    fn location_lists() {
+        let sections = debug_info().emit();
+        let loc = section(&sections, DwarfSectionId::DebugLoc);
+        assert_eq!(loc.data.len(), 90);
+        let addends: Vec<_> = loc.relocs.iter().map(|r| (r.offset, r.addend)).collect();
+        assert_eq!(
+            addends,
+            [(0, 4), (8, 8), (19, 8), (27, 12), (55, 0), (63, 4)]
+        );
+        assert_eq!(&loc.data[8..16], &[8, 0, 0, 0, 0, 0, 0, 0]);
+
+        let info = section(&sections, DwarfSectionId::DebugInfo);
+        let loc_relocs: Vec<_> = info
+            .relocs
+            .iter()
+            .filter(|r| r.target == DwarfRelocTarget::Section(DwarfSectionId::DebugLoc))
+            .map(|r| r.addend)
+            .collect();
+        assert_eq!(loc_relocs, [0, 55]);
+        let length = u32::from_le_bytes([info.data[0], info.data[1], info.data[2], info.data[3]]);
+        assert_eq!(length as usize, info.data.len() - 4);
+    }
+}
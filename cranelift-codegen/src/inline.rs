@@ -0,0 +1,406 @@
+//! A function inlining pass.
+//!
+//! Inlining replaces a `call` instruction with a copy of the body of the called function. The
+//! callee's EBBs, values, stack slots, global values, heaps, tables, signatures, external
+//! functions, jump tables and constants are remapped into the caller, and its `return`
+//! instructions become jumps to a new EBB following the call, whose parameters replace the call
+//! results.
+//!
+//! Callees are found through a lookup function supplied by the embedder, so the pass doesn't
+//! depend on how the definitions of functions are stored. It must run before legalization.
+
+use crate::cursor::{Cursor, FuncCursor};
+use crate::entity::{EntityList, SecondaryMap};
+use crate::ir::{
+    self, ArgumentPurpose, Constant, Ebb, ExternalName, FuncRef, Function, GlobalValue,
+    GlobalValueData, Heap, HeapStyle, Inst, InstBuilder, InstructionData, JumpTable, JumpTableData,
+    Opcode, SigRef, StackSlot, StackSlotKind, Table, Value,
+};
+use crate::packed_option::PackedOption;
+use crate::timing;
+use std::vec::Vec;
+
+/// Inline the functions called by `func` wherever possible.
+///
+/// The function called by each `call` instruction is found by calling `lookup` with the name of
+/// its external function. Calls for which `lookup` returns `None`, or whose callee can't be
+/// inlined, are left alone. Calls appearing in the inlined bodies aren't inlined themselves, so
+/// recursive functions are only expanded once per invocation.
+pub fn do_inlining<'f, F>(func: &mut Function, mut lookup: F)
+where
+    F: FnMut(&ExternalName) -> Option<&'f Function>,
+{
+    let _tt = timing::inline();
+
+    // Inlining inserts unencoded instructions, and would invalidate the ABI legalization.
+    if !func.encodings.is_empty() {
+        return;
+    }
+
+    let mut calls = Vec::new();
+    let mut pos = FuncCursor::new(func);
+    while let Some(_ebb) = pos.next_ebb() {
+        while let Some(inst) = pos.next_inst() {
+            if let InstructionData::Call { func_ref, .. } = pos.func.dfg[inst] {
+                calls.push((inst, func_ref));
+            }
+        }
+    }
+
+    for (call, func_ref) in calls {
+        let callee = match lookup(&func.dfg.ext_funcs[func_ref].name) {
+            Some(callee) => callee,
+            None => continue,
+        };
+        if can_inline(func, call, func_ref, callee) {
+            inline_call(func, call, callee);
+        }
+    }
+}
+
+/// Check if the call `call` to `func_ref` in `func` can be replaced by the body of `callee`.
+fn can_inline(func: &Function, call: Inst, func_ref: FuncRef, callee: &Function) -> bool {
+    if !callee.encodings.is_empty() || callee.layout.entry_block().is_none() {
+        return false;
+    }
+
+    // The call must agree with the callee's signature, and the callee's special parameters must
+    // make sense in the caller.
+    let sig = &func.dfg.signatures[func.dfg.ext_funcs[func_ref].signature];
+    let same_types = |a: &[ir::AbiParam], b: &[ir::AbiParam]| {
+        a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.value_type == b.value_type)
+    };
+    if !same_types(&sig.params, &callee.signature.params)
+        || !same_types(&sig.returns, &callee.signature.returns)
+    {
+        return false;
+    }
+    if callee
+        .signature
+        .params
+        .iter()
+        .any(|p| p.purpose != ArgumentPurpose::Normal && p.purpose != ArgumentPurpose::VMContext)
+        || callee
+            .signature
+            .returns
+            .iter()
+            .any(|r| r.purpose != ArgumentPurpose::Normal)
+    {
+        return false;
+    }
+
+    // Other kinds of stack slots are only created by the ABI legalization.
+    if callee
+        .stack_slots
+        .values()
+        .any(|ss| ss.kind != StackSlotKind::ExplicitSlot)
+    {
+        return false;
+    }
+
+    // A `vmctx` global value refers to the caller's VM context once inlined, so it must be the
+    // one passed to the callee.
+    let uses_vmctx = callee.global_values.values().any(|gv| match *gv {
+        GlobalValueData::VMContext => true,
+        _ => false,
+    });
+    if uses_vmctx {
+        let vmctx_arg = callee
+            .signature
+            .special_param_index(ArgumentPurpose::VMContext)
+            .map(|i| func.dfg.inst_args(call)[i]);
+        let caller_vmctx = func.special_param(ArgumentPurpose::VMContext);
+        match (vmctx_arg, caller_vmctx) {
+            (Some(arg), Some(vmctx)) if func.dfg.resolve_aliases(arg) == vmctx => {}
+            _ => return false,
+        }
+    }
+
+    true
+}
+
+/// The entities of the callee and the caller entities they were copied to.
+struct EntityMaps {
+    ebbs: SecondaryMap<Ebb, PackedOption<Ebb>>,
+    values: SecondaryMap<Value, PackedOption<Value>>,
+    stack_slots: SecondaryMap<StackSlot, PackedOption<StackSlot>>,
+    global_values: SecondaryMap<GlobalValue, PackedOption<GlobalValue>>,
+    heaps: SecondaryMap<Heap, PackedOption<Heap>>,
+    tables: SecondaryMap<Table, PackedOption<Table>>,
+    sig_refs: SecondaryMap<SigRef, PackedOption<SigRef>>,
+    func_refs: SecondaryMap<FuncRef, PackedOption<FuncRef>>,
+    jump_tables: SecondaryMap<JumpTable, PackedOption<JumpTable>>,
+    constants: SecondaryMap<Constant, PackedOption<Constant>>,
+}
+
+impl EntityMaps {
+    fn new() -> Self {
+        Self {
+            ebbs: SecondaryMap::new(),
+            values: SecondaryMap::new(),
+            stack_slots: SecondaryMap::new(),
+            global_values: SecondaryMap::new(),
+            heaps: SecondaryMap::new(),
+            tables: SecondaryMap::new(),
+            sig_refs: SecondaryMap::new(),
+            func_refs: SecondaryMap::new(),
+            jump_tables: SecondaryMap::new(),
+            constants: SecondaryMap::new(),
+        }
+    }
+
+    fn value(&self, callee: &Function, value: Value) -> Value {
+        self.values[callee.dfg.resolve_aliases(value)]
+            .expect("callee value used before it is defined")
+    }
+}
+
+/// Replace the call `call` in `func` with the body of `callee`.
+fn inline_call(func: &mut Function, call: Inst, callee: &Function) {
+    let mut maps = EntityMaps::new();
+    copy_entities(func, callee, &mut maps);
+
+    // Split the calling EBB after the call. The call results become the parameters of the new
+    // EBB, which the inlined returns jump to.
+    let cont = func.dfg.make_ebb();
+    let next = func
+        .layout
+        .next_inst(call)
+        .expect("a call is never the last instruction of an EBB");
+    func.layout.split_ebb(cont, next);
+    let results = func.dfg.detach_results(call);
+    let results: Vec<Value> = results.as_slice(&func.dfg.value_lists).to_vec();
+    for result in results {
+        func.dfg.attach_ebb_param(cont, result);
+    }
+
+    // Create the EBBs first, so that branches can be remapped as they are copied.
+    for ebb in callee.layout.ebbs() {
+        let new_ebb = func.dfg.make_ebb();
+        func.layout.insert_ebb(new_ebb, cont);
+        for &param in callee.dfg.ebb_params(ebb) {
+            let ty = callee.dfg.value_type(param);
+            maps.values[param] = func.dfg.append_ebb_param(new_ebb, ty).into();
+        }
+        maps.ebbs[ebb] = new_ebb.into();
+    }
+
+    for (jt, data) in callee.jump_tables.iter() {
+        let mut new_data = JumpTableData::with_capacity(data.len());
+        for &ebb in data.iter() {
+            new_data.push_entry(maps.ebbs[ebb].unwrap());
+        }
+        maps.jump_tables[jt] = func.create_jump_table(new_data).into();
+    }
+
+    // Copy the instructions. Their arguments are remapped afterwards, since a value may be used
+    // in the layout before the instruction defining it.
+    let srcloc = func.srclocs[call];
+    let mut copied = Vec::new();
+    for ebb in callee.layout.ebbs() {
+        let new_ebb = maps.ebbs[ebb].unwrap();
+        for inst in callee.layout.ebb_insts(ebb) {
+            let data = copy_inst_data(func, callee, inst, cont, &mut maps);
+            let new_inst = func.dfg.make_inst(data);
+            func.layout.append_inst(new_inst, new_ebb);
+            func.dfg
+                .make_inst_results(new_inst, callee.dfg.ctrl_typevar(inst));
+            for (&old, &new) in callee
+                .dfg
+                .inst_results(inst)
+                .iter()
+                .zip(func.dfg.inst_results(new_inst))
+            {
+                maps.values[old] = new.into();
+            }
+            if !srcloc.is_default() {
+                func.srclocs[new_inst] = srcloc;
+            }
+            copied.push(new_inst);
+        }
+    }
+    for inst in copied {
+        for arg in func.dfg.inst_args_mut(inst) {
+            *arg = maps.value(callee, *arg);
+        }
+    }
+
+    // Finally, replace the call with a jump to the inlined entry block.
+    let entry = maps.ebbs[callee.layout.entry_block().unwrap()].unwrap();
+    let args: Vec<Value> = func.dfg.inst_args(call).to_vec();
+    func.dfg.replace(call).jump(entry, &args);
+}
+
+/// Copy the entities referenced by the instructions of `callee` into `func`, except EBBs, jump
+/// tables and constants.
+fn copy_entities(func: &mut Function, callee: &Function, maps: &mut EntityMaps) {
+    for (ss, data) in callee.stack_slots.iter() {
+        maps.stack_slots[ss] = func.stack_slots.push(data.clone()).into();
+    }
+
+    // Global values can refer to each other, so their bases are remapped once they all exist.
+    let first_gv = func.global_values.len();
+    for (gv, data) in callee.global_values.iter() {
+        maps.global_values[gv] = func.global_values.push(data.clone()).into();
+    }
+    for data in func.global_values.values_mut().skip(first_gv) {
+        match *data {
+            GlobalValueData::Load { ref mut base, .. }
+            | GlobalValueData::IAddImm { ref mut base, .. } => {
+                *base = maps.global_values[*base].unwrap();
+            }
+            GlobalValueData::VMContext | GlobalValueData::Symbol { .. } => {}
+        }
+    }
+
+    for (heap, data) in callee.heaps.iter() {
+        let mut data = data.clone();
+        data.base = maps.global_values[data.base].unwrap();
+        if let HeapStyle::Dynamic { ref mut bound_gv } = data.style {
+            *bound_gv = maps.global_values[*bound_gv].unwrap();
+        }
+        maps.heaps[heap] = func.heaps.push(data).into();
+    }
+
+    for (table, data) in callee.tables.iter() {
+        let mut data = data.clone();
+        data.base_gv = maps.global_values[data.base_gv].unwrap();
+        data.bound_gv = maps.global_values[data.bound_gv].unwrap();
+        maps.tables[table] = func.tables.push(data).into();
+    }
+
+    for (sig, data) in callee.dfg.signatures.iter() {
+        maps.sig_refs[sig] = func.import_signature(data.clone()).into();
+    }
+
+    for (fref, data) in callee.dfg.ext_funcs.iter() {
+        let mut data = data.clone();
+        data.signature = maps.sig_refs[data.signature].unwrap();
+        maps.func_refs[fref] = func.import_function(data).into();
+    }
+}
+
+/// Copy the instruction data of `inst` in `callee`, remapping everything but its value
+/// arguments. Returns are turned into jumps to `cont`.
+fn copy_inst_data(
+    func: &mut Function,
+    callee: &Function,
+    inst: Inst,
+    cont: Ebb,
+    maps: &mut EntityMaps,
+) -> InstructionData {
+    let mut data = callee.dfg[inst].clone();
+
+    if let Some(args) = data.take_value_list() {
+        let args = EntityList::from_slice(
+            args.as_slice(&callee.dfg.value_lists),
+            &mut func.dfg.value_lists,
+        );
+        data.put_value_list(args);
+    }
+
+    match data {
+        InstructionData::Jump {
+            ref mut destination,
+            ..
+        }
+        | InstructionData::Branch {
+            ref mut destination,
+            ..
+        }
+        | InstructionData::BranchInt {
+            ref mut destination,
+            ..
+        }
+        | InstructionData::BranchFloat {
+            ref mut destination,
+            ..
+        }
+        | InstructionData::BranchIcmp {
+            ref mut destination,
+            ..
+        } => {
+            *destination = maps.ebbs[*destination].unwrap();
+        }
+        InstructionData::BranchTable {
+            ref mut destination,
+            ref mut table,
+            ..
+        } => {
+            *destination = maps.ebbs[*destination].unwrap();
+            *table = maps.jump_tables[*table].unwrap();
+        }
+        InstructionData::BranchTableEntry { ref mut table, .. }
+        | InstructionData::BranchTableBase { ref mut table, .. }
+        | InstructionData::IndirectJump { ref mut table, .. } => {
+            *table = maps.jump_tables[*table].unwrap();
+        }
+        InstructionData::Call {
+            ref mut func_ref, ..
+        }
+        | InstructionData::FuncAddr {
+            ref mut func_ref, ..
+        } => {
+            *func_ref = maps.func_refs[*func_ref].unwrap();
+        }
+        InstructionData::CallIndirect {
+            ref mut sig_ref, ..
+        } => {
+            *sig_ref = maps.sig_refs[*sig_ref].unwrap();
+        }
+        InstructionData::UnaryGlobalValue {
+            ref mut global_value,
+            ..
+        } => {
+            *global_value = maps.global_values[*global_value].unwrap();
+        }
+        InstructionData::StackLoad {
+            ref mut stack_slot, ..
+        }
+        | InstructionData::StackStore {
+            ref mut stack_slot, ..
+        } => {
+            *stack_slot = maps.stack_slots[*stack_slot].unwrap();
+        }
+        InstructionData::HeapAddr { ref mut heap, .. } => {
+            *heap = maps.heaps[*heap].unwrap();
+        }
+        InstructionData::TableAddr { ref mut table, .. } => {
+            *table = maps.tables[*table].unwrap();
+        }
+        InstructionData::UnaryImm128 { ref mut imm, .. }
+        | InstructionData::Shuffle {
+            mask: ref mut imm, ..
+        } => {
+            *imm = copy_constant(func, callee, *imm, maps);
+        }
+        InstructionData::MultiAry { opcode, args }
+            if opcode == Opcode::Return || opcode == Opcode::FallthroughReturn =>
+        {
+            return InstructionData::Jump {
+                opcode: Opcode::Jump,
+                args,
+                destination: cont,
+            };
+        }
+        _ => {}
+    }
+
+    data
+}
+
+/// Copy the constant `constant` of `callee` into the constant pool of `func`.
+fn copy_constant(
+    func: &mut Function,
+    callee: &Function,
+    constant: Constant,
+    maps: &mut EntityMaps,
+) -> Constant {
+    if let Some(new) = maps.constants[constant].expand() {
+        return new;
+    }
+    let data = callee.dfg.constants.get(constant).clone();
+    let new = func.dfg.constants.insert(data);
+    maps.constants[constant] = new.into();
+    new
+}
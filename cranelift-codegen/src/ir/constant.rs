@@ -133,6 +133,16 @@ impl ConstantPool {
         self.handles_to_values.iter().map(|(h, e)| (h, &e.data))
     }
 
+    /// Iterate over the constants in insertion order, along with their offsets if they have been
+    /// assigned.
+    pub fn iter_with_offsets(
+        &self,
+    ) -> impl Iterator<Item = (&Constant, &ConstantData, Option<ConstantOffset>)> {
+        self.handles_to_values
+            .iter()
+            .map(|(h, e)| (h, &e.data, e.offset))
+    }
+
     /// Iterate over mutable entries in the constant pool in insertion order.
     pub fn entries_mut(&mut self) -> impl Iterator<Item = &mut ConstantPoolEntry> {
         self.handles_to_values.values_mut()
@@ -19,6 +19,7 @@ use core::mem;
 use core::ops::{Index, IndexMut};
 use core::u16;
 use std::collections::HashMap;
+use std::vec::Vec;
 
 /// A data flow graph defines all instructions and extended basic blocks in a function as well as
 /// the data flow dependencies between them. The DFG also tracks values which can be either
@@ -430,6 +431,22 @@ impl DataFlowGraph {
         self.insts.push(data)
     }
 
+    /// Create a copy of `inst` with new result values.
+    ///
+    /// The copy has the same arguments as `inst`, in a value list of its own if `inst` has one.
+    /// It isn't inserted in the layout.
+    pub fn clone_inst(&mut self, inst: Inst) -> Inst {
+        let mut data = self.insts[inst].clone();
+        if let Some(args) = data.take_value_list() {
+            let args: Vec<Value> = args.as_slice(&self.value_lists).to_vec();
+            data.put_value_list(ValueList::from_slice(&args, &mut self.value_lists));
+        }
+        let ctrl_typevar = self.ctrl_typevar(inst);
+        let new_inst = self.make_inst(data);
+        self.make_inst_results(new_inst, ctrl_typevar);
+        new_inst
+    }
+
     /// Returns an object that displays `inst`.
     pub fn display_inst<'a, I: Into<Option<&'a dyn TargetIsa>>>(
         &'a self,
@@ -942,10 +959,10 @@ impl<'a> fmt::Display for DisplayInst<'a> {
 
 /// Parser routines. These routines should not be used outside the parser.
 impl DataFlowGraph {
-    /// Set the type of a value. This is only for use in the parser, which needs
-    /// to create invalid values for index padding which may be reassigned later.
+    /// Set the type of a value. This is only for use in the parser and the binary format decoder,
+    /// which need to create invalid values for index padding which may be reassigned later.
     #[cold]
-    fn set_value_type_for_parser(&mut self, v: Value, t: Type) {
+    pub(crate) fn set_value_type_for_parser(&mut self, v: Value, t: Type) {
         assert_eq!(
             self.value_type(v),
             types::INVALID,
@@ -1276,4 +1293,33 @@ mod tests {
         // This does not see through copies.
         assert_eq!(pos.func.dfg.resolve_aliases(c3), c3);
     }
+
+    #[test]
+    fn clone_inst() {
+        use crate::ir::InstBuilder;
+
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let mut pos = FuncCursor::new(&mut func);
+        pos.insert_ebb(ebb0);
+        let v0 = pos.func.dfg.append_ebb_param(ebb0, types::I32);
+        let v1 = pos.ins().iadd_imm(v0, 1);
+        let jump = pos.ins().jump(ebb1, &[v0, v1]);
+
+        let inst = pos.func.dfg.value_def(v1).unwrap_inst();
+        let copy = pos.func.dfg.clone_inst(inst);
+        assert_ne!(pos.func.dfg.first_result(copy), v1);
+        assert_eq!(pos.func.dfg.inst_args(copy), &[v0]);
+        assert_eq!(
+            pos.func.dfg.display_inst(copy, None).to_string(),
+            "v2 = iadd_imm.i32 v0, 1"
+        );
+
+        // The copy's value list is separate from the original's.
+        let jump_copy = pos.func.dfg.clone_inst(jump);
+        pos.func.dfg.inst_args_mut(jump_copy)[1] = v0;
+        assert_eq!(pos.func.dfg.inst_args(jump), &[v0, v1]);
+        assert_eq!(pos.func.dfg.inst_args(jump_copy), &[v0, v0]);
+    }
 }
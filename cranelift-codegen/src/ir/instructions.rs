@@ -16,6 +16,8 @@ use crate::ir::types;
 use crate::ir::{Ebb, FuncRef, JumpTable, SigRef, Type, Value};
 use crate::isa;
 
+#[cfg(feature = "binary-format")]
+use crate::binary_format;
 use crate::bitset::BitSet;
 use crate::entity;
 use crate::ref_slice::{ref_slice, ref_slice_mut};
@@ -32,6 +34,7 @@ pub type ValueListPool = entity::ListPool<Value>;
 //
 // - The `pub enum InstructionFormat` enum with all the instruction formats.
 // - The `pub enum InstructionData` enum with all the instruction data fields.
+// - The `InstructionData` methods for the binary format, with the `binary-format` feature.
 // - The `pub enum Opcode` definition with all known opcodes,
 // - The `const OPCODE_FORMAT: [InstructionFormat; N]` table.
 // - The private `fn opcode_name(Opcode) -> &'static str` function, and
@@ -62,6 +65,23 @@ impl Opcode {
     pub fn constraints(self) -> OpcodeConstraints {
         OPCODE_CONSTRAINTS[self as usize - 1]
     }
+
+    /// Is this an atomic memory access or a fence?
+    pub fn is_atomic(self) -> bool {
+        match self {
+            Opcode::AtomicLoad
+            | Opcode::AtomicStore
+            | Opcode::AtomicRmwAdd
+            | Opcode::AtomicRmwSub
+            | Opcode::AtomicRmwAnd
+            | Opcode::AtomicRmwOr
+            | Opcode::AtomicRmwXor
+            | Opcode::AtomicRmwXchg
+            | Opcode::AtomicCas
+            | Opcode::Fence => true,
+            _ => false,
+        }
+    }
 }
 
 // This trait really belongs in cranelift-reader where it is used by the `.clif` file parser, but since
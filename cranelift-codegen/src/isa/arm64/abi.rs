@@ -1,18 +1,117 @@
 //! ARM 64 ABI implementation.
+//!
+//! This module implements the AAPCS64 procedure call standard through the primary
+//! `legalize_signature()` entry point, and the matching prologue and epilogue.
 
-use super::registers::{FPR, GPR};
+use super::registers::{FPR, GPR, RU};
+use crate::abi::{legalize_args, ArgAction, ArgAssigner, ValueConversion};
+use crate::cursor::{Cursor, CursorPosition, EncCursor};
 use crate::ir;
-use crate::isa::RegClass;
+use crate::ir::immediates::Imm64;
+use crate::ir::stackslot::StackOffset;
+use crate::ir::{AbiParam, ArgumentExtension, ArgumentLoc, ArgumentPurpose, InstBuilder, ValueLoc};
+use crate::isa::{RegClass, RegUnit, TargetIsa};
 use crate::regalloc::RegisterSet;
+use crate::result::CodegenResult;
 use crate::settings as shared_settings;
+use crate::stack_layout::layout_stack;
+use core::i32;
+use std::vec::Vec;
+
+/// Number of registers used to pass arguments and return values, in both register files.
+const ARG_REG_LIMIT: usize = 8;
+
+/// The frame pointer.
+const FP: RegUnit = RU::x29 as RegUnit;
+
+/// The link register.
+const LR: RegUnit = RU::x30 as RegUnit;
+
+struct Args {
+    pointer_type: ir::Type,
+    gpr_used: usize,
+    fpr_used: usize,
+    offset: u32,
+}
+
+impl Args {
+    fn new() -> Self {
+        Self {
+            pointer_type: ir::types::I64,
+            gpr_used: 0,
+            fpr_used: 0,
+            offset: 0,
+        }
+    }
+}
+
+impl ArgAssigner for Args {
+    fn assign(&mut self, arg: &AbiParam) -> ArgAction {
+        fn align(value: u32, to: u32) -> u32 {
+            (value + to - 1) & !(to - 1)
+        }
+
+        let ty = arg.value_type;
+
+        // SIMD isn't supported yet, so break all vectors down.
+        if ty.is_vector() {
+            return ValueConversion::VectorSplit.into();
+        }
+
+        // Large integers and booleans are broken down to fit in a register. Quad-word values
+        // start in an even numbered register, or at a 16-byte aligned stack offset.
+        if !ty.is_float() && ty.bits() > 64 {
+            self.gpr_used = align(self.gpr_used as u32, 2) as usize;
+            self.offset = align(self.offset, 16);
+            return ValueConversion::IntSplit.into();
+        }
+
+        // Small integers are extended to the size of a pointer register.
+        if ty.is_int() && ty.bits() < 64 {
+            match arg.extension {
+                ArgumentExtension::None => {}
+                ArgumentExtension::Uext => return ValueConversion::Uext(self.pointer_type).into(),
+                ArgumentExtension::Sext => return ValueConversion::Sext(self.pointer_type).into(),
+            }
+        }
+
+        // The address of a returned structure is passed in the indirect result location
+        // register.
+        if arg.purpose == ArgumentPurpose::StructReturn {
+            return ArgumentLoc::Reg(RU::x8 as RegUnit).into();
+        }
+
+        if ty.is_float() {
+            if self.fpr_used < ARG_REG_LIMIT {
+                let reg = FPR.unit(self.fpr_used);
+                self.fpr_used += 1;
+                return ArgumentLoc::Reg(reg).into();
+            }
+        } else if self.gpr_used < ARG_REG_LIMIT {
+            let reg = GPR.unit(self.gpr_used);
+            self.gpr_used += 1;
+            return ArgumentLoc::Reg(reg).into();
+        }
+
+        // Assign a stack location. Each argument takes an 8-byte slot.
+        let loc = ArgumentLoc::Stack(self.offset as i32);
+        self.offset += 8;
+        debug_assert!(self.offset <= i32::MAX as u32);
+        loc.into()
+    }
+}
 
 /// Legalize `sig`.
 pub fn legalize_signature(
-    _sig: &mut ir::Signature,
+    sig: &mut ir::Signature,
     _flags: &shared_settings::Flags,
     _current: bool,
 ) {
-    unimplemented!()
+    let mut args = Args::new();
+    legalize_args(&mut sig.params, &mut args);
+
+    let mut rets = Args::new();
+    legalize_args(&mut sig.returns, &mut rets);
 }
 
 /// Get register class for a type appearing in a legalized signature.
@@ -26,5 +125,233 @@ pub fn regclass_for_abi_type(ty: ir::Type) -> RegClass {
 
 /// Get the set of allocatable registers for `func`.
 pub fn allocatable_registers(_func: &ir::Function) -> RegisterSet {
-    unimplemented!()
+    let mut regs = RegisterSet::new();
+    regs.take(GPR, RU::x16 as RegUnit); // Scratch register used by the recipes.
+    regs.take(GPR, RU::x17 as RegUnit); // Scratch register used by the recipes.
+    regs.take(GPR, RU::x18 as RegUnit); // Platform register.
+    regs.take(GPR, FP);
+    regs.take(GPR, LR);
+    regs.take(GPR, RU::x31 as RegUnit); // Stack pointer or zero register.
+    regs
+}
+
+/// Get the set of callee-saved registers that are used.
+///
+/// Only the low 64 bits of `v8`-`v15` are callee-saved.
+fn callee_saved_regs_used(func: &ir::Function) -> RegisterSet {
+    let mut all_callee_saved = RegisterSet::empty();
+    for reg in 19..29 {
+        all_callee_saved.free(GPR, GPR.unit(reg));
+    }
+    for reg in 8..16 {
+        all_callee_saved.free(FPR, FPR.unit(reg));
+    }
+
+    let mut used = RegisterSet::empty();
+    let mut mark_used = |ru: RegUnit| {
+        for &rc in &[GPR, FPR] {
+            if rc.contains(ru) && !used.is_avail(rc, ru) {
+                used.free(rc, ru);
+            }
+        }
+    };
+
+    for value_loc in func.locations.values() {
+        if let ValueLoc::Reg(ru) = *value_loc {
+            mark_used(ru);
+        }
+    }
+
+    // Diversions aren't recorded in `func.locations`, and may use x19-x28 or v8-v15.
+    for ebb in &func.layout {
+        for inst in func.layout.ebb_insts(ebb) {
+            match func.dfg[inst] {
+                ir::InstructionData::RegMove { dst, .. }
+                | ir::InstructionData::RegFill { dst, .. } => mark_used(dst),
+                _ => (),
+            }
+        }
+    }
+
+    used.intersect(&all_callee_saved);
+    used
+}
+
+/// Insert the prologue and epilogues.
+///
+/// The frame record (frame pointer and link register) is pushed first and the frame pointer is
+/// set up to point to it, followed by pairs of the used callee-saved registers. The stack pointer
+/// is kept 16-byte aligned throughout.
+pub fn prologue_epilogue(func: &mut ir::Function, isa: &dyn TargetIsa) -> CodegenResult<()> {
+    let csrs = callee_saved_regs_used(func);
+    let gprs: Vec<RegUnit> = csrs.iter(GPR).collect();
+    let fprs: Vec<RegUnit> = csrs.iter(FPR).collect();
+
+    // The reserved stack area is composed of the frame record and the callee-saved registers,
+    // each group padded to a multiple of 16 bytes.
+    let csr_stack_size =
+        (16 + 16 * ((gprs.len() + 1) / 2) + 16 * ((fprs.len() + 1) / 2)) as StackOffset;
+    func.create_stack_slot(ir::StackSlotData {
+        kind: ir::StackSlotKind::IncomingArg,
+        size: csr_stack_size as u32,
+        offset: Some(-csr_stack_size),
+    });
+
+    let total_stack_size = layout_stack(&mut func.stack_slots, 16)? as i32;
+    let local_stack_size = i64::from(total_stack_size - csr_stack_size);
+
+    // Add the frame record and CSRs to the function signature.
+    let fp_arg = AbiParam::special_reg(ir::types::I64, ArgumentPurpose::FramePointer, FP);
+    func.signature.params.push(fp_arg);
+    func.signature.returns.push(fp_arg);
+
+    let lr_arg = AbiParam::special_reg(ir::types::I64, ArgumentPurpose::Link, LR);
+    func.signature.params.push(lr_arg);
+    func.signature.returns.push(lr_arg);
+
+    for &(regs, ty) in &[(&gprs, ir::types::I64), (&fprs, ir::types::F64)] {
+        for &reg in regs.iter() {
+            let csr_arg = AbiParam::special_reg(ty, ArgumentPurpose::CalleeSaved, reg);
+            func.signature.params.push(csr_arg);
+            func.signature.returns.push(csr_arg);
+        }
+    }
+
+    // Set up the cursor and insert the prologue.
+    let entry_ebb = func.layout.entry_block().expect("missing entry block");
+    let mut pos = EncCursor::new(func, isa).at_first_insertion_point(entry_ebb);
+    insert_prologue(&mut pos, local_stack_size, csr_stack_size, &gprs, &fprs);
+
+    // Reset the cursor and insert the epilogues.
+    let mut pos = pos.at_position(CursorPosition::Nowhere);
+    while let Some(ebb) = pos.next_ebb() {
+        pos.goto_last_inst(ebb);
+        if let Some(inst) = pos.current_inst() {
+            if pos.func.dfg[inst].opcode().is_return() {
+                insert_epilogue(inst, local_stack_size, &mut pos, &gprs, &fprs);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Insert the prologue at the cursor position in the entry EBB.
+fn insert_prologue(
+    pos: &mut EncCursor,
+    stack_size: i64,
+    csr_stack_size: StackOffset,
+    gprs: &[RegUnit],
+    fprs: &[RegUnit],
+) {
+    if stack_size > 0 {
+        // Check if there is a special stack limit parameter. If so insert stack check.
+        if let Some(stack_limit_arg) = pos.func.special_param(ArgumentPurpose::StackLimit) {
+            // The total stack size includes the frame record and the callee-saved registers.
+            let total_stack_size = stack_size + i64::from(csr_stack_size);
+            insert_stack_check(pos, total_stack_size, stack_limit_arg);
+        }
+    }
+
+    let ebb = pos.current_ebb().expect("missing ebb under cursor");
+    let ebb_param = |pos: &mut EncCursor, ty, reg| {
+        let v = pos.func.dfg.append_ebb_param(ebb, ty);
+        pos.func.locations[v] = ValueLoc::Reg(reg);
+        v
+    };
+
+    // Push the frame record and point the frame pointer to it.
+    let fp = ebb_param(pos, ir::types::I64, FP);
+    let lr = ebb_param(pos, ir::types::I64, LR);
+    pos.ins().arm64_push2(fp, lr);
+    pos.ins().copy_special(RU::x31 as RegUnit, FP);
+
+    for &(regs, ty) in &[(gprs, ir::types::I64), (fprs, ir::types::F64)] {
+        for pair in regs.chunks(2) {
+            let first = ebb_param(pos, ty, pair[0]);
+            if pair.len() == 2 {
+                let second = ebb_param(pos, ty, pair[1]);
+                pos.ins().arm64_push2(first, second);
+            } else {
+                pos.ins().arm64_push(first);
+            }
+        }
+    }
+
+    // Allocate stack frame storage.
+    if stack_size > 0 {
+        pos.ins().adjust_sp_down_imm(Imm64::new(stack_size));
+    }
+}
+
+/// Insert a check that generates a trap if the stack pointer goes below a value in
+/// `stack_limit_arg`.
+///
+/// This happens before the frame record is pushed, so the scratch registers are free to use.
+fn insert_stack_check(pos: &mut EncCursor, stack_size: i64, stack_limit_arg: ir::Value) {
+    use crate::ir::condcodes::IntCC;
+
+    let scratch = RU::x16 as RegUnit;
+    let stack_limit_copy = pos.ins().copy(stack_limit_arg);
+    pos.func.locations[stack_limit_copy] = ValueLoc::Reg(scratch);
+    let sp_threshold = if stack_size < 1 << 12 {
+        pos.ins().iadd_imm(stack_limit_copy, stack_size)
+    } else {
+        let size = pos.ins().iconst(ir::types::I64, stack_size);
+        pos.func.locations[size] = ValueLoc::Reg(RU::x17 as RegUnit);
+        pos.ins().iadd(stack_limit_copy, size)
+    };
+    pos.func.locations[sp_threshold] = ValueLoc::Reg(scratch);
+
+    // If the stack pointer currently reaches the SP threshold or below it then after opening
+    // the current stack frame, the current stack pointer will reach the limit.
+    let cflags = pos.ins().ifcmp_sp(sp_threshold);
+    pos.func.locations[cflags] = ValueLoc::Reg(RU::nzcv as RegUnit);
+    pos.ins().trapif(
+        IntCC::UnsignedGreaterThanOrEqual,
+        cflags,
+        ir::TrapCode::StackOverflow,
+    );
+}
+
+/// Insert an epilogue before the `return` instruction `inst`.
+fn insert_epilogue(
+    inst: ir::Inst,
+    stack_size: i64,
+    pos: &mut EncCursor,
+    gprs: &[RegUnit],
+    fprs: &[RegUnit],
+) {
+    if stack_size > 0 {
+        pos.ins().adjust_sp_up_imm(Imm64::new(stack_size));
+    }
+
+    // Pop the callee-saved registers in the reverse order they were pushed.
+    let mut csr_rets = Vec::new();
+    for &(regs, ty) in &[(fprs, ir::types::F64), (gprs, ir::types::I64)] {
+        for pair in regs.chunks(2).rev() {
+            if pair.len() == 2 {
+                let (first, second) = pos.ins().arm64_pop2(ty);
+                csr_rets.push((pair[0], first));
+                csr_rets.push((pair[1], second));
+            } else {
+                let first = pos.ins().arm64_pop(ty);
+                csr_rets.push((pair[0], first));
+            }
+        }
+    }
+
+    let (fp, lr) = pos.ins().arm64_pop2(ir::types::I64);
+    pos.func.locations[fp] = ValueLoc::Reg(FP);
+    pos.func.locations[lr] = ValueLoc::Reg(LR);
+
+    // The order of the return arguments matches the order of the special return values in the
+    // signature.
+    pos.func.dfg.append_inst_arg(inst, fp);
+    pos.func.dfg.append_inst_arg(inst, lr);
+    for &reg in gprs.iter().chain(fprs) {
+        let &(_, csr_ret) = csr_rets.iter().find(|&&(r, _)| r == reg).unwrap();
+        pos.func.locations[csr_ret] = ValueLoc::Reg(reg);
+        pos.func.dfg.append_inst_arg(inst, csr_ret);
+    }
 }
@@ -1,8 +1,644 @@
 //! Emitting binary ARM64 machine code.
 
-use crate::binemit::{bad_encoding, CodeSink};
-use crate::ir::{Function, Inst};
-use crate::isa::TargetIsa;
+use crate::binemit::{bad_encoding, CodeSink, Reloc};
+use crate::ir::condcodes::{CondCode, FloatCC, IntCC};
+use crate::ir::{Function, Inst, InstructionData, TrapCode};
+use crate::isa::{RegUnit, StackBaseMask, StackRef, TargetIsa};
+use crate::predicates::is_unsigned_int;
 use crate::regalloc::RegDiversions;
 
 include!(concat!(env!("OUT_DIR"), "/binemit-arm64.rs"));
+
+/// Register number 31 is the zero register in most instructions...
+const ZERO_REG: RegUnit = 31;
+
+/// ... and the stack pointer when used as a base address or in the add/subtract immediate
+/// instructions.
+const STACK_REG: RegUnit = 31;
+
+/// The link register holding the return address.
+const LINK_REG: RegUnit = 30;
+
+/// The intra-procedure-call scratch registers. They are reserved from register allocation, so
+/// recipes needing a temporary can use them.
+const SCRATCH_REG: RegUnit = 16;
+const SCRATCH_REG2: RegUnit = 17;
+
+/// Condition codes, as encoded in the `cond` fields.
+const COND_NE: u32 = 0b0001;
+const COND_VC: u32 = 0b0111;
+
+/// Bit 29 of a move wide instruction turns a `movz` into a `movk`, and is bit 6 of the encbits.
+const MOVK_BIT: u16 = 1 << 6;
+
+/// The `option:S` field of the register offset loads and stores: sign-extend a 32-bit offset.
+const EXTEND_SXTW: u32 = 0b1100;
+
+/// The `option:S` field of the register offset loads and stores: 64-bit offset scaled by the
+/// access size.
+const EXTEND_LSL_SCALED: u32 = 0b0111;
+
+/// Mask a register unit to the 5-bit register number used in the instruction fields.
+///
+/// The floating point registers are numbered from 32 in the register units.
+fn rn(reg: RegUnit) -> u32 {
+    u32::from(reg) & 0x1f
+}
+
+/// Convert an integer condition code to the `cond` field of a conditional instruction.
+fn icc2cond(cond: IntCC) -> u32 {
+    use crate::ir::condcodes::IntCC::*;
+    match cond {
+        Equal => 0b0000,
+        NotEqual => 0b0001,
+        UnsignedGreaterThanOrEqual => 0b0010,
+        UnsignedLessThan => 0b0011,
+        UnsignedGreaterThan => 0b1000,
+        UnsignedLessThanOrEqual => 0b1001,
+        SignedGreaterThanOrEqual => 0b1010,
+        SignedLessThan => 0b1011,
+        SignedGreaterThan => 0b1100,
+        SignedLessThanOrEqual => 0b1101,
+    }
+}
+
+/// Convert a floating point condition code to the `cond` field of a conditional instruction
+/// following an `fcmp`.
+///
+/// An unordered comparison sets the C and V flags. The `one` and `ueq` conditions can't be
+/// tested with a single condition and must be legalized.
+fn fcc2cond(cond: FloatCC) -> u32 {
+    use crate::ir::condcodes::FloatCC::*;
+    match cond {
+        Ordered => 0b0111,                       // vc
+        Unordered => 0b0110,                     // vs
+        Equal => 0b0000,                         // eq
+        NotEqual => 0b0001,                      // ne
+        LessThan => 0b0100,                      // mi
+        LessThanOrEqual => 0b1001,               // ls
+        GreaterThan => 0b1100,                   // gt
+        GreaterThanOrEqual => 0b1010,            // ge
+        UnorderedOrLessThan => 0b1011,           // lt
+        UnorderedOrLessThanOrEqual => 0b1101,    // le
+        UnorderedOrGreaterThan => 0b1000,        // hi
+        UnorderedOrGreaterThanOrEqual => 0b0101, // pl
+        OrderedNotEqual | UnorderedOrEqual => panic!("{} needs legalization", cond),
+    }
+}
+
+/// Data-processing instructions with three registers.
+///
+///   31    20 15  9  4
+///   opcode rm op rn rd
+///       21 16 10  5  0
+///
+/// Encoding bits: instruction bits 31:21.
+fn put_rrr<CS: CodeSink + ?Sized>(
+    bits: u16,
+    rn_: RegUnit,
+    rm: RegUnit,
+    rd: RegUnit,
+    sink: &mut CS,
+) {
+    let mut i = u32::from(bits & 0x7ff) << 21;
+    i |= rn(rm) << 16;
+    i |= rn(rn_) << 5;
+    i |= rn(rd);
+    sink.put4(i);
+}
+
+/// Data-processing (3 source) instructions.
+///
+///   31    20 15 14 9  4
+///   opcode rm o0 ra rn rd
+///       21 16 15 10  5  0
+///
+/// Encoding bits: instruction bits 31:21 | (o0 << 11).
+fn put_rrrr<CS: CodeSink + ?Sized>(
+    bits: u16,
+    rn_: RegUnit,
+    rm: RegUnit,
+    ra: RegUnit,
+    rd: RegUnit,
+    sink: &mut CS,
+) {
+    let mut i = u32::from(bits & 0x7ff) << 21;
+    i |= rn(rm) << 16;
+    i |= u32::from((bits >> 11) & 1) << 15;
+    i |= rn(ra) << 10;
+    i |= rn(rn_) << 5;
+    i |= rn(rd);
+    sink.put4(i);
+}
+
+/// Data-processing (1 source) instructions.
+///
+///   31 30         15     9  4
+///   sf 1011010110 opcode rn rd
+///   31         21     10  5  0
+///
+/// Encoding bits: `opcode | (sf << 6)`.
+fn put_dp1<CS: CodeSink + ?Sized>(bits: u16, rn_: RegUnit, rd: RegUnit, sink: &mut CS) {
+    let bits = u32::from(bits);
+    let mut i = 0x5ac0_0000;
+    i |= ((bits >> 6) & 1) << 31;
+    i |= (bits & 0x3f) << 10;
+    i |= rn(rn_) << 5;
+    i |= rn(rd);
+    sink.put4(i);
+}
+
+/// Data-processing (2 source) instructions.
+///
+///   31 30         20 15     9  4
+///   sf 0011010110 rm opcode rn rd
+///   31         21 16     10  5  0
+///
+/// Encoding bits: `opcode | (sf << 6)`.
+fn put_dp2<CS: CodeSink + ?Sized>(
+    bits: u16,
+    rn_: RegUnit,
+    rm: RegUnit,
+    rd: RegUnit,
+    sink: &mut CS,
+) {
+    let bits = u32::from(bits);
+    let mut i = 0x1ac0_0000;
+    i |= ((bits >> 6) & 1) << 31;
+    i |= rn(rm) << 16;
+    i |= (bits & 0x3f) << 10;
+    i |= rn(rn_) << 5;
+    i |= rn(rd);
+    sink.put4(i);
+}
+
+/// Add/subtract (immediate) instructions.
+///
+///   31     21    9  4
+///   opcode imm12 rn rd
+///       22    10  5  0
+///
+/// Encoding bits: instruction bits 31:22. The immediate is negated and the operation flipped
+/// between add and subtract for negative immediates.
+fn put_addsub_imm<CS: CodeSink + ?Sized>(
+    bits: u16,
+    rn_: RegUnit,
+    imm: i64,
+    rd: RegUnit,
+    sink: &mut CS,
+) {
+    let (bits, imm) = if imm < 0 {
+        (bits ^ (1 << 8), -imm)
+    } else {
+        (bits, imm)
+    };
+    debug_assert!(imm < 1 << 12, "immediate out of range: {}", imm);
+    let mut i = u32::from(bits & 0x3ff) << 22;
+    i |= (imm as u32 & 0xfff) << 10;
+    i |= rn(rn_) << 5;
+    i |= rn(rd);
+    sink.put4(i);
+}
+
+/// Add/subtract (extended register) instructions with a 64-bit offset register.
+///
+/// These accept the stack pointer as first operand and destination.
+///
+/// Encoding bits: instruction bits 31:22 of the immediate variant.
+fn put_addsub_ext<CS: CodeSink + ?Sized>(
+    bits: u16,
+    rn_: RegUnit,
+    rm: RegUnit,
+    rd: RegUnit,
+    sink: &mut CS,
+) {
+    // add xd, xn, xm, uxtx
+    let mut i = 0x8b20_6000;
+    i |= u32::from(bits & (1 << 8)) << 22;
+    i |= rn(rm) << 16;
+    i |= rn(rn_) << 5;
+    i |= rn(rd);
+    sink.put4(i);
+}
+
+/// Immediate shifts, encoded as bitfield moves.
+///
+///   31     21   15   9  4
+///   opcode immr imms rn rd
+///       22   16   10  5  0
+///
+/// Encoding bits: instruction bits 31:22 | (left << 10).
+fn put_shift_imm<CS: CodeSink + ?Sized>(
+    bits: u16,
+    rn_: RegUnit,
+    imm: i64,
+    rd: RegUnit,
+    sink: &mut CS,
+) {
+    let width = if bits & (1 << 9) != 0 { 64 } else { 32 };
+    let shift = imm as u32 & (width - 1);
+    let (immr, imms) = if bits & (1 << 10) != 0 {
+        ((width - shift) % width, width - 1 - shift)
+    } else {
+        (shift, width - 1)
+    };
+    let mut i = u32::from(bits & 0x3ff) << 22;
+    i |= immr << 16;
+    i |= imms << 10;
+    i |= rn(rn_) << 5;
+    i |= rn(rd);
+    sink.put4(i);
+}
+
+/// Immediate rotates, encoded as `extr` with both source registers equal.
+///
+///   31     20 15   9  4
+///   opcode rm imms rn rd
+///       21 16   10  5  0
+///
+/// Encoding bits: instruction bits 31:21 | (left << 11).
+fn put_rotate_imm<CS: CodeSink + ?Sized>(
+    bits: u16,
+    rn_: RegUnit,
+    imm: i64,
+    rd: RegUnit,
+    sink: &mut CS,
+) {
+    let width = if bits & (1 << 10) != 0 { 64 } else { 32 };
+    let mut shift = imm as u32 & (width - 1);
+    if bits & (1 << 11) != 0 {
+        shift = (width - shift) % width;
+    }
+    let mut i = u32::from(bits & 0x7ff) << 21;
+    i |= rn(rn_) << 16;
+    i |= shift << 10;
+    i |= rn(rn_) << 5;
+    i |= rn(rd);
+    sink.put4(i);
+}
+
+/// Bitfield moves with `immr` = 0, extracting the low `imms + 1` bits.
+///
+/// Encoding bits: instruction bits 31:22 | (imms << 10).
+fn put_bfm<CS: CodeSink + ?Sized>(bits: u16, rn_: RegUnit, rd: RegUnit, sink: &mut CS) {
+    let mut i = u32::from(bits & 0x3ff) << 22;
+    i |= u32::from((bits >> 10) & 0x3f) << 10;
+    i |= rn(rn_) << 5;
+    i |= rn(rd);
+    sink.put4(i);
+}
+
+/// Move wide (immediate) instructions.
+///
+///   31     22 20    4
+///   opcode hw imm16 rd
+///       23 21     5  0
+///
+/// Encoding bits: instruction bits 31:23.
+fn put_mov_wide<CS: CodeSink + ?Sized>(bits: u16, imm: u32, hw: u32, rd: RegUnit, sink: &mut CS) {
+    let mut i = u32::from(bits & 0x1ff) << 23;
+    i |= hw << 21;
+    i |= (imm & 0xffff) << 5;
+    i |= rn(rd);
+    sink.put4(i);
+}
+
+/// Materialize a 32-bit constant in `rd` with a `movz` and a `movk`.
+fn put_mov_i32<CS: CodeSink + ?Sized>(rd: RegUnit, imm: i32, sink: &mut CS) {
+    let imm = imm as u32;
+    put_mov_wide(0x0a5, imm, 0, rd, sink);
+    put_mov_wide(0x0a5 | MOVK_BIT, imm >> 16, 1, rd, sink);
+}
+
+/// Conditional set: `cset wd, cond`, which is `csinc wd, wzr, wzr, !cond`.
+fn put_cset<CS: CodeSink + ?Sized>(cond: u32, rd: RegUnit, sink: &mut CS) {
+    let mut i = 0x1a9f_07e0;
+    i |= (cond ^ 1) << 12;
+    i |= rn(rd);
+    sink.put4(i);
+}
+
+/// Conditional select instructions.
+///
+///   31     20 15   11 9  4
+///   opcode rm cond o2 rn rd
+///       21 16   12 10  5  0
+///
+/// Encoding bits: instruction bits 31:21 | (o2 << 11).
+fn put_csel<CS: CodeSink + ?Sized>(
+    bits: u16,
+    cond: u32,
+    rn_: RegUnit,
+    rm: RegUnit,
+    rd: RegUnit,
+    sink: &mut CS,
+) {
+    let mut i = u32::from(bits & 0x7ff) << 21;
+    i |= rn(rm) << 16;
+    i |= cond << 12;
+    i |= u32::from((bits >> 11) & 1) << 10;
+    i |= rn(rn_) << 5;
+    i |= rn(rd);
+    sink.put4(i);
+}
+
+/// Encoding bits of the `cmp c, #0` preceding a select, depending on bit 12 of the select
+/// encoding bits.
+fn select_cmp_bits(bits: u16) -> u16 {
+    if bits & (1 << 12) != 0 {
+        0x3c4
+    } else {
+        0x1c4
+    }
+}
+
+/// Floating-point conditional select.
+///
+/// Encoding bits: the floating point type.
+fn put_fcsel<CS: CodeSink + ?Sized>(
+    bits: u16,
+    cond: u32,
+    rn_: RegUnit,
+    rm: RegUnit,
+    rd: RegUnit,
+    sink: &mut CS,
+) {
+    let mut i = 0x1e20_0c00;
+    i |= u32::from(bits & 1) << 22;
+    i |= rn(rm) << 16;
+    i |= cond << 12;
+    i |= rn(rn_) << 5;
+    i |= rn(rd);
+    sink.put4(i);
+}
+
+/// Floating-point data-processing (1 source) instructions.
+///
+///   31        23   21     14        9  4
+///   000111100 type 1 opcode 10000 rn rd
+///          24   22 21    15    10  5  0
+///
+/// Encoding bits: `opcode | (type << 6)`.
+fn put_fp1<CS: CodeSink + ?Sized>(bits: u16, rn_: RegUnit, rd: RegUnit, sink: &mut CS) {
+    let bits = u32::from(bits);
+    let mut i = 0x1e20_4000;
+    i |= ((bits >> 6) & 0b11) << 22;
+    i |= (bits & 0x3f) << 15;
+    i |= rn(rn_) << 5;
+    i |= rn(rd);
+    sink.put4(i);
+}
+
+/// Floating-point data-processing (2 source) instructions.
+///
+///   31        23   21 20 15     11 9  4
+///   000111100 type 1  rm opcode 10 rn rd
+///          24   22 21 16     12 10  5  0
+///
+/// Encoding bits: `opcode | (type << 4)`.
+fn put_fp2<CS: CodeSink + ?Sized>(
+    bits: u16,
+    rn_: RegUnit,
+    rm: RegUnit,
+    rd: RegUnit,
+    sink: &mut CS,
+) {
+    let bits = u32::from(bits);
+    let mut i = 0x1e20_0800;
+    i |= ((bits >> 4) & 0b11) << 22;
+    i |= rn(rm) << 16;
+    i |= (bits & 0xf) << 12;
+    i |= rn(rn_) << 5;
+    i |= rn(rd);
+    sink.put4(i);
+}
+
+/// Fused multiply-add: `fmadd rd, rn, rm, ra`.
+///
+/// Encoding bits: the floating point type.
+fn put_fp3<CS: CodeSink + ?Sized>(
+    bits: u16,
+    rn_: RegUnit,
+    rm: RegUnit,
+    ra: RegUnit,
+    rd: RegUnit,
+    sink: &mut CS,
+) {
+    let mut i = 0x1f00_0000;
+    i |= u32::from(bits & 0b11) << 22;
+    i |= rn(rm) << 16;
+    i |= rn(ra) << 10;
+    i |= rn(rn_) << 5;
+    i |= rn(rd);
+    sink.put4(i);
+}
+
+/// Floating-point compare.
+///
+/// Encoding bits: the floating point type.
+fn put_fcmp<CS: CodeSink + ?Sized>(bits: u16, rn_: RegUnit, rm: RegUnit, sink: &mut CS) {
+    let mut i = 0x1e20_2000;
+    i |= u32::from(bits & 0b11) << 22;
+    i |= rn(rm) << 16;
+    i |= rn(rn_) << 5;
+    sink.put4(i);
+}
+
+/// Instructions with two registers and a fixed upper half, like moves between register files
+/// and conversions.
+///
+/// Encoding bits: instruction bits 31:16.
+fn put_rr<CS: CodeSink + ?Sized>(bits: u16, rn_: RegUnit, rd: RegUnit, sink: &mut CS) {
+    let mut i = u32::from(bits) << 16;
+    i |= rn(rn_) << 5;
+    i |= rn(rd);
+    sink.put4(i);
+}
+
+/// Load/store (unsigned offset) instructions. The offset is scaled by the access size.
+///
+///   31     21    9  4
+///   opcode imm12 rn rt
+///       22    10  5  0
+///
+/// Encoding bits: instruction bits 31:22. The access size is encoded in bits 31:30.
+fn put_ldst<CS: CodeSink + ?Sized>(
+    bits: u16,
+    rt: RegUnit,
+    rn_: RegUnit,
+    offset: i64,
+    sink: &mut CS,
+) {
+    let scale = bits >> 8;
+    debug_assert!(is_unsigned_int(offset, 12 + scale as u8, scale as u8));
+    let mut i = u32::from(bits & 0x3ff) << 22;
+    i |= ((offset >> scale) as u32 & 0xfff) << 10;
+    i |= rn(rn_) << 5;
+    i |= rn(rt);
+    sink.put4(i);
+}
+
+/// Load/store (unscaled immediate) instructions, with a 9-bit signed offset.
+///
+/// Encoding bits: instruction bits 31:22 of the unsigned offset variant.
+fn put_ldst_unscaled<CS: CodeSink + ?Sized>(
+    bits: u16,
+    rt: RegUnit,
+    rn_: RegUnit,
+    offset: i64,
+    sink: &mut CS,
+) {
+    let mut i = (u32::from(bits & 0x3ff) << 22) & !(1 << 24);
+    i |= (offset as u32 & 0x1ff) << 12;
+    i |= rn(rn_) << 5;
+    i |= rn(rt);
+    sink.put4(i);
+}
+
+/// Load/store (register offset) instructions.
+///
+///   31     20 15     12 11 9  4
+///   opcode rm option S  10 rn rt
+///       21 16     13 12 10  5  0
+///
+/// Encoding bits: instruction bits 31:22 of the unsigned offset variant.
+fn put_ldst_reg<CS: CodeSink + ?Sized>(
+    bits: u16,
+    rt: RegUnit,
+    rn_: RegUnit,
+    rm: RegUnit,
+    extend: u32,
+    sink: &mut CS,
+) {
+    let mut i = (u32::from(bits & 0x3ff) << 22) & !(1 << 24);
+    i |= 1 << 21;
+    i |= rn(rm) << 16;
+    i |= extend << 12;
+    i |= 0b10 << 10;
+    i |= rn(rn_) << 5;
+    i |= rn(rt);
+    sink.put4(i);
+}
+
+/// Load or store `rt` at `offset` from the stack pointer, building the offset in the scratch
+/// register when it doesn't fit in the scaled immediate.
+///
+/// This must agree with `size_plus_maybe_offset_for_stack_slot` in `enc_tables`.
+fn put_ldst_sp<CS: CodeSink + ?Sized>(bits: u16, rt: RegUnit, offset: i32, sink: &mut CS) {
+    let scale = (bits >> 8) as u8;
+    if is_unsigned_int(offset, 12 + scale, scale) {
+        put_ldst(bits, rt, STACK_REG, offset.into(), sink);
+    } else {
+        put_mov_i32(SCRATCH_REG, offset, sink);
+        put_ldst_reg(bits, rt, STACK_REG, SCRATCH_REG, EXTEND_SXTW, sink);
+    }
+}
+
+/// Load/store with a pre-indexed (negative offset) or post-indexed (positive offset) update of
+/// the stack pointer.
+///
+/// Encoding bits: instruction bits 31:22.
+fn put_ldst_sp_indexed<CS: CodeSink + ?Sized>(bits: u16, rt: RegUnit, offset: i32, sink: &mut CS) {
+    let index_mode = if offset < 0 { 0b11 } else { 0b01 };
+    let mut i = u32::from(bits & 0x3ff) << 22;
+    i |= (offset as u32 & 0x1ff) << 12;
+    i |= index_mode << 10;
+    i |= rn(STACK_REG) << 5;
+    i |= rn(rt);
+    sink.put4(i);
+}
+
+/// Load/store pair of 8-byte registers with a pre-indexed (negative offset) or post-indexed
+/// (positive offset) update of the stack pointer.
+///
+///   31     21   14  9  4
+///   opcode imm7 rt2 rn rt
+///       22   15  10  5  0
+///
+/// Encoding bits: instruction bits 31:22.
+fn put_ldst_pair_sp<CS: CodeSink + ?Sized>(
+    bits: u16,
+    rt: RegUnit,
+    rt2: RegUnit,
+    offset: i32,
+    sink: &mut CS,
+) {
+    let mut i = u32::from(bits & 0x3ff) << 22;
+    i |= ((offset >> 3) as u32 & 0x7f) << 15;
+    i |= rn(rt2) << 10;
+    i |= rn(STACK_REG) << 5;
+    i |= rn(rt);
+    sink.put4(i);
+}
+
+/// Unconditional branch (immediate): `b` and `bl`.
+///
+///   31     25
+///   opcode imm26
+///       26     0
+///
+/// Encoding bits: instruction bits 31:26.
+fn put_b<CS: CodeSink + ?Sized>(bits: u16, disp: i64, sink: &mut CS) {
+    debug_assert!(disp & 3 == 0);
+    let mut i = u32::from(bits & 0x3f) << 26;
+    i |= (disp >> 2) as u32 & 0x3ff_ffff;
+    sink.put4(i);
+}
+
+/// Compare and branch: `cbz` and `cbnz`.
+///
+///   31     23    4
+///   opcode imm19 rt
+///       24     5  0
+///
+/// Encoding bits: instruction bits 31:24.
+fn put_cb<CS: CodeSink + ?Sized>(bits: u16, rt: RegUnit, disp: i64, sink: &mut CS) {
+    let mut i = u32::from(bits & 0xff) << 24;
+    i |= ((disp >> 2) as u32 & 0x7_ffff) << 5;
+    i |= rn(rt);
+    sink.put4(i);
+}
+
+/// Conditional branch: `b.cond`.
+fn put_bcond<CS: CodeSink + ?Sized>(cond: u32, disp: i64, sink: &mut CS) {
+    let mut i = 0x5400_0000;
+    i |= ((disp >> 2) as u32 & 0x7_ffff) << 5;
+    i |= cond;
+    sink.put4(i);
+}
+
+/// Unconditional branch (register): `br`, `blr` and `ret`.
+///
+/// Encoding bits: instruction bits 31:16.
+fn put_br<CS: CodeSink + ?Sized>(bits: u16, rn_: RegUnit, sink: &mut CS) {
+    let mut i = u32::from(bits) << 16;
+    i |= rn(rn_) << 5;
+    sink.put4(i);
+}
+
+/// PC-relative address: `adr`.
+fn put_adr<CS: CodeSink + ?Sized>(rd: RegUnit, disp: i64, sink: &mut CS) {
+    let mut i = 0x1000_0000;
+    i |= (disp as u32 & 0b11) << 29;
+    i |= ((disp >> 2) as u32 & 0x7_ffff) << 5;
+    i |= rn(rd);
+    sink.put4(i);
+}
+
+/// Load `rd` from the 8-byte literal following a branch over it: `ldr rd, #8; b #12`.
+///
+/// The caller emits the literal itself.
+fn put_ldr_literal_skip<CS: CodeSink + ?Sized>(rd: RegUnit, sink: &mut CS) {
+    sink.put4(0x5800_0040 | rn(rd));
+    sink.put4(0x1400_0003);
+}
+
+/// Permanently undefined instruction, used for traps.
+fn put_udf<CS: CodeSink + ?Sized>(sink: &mut CS) {
+    sink.put4(0);
+}
+
+/// Breakpoint instruction: `brk #0`.
+fn put_brk<CS: CodeSink + ?Sized>(sink: &mut CS) {
+    sink.put4(0xd420_0000);
+}
@@ -1,10 +1,82 @@
 //! Encoding tables for ARM64 ISA.
 
-use crate::ir;
+use super::registers::*;
+use crate::ir::{self, Function, Inst};
 use crate::isa;
 use crate::isa::constraints::*;
 use crate::isa::enc_tables::*;
-use crate::isa::encoding::RecipeSizing;
+use crate::isa::encoding::{base_size, RecipeSizing};
+use crate::isa::StackRef;
+use crate::legalizer::expand_fcvt_to_int;
+use crate::predicates;
+use crate::regalloc::RegDiversions;
 
 include!(concat!(env!("OUT_DIR"), "/encoding-arm64.rs"));
 include!(concat!(env!("OUT_DIR"), "/legalize-arm64.rs"));
+
+/// Number of bytes needed to build an offset that doesn't fit in an immediate field in the
+/// scratch register.
+const LARGE_OFFSET_SIZE: u8 = 8;
+
+/// The size of a spill or fill: offsets from the stack pointer that don't fit in the scaled
+/// unsigned immediate of the load or store are first built in a register.
+fn size_plus_maybe_offset_for_stack_slot(
+    sizing: &RecipeSizing,
+    inst: Inst,
+    divert: &RegDiversions,
+    func: &Function,
+) -> u8 {
+    let ss = match func.dfg[inst] {
+        ir::InstructionData::RegSpill { dst, .. } => dst,
+        ir::InstructionData::RegFill { src, .. } => src,
+        ir::InstructionData::Unary { arg, .. } => {
+            // A spill has its result on the stack, and a fill its argument.
+            let result = func.dfg.first_result(inst);
+            match func.locations[result] {
+                ir::ValueLoc::Stack(ss) => ss,
+                _ => divert.stack(arg, &func.locations),
+            }
+        }
+        _ => panic!(
+            "Unexpected stack access {}",
+            func.dfg.display_inst(inst, None)
+        ),
+    };
+    let offset = StackRef::sp(ss, &func.stack_slots).offset;
+    let scale = (func.encodings[inst].bits() >> 8) as u8;
+    if predicates::is_unsigned_int(offset, 12 + scale, scale) {
+        sizing.base_size
+    } else {
+        sizing.base_size + LARGE_OFFSET_SIZE
+    }
+}
+
+/// The size of a `stack_addr`: offsets from the stack pointer that don't fit in the immediate
+/// of an `add` are first built in a register.
+fn size_plus_maybe_offset_for_stack_addr(
+    sizing: &RecipeSizing,
+    inst: Inst,
+    _divert: &RegDiversions,
+    func: &Function,
+) -> u8 {
+    let offset = match func.dfg[inst] {
+        ir::InstructionData::StackLoad {
+            stack_slot, offset, ..
+        } => {
+            let imm: i32 = offset.into();
+            StackRef::sp(stack_slot, &func.stack_slots)
+                .offset
+                .checked_add(imm)
+                .unwrap()
+        }
+        _ => panic!(
+            "Unexpected stack address {}",
+            func.dfg.display_inst(inst, None)
+        ),
+    };
+    if predicates::is_unsigned_int(offset, 12, 0) {
+        sizing.base_size
+    } else {
+        sizing.base_size + LARGE_OFFSET_SIZE
+    }
+}
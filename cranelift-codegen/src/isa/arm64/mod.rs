@@ -13,8 +13,10 @@ use crate::binemit::{emit_function, MemoryCodeSink};
 use crate::ir;
 use crate::isa::enc_tables::{lookup_enclist, Encodings};
 use crate::isa::Builder as IsaBuilder;
-use crate::isa::{EncInfo, RegClass, RegInfo, TargetIsa};
+use crate::isa::{EncInfo, RegClass, RegInfo, RegUnit, TargetIsa};
 use crate::regalloc;
+use crate::result::CodegenResult;
+use crate::timing;
 use core::fmt;
 use std::boxed::Box;
 use target_lexicon::Triple;
@@ -60,6 +62,10 @@ impl TargetIsa for Isa {
         &self.shared_flags
     }
 
+    fn uses_cpu_flags(&self) -> bool {
+        true
+    }
+
     fn register_info(&self) -> RegInfo {
         registers::INFO.clone()
     }
@@ -114,6 +120,22 @@ impl TargetIsa for Isa {
     fn emit_function_to_memory(&self, func: &ir::Function, sink: &mut MemoryCodeSink) {
         emit_function(func, binemit::emit_inst, sink, self)
     }
+
+    fn prologue_epilogue(&self, func: &mut ir::Function) -> CodegenResult<()> {
+        let _tt = timing::prologue_epilogue();
+        abi::prologue_epilogue(func, self)
+    }
+
+    fn map_dwarf_register(&self, reg: RegUnit) -> Option<u16> {
+        // The AArch64 DWARF numbers are x0-x30 and sp as 0-31, and v0-v31 as 64-95.
+        let bank = registers::INFO.bank_containing_regunit(reg)?;
+        let index = reg - bank.first_unit;
+        match bank.name {
+            "IntRegs" => Some(index),
+            "FloatRegs" => Some(64 + index),
+            _ => None,
+        }
+    }
 }
 
 impl fmt::Display for Isa {
@@ -121,3 +143,104 @@ impl fmt::Display for Isa {
         write!(f, "{}\n{}", self.shared_flags, self.isa_flags)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::ir::{immediates, types};
+    use crate::ir::{Function, InstructionData, Opcode};
+    use crate::isa;
+    use crate::settings;
+    use core::str::FromStr;
+    use std::string::{String, ToString};
+    use target_lexicon::triple;
+
+    fn encstr(isa: &dyn isa::TargetIsa, enc: Result<isa::Encoding, isa::Legalize>) -> String {
+        match enc {
+            Ok(e) => isa.encoding_info().display(e).to_string(),
+            Err(_) => "no encoding".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_addsub_imm() {
+        let shared_builder = settings::builder();
+        let shared_flags = settings::Flags::new(shared_builder);
+        let isa = isa::lookup(triple!("aarch64"))
+            .unwrap()
+            .finish(shared_flags);
+
+        let mut func = Function::new();
+        let ebb = func.dfg.make_ebb();
+        let arg64 = func.dfg.append_ebb_param(ebb, types::I64);
+        let arg32 = func.dfg.append_ebb_param(ebb, types::I32);
+
+        // Try to encode iadd_imm.i64 v1, -10.
+        let inst64 = InstructionData::BinaryImm {
+            opcode: Opcode::IaddImm,
+            arg: arg64,
+            imm: immediates::Imm64::new(-10),
+        };
+
+        // ADD (immediate) is 0x91000000 for 64-bit registers.
+        assert_eq!(
+            encstr(&*isa, isa.encode(&func, &inst64, types::I64)),
+            "addsub_imm#244"
+        );
+
+        // Try to encode iadd_imm.i64 v1, 10000.
+        let inst64_large = InstructionData::BinaryImm {
+            opcode: Opcode::IaddImm,
+            arg: arg64,
+            imm: immediates::Imm64::new(10000),
+        };
+
+        // Immediate is out of range for ADD.
+        assert!(isa.encode(&func, &inst64_large, types::I64).is_err());
+
+        // Create an iadd_imm.i32.
+        let inst32 = InstructionData::BinaryImm {
+            opcode: Opcode::IaddImm,
+            arg: arg32,
+            imm: immediates::Imm64::new(10),
+        };
+
+        // ADD (immediate) is 0x11000000 for 32-bit registers.
+        assert_eq!(
+            encstr(&*isa, isa.encode(&func, &inst32, types::I32)),
+            "addsub_imm#44"
+        );
+    }
+
+    #[test]
+    fn test_iconst() {
+        let shared_builder = settings::builder();
+        let shared_flags = settings::Flags::new(shared_builder);
+        let isa = isa::lookup(triple!("aarch64"))
+            .unwrap()
+            .finish(shared_flags);
+
+        let func = Function::new();
+        let iconst = |imm| InstructionData::UnaryImm {
+            opcode: Opcode::Iconst,
+            imm: immediates::Imm64::new(imm),
+        };
+
+        // Small positive constants use MOVZ, small negative ones MOVN.
+        assert_eq!(
+            encstr(&*isa, isa.encode(&func, &iconst(0xffff), types::I64)),
+            "movz#1a5"
+        );
+        assert_eq!(
+            encstr(&*isa, isa.encode(&func, &iconst(-0x10000), types::I64)),
+            "movn#125"
+        );
+        assert_eq!(
+            encstr(&*isa, isa.encode(&func, &iconst(0x10000), types::I64)),
+            "movk64#1a5"
+        );
+        assert_eq!(
+            encstr(&*isa, isa.encode(&func, &iconst(0x10000), types::I32)),
+            "movk32#a5"
+        );
+    }
+}
@@ -372,4 +372,24 @@ pub trait TargetIsa: fmt::Display + Sync {
 
     /// Emit a whole function into memory.
     fn emit_function_to_memory(&self, func: &ir::Function, sink: &mut binemit::MemoryCodeSink);
+
+    /// Emit unwind information for the given function.
+    ///
+    /// Only some calling conventions (e.g. Windows fastcall) will have unwind information.
+    fn emit_unwind_info(
+        &self,
+        _func: &ir::Function,
+        _kind: binemit::FrameUnwindKind,
+        _sink: &mut dyn binemit::FrameUnwindSink,
+    ) {
+        // No-op by default
+    }
+
+    /// Map a register unit to its DWARF register number, for use in debug information.
+    ///
+    /// Returns `None` if the register has no DWARF number, or if the ISA doesn't support debug
+    /// information.
+    fn map_dwarf_register(&self, _reg: RegUnit) -> Option<u16> {
+        None
+    }
 }
@@ -1,17 +1,25 @@
 //! RISC-V ABI implementation.
 //!
 //! This module implements the RISC-V calling convention through the primary `legalize_signature()`
-//! entry point.
+//! entry point, and the matching prologue and epilogue.
 //!
 //! This doesn't support the soft-float ABI at the moment.
 
 use super::registers::{FPR, GPR};
 use super::settings;
 use crate::abi::{legalize_args, ArgAction, ArgAssigner, ValueConversion};
-use crate::ir::{self, AbiParam, ArgumentExtension, ArgumentLoc, ArgumentPurpose, Type};
-use crate::isa::RegClass;
+use crate::cursor::{Cursor, CursorPosition, EncCursor};
+use crate::ir::immediates::Imm64;
+use crate::ir::stackslot::StackOffset;
+use crate::ir::{
+    self, AbiParam, ArgumentExtension, ArgumentLoc, ArgumentPurpose, InstBuilder, Type, ValueLoc,
+};
+use crate::isa::{RegClass, RegUnit, TargetIsa};
 use crate::regalloc::RegisterSet;
+use crate::result::{CodegenError, CodegenResult};
+use crate::stack_layout::layout_stack;
 use core::i32;
+use std::vec::Vec;
 use target_lexicon::Triple;
 
 struct Args {
@@ -132,13 +140,154 @@ pub fn allocatable_registers(_func: &ir::Function, isa_flags: &settings::Flags)
     regs.take(GPR, GPR.unit(3)); // Global pointer.
     regs.take(GPR, GPR.unit(4)); // Thread pointer.
                                  // TODO: %x8 is the frame pointer. Reserve it?
+    regs.take(GPR, GPR.unit(31)); // Scratch register for large offsets and immediates.
 
     // Remove %x16 and up for RV32E.
     if isa_flags.enable_e() {
-        for u in 16..32 {
+        for u in 16..31 {
             regs.take(GPR, GPR.unit(u));
         }
     }
 
     regs
 }
+
+/// Get the set of callee-saved registers that are used by `func`.
+fn callee_saved_regs_used(func: &ir::Function, isa_flags: &settings::Flags) -> RegisterSet {
+    // %x8-%x9 and %x18-%x27 are callee-saved, and so are the same numbers in the FPR bank.
+    let mut all_callee_saved = RegisterSet::empty();
+    for reg in (8..10).chain(18..28) {
+        if reg < 16 || !isa_flags.enable_e() {
+            all_callee_saved.free(GPR, GPR.unit(reg));
+        }
+        all_callee_saved.free(FPR, FPR.unit(reg));
+    }
+
+    let mut used = RegisterSet::empty();
+    let mut mark_used = |ru: RegUnit| {
+        for &rc in &[GPR, FPR] {
+            if rc.contains(ru) && !used.is_avail(rc, ru) {
+                used.free(rc, ru);
+            }
+        }
+    };
+
+    for value_loc in func.locations.values() {
+        if let ValueLoc::Reg(ru) = *value_loc {
+            mark_used(ru);
+        }
+    }
+
+    // A `regmove` or `regfill` can divert a value into one of the s or fs registers without
+    // that showing up in `func.locations`.
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            match func.dfg[inst] {
+                ir::instructions::InstructionData::RegMove { dst, .. }
+                | ir::instructions::InstructionData::RegFill { dst, .. } => {
+                    mark_used(dst);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    used.intersect(&all_callee_saved);
+    used
+}
+
+/// Insert the prologue and epilogues of `func`.
+///
+/// The callee-saved registers are stored in an explicit stack slot. The link register is handled
+/// by the register allocator like any other value.
+pub fn prologue_epilogue(
+    func: &mut ir::Function,
+    isa: &dyn TargetIsa,
+    isa_flags: &settings::Flags,
+) -> CodegenResult<()> {
+    let csrs = callee_saved_regs_used(func, isa_flags);
+    let gprs: Vec<RegUnit> = csrs.iter(GPR).collect();
+    let fprs: Vec<RegUnit> = csrs.iter(FPR).collect();
+
+    let gpr_type = isa.pointer_type();
+    let fpr_type = if isa_flags.use_d() {
+        ir::types::F64
+    } else {
+        ir::types::F32
+    };
+
+    let csr_stack_size = (gprs.len() as u32 * gpr_type.bytes()
+        + fprs.len() as u32 * fpr_type.bytes()) as StackOffset;
+    let csr_slot = if csr_stack_size > 0 {
+        Some(func.create_stack_slot(ir::StackSlotData::new(
+            ir::StackSlotKind::ExplicitSlot,
+            csr_stack_size as u32,
+        )))
+    } else {
+        None
+    };
+
+    let stack_size = layout_stack(&mut func.stack_slots, 16)?;
+
+    // RV32E has no scratch register for building larger stack offsets, and adjustments of the
+    // stack pointer can only be built in 30 bits.
+    let max_stack_size = if isa_flags.enable_e() {
+        1 << 11
+    } else {
+        1 << 30
+    };
+    if stack_size >= max_stack_size {
+        return Err(CodegenError::ImplLimitExceeded);
+    }
+    let stack_size = i64::from(stack_size);
+
+    for &(regs, ty) in &[(&gprs, gpr_type), (&fprs, fpr_type)] {
+        for &reg in regs.iter() {
+            let csr_arg = AbiParam::special_reg(ty, ArgumentPurpose::CalleeSaved, reg);
+            func.signature.params.push(csr_arg);
+            func.signature.returns.push(csr_arg);
+        }
+    }
+
+    // The callee-saved registers in the order they are stored, and their offset in the slot.
+    let mut offset = 0;
+    let mut csr_offsets = Vec::new();
+    for &(regs, ty) in &[(&gprs, gpr_type), (&fprs, fpr_type)] {
+        for &reg in regs.iter() {
+            csr_offsets.push((reg, ty, offset));
+            offset += ty.bytes() as i32;
+        }
+    }
+
+    // Set up the cursor and insert the prologue.
+    let entry_ebb = func.layout.entry_block().expect("missing entry block");
+    let mut pos = EncCursor::new(func, isa).at_first_insertion_point(entry_ebb);
+    if stack_size > 0 {
+        pos.ins().adjust_sp_down_imm(Imm64::new(stack_size));
+    }
+    for &(reg, ty, offset) in &csr_offsets {
+        let v = pos.func.dfg.append_ebb_param(entry_ebb, ty);
+        pos.func.locations[v] = ValueLoc::Reg(reg);
+        pos.ins().stack_store(v, csr_slot.unwrap(), offset);
+    }
+
+    // Reset the cursor and insert the epilogues.
+    let mut pos = pos.at_position(CursorPosition::Nowhere);
+    while let Some(ebb) = pos.next_ebb() {
+        pos.goto_last_inst(ebb);
+        if let Some(inst) = pos.current_inst() {
+            if pos.func.dfg[inst].opcode().is_return() {
+                for &(reg, ty, offset) in &csr_offsets {
+                    let v = pos.ins().stack_load(ty, csr_slot.unwrap(), offset);
+                    pos.func.locations[v] = ValueLoc::Reg(reg);
+                    pos.func.dfg.append_inst_arg(inst, v);
+                }
+                if stack_size > 0 {
+                    pos.ins().adjust_sp_up_imm(Imm64::new(stack_size));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
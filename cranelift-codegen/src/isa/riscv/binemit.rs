@@ -1,7 +1,7 @@
 //! Emitting binary RISC-V machine code.
 
 use crate::binemit::{bad_encoding, CodeSink, Reloc};
-use crate::ir::{Function, Inst, InstructionData};
+use crate::ir::{types, Function, Inst, InstructionData, SourceLoc, TrapCode, Type};
 use crate::isa::{RegUnit, StackBaseMask, StackRef, TargetIsa};
 use crate::predicates::is_signed_int;
 use crate::regalloc::RegDiversions;
@@ -9,6 +9,30 @@ use core::u32;
 
 include!(concat!(env!("OUT_DIR"), "/binemit-riscv.rs"));
 
+/// The stack pointer %x2.
+const STACK_REG: RegUnit = 2;
+
+/// The %x31 register is reserved as a scratch register for offsets and constants that don't fit
+/// in an immediate field.
+pub(super) const SCRATCH_REG: RegUnit = 31;
+
+// Encoding bits of the instructions used in multi-instruction sequences.
+const ADDI_BITS: u16 = 0b00100;
+const ADDIW_BITS: u16 = 0b00110;
+const ADD_BITS: u16 = 0b01100;
+const AND_BITS: u16 = 0b01100 | (0b111 << 5);
+const AUIPC_BITS: u16 = 0b00101;
+const BNE_BITS: u16 = 0b11000 | (0b001 << 5);
+const JAL_BITS: u16 = 0b11011;
+const LUI_BITS: u16 = 0b01101;
+const SLLI_BITS: u16 = 0b00100 | (0b001 << 5);
+const SRAI_BITS: u16 = 0b00100 | (0b101 << 5) | (0b0100000 << 8);
+const SRLI_BITS: u16 = 0b00100 | (0b101 << 5);
+const SUB_BITS: u16 = 0b01100 | (0b0100000 << 8);
+
+/// The `feq` OP-FP encoding bits, without the format.
+const FEQ_BITS: u16 = 0b010 | (0b1010000 << 3);
+
 /// R-type instructions.
 ///
 ///   31     24  19  14     11 6
@@ -180,3 +204,278 @@ fn put_uj<CS: CodeSink + ?Sized>(bits: u16, imm: i64, rd: RegUnit, sink: &mut CS
 
     sink.put4(i);
 }
+
+/// S-type store instructions.
+///
+///   31  24  19  14     11  6
+///   imm rs2 rs1 funct3 imm opcode
+///    25  20  15     12   7      0
+///
+/// Encoding bits: `opcode[6:2] | (funct3 << 5)`
+fn put_s<CS: CodeSink + ?Sized>(bits: u16, rs1: RegUnit, rs2: RegUnit, imm: i64, sink: &mut CS) {
+    let bits = u32::from(bits);
+    let opcode5 = bits & 0x1f;
+    let funct3 = (bits >> 5) & 0x7;
+    let rs1 = u32::from(rs1) & 0x1f;
+    let rs2 = u32::from(rs2) & 0x1f;
+
+    debug_assert!(is_signed_int(imm, 12, 0), "S out of range {:#x}", imm);
+    let imm = imm as u32;
+
+    // 0-6: opcode
+    let mut i = 0x3;
+    i |= opcode5 << 2;
+    i |= (imm & 0x1f) << 7;
+    i |= funct3 << 12;
+    i |= rs1 << 15;
+    i |= rs2 << 20;
+    i |= ((imm >> 5) & 0x7f) << 25;
+
+    sink.put4(i);
+}
+
+/// R4-type fused multiply-add instructions.
+///
+///   31  26  24  19  14 11 6
+///   rs3 fmt rs2 rs1 rm rd opcode
+///    27  25  20  15 12  7      0
+///
+/// Encoding bits: `opcode[6:2] | (rm << 5) | (fmt << 8)`
+fn put_r4<CS: CodeSink + ?Sized>(
+    bits: u16,
+    rs1: RegUnit,
+    rs2: RegUnit,
+    rs3: RegUnit,
+    rd: RegUnit,
+    sink: &mut CS,
+) {
+    let bits = u32::from(bits);
+    let opcode5 = bits & 0x1f;
+    let rm = (bits >> 5) & 0x7;
+    let fmt = (bits >> 8) & 0x3;
+    let rs1 = u32::from(rs1) & 0x1f;
+    let rs2 = u32::from(rs2) & 0x1f;
+    let rs3 = u32::from(rs3) & 0x1f;
+    let rd = u32::from(rd) & 0x1f;
+
+    // 0-6: opcode
+    let mut i = 0x3;
+    i |= opcode5 << 2;
+    i |= rd << 7;
+    i |= rm << 12;
+    i |= rs1 << 15;
+    i |= rs2 << 20;
+    i |= fmt << 25;
+    i |= rs3 << 27;
+
+    sink.put4(i);
+}
+
+/// OP-FP instructions. These are R-type instructions with a fixed opcode.
+///
+/// Encoding bits: `rm | (funct7 << 3) | (rs2 << 10)`. The rs2 bits are combined with the `rs2`
+/// register, and provide the fixed rs2 field of unary instructions.
+fn put_fp<CS: CodeSink + ?Sized>(
+    bits: u16,
+    rs1: RegUnit,
+    rs2: RegUnit,
+    rd: RegUnit,
+    sink: &mut CS,
+) {
+    let rm = bits & 0x7;
+    let funct7 = (bits >> 3) & 0x7f;
+    let rs2 = rs2 | (bits >> 10);
+    put_r(0b10100 | (rm << 5) | (funct7 << 8), rs1, rs2, rd, sink);
+}
+
+/// Saturating conversion from a float to an integer, with NaN converting to 0.
+///
+/// Encoding bits: the OP-FP bits of the `fcvt` instruction.
+fn put_fcvt_sat<CS: CodeSink + ?Sized>(bits: u16, rs: RegUnit, rd: RegUnit, sink: &mut CS) {
+    let fmt = (bits >> 3) & 0x3;
+    // feq x31, rs, rs
+    put_fp(FEQ_BITS | (fmt << 3), rs, rs, SCRATCH_REG, sink);
+    put_fp(bits, rs, 0, rd, sink);
+    // The mask is all ones unless the input is NaN.
+    put_r(SUB_BITS, 0, SCRATCH_REG, SCRATCH_REG, sink);
+    put_r(AND_BITS, rd, SCRATCH_REG, rd, sink);
+}
+
+/// Split a 32-bit immediate into the `lui` and `addi` parts. The low part is sign-extended, so
+/// the high part is rounded accordingly.
+fn split_imm32(imm: i32) -> (i64, i64) {
+    let lo = i64::from((imm << 20) >> 20);
+    let hi = i64::from(imm).wrapping_sub(lo);
+    (hi, lo)
+}
+
+/// Build the 32-bit constant `imm` in `rd` with a `lui` and `addi` pair.
+fn put_li32<CS: CodeSink + ?Sized>(rd: RegUnit, imm: i32, sink: &mut CS) {
+    let (hi, lo) = split_imm32(imm);
+    put_u(LUI_BITS, hi, rd, sink);
+    put_i(ADDI_BITS, rd, lo, rd, sink);
+}
+
+/// Compute `base + offset` with the low 12 bits of `offset` cleared in the scratch register,
+/// and return the remaining low part of the offset.
+fn put_large_offset<CS: CodeSink + ?Sized>(base: RegUnit, offset: i32, sink: &mut CS) -> i64 {
+    let (hi, lo) = split_imm32(offset);
+    put_u(LUI_BITS, hi, SCRATCH_REG, sink);
+    put_r(ADD_BITS, SCRATCH_REG, base, SCRATCH_REG, sink);
+    lo
+}
+
+/// Store `rs` to the stack at `offset` from the stack pointer.
+fn put_sp_store<CS: CodeSink + ?Sized>(bits: u16, rs: RegUnit, offset: i32, sink: &mut CS) {
+    if is_signed_int(offset, 12, 0) {
+        put_s(bits, STACK_REG, rs, offset.into(), sink);
+    } else {
+        let lo = put_large_offset(STACK_REG, offset, sink);
+        put_s(bits, SCRATCH_REG, rs, lo, sink);
+    }
+}
+
+/// Load `rd` from the stack at `offset` from the stack pointer.
+fn put_sp_load<CS: CodeSink + ?Sized>(bits: u16, rd: RegUnit, offset: i32, sink: &mut CS) {
+    if is_signed_int(offset, 12, 0) {
+        put_i(bits, STACK_REG, offset.into(), rd, sink);
+    } else {
+        let lo = put_large_offset(STACK_REG, offset, sink);
+        put_i(bits, SCRATCH_REG, lo, rd, sink);
+    }
+}
+
+/// Call `put` with the encoding bits, source register and immediate of each instruction in the
+/// sequence that materializes `imm`. A `None` source register stands for the destination.
+///
+/// This follows the usual RISC-V approach: 32-bit values are built with `lui` and `addi(w)`,
+/// and larger values are built recursively from their high bits, shifted left and with the low
+/// 12 bits added.
+fn li_sequence(imm: i64, rv64: bool, put: &mut dyn FnMut(u16, Option<RegUnit>, i64)) {
+    if is_signed_int(imm, 32, 0) {
+        let (hi, lo) = split_imm32(imm as i32);
+        if hi != 0 {
+            put(LUI_BITS, None, hi);
+        }
+        if lo != 0 || hi == 0 {
+            if hi == 0 {
+                put(ADDI_BITS, Some(0), lo);
+            } else if rv64 {
+                // `addiw` wraps the sum to 32 bits, which covers a rounded up `lui` part.
+                put(ADDIW_BITS, None, lo);
+            } else {
+                put(ADDI_BITS, None, lo);
+            }
+        }
+        return;
+    }
+
+    debug_assert!(rv64, "64-bit constant in RV32");
+    let lo = (imm << 52) >> 52;
+    let hi = imm.wrapping_sub(lo) >> 12;
+    let shift = 12 + hi.trailing_zeros();
+    let hi = hi >> (shift - 12);
+    li_sequence(hi, rv64, put);
+    put(SLLI_BITS, None, i64::from(shift));
+    if lo != 0 {
+        put(ADDI_BITS, None, lo);
+    }
+}
+
+/// Normalize the immediate of an `Ili` encoding, and return it with the RV64 flag.
+fn li_operands(bits: u16, imm: i64) -> (i64, bool) {
+    let imm = if bits & 2 != 0 {
+        i64::from(imm as i32)
+    } else {
+        imm
+    };
+    (imm, bits & 1 != 0)
+}
+
+/// Number of bytes of the sequence emitted by `put_li`.
+pub(super) fn li_size(bits: u16, imm: i64) -> u8 {
+    let (imm, rv64) = li_operands(bits, imm);
+    let mut size = 0;
+    li_sequence(imm, rv64, &mut |_, _, _| size += 4);
+    size
+}
+
+/// Materialize the constant `imm` in `rd`.
+///
+/// Encoding bits: 1 in RV64, and 2 when `imm` is a 32-bit constant.
+fn put_li<CS: CodeSink + ?Sized>(bits: u16, imm: i64, rd: RegUnit, sink: &mut CS) {
+    let (imm, rv64) = li_operands(bits, imm);
+    li_sequence(imm, rv64, &mut |bits, rs1, imm| {
+        let rs1 = rs1.unwrap_or(rd);
+        match bits {
+            LUI_BITS => put_u(bits, imm, rd, sink),
+            SLLI_BITS => put_rshamt(bits, rs1, imm, rd, sink),
+            _ => put_i(bits, rs1, imm, rd, sink),
+        }
+    });
+}
+
+/// Integer extensions as a left shift followed by a logical or arithmetic right shift.
+///
+/// Encoding bits: the shift amount, with bit 6 set for a sign extension.
+fn put_extend<CS: CodeSink + ?Sized>(bits: u16, rs: RegUnit, rd: RegUnit, sink: &mut CS) {
+    let shift = i64::from(bits & 0x3f);
+    let right = if bits & 0x40 != 0 {
+        SRAI_BITS
+    } else {
+        SRLI_BITS
+    };
+    put_rshamt(SLLI_BITS, rs, shift, rd, sink);
+    put_rshamt(right, rd, shift, rd, sink);
+}
+
+/// The canonical illegal instruction `unimp`, an attempt to write to the read-only `cycle` CSR.
+fn put_unimp<CS: CodeSink + ?Sized>(sink: &mut CS) {
+    sink.put4(0xc000_1073);
+}
+
+/// Trap with `code` when `rs` is zero: `bnez rs, 1f; unimp; 1:`.
+fn put_trapz<CS: CodeSink + ?Sized>(rs: RegUnit, code: TrapCode, srcloc: SourceLoc, sink: &mut CS) {
+    put_sb(BNE_BITS, 8, rs, 0, sink);
+    sink.trap(code, srcloc);
+    put_unimp(sink);
+}
+
+/// Trap when a signed division of `rs1` by `rs2` of type `ty` overflows, which only happens for
+/// INT_MIN / -1.
+fn put_sdiv_overflow_check<CS: CodeSink + ?Sized>(
+    ty: Type,
+    rs1: RegUnit,
+    rs2: RegUnit,
+    srcloc: SourceLoc,
+    sink: &mut CS,
+) {
+    // li x31, -1; bne rs2, x31, 1f
+    put_i(ADDI_BITS, 0, -1, SCRATCH_REG, sink);
+    put_sb(BNE_BITS, 16, rs2, SCRATCH_REG, sink);
+    // Build INT_MIN, sign-extended for 32-bit divisions in RV64.
+    if ty == types::I32 {
+        put_u(LUI_BITS, -0x8000_0000, SCRATCH_REG, sink);
+    } else {
+        put_rshamt(SLLI_BITS, SCRATCH_REG, 63, SCRATCH_REG, sink);
+    }
+    // bne rs1, x31, 1f; unimp; 1:
+    put_sb(BNE_BITS, 8, rs1, SCRATCH_REG, sink);
+    sink.trap(TrapCode::IntegerOverflow, srcloc);
+    put_unimp(sink);
+}
+
+/// Compute a PC-relative address with an `auipc` and `addi` pair.
+fn put_pcrel_addr<CS: CodeSink + ?Sized>(rd: RegUnit, disp: i64, sink: &mut CS) {
+    let (hi, lo) = split_imm32(disp as i32);
+    put_u(AUIPC_BITS, hi, rd, sink);
+    put_i(ADDI_BITS, rd, lo, rd, sink);
+}
+
+/// Load the `size`-byte literal that follows this sequence into `rd` with the load instruction
+/// `bits`, and jump over it: `auipc rd, 0; l{w,d} rd, 12(rd); j 4+size`.
+fn put_literal_load<CS: CodeSink + ?Sized>(bits: u16, size: i64, rd: RegUnit, sink: &mut CS) {
+    put_u(AUIPC_BITS, 0, rd, sink);
+    put_i(bits, rd, 12, rd, sink);
+    put_uj(JAL_BITS, 4 + size, 0, sink);
+}
@@ -1,12 +1,15 @@
 //! Encoding tables for RISC-V.
 
 use super::registers::*;
-use crate::ir;
+use crate::ir::{self, Function, Inst};
 use crate::isa;
 use crate::isa::constraints::*;
 use crate::isa::enc_tables::*;
 use crate::isa::encoding::{base_size, RecipeSizing};
+use crate::isa::StackRef;
+use crate::legalizer::expand_fcvt_to_int;
 use crate::predicates;
+use crate::regalloc::RegDiversions;
 
 // Include the generated encoding tables:
 // - `LEVEL1_RV32`
@@ -16,3 +19,61 @@ use crate::predicates;
 // - `INFO`
 include!(concat!(env!("OUT_DIR"), "/encoding-riscv.rs"));
 include!(concat!(env!("OUT_DIR"), "/legalize-riscv.rs"));
+
+/// Number of bytes needed to add the high part of an offset that doesn't fit in an immediate
+/// field to the base register.
+const LARGE_OFFSET_SIZE: u8 = 8;
+
+/// The size of a stack pointer relative access: offsets that don't fit in the 12-bit immediate
+/// need a `lui` and an `add` first.
+fn size_plus_maybe_offset_for_stack_slot(
+    sizing: &RecipeSizing,
+    inst: Inst,
+    divert: &RegDiversions,
+    func: &Function,
+) -> u8 {
+    let offset = match func.dfg[inst] {
+        ir::InstructionData::RegSpill { dst, .. } => StackRef::sp(dst, &func.stack_slots).offset,
+        ir::InstructionData::RegFill { src, .. } => StackRef::sp(src, &func.stack_slots).offset,
+        ir::InstructionData::Unary { arg, .. } => {
+            // A spill has its result on the stack, and a fill its argument.
+            let result = func.dfg.first_result(inst);
+            let ss = match func.locations[result] {
+                ir::ValueLoc::Stack(ss) => ss,
+                _ => divert.stack(arg, &func.locations),
+            };
+            StackRef::sp(ss, &func.stack_slots).offset
+        }
+        ir::InstructionData::StackLoad {
+            stack_slot, offset, ..
+        }
+        | ir::InstructionData::StackStore {
+            stack_slot, offset, ..
+        } => {
+            let imm: i32 = offset.into();
+            StackRef::sp(stack_slot, &func.stack_slots)
+                .offset
+                .checked_add(imm)
+                .unwrap()
+        }
+        _ => panic!(
+            "Unexpected stack access {}",
+            func.dfg.display_inst(inst, None)
+        ),
+    };
+    if predicates::is_signed_int(offset, 12, 0) {
+        sizing.base_size
+    } else {
+        sizing.base_size + LARGE_OFFSET_SIZE
+    }
+}
+
+/// The size of the instruction sequence materializing an integer constant.
+fn size_for_li(_sizing: &RecipeSizing, inst: Inst, _divert: &RegDiversions, func: &Function) -> u8 {
+    match func.dfg[inst] {
+        ir::InstructionData::UnaryImm { imm, .. } => {
+            super::binemit::li_size(func.encodings[inst].bits(), imm.into())
+        }
+        _ => panic!("Unexpected constant {}", func.dfg.display_inst(inst, None)),
+    }
+}
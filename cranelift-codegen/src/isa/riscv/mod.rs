@@ -13,8 +13,10 @@ use crate::binemit::{emit_function, MemoryCodeSink};
 use crate::ir;
 use crate::isa::enc_tables::{self as shared_enc_tables, lookup_enclist, Encodings};
 use crate::isa::Builder as IsaBuilder;
-use crate::isa::{EncInfo, RegClass, RegInfo, TargetIsa};
+use crate::isa::{EncInfo, RegClass, RegInfo, RegUnit, TargetIsa};
 use crate::regalloc;
+use crate::result::CodegenResult;
+use crate::timing;
 use core::fmt;
 use std::boxed::Box;
 use target_lexicon::{PointerWidth, Triple};
@@ -107,6 +109,22 @@ impl TargetIsa for Isa {
         abi::allocatable_registers(func, &self.isa_flags)
     }
 
+    fn prologue_epilogue(&self, func: &mut ir::Function) -> CodegenResult<()> {
+        let _tt = timing::prologue_epilogue();
+        abi::prologue_epilogue(func, self, &self.isa_flags)
+    }
+
+    fn map_dwarf_register(&self, reg: RegUnit) -> Option<u16> {
+        // The RISC-V DWARF numbers are x0-x31 as 0-31, and f0-f31 as 32-63.
+        let bank = registers::INFO.bank_containing_regunit(reg)?;
+        let index = reg - bank.first_unit;
+        match bank.name {
+            "IntRegs" => Some(index),
+            "FloatRegs" => Some(32 + index),
+            _ => None,
+        }
+    }
+
     #[cfg(feature = "testing_hooks")]
     fn emit_inst(
         &self,
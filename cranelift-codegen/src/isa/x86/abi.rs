@@ -1,9 +1,12 @@
 //! x86 ABI implementation.
 
 use super::super::settings as shared_settings;
+use super::fde::emit_fde;
 use super::registers::{FPR, GPR, RU};
 use super::settings as isa_settings;
+use super::unwind::UnwindInfo;
 use crate::abi::{legalize_args, ArgAction, ArgAssigner, ValueConversion};
+use crate::binemit::{FrameUnwindKind, FrameUnwindSink};
 use crate::cursor::{Cursor, CursorPosition, EncCursor};
 use crate::ir;
 use crate::ir::immediates::Imm64;
@@ -651,3 +654,28 @@ fn insert_common_epilogue(
         pos.func.dfg.append_inst_arg(inst, csr_ret);
     }
 }
+
+/// Emit the unwind information of `kind` for the frame of `func`, if it has any.
+pub fn emit_unwind_info(
+    func: &ir::Function,
+    isa: &dyn TargetIsa,
+    kind: FrameUnwindKind,
+    sink: &mut dyn FrameUnwindSink,
+) {
+    // Only the 64-bit prologues are described.
+    if isa.triple().pointer_width().unwrap() != PointerWidth::U64 {
+        return;
+    }
+
+    match (kind, func.signature.call_conv) {
+        (FrameUnwindKind::Fastcall, CallConv::WindowsFastcall) => {
+            if let Some(info) = UnwindInfo::try_from_func(func, isa, Some(RU::rbp as RegUnit)) {
+                info.emit(sink);
+            }
+        }
+        (FrameUnwindKind::Libunwind, CallConv::Fast)
+        | (FrameUnwindKind::Libunwind, CallConv::Cold)
+        | (FrameUnwindKind::Libunwind, CallConv::SystemV) => emit_fde(func, isa, sink),
+        _ => {}
+    }
+}
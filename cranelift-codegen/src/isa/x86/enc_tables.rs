@@ -14,6 +14,7 @@ use crate::isa::RegUnit;
 use crate::isa::{self, TargetIsa};
 use crate::predicates;
 use crate::regalloc::RegDiversions;
+use std::vec::Vec;
 
 include!(concat!(env!("OUT_DIR"), "/encoding-x86.rs"));
 include!(concat!(env!("OUT_DIR"), "/legalize-x86.rs"));
@@ -893,3 +894,228 @@ fn expand_fcvt_to_uint_sat(
     cfg.recompute_ebb(pos.func, uint_large_ebb);
     cfg.recompute_ebb(pos.func, done);
 }
+
+/// Build a vector constant of type `ty` from its bytes, lane 0 first.
+fn vector_constant(pos: &mut FuncCursor, ty: ir::Type, bytes: Vec<u8>) -> ir::Value {
+    debug_assert_eq!(bytes.len(), ty.bytes() as usize);
+    let handle = pos.func.dfg.constants.insert(bytes);
+    pos.ins().vconst(ty, handle)
+}
+
+/// Build a vector constant of type `ty` with every lane holding `lane`; only the low
+/// `ty.lane_bits()` bits of `lane` are used.
+fn splat_constant(pos: &mut FuncCursor, ty: ir::Type, lane: u64) -> ir::Value {
+    let lane_bytes = ty.lane_type().bytes() as usize;
+    let bytes = (0..ty.lane_count())
+        .flat_map(|_| lane.to_le_bytes()[..lane_bytes].to_vec())
+        .collect();
+    vector_constant(pos, ty, bytes)
+}
+
+/// Expand the `shuffle` instruction using `x86_pshufb`, which can only select bytes from a single
+/// register. Each operand is shuffled separately with a mask that clears the lanes coming from the
+/// other operand, and the two results are combined.
+fn expand_shuffle(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    _cfg: &mut ControlFlowGraph,
+    _isa: &dyn TargetIsa,
+) {
+    let (x, y, mask) = match func.dfg[inst] {
+        ir::InstructionData::Shuffle {
+            opcode: ir::Opcode::Shuffle,
+            args,
+            mask,
+        } => (args[0], args[1], func.dfg.constants.get(mask).clone()),
+        _ => panic!("Expected shuffle: {}", func.dfg.display_inst(inst, None)),
+    };
+    let ty = func.dfg.ctrl_typevar(inst);
+
+    // PSHUFB clears the destination lane when the high bit of the mask byte is set.
+    const ZERO_LANE: u8 = 0x80;
+    let x_mask: Vec<u8> = mask
+        .iter()
+        .map(|&lane| if lane < 16 { lane } else { ZERO_LANE })
+        .collect();
+    let y_mask: Vec<u8> = mask
+        .iter()
+        .map(|&lane| {
+            if lane >= 16 && lane < 32 {
+                lane - 16
+            } else {
+                ZERO_LANE
+            }
+        })
+        .collect();
+    let uses_x = x_mask.iter().any(|&lane| lane != ZERO_LANE);
+    let uses_y = y_mask.iter().any(|&lane| lane != ZERO_LANE);
+
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+
+    if uses_y && !uses_x {
+        let y_mask = vector_constant(&mut pos, ty, y_mask);
+        pos.func.dfg.replace(inst).x86_pshufb(y, y_mask);
+        return;
+    }
+
+    let x_mask = vector_constant(&mut pos, ty, x_mask);
+    if !uses_y {
+        pos.func.dfg.replace(inst).x86_pshufb(x, x_mask);
+        return;
+    }
+
+    let x_shuffled = pos.ins().x86_pshufb(x, x_mask);
+    let y_mask = vector_constant(&mut pos, ty, y_mask);
+    let y_shuffled = pos.ins().x86_pshufb(y, y_mask);
+    pos.func.dfg.replace(inst).bor(x_shuffled, y_shuffled);
+}
+
+/// Expand the `swizzle` instruction using `x86_pshufb`. Out-of-range lane indices must produce
+/// zero; a saturating add of 0x70 keeps the valid indices in the low four bits while setting the
+/// high bit, which makes PSHUFB clear the lane, for all indices above 15.
+fn expand_swizzle(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    _cfg: &mut ControlFlowGraph,
+    _isa: &dyn TargetIsa,
+) {
+    let (x, y) = match func.dfg[inst] {
+        ir::InstructionData::Binary {
+            opcode: ir::Opcode::Swizzle,
+            args,
+        } => (args[0], args[1]),
+        _ => panic!("Expected swizzle: {}", func.dfg.display_inst(inst, None)),
+    };
+    let ty = func.dfg.ctrl_typevar(inst);
+
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+
+    let bias = splat_constant(&mut pos, ty, 0x70);
+    let indices = pos.ins().uadd_sat(y, bias);
+    pos.func.dfg.replace(inst).x86_pshufb(x, indices);
+}
+
+/// Expand the vector `icmp` condition codes that have no direct SSE encoding in terms of the `eq`
+/// and `sgt` comparisons, which map to PCMPEQ* and PCMPGT*.
+fn expand_simd_icmp(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    _cfg: &mut ControlFlowGraph,
+    _isa: &dyn TargetIsa,
+) {
+    let (orig_cond, orig_x, orig_y) = match func.dfg[inst] {
+        ir::InstructionData::IntCompare {
+            opcode: ir::Opcode::Icmp,
+            cond,
+            args,
+        } => (cond, args[0], args[1]),
+        _ => panic!("Expected icmp: {}", func.dfg.display_inst(inst, None)),
+    };
+    let ty = func.dfg.value_type(orig_x);
+    if !ty.is_vector() {
+        panic!("Can't legalize {}", func.dfg.display_inst(inst, None));
+    }
+
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+
+    // Unsigned comparisons become signed comparisons once the sign bit of each lane is flipped.
+    let (mut x, mut y) = (orig_x, orig_y);
+    let signed_cond = match orig_cond {
+        IntCC::UnsignedLessThan => Some(IntCC::SignedLessThan),
+        IntCC::UnsignedGreaterThanOrEqual => Some(IntCC::SignedGreaterThanOrEqual),
+        IntCC::UnsignedGreaterThan => Some(IntCC::SignedGreaterThan),
+        IntCC::UnsignedLessThanOrEqual => Some(IntCC::SignedLessThanOrEqual),
+        _ => None,
+    };
+    let cond = match signed_cond {
+        Some(signed_cond) => {
+            let sign_bits = splat_constant(&mut pos, ty, 1u64 << (ty.lane_bits() - 1));
+            x = pos.ins().bxor(x, sign_bits);
+            y = pos.ins().bxor(y, sign_bits);
+            signed_cond
+        }
+        None => orig_cond,
+    };
+
+    // Express the condition as `eq` or `sgt`, possibly with swapped operands, and whether the
+    // result needs to be inverted.
+    let (cond, x, y, invert) = match cond {
+        IntCC::Equal => (IntCC::Equal, x, y, false),
+        IntCC::NotEqual => (IntCC::Equal, x, y, true),
+        IntCC::SignedGreaterThan => (IntCC::SignedGreaterThan, x, y, false),
+        IntCC::SignedLessThan => (IntCC::SignedGreaterThan, y, x, false),
+        IntCC::SignedGreaterThanOrEqual => (IntCC::SignedGreaterThan, y, x, true),
+        IntCC::SignedLessThanOrEqual => (IntCC::SignedGreaterThan, x, y, true),
+        _ => panic!("Can't legalize {}", pos.func.dfg.display_inst(inst, None)),
+    };
+
+    if invert {
+        let cmp = pos.ins().icmp(cond, x, y);
+        let ones = splat_constant(&mut pos, ty.as_bool(), u64::max_value());
+        pos.func.dfg.replace(inst).bxor(cmp, ones);
+    } else if (cond, x, y) != (orig_cond, orig_x, orig_y) {
+        pos.func.dfg.replace(inst).icmp(cond, x, y);
+    } else {
+        // The comparison is already in its simplest form; it only lacks an encoding because the
+        // required SSE version is not enabled.
+        panic!(
+            "{} needs a more recent SSE version",
+            pos.func.dfg.display_inst(inst, None)
+        );
+    }
+}
+
+/// Expand a vector `fneg` by flipping the sign bit of each lane.
+fn expand_simd_fneg(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    _cfg: &mut ControlFlowGraph,
+    _isa: &dyn TargetIsa,
+) {
+    let x = match func.dfg[inst] {
+        ir::InstructionData::Unary {
+            opcode: ir::Opcode::Fneg,
+            arg,
+        } => arg,
+        _ => panic!("Expected fneg: {}", func.dfg.display_inst(inst, None)),
+    };
+    let ty = func.dfg.value_type(x);
+    if !ty.is_vector() {
+        panic!("Can't legalize {}", func.dfg.display_inst(inst, None));
+    }
+
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+
+    let sign_bits = splat_constant(&mut pos, ty, 1u64 << (ty.lane_bits() - 1));
+    pos.func.dfg.replace(inst).bxor(x, sign_bits);
+}
+
+/// Expand a vector `fabs` by clearing the sign bit of each lane.
+fn expand_simd_fabs(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    _cfg: &mut ControlFlowGraph,
+    _isa: &dyn TargetIsa,
+) {
+    let x = match func.dfg[inst] {
+        ir::InstructionData::Unary {
+            opcode: ir::Opcode::Fabs,
+            arg,
+        } => arg,
+        _ => panic!("Expected fabs: {}", func.dfg.display_inst(inst, None)),
+    };
+    let ty = func.dfg.value_type(x);
+    if !ty.is_vector() {
+        panic!("Can't legalize {}", func.dfg.display_inst(inst, None));
+    }
+
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+
+    let sign_bits = splat_constant(&mut pos, ty, 1u64 << (ty.lane_bits() - 1));
+    pos.func.dfg.replace(inst).band_not(x, sign_bits);
+}
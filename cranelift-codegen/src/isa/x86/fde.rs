@@ -0,0 +1,253 @@
+//! Call frame information for x86-64 System V, in the `.eh_frame` format used by libunwind and
+//! libgcc.
+//!
+//! The CIE and FDE are encoded by hand as described by the System V x86-64 psABI and the LSB
+//! `.eh_frame` specification. The FDE refers to the function with an absolute 8-byte address that
+//! is supplied through an `Abs8` relocation.
+
+use super::registers::{INFO, RU};
+use crate::binemit::{CodeOffset, FrameUnwindSink, Reloc};
+use crate::ir::{Function, InstructionData, Opcode, ValueLoc};
+use crate::isa::{RegUnit, TargetIsa};
+use std::vec::Vec;
+
+/// DWARF call frame instructions.
+const DW_CFA_ADVANCE_LOC: u8 = 0x40;
+const DW_CFA_OFFSET: u8 = 0x80;
+const DW_CFA_NOP: u8 = 0x00;
+const DW_CFA_ADVANCE_LOC1: u8 = 0x02;
+const DW_CFA_ADVANCE_LOC2: u8 = 0x03;
+const DW_CFA_ADVANCE_LOC4: u8 = 0x04;
+const DW_CFA_REMEMBER_STATE: u8 = 0x0a;
+const DW_CFA_RESTORE_STATE: u8 = 0x0b;
+const DW_CFA_DEF_CFA: u8 = 0x0c;
+const DW_CFA_DEF_CFA_REGISTER: u8 = 0x0d;
+const DW_CFA_DEF_CFA_OFFSET: u8 = 0x0e;
+
+/// Pointer encoding of the FDE address: an absolute 8-byte pointer.
+const DW_EH_PE_ABSPTR: u8 = 0x00;
+
+/// DWARF register number of the return address.
+const DW_RETURN_ADDRESS: u8 = 16;
+
+/// DWARF register numbers of the GPRs, indexed by `RU`.
+const DW_GPRS: [u8; 16] = [0, 2, 1, 3, 7, 6, 4, 5, 8, 9, 10, 11, 12, 13, 14, 15];
+
+/// The data alignment factor. Saved registers are at multiples of 8 bytes below the CFA.
+const DATA_ALIGN: i32 = -8;
+
+/// Map a GPR to its DWARF register number.
+fn dwarf_reg(reg: RegUnit) -> u8 {
+    DW_GPRS[reg as usize]
+}
+
+/// Map a register to its DWARF register number, as assigned by the System V x86-64 psABI.
+pub fn map_dwarf_register(reg: RegUnit) -> Option<u16> {
+    let bank = INFO.bank_containing_regunit(reg)?;
+    let index = reg - bank.first_unit;
+    match bank.name {
+        "IntRegs" => Some(DW_GPRS[index as usize].into()),
+        // %xmm0-15 are numbered 17-32.
+        "FloatRegs" => Some(17 + index),
+        _ => None,
+    }
+}
+
+fn put_uleb128(buf: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf.push(byte);
+            return;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+fn put_sleb128(buf: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            buf.push(byte);
+            return;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+/// Pad a CIE or FDE that starts at `start` in `buf` with `DW_CFA_nop` to a multiple of 8 bytes,
+/// and fill in its length field.
+fn finish_entry(buf: &mut Vec<u8>, start: usize) {
+    while (buf.len() - start) % 8 != 0 {
+        buf.push(DW_CFA_NOP);
+    }
+    let length = (buf.len() - start - 4) as u32;
+    buf[start..start + 4].copy_from_slice(&length.to_le_bytes());
+}
+
+/// Call frame instructions of an FDE.
+struct CfaProgram {
+    insts: Vec<u8>,
+    loc: CodeOffset,
+}
+
+impl CfaProgram {
+    /// Advance the location to `offset` in the function.
+    fn advance_to(&mut self, offset: CodeOffset) {
+        let delta = offset - self.loc;
+        if delta == 0 {
+            return;
+        } else if delta < 0x40 {
+            self.insts.push(DW_CFA_ADVANCE_LOC | delta as u8);
+        } else if delta <= 0xff {
+            self.insts.push(DW_CFA_ADVANCE_LOC1);
+            self.insts.push(delta as u8);
+        } else if delta <= 0xffff {
+            self.insts.push(DW_CFA_ADVANCE_LOC2);
+            self.insts.extend_from_slice(&(delta as u16).to_le_bytes());
+        } else {
+            self.insts.push(DW_CFA_ADVANCE_LOC4);
+            self.insts.extend_from_slice(&delta.to_le_bytes());
+        }
+        self.loc = offset;
+    }
+
+    /// The CFA is now `reg + offset`.
+    fn def_cfa(&mut self, reg: RegUnit, offset: u32) {
+        self.insts.push(DW_CFA_DEF_CFA);
+        put_uleb128(&mut self.insts, dwarf_reg(reg).into());
+        put_uleb128(&mut self.insts, offset.into());
+    }
+
+    /// The CFA is now `reg` plus the current offset.
+    fn def_cfa_register(&mut self, reg: RegUnit) {
+        self.insts.push(DW_CFA_DEF_CFA_REGISTER);
+        put_uleb128(&mut self.insts, dwarf_reg(reg).into());
+    }
+
+    /// The CFA is now the current register plus `offset`.
+    fn def_cfa_offset(&mut self, offset: u32) {
+        self.insts.push(DW_CFA_DEF_CFA_OFFSET);
+        put_uleb128(&mut self.insts, offset.into());
+    }
+
+    /// `reg` is saved at `CFA - offset`.
+    fn offset(&mut self, reg: RegUnit, offset: u32) {
+        self.insts.push(DW_CFA_OFFSET | dwarf_reg(reg));
+        put_uleb128(&mut self.insts, (offset as i32 / -DATA_ALIGN) as u64);
+    }
+}
+
+/// Emit a CIE and an FDE describing the frame of `func`, followed by a zero terminator.
+///
+/// The frame is tracked through the instructions inserted by `insert_common_prologue` and
+/// `insert_common_epilogue`: the pushes of the frame pointer and the callee-saved registers, the
+/// frame pointer setup, and the final pop of the frame pointer before each return. Since the CFA
+/// is based on the frame pointer once it is set up, stack pointer adjustments don't matter.
+pub fn emit_fde(func: &Function, isa: &dyn TargetIsa, sink: &mut dyn FrameUnwindSink) {
+    let encinfo = isa.encoding_info();
+    let mut buf = Vec::new();
+
+    // The CIE: on entry, the CFA is `rsp + 8` and the return address is saved at `CFA - 8`.
+    buf.extend_from_slice(&[0; 4]);
+    buf.extend_from_slice(&0u32.to_le_bytes());
+    buf.push(1); // Version.
+    buf.extend_from_slice(b"zR\0");
+    put_uleb128(&mut buf, 1); // Code alignment factor.
+    put_sleb128(&mut buf, DATA_ALIGN.into());
+    put_uleb128(&mut buf, DW_RETURN_ADDRESS.into());
+    put_uleb128(&mut buf, 1); // Augmentation data length.
+    buf.push(DW_EH_PE_ABSPTR);
+    buf.push(DW_CFA_DEF_CFA);
+    put_uleb128(&mut buf, dwarf_reg(RU::rsp as RegUnit).into());
+    put_uleb128(&mut buf, 8);
+    buf.push(DW_CFA_OFFSET | DW_RETURN_ADDRESS);
+    put_uleb128(&mut buf, 1);
+    finish_entry(&mut buf, 0);
+
+    // The call frame instructions of the FDE.
+    let mut cfa = CfaProgram {
+        insts: Vec::new(),
+        loc: 0,
+    };
+    // Distance from the stack pointer to the CFA while the CFA is still based on it.
+    let mut cfa_offset = 8;
+    let mut fp_set = false;
+    let mut in_epilogue = false;
+    let mut code_size = 0;
+
+    let last_inst = func
+        .layout
+        .last_ebb()
+        .and_then(|ebb| func.layout.last_inst(ebb));
+    for ebb in func.layout.ebbs() {
+        for (offset, inst, size) in func.inst_offsets(ebb, &encinfo) {
+            let end = offset + size;
+            code_size = end;
+            match func.dfg[inst] {
+                InstructionData::Unary {
+                    opcode: Opcode::X86Push,
+                    arg,
+                } => {
+                    cfa.advance_to(end);
+                    cfa_offset += 8;
+                    if !fp_set {
+                        cfa.def_cfa_offset(cfa_offset);
+                    }
+                    cfa.offset(func.locations[arg].unwrap_reg(), cfa_offset);
+                }
+                InstructionData::CopySpecial { src, dst, .. }
+                    if src == RU::rsp as RegUnit && dst == RU::rbp as RegUnit =>
+                {
+                    cfa.advance_to(end);
+                    cfa.def_cfa_register(dst);
+                    fp_set = true;
+                }
+                InstructionData::NullAry {
+                    opcode: Opcode::X86Pop,
+                } if func.locations[func.dfg.first_result(inst)]
+                    == ValueLoc::Reg(RU::rbp as RegUnit) =>
+                {
+                    // The frame pointer is restored just before a return. Once it is popped, the
+                    // CFA is back to being based on the stack pointer.
+                    cfa.advance_to(end);
+                    cfa.insts.push(DW_CFA_REMEMBER_STATE);
+                    cfa.def_cfa(RU::rsp as RegUnit, 8);
+                    in_epilogue = true;
+                }
+                _ if in_epilogue && func.dfg[inst].opcode().is_return() => {
+                    // Code following the return is still in the body of the function.
+                    if Some(inst) != last_inst {
+                        cfa.advance_to(end);
+                        cfa.insts.push(DW_CFA_RESTORE_STATE);
+                    }
+                    in_epilogue = false;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // The FDE.
+    let fde_start = buf.len();
+    buf.extend_from_slice(&[0; 4]);
+    // The CIE pointer is the distance back to the CIE, which starts the buffer.
+    let cie_pointer = buf.len() as u32;
+    buf.extend_from_slice(&cie_pointer.to_le_bytes());
+    let pc_begin = buf.len();
+    buf.extend_from_slice(&0u64.to_le_bytes());
+    buf.extend_from_slice(&u64::from(code_size).to_le_bytes());
+    put_uleb128(&mut buf, 0); // Augmentation data length.
+    buf.extend_from_slice(&cfa.insts);
+    finish_entry(&mut buf, fde_start);
+
+    // A zero length terminates the list of entries.
+    buf.extend_from_slice(&[0; 4]);
+
+    let start = sink.len();
+    sink.bytes(&buf);
+    sink.reloc(Reloc::Abs8, start + pc_begin);
+    sink.set_entry_offset(start + fde_start);
+}
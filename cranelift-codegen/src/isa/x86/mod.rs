@@ -3,17 +3,19 @@
 mod abi;
 mod binemit;
 mod enc_tables;
+mod fde;
 mod registers;
 pub mod settings;
+mod unwind;
 
 use super::super::settings as shared_settings;
 #[cfg(feature = "testing_hooks")]
 use crate::binemit::CodeSink;
-use crate::binemit::{emit_function, MemoryCodeSink};
+use crate::binemit::{emit_function, FrameUnwindKind, FrameUnwindSink, MemoryCodeSink};
 use crate::ir;
 use crate::isa::enc_tables::{self as shared_enc_tables, lookup_enclist, Encodings};
 use crate::isa::Builder as IsaBuilder;
-use crate::isa::{EncInfo, RegClass, RegInfo, TargetIsa};
+use crate::isa::{EncInfo, RegClass, RegInfo, RegUnit, TargetIsa};
 use crate::regalloc;
 use crate::result::CodegenResult;
 use crate::timing;
@@ -142,6 +144,23 @@ impl TargetIsa for Isa {
         let _tt = timing::prologue_epilogue();
         abi::prologue_epilogue(func, self)
     }
+
+    fn emit_unwind_info(
+        &self,
+        func: &ir::Function,
+        kind: FrameUnwindKind,
+        sink: &mut dyn FrameUnwindSink,
+    ) {
+        abi::emit_unwind_info(func, self, kind, sink);
+    }
+
+    fn map_dwarf_register(&self, reg: RegUnit) -> Option<u16> {
+        // Only the x86-64 register numbers are supported.
+        if self.pointer_width() != PointerWidth::U64 {
+            return None;
+        }
+        fde::map_dwarf_register(reg)
+    }
 }
 
 impl fmt::Display for Isa {
@@ -0,0 +1,261 @@
+//! Unwind information for x64 Windows.
+//!
+//! See https://docs.microsoft.com/en-us/cpp/build/exception-handling-x64 for a description of the
+//! `UNWIND_INFO` structure emitted here.
+
+use super::registers::RU;
+use crate::binemit::FrameUnwindSink;
+use crate::ir::{ExternalName, FuncRef, Function, InstructionData, LibCall, Opcode};
+use crate::isa::{RegUnit, TargetIsa};
+use std::vec::Vec;
+
+/// Largest stack allocation that can be described by `UWOP_ALLOC_SMALL`.
+const SMALL_ALLOC_MAX_SIZE: u32 = 128;
+
+/// Largest stack allocation that can be described by a 16-bit `UWOP_ALLOC_LARGE`.
+const LARGE_ALLOC_16BIT_MAX_SIZE: u32 = 524_280;
+
+/// Unwind operation codes.
+const UWOP_PUSH_NONVOL: u8 = 0;
+const UWOP_ALLOC_LARGE: u8 = 1;
+const UWOP_ALLOC_SMALL: u8 = 2;
+const UWOP_SET_FPREG: u8 = 3;
+
+/// The unwind codes needed to describe the prologues generated by the x86 ISA.
+///
+/// The `offset` of each code is the offset of the end of the corresponding prologue instruction.
+/// The register numbers used by Windows happen to match the `RU` enumeration of the GPRs.
+#[derive(Debug, PartialEq, Eq)]
+enum UnwindCode {
+    PushRegister { offset: u8, reg: RegUnit },
+    StackAlloc { offset: u8, size: u32 },
+    SetFramePointer { offset: u8 },
+}
+
+impl UnwindCode {
+    /// Number of 16-bit slots used by this code.
+    fn node_count(&self) -> usize {
+        match *self {
+            UnwindCode::StackAlloc { size, .. } if size > LARGE_ALLOC_16BIT_MAX_SIZE => 3,
+            UnwindCode::StackAlloc { size, .. } if size > SMALL_ALLOC_MAX_SIZE => 2,
+            _ => 1,
+        }
+    }
+
+    fn emit(&self, sink: &mut dyn FrameUnwindSink) {
+        match *self {
+            UnwindCode::PushRegister { offset, reg } => {
+                sink.bytes(&[offset, (reg as u8) << 4 | UWOP_PUSH_NONVOL]);
+            }
+            UnwindCode::StackAlloc { offset, size } => {
+                // Stack allocations are described in units of 8 bytes.
+                debug_assert!(size >= 8 && size % 8 == 0, "bad stack allocation {}", size);
+                if size <= SMALL_ALLOC_MAX_SIZE {
+                    sink.bytes(&[offset, (((size - 8) / 8) as u8) << 4 | UWOP_ALLOC_SMALL]);
+                } else if size <= LARGE_ALLOC_16BIT_MAX_SIZE {
+                    sink.bytes(&[offset, UWOP_ALLOC_LARGE]);
+                    sink.bytes(&((size / 8) as u16).to_le_bytes());
+                } else {
+                    sink.bytes(&[offset, 1 << 4 | UWOP_ALLOC_LARGE]);
+                    sink.bytes(&size.to_le_bytes());
+                }
+            }
+            UnwindCode::SetFramePointer { offset } => {
+                sink.bytes(&[offset, UWOP_SET_FPREG]);
+            }
+        }
+    }
+}
+
+/// Is `func_ref` the stack probe function inserted by the prologue?
+fn is_probestack(func: &Function, func_ref: FuncRef) -> bool {
+    func.dfg.ext_funcs[func_ref].name == ExternalName::LibCall(LibCall::Probestack)
+}
+
+/// Windows x64 unwind information for a single function.
+#[derive(Debug, PartialEq, Eq)]
+pub struct UnwindInfo {
+    prologue_size: u8,
+    frame_register: Option<RegUnit>,
+    unwind_codes: Vec<UnwindCode>,
+}
+
+impl UnwindInfo {
+    /// Compute the unwind information from the prologue at the top of the entry EBB.
+    ///
+    /// The prologue is the sequence of instructions inserted by `insert_common_prologue`: an
+    /// optional stack check, the frame pointer and callee-saved register pushes, the frame
+    /// pointer setup, and the stack allocation, possibly through a stack probe call.
+    ///
+    /// Returns `None` if the function has no prologue.
+    pub fn try_from_func(
+        func: &Function,
+        isa: &dyn TargetIsa,
+        frame_register: Option<RegUnit>,
+    ) -> Option<Self> {
+        let entry = func.layout.entry_block()?;
+
+        // The size passed to a stack probe is materialized in a register first.
+        let mut probe_size = None;
+        let mut frame_set = false;
+        let mut prologue_size = 0;
+        let mut unwind_codes = Vec::new();
+
+        for (offset, inst, size) in func.inst_offsets(entry, &isa.encoding_info()) {
+            // Unwind codes record the offset of the end of each prologue instruction.
+            let end = offset + size;
+            let mut code = None;
+            match func.dfg[inst] {
+                InstructionData::Unary {
+                    opcode: Opcode::X86Push,
+                    arg,
+                } => {
+                    code = Some(UnwindCode::PushRegister {
+                        offset: end as u8,
+                        reg: func.locations[arg].unwrap_reg(),
+                    });
+                }
+                InstructionData::CopySpecial { src, dst, .. }
+                    if src == RU::rsp as RegUnit && Some(dst) == frame_register =>
+                {
+                    frame_set = true;
+                    code = Some(UnwindCode::SetFramePointer { offset: end as u8 });
+                }
+                InstructionData::UnaryImm {
+                    opcode: Opcode::AdjustSpDownImm,
+                    imm,
+                } if frame_set => {
+                    let imm: i64 = imm.into();
+                    code = Some(UnwindCode::StackAlloc {
+                        offset: end as u8,
+                        size: imm as u32,
+                    });
+                }
+                InstructionData::UnaryImm {
+                    opcode: Opcode::Iconst,
+                    imm,
+                } if frame_set && probe_size.is_none() => {
+                    let imm: i64 = imm.into();
+                    probe_size = Some(imm as u32);
+                }
+                InstructionData::FuncAddr { func_ref, .. }
+                    if probe_size.is_some() && is_probestack(func, func_ref) => {}
+                InstructionData::Call { func_ref, .. }
+                    if probe_size.is_some() && is_probestack(func, func_ref) =>
+                {
+                    if isa.flags().probestack_func_adjusts_sp() {
+                        code = Some(UnwindCode::StackAlloc {
+                            offset: end as u8,
+                            size: probe_size.unwrap(),
+                        });
+                    }
+                }
+                InstructionData::CallIndirect { .. } if probe_size.is_some() => {
+                    if isa.flags().probestack_func_adjusts_sp() {
+                        code = Some(UnwindCode::StackAlloc {
+                            offset: end as u8,
+                            size: probe_size.unwrap(),
+                        });
+                    }
+                }
+                InstructionData::Unary {
+                    opcode: Opcode::AdjustSpDown,
+                    ..
+                } if probe_size.is_some() => {
+                    code = Some(UnwindCode::StackAlloc {
+                        offset: end as u8,
+                        size: probe_size.unwrap(),
+                    });
+                }
+                // The stack check comes before the frame pointer is pushed.
+                InstructionData::Unary {
+                    opcode: Opcode::Copy,
+                    ..
+                }
+                | InstructionData::BinaryImm {
+                    opcode: Opcode::IaddImm,
+                    ..
+                }
+                | InstructionData::Unary {
+                    opcode: Opcode::IfcmpSp,
+                    ..
+                }
+                | InstructionData::IntCondTrap { .. }
+                    if unwind_codes.is_empty() => {}
+                _ => break,
+            }
+
+            if let Some(code) = code {
+                // Unwind code offsets are 8 bits, so the prologue must fit in 255 bytes.
+                assert!(end <= 255, "Windows x64 prologues can't exceed 255 bytes");
+                prologue_size = end as u8;
+                let is_alloc = match code {
+                    UnwindCode::StackAlloc { .. } => true,
+                    _ => false,
+                };
+                unwind_codes.push(code);
+
+                // The stack allocation is the last part of the prologue.
+                if is_alloc {
+                    break;
+                }
+            }
+        }
+
+        if unwind_codes.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            prologue_size,
+            frame_register,
+            unwind_codes,
+        })
+    }
+
+    /// Number of 16-bit unwind code slots.
+    fn node_count(&self) -> usize {
+        self.unwind_codes.iter().map(UnwindCode::node_count).sum()
+    }
+
+    /// Get the size of the emitted unwind information in bytes.
+    pub fn emit_size(&self) -> usize {
+        // The fixed part is 4 bytes, and the array of unwind codes is padded to an even number of
+        // slots. There are no exception handlers or chained unwind information.
+        let node_count = self.node_count();
+        4 + 2 * (node_count + (node_count & 1))
+    }
+
+    /// Emit the `UNWIND_INFO` structure to `sink`.
+    pub fn emit(&self, sink: &mut dyn FrameUnwindSink) {
+        const UNWIND_INFO_VERSION: u8 = 1;
+
+        let size = self.emit_size();
+        let start = sink.len();
+        assert_eq!(start % 4, 0, "UNWIND_INFO must be 4-byte aligned");
+        sink.reserve(size);
+
+        let node_count = self.node_count();
+        assert!(node_count <= 255, "too many unwind codes");
+
+        // Version and flags, prologue size, number of codes, and frame register and offset. The
+        // frame pointer always points at the saved frame pointer, so the scaled offset is 0.
+        let frame_register = self.frame_register.map_or(0, |reg| reg as u8);
+        sink.bytes(&[
+            UNWIND_INFO_VERSION,
+            self.prologue_size,
+            node_count as u8,
+            frame_register,
+        ]);
+
+        // Unwind codes are sorted by descending prologue offset.
+        for code in self.unwind_codes.iter().rev() {
+            code.emit(sink);
+        }
+        if node_count & 1 == 1 {
+            sink.bytes(&[0, 0]);
+        }
+
+        debug_assert_eq!(sink.len() - start, size);
+    }
+}
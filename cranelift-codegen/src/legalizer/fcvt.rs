@@ -0,0 +1,93 @@
+//! Legalization of float to integer conversions.
+//!
+//! This module exports the `expand_fcvt_to_int` function which transforms the trapping
+//! `fcvt_to_sint` and `fcvt_to_uint` instructions into explicit checks followed by a saturating
+//! conversion.
+
+use crate::cursor::{Cursor, FuncCursor};
+use crate::flowgraph::ControlFlowGraph;
+use crate::ir::condcodes::FloatCC;
+use crate::ir::immediates::{Ieee32, Ieee64};
+use crate::ir::{self, InstBuilder};
+use crate::isa::TargetIsa;
+
+/// Expand `fcvt_to_sint` and `fcvt_to_uint` into the range checks and a saturating conversion.
+///
+/// This is for ISAs whose conversion instructions saturate instead of trapping, so NaNs and out
+/// of range values are tested first.
+pub fn expand_fcvt_to_int(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    _cfg: &mut ControlFlowGraph,
+    _isa: &dyn TargetIsa,
+) {
+    let (x, signed) = match func.dfg[inst] {
+        ir::InstructionData::Unary {
+            opcode: ir::Opcode::FcvtToSint,
+            arg,
+        } => (arg, true),
+        ir::InstructionData::Unary {
+            opcode: ir::Opcode::FcvtToUint,
+            arg,
+        } => (arg, false),
+        _ => panic!(
+            "Need fcvt_to_sint or fcvt_to_uint: {}",
+            func.dfg.display_inst(inst, None)
+        ),
+    };
+    let xty = func.dfg.value_type(x);
+    let ty = func.dfg.value_type(func.dfg.first_result(inst));
+    let output_bits = ty.lane_bits();
+
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+
+    // Check for NaN.
+    let is_nan = pos.ins().fcmp(FloatCC::Unordered, x, x);
+    pos.ins()
+        .trapnz(is_nan, ir::TrapCode::BadConversionToInteger);
+
+    // Check the lower bound. For signed conversions, determine the smallest floating point
+    // number that converts to INT_MIN. Values in (-1, 0] convert to 0 in the unsigned case.
+    let mut overflow_cc = FloatCC::LessThan;
+    let flimit = match xty {
+        ir::types::F32 => pos.ins().f32const(if !signed {
+            overflow_cc = FloatCC::LessThanOrEqual;
+            Ieee32::with_float(-1.0)
+        } else if output_bits < 32 {
+            overflow_cc = FloatCC::LessThanOrEqual;
+            Ieee32::fcvt_to_sint_negative_overflow(output_bits)
+        } else {
+            Ieee32::pow2(output_bits - 1).neg()
+        }),
+        ir::types::F64 => pos.ins().f64const(if !signed {
+            overflow_cc = FloatCC::LessThanOrEqual;
+            Ieee64::with_float(-1.0)
+        } else if output_bits < 64 {
+            overflow_cc = FloatCC::LessThanOrEqual;
+            Ieee64::fcvt_to_sint_negative_overflow(output_bits)
+        } else {
+            Ieee64::pow2(output_bits - 1).neg()
+        }),
+        _ => panic!("Can't convert {}", xty),
+    };
+    let overflow = pos.ins().fcmp(overflow_cc, x, flimit);
+    pos.ins().trapnz(overflow, ir::TrapCode::IntegerOverflow);
+
+    // Check the upper bound, which is a power of two in both cases.
+    let upper_bits = if signed { output_bits - 1 } else { output_bits };
+    let flimit = match xty {
+        ir::types::F32 => pos.ins().f32const(Ieee32::pow2(upper_bits)),
+        ir::types::F64 => pos.ins().f64const(Ieee64::pow2(upper_bits)),
+        _ => panic!("Can't convert {}", xty),
+    };
+    let overflow = pos.ins().fcmp(FloatCC::GreaterThanOrEqual, x, flimit);
+    pos.ins().trapnz(overflow, ir::TrapCode::IntegerOverflow);
+
+    // The value is in range now, so the saturating conversion computes the right result.
+    if signed {
+        pos.func.dfg.replace(inst).fcvt_to_sint_sat(ty, x);
+    } else {
+        pos.func.dfg.replace(inst).fcvt_to_uint_sat(ty, x);
+    }
+}
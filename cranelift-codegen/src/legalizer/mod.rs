@@ -26,6 +26,8 @@ use std::vec::Vec;
 
 mod boundary;
 mod call;
+#[cfg(any(feature = "arm64", feature = "riscv"))]
+mod fcvt;
 mod globalvalue;
 mod heap;
 mod libcall;
@@ -33,6 +35,8 @@ mod split;
 mod table;
 
 use self::call::expand_call;
+#[cfg(any(feature = "arm64", feature = "riscv"))]
+pub(crate) use self::fcvt::expand_fcvt_to_int;
 use self::globalvalue::expand_global_value;
 use self::heap::expand_heap_addr;
 use self::libcall::expand_as_libcall;
@@ -570,6 +574,98 @@ fn expand_stack_store(
     pos.func.dfg.replace(inst).store(mflags, val, addr, 0);
 }
 
+/// Expand atomic read-modify-write instructions into a compare-and-swap loop.
+fn expand_atomic_rmw(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    cfg: &mut ControlFlowGraph,
+    _isa: &dyn TargetIsa,
+) {
+    let (opcode, flags, x, p, offset) = match func.dfg[inst] {
+        ir::InstructionData::Store {
+            opcode,
+            flags,
+            args,
+            offset,
+        } => (opcode, flags, args[0], args[1], offset),
+        _ => panic!("Expected atomic_rmw: {}", func.dfg.display_inst(inst, None)),
+    };
+    let binary_opc = match opcode {
+        ir::Opcode::AtomicRmwAdd => Some(ir::Opcode::Iadd),
+        ir::Opcode::AtomicRmwSub => Some(ir::Opcode::Isub),
+        ir::Opcode::AtomicRmwAnd => Some(ir::Opcode::Band),
+        ir::Opcode::AtomicRmwOr => Some(ir::Opcode::Bor),
+        ir::Opcode::AtomicRmwXor => Some(ir::Opcode::Bxor),
+        ir::Opcode::AtomicRmwXchg => None,
+        _ => panic!("Expected atomic_rmw: {}", func.dfg.display_inst(inst, None)),
+    };
+
+    // Split the EBB around `inst`:
+    //
+    //     v = atomic_rmw_op x, p+off
+    //
+    // Becomes:
+    //
+    //     addr = iadd_imm p, off
+    //     init = atomic_load addr
+    //     jump loop(init)
+    //
+    //   loop(cur):
+    //     new = op cur, x
+    //     prev = atomic_cas cur, new, addr
+    //     retry = icmp ne prev, cur
+    //     brnz retry, loop(prev)
+    //     jump done(prev)
+    //
+    //   done(v):
+    //     ..
+    let old_ebb = func.layout.pp_ebb(inst);
+    let loop_ebb = func.dfg.make_ebb();
+    let done = func.dfg.make_ebb();
+
+    // Move the `inst` result value onto the `done` EBB.
+    let result = func.dfg.first_result(inst);
+    let ty = func.dfg.value_type(result);
+    func.dfg.clear_results(inst);
+    func.dfg.attach_ebb_param(done, result);
+
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+
+    // `atomic_cas` has no offset immediate, so compute the full address up front.
+    let offset: i64 = offset.into();
+    let addr = if offset == 0 {
+        p
+    } else {
+        pos.ins().iadd_imm(p, offset)
+    };
+    let init = pos.ins().atomic_load(ty, flags, addr, 0);
+    pos.ins().jump(loop_ebb, &[init]);
+
+    pos.insert_ebb(loop_ebb);
+    let cur = pos.func.dfg.append_ebb_param(loop_ebb, ty);
+    let new = match binary_opc {
+        Some(opc) => {
+            let new_inst = pos.ins().Binary(opc, ty, cur, x).0;
+            pos.func.dfg.first_result(new_inst)
+        }
+        None => x,
+    };
+    let prev = pos.ins().atomic_cas(flags, cur, new, addr);
+    let retry = pos.ins().icmp(ir::condcodes::IntCC::NotEqual, prev, cur);
+    pos.ins().brnz(retry, loop_ebb, &[prev]);
+
+    // Recycle the original instruction as the loop exit.
+    pos.func.dfg.replace(inst).jump(done, &[prev]);
+
+    pos.next_inst();
+    pos.insert_ebb(done);
+
+    cfg.recompute_ebb(pos.func, old_ebb);
+    cfg.recompute_ebb(pos.func, loop_ebb);
+    cfg.recompute_ebb(pos.func, done);
+}
+
 /// Split a load into two parts before `iconcat`ing the result together.
 fn narrow_load(
     inst: ir::Inst,
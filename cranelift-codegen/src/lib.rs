@@ -64,9 +64,14 @@ pub use cranelift_bforest as bforest;
 pub use cranelift_entity as entity;
 
 pub mod binemit;
+#[cfg(feature = "binary-format")]
+pub mod binary_format;
+#[cfg(feature = "cache")]
+pub mod cache;
 pub mod cfg_printer;
 pub mod cursor;
 pub mod dbg;
+pub mod debuginfo;
 pub mod dominator_tree;
 pub mod flowgraph;
 pub mod ir;
@@ -87,13 +92,17 @@ mod context;
 mod dce;
 mod divconst_magic_numbers;
 mod fx;
+mod inline;
 mod iterators;
 mod legalizer;
 mod licm;
+mod loop_unroll;
+mod loop_unswitch;
 mod nan_canonicalization;
 mod partition_slice;
 mod postopt;
 mod predicates;
+mod redundant_load_elim;
 mod redundant_reload_remover;
 mod ref_slice;
 mod regalloc;
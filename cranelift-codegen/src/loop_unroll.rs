@@ -0,0 +1,474 @@
+//! A loop unrolling pass.
+//!
+//! Loops made of a single EBB are unrolled when the number of times they run is known at compile
+//! time. Such a loop is controlled by a `brz` or `brnz` on an `icmp` or `icmp_imm` comparing an
+//! induction variable with a constant. The induction variable is a parameter of the loop EBB,
+//! initialized with the same `iconst` on every entry into the loop and incremented by `iadd_imm`
+//! on the back edge. The conditional branch either leaves the loop from the middle of the EBB,
+//! which then ends with a `jump` back to itself, or is itself the back edge, followed by a `jump`
+//! out of the loop.
+//!
+//! Short loops are unrolled fully, removing the back edge. Longer loops are unrolled by a factor
+//! which divides their trip count, so that only the first copy of the body needs to test the
+//! condition.
+
+use crate::cursor::{Cursor, FuncCursor};
+use crate::dominator_tree::DominatorTree;
+use crate::entity::SecondaryMap;
+use crate::flowgraph::{BasicBlock, ControlFlowGraph};
+use crate::ir::condcodes::IntCC;
+use crate::ir::{
+    DataFlowGraph, Ebb, Function, Inst, InstBuilder, InstructionData, Opcode, Value, ValueDef,
+};
+use crate::loop_analysis::{Loop, LoopAnalysis};
+use crate::packed_option::PackedOption;
+use crate::timing;
+use std::vec::Vec;
+
+/// The largest number of times a loop EBB is simulated to find its trip count.
+const MAX_TRIP_COUNT: u64 = 1 << 16;
+
+/// The largest number of instructions in an unrolled loop.
+const MAX_UNROLLED_SIZE: u64 = 256;
+
+/// The factors tried when unrolling a loop partially, in order of preference.
+const UNROLL_FACTORS: [u64; 3] = [8, 4, 2];
+
+/// A single-EBB loop whose trip count is known.
+struct Candidate {
+    header: Ebb,
+    /// The conditional branch controlling the loop.
+    branch: Inst,
+    /// The `jump` terminating the loop EBB.
+    terminator: Inst,
+    /// Does `branch` leave the loop, rather than being the back edge?
+    exits_in_middle: bool,
+    /// The number of times the loop EBB is entered each time the loop runs.
+    entries: u64,
+}
+
+/// An operand of the loop condition.
+#[derive(Clone, Copy)]
+enum Term {
+    /// A constant.
+    Const(i64),
+    /// The loop EBB parameter with the given index, in the current iteration.
+    Current(usize),
+    /// The loop EBB parameter with the given index, in the next iteration.
+    Next(usize),
+}
+
+/// Unroll the loops of `func` with a trip count known at compile time.
+///
+/// The control flow graph, dominator tree and loop analysis are recomputed if anything changed.
+pub fn do_loop_unroll(
+    func: &mut Function,
+    cfg: &mut ControlFlowGraph,
+    domtree: &mut DominatorTree,
+    loop_analysis: &mut LoopAnalysis,
+) {
+    let _tt = timing::loop_unroll();
+    debug_assert!(cfg.is_valid());
+    debug_assert!(domtree.is_valid());
+    debug_assert!(loop_analysis.is_valid());
+
+    let candidates: Vec<Candidate> = loop_analysis
+        .loops()
+        .filter_map(|lp| analyze_loop(func, cfg, loop_analysis, lp))
+        .collect();
+
+    let mut changed = false;
+    for candidate in &candidates {
+        let size = func.layout.ebb_insts(candidate.header).count() as u64;
+        if candidate.entries.saturating_mul(size) <= MAX_UNROLLED_SIZE {
+            unroll(func, candidate, candidate.entries, false);
+            changed = true;
+            continue;
+        }
+
+        // Only the first copy of the body tests the condition, so the number of iterations
+        // taking the back edge must be a multiple of the unrolling factor.
+        let iterations = if candidate.exits_in_middle {
+            candidate.entries - 1
+        } else {
+            candidate.entries
+        };
+        if let Some(&factor) = UNROLL_FACTORS
+            .iter()
+            .find(|&&factor| factor * size <= MAX_UNROLLED_SIZE && iterations % factor == 0)
+        {
+            unroll(func, candidate, factor, true);
+            changed = true;
+        }
+    }
+
+    if changed {
+        cfg.compute(func);
+        domtree.compute(func, cfg);
+        loop_analysis.compute(func, cfg, domtree);
+    }
+}
+
+/// Check if the loop `lp` can be unrolled, and find its trip count.
+fn analyze_loop(
+    func: &Function,
+    cfg: &ControlFlowGraph,
+    loop_analysis: &LoopAnalysis,
+    lp: Loop,
+) -> Option<Candidate> {
+    let header = loop_analysis.loop_header(lp);
+    if func
+        .layout
+        .ebbs()
+        .any(|ebb| ebb != header && loop_analysis.is_in_loop(ebb, lp))
+    {
+        return None;
+    }
+
+    // The loop EBB must contain a conditional branch followed by a terminating jump.
+    let mut branches = func
+        .layout
+        .ebb_insts(header)
+        .filter(|&inst| func.dfg[inst].opcode().is_branch());
+    let branch = branches.next()?;
+    let terminator = branches.next()?;
+    if branches.next().is_some() || func.dfg[terminator].opcode() != Opcode::Jump {
+        return None;
+    }
+    let branch_opcode = func.dfg[branch].opcode();
+    if branch_opcode != Opcode::Brz && branch_opcode != Opcode::Brnz {
+        return None;
+    }
+    let exits_in_middle = match (
+        func.dfg[branch].branch_destination() == Some(header),
+        func.dfg[terminator].branch_destination() == Some(header),
+    ) {
+        (false, true) => true,
+        (true, false) => false,
+        _ => return None,
+    };
+    let back_edge = if exits_in_middle { terminator } else { branch };
+
+    // Find the induction variable, compared with a constant by the loop condition.
+    let back_args = func.dfg.inst_variable_args(back_edge);
+    let (cond, lhs, rhs) = loop_condition(func, header, back_args, func.dfg.inst_args(branch)[0])?;
+    let param = match (lhs, rhs) {
+        (Term::Current(param), Term::Const(_))
+        | (Term::Next(param), Term::Const(_))
+        | (Term::Const(_), Term::Current(param))
+        | (Term::Const(_), Term::Next(param)) => param,
+        _ => return None,
+    };
+    let step = induction_step(&func.dfg, header, back_args, param)?;
+    let init = initial_value(func, cfg, header, back_edge, param)?;
+
+    let ty = func.dfg.value_type(func.dfg.ebb_params(header)[param]);
+    if !ty.is_int() || ty.bits() > 64 {
+        return None;
+    }
+    let bits = u32::from(ty.bits());
+    let mask = if bits == 64 { !0 } else { (1 << bits) - 1 };
+
+    // Simulate the loop until it exits.
+    let exit_when_taken = exits_in_middle == (branch_opcode == Opcode::Brnz);
+    let mut current = init as u64 & mask;
+    for entries in 1..=MAX_TRIP_COUNT {
+        let next = current.wrapping_add(step as u64) & mask;
+        let value = |term| match term {
+            Term::Const(imm) => imm as u64 & mask,
+            Term::Current(_) => current,
+            Term::Next(_) => next,
+        };
+        if compare(cond, value(lhs), value(rhs), bits) == exit_when_taken {
+            return Some(Candidate {
+                header,
+                branch,
+                terminator,
+                exits_in_middle,
+                entries,
+            });
+        }
+        current = next;
+    }
+    None
+}
+
+/// Get the condition code and operands of the comparison defining `cond`, if it's done in
+/// `header`.
+fn loop_condition(
+    func: &Function,
+    header: Ebb,
+    back_args: &[Value],
+    cond: Value,
+) -> Option<(IntCC, Term, Term)> {
+    let dfg = &func.dfg;
+    let inst = match dfg.value_def(dfg.resolve_aliases(cond)) {
+        ValueDef::Result(inst, _) if func.layout.inst_ebb(inst) == Some(header) => inst,
+        _ => return None,
+    };
+    match dfg[inst] {
+        InstructionData::IntCompare {
+            opcode: Opcode::Icmp,
+            cond,
+            args,
+        } => Some((
+            cond,
+            classify_operand(dfg, header, back_args, args[0])?,
+            classify_operand(dfg, header, back_args, args[1])?,
+        )),
+        InstructionData::IntCompareImm {
+            opcode: Opcode::IcmpImm,
+            cond,
+            arg,
+            imm,
+        } => Some((
+            cond,
+            classify_operand(dfg, header, back_args, arg)?,
+            Term::Const(imm.into()),
+        )),
+        _ => None,
+    }
+}
+
+/// Describe the operand `value` of the loop condition.
+fn classify_operand(
+    dfg: &DataFlowGraph,
+    header: Ebb,
+    back_args: &[Value],
+    value: Value,
+) -> Option<Term> {
+    let value = dfg.resolve_aliases(value);
+    if let Some(param) = dfg.ebb_params(header).iter().position(|&p| p == value) {
+        return Some(Term::Current(param));
+    }
+    if let Some(param) = back_args
+        .iter()
+        .position(|&arg| dfg.resolve_aliases(arg) == value)
+    {
+        return Some(Term::Next(param));
+    }
+    if let ValueDef::Result(inst, _) = dfg.value_def(value) {
+        if let InstructionData::UnaryImm {
+            opcode: Opcode::Iconst,
+            imm,
+        } = dfg[inst]
+        {
+            return Some(Term::Const(imm.into()));
+        }
+    }
+    None
+}
+
+/// Get the amount the loop EBB parameter `param` is incremented by on the back edge.
+fn induction_step(
+    dfg: &DataFlowGraph,
+    header: Ebb,
+    back_args: &[Value],
+    param: usize,
+) -> Option<i64> {
+    if let ValueDef::Result(inst, _) = dfg.value_def(dfg.resolve_aliases(back_args[param])) {
+        if let InstructionData::BinaryImm {
+            opcode: Opcode::IaddImm,
+            arg,
+            imm,
+        } = dfg[inst]
+        {
+            if dfg.resolve_aliases(arg) == dfg.ebb_params(header)[param] {
+                return Some(imm.into());
+            }
+        }
+    }
+    None
+}
+
+/// Get the constant passed as the loop EBB parameter `param` by every branch into the loop.
+fn initial_value(
+    func: &Function,
+    cfg: &ControlFlowGraph,
+    header: Ebb,
+    back_edge: Inst,
+    param: usize,
+) -> Option<i64> {
+    let mut init = None;
+    for BasicBlock { inst, .. } in cfg.pred_iter(header) {
+        if inst == back_edge {
+            continue;
+        }
+        let arg = func.dfg.inst_variable_args(inst)[param];
+        let imm = match func.dfg.value_def(func.dfg.resolve_aliases(arg)) {
+            ValueDef::Result(def, _) => match func.dfg[def] {
+                InstructionData::UnaryImm {
+                    opcode: Opcode::Iconst,
+                    imm,
+                } => imm.into(),
+                _ => return None,
+            },
+            ValueDef::Param(..) => return None,
+        };
+        match init {
+            None => init = Some(imm),
+            Some(init) if init == imm => {}
+            Some(_) => return None,
+        }
+    }
+    init
+}
+
+/// Evaluate the integer comparison `cond` of the `bits`-bit values `a` and `b`.
+fn compare(cond: IntCC, a: u64, b: u64, bits: u32) -> bool {
+    let signed = |x: u64| ((x << (64 - bits)) as i64) >> (64 - bits);
+    match cond {
+        IntCC::Equal => a == b,
+        IntCC::NotEqual => a != b,
+        IntCC::SignedLessThan => signed(a) < signed(b),
+        IntCC::SignedGreaterThanOrEqual => signed(a) >= signed(b),
+        IntCC::SignedGreaterThan => signed(a) > signed(b),
+        IntCC::SignedLessThanOrEqual => signed(a) <= signed(b),
+        IntCC::UnsignedLessThan => a < b,
+        IntCC::UnsignedGreaterThanOrEqual => a >= b,
+        IntCC::UnsignedGreaterThan => a > b,
+        IntCC::UnsignedLessThanOrEqual => a <= b,
+    }
+}
+
+/// Replace the body of the candidate loop with `copies` copies of it.
+///
+/// If `keep_loop` is set, the first copy keeps the conditional branch and the last one the back
+/// edge. Otherwise, the copies run the whole loop.
+fn unroll(func: &mut Function, candidate: &Candidate, copies: u64, keep_loop: bool) {
+    let header = candidate.header;
+    let insts: Vec<Inst> = func.layout.ebb_insts(header).collect();
+    let split = insts
+        .iter()
+        .position(|&inst| inst == candidate.branch)
+        .expect("the branch is in the loop EBB");
+    let before = &insts[..split];
+    let after = &insts[split + 1..insts.len() - 1];
+    for &inst in &insts {
+        func.layout.remove_inst(inst);
+    }
+
+    let params = func.dfg.ebb_params(header).to_vec();
+    let back_edge = if candidate.exits_in_middle {
+        candidate.terminator
+    } else {
+        candidate.branch
+    };
+
+    // The copies of the original values, in the current copy of the body.
+    let mut map = SecondaryMap::new();
+    // The copies of the original values used after leaving the loop.
+    let mut exit_map = SecondaryMap::new();
+
+    for copy in 0..copies {
+        if copy > 0 {
+            // The parameters of this copy are the back edge arguments of the previous one.
+            let args: Vec<Value> = func
+                .dfg
+                .inst_variable_args(back_edge)
+                .iter()
+                .map(|&arg| map_value(&func.dfg, &map, arg))
+                .collect();
+            for (&param, arg) in params.iter().zip(args) {
+                map[param] = arg.into();
+            }
+        }
+        let last = copy + 1 == copies;
+
+        copy_insts(func, header, before, &mut map);
+        if candidate.exits_in_middle {
+            if keep_loop && copy == 0 {
+                copy_insts(func, header, &[candidate.branch], &mut map);
+                exit_map = map.clone();
+            }
+            if !keep_loop && last {
+                exit_map = map.clone();
+                let destination = func.dfg[candidate.branch]
+                    .branch_destination()
+                    .expect("the branch has a destination");
+                let args: Vec<Value> = func
+                    .dfg
+                    .inst_variable_args(candidate.branch)
+                    .iter()
+                    .map(|&arg| map_value(&func.dfg, &map, arg))
+                    .collect();
+                FuncCursor::new(func)
+                    .at_bottom(header)
+                    .ins()
+                    .jump(destination, &args);
+            } else {
+                copy_insts(func, header, after, &mut map);
+            }
+        }
+    }
+
+    if candidate.exits_in_middle {
+        if keep_loop {
+            copy_insts(func, header, &[candidate.terminator], &mut map);
+        }
+    } else {
+        if keep_loop {
+            copy_insts(func, header, &[candidate.branch], &mut map);
+        }
+        copy_insts(func, header, after, &mut map);
+        exit_map = map.clone();
+        copy_insts(func, header, &[candidate.terminator], &mut map);
+    }
+
+    // Replace the uses of the original values after the loop.
+    let mut pos = FuncCursor::new(func);
+    while let Some(ebb) = pos.next_ebb() {
+        if ebb == header {
+            continue;
+        }
+        while let Some(inst) = pos.next_inst() {
+            let args: Vec<Value> = pos
+                .func
+                .dfg
+                .inst_args(inst)
+                .iter()
+                .map(|&arg| map_value(&pos.func.dfg, &exit_map, arg))
+                .collect();
+            pos.func.dfg.inst_args_mut(inst).copy_from_slice(&args);
+        }
+    }
+}
+
+/// Append copies of `insts` to `ebb`, using and updating the copies of the original values in
+/// `map`.
+fn copy_insts(
+    func: &mut Function,
+    ebb: Ebb,
+    insts: &[Inst],
+    map: &mut SecondaryMap<Value, PackedOption<Value>>,
+) {
+    for &inst in insts {
+        let copy = func.dfg.clone_inst(inst);
+        let args: Vec<Value> = func
+            .dfg
+            .inst_args(copy)
+            .iter()
+            .map(|&arg| map_value(&func.dfg, map, arg))
+            .collect();
+        func.dfg.inst_args_mut(copy).copy_from_slice(&args);
+        for (&result, &new_result) in func
+            .dfg
+            .inst_results(inst)
+            .iter()
+            .zip(func.dfg.inst_results(copy))
+        {
+            map[result] = new_result.into();
+        }
+        func.layout.append_inst(copy, ebb);
+    }
+}
+
+/// Get the copy of `value` in `map`, or `value` itself if it wasn't copied.
+fn map_value(
+    dfg: &DataFlowGraph,
+    map: &SecondaryMap<Value, PackedOption<Value>>,
+    value: Value,
+) -> Value {
+    let value = dfg.resolve_aliases(value);
+    map[value].expand().unwrap_or(value)
+}
@@ -0,0 +1,316 @@
+//! A loop unswitching pass.
+//!
+//! A conditional branch on a value defined outside a loop goes the same way in every iteration.
+//! Unswitching the loop makes two copies of it, one where the branch is never taken and one where
+//! it's always taken, and tests the value once before entering either of them. The branch is
+//! removed from the first copy, and turned into a `jump` in the second one.
+//!
+//! The loop must be entered by a single `jump` to its header and leave to at most one EBB, which
+//! isn't reached from anywhere else. The values defined in the loop and used after it are passed
+//! to that EBB as new parameters by both copies.
+
+use crate::cursor::{Cursor, FuncCursor};
+use crate::dominator_tree::DominatorTree;
+use crate::entity::{EntitySet, SecondaryMap};
+use crate::flowgraph::{BasicBlock, ControlFlowGraph};
+use crate::ir::instructions::BranchInfo;
+use crate::ir::{Ebb, Function, Inst, InstBuilder, Opcode, Value, ValueDef};
+use crate::loop_analysis::{Loop, LoopAnalysis};
+use crate::packed_option::PackedOption;
+use crate::timing;
+use std::vec::Vec;
+
+/// The largest number of instructions in a loop that gets unswitched.
+const MAX_LOOP_SIZE: usize = 64;
+
+/// The largest number of loops unswitched in a function.
+const MAX_UNSWITCHES: usize = 4;
+
+/// A loop which can be unswitched.
+struct Candidate {
+    /// The EBBs of the loop, in layout order.
+    ebbs: Vec<Ebb>,
+    header: Ebb,
+    /// The `jump` entering the loop.
+    entry: Inst,
+    /// The conditional branch on a value defined outside the loop.
+    branch: Inst,
+    /// The EBB the loop leaves to, if any.
+    exit: Option<Ebb>,
+    /// The values defined in the loop and used after it.
+    escaping: Vec<Value>,
+}
+
+/// Unswitch the loops of `func` containing conditional branches on loop-invariant values.
+///
+/// The control flow graph, dominator tree and loop analysis are recomputed if anything changed.
+pub fn do_loop_unswitch(
+    func: &mut Function,
+    cfg: &mut ControlFlowGraph,
+    domtree: &mut DominatorTree,
+    loop_analysis: &mut LoopAnalysis,
+) {
+    let _tt = timing::loop_unswitch();
+    debug_assert!(cfg.is_valid());
+    debug_assert!(domtree.is_valid());
+    debug_assert!(loop_analysis.is_valid());
+
+    for _ in 0..MAX_UNSWITCHES {
+        let candidate = loop_analysis
+            .loops()
+            .find_map(|lp| analyze_loop(func, cfg, domtree, loop_analysis, lp));
+        match candidate {
+            Some(candidate) => unswitch(func, &candidate),
+            None => break,
+        }
+        cfg.compute(func);
+        domtree.compute(func, cfg);
+        loop_analysis.compute(func, cfg, domtree);
+    }
+}
+
+/// Check if the loop `lp` can be unswitched.
+fn analyze_loop(
+    func: &Function,
+    cfg: &ControlFlowGraph,
+    domtree: &DominatorTree,
+    loop_analysis: &LoopAnalysis,
+    lp: Loop,
+) -> Option<Candidate> {
+    let header = loop_analysis.loop_header(lp);
+    let ebbs: Vec<Ebb> = func
+        .layout
+        .ebbs()
+        .filter(|&ebb| loop_analysis.is_in_loop(ebb, lp))
+        .collect();
+    let mut in_loop = EntitySet::new();
+    for &ebb in &ebbs {
+        in_loop.insert(ebb);
+    }
+
+    // The loop must be entered by a single jump.
+    let mut entry = None;
+    for BasicBlock { ebb, inst } in cfg.pred_iter(header) {
+        if !in_loop.contains(ebb) {
+            if entry.is_some() || func.dfg[inst].opcode() != Opcode::Jump {
+                return None;
+            }
+            entry = Some(inst);
+        }
+    }
+    let entry = entry?;
+
+    let defined_in_loop = |value: Value| match func.dfg.value_def(value) {
+        ValueDef::Result(inst, _) => func
+            .layout
+            .inst_ebb(inst)
+            .map_or(false, |ebb| in_loop.contains(ebb)),
+        ValueDef::Param(ebb, _) => in_loop.contains(ebb),
+    };
+
+    let mut size = 0;
+    let mut exit = None;
+    let mut branch = None;
+    for &ebb in &ebbs {
+        for inst in func.layout.ebb_insts(ebb) {
+            size += 1;
+            let opcode = func.dfg[inst].opcode();
+            match func.dfg.analyze_branch(inst) {
+                BranchInfo::NotABranch => {}
+                BranchInfo::SingleDest(destination, _) => {
+                    if opcode == Opcode::Fallthrough {
+                        return None;
+                    }
+                    if !in_loop.contains(destination) {
+                        match exit {
+                            None => exit = Some(destination),
+                            Some(exit) if exit == destination => {}
+                            Some(_) => return None,
+                        }
+                    }
+                }
+                BranchInfo::Table(..) => return None,
+            }
+            if opcode.is_indirect_branch() || opcode == Opcode::FallthroughReturn {
+                return None;
+            }
+            if branch.is_none()
+                && (opcode == Opcode::Brz || opcode == Opcode::Brnz)
+                && !defined_in_loop(func.dfg.resolve_aliases(func.dfg.inst_args(inst)[0]))
+            {
+                branch = Some(inst);
+            }
+        }
+    }
+    let branch = branch?;
+    if size > MAX_LOOP_SIZE {
+        return None;
+    }
+    if let Some(exit) = exit {
+        if cfg
+            .pred_iter(exit)
+            .any(|BasicBlock { ebb, .. }| !in_loop.contains(ebb))
+        {
+            return None;
+        }
+    }
+
+    // Find the values escaping the loop. They can only be passed to the exit EBB if their
+    // definitions dominate every branch to it.
+    let mut escaping = Vec::new();
+    let mut seen = EntitySet::new();
+    for ebb in func.layout.ebbs() {
+        if in_loop.contains(ebb) {
+            continue;
+        }
+        for inst in func.layout.ebb_insts(ebb) {
+            for &arg in func.dfg.inst_args(inst) {
+                let arg = func.dfg.resolve_aliases(arg);
+                if defined_in_loop(arg) && !seen.contains(arg) {
+                    seen.insert(arg);
+                    escaping.push(arg);
+                }
+            }
+        }
+    }
+    if !escaping.is_empty() {
+        let exit = exit?;
+        for &ebb in &ebbs {
+            for inst in func.layout.ebb_insts(ebb) {
+                if func.dfg[inst].branch_destination() == Some(exit)
+                    && escaping.iter().any(|&value| {
+                        !domtree.dominates(func.dfg.value_def(value).pp(), inst, &func.layout)
+                    })
+                {
+                    return None;
+                }
+            }
+        }
+    }
+
+    Some(Candidate {
+        ebbs,
+        header,
+        entry,
+        branch,
+        exit,
+        escaping,
+    })
+}
+
+/// Unswitch the candidate loop.
+fn unswitch(func: &mut Function, candidate: &Candidate) {
+    // Copy the loop after the original one.
+    let mut ebb_map: SecondaryMap<Ebb, PackedOption<Ebb>> = SecondaryMap::new();
+    let mut value_map: SecondaryMap<Value, PackedOption<Value>> = SecondaryMap::new();
+    let mut copy_ebbs = EntitySet::new();
+    let mut last = *candidate.ebbs.last().expect("a loop has a header");
+    for &ebb in &candidate.ebbs {
+        let copy = func.dfg.make_ebb();
+        func.layout.insert_ebb_after(copy, last);
+        last = copy;
+        for i in 0..func.dfg.num_ebb_params(ebb) {
+            let param = func.dfg.ebb_params(ebb)[i];
+            let ty = func.dfg.value_type(param);
+            value_map[param] = func.dfg.append_ebb_param(copy, ty).into();
+        }
+        ebb_map[ebb] = copy.into();
+        copy_ebbs.insert(copy);
+    }
+
+    let mut copies = Vec::new();
+    for &ebb in &candidate.ebbs {
+        let copy_ebb = ebb_map[ebb].unwrap();
+        let insts: Vec<Inst> = func.layout.ebb_insts(ebb).collect();
+        for inst in insts {
+            let copy = func.dfg.clone_inst(inst);
+            for i in 0..func.dfg.inst_results(inst).len() {
+                let result = func.dfg.inst_results(inst)[i];
+                value_map[result] = func.dfg.inst_results(copy)[i].into();
+            }
+            if let Some(destination) = func.dfg[copy].branch_destination_mut() {
+                if let Some(copy_destination) = ebb_map[*destination].expand() {
+                    *destination = copy_destination;
+                }
+            }
+            func.layout.append_inst(copy, copy_ebb);
+            copies.push((inst, copy));
+        }
+    }
+    for &(_, copy) in &copies {
+        for i in 0..func.dfg.inst_args(copy).len() {
+            let arg = func.dfg.resolve_aliases(func.dfg.inst_args(copy)[i]);
+            if let Some(new_arg) = value_map[arg].expand() {
+                func.dfg.inst_args_mut(copy)[i] = new_arg;
+            }
+        }
+    }
+
+    // Merge the escaping values of both loops in new parameters of the exit EBB.
+    if let Some(exit) = candidate.exit {
+        let mut merged: SecondaryMap<Value, PackedOption<Value>> = SecondaryMap::new();
+        for &value in &candidate.escaping {
+            let ty = func.dfg.value_type(value);
+            merged[value] = func.dfg.append_ebb_param(exit, ty).into();
+        }
+
+        let mut pos = FuncCursor::new(func);
+        while let Some(ebb) = pos.next_ebb() {
+            if candidate.ebbs.contains(&ebb) || copy_ebbs.contains(ebb) {
+                continue;
+            }
+            while let Some(inst) = pos.next_inst() {
+                for i in 0..pos.func.dfg.inst_args(inst).len() {
+                    let arg = pos
+                        .func
+                        .dfg
+                        .resolve_aliases(pos.func.dfg.inst_args(inst)[i]);
+                    if let Some(new_arg) = merged[arg].expand() {
+                        pos.func.dfg.inst_args_mut(inst)[i] = new_arg;
+                    }
+                }
+            }
+        }
+
+        for &(inst, copy) in &copies {
+            if func.dfg[inst].branch_destination() == Some(exit) {
+                for &value in &candidate.escaping {
+                    func.dfg.append_inst_arg(inst, value);
+                    let copied_value = value_map[value].unwrap();
+                    func.dfg.append_inst_arg(copy, copied_value);
+                }
+            }
+        }
+    }
+
+    // The branch is never taken in the original loop, and always taken in the copy.
+    let branch_copy = copies
+        .iter()
+        .find(|&&(inst, _)| inst == candidate.branch)
+        .map(|&(_, copy)| copy)
+        .expect("the branch was copied");
+    func.layout.remove_inst(candidate.branch);
+    let destination = func.dfg[branch_copy]
+        .branch_destination()
+        .expect("the branch has a destination");
+    let args = func.dfg.inst_variable_args(branch_copy).to_vec();
+    let tail = func.dfg.make_ebb();
+    let next = func
+        .layout
+        .next_inst(branch_copy)
+        .expect("a conditional branch isn't a terminator");
+    func.layout.split_ebb(tail, next);
+    func.dfg.replace(branch_copy).jump(destination, &args);
+
+    // Test the condition before entering the loop.
+    let opcode = func.dfg[candidate.branch].opcode();
+    let cond = func.dfg.inst_args(candidate.branch)[0];
+    let header_copy = ebb_map[candidate.header].unwrap();
+    let entry_args = func.dfg.inst_variable_args(candidate.entry).to_vec();
+    let mut pos = FuncCursor::new(func).at_inst(candidate.entry);
+    if opcode == Opcode::Brz {
+        pos.ins().brz(cond, header_copy, &entry_args);
+    } else {
+        pos.ins().brnz(cond, header_copy, &entry_args);
+    }
+}
@@ -180,6 +180,12 @@ struct MemOpInfo {
 }
 
 fn optimize_complex_addresses(pos: &mut EncCursor, inst: Inst, isa: &dyn TargetIsa) {
+    // Atomic memory accesses share the load and store formats, but they only have encodings for
+    // simple addressing modes.
+    if pos.func.dfg[inst].opcode().is_atomic() {
+        return;
+    }
+
     // Look for simple loads and stores we can optimize.
     let info = match pos.func.dfg[inst] {
         InstructionData::Load {
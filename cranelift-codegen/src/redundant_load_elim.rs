@@ -0,0 +1,427 @@
+//! A redundant load elimination and store-to-load forwarding pass.
+//!
+//! The pass tracks the values known to be in memory at each program point. A `load` of a
+//! location holding a known value is replaced by that value, whether it was loaded before or
+//! stored by a `store`. The memory locations are described by a base and a constant offset, found
+//! by looking through `iadd_imm` and `stack_addr` instructions, and a type.
+//!
+//! The known values flow forward through each EBB, and into EBBs whose predecessors have all been
+//! visited before, keeping the values known at the end of every predecessor. EBBs are visited in
+//! reverse post-order, so loop headers start out knowing nothing, and every known value dominates
+//! the program point it's known at.
+//!
+//! Stores and instructions that may write memory forget the known values they may overwrite:
+//!
+//! - The explicit stack slots whose address never escapes are only accessed through their own
+//!   `stack_load`, `stack_store` and `stack_addr` instructions.
+//! - The locations based on global values for distinct symbols don't overlap. This includes the
+//!   heaps whose bases are such global values, before and after `heap_addr` is legalized. Heaps
+//!   with other bases may share their memory, so they're not told apart.
+//! - Loads with the `readonly` flag read memory that isn't written while the function runs.
+//!
+//! The other memory flags don't matter: a location that was accessed before won't trap when
+//! accessed again.
+
+use crate::cursor::{Cursor, FuncCursor};
+use crate::dominator_tree::DominatorTree;
+use crate::entity::EntitySet;
+use crate::flowgraph::{BasicBlock, ControlFlowGraph};
+use crate::fx::FxHashMap;
+use crate::ir::{
+    DataFlowGraph, Ebb, Function, GlobalValue, GlobalValueData, Inst, InstructionData, Opcode,
+    StackSlot, StackSlotKind, Type, Value, ValueDef,
+};
+use crate::timing;
+
+/// The base of a memory location.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Base {
+    /// A stack slot, accessed with `stack_load`, `stack_store` or a `stack_addr` address.
+    StackSlot(StackSlot),
+    /// An address computed by an instruction or passed as a parameter.
+    Value(Value),
+}
+
+/// A memory location accessed as a value of type `ty`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct Location {
+    base: Base,
+    offset: i64,
+    ty: Type,
+}
+
+/// A value known to be in memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Known {
+    value: Value,
+    /// The location is only read, so stores never overwrite it.
+    readonly: bool,
+}
+
+/// The values known to be in memory at a program point.
+type Memory = FxHashMap<Location, Known>;
+
+/// The memory region an address based on a value points into, used to tell apart bases which
+/// can't overlap.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Region {
+    Unknown,
+    /// The memory around a symbol, named by a `GlobalValueData::Symbol` global value.
+    Symbol(GlobalValue),
+}
+
+/// Perform redundant load elimination and store-to-load forwarding on `func`.
+pub fn do_redundant_load_elim(
+    func: &mut Function,
+    cfg: &ControlFlowGraph,
+    domtree: &DominatorTree,
+) {
+    let _tt = timing::redundant_load_elim();
+    debug_assert!(cfg.is_valid());
+    debug_assert!(domtree.is_valid());
+
+    let escaped = escaped_stack_slots(func);
+
+    // The values known to be in memory when leaving each branch instruction.
+    let mut exits: FxHashMap<Inst, Memory> = FxHashMap();
+    let mut pos = FuncCursor::new(func);
+
+    for &ebb in domtree.cfg_postorder().iter().rev() {
+        let mut memory = entry_memory(ebb, cfg, &exits);
+
+        pos.goto_top(ebb);
+        while let Some(inst) = pos.next_inst() {
+            let opcode = pos.func.dfg[inst].opcode();
+            if opcode.is_call() || opcode.other_side_effects() {
+                forget_all(&mut memory, &escaped);
+            }
+
+            match pos.func.dfg[inst] {
+                InstructionData::Load {
+                    opcode: Opcode::Load,
+                    arg,
+                    flags,
+                    offset,
+                } => {
+                    let (base, offset) = resolve_address(&pos.func.dfg, arg, offset.into());
+                    if load(pos.func, inst, base, offset, flags.readonly(), &mut memory) {
+                        pos.remove_inst_and_step_back();
+                    }
+                }
+                InstructionData::StackLoad {
+                    opcode: Opcode::StackLoad,
+                    stack_slot,
+                    offset,
+                } => {
+                    let base = Base::StackSlot(stack_slot);
+                    if load(pos.func, inst, base, offset.into(), false, &mut memory) {
+                        pos.remove_inst_and_step_back();
+                    }
+                }
+                InstructionData::Store {
+                    opcode,
+                    args,
+                    offset,
+                    ..
+                } if opcode.can_store() => {
+                    let (base, offset) = resolve_address(&pos.func.dfg, args[1], offset.into());
+                    store(
+                        pos.func,
+                        opcode,
+                        args[0],
+                        base,
+                        offset,
+                        &escaped,
+                        &mut memory,
+                    );
+                }
+                InstructionData::StackStore {
+                    opcode: Opcode::StackStore,
+                    arg,
+                    stack_slot,
+                    offset,
+                } => {
+                    let base = Base::StackSlot(stack_slot);
+                    store(
+                        pos.func,
+                        Opcode::Store,
+                        arg,
+                        base,
+                        offset.into(),
+                        &escaped,
+                        &mut memory,
+                    );
+                }
+                _ => {
+                    if opcode.can_store() {
+                        forget_all(&mut memory, &escaped);
+                    }
+                }
+            }
+
+            if opcode.is_branch() {
+                exits.insert(inst, memory.clone());
+            }
+        }
+    }
+}
+
+/// Get the values known to be in memory when entering `ebb`: the values known when leaving all
+/// of its predecessors, or nothing if some of them haven't been visited yet.
+fn entry_memory(ebb: Ebb, cfg: &ControlFlowGraph, exits: &FxHashMap<Inst, Memory>) -> Memory {
+    let mut preds = cfg.pred_iter(ebb);
+    let mut memory = match preds
+        .next()
+        .and_then(|BasicBlock { inst, .. }| exits.get(&inst))
+    {
+        Some(memory) => memory.clone(),
+        None => return Memory::default(),
+    };
+    for BasicBlock { inst, .. } in preds {
+        match exits.get(&inst) {
+            Some(other) => memory.retain(|location, known| other.get(location) == Some(known)),
+            None => return Memory::default(),
+        }
+    }
+    memory
+}
+
+/// Handle the load `inst` of the location at `offset` from `base`.
+///
+/// Returns true if the loaded value was known, in which case the load result has been turned
+/// into an alias of it and the load should be removed.
+fn load(
+    func: &mut Function,
+    inst: Inst,
+    base: Base,
+    offset: i64,
+    readonly: bool,
+    memory: &mut Memory,
+) -> bool {
+    let result = func.dfg.first_result(inst);
+    let location = Location {
+        base,
+        offset,
+        ty: func.dfg.value_type(result),
+    };
+    if let Some(known) = memory.get(&location) {
+        func.dfg.clear_results(inst);
+        func.dfg.change_to_alias(result, known.value);
+        return true;
+    }
+    memory.insert(
+        location,
+        Known {
+            value: result,
+            readonly,
+        },
+    );
+    false
+}
+
+/// Handle a store of `value` by `opcode` to the location at `offset` from `base`.
+fn store(
+    func: &Function,
+    opcode: Opcode,
+    value: Value,
+    base: Base,
+    offset: i64,
+    escaped: &EntitySet<StackSlot>,
+    memory: &mut Memory,
+) {
+    let value = func.dfg.resolve_aliases(value);
+    let ty = func.dfg.value_type(value);
+    let size = match opcode {
+        Opcode::Istore8 => 1,
+        Opcode::Istore16 => 2,
+        Opcode::Istore32 => 4,
+        _ => i64::from(ty.bytes()),
+    };
+
+    memory.retain(|location, known| {
+        known.readonly || !may_alias(func, escaped, base, offset, size, location)
+    });
+    if opcode == Opcode::Store {
+        memory.insert(
+            Location { base, offset, ty },
+            Known {
+                value,
+                readonly: false,
+            },
+        );
+    }
+}
+
+/// Forget the values which may be overwritten by an unknown store.
+fn forget_all(memory: &mut Memory, escaped: &EntitySet<StackSlot>) {
+    memory.retain(|location, known| {
+        known.readonly
+            || match location.base {
+                Base::StackSlot(ss) => !escaped.contains(ss),
+                Base::Value(_) => false,
+            }
+    });
+}
+
+/// Check if a store of `size` bytes at `offset` from `base` may overwrite `location`.
+fn may_alias(
+    func: &Function,
+    escaped: &EntitySet<StackSlot>,
+    base: Base,
+    offset: i64,
+    size: i64,
+    location: &Location,
+) -> bool {
+    let overlaps = || {
+        offset < location.offset + i64::from(location.ty.bytes()) && location.offset < offset + size
+    };
+    match (base, location.base) {
+        (Base::StackSlot(a), Base::StackSlot(b)) => a == b && overlaps(),
+        (Base::StackSlot(ss), Base::Value(_)) | (Base::Value(_), Base::StackSlot(ss)) => {
+            escaped.contains(ss)
+        }
+        (Base::Value(a), Base::Value(b)) => {
+            if a == b {
+                overlaps()
+            } else {
+                !disjoint_regions(func, region(func, a), region(func, b))
+            }
+        }
+    }
+}
+
+/// Check if the memory regions `a` and `b` are known not to overlap.
+fn disjoint_regions(func: &Function, a: Region, b: Region) -> bool {
+    match (a, b) {
+        (Region::Symbol(a), Region::Symbol(b)) => {
+            match (&func.global_values[a], &func.global_values[b]) {
+                (
+                    GlobalValueData::Symbol { name: a, .. },
+                    GlobalValueData::Symbol { name: b, .. },
+                ) => a != b,
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+/// Get the memory region the address `addr` points into.
+///
+/// A legalized `heap_addr` adds the index to the heap base with an `iadd`, so the region of an
+/// `iadd` is the region of its operand which has one.
+fn region(func: &Function, addr: Value) -> Region {
+    if let ValueDef::Result(inst, _) = func.dfg.value_def(addr) {
+        if let InstructionData::Binary {
+            opcode: Opcode::Iadd,
+            args,
+        } = func.dfg[inst]
+        {
+            return match (base_region(func, args[0]), base_region(func, args[1])) {
+                (Region::Unknown, region) | (region, Region::Unknown) => region,
+                _ => Region::Unknown,
+            };
+        }
+    }
+    base_region(func, addr)
+}
+
+/// Get the memory region the base address `base` points into, looking through `iadd_imm`
+/// instructions.
+fn base_region(func: &Function, base: Value) -> Region {
+    let base = match resolve_address(&func.dfg, base, 0) {
+        (Base::Value(base), _) => base,
+        (Base::StackSlot(_), _) => return Region::Unknown,
+    };
+    if let ValueDef::Result(inst, _) = func.dfg.value_def(base) {
+        match func.dfg[inst] {
+            InstructionData::HeapAddr { heap, .. } => {
+                return global_value_region(func, func.heaps[heap].base)
+            }
+            InstructionData::UnaryGlobalValue { global_value, .. } => {
+                return global_value_region(func, global_value)
+            }
+            _ => {}
+        }
+    }
+    Region::Unknown
+}
+
+/// Get the memory region the global value `gv` points into.
+///
+/// Only symbols and constant offsets from them are known. Loaded global values may hold the same
+/// address, even when they're loaded from different locations.
+fn global_value_region(func: &Function, mut gv: GlobalValue) -> Region {
+    loop {
+        match func.global_values[gv] {
+            GlobalValueData::Symbol { .. } => return Region::Symbol(gv),
+            GlobalValueData::IAddImm { base, .. } => gv = base,
+            GlobalValueData::VMContext | GlobalValueData::Load { .. } => return Region::Unknown,
+        }
+    }
+}
+
+/// Find the base of the address `addr` and the offset from it of the location at `offset` from
+/// `addr`, looking through `iadd_imm` and `stack_addr` instructions.
+fn resolve_address(dfg: &DataFlowGraph, addr: Value, offset: i64) -> (Base, i64) {
+    let mut addr = dfg.resolve_aliases(addr);
+    let mut offset = offset;
+    while let ValueDef::Result(inst, _) = dfg.value_def(addr) {
+        match dfg[inst] {
+            InstructionData::BinaryImm {
+                opcode: Opcode::IaddImm,
+                arg,
+                imm,
+            } => {
+                let imm: i64 = imm.into();
+                offset = offset.wrapping_add(imm);
+                addr = dfg.resolve_aliases(arg);
+            }
+            InstructionData::StackLoad {
+                opcode: Opcode::StackAddr,
+                stack_slot,
+                offset: slot_offset,
+            } => {
+                let slot_offset: i64 = slot_offset.into();
+                return (
+                    Base::StackSlot(stack_slot),
+                    offset.wrapping_add(slot_offset),
+                );
+            }
+            _ => break,
+        }
+    }
+    (Base::Value(addr), offset)
+}
+
+/// Find the stack slots which may be accessed other than by `stack_load`, `stack_store`, or
+/// loads and stores of their `stack_addr` addresses.
+fn escaped_stack_slots(func: &Function) -> EntitySet<StackSlot> {
+    let mut escaped = EntitySet::new();
+    for (ss, data) in func.stack_slots.iter() {
+        if data.kind != StackSlotKind::ExplicitSlot {
+            escaped.insert(ss);
+        }
+    }
+
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            let data = &func.dfg[inst];
+            let opcode = data.opcode();
+            for (i, &arg) in data.arguments(&func.dfg.value_lists).iter().enumerate() {
+                let is_address = match *data {
+                    InstructionData::Load { .. } => true,
+                    InstructionData::Store { .. } => i == 1,
+                    _ => opcode == Opcode::IaddImm,
+                };
+                if is_address {
+                    continue;
+                }
+                if let (Base::StackSlot(ss), _) = resolve_address(&func.dfg, arg, 0) {
+                    escaped.insert(ss);
+                }
+            }
+        }
+    }
+    escaped
+}
@@ -245,6 +245,11 @@ impl EntryRegDiversions {
     pub fn clear(&mut self) {
         self.map.clear();
     }
+
+    /// Get the diversions recorded at the entry of `ebb`, if any.
+    pub fn get(&self, ebb: Ebb) -> Option<&RegDiversions> {
+        self.map.get(ebb).map(|entry| &entry.divert)
+    }
 }
 
 impl Clone for EntryRegDiversions {
@@ -58,9 +58,13 @@ define_passes! {
     postopt: "Post-legalization rewriting",
     preopt: "Pre-legalization rewriting",
     dce: "Dead code elimination",
+    inline: "Function inlining",
     legalize: "Legalization",
     gvn: "Global value numbering",
     licm: "Loop invariant code motion",
+    loop_unroll: "Loop unrolling",
+    loop_unswitch: "Loop unswitching",
+    redundant_load_elim: "Redundant load elimination",
     unreachable_code: "Remove unreachable blocks",
 
     regalloc: "Register allocation",
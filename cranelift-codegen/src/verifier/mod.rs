@@ -46,10 +46,14 @@
 //! - Detect cycles in global values.
 //! - Detect use of 'vmctx' global value when no corresponding parameter is defined.
 //!
+//! Memory accesses
+//!
+//! - Stack slot loads and stores must be in-bounds.
+//! - The minimum size of a static heap must not exceed its bound.
+//!
 //! TODO:
 //! Ad hoc checking
 //!
-//! - Stack slot loads and stores must be in-bounds.
 //! - Immediate constraints for certain opcodes, like `udiv_imm v3, 0`.
 //! - `Insertlane` and `extractlane` instructions have immediate lane numbers that must be in
 //!   range for their polymorphic type.
@@ -375,6 +379,22 @@ impl<'a> Verifier<'a> {
     }
 
     fn verify_heaps(&self, errors: &mut VerifierErrors) -> VerifierStepResult<()> {
+        for (heap, heap_data) in &self.func.heaps {
+            if let ir::HeapStyle::Static { bound } = heap_data.style {
+                let min_size: u64 = heap_data.min_size.into();
+                let bound: u64 = bound.into();
+                if min_size > bound {
+                    report!(
+                        errors,
+                        heap,
+                        "heap minimum size {:#x} exceeds its bound {:#x}",
+                        min_size,
+                        bound
+                    );
+                }
+            }
+        }
+
         if let Some(isa) = self.isa {
             for (heap, heap_data) in &self.func.heaps {
                 let base = heap_data.base;
@@ -593,6 +613,18 @@ impl<'a> Verifier<'a> {
             self.verify_inst_result(inst, res, errors)?;
         }
 
+        if self.func.dfg[inst].opcode().is_atomic() {
+            if let Some(isa) = &self.isa {
+                if !isa.flags().enable_atomics() {
+                    return fatal!(
+                        errors,
+                        inst,
+                        "atomic instructions cannot be used without enable_atomics"
+                    );
+                }
+            }
+        }
+
         match self.func.dfg[inst] {
             MultiAry { ref args, .. } => {
                 self.verify_value_list(inst, args, errors)?;
@@ -708,6 +740,7 @@ impl<'a> Verifier<'a> {
             | Ternary { .. }
             | InsertLane { .. }
             | ExtractLane { .. }
+            | Shuffle { .. }
             | IntCompare { .. }
             | IntCompareImm { .. }
             | IntCond { .. }
@@ -716,6 +749,7 @@ impl<'a> Verifier<'a> {
             | IntSelect { .. }
             | Load { .. }
             | Store { .. }
+            | AtomicCas { .. }
             | RegMove { .. }
             | CopySpecial { .. }
             | CopyToSsa { .. }
@@ -1749,7 +1783,8 @@ impl<'a> Verifier<'a> {
         // If this is some sort of a store instruction, get the memflags, else, just return.
         let memflags = match *inst_data {
             ir::InstructionData::Store { flags, .. }
-            | ir::InstructionData::StoreComplex { flags, .. } => flags,
+            | ir::InstructionData::StoreComplex { flags, .. }
+            | ir::InstructionData::AtomicCas { flags, .. } => flags,
             _ => return Ok(()),
         };
 
@@ -1764,6 +1799,47 @@ impl<'a> Verifier<'a> {
         }
     }
 
+    fn verify_stack_access(
+        &self,
+        inst: Inst,
+        errors: &mut VerifierErrors,
+    ) -> VerifierStepResult<()> {
+        let (stack_slot, offset, access_type) = match self.func.dfg[inst] {
+            ir::InstructionData::StackLoad {
+                opcode: Opcode::StackLoad,
+                stack_slot,
+                offset,
+            } => (stack_slot, offset, self.func.dfg.ctrl_typevar(inst)),
+            ir::InstructionData::StackStore {
+                arg,
+                stack_slot,
+                offset,
+                ..
+            } => (stack_slot, offset, self.func.dfg.value_type(arg)),
+            _ => return Ok(()),
+        };
+        if !self.func.stack_slots.is_valid(stack_slot) {
+            // Already reported as an invalid entity reference.
+            return Ok(());
+        }
+
+        let offset: i64 = offset.into();
+        let access_size = i64::from(access_type.bytes());
+        let slot_size = self.func.stack_slots[stack_slot].size;
+        if offset < 0 || offset + access_size > i64::from(slot_size) {
+            return nonfatal!(
+                errors,
+                inst,
+                "access of {} bytes at offset {} is out of bounds for {}, which has {} bytes",
+                access_size,
+                offset,
+                stack_slot,
+                slot_size
+            );
+        }
+        Ok(())
+    }
+
     fn verify_safepoint_unused(
         &self,
         inst: Inst,
@@ -1797,6 +1873,7 @@ impl<'a> Verifier<'a> {
                 self.typecheck(inst, errors)?;
                 self.verify_encoding(inst, errors)?;
                 self.immediate_constraints(inst, errors)?;
+                self.verify_stack_access(inst, errors)?;
             }
 
             #[cfg(feature = "basic-blocks")]
@@ -0,0 +1,506 @@
+//! Defines `CoffBackend`.
+
+use crate::coff::{
+    CoffObject, SectionId, IMAGE_REL_AMD64_ADDR32, IMAGE_REL_AMD64_ADDR64, IMAGE_REL_AMD64_REL32,
+};
+use cranelift_codegen::binemit::{
+    Addend, CodeOffset, NullStackmapSink, NullTrapSink, Reloc, RelocSink,
+};
+use cranelift_codegen::entity::PrimaryMap;
+use cranelift_codegen::isa::TargetIsa;
+use cranelift_codegen::{self, binemit, ir};
+use cranelift_module::{
+    Backend, DataContext, DataDescription, DataId, FuncId, Init, Linkage, ModuleError,
+    ModuleNamespace, ModuleResult,
+};
+use std::fs::File;
+use std::io::{self, Write};
+use target_lexicon::{Architecture, BinaryFormat};
+
+/// Alignment of functions in the `.text` section.
+const FUNCTION_ALIGNMENT: u32 = 16;
+
+/// A builder for `CoffBackend`.
+pub struct CoffBuilder {
+    isa: Box<dyn TargetIsa>,
+    name: String,
+    libcall_names: Box<dyn Fn(ir::LibCall) -> String>,
+}
+
+impl CoffBuilder {
+    /// Create a new `CoffBuilder` using the given Cranelift target, that
+    /// can be passed to
+    /// [`Module::new`](cranelift_module::Module::new)
+    ///
+    /// COFF output is only supported for x86-64. Since COFF has no global offset table, the
+    /// TargetIsa must not have PIC (Position Independent Code) enabled.
+    ///
+    /// The `libcall_names` function provides a way to translate `cranelift_codegen`'s `ir::LibCall`
+    /// enum to symbols. LibCalls are inserted in the IR as part of the legalization for certain
+    /// floating point instructions, and for stack probes. If you don't know what to use for this
+    /// argument, use `cranelift_module::default_libcall_names()`.
+    pub fn new(
+        isa: Box<dyn TargetIsa>,
+        name: String,
+        libcall_names: Box<dyn Fn(ir::LibCall) -> String>,
+    ) -> ModuleResult<Self> {
+        let triple = isa.triple();
+        if triple.architecture != Architecture::X86_64 {
+            return Err(ModuleError::Backend(format!(
+                "COFF output is not supported for {}",
+                triple
+            )));
+        }
+        match triple.binary_format {
+            BinaryFormat::Coff | BinaryFormat::Unknown => {}
+            _ => {
+                return Err(ModuleError::Backend(format!(
+                    "{} doesn't use the COFF format",
+                    triple
+                )))
+            }
+        }
+        if isa.flags().is_pic() {
+            return Err(ModuleError::Backend(
+                "COFF output requires TargetIsa not be PIC".to_owned(),
+            ));
+        }
+        Ok(Self {
+            isa,
+            name,
+            libcall_names,
+        })
+    }
+}
+
+/// A `CoffBackend` implements `Backend` and emits COFF ".obj" files for x86-64 Windows.
+///
+/// See the `CoffBuilder` for a convenient way to construct `CoffBackend` instances.
+pub struct CoffBackend {
+    isa: Box<dyn TargetIsa>,
+    name: String,
+    object: CoffObject,
+    libcall_names: Box<dyn Fn(ir::LibCall) -> String>,
+}
+
+pub struct CoffCompiledFunction {
+    code_length: u32,
+}
+
+impl CoffCompiledFunction {
+    pub fn code_length(&self) -> u32 {
+        self.code_length
+    }
+}
+
+pub struct CoffCompiledData {
+    section: SectionId,
+    /// The offset of the data object in its section.
+    offset: u32,
+    /// The names of the functions and data objects the data object refers to.
+    function_names: PrimaryMap<ir::FuncRef, String>,
+    data_names: PrimaryMap<ir::GlobalValue, String>,
+}
+
+impl Backend for CoffBackend {
+    type Builder = CoffBuilder;
+
+    type CompiledFunction = CoffCompiledFunction;
+    type CompiledData = CoffCompiledData;
+
+    // There's no need to return individual artifacts; we're writing them into
+    // the output file instead.
+    type FinalizedFunction = ();
+    type FinalizedData = ();
+
+    /// The returned value here provides functions for emitting object files
+    /// to memory and files.
+    type Product = CoffProduct;
+
+    /// Create a new `CoffBackend` using the given Cranelift target.
+    fn new(builder: CoffBuilder) -> Self {
+        Self {
+            isa: builder.isa,
+            name: builder.name,
+            object: CoffObject::new(),
+            libcall_names: builder.libcall_names,
+        }
+    }
+
+    fn isa(&self) -> &dyn TargetIsa {
+        &*self.isa
+    }
+
+    fn declare_function(&mut self, _id: FuncId, name: &str, linkage: Linkage) {
+        self.object.declare(name, translate_linkage(linkage), true);
+    }
+
+    fn declare_data(
+        &mut self,
+        _id: DataId,
+        name: &str,
+        linkage: Linkage,
+        _writable: bool,
+        _align: Option<u8>,
+    ) {
+        self.object.declare(name, translate_linkage(linkage), false);
+    }
+
+    fn define_function(
+        &mut self,
+        _id: FuncId,
+        name: &str,
+        ctx: &cranelift_codegen::Context,
+        namespace: &ModuleNamespace<Self>,
+        total_size: u32,
+    ) -> ModuleResult<CoffCompiledFunction> {
+        let mut code: Vec<u8> = vec![0; total_size as usize];
+        let mut reloc_sink = CoffRelocSink {
+            namespace,
+            libcall_names: &*self.libcall_names,
+            relocs: Vec::new(),
+            error: None,
+        };
+        let mut trap_sink = NullTrapSink {};
+        let mut stackmap_sink = NullStackmapSink {};
+        unsafe {
+            ctx.emit_to_memory(
+                &*self.isa,
+                code.as_mut_ptr(),
+                &mut reloc_sink,
+                &mut trap_sink,
+                &mut stackmap_sink,
+            )
+        };
+        if let Some(message) = reloc_sink.error {
+            return Err(ModuleError::Backend(format!(
+                "can't emit function {}: {}",
+                name, message
+            )));
+        }
+
+        // The addends of COFF relocations are stored in the relocated field.
+        for reloc in &reloc_sink.relocs {
+            write_addend(&mut code, reloc.offset, reloc.kind, reloc.addend);
+        }
+
+        let offset = self
+            .object
+            .define(name, SectionId::Text, &code, FUNCTION_ALIGNMENT);
+        for reloc in reloc_sink.relocs {
+            if !self.object.is_declared(&reloc.name) {
+                // Libcalls are imported on first use.
+                self.object.declare(&reloc.name, true, true);
+            }
+            self.object.reloc(
+                SectionId::Text,
+                offset + reloc.offset,
+                &reloc.name,
+                reloc.kind,
+            );
+        }
+
+        Ok(CoffCompiledFunction {
+            code_length: total_size,
+        })
+    }
+
+    fn define_data(
+        &mut self,
+        _id: DataId,
+        name: &str,
+        writable: bool,
+        align: Option<u8>,
+        data_ctx: &DataContext,
+        namespace: &ModuleNamespace<Self>,
+    ) -> ModuleResult<CoffCompiledData> {
+        let &DataDescription {
+            ref init,
+            ref function_decls,
+            ref data_decls,
+            ref function_relocs,
+            ref data_relocs,
+        } = data_ctx.description();
+
+        let size = init.size();
+        let mut bytes = Vec::with_capacity(size);
+        match *init {
+            Init::Uninitialized => {
+                panic!("data is not initialized yet");
+            }
+            Init::Zeros { .. } => {
+                bytes.resize(size, 0);
+            }
+            Init::Bytes { ref contents } => {
+                bytes.extend_from_slice(contents);
+            }
+        }
+
+        let mut function_names = PrimaryMap::new();
+        for name in function_decls.values() {
+            function_names.push(namespace.get_function_decl(name).name.clone());
+        }
+        let mut data_names = PrimaryMap::new();
+        for name in data_decls.values() {
+            data_names.push(namespace.get_data_decl(name).name.clone());
+        }
+
+        let mut relocs = Vec::new();
+        for &(offset, id) in function_relocs {
+            relocs.push((offset, &function_names[id], 0));
+        }
+        for &(offset, id, addend) in data_relocs {
+            relocs.push((offset, &data_names[id], addend));
+        }
+        for &(offset, _, addend) in &relocs {
+            write_addend(&mut bytes, offset, IMAGE_REL_AMD64_ADDR64, addend);
+        }
+
+        let section = if writable {
+            SectionId::Data
+        } else {
+            SectionId::Rdata
+        };
+        let align = align.map_or(1, u32::from);
+        let data_offset = self.object.define(name, section, &bytes, align);
+        for (offset, to, _) in relocs {
+            self.object
+                .reloc(section, data_offset + offset, to, IMAGE_REL_AMD64_ADDR64);
+        }
+        Ok(CoffCompiledData {
+            section,
+            offset: data_offset,
+            function_names,
+            data_names,
+        })
+    }
+
+    fn write_data_funcaddr(
+        &mut self,
+        data: &mut CoffCompiledData,
+        offset: usize,
+        what: ir::FuncRef,
+    ) {
+        let name = &data.function_names[what];
+        self.write_data_reloc(data, offset, name, 0);
+    }
+
+    fn write_data_dataaddr(
+        &mut self,
+        data: &mut CoffCompiledData,
+        offset: usize,
+        what: ir::GlobalValue,
+        addend: binemit::Addend,
+    ) {
+        let name = &data.data_names[what];
+        self.write_data_reloc(data, offset, name, addend);
+    }
+
+    fn finalize_function(
+        &mut self,
+        _func: &CoffCompiledFunction,
+        _namespace: &ModuleNamespace<Self>,
+    ) {
+        // Nothing to do.
+    }
+
+    fn get_finalized_function(&self, _func: &CoffCompiledFunction) {
+        // Nothing to do.
+    }
+
+    fn finalize_data(&mut self, _data: &CoffCompiledData, _namespace: &ModuleNamespace<Self>) {
+        // Nothing to do.
+    }
+
+    fn get_finalized_data(&self, _data: &CoffCompiledData) {
+        // Nothing to do.
+    }
+
+    fn publish(&mut self) {
+        // Nothing to do.
+    }
+
+    fn finish(self) -> CoffProduct {
+        CoffProduct {
+            name: self.name,
+            object: self.object,
+        }
+    }
+}
+
+impl CoffBackend {
+    /// Relocate the 8 bytes at `offset` in `data` to the address of `name` plus `addend`.
+    fn write_data_reloc(
+        &mut self,
+        data: &CoffCompiledData,
+        offset: usize,
+        name: &str,
+        addend: binemit::Addend,
+    ) {
+        let offset = data.offset + offset as u32;
+        write_addend(
+            self.object.data_mut(data.section),
+            offset,
+            IMAGE_REL_AMD64_ADDR64,
+            addend,
+        );
+        self.object
+            .reloc(data.section, offset, name, IMAGE_REL_AMD64_ADDR64);
+    }
+}
+
+/// This is the output of `Module`'s
+/// [`finish`](../cranelift_module/struct.Module.html#method.finish) function.
+/// It provides functions for writing out the object file to memory or a file.
+pub struct CoffProduct {
+    name: String,
+    object: CoffObject,
+}
+
+impl CoffProduct {
+    /// Return the name of the output file. This is the name passed into `new`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Encode the object file in memory.
+    pub fn emit(&self) -> Vec<u8> {
+        self.object.emit()
+    }
+
+    /// Write the object file to a file.
+    pub fn write(&self, mut sink: File) -> io::Result<()> {
+        sink.write_all(&self.object.emit())
+    }
+}
+
+/// Are symbols with `linkage` visible outside of the object?
+///
+/// COFF has no symbol preemption, so preemptible symbols are simply external.
+fn translate_linkage(linkage: Linkage) -> bool {
+    match linkage {
+        Linkage::Local => false,
+        Linkage::Import | Linkage::Export | Linkage::Preemptible => true,
+    }
+}
+
+/// Store the implicit addend of a relocation of `kind` at `offset` in `bytes`.
+fn write_addend(bytes: &mut [u8], offset: CodeOffset, kind: u16, addend: Addend) {
+    let offset = offset as usize;
+    match kind {
+        IMAGE_REL_AMD64_ADDR64 => {
+            bytes[offset..offset + 8].copy_from_slice(&addend.to_le_bytes());
+        }
+        IMAGE_REL_AMD64_ADDR32 | IMAGE_REL_AMD64_REL32 => {
+            let addend = addend as i32;
+            bytes[offset..offset + 4].copy_from_slice(&addend.to_le_bytes());
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// A relocation in a function, before it's added to the object.
+struct CoffReloc {
+    offset: CodeOffset,
+    name: String,
+    kind: u16,
+    addend: Addend,
+}
+
+struct CoffRelocSink<'a> {
+    namespace: &'a ModuleNamespace<'a, CoffBackend>,
+    libcall_names: &'a dyn Fn(ir::LibCall) -> String,
+    relocs: Vec<CoffReloc>,
+    /// Why the first relocation which can't be represented in COFF was rejected, if any.
+    error: Option<String>,
+}
+
+impl<'a> CoffRelocSink<'a> {
+    /// Reject a relocation, unless an earlier one was rejected already.
+    fn reject(&mut self, message: String) {
+        if self.error.is_none() {
+            self.error = Some(message);
+        }
+    }
+}
+
+impl<'a> RelocSink for CoffRelocSink<'a> {
+    fn reloc_ebb(&mut self, offset: CodeOffset, reloc: Reloc, _ebb_offset: CodeOffset) {
+        self.reject(format!(
+            "unsupported {} relocation of an EBB at {:#x}",
+            reloc, offset
+        ));
+    }
+
+    fn reloc_external(
+        &mut self,
+        offset: CodeOffset,
+        reloc: Reloc,
+        name: &ir::ExternalName,
+        addend: Addend,
+    ) {
+        let ref_name: String = match *name {
+            ir::ExternalName::User { .. } => {
+                if self.namespace.is_function(name) {
+                    self.namespace.get_function_decl(name).name.clone()
+                } else {
+                    self.namespace.get_data_decl(name).name.clone()
+                }
+            }
+            ir::ExternalName::LibCall(ref libcall) => (self.libcall_names)(*libcall),
+            _ => panic!("invalid ExternalName {}", name),
+        };
+        let (kind, addend) = match reloc {
+            Reloc::Abs4 => (IMAGE_REL_AMD64_ADDR32, addend),
+            Reloc::Abs8 => (IMAGE_REL_AMD64_ADDR64, addend),
+            // PC-relative relocations are relative to the end of the 4-byte field, while
+            // Cranelift's addends are relative to its start.
+            Reloc::X86PCRel4 | Reloc::X86CallPCRel4 | Reloc::X86CallPLTRel4 => {
+                (IMAGE_REL_AMD64_REL32, addend + 4)
+            }
+            _ => {
+                return self.reject(format!(
+                    "unsupported {} relocation of {} at {:#x}",
+                    reloc, ref_name, offset
+                ))
+            }
+        };
+        // The addend is stored in the relocated field, so it must fit in it.
+        if kind != IMAGE_REL_AMD64_ADDR64 && i64::from(addend as i32) != addend {
+            return self.reject(format!(
+                "the addend {} of the {} relocation of {} at {:#x} doesn't fit in 32 bits",
+                addend, reloc, ref_name, offset
+            ));
+        }
+        self.relocs.push(CoffReloc {
+            offset,
+            name: ref_name,
+            kind,
+            addend,
+        });
+    }
+
+    fn reloc_jt(&mut self, _offset: CodeOffset, reloc: Reloc, _jt: ir::JumpTable) {
+        match reloc {
+            Reloc::X86PCRelRodata4 => {
+                // Not necessary to record this unless we are going to split apart code and its
+                // jumptbl/rodata.
+            }
+            _ => {
+                panic!("Unhandled reloc");
+            }
+        }
+    }
+
+    fn reloc_constant(&mut self, _offset: CodeOffset, reloc: Reloc, _jt: ir::ConstantOffset) {
+        match reloc {
+            Reloc::X86PCRelRodata4 => {
+                // Not necessary to record this unless we are going to split apart code and its
+                // jumptbl/rodata.
+            }
+            _ => {
+                panic!("Unhandled reloc");
+            }
+        }
+    }
+}
@@ -0,0 +1,386 @@
+//! A writer for x86-64 COFF object files.
+//!
+//! See the "PE Format" documentation at
+//! https://docs.microsoft.com/en-us/windows/win32/debug/pe-format for a description of the
+//! structures written here.
+
+use std::collections::HashMap;
+
+/// The machine type of x86-64 objects.
+const IMAGE_FILE_MACHINE_AMD64: u16 = 0x8664;
+
+/// Section characteristics.
+const IMAGE_SCN_CNT_CODE: u32 = 0x0000_0020;
+const IMAGE_SCN_CNT_INITIALIZED_DATA: u32 = 0x0000_0040;
+const IMAGE_SCN_LNK_NRELOC_OVFL: u32 = 0x0100_0000;
+const IMAGE_SCN_MEM_EXECUTE: u32 = 0x2000_0000;
+const IMAGE_SCN_MEM_READ: u32 = 0x4000_0000;
+const IMAGE_SCN_MEM_WRITE: u32 = 0x8000_0000;
+
+/// Symbol section numbers, types and storage classes.
+const IMAGE_SYM_UNDEFINED: i16 = 0;
+const IMAGE_SYM_DTYPE_FUNCTION: u16 = 0x20;
+const IMAGE_SYM_CLASS_EXTERNAL: u8 = 2;
+const IMAGE_SYM_CLASS_STATIC: u8 = 3;
+
+/// x86-64 relocation types.
+pub const IMAGE_REL_AMD64_ADDR64: u16 = 0x0001;
+pub const IMAGE_REL_AMD64_ADDR32: u16 = 0x0002;
+pub const IMAGE_REL_AMD64_REL32: u16 = 0x0004;
+
+/// Sizes of the structures.
+const FILE_HEADER_SIZE: usize = 20;
+const SECTION_HEADER_SIZE: usize = 40;
+const RELOCATION_SIZE: usize = 10;
+const SYMBOL_SIZE: usize = 18;
+
+/// The sections of an object file.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SectionId {
+    /// Code.
+    Text,
+    /// Writable data.
+    Data,
+    /// Read-only data.
+    Rdata,
+}
+
+impl SectionId {
+    /// All the sections, in the order they appear in the object file.
+    const ALL: [SectionId; 3] = [SectionId::Text, SectionId::Data, SectionId::Rdata];
+
+    /// The one-based section number used by symbols.
+    fn number(self) -> i16 {
+        self as i16 + 1
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            SectionId::Text => ".text",
+            SectionId::Data => ".data",
+            SectionId::Rdata => ".rdata",
+        }
+    }
+
+    fn characteristics(self) -> u32 {
+        match self {
+            SectionId::Text => IMAGE_SCN_CNT_CODE | IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_READ,
+            SectionId::Data => {
+                IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ | IMAGE_SCN_MEM_WRITE
+            }
+            SectionId::Rdata => IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ,
+        }
+    }
+
+    /// The byte used to pad between the objects of the section.
+    fn fill(self) -> u8 {
+        match self {
+            // `int3`.
+            SectionId::Text => 0xcc,
+            SectionId::Data | SectionId::Rdata => 0,
+        }
+    }
+}
+
+/// A relocation in a section.
+struct Relocation {
+    offset: u32,
+    symbol: usize,
+    kind: u16,
+}
+
+/// The contents of a section.
+struct Section {
+    data: Vec<u8>,
+    relocs: Vec<Relocation>,
+    align: u32,
+}
+
+/// A symbol, which may be defined in a section of the object.
+struct Symbol {
+    name: String,
+    definition: Option<(SectionId, u32)>,
+    external: bool,
+    function: bool,
+}
+
+/// An x86-64 COFF object file under construction.
+pub struct CoffObject {
+    sections: [Section; 3],
+    symbols: Vec<Symbol>,
+    symbol_indices: HashMap<String, usize>,
+}
+
+impl CoffObject {
+    /// Create an empty object.
+    pub fn new() -> Self {
+        let section = || Section {
+            data: Vec::new(),
+            relocs: Vec::new(),
+            align: 1,
+        };
+        Self {
+            sections: [section(), section(), section()],
+            symbols: Vec::new(),
+            symbol_indices: HashMap::new(),
+        }
+    }
+
+    fn section_mut(&mut self, id: SectionId) -> &mut Section {
+        &mut self.sections[id as usize]
+    }
+
+    /// Declare the symbol `name`, or update the declaration of an existing symbol.
+    ///
+    /// External symbols are visible to other objects, and undefined ones are imported.
+    pub fn declare(&mut self, name: &str, external: bool, function: bool) -> usize {
+        if let Some(&index) = self.symbol_indices.get(name) {
+            let symbol = &mut self.symbols[index];
+            symbol.external = external;
+            symbol.function = function;
+            return index;
+        }
+        let index = self.symbols.len();
+        self.symbols.push(Symbol {
+            name: name.to_owned(),
+            definition: None,
+            external,
+            function,
+        });
+        self.symbol_indices.insert(name.to_owned(), index);
+        index
+    }
+
+    /// Is `name` a declared symbol?
+    pub fn is_declared(&self, name: &str) -> bool {
+        self.symbol_indices.contains_key(name)
+    }
+
+    /// Append `bytes` at an offset aligned to `align` in the section `id`, and define the
+    /// declared symbol `name` there. Return the offset.
+    pub fn define(&mut self, name: &str, id: SectionId, bytes: &[u8], align: u32) -> u32 {
+        debug_assert!(align.is_power_of_two() && align <= 8192);
+        let fill = id.fill();
+        let section = self.section_mut(id);
+        while section.data.len() % align as usize != 0 {
+            section.data.push(fill);
+        }
+        let offset = section.data.len() as u32;
+        section.data.extend_from_slice(bytes);
+        section.align = section.align.max(align);
+
+        let index = self.symbol_indices[name];
+        debug_assert!(self.symbols[index].definition.is_none());
+        self.symbols[index].definition = Some((id, offset));
+        offset
+    }
+
+    /// Get the contents of the section `id`, so that addends can be stored in place.
+    pub fn data_mut(&mut self, id: SectionId) -> &mut [u8] {
+        &mut self.section_mut(id).data
+    }
+
+    /// Add a relocation of `kind` at `offset` in the section `id`, referring to the declared
+    /// symbol `name`. The addend must already be stored at `offset`.
+    pub fn reloc(&mut self, id: SectionId, offset: u32, name: &str, kind: u16) {
+        let symbol = self.symbol_indices[name];
+        self.section_mut(id).relocs.push(Relocation {
+            offset,
+            symbol,
+            kind,
+        });
+    }
+
+    /// Encode the object file.
+    pub fn emit(&self) -> Vec<u8> {
+        let num_sections = SectionId::ALL.len();
+        // Each section has a symbol with an auxiliary record, and they come first.
+        let first_symbol = 2 * num_sections;
+
+        // Lay out the raw data and relocations of each section after the headers.
+        let mut layout = Vec::new();
+        let mut offset = FILE_HEADER_SIZE + num_sections * SECTION_HEADER_SIZE;
+        for section in &self.sections {
+            let data_offset = offset;
+            offset += section.data.len();
+            offset = align4(offset);
+            let relocs_offset = offset;
+            let num_relocs = num_relocations(section);
+            offset += num_relocs * RELOCATION_SIZE;
+            layout.push((data_offset, relocs_offset, num_relocs));
+        }
+        let symtab_offset = offset;
+        let num_symbols = first_symbol + self.symbols.len();
+
+        let mut strtab = StringTable::default();
+        let mut buf = Vec::new();
+
+        // The file header.
+        put_u16(&mut buf, IMAGE_FILE_MACHINE_AMD64);
+        put_u16(&mut buf, num_sections as u16);
+        put_u32(&mut buf, 0); // TimeDateStamp.
+        put_u32(&mut buf, symtab_offset as u32);
+        put_u32(&mut buf, num_symbols as u32);
+        put_u16(&mut buf, 0); // SizeOfOptionalHeader.
+        put_u16(&mut buf, 0); // Characteristics.
+
+        // The section headers.
+        for (&id, (section, &(data_offset, relocs_offset, num_relocs))) in SectionId::ALL
+            .iter()
+            .zip(self.sections.iter().zip(layout.iter()))
+        {
+            let mut characteristics = id.characteristics() | align_characteristics(section.align);
+            if num_relocs > 0xffff {
+                characteristics |= IMAGE_SCN_LNK_NRELOC_OVFL;
+            }
+            put_name(&mut buf, id.name(), &mut strtab);
+            put_u32(&mut buf, 0); // VirtualSize.
+            put_u32(&mut buf, 0); // VirtualAddress.
+            put_u32(&mut buf, section.data.len() as u32);
+            put_u32(
+                &mut buf,
+                if section.data.is_empty() {
+                    0
+                } else {
+                    data_offset as u32
+                },
+            );
+            put_u32(
+                &mut buf,
+                if num_relocs > 0 {
+                    relocs_offset as u32
+                } else {
+                    0
+                },
+            );
+            put_u32(&mut buf, 0); // PointerToLinenumbers.
+            put_u16(&mut buf, num_relocs.min(0xffff) as u16);
+            put_u16(&mut buf, 0); // NumberOfLinenumbers.
+            put_u32(&mut buf, characteristics);
+        }
+
+        // The raw data and relocations.
+        for (section, &(_, relocs_offset, num_relocs)) in self.sections.iter().zip(layout.iter()) {
+            buf.extend_from_slice(&section.data);
+            while buf.len() < relocs_offset {
+                buf.push(0);
+            }
+            if num_relocs > section.relocs.len() {
+                // With too many relocations for the header, the first one holds the count.
+                put_u32(&mut buf, num_relocs as u32);
+                put_u32(&mut buf, 0);
+                put_u16(&mut buf, 0);
+            }
+            for reloc in &section.relocs {
+                put_u32(&mut buf, reloc.offset);
+                put_u32(&mut buf, (first_symbol + reloc.symbol) as u32);
+                put_u16(&mut buf, reloc.kind);
+            }
+        }
+        debug_assert_eq!(buf.len(), symtab_offset);
+
+        // The section symbols, with their auxiliary section definition records.
+        for (&id, section) in SectionId::ALL.iter().zip(self.sections.iter()) {
+            put_name(&mut buf, id.name(), &mut strtab);
+            put_u32(&mut buf, 0);
+            put_u16(&mut buf, id.number() as u16);
+            put_u16(&mut buf, 0);
+            buf.push(IMAGE_SYM_CLASS_STATIC);
+            buf.push(1);
+
+            put_u32(&mut buf, section.data.len() as u32);
+            put_u16(&mut buf, section.relocs.len().min(0xffff) as u16);
+            put_u16(&mut buf, 0); // NumberOfLinenumbers.
+            put_u32(&mut buf, 0); // CheckSum.
+            put_u16(&mut buf, 0); // Number.
+            buf.push(0); // Selection.
+            buf.extend_from_slice(&[0; 3]);
+        }
+
+        for symbol in &self.symbols {
+            let (section_number, value) = match symbol.definition {
+                Some((id, offset)) => (id.number(), offset),
+                None => (IMAGE_SYM_UNDEFINED, 0),
+            };
+            // Undefined symbols are always external.
+            let storage_class = if symbol.external || symbol.definition.is_none() {
+                IMAGE_SYM_CLASS_EXTERNAL
+            } else {
+                IMAGE_SYM_CLASS_STATIC
+            };
+            put_name(&mut buf, &symbol.name, &mut strtab);
+            put_u32(&mut buf, value);
+            put_u16(&mut buf, section_number as u16);
+            put_u16(
+                &mut buf,
+                if symbol.function {
+                    IMAGE_SYM_DTYPE_FUNCTION
+                } else {
+                    0
+                },
+            );
+            buf.push(storage_class);
+            buf.push(0);
+        }
+        debug_assert_eq!(buf.len(), symtab_offset + num_symbols * SYMBOL_SIZE);
+
+        // The string table starts with its size, including the size field.
+        put_u32(&mut buf, strtab.data.len() as u32 + 4);
+        buf.extend_from_slice(&strtab.data);
+        buf
+    }
+}
+
+/// Long names, which are referred to by their offset from the start of the string table.
+#[derive(Default)]
+struct StringTable {
+    data: Vec<u8>,
+}
+
+impl StringTable {
+    fn add(&mut self, name: &str) -> u32 {
+        let offset = self.data.len() as u32 + 4;
+        self.data.extend_from_slice(name.as_bytes());
+        self.data.push(0);
+        offset
+    }
+}
+
+/// Number of relocation records of `section`, including the one holding an overflowed count.
+fn num_relocations(section: &Section) -> usize {
+    if section.relocs.len() >= 0xffff {
+        section.relocs.len() + 1
+    } else {
+        section.relocs.len()
+    }
+}
+
+/// The `IMAGE_SCN_ALIGN_*` characteristics for an alignment of `align` bytes.
+fn align_characteristics(align: u32) -> u32 {
+    (align.trailing_zeros() + 1) << 20
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+/// Write an 8-byte symbol or section name. Longer names are moved to the string table, and
+/// replaced by four zero bytes followed by their offset. Section names are always short.
+fn put_name(buf: &mut Vec<u8>, name: &str, strtab: &mut StringTable) {
+    let bytes = name.as_bytes();
+    if bytes.len() <= 8 {
+        buf.extend_from_slice(bytes);
+        buf.extend_from_slice(&[0; 8][bytes.len()..]);
+    } else {
+        put_u32(buf, 0);
+        put_u32(buf, strtab.add(name));
+    }
+}
+
+fn put_u16(buf: &mut Vec<u8>, value: u16) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
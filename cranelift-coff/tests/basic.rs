@@ -0,0 +1,326 @@
+use cranelift_codegen::ir::*;
+use cranelift_codegen::isa;
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_codegen::Context;
+use cranelift_coff::*;
+use cranelift_frontend::*;
+use cranelift_module::*;
+use std::str::FromStr;
+use target_lexicon::Triple;
+
+const IMAGE_REL_AMD64_ADDR64: u16 = 1;
+const IMAGE_REL_AMD64_REL32: u16 = 4;
+
+/// A parsed COFF object file.
+struct Object {
+    machine: u16,
+    sections: Vec<Section>,
+    symbols: Vec<Symbol>,
+}
+
+struct Section {
+    name: String,
+    data: Vec<u8>,
+    relocs: Vec<(u32, u32, u16)>,
+}
+
+struct Symbol {
+    name: String,
+    value: u32,
+    section: i16,
+    storage_class: u8,
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    let mut b = [0; 4];
+    b.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_le_bytes(b)
+}
+
+fn c_str(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8(bytes[..end].to_vec()).unwrap()
+}
+
+fn parse(bytes: &[u8]) -> Object {
+    let machine = u16_at(bytes, 0);
+    let num_sections = u16_at(bytes, 2) as usize;
+    let symtab = u32_at(bytes, 8) as usize;
+    let num_symbols = u32_at(bytes, 12) as usize;
+    assert_eq!(u16_at(bytes, 16), 0, "no optional header");
+    let strtab = symtab + num_symbols * 18;
+    assert_eq!(u32_at(bytes, strtab) as usize, bytes.len() - strtab);
+
+    let name_at = |offset: usize| {
+        if u32_at(bytes, offset) == 0 {
+            c_str(&bytes[strtab + u32_at(bytes, offset + 4) as usize..])
+        } else {
+            c_str(&bytes[offset..offset + 8])
+        }
+    };
+
+    let mut sections = Vec::new();
+    for i in 0..num_sections {
+        let header = 20 + i * 40;
+        let size = u32_at(bytes, header + 16) as usize;
+        let data_offset = u32_at(bytes, header + 20) as usize;
+        let relocs_offset = u32_at(bytes, header + 24) as usize;
+        let num_relocs = u16_at(bytes, header + 32) as usize;
+        let relocs = (0..num_relocs)
+            .map(|r| {
+                let reloc = relocs_offset + r * 10;
+                (
+                    u32_at(bytes, reloc),
+                    u32_at(bytes, reloc + 4),
+                    u16_at(bytes, reloc + 8),
+                )
+            })
+            .collect();
+        sections.push(Section {
+            name: name_at(header),
+            data: bytes[data_offset..data_offset + size].to_vec(),
+            relocs,
+        });
+    }
+
+    let mut symbols = Vec::new();
+    let mut i = 0;
+    while i < num_symbols {
+        let symbol = symtab + i * 18;
+        symbols.push(Symbol {
+            name: name_at(symbol),
+            value: u32_at(bytes, symbol + 8),
+            section: u16_at(bytes, symbol + 12) as i16,
+            storage_class: bytes[symbol + 16],
+        });
+        // Auxiliary records are skipped, but keep the symbol indices intact.
+        for _ in 0..bytes[symbol + 17] {
+            symbols.push(Symbol {
+                name: String::new(),
+                value: 0,
+                section: 0,
+                storage_class: 0,
+            });
+            i += 1;
+        }
+        i += 1;
+    }
+
+    Object {
+        machine,
+        sections,
+        symbols,
+    }
+}
+
+impl Object {
+    fn section(&self, name: &str) -> &Section {
+        self.sections.iter().find(|s| s.name == name).unwrap()
+    }
+
+    fn symbol(&self, name: &str) -> (u32, &Symbol) {
+        let index = self.symbols.iter().position(|s| s.name == name).unwrap();
+        (index as u32, &self.symbols[index])
+    }
+}
+
+fn windows_isa() -> Box<dyn isa::TargetIsa> {
+    let mut flag_builder = settings::builder();
+    flag_builder.set("is_pic", "false").unwrap();
+    isa::lookup(Triple::from_str("x86_64-pc-windows-msvc").unwrap())
+        .unwrap()
+        .finish(settings::Flags::new(flag_builder))
+}
+
+fn new_module() -> Module<CoffBackend> {
+    Module::new(
+        CoffBuilder::new(
+            windows_isa(),
+            "test.obj".to_owned(),
+            default_libcall_names(),
+        )
+        .unwrap(),
+    )
+}
+
+#[test]
+fn requires_non_pic_x86_64() {
+    let mut flag_builder = settings::builder();
+    flag_builder.set("is_pic", "true").unwrap();
+    let isa = isa::lookup(Triple::from_str("x86_64-pc-windows-msvc").unwrap())
+        .unwrap()
+        .finish(settings::Flags::new(flag_builder));
+    assert!(CoffBuilder::new(isa, "test.obj".to_owned(), default_libcall_names()).is_err());
+}
+
+#[test]
+fn functions_data_and_relocations() {
+    let mut module = new_module();
+    let sig = module.make_signature();
+
+    let imported = module
+        .declare_function("imported_function", Linkage::Import, &sig)
+        .unwrap();
+    let helper = module
+        .declare_function("helper", Linkage::Local, &sig)
+        .unwrap();
+    let entry = module
+        .declare_function("entry", Linkage::Export, &sig)
+        .unwrap();
+    let counter = module
+        .declare_data("counter", Linkage::Export, true, Some(8))
+        .unwrap();
+    let table = module
+        .declare_data("function_table", Linkage::Local, false, None)
+        .unwrap();
+
+    let mut ctx = Context::new();
+
+    // `helper` just returns.
+    ctx.func = {
+        let func =
+            Function::with_name_signature(ExternalName::user(0, helper.as_u32()), sig.clone());
+        let mut bcx = FunctionBuilder::new(func);
+        let ebb = bcx.create_ebb();
+        bcx.switch_to_block(ebb);
+        bcx.ins().return_(&[]);
+        bcx.seal_all_blocks();
+        bcx.finalize()
+    };
+    module.define_function(helper, &mut ctx).unwrap();
+    module.clear_context(&mut ctx);
+
+    // `entry` calls both functions and takes the address of `counter`.
+    ctx.func = {
+        let func =
+            Function::with_name_signature(ExternalName::user(0, entry.as_u32()), sig.clone());
+        let mut bcx = FunctionBuilder::new(func);
+        let ebb = bcx.create_ebb();
+        bcx.switch_to_block(ebb);
+        let local_callee = module.declare_func_in_func(helper, &mut bcx.func);
+        let imported_callee = module.declare_func_in_func(imported, &mut bcx.func);
+        let counter_gv = module.declare_data_in_func(counter, &mut bcx.func);
+        bcx.ins().call(local_callee, &[]);
+        bcx.ins().call(imported_callee, &[]);
+        let addr = bcx.ins().symbol_value(types::I64, counter_gv);
+        let zero = bcx.ins().iconst(types::I64, 0);
+        bcx.ins().store(MemFlags::new(), zero, addr, 0);
+        bcx.ins().return_(&[]);
+        bcx.seal_all_blocks();
+        bcx.finalize()
+    };
+    module.define_function(entry, &mut ctx).unwrap();
+    module.clear_context(&mut ctx);
+
+    let mut data_ctx = DataContext::new();
+    data_ctx.define_zeroinit(8);
+    module.define_data(counter, &data_ctx).unwrap();
+    data_ctx.clear();
+
+    // `function_table` holds the address of `entry` and of `counter` plus 4.
+    data_ctx.define_zeroinit(16);
+    let entry_ref = module.declare_func_in_data(entry, &mut data_ctx);
+    let counter_ref = module.declare_data_in_data(counter, &mut data_ctx);
+    data_ctx.write_function_addr(0, entry_ref);
+    data_ctx.write_data_addr(8, counter_ref, 4);
+    module.define_data(table, &data_ctx).unwrap();
+
+    module.finalize_definitions();
+    let product = module.finish();
+    assert_eq!(product.name(), "test.obj");
+    let object = parse(&product.emit());
+
+    assert_eq!(object.machine, 0x8664);
+    let names: Vec<_> = object.sections.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, [".text", ".data", ".rdata"]);
+
+    // Symbols are defined in the right sections, with the right visibility.
+    let (_, helper_sym) = object.symbol("helper");
+    assert_eq!((helper_sym.section, helper_sym.value), (1, 0));
+    assert_eq!(helper_sym.storage_class, 3);
+    let (entry_index, entry_sym) = object.symbol("entry");
+    assert_eq!((entry_sym.section, entry_sym.value), (1, 16));
+    assert_eq!(entry_sym.storage_class, 2);
+    let (counter_index, counter_sym) = object.symbol("counter");
+    assert_eq!((counter_sym.section, counter_sym.value), (2, 0));
+    let (_, table_sym) = object.symbol("function_table");
+    assert_eq!((table_sym.section, table_sym.value), (3, 0));
+    let (imported_index, imported_sym) = object.symbol("imported_function");
+    assert_eq!((imported_sym.section, imported_sym.storage_class), (0, 2));
+
+    // The call to `helper` is PC-relative, and the rest are absolute.
+    let text = object.section(".text");
+    let (helper_index, _) = object.symbol("helper");
+    let kinds: Vec<_> = text
+        .relocs
+        .iter()
+        .map(|&(_, sym, kind)| (sym, kind))
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            (helper_index, IMAGE_REL_AMD64_REL32),
+            (imported_index, IMAGE_REL_AMD64_ADDR64),
+            (counter_index, IMAGE_REL_AMD64_ADDR64),
+        ]
+    );
+    let (call_offset, _, _) = text.relocs[0];
+    assert_eq!(text.data[call_offset as usize - 1], 0xe8);
+    assert_eq!(&text.data[call_offset as usize..][..4], &[0; 4]);
+
+    // The data relocations store their addends in place.
+    let rdata = object.section(".rdata");
+    assert_eq!(
+        rdata.relocs,
+        [
+            (0, entry_index, IMAGE_REL_AMD64_ADDR64),
+            (8, counter_index, IMAGE_REL_AMD64_ADDR64),
+        ]
+    );
+    assert_eq!(&rdata.data[8..], &[4, 0, 0, 0, 0, 0, 0, 0]);
+}
+
+#[test]
+fn write_addresses_into_defined_data() {
+    let mut module = new_module();
+    let sig = module.make_signature();
+
+    let imported = module
+        .declare_function("imported_function", Linkage::Import, &sig)
+        .unwrap();
+    let counter = module
+        .declare_data("counter", Linkage::Import, true, None)
+        .unwrap();
+    let table = module
+        .declare_data("table", Linkage::Export, true, Some(8))
+        .unwrap();
+
+    let mut data_ctx = DataContext::new();
+    data_ctx.define_zeroinit(24);
+    let func_ref = module.declare_func_in_data(imported, &mut data_ctx);
+    let data_ref = module.declare_data_in_data(counter, &mut data_ctx);
+    module.define_data(table, &data_ctx).unwrap();
+
+    // The addresses are written after the data object is defined.
+    module.write_data_funcaddr(table, 8, func_ref);
+    module.write_data_dataaddr(table, 16, data_ref, 12);
+
+    module.finalize_definitions();
+    let object = parse(&module.finish().emit());
+    let (imported_index, _) = object.symbol("imported_function");
+    let (counter_index, _) = object.symbol("counter");
+    let data = object.section(".data");
+    assert_eq!(
+        data.relocs,
+        [
+            (8, imported_index, IMAGE_REL_AMD64_ADDR64),
+            (16, counter_index, IMAGE_REL_AMD64_ADDR64),
+        ]
+    );
+    assert_eq!(&data.data[..16], &[0; 16]);
+    assert_eq!(&data.data[16..], &[12, 0, 0, 0, 0, 0, 0, 0]);
+}
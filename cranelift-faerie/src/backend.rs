@@ -5,11 +5,12 @@ use crate::traps::{FaerieTrapManifest, FaerieTrapSink};
 use cranelift_codegen::binemit::{
     Addend, CodeOffset, NullStackmapSink, NullTrapSink, Reloc, RelocSink, Stackmap, StackmapSink,
 };
+use cranelift_codegen::debuginfo::{DebugInfo, DwarfRelocTarget, FunctionDebugInfo};
 use cranelift_codegen::isa::TargetIsa;
 use cranelift_codegen::{self, binemit, ir};
 use cranelift_module::{
-    Backend, DataContext, DataDescription, Init, Linkage, ModuleError, ModuleNamespace,
-    ModuleResult,
+    Backend, DataContext, DataDescription, DataId, FuncId, Init, Linkage, ModuleError,
+    ModuleNamespace, ModuleResult,
 };
 use faerie;
 use failure::Error;
@@ -33,6 +34,7 @@ pub struct FaerieBuilder {
     name: String,
     collect_traps: FaerieTrapCollection,
     libcall_names: Box<dyn Fn(ir::LibCall) -> String>,
+    debug_info: Option<(String, String)>,
 }
 
 impl FaerieBuilder {
@@ -65,8 +67,19 @@ impl FaerieBuilder {
             name,
             collect_traps,
             libcall_names,
+            debug_info: None,
         })
     }
+
+    /// Emit DWARF debug information for the functions of the module, as a compilation unit for
+    /// the source file `file_name` in the directory `comp_dir`.
+    ///
+    /// The source locations of the functions are used as line numbers in `file_name`, and their
+    /// value labels become variables named after the labels.
+    pub fn debug_info(&mut self, file_name: &str, comp_dir: &str) -> &mut Self {
+        self.debug_info = Some((file_name.to_owned(), comp_dir.to_owned()));
+        self
+    }
 }
 
 /// A `FaerieBackend` implements `Backend` and emits ".o" files using the `faerie` library.
@@ -77,6 +90,7 @@ pub struct FaerieBackend {
     artifact: faerie::Artifact,
     trap_manifest: Option<FaerieTrapManifest>,
     libcall_names: Box<dyn Fn(ir::LibCall) -> String>,
+    debug_info: Option<DebugInfo>,
 }
 
 pub struct FaerieCompiledFunction {
@@ -108,6 +122,12 @@ impl Backend for FaerieBackend {
 
     /// Create a new `FaerieBackend` using the given Cranelift target.
     fn new(builder: FaerieBuilder) -> Self {
+        let debug_info = match builder.debug_info {
+            Some((ref file_name, ref comp_dir)) => {
+                Some(DebugInfo::new(&*builder.isa, file_name, comp_dir))
+            }
+            None => None,
+        };
         Self {
             artifact: faerie::Artifact::new(builder.isa.triple().clone(), builder.name),
             isa: builder.isa,
@@ -116,6 +136,7 @@ impl Backend for FaerieBackend {
                 FaerieTrapCollection::Disabled => None,
             },
             libcall_names: builder.libcall_names,
+            debug_info,
         }
     }
 
@@ -123,13 +144,20 @@ impl Backend for FaerieBackend {
         &*self.isa
     }
 
-    fn declare_function(&mut self, name: &str, linkage: Linkage) {
+    fn declare_function(&mut self, _id: FuncId, name: &str, linkage: Linkage) {
         self.artifact
             .declare(name, translate_function_linkage(linkage))
             .expect("inconsistent declarations");
     }
 
-    fn declare_data(&mut self, name: &str, linkage: Linkage, writable: bool, align: Option<u8>) {
+    fn declare_data(
+        &mut self,
+        _id: DataId,
+        name: &str,
+        linkage: Linkage,
+        writable: bool,
+        align: Option<u8>,
+    ) {
         self.artifact
             .declare(name, translate_data_linkage(linkage, writable, align))
             .expect("inconsistent declarations");
@@ -137,6 +165,7 @@ impl Backend for FaerieBackend {
 
     fn define_function(
         &mut self,
+        _id: FuncId,
         name: &str,
         ctx: &cranelift_codegen::Context,
         namespace: &ModuleNamespace<Self>,
@@ -182,6 +211,21 @@ impl Backend for FaerieBackend {
             }
         }
 
+        if let Some(ref mut debug_info) = self.debug_info {
+            let ranges = ctx
+                .build_value_labels_ranges(&*self.isa)
+                .map_err(ModuleError::Compilation)?;
+            // The function's symbol covers its code as well as its jump tables and constants.
+            debug_info.add_function(FunctionDebugInfo::new(
+                name,
+                &ctx.func,
+                &*self.isa,
+                total_size,
+                &ranges,
+                &|label| label.to_string(),
+            ));
+        }
+
         // because `define` will take ownership of code, this is our last chance
         let code_length = code.len() as u32;
 
@@ -194,6 +238,7 @@ impl Backend for FaerieBackend {
 
     fn define_data(
         &mut self,
+        _id: DataId,
         name: &str,
         _writable: bool,
         _align: Option<u8>,
@@ -296,7 +341,13 @@ impl Backend for FaerieBackend {
         // Nothing to do.
     }
 
-    fn finish(self) -> FaerieProduct {
+    fn finish(mut self) -> FaerieProduct {
+        if let Some(ref debug_info) = self.debug_info {
+            if !debug_info.is_empty() {
+                emit_debug_sections(&mut self.artifact, debug_info);
+            }
+        }
+
         FaerieProduct {
             artifact: self.artifact,
             trap_manifest: self.trap_manifest,
@@ -332,6 +383,41 @@ impl FaerieProduct {
     }
 }
 
+/// Define the DWARF debug sections in `artifact`, along with their relocations.
+fn emit_debug_sections(artifact: &mut faerie::Artifact, debug_info: &DebugInfo) {
+    let sections = debug_info.emit();
+    for section in &sections {
+        artifact
+            .declare_with(
+                section.id.name(),
+                faerie::Decl::section(faerie::SectionKind::Debug),
+                section.data.clone(),
+            )
+            .expect("faerie declaration of debug section");
+    }
+    for section in &sections {
+        for reloc in &section.relocs {
+            let to = match reloc.target {
+                DwarfRelocTarget::Symbol(ref name) => name.as_str(),
+                DwarfRelocTarget::Section(id) => id.name(),
+            };
+            artifact
+                .link_with(
+                    faerie::Link {
+                        from: section.id.name(),
+                        to,
+                        at: u64::from(reloc.offset),
+                    },
+                    faerie::Reloc::Debug {
+                        size: reloc.size,
+                        addend: reloc.addend as i32,
+                    },
+                )
+                .expect("faerie relocation error");
+        }
+    }
+}
+
 fn translate_function_linkage(linkage: Linkage) -> faerie::Decl {
     match linkage {
         Linkage::Import => faerie::Decl::function_import().into(),
@@ -12,6 +12,13 @@ pub enum Format {
     MachO,
 }
 
+/// RISC-V ELF relocation types, from the RISC-V ELF psABI.
+mod riscv {
+    pub const R_RISCV_32: u32 = 1;
+    pub const R_RISCV_64: u32 = 2;
+    pub const R_RISCV_JAL: u32 = 17;
+}
+
 /// Translate from a Cranelift `Reloc` to a raw object-file-format-specific
 /// relocation code and relocation-implied addend.
 pub fn raw_relocation(reloc: Reloc, triple: &Triple) -> (u32, i64) {
@@ -32,6 +39,18 @@ pub fn raw_relocation(reloc: Reloc, triple: &Triple) -> (u32, i64) {
                             _ => unimplemented!(),
                         }
                     }
+                    Architecture::Aarch64 { .. } => match reloc {
+                        Reloc::Abs4 => elf::reloc::R_AARCH64_ABS32,
+                        Reloc::Abs8 => elf::reloc::R_AARCH64_ABS64,
+                        Reloc::Arm64Call => elf::reloc::R_AARCH64_CALL26,
+                        _ => unimplemented!("unsupported aarch64 reloc: {}", reloc),
+                    },
+                    Architecture::Riscv64 => match reloc {
+                        Reloc::Abs4 => riscv::R_RISCV_32,
+                        Reloc::Abs8 => riscv::R_RISCV_64,
+                        Reloc::RiscvCall => riscv::R_RISCV_JAL,
+                        _ => unimplemented!("unsupported riscv64 reloc: {}", reloc),
+                    },
                     _ => unimplemented!("unsupported architecture: {}", triple),
                 },
                 // Most ELF relocations do not include an implicit addend.
@@ -63,3 +82,25 @@ pub fn raw_relocation(reloc: Reloc, triple: &Triple) -> (u32, i64) {
         _ => unimplemented!("unsupported format"),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::str::FromStr;
+
+    #[test]
+    fn aarch64_elf_relocations() {
+        let triple = Triple::from_str("aarch64-unknown-linux-gnu").unwrap();
+        assert_eq!(raw_relocation(Reloc::Abs4, &triple), (258, 0));
+        assert_eq!(raw_relocation(Reloc::Abs8, &triple), (257, 0));
+        assert_eq!(raw_relocation(Reloc::Arm64Call, &triple), (283, 0));
+    }
+
+    #[test]
+    fn riscv64_elf_relocations() {
+        let triple = Triple::from_str("riscv64-unknown-linux-gnu").unwrap();
+        assert_eq!(raw_relocation(Reloc::Abs4, &triple), (1, 0));
+        assert_eq!(raw_relocation(Reloc::Abs8, &triple), (2, 0));
+        assert_eq!(raw_relocation(Reloc::RiscvCall, &triple), (17, 0));
+    }
+}
@@ -0,0 +1,128 @@
+use cranelift_codegen::ir::*;
+use cranelift_codegen::isa;
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_codegen::Context;
+use cranelift_faerie::*;
+use cranelift_frontend::*;
+use cranelift_module::*;
+use goblin::elf::Elf;
+use std::str::FromStr;
+use target_lexicon::Triple;
+
+/// Compile a function calling a local function and an imported one for `triple`, and return the
+/// object file.
+fn emit_calls(triple: &str) -> Vec<u8> {
+    let mut flag_builder = settings::builder();
+    flag_builder.set("is_pic", "true").unwrap();
+    let isa = isa::lookup(Triple::from_str(triple).unwrap())
+        .unwrap()
+        .finish(settings::Flags::new(flag_builder));
+    let mut module: Module<FaerieBackend> = Module::new(
+        FaerieBuilder::new(
+            isa,
+            "test.o".to_owned(),
+            FaerieTrapCollection::Disabled,
+            default_libcall_names(),
+        )
+        .unwrap(),
+    );
+    let sig = module.make_signature();
+
+    let imported = module
+        .declare_function("imported_function", Linkage::Import, &sig)
+        .unwrap();
+    let helper = module
+        .declare_function("helper", Linkage::Local, &sig)
+        .unwrap();
+    let entry = module
+        .declare_function("entry", Linkage::Export, &sig)
+        .unwrap();
+
+    let mut ctx = Context::new();
+    ctx.func = {
+        let func =
+            Function::with_name_signature(ExternalName::user(0, helper.as_u32()), sig.clone());
+        let mut bcx = FunctionBuilder::new(func);
+        let ebb = bcx.create_ebb();
+        bcx.switch_to_block(ebb);
+        bcx.ins().return_(&[]);
+        bcx.seal_all_blocks();
+        bcx.finalize()
+    };
+    module.define_function(helper, &mut ctx).unwrap();
+    module.clear_context(&mut ctx);
+
+    ctx.func = {
+        let func =
+            Function::with_name_signature(ExternalName::user(0, entry.as_u32()), sig.clone());
+        let mut bcx = FunctionBuilder::new(func);
+        let ebb = bcx.create_ebb();
+        bcx.switch_to_block(ebb);
+        let local_callee = module.declare_func_in_func(helper, &mut bcx.func);
+        let imported_callee = module.declare_func_in_func(imported, &mut bcx.func);
+        bcx.ins().call(local_callee, &[]);
+        bcx.ins().call(imported_callee, &[]);
+        bcx.ins().return_(&[]);
+        bcx.seal_all_blocks();
+        bcx.finalize()
+    };
+    module.define_function(entry, &mut ctx).unwrap();
+
+    module.finalize_definitions();
+    module.finish().emit().unwrap()
+}
+
+/// Get the machine of the ELF object `bytes`, and the type and symbol name of its relocations.
+fn relocations(bytes: &[u8]) -> (u16, Vec<(u32, String)>) {
+    let elf = Elf::parse(bytes).unwrap();
+    let mut relocs = Vec::new();
+    for (_, section) in &elf.shdr_relocs {
+        for reloc in section.iter() {
+            let sym = elf.syms.get(reloc.r_sym).unwrap();
+            // Relocations against a defined function refer to the section holding it, which is
+            // named after the function.
+            let name = if sym.st_type() == goblin::elf::sym::STT_SECTION {
+                let section = &elf.section_headers[sym.st_shndx];
+                elf.shdr_strtab.get(section.sh_name).unwrap().unwrap()
+            } else {
+                elf.strtab.get(sym.st_name).unwrap().unwrap()
+            };
+            relocs.push((reloc.r_type, name.to_owned()));
+        }
+    }
+    (elf.header.e_machine, relocs)
+}
+
+#[test]
+fn aarch64_calls() {
+    let (machine, relocs) = relocations(&emit_calls("aarch64-unknown-linux-gnu"));
+    assert_eq!(machine, goblin::elf::header::EM_AARCH64);
+    // Only colocated functions are called directly, and the others through their address.
+    assert_eq!(
+        relocs,
+        [
+            (
+                goblin::elf::reloc::R_AARCH64_CALL26,
+                ".text.helper".to_owned()
+            ),
+            (
+                goblin::elf::reloc::R_AARCH64_ABS64,
+                "imported_function".to_owned()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn riscv64_calls() {
+    let (machine, relocs) = relocations(&emit_calls("riscv64-unknown-linux-gnu"));
+    assert_eq!(machine, goblin::elf::header::EM_RISCV);
+    // Only colocated functions are called with a `jal`, and the others through their address.
+    assert_eq!(
+        relocs,
+        [
+            (17, ".text.helper".to_owned()),
+            (2, "imported_function".to_owned()),
+        ]
+    );
+}
@@ -0,0 +1,506 @@
+//! Differential testing of the optimization passes.
+//!
+//! A `FunctionGenerator` turns arbitrary bytes, such as a fuzzer's input, into a random but
+//! well-typed function and some arguments to call it with. `check_opt_levels` compiles a function
+//! for the host with `opt_level=fastest`, which skips the optional optimization passes, and with
+//! `opt_level=best` and `loop_opts=all`, which runs all of them, and reports any difference in the
+//! results of the two.
+//!
+//! The generated functions never trap: divisors are forced to be non-zero and positive, and loops
+//! have a small constant trip count. A trap or a crash while running them is a miscompile too.
+
+use crate::function_runner::FunctionRunner;
+use cranelift_codegen::entity::EntityRef;
+use cranelift_codegen::ir::condcodes::{FloatCC, IntCC};
+use cranelift_codegen::ir::immediates::{Ieee32, Ieee64};
+use cranelift_codegen::ir::types::*;
+use cranelift_codegen::ir::{
+    AbiParam, ExternalName, Function, InstBuilder, Signature, StackSlot, StackSlotData,
+    StackSlotKind, Type, Value,
+};
+use cranelift_codegen::isa::CallConv;
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, Variable};
+use cranelift_reader::{DataValue, DisplayDataValues};
+
+/// The types of the parameters, results and variables of generated functions.
+const TYPES: [Type; 6] = [I8, I16, I32, I64, F32, F64];
+
+/// The integer types which arithmetic is generated for. Narrower integers are only extended and
+/// reduced, since not all the instructions on them can be legalized.
+const ARITHMETIC_TYPES: [Type; 2] = [I32, I64];
+
+/// The types which are selected, loaded and stored.
+const WIDE_TYPES: [Type; 4] = [I32, I64, F32, F64];
+
+const MAX_PARAMS: usize = 4;
+/// Two results fit in registers in every calling convention; more would need a return area.
+const MAX_RETURNS: usize = 2;
+const MAX_INSTS_PER_REGION: usize = 16;
+const MAX_NESTING: usize = 2;
+const MAX_LOOP_ITERATIONS: usize = 8;
+const MAX_RUNS: usize = 4;
+
+/// The size of the stack slot which generated functions load from and store to.
+const SLOT_SIZE: u32 = 16;
+
+/// A stream of choices, read from the input bytes. Once the bytes run out, every choice is zero,
+/// which always leads to a small function.
+struct Choices<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Choices<'a> {
+    fn byte(&mut self) -> u8 {
+        match self.bytes.split_first() {
+            Some((&byte, rest)) => {
+                self.bytes = rest;
+                byte
+            }
+            None => 0,
+        }
+    }
+
+    /// Choose a number in `0..n`.
+    fn below(&mut self, n: usize) -> usize {
+        usize::from(self.byte()) % n
+    }
+
+    /// Choose one of `items`.
+    fn choose<T: Copy>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len())]
+    }
+
+    /// Choose an arbitrary 64-bit number.
+    fn bits(&mut self) -> u64 {
+        (0..8).fold(0, |bits, _| (bits << 8) | u64::from(self.byte()))
+    }
+}
+
+/// Builds random functions from bytes.
+pub struct FunctionGenerator<'a> {
+    choices: Choices<'a>,
+    /// The variables which can be used and redefined by generated instructions.
+    vars: Vec<(Variable, Type)>,
+    /// The number of variables declared so far, including loop counters.
+    next_var: usize,
+    slot: Option<StackSlot>,
+}
+
+impl<'a> FunctionGenerator<'a> {
+    /// Create a generator making its choices from `bytes`.
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            choices: Choices { bytes },
+            vars: Vec::new(),
+            next_var: 0,
+            slot: None,
+        }
+    }
+
+    /// Generate a function with the calling convention `call_conv`, and a list of arguments to
+    /// call it with.
+    pub fn generate(mut self, call_conv: CallConv) -> (Function, Vec<Vec<DataValue>>) {
+        let mut sig = Signature::new(call_conv);
+        for _ in 0..self.choices.below(MAX_PARAMS + 1) {
+            sig.params.push(AbiParam::new(self.choices.choose(&TYPES)));
+        }
+        for _ in 0..=self.choices.below(MAX_RETURNS) {
+            sig.returns.push(AbiParam::new(self.choices.choose(&TYPES)));
+        }
+
+        let function = Function::with_name_signature(ExternalName::testcase("fuzz"), sig.clone());
+        let mut builder = FunctionBuilder::new(function);
+        let entry = builder.create_ebb();
+        builder.append_ebb_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        builder.seal_block(entry);
+
+        for i in 0..sig.params.len() {
+            let param = builder.ebb_params(entry)[i];
+            self.define(&mut builder, param, true);
+        }
+
+        // Start with a zeroed stack slot, so loads never read uninitialized memory.
+        let slot =
+            builder.create_stack_slot(StackSlotData::new(StackSlotKind::ExplicitSlot, SLOT_SIZE));
+        let zero = builder.ins().iconst(I64, 0);
+        for offset in (0..SLOT_SIZE).step_by(8) {
+            builder.ins().stack_store(zero, slot, offset as i32);
+        }
+        self.slot = Some(slot);
+
+        self.region(&mut builder, 0);
+
+        let results = sig
+            .returns
+            .iter()
+            .map(|ret| self.value(&mut builder, ret.value_type))
+            .collect::<Vec<_>>();
+        builder.ins().return_(&results);
+        let function = builder.finalize();
+
+        let mut runs = Vec::new();
+        for _ in 0..=self.choices.below(MAX_RUNS) {
+            let args = sig
+                .params
+                .iter()
+                .map(|param| self.data_value(param.value_type))
+                .collect();
+            runs.push(args);
+        }
+        (function, runs)
+    }
+
+    /// Generate a sequence of instructions, with nested conditionals and loops.
+    fn region(&mut self, builder: &mut FunctionBuilder, nesting: usize) {
+        for _ in 0..=self.choices.below(MAX_INSTS_PER_REGION) {
+            match self.choices.below(16) {
+                0 if nesting < MAX_NESTING => self.if_else(builder, nesting),
+                1 if nesting < MAX_NESTING => self.counted_loop(builder, nesting),
+                _ => self.instruction(builder),
+            }
+        }
+    }
+
+    /// Generate a conditional with a region on each side.
+    fn if_else(&mut self, builder: &mut FunctionBuilder, nesting: usize) {
+        let condition = self.condition(builder);
+        let then_ebb = builder.create_ebb();
+        let else_ebb = builder.create_ebb();
+        let merge_ebb = builder.create_ebb();
+        builder.ins().brz(condition, else_ebb, &[]);
+        builder.ins().jump(then_ebb, &[]);
+
+        for &ebb in &[then_ebb, else_ebb] {
+            builder.switch_to_block(ebb);
+            builder.seal_block(ebb);
+            self.region(builder, nesting + 1);
+            builder.ins().jump(merge_ebb, &[]);
+        }
+
+        builder.switch_to_block(merge_ebb);
+        builder.seal_block(merge_ebb);
+    }
+
+    /// Generate a loop running a region a constant number of times.
+    fn counted_loop(&mut self, builder: &mut FunctionBuilder, nesting: usize) {
+        let iterations = self.choices.below(MAX_LOOP_ITERATIONS) + 1;
+        // The counter isn't in `vars`, so the body can't change it.
+        let counter = Variable::new(self.next_var);
+        self.next_var += 1;
+        builder.declare_var(counter, I32);
+        let zero = builder.ins().iconst(I32, 0);
+        builder.def_var(counter, zero);
+
+        let header = builder.create_ebb();
+        let exit = builder.create_ebb();
+        builder.ins().jump(header, &[]);
+        builder.switch_to_block(header);
+
+        self.region(builder, nesting + 1);
+
+        let count = builder.use_var(counter);
+        let count = builder.ins().iadd_imm(count, 1);
+        builder.def_var(counter, count);
+        let again = builder
+            .ins()
+            .icmp_imm(IntCC::UnsignedLessThan, count, iterations as i64);
+        builder.ins().brnz(again, header, &[]);
+        builder.ins().jump(exit, &[]);
+        builder.seal_block(header);
+
+        builder.switch_to_block(exit);
+        builder.seal_block(exit);
+    }
+
+    /// Generate a single instruction and assign its result to a variable.
+    fn instruction(&mut self, builder: &mut FunctionBuilder) {
+        let ty = self.choices.choose(&ARITHMETIC_TYPES);
+        let result = match self.choices.below(12) {
+            0 => {
+                let (x, y) = (self.value(builder, ty), self.value(builder, ty));
+                match self.choices.below(11) {
+                    0 => builder.ins().iadd(x, y),
+                    1 => builder.ins().isub(x, y),
+                    2 => builder.ins().imul(x, y),
+                    3 => builder.ins().band(x, y),
+                    4 => builder.ins().bor(x, y),
+                    5 => builder.ins().bxor(x, y),
+                    6 => builder.ins().ishl(x, y),
+                    7 => builder.ins().ushr(x, y),
+                    8 => builder.ins().sshr(x, y),
+                    9 => builder.ins().rotl(x, y),
+                    _ => builder.ins().rotr(x, y),
+                }
+            }
+            1 => {
+                let x = self.value(builder, ty);
+                // Divide by a number in 1..=128, which can't trap.
+                let y = self.value(builder, ty);
+                let y = builder.ins().band_imm(y, 0x7f);
+                let y = builder.ins().iadd_imm(y, 1);
+                match self.choices.below(4) {
+                    0 => builder.ins().udiv(x, y),
+                    1 => builder.ins().urem(x, y),
+                    2 => builder.ins().sdiv(x, y),
+                    _ => builder.ins().srem(x, y),
+                }
+            }
+            2 => {
+                let x = self.value(builder, ty);
+                let imm = self.choices.bits() as i64 >> self.choices.below(64);
+                match self.choices.below(8) {
+                    0 => builder.ins().iadd_imm(x, imm),
+                    1 => builder.ins().imul_imm(x, imm),
+                    2 => builder.ins().band_imm(x, imm),
+                    3 => builder.ins().bor_imm(x, imm),
+                    4 => builder.ins().bxor_imm(x, imm),
+                    5 => builder.ins().ishl_imm(x, imm & 63),
+                    6 => builder.ins().ushr_imm(x, imm & 63),
+                    _ => builder.ins().sshr_imm(x, imm & 63),
+                }
+            }
+            3 => {
+                let x = self.value(builder, ty);
+                match self.choices.below(4) {
+                    0 => builder.ins().bnot(x),
+                    1 => builder.ins().clz(x),
+                    2 => builder.ins().ctz(x),
+                    _ => builder.ins().popcnt(x),
+                }
+            }
+            4 => {
+                let condition = self.condition(builder);
+                let ty = self.choices.choose(&WIDE_TYPES);
+                let (x, y) = (self.value(builder, ty), self.value(builder, ty));
+                builder.ins().select(condition, x, y)
+            }
+            5 => {
+                let condition = self.condition(builder);
+                builder.ins().bint(ty, condition)
+            }
+            6 => self.conversion(builder),
+            7 => {
+                let ty = self.choices.choose(&[F32, F64]);
+                let (x, y) = (self.value(builder, ty), self.value(builder, ty));
+                match self.choices.below(6) {
+                    0 => builder.ins().fadd(x, y),
+                    1 => builder.ins().fsub(x, y),
+                    2 => builder.ins().fmul(x, y),
+                    3 => builder.ins().fdiv(x, y),
+                    4 => builder.ins().fmin(x, y),
+                    _ => builder.ins().fmax(x, y),
+                }
+            }
+            8 => {
+                let ty = self.choices.choose(&[F32, F64]);
+                let x = self.value(builder, ty);
+                match self.choices.below(3) {
+                    0 => builder.ins().fneg(x),
+                    1 => builder.ins().fabs(x),
+                    _ => builder.ins().sqrt(x),
+                }
+            }
+            9 => {
+                let ty = self.choices.choose(&WIDE_TYPES);
+                let x = self.value(builder, ty);
+                let offset = self.slot_offset();
+                builder.ins().stack_store(x, self.slot.unwrap(), offset);
+                return;
+            }
+            10 => {
+                let ty = self.choices.choose(&WIDE_TYPES);
+                let offset = self.slot_offset();
+                builder.ins().stack_load(ty, self.slot.unwrap(), offset)
+            }
+            _ => {
+                let ty = self.choices.choose(&TYPES);
+                self.constant(builder, ty)
+            }
+        };
+        let reuse = self.choices.below(2) == 0;
+        self.define(builder, result, reuse);
+    }
+
+    /// Generate a conversion between integer types, or from an integer to a float.
+    fn conversion(&mut self, builder: &mut FunctionBuilder) -> Value {
+        let from = self.choices.choose(&[I8, I16, I32, I64]);
+        let to = self.choices.choose(&TYPES);
+        let x = self.value(builder, from);
+        match to {
+            F32 | F64 => {
+                let x = if from.bits() < 32 {
+                    builder.ins().sextend(I32, x)
+                } else {
+                    x
+                };
+                builder.ins().fcvt_from_sint(to, x)
+            }
+            _ if to.bits() > from.bits() => {
+                if self.choices.below(2) == 0 {
+                    builder.ins().uextend(to, x)
+                } else {
+                    builder.ins().sextend(to, x)
+                }
+            }
+            _ if to.bits() < from.bits() => builder.ins().ireduce(to, x),
+            _ => x,
+        }
+    }
+
+    /// Generate a comparison producing a `b1`.
+    fn condition(&mut self, builder: &mut FunctionBuilder) -> Value {
+        if self.choices.below(4) == 0 {
+            let ty = self.choices.choose(&[F32, F64]);
+            let (x, y) = (self.value(builder, ty), self.value(builder, ty));
+            let cond = self.choices.choose(&[
+                FloatCC::Equal,
+                FloatCC::OrderedNotEqual,
+                FloatCC::LessThan,
+                FloatCC::GreaterThanOrEqual,
+                FloatCC::Unordered,
+            ]);
+            builder.ins().fcmp(cond, x, y)
+        } else {
+            let ty = self.choices.choose(&ARITHMETIC_TYPES);
+            let (x, y) = (self.value(builder, ty), self.value(builder, ty));
+            let cond = self.choices.choose(&[
+                IntCC::Equal,
+                IntCC::NotEqual,
+                IntCC::SignedLessThan,
+                IntCC::SignedGreaterThanOrEqual,
+                IntCC::UnsignedLessThan,
+                IntCC::UnsignedGreaterThan,
+            ]);
+            builder.ins().icmp(cond, x, y)
+        }
+    }
+
+    /// Choose an offset in the stack slot with room for any type.
+    fn slot_offset(&mut self) -> i32 {
+        self.choices.choose(&[0, 8])
+    }
+
+    /// Get a value of type `ty`, either from a variable or from a new constant.
+    fn value(&mut self, builder: &mut FunctionBuilder, ty: Type) -> Value {
+        let candidates = self
+            .vars
+            .iter()
+            .filter(|&&(_, var_ty)| var_ty == ty)
+            .map(|&(var, _)| var)
+            .collect::<Vec<_>>();
+        let choice = self.choices.below(candidates.len() + 1);
+        match candidates.get(choice) {
+            Some(&var) => builder.use_var(var),
+            None => self.constant(builder, ty),
+        }
+    }
+
+    /// Generate a constant of type `ty`.
+    fn constant(&mut self, builder: &mut FunctionBuilder, ty: Type) -> Value {
+        let bits = self.choices.bits();
+        match ty {
+            F32 => builder.ins().f32const(Ieee32::with_bits(bits as u32)),
+            F64 => builder.ins().f64const(Ieee64::with_bits(bits)),
+            _ => builder.ins().iconst(ty, bits as i64),
+        }
+    }
+
+    /// Assign `value` to a variable of its type, either an existing one if `reuse` is set, or a
+    /// new one.
+    fn define(&mut self, builder: &mut FunctionBuilder, value: Value, reuse: bool) {
+        let ty = builder.func.dfg.value_type(value);
+        let existing = self
+            .vars
+            .iter()
+            .filter(|&&(_, var_ty)| var_ty == ty)
+            .map(|&(var, _)| var)
+            .collect::<Vec<_>>();
+        let var = if reuse && !existing.is_empty() {
+            self.choices.choose(&existing)
+        } else {
+            let var = Variable::new(self.next_var);
+            self.next_var += 1;
+            builder.declare_var(var, ty);
+            self.vars.push((var, ty));
+            var
+        };
+        builder.def_var(var, value);
+    }
+
+    /// Choose an argument of type `ty`.
+    fn data_value(&mut self, ty: Type) -> DataValue {
+        let bits = self.choices.bits();
+        match ty {
+            I8 => DataValue::I8(bits as i8),
+            I16 => DataValue::I16(bits as i16),
+            I32 => DataValue::I32(bits as i32),
+            I64 => DataValue::I64(bits as i64),
+            F32 => DataValue::F32(Ieee32::with_bits(bits as u32)),
+            _ => DataValue::F64(Ieee64::with_bits(bits)),
+        }
+    }
+}
+
+/// Compile `function` for the host with `opt_level=fastest` and with `opt_level=best` and
+/// `loop_opts=all`, call both with each of `runs`, and check that they return the same results.
+pub fn check_opt_levels(function: &Function, runs: &[Vec<DataValue>]) -> Result<(), String> {
+    let compile = |opt_level| {
+        let mut flags = settings::builder();
+        flags.set("opt_level", opt_level).unwrap();
+        if opt_level == "best" {
+            flags.set("loop_opts", "all").unwrap();
+        }
+        FunctionRunner::with_host_isa(function.clone(), settings::Flags::new(flags))
+            .compile()
+            .map_err(|e| format!("compiling with opt_level={}: {}", opt_level, e))
+    };
+    let fastest = compile("fastest")?;
+    let best = compile("best")?;
+    for args in runs {
+        let expected = fastest.call(args)?;
+        let actual = best.call(args)?;
+        if actual != expected {
+            return Err(format!(
+                "{}{} returned {} with opt_level=fastest, but {} with opt_level=best",
+                function.name,
+                DisplayDataValues(args),
+                DisplayDataValues(&expected),
+                DisplayDataValues(&actual)
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cranelift_codegen::verify_function;
+    use cranelift_native::builder as host_isa_builder;
+
+    #[test]
+    fn generated_functions_agree() {
+        let isa = host_isa_builder()
+            .unwrap()
+            .finish(settings::Flags::new(settings::builder()));
+        // Generate functions from a simple pseudo-random byte stream.
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        for size in 0..64 {
+            let bytes = (0..size * 16)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    state as u8
+                })
+                .collect::<Vec<_>>();
+            let (function, runs) = FunctionGenerator::new(&bytes).generate(isa.default_call_conv());
+            verify_function(&function, isa.as_ref()).unwrap();
+            if let Err(message) = check_opt_levels(&function, &runs) {
+                panic!("{}\n{}", message, function.display(isa.as_ref()));
+            }
+        }
+    }
+}
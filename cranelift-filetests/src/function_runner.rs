@@ -1,13 +1,21 @@
 use core::mem;
 use cranelift_codegen::binemit::{NullRelocSink, NullStackmapSink, NullTrapSink};
-use cranelift_codegen::ir::Function;
+use cranelift_codegen::cursor::{Cursor, FuncCursor};
+use cranelift_codegen::ir::{
+    AbiParam, ExternalName, Function, InstBuilder, MemFlags, Signature, Type,
+};
 use cranelift_codegen::isa::TargetIsa;
 use cranelift_codegen::{settings, Context};
 use cranelift_native::builder as host_isa_builder;
+use cranelift_reader::{DataValue, DisplayDataValues, RunCommand};
 use memmap::MmapMut;
 use region;
 use region::Protection;
 
+/// The size of the slots holding the arguments and results of a function called through a
+/// trampoline; large enough for any value supported by `DataValue`.
+const SLOT_SIZE: usize = 16;
+
 /// Run a function on a host
 pub struct FunctionRunner {
     function: Function,
@@ -37,36 +45,81 @@ impl FunctionRunner {
     /// interpreted as a successful test execution and mapped to Ok whereas a 'false' value is
     /// interpreted as a failed test and mapped to Err.
     pub fn run(&self) -> Result<(), String> {
-        let func = self.function.clone();
-        if !(func.signature.params.is_empty()
-            && func.signature.returns.len() == 1
-            && func.signature.returns.first().unwrap().value_type.is_bool())
+        let signature = &self.function.signature;
+        if !(signature.params.is_empty()
+            && signature.returns.len() == 1
+            && signature.returns.first().unwrap().value_type.is_bool())
         {
             return Err(String::from(
                 "Functions must have a signature like: () -> boolean",
             ));
         }
 
+        match self.compile()?.call(&[])?[0] {
+            DataValue::B(true) => Ok(()),
+            _ => Err(format!("Failed: {}", self.function.name)),
+        }
+    }
+
+    /// Compile a function and execute it once for each of the run `commands`, comparing its
+    /// results with the expected ones.
+    pub fn run_commands(&self, commands: &[RunCommand]) -> Result<(), String> {
+        let compiled = self.compile()?;
+        for command in commands {
+            let actual = compiled.call(&command.invocation.args)?;
+            if !command.matches(&actual) {
+                let actual = if actual.len() == 1 {
+                    actual[0].to_string()
+                } else {
+                    DisplayDataValues(&actual).to_string()
+                };
+                return Err(format!("Failed: {}, actual: {}", command, actual));
+            }
+        }
+        Ok(())
+    }
+
+    /// Compile the function to executable memory, along with a trampoline which calls it using
+    /// the host's calling convention.
+    pub fn compile(&self) -> Result<CompiledFunction, String> {
+        let func = self.function.clone();
         if func.signature.call_conv != self.isa.default_call_conv() {
             return Err(String::from(
                 "Functions only run on the host's default calling convention; remove the specified calling convention in the function signature to use the host's default.",
             ));
         }
+        for param in func.signature.params.iter().chain(&func.signature.returns) {
+            if !DataValue::is_supported(param.value_type) {
+                return Err(format!(
+                    "Functions with {} parameters or results can't be run",
+                    param.value_type
+                ));
+            }
+        }
+        let signature = func.signature.clone();
+        let trampoline = make_trampoline(&signature, self.isa.as_ref());
 
-        // set up the context
+        // set up the contexts
         let mut context = Context::new();
         context.func = func;
+        let mut trampoline_context = Context::new();
+        trampoline_context.func = trampoline;
 
-        // compile and encode the result to machine code
+        // compile both functions and encode them to the same code page, the function first
         let relocs = &mut NullRelocSink {};
         let traps = &mut NullTrapSink {};
         let stackmaps = &mut NullStackmapSink {};
         let code_info = context
             .compile(self.isa.as_ref())
             .map_err(|e| e.to_string())?;
+        let trampoline_info = trampoline_context
+            .compile(self.isa.as_ref())
+            .map_err(|e| e.to_string())?;
+        let trampoline_offset = (code_info.total_size as usize + 15) & !15;
         let mut code_page =
-            MmapMut::map_anon(code_info.total_size as usize).map_err(|e| e.to_string())?;
-        let callable_fn: fn() -> bool = unsafe {
+            MmapMut::map_anon(trampoline_offset + trampoline_info.total_size as usize)
+                .map_err(|e| e.to_string())?;
+        unsafe {
             context.emit_to_memory(
                 self.isa.as_ref(),
                 code_page.as_mut_ptr(),
@@ -74,27 +127,139 @@ impl FunctionRunner {
                 traps,
                 stackmaps,
             );
+            trampoline_context.emit_to_memory(
+                self.isa.as_ref(),
+                code_page.as_mut_ptr().add(trampoline_offset),
+                relocs,
+                traps,
+                stackmaps,
+            );
             region::protect(
                 code_page.as_mut_ptr(),
                 code_page.len(),
                 Protection::ReadExecute,
             )
             .map_err(|e| e.to_string())?;
-            mem::transmute(code_page.as_mut_ptr())
-        };
+        }
+
+        Ok(CompiledFunction {
+            signature,
+            code_page,
+            trampoline_offset,
+        })
+    }
+}
+
+/// A function compiled by a `FunctionRunner`, which can be called with `DataValue` arguments.
+pub struct CompiledFunction {
+    signature: Signature,
+    code_page: MmapMut,
+    trampoline_offset: usize,
+}
+
+impl CompiledFunction {
+    /// Call the function with `args`, returning its results.
+    pub fn call(&self, args: &[DataValue]) -> Result<Vec<DataValue>, String> {
+        if args.len() != self.signature.params.len() {
+            return Err(format!(
+                "Expected {} arguments, got {}",
+                self.signature.params.len(),
+                args.len()
+            ));
+        }
+
+        // The arguments and the results share the slots.
+        let num_slots = args.len().max(self.signature.returns.len());
+        let mut slots = vec![0u128; num_slots];
+        for ((slot, arg), param) in slots.iter_mut().zip(args).zip(&self.signature.params) {
+            let mut bytes = [0; SLOT_SIZE];
+            arg.write_to_slice(&mut bytes[..param.value_type.bytes() as usize]);
+            *slot = u128::from_le_bytes(bytes);
+        }
 
         // execute
-        match callable_fn() {
-            true => Ok(()),
-            false => Err(format!("Failed: {}", context.func.name.to_string())),
+        let code = self.code_page.as_ptr();
+        unsafe {
+            let trampoline: extern "C" fn(*const u8, *mut u128) =
+                mem::transmute(code.add(self.trampoline_offset));
+            trampoline(code, slots.as_mut_ptr());
         }
+
+        Ok(self
+            .signature
+            .returns
+            .iter()
+            .zip(&slots)
+            .map(|(ret, slot)| DataValue::read_from_slice(&slot.to_le_bytes(), ret.value_type))
+            .collect())
+    }
+}
+
+/// Build a trampoline for calling a function with signature `sig`. The trampoline takes the
+/// address of the function and the address of an array of `SLOT_SIZE`-byte slots. It loads the
+/// arguments from the slots, calls the function, and stores its results back to the slots.
+///
+/// Booleans are held in integer registers, so the trampoline passes them as integers of the same
+/// width, or `i8` for `b1`.
+fn make_trampoline(sig: &Signature, isa: &dyn TargetIsa) -> Function {
+    let pointer_type = isa.pointer_type();
+    let mut trampoline_sig = Signature::new(isa.default_call_conv());
+    trampoline_sig.params.push(AbiParam::new(pointer_type));
+    trampoline_sig.params.push(AbiParam::new(pointer_type));
+
+    let mut callee_sig = sig.clone();
+    for param in callee_sig
+        .params
+        .iter_mut()
+        .chain(callee_sig.returns.iter_mut())
+    {
+        param.value_type = slot_type(param.value_type);
+    }
+
+    let mut func =
+        Function::with_name_signature(ExternalName::testcase("trampoline"), trampoline_sig);
+    let callee_sig = func.import_signature(callee_sig);
+    let ebb = func.dfg.make_ebb();
+    let callee = func.dfg.append_ebb_param(ebb, pointer_type);
+    let slots = func.dfg.append_ebb_param(ebb, pointer_type);
+
+    let mut pos = FuncCursor::new(&mut func);
+    pos.insert_ebb(ebb);
+    let flags = MemFlags::trusted();
+
+    let mut args = Vec::with_capacity(sig.params.len());
+    for (i, param) in sig.params.iter().enumerate() {
+        let offset = (i * SLOT_SIZE) as i32;
+        args.push(
+            pos.ins()
+                .load(slot_type(param.value_type), flags, slots, offset),
+        );
+    }
+
+    let call = pos.ins().call_indirect(callee_sig, callee, &args);
+    let results = pos.func.dfg.inst_results(call).to_vec();
+    for (i, &result) in results.iter().enumerate() {
+        let offset = (i * SLOT_SIZE) as i32;
+        pos.ins().store(flags, result, slots, offset);
+    }
+    pos.ins().return_(&[]);
+
+    func
+}
+
+/// The type used by the trampoline to pass a value of type `ty` through a slot.
+fn slot_type(ty: Type) -> Type {
+    if ty.is_bool() {
+        Type::int(ty.bits().max(8)).unwrap()
+    } else {
+        ty
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use cranelift_reader::{parse_test, ParseOptions};
+    use cranelift_reader::{parse_run_command, parse_test, ParseOptions};
 
     #[test]
     fn nop() {
@@ -118,4 +283,36 @@ mod test {
         let runner = FunctionRunner::with_default_host_isa(function);
         runner.run().unwrap() // will panic if execution fails
     }
+
+    #[test]
+    fn arguments_and_results() {
+        let code = String::from(
+            "
+            test run
+            function %test(i8, i64, f32, b1) -> f32, b1, i16, b1 {
+            ebb0(v0: i8, v1: i64, v2: f32, v3: b1):
+                v4 = icmp_imm slt v1, 0
+                v5 = sextend.i16 v0
+                return v2, v4, v5, v3
+            }",
+        );
+
+        let test_file = parse_test(code.as_str(), ParseOptions::default()).unwrap();
+        let function = test_file.functions[0].0.clone();
+        let command = |text| {
+            parse_run_command(text, &function.signature)
+                .unwrap()
+                .unwrap()
+        };
+        let passing = command("run: %test(-1, -2, 0x1.0p1, true) == [0x1.0p1, true, -1, true]");
+        let failing = command("run: %test(3, 2, 0x1.0p1, false) == [0x1.0p1, true, 3, false]");
+
+        let runner = FunctionRunner::with_default_host_isa(function);
+        runner.run_commands(&[passing]).unwrap();
+        assert_eq!(
+            runner.run_commands(&[failing]).unwrap_err(),
+            "Failed: %test(3, 2, 0x1.000000p1, false) == [0x1.000000p1, true, 3, false], \
+             actual: [0x1.000000p1, false, 3, false]"
+        );
+    }
 }
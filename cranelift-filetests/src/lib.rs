@@ -23,6 +23,7 @@
     )
 )]
 
+pub use crate::differential::{check_opt_levels, FunctionGenerator};
 pub use crate::function_runner::FunctionRunner;
 use crate::runner::TestRunner;
 use cranelift_codegen::timing;
@@ -31,6 +32,7 @@ use std::path::Path;
 use std::time;
 
 mod concurrent;
+mod differential;
 mod function_runner;
 mod match_directive;
 mod runner;
@@ -42,11 +44,16 @@ mod test_cat;
 mod test_compile;
 mod test_dce;
 mod test_domtree;
+mod test_inline;
+mod test_interpret;
 mod test_legalizer;
 mod test_licm;
+mod test_loop_unroll;
+mod test_loop_unswitch;
 mod test_postopt;
 mod test_preopt;
 mod test_print_cfg;
+mod test_redundant_load_elim;
 mod test_regalloc;
 mod test_rodata;
 mod test_run;
@@ -54,6 +61,7 @@ mod test_safepoint;
 mod test_shrink;
 mod test_simple_gvn;
 mod test_simple_preopt;
+mod test_unwind;
 mod test_verifier;
 
 /// The result of running the test in a file.
@@ -123,11 +131,16 @@ fn new_subtest(parsed: &TestCommand) -> subtest::SubtestResult<Box<dyn subtest::
         "rodata" => test_rodata::subtest(parsed),
         "dce" => test_dce::subtest(parsed),
         "domtree" => test_domtree::subtest(parsed),
+        "inline" => test_inline::subtest(parsed),
+        "interpret" => test_interpret::subtest(parsed),
         "legalizer" => test_legalizer::subtest(parsed),
         "licm" => test_licm::subtest(parsed),
+        "loop-unroll" => test_loop_unroll::subtest(parsed),
+        "loop-unswitch" => test_loop_unswitch::subtest(parsed),
         "postopt" => test_postopt::subtest(parsed),
         "simple_preopt" => test_simple_preopt::subtest(parsed),
         "print-cfg" => test_print_cfg::subtest(parsed),
+        "redundant-load-elim" => test_redundant_load_elim::subtest(parsed),
         "regalloc" => test_regalloc::subtest(parsed),
         "run" => test_run::subtest(parsed),
         "shrink" => test_shrink::subtest(parsed),
@@ -135,6 +148,7 @@ fn new_subtest(parsed: &TestCommand) -> subtest::SubtestResult<Box<dyn subtest::
         "verifier" => test_verifier::subtest(parsed),
         "preopt" => test_preopt::subtest(parsed),
         "safepoint" => test_safepoint::subtest(parsed),
+        "unwind" => test_unwind::subtest(parsed),
         _ => Err(format!("unknown test command '{}'", parsed.command)),
     }
 }
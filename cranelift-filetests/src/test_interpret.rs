@@ -0,0 +1,74 @@
+//! Test command for interpreting CLIF files and verifying their results
+//!
+//! The `interpret` test command interprets each function with the arguments of each `run:`
+//! command in its comments, comparing the results with the expected ones. Unlike `test run`, it
+//! doesn't generate native code, so it works on any host, and the functions of a file can call
+//! each other.
+
+use crate::subtest::{Context, SubTest, SubtestResult};
+use cranelift_codegen::ir;
+use cranelift_interpreter::{FunctionStore, Interpreter};
+use cranelift_reader::{parse_run_command, DataValue, DisplayDataValues, TestCommand};
+use std::borrow::Cow;
+
+struct TestInterpret;
+
+pub fn subtest(parsed: &TestCommand) -> SubtestResult<Box<dyn SubTest>> {
+    assert_eq!(parsed.command, "interpret");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestInterpret))
+    }
+}
+
+impl SubTest for TestInterpret {
+    fn name(&self) -> &'static str {
+        "interpret"
+    }
+
+    fn needs_functions(&self) -> bool {
+        true
+    }
+
+    fn run(&self, func: Cow<ir::Function>, context: &Context) -> SubtestResult<()> {
+        let mut functions = FunctionStore::new();
+        for function in context.functions {
+            functions.add(function);
+        }
+        let mut interpreter = Interpreter::new(functions);
+
+        for comment in context.details.comments.iter() {
+            if let Some(command) = parse_run_command(comment.text, &func.signature)
+                .map_err(|e| format!("invalid run command `{}`: {}", comment.text, e.message))?
+            {
+                if format!("%{}", command.invocation.func) != func.name.to_string() {
+                    return Err(format!(
+                        "{} must call the function it annotates, {}",
+                        command.invocation, func.name
+                    ));
+                }
+                let actual = interpreter
+                    .call(&func, &command.invocation.args)
+                    .map_err(|e| format!("Failed: {}, {}", command, e))?;
+                if !command.matches(&actual) {
+                    let actual = if actual.len() == 1 {
+                        actual[0].to_string()
+                    } else {
+                        DisplayDataValues(&actual).to_string()
+                    };
+                    return Err(format!("Failed: {}, actual: {}", command, actual));
+                }
+            } else if comment.text.trim_start_matches(';').trim() == "run" {
+                // A bare `; run` expects a `() -> bool` function to return true.
+                let actual = interpreter
+                    .call(&func, &[])
+                    .map_err(|e| format!("Failed: {}, {}", func.name, e))?;
+                if actual != [DataValue::B(true)] {
+                    return Err(format!("Failed: {}", func.name));
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,12 +1,13 @@
 //! Test command for running CLIF files and verifying their results
 //!
-//! The `run` test command compiles each function on the host machine and executes it
+//! The `run` test command compiles each function on the host machine and executes it with the
+//! arguments of each `run:` command in its comments, comparing the results with the expected ones.
 
 use crate::function_runner::FunctionRunner;
 use crate::subtest::{Context, SubTest, SubtestResult};
 use cranelift_codegen;
 use cranelift_codegen::ir;
-use cranelift_reader::TestCommand;
+use cranelift_reader::{parse_run_command, TestCommand};
 use std::borrow::Cow;
 
 struct TestRun;
@@ -34,13 +35,33 @@ impl SubTest for TestRun {
     }
 
     fn run(&self, func: Cow<ir::Function>, context: &Context) -> SubtestResult<()> {
+        let mut legacy = false;
+        let mut commands = Vec::new();
         for comment in context.details.comments.iter() {
-            if comment.text.contains("run") {
-                let runner =
-                    FunctionRunner::with_host_isa(func.clone().into_owned(), context.flags.clone());
-                runner.run()?
+            if let Some(command) = parse_run_command(comment.text, &func.signature)
+                .map_err(|e| format!("invalid run command `{}`: {}", comment.text, e.message))?
+            {
+                if format!("%{}", command.invocation.func) != func.name.to_string() {
+                    return Err(format!(
+                        "{} must call the function it annotates, {}",
+                        command.invocation, func.name
+                    ));
+                }
+                commands.push(command);
+            } else if comment.text.trim_start_matches(';').trim() == "run" {
+                // A bare `; run` expects a `() -> bool` function to return true.
+                legacy = true;
             }
         }
+
+        let runner =
+            FunctionRunner::with_host_isa(func.clone().into_owned(), context.flags.clone());
+        if legacy {
+            runner.run()?;
+        }
+        if !commands.is_empty() {
+            runner.run_commands(&commands)?;
+        }
         Ok(())
     }
 }
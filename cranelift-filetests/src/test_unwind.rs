@@ -0,0 +1,98 @@
+//! Test command for verifying the unwind information emitted for each function.
+//!
+//! The `unwind` test command compiles each function, emits its unwind information and runs a
+//! hexadecimal dump of it through filecheck. Windows fastcall functions get Windows x64
+//! `UNWIND_INFO`, and other functions get an `.eh_frame` CIE and FDE.
+
+use crate::subtest::{run_filecheck, Context, SubTest, SubtestResult};
+use cranelift_codegen::binemit::{FrameUnwindKind, FrameUnwindOffset, FrameUnwindSink, Reloc};
+use cranelift_codegen::ir;
+use cranelift_codegen::isa::CallConv;
+use cranelift_codegen::print_errors::pretty_error;
+use cranelift_reader::TestCommand;
+use std::borrow::Cow;
+use std::fmt::Write;
+
+struct TestUnwind;
+
+pub fn subtest(parsed: &TestCommand) -> SubtestResult<Box<dyn SubTest>> {
+    assert_eq!(parsed.command, "unwind");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestUnwind))
+    }
+}
+
+impl SubTest for TestUnwind {
+    fn name(&self) -> &'static str {
+        "unwind"
+    }
+
+    fn is_mutating(&self) -> bool {
+        false
+    }
+
+    fn needs_isa(&self) -> bool {
+        true
+    }
+
+    fn run(&self, func: Cow<ir::Function>, context: &Context) -> SubtestResult<()> {
+        let isa = context.isa.expect("unwind needs an ISA");
+        let kind = match func.signature.call_conv {
+            CallConv::WindowsFastcall => FrameUnwindKind::Fastcall,
+            _ => FrameUnwindKind::Libunwind,
+        };
+
+        let mut comp_ctx = cranelift_codegen::Context::for_function(func.into_owned());
+        comp_ctx
+            .compile(isa)
+            .map_err(|e| pretty_error(&comp_ctx.func, context.isa, e))?;
+
+        let mut sink = TextSink::default();
+        comp_ctx.emit_unwind_info(isa, kind, &mut sink);
+
+        let mut text = String::new();
+        if let Some(entry) = sink.entry {
+            writeln!(text, "entry: {}", entry).unwrap();
+        }
+        for (reloc, offset) in &sink.relocs {
+            writeln!(text, "reloc: {:?} at {}", reloc, offset).unwrap();
+        }
+        for (i, chunk) in sink.bytes.chunks(8).enumerate() {
+            write!(text, "{:04x}:", i * 8).unwrap();
+            for byte in chunk {
+                write!(text, " {:02x}", byte).unwrap();
+            }
+            writeln!(text).unwrap();
+        }
+
+        run_filecheck(&text, context)
+    }
+}
+
+/// Unwind information sink that records everything for printing.
+#[derive(Default)]
+struct TextSink {
+    bytes: Vec<u8>,
+    relocs: Vec<(Reloc, FrameUnwindOffset)>,
+    entry: Option<FrameUnwindOffset>,
+}
+
+impl FrameUnwindSink for TextSink {
+    fn len(&self) -> FrameUnwindOffset {
+        self.bytes.len()
+    }
+
+    fn bytes(&mut self, b: &[u8]) {
+        self.bytes.extend_from_slice(b);
+    }
+
+    fn reloc(&mut self, r: Reloc, off: FrameUnwindOffset) {
+        self.relocs.push((r, off));
+    }
+
+    fn set_entry_offset(&mut self, off: FrameUnwindOffset) {
+        self.entry = Some(off);
+    }
+}
@@ -0,0 +1,49 @@
+//! The set of functions available to the interpreter.
+
+use cranelift_codegen::ir::{ExternalName, Function};
+use std::collections::HashMap;
+
+/// A set of functions which can call each other when interpreted.
+///
+/// Functions are found by their `ExternalName`, so a `call` to a function declared as `%foo`
+/// executes the function named `%foo` in the store.
+#[derive(Default)]
+pub struct FunctionStore<'a> {
+    functions: Vec<&'a Function>,
+    indices: HashMap<String, usize>,
+}
+
+impl<'a> FunctionStore<'a> {
+    /// Create an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `function` to the store, replacing any function with the same name.
+    pub fn add(&mut self, function: &'a Function) {
+        let name = function.name.to_string();
+        match self.indices.get(&name) {
+            Some(&index) => self.functions[index] = function,
+            None => {
+                self.indices.insert(name, self.functions.len());
+                self.functions.push(function);
+            }
+        }
+    }
+
+    /// Get the index of the function named `name`, as written in Cranelift IR, e.g. `%foo`.
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.indices.get(name).cloned()
+    }
+
+    /// Get the function at `index`.
+    pub fn get(&self, index: usize) -> Option<&'a Function> {
+        self.functions.get(index).cloned()
+    }
+
+    /// Get the function named `name`.
+    pub fn get_by_name(&self, name: &ExternalName) -> Option<&'a Function> {
+        self.index_of(&name.to_string())
+            .and_then(|index| self.get(index))
+    }
+}
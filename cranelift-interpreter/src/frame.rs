@@ -0,0 +1,73 @@
+//! The state of a function being interpreted.
+
+use cranelift_codegen::entity::SecondaryMap;
+use cranelift_codegen::ir::{Function, Inst, StackSlot, Value};
+use cranelift_reader::DataValue;
+
+/// The state of a flags value. For comparisons, these are the operands, and instructions reading
+/// the flags evaluate their condition code on them.
+#[derive(Clone, Copy, Debug)]
+pub enum Flags {
+    /// Integer flags, from `ifcmp` or `ifcmp_imm`.
+    Int(DataValue, DataValue),
+    /// Floating point flags, from `ffcmp`.
+    Float(DataValue, DataValue),
+    /// The carry or borrow out of an addition or subtraction, e.g. from `iadd_cout`. Only the
+    /// `ult` (set) and `uge` (clear) condition codes can be tested on these flags.
+    Carry(bool),
+}
+
+/// The values defined so far by a call of a function, and the addresses of its stack slots.
+pub struct Frame<'a> {
+    /// The function being interpreted.
+    pub function: &'a Function,
+    /// The instruction being executed.
+    pub pc: Inst,
+    /// The end of the interpreter's memory before the stack slots of this call were allocated.
+    pub stack_base: u64,
+    values: SecondaryMap<Value, Option<DataValue>>,
+    flags: SecondaryMap<Value, Option<Flags>>,
+    /// The address of each stack slot in the interpreter's memory.
+    pub stack_slots: SecondaryMap<StackSlot, u64>,
+}
+
+impl<'a> Frame<'a> {
+    /// Create a frame for a call of `function` starting at `pc`, with no values defined yet.
+    pub fn new(function: &'a Function, pc: Inst, stack_base: u64) -> Self {
+        Self {
+            function,
+            pc,
+            stack_base,
+            values: SecondaryMap::new(),
+            flags: SecondaryMap::new(),
+            stack_slots: SecondaryMap::new(),
+        }
+    }
+
+    /// Get the value of `value`, which must have been defined.
+    pub fn get(&self, value: Value) -> DataValue {
+        let value = self.function.dfg.resolve_aliases(value);
+        self.values[value].unwrap_or_else(|| panic!("{} is used before it is defined", value))
+    }
+
+    /// Get the values of `values`.
+    pub fn get_all(&self, values: &[Value]) -> Vec<DataValue> {
+        values.iter().map(|&v| self.get(v)).collect()
+    }
+
+    /// Define `value`.
+    pub fn set(&mut self, value: Value, data: DataValue) {
+        self.values[value] = Some(data);
+    }
+
+    /// Get the flags value `value`, which must have been defined.
+    pub fn get_flags(&self, value: Value) -> Flags {
+        let value = self.function.dfg.resolve_aliases(value);
+        self.flags[value].unwrap_or_else(|| panic!("{} is used before it is defined", value))
+    }
+
+    /// Define the flags value `value`.
+    pub fn set_flags(&mut self, value: Value, flags: Flags) {
+        self.flags[value] = Some(flags);
+    }
+}
@@ -0,0 +1,1263 @@
+//! Execution of Cranelift IR functions.
+//!
+//! The interpreter keeps its own stack of call frames instead of recursing on the host stack, so
+//! runaway recursion in the interpreted code traps with `StackOverflow` rather than crashing the
+//! host.
+
+use crate::environment::FunctionStore;
+use crate::frame::{Flags, Frame};
+use crate::memory::{Memory, MEMORY_BASE};
+use crate::value::*;
+use cranelift_codegen::ir::condcodes::{FloatCC, IntCC};
+use cranelift_codegen::ir::{
+    types, ArgumentPurpose, Ebb, Function, GlobalValue, GlobalValueData, HeapStyle, Inst,
+    InstructionData, Opcode, Signature, TrapCode, Type, Value,
+};
+use cranelift_reader::DataValue;
+use failure_derive::Fail;
+
+/// The maximum number of nested calls. Calling deeper traps with `StackOverflow`.
+const MAX_CALL_DEPTH: usize = 10_000;
+
+/// The address of the first function in the `FunctionStore`, as produced by `func_addr`. The
+/// function at index `n` has the address `FUNCTION_BASE + n`.
+const FUNCTION_BASE: u64 = 0x1000;
+
+/// The alignment of stack slots in the interpreter's memory.
+const STACK_SLOT_ALIGN: u64 = 16;
+
+/// An error which stopped the interpretation of a function.
+#[derive(Fail, Debug, PartialEq, Eq)]
+pub enum InterpreterError {
+    /// The function trapped.
+    #[fail(display = "trap: {}", _0)]
+    Trap(TrapCode),
+
+    /// A call referred to a function which isn't in the `FunctionStore`.
+    #[fail(display = "unknown function {}", _0)]
+    UnknownFunction(String),
+
+    /// A function was called with arguments which don't match its signature.
+    #[fail(display = "bad arguments for {}: {}", _0, _1)]
+    BadArguments(String, String),
+
+    /// The function uses an instruction or a feature the interpreter doesn't support.
+    #[fail(display = "unsupported: {}", _0)]
+    Unsupported(String),
+}
+
+impl From<TrapCode> for InterpreterError {
+    fn from(code: TrapCode) -> Self {
+        InterpreterError::Trap(code)
+    }
+}
+
+/// A result of interpreting a function.
+pub type InterpreterResult<T> = Result<T, InterpreterError>;
+
+/// What to do after executing an instruction.
+enum ControlFlow<'a> {
+    /// Execute the next instruction in the EBB.
+    Continue,
+    /// Jump to the start of an EBB, passing it arguments.
+    Jump(Ebb, Vec<DataValue>),
+    /// Call a function, then continue with the next instruction.
+    Call(&'a Function, Vec<DataValue>),
+    /// Return from the current function.
+    Return(Vec<DataValue>),
+}
+
+/// An interpreter for the functions in a `FunctionStore`.
+///
+/// All the functions share a single `Memory`. Pointers into it can be passed to the interpreted
+/// functions as ordinary integers, including as the `vmctx` parameter used to compute the base
+/// and bound of heaps and tables.
+pub struct Interpreter<'a> {
+    functions: FunctionStore<'a>,
+    memory: Memory,
+}
+
+impl<'a> Interpreter<'a> {
+    /// Create an interpreter for the functions in `functions`, with an empty memory.
+    pub fn new(functions: FunctionStore<'a>) -> Self {
+        Self {
+            functions,
+            memory: Memory::new(),
+        }
+    }
+
+    /// The memory of the interpreted functions.
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    /// The memory of the interpreted functions, for setting up data before a call.
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    /// Call the function named `name` in the store, as written in Cranelift IR (e.g. `%foo`),
+    /// with `arguments`, and return its results.
+    pub fn call_by_name(
+        &mut self,
+        name: &str,
+        arguments: &[DataValue],
+    ) -> InterpreterResult<Vec<DataValue>> {
+        let function = self
+            .functions
+            .index_of(name)
+            .and_then(|index| self.functions.get(index))
+            .ok_or_else(|| InterpreterError::UnknownFunction(name.to_string()))?;
+        self.call(function, arguments)
+    }
+
+    /// Call `function` with `arguments`, and return its results. The function doesn't need to be
+    /// in the store, but the functions it calls do.
+    pub fn call(
+        &mut self,
+        function: &'a Function,
+        arguments: &[DataValue],
+    ) -> InterpreterResult<Vec<DataValue>> {
+        let base = self.memory.end();
+        let result = self.run(function, arguments);
+        self.memory.truncate(base);
+        result
+    }
+
+    /// Execute `function` until it returns or traps.
+    fn run(
+        &mut self,
+        function: &'a Function,
+        arguments: &[DataValue],
+    ) -> InterpreterResult<Vec<DataValue>> {
+        let mut stack = vec![self.enter(function, arguments)?];
+        loop {
+            let frame = stack.last_mut().expect("the call stack is never empty");
+            let inst = frame.pc;
+            match self.step(frame, inst)? {
+                ControlFlow::Continue => advance(frame)?,
+                ControlFlow::Jump(ebb, arguments) => jump(frame, ebb, arguments)?,
+                ControlFlow::Call(callee, arguments) => {
+                    if stack.len() >= MAX_CALL_DEPTH {
+                        return Err(TrapCode::StackOverflow.into());
+                    }
+                    let callee = self.enter(callee, &arguments)?;
+                    stack.push(callee);
+                }
+                ControlFlow::Return(results) => {
+                    let callee = stack.pop().expect("the call stack is never empty");
+                    self.memory.truncate(callee.stack_base);
+                    let caller = match stack.last_mut() {
+                        Some(caller) => caller,
+                        None => return Ok(results),
+                    };
+                    let dfg = &caller.function.dfg;
+                    for (&value, result) in dfg.inst_results(caller.pc).iter().zip(results) {
+                        caller.set(value, result);
+                    }
+                    advance(caller)?;
+                }
+            }
+        }
+    }
+
+    /// Create a frame for a call of `function` with `arguments`, and allocate its stack slots.
+    fn enter(
+        &mut self,
+        function: &'a Function,
+        arguments: &[DataValue],
+    ) -> InterpreterResult<Frame<'a>> {
+        check_arguments(function, arguments)?;
+        let ebb = function.layout.entry_block().ok_or_else(|| {
+            InterpreterError::Unsupported(format!("{} has no entry block", function.name))
+        })?;
+        let pc = function.layout.first_inst(ebb).ok_or_else(|| {
+            InterpreterError::Unsupported(format!("{} in {} is empty", ebb, function.name))
+        })?;
+
+        let mut frame = Frame::new(function, pc, self.memory.end());
+        for (slot, data) in function.stack_slots.iter() {
+            frame.stack_slots[slot] = self.memory.allocate(u64::from(data.size), STACK_SLOT_ALIGN);
+        }
+        for (&param, &argument) in function.dfg.ebb_params(ebb).iter().zip(arguments) {
+            frame.set(param, argument);
+        }
+        Ok(frame)
+    }
+
+    /// Execute `inst` in `frame`.
+    fn step(&mut self, frame: &mut Frame<'a>, inst: Inst) -> InterpreterResult<ControlFlow<'a>> {
+        let function = frame.function;
+        let dfg = &function.dfg;
+        let data = &dfg[inst];
+        let opcode = data.opcode();
+        let args = dfg.inst_args(inst);
+        let results = dfg.inst_results(inst);
+        let arg = |n: usize| frame.get(args[n]);
+        let result_type = || dfg.value_type(results[0]);
+
+        let result = match *data {
+            InstructionData::Unary { arg: x, .. } => {
+                let x = frame.get(x);
+                match opcode {
+                    Opcode::Copy | Opcode::Spill | Opcode::Fill | Opcode::FillNop => x,
+                    Opcode::CopyNop => return Ok(ControlFlow::Continue),
+                    _ => unary(opcode, x, result_type())?,
+                }
+            }
+            InstructionData::UnaryImm { imm, .. } => {
+                let imm: i64 = imm.into();
+                match opcode {
+                    Opcode::Iconst => from_i64(imm, result_type()),
+                    _ => return Err(unsupported(opcode)),
+                }
+            }
+            InstructionData::UnaryImm128 { imm, .. } => match opcode {
+                Opcode::Vconst => {
+                    let bytes = dfg.constants.get(imm).as_slice();
+                    if bytes.len() != 16 {
+                        return Err(unsupported(opcode));
+                    }
+                    let mut vector = [0; 16];
+                    vector.copy_from_slice(bytes);
+                    DataValue::V128(vector)
+                }
+                _ => return Err(unsupported(opcode)),
+            },
+            InstructionData::UnaryIeee32 { imm, .. } => DataValue::F32(imm),
+            InstructionData::UnaryIeee64 { imm, .. } => DataValue::F64(imm),
+            InstructionData::UnaryBool { imm, .. } => DataValue::B(imm),
+            InstructionData::UnaryGlobalValue { global_value, .. } => match opcode {
+                Opcode::GlobalValue => {
+                    let address = self.global_value(frame, global_value)?;
+                    from_u64(address, result_type())
+                }
+                _ => return Err(unsupported(opcode)),
+            },
+            InstructionData::Binary { args: [x, y], .. } => {
+                let (x, y) = (frame.get(x), frame.get(y));
+                match opcode {
+                    Opcode::Ifcmp => {
+                        frame.set_flags(results[0], Flags::Int(x, y));
+                        return Ok(ControlFlow::Continue);
+                    }
+                    Opcode::Ffcmp => {
+                        frame.set_flags(results[0], Flags::Float(x, y));
+                        return Ok(ControlFlow::Continue);
+                    }
+                    Opcode::IaddCout | Opcode::IsubBout => {
+                        let ty = result_type();
+                        let (value, c_out) = carry(opcode, x, y, false, ty);
+                        frame.set(results[0], value);
+                        frame.set_flags(results[1], Flags::Carry(c_out));
+                        return Ok(ControlFlow::Continue);
+                    }
+                    _ if is_float(x) => float_binary(opcode, x, y)?,
+                    _ => int_binary(opcode, x, y, result_type())?,
+                }
+            }
+            InstructionData::BinaryImm { arg: x, imm, .. } => {
+                let x = frame.get(x);
+                let imm: i64 = imm.into();
+                let ty = dfg.value_type(args[0]);
+                match opcode {
+                    Opcode::IfcmpImm => {
+                        frame.set_flags(results[0], Flags::Int(x, from_i64(imm, ty)));
+                        return Ok(ControlFlow::Continue);
+                    }
+                    Opcode::IrsubImm => int_binary(Opcode::Isub, from_i64(imm, ty), x, ty)?,
+                    _ => int_binary(without_imm(opcode), x, from_i64(imm, ty), ty)?,
+                }
+            }
+            InstructionData::Ternary {
+                args: [x, y, c], ..
+            } if takes_carry(opcode) => {
+                let (x, y) = (frame.get(x), frame.get(y));
+                let c_in = carry_flag(frame.get_flags(c));
+                let (value, c_out) = carry(opcode, x, y, c_in, result_type());
+                frame.set(results[0], value);
+                if let Some(&c_out_value) = results.get(1) {
+                    frame.set_flags(c_out_value, Flags::Carry(c_out));
+                }
+                return Ok(ControlFlow::Continue);
+            }
+            InstructionData::Ternary {
+                args: [x, y, z], ..
+            } => {
+                let (x, y, z) = (frame.get(x), frame.get(y), frame.get(z));
+                match opcode {
+                    Opcode::Select => {
+                        if is_true(x) {
+                            y
+                        } else {
+                            z
+                        }
+                    }
+                    Opcode::Fma => match x {
+                        DataValue::F32(_) => from_f32(to_f32(x).mul_add(to_f32(y), to_f32(z))),
+                        _ => from_f64(to_f64(x).mul_add(to_f64(y), to_f64(z))),
+                    },
+                    _ => return Err(unsupported(opcode)),
+                }
+            }
+            InstructionData::MultiAry { .. } => match opcode {
+                Opcode::Return | Opcode::FallthroughReturn => {
+                    return Ok(ControlFlow::Return(frame.get_all(args)));
+                }
+                _ => return Err(unsupported(opcode)),
+            },
+            InstructionData::NullAry { .. } => match opcode {
+                Opcode::Nop | Opcode::Fence | Opcode::Debugtrap => {
+                    return Ok(ControlFlow::Continue);
+                }
+                _ => return Err(unsupported(opcode)),
+            },
+            InstructionData::IntCompare {
+                cond, args: [x, y], ..
+            } => DataValue::B(int_cc(cond, frame.get(x), frame.get(y))),
+            InstructionData::IntCompareImm {
+                cond, arg: x, imm, ..
+            } => {
+                let y = from_i64(imm.into(), dfg.value_type(x));
+                DataValue::B(int_cc(cond, frame.get(x), y))
+            }
+            InstructionData::IntCond { cond, arg: f, .. } => {
+                DataValue::B(flags_cc(frame.get_flags(f), Condition::Int(cond))?)
+            }
+            InstructionData::FloatCompare {
+                cond, args: [x, y], ..
+            } => DataValue::B(float_cc(cond, frame.get(x), frame.get(y))),
+            InstructionData::FloatCond { cond, arg: f, .. } => {
+                DataValue::B(flags_cc(frame.get_flags(f), Condition::Float(cond))?)
+            }
+            InstructionData::IntSelect {
+                cond,
+                args: [f, x, y],
+                ..
+            } => {
+                if flags_cc(frame.get_flags(f), Condition::Int(cond))? {
+                    frame.get(x)
+                } else {
+                    frame.get(y)
+                }
+            }
+            InstructionData::Jump { destination, .. } => {
+                return Ok(ControlFlow::Jump(destination, frame.get_all(args)));
+            }
+            InstructionData::Branch { destination, .. } => {
+                let taken = is_true(arg(0));
+                return Ok(branch(
+                    frame,
+                    taken == (opcode == Opcode::Brnz),
+                    destination,
+                    &args[1..],
+                ));
+            }
+            InstructionData::BranchIcmp {
+                cond, destination, ..
+            } => {
+                let taken = int_cc(cond, arg(0), arg(1));
+                return Ok(branch(frame, taken, destination, &args[2..]));
+            }
+            InstructionData::BranchInt {
+                cond, destination, ..
+            } => {
+                let taken = flags_cc(frame.get_flags(args[0]), Condition::Int(cond))?;
+                return Ok(branch(frame, taken, destination, &args[1..]));
+            }
+            InstructionData::BranchFloat {
+                cond, destination, ..
+            } => {
+                let taken = flags_cc(frame.get_flags(args[0]), Condition::Float(cond))?;
+                return Ok(branch(frame, taken, destination, &args[1..]));
+            }
+            InstructionData::BranchTable {
+                arg: index,
+                destination,
+                table,
+                ..
+            } => {
+                let index = to_u64(frame.get(index));
+                let ebb = function.jump_tables[table]
+                    .as_slice()
+                    .get(index as usize)
+                    .cloned()
+                    .unwrap_or(destination);
+                return Ok(ControlFlow::Jump(ebb, Vec::new()));
+            }
+            InstructionData::Call { func_ref, .. } => {
+                let name = &dfg.ext_funcs[func_ref].name;
+                let callee = self
+                    .functions
+                    .get_by_name(name)
+                    .ok_or_else(|| InterpreterError::UnknownFunction(name.to_string()))?;
+                return Ok(ControlFlow::Call(callee, frame.get_all(args)));
+            }
+            InstructionData::CallIndirect { sig_ref, .. } => {
+                let address = to_u64(arg(0));
+                if address == 0 {
+                    return Err(TrapCode::IndirectCallToNull.into());
+                }
+                let callee = address
+                    .checked_sub(FUNCTION_BASE)
+                    .and_then(|index| self.functions.get(index as usize))
+                    .filter(|callee| same_types(&callee.signature, &dfg.signatures[sig_ref]))
+                    .ok_or(TrapCode::BadSignature)?;
+                return Ok(ControlFlow::Call(callee, frame.get_all(&args[1..])));
+            }
+            InstructionData::FuncAddr { func_ref, .. } => {
+                let name = dfg.ext_funcs[func_ref].name.to_string();
+                let index = self
+                    .functions
+                    .index_of(&name)
+                    .filter(|&index| FUNCTION_BASE + (index as u64) < MEMORY_BASE)
+                    .ok_or(InterpreterError::UnknownFunction(name))?;
+                from_u64(FUNCTION_BASE + index as u64, result_type())
+            }
+            InstructionData::Load { arg: p, offset, .. } => {
+                let address = to_u64(frame.get(p)).wrapping_add(offset_of(offset));
+                self.load(opcode, address, result_type())?
+            }
+            InstructionData::LoadComplex { offset, .. } => {
+                let address = sum(&frame.get_all(args)).wrapping_add(offset_of(offset));
+                self.load(opcode, address, result_type())?
+            }
+            InstructionData::Store {
+                args: [x, p],
+                offset,
+                ..
+            } => {
+                let (x, address) = (frame.get(x), to_u64(frame.get(p)));
+                let address = address.wrapping_add(offset_of(offset));
+                let ty = dfg.value_type(args[0]);
+                if opcode.is_atomic() && opcode != Opcode::AtomicStore {
+                    let old = self.load(Opcode::AtomicLoad, address, ty)?;
+                    let new = match opcode {
+                        Opcode::AtomicRmwXchg => x,
+                        Opcode::AtomicRmwAdd => int_binary(Opcode::Iadd, old, x, ty)?,
+                        Opcode::AtomicRmwSub => int_binary(Opcode::Isub, old, x, ty)?,
+                        Opcode::AtomicRmwAnd => int_binary(Opcode::Band, old, x, ty)?,
+                        Opcode::AtomicRmwOr => int_binary(Opcode::Bor, old, x, ty)?,
+                        Opcode::AtomicRmwXor => int_binary(Opcode::Bxor, old, x, ty)?,
+                        _ => return Err(unsupported(opcode)),
+                    };
+                    self.store(Opcode::AtomicStore, address, new, ty)?;
+                    old
+                } else {
+                    self.store(opcode, address, x, ty)?;
+                    return Ok(ControlFlow::Continue);
+                }
+            }
+            InstructionData::StoreComplex { offset, .. } => {
+                let address = sum(&frame.get_all(&args[1..])).wrapping_add(offset_of(offset));
+                self.store(opcode, address, arg(0), dfg.value_type(args[0]))?;
+                return Ok(ControlFlow::Continue);
+            }
+            InstructionData::AtomicCas {
+                args: [e, r, p], ..
+            } => {
+                let (expected, replacement) = (frame.get(e), frame.get(r));
+                let address = to_u64(frame.get(p));
+                let ty = dfg.value_type(e);
+                let old = self.load(Opcode::AtomicLoad, address, ty)?;
+                if old == expected {
+                    self.store(Opcode::AtomicStore, address, replacement, ty)?;
+                }
+                old
+            }
+            InstructionData::StackLoad {
+                stack_slot, offset, ..
+            } => {
+                let address = frame.stack_slots[stack_slot].wrapping_add(offset_of(offset));
+                match opcode {
+                    Opcode::StackAddr => from_u64(address, result_type()),
+                    _ => self.load(Opcode::Load, address, result_type())?,
+                }
+            }
+            InstructionData::StackStore {
+                arg: x,
+                stack_slot,
+                offset,
+                ..
+            } => {
+                let address = frame.stack_slots[stack_slot].wrapping_add(offset_of(offset));
+                self.store(Opcode::Store, address, frame.get(x), dfg.value_type(x))?;
+                return Ok(ControlFlow::Continue);
+            }
+            InstructionData::HeapAddr {
+                arg: index,
+                heap,
+                imm: size,
+                ..
+            } => {
+                let heap = &function.heaps[heap];
+                let index = to_u64(frame.get(index));
+                let size: u32 = size.into();
+                let bound = match heap.style {
+                    HeapStyle::Static { bound } => bound.into(),
+                    HeapStyle::Dynamic { bound_gv } => self.global_value(frame, bound_gv)?,
+                };
+                if index
+                    .checked_add(u64::from(size))
+                    .map_or(true, |end| end > bound)
+                {
+                    return Err(TrapCode::HeapOutOfBounds.into());
+                }
+                let base = self.global_value(frame, heap.base)?;
+                from_u64(base.wrapping_add(index), result_type())
+            }
+            InstructionData::TableAddr {
+                arg: index,
+                table,
+                offset,
+                ..
+            } => {
+                let table = &function.tables[table];
+                let index = to_u64(frame.get(index));
+                if index >= self.global_value(frame, table.bound_gv)? {
+                    return Err(TrapCode::TableOutOfBounds.into());
+                }
+                let element_size: u64 = table.element_size.into();
+                let base = self.global_value(frame, table.base_gv)?;
+                let address = base
+                    .wrapping_add(index.wrapping_mul(element_size))
+                    .wrapping_add(offset_of(offset));
+                from_u64(address, result_type())
+            }
+            InstructionData::Trap { code, .. } => match opcode {
+                Opcode::Trap | Opcode::ResumableTrap => return Err(code.into()),
+                _ => return Err(unsupported(opcode)),
+            },
+            InstructionData::CondTrap { arg: x, code, .. } => {
+                if is_true(frame.get(x)) == (opcode == Opcode::Trapnz) {
+                    return Err(code.into());
+                }
+                return Ok(ControlFlow::Continue);
+            }
+            InstructionData::IntCondTrap {
+                cond, arg: f, code, ..
+            } => {
+                if flags_cc(frame.get_flags(f), Condition::Int(cond))? {
+                    return Err(code.into());
+                }
+                return Ok(ControlFlow::Continue);
+            }
+            InstructionData::FloatCondTrap {
+                cond, arg: f, code, ..
+            } => {
+                if flags_cc(frame.get_flags(f), Condition::Float(cond))? {
+                    return Err(code.into());
+                }
+                return Ok(ControlFlow::Continue);
+            }
+            _ => return Err(unsupported(opcode)),
+        };
+
+        frame.set(results[0], result);
+        Ok(ControlFlow::Continue)
+    }
+
+    /// Compute the value of the global value `gv` in `frame`.
+    fn global_value(&self, frame: &Frame<'a>, gv: GlobalValue) -> InterpreterResult<u64> {
+        let function = frame.function;
+        match function.global_values[gv] {
+            GlobalValueData::VMContext => {
+                let vmctx = function
+                    .special_param(ArgumentPurpose::VMContext)
+                    .ok_or_else(|| {
+                        InterpreterError::Unsupported(format!("{} without a vmctx parameter", gv))
+                    })?;
+                Ok(to_u64(frame.get(vmctx)))
+            }
+            GlobalValueData::Load {
+                base,
+                offset,
+                global_type,
+                ..
+            } => {
+                let address = self
+                    .global_value(frame, base)?
+                    .wrapping_add(offset_of(offset));
+                Ok(to_u64(self.load(Opcode::Load, address, global_type)?))
+            }
+            GlobalValueData::IAddImm { base, offset, .. } => {
+                let offset: i64 = offset.into();
+                Ok(self.global_value(frame, base)?.wrapping_add(offset as u64))
+            }
+            GlobalValueData::Symbol { ref name, .. } => Err(InterpreterError::Unsupported(
+                format!("symbolic global value {}", name),
+            )),
+        }
+    }
+
+    /// Execute a load instruction reading from `address` into a value of type `ty`.
+    fn load(&self, opcode: Opcode, address: u64, ty: Type) -> InterpreterResult<DataValue> {
+        let (size, signed) = match opcode {
+            Opcode::Uload8 | Opcode::Uload8Complex => (1, false),
+            Opcode::Sload8 | Opcode::Sload8Complex => (1, true),
+            Opcode::Uload16 | Opcode::Uload16Complex => (2, false),
+            Opcode::Sload16 | Opcode::Sload16Complex => (2, true),
+            Opcode::Uload32 | Opcode::Uload32Complex => (4, false),
+            Opcode::Sload32 | Opcode::Sload32Complex => (4, true),
+            _ => {
+                if !DataValue::is_supported(ty) {
+                    return Err(InterpreterError::Unsupported(format!("loads of {}", ty)));
+                }
+                let bytes = self.memory.read(address, ty.bytes() as usize)?;
+                return Ok(DataValue::read_from_slice(bytes, ty));
+            }
+        };
+        let mut bytes = [0; 8];
+        bytes[..size].copy_from_slice(self.memory.read(address, size)?);
+        let bits = u64::from_le_bytes(bytes);
+        let shift = 64 - 8 * size as u32;
+        Ok(if signed {
+            from_i64(((bits << shift) as i64) >> shift, ty)
+        } else {
+            from_u64(bits, ty)
+        })
+    }
+
+    /// Execute a store instruction writing `value` of type `ty` at `address`.
+    fn store(
+        &mut self,
+        opcode: Opcode,
+        address: u64,
+        value: DataValue,
+        ty: Type,
+    ) -> InterpreterResult<()> {
+        let size = match opcode {
+            Opcode::Istore8 | Opcode::Istore8Complex => 1,
+            Opcode::Istore16 | Opcode::Istore16Complex => 2,
+            Opcode::Istore32 | Opcode::Istore32Complex => 4,
+            _ => {
+                if !DataValue::is_supported(ty) {
+                    return Err(InterpreterError::Unsupported(format!("stores of {}", ty)));
+                }
+                let mut bytes = vec![0; ty.bytes() as usize];
+                value.write_to_slice(&mut bytes);
+                return Ok(self.memory.write(address, &bytes)?);
+            }
+        };
+        let bytes = to_u64(value).to_le_bytes();
+        Ok(self.memory.write(address, &bytes[..size])?)
+    }
+}
+
+/// Move `frame` to the instruction after the current one.
+fn advance(frame: &mut Frame) -> InterpreterResult<()> {
+    frame.pc = frame.function.layout.next_inst(frame.pc).ok_or_else(|| {
+        InterpreterError::Unsupported(format!("falling off the end of an EBB after {}", frame.pc))
+    })?;
+    Ok(())
+}
+
+/// Move `frame` to the start of `ebb`, defining its parameters as `arguments`.
+fn jump(frame: &mut Frame, ebb: Ebb, arguments: Vec<DataValue>) -> InterpreterResult<()> {
+    let function = frame.function;
+    for (&param, argument) in function.dfg.ebb_params(ebb).iter().zip(arguments) {
+        frame.set(param, argument);
+    }
+    frame.pc = function.layout.first_inst(ebb).ok_or_else(|| {
+        InterpreterError::Unsupported(format!("{} in {} is empty", ebb, function.name))
+    })?;
+    Ok(())
+}
+
+/// Jump to `destination` with the values of `args` if `taken`, or continue.
+fn branch<'a>(frame: &Frame, taken: bool, destination: Ebb, args: &[Value]) -> ControlFlow<'a> {
+    if taken {
+        ControlFlow::Jump(destination, frame.get_all(args))
+    } else {
+        ControlFlow::Continue
+    }
+}
+
+/// Check that `arguments` have the types of the parameters of `function`.
+fn check_arguments(function: &Function, arguments: &[DataValue]) -> InterpreterResult<()> {
+    let params = &function.signature.params;
+    let error = |message| {
+        Err(InterpreterError::BadArguments(
+            function.name.to_string(),
+            message,
+        ))
+    };
+    if params.len() != arguments.len() {
+        return error(format!(
+            "expected {} arguments, got {}",
+            params.len(),
+            arguments.len()
+        ));
+    }
+    for (param, &argument) in params.iter().zip(arguments) {
+        if !has_type(argument, param.value_type) {
+            return error(format!("{} is not a {}", argument, param.value_type));
+        }
+    }
+    Ok(())
+}
+
+/// Do the parameters and returns of `a` and `b` have the same types?
+fn same_types(a: &Signature, b: &Signature) -> bool {
+    let types = |sig: &Signature| {
+        (
+            sig.params.iter().map(|p| p.value_type).collect::<Vec<_>>(),
+            sig.returns.iter().map(|p| p.value_type).collect::<Vec<_>>(),
+        )
+    };
+    types(a) == types(b)
+}
+
+/// Get the signed value of a memory offset, as an address increment.
+fn offset_of<T: Into<i64>>(offset: T) -> u64 {
+    offset.into() as u64
+}
+
+/// The sum of the addresses in `values`, as used by the complex loads and stores.
+fn sum(values: &[DataValue]) -> u64 {
+    values
+        .iter()
+        .fold(0, |sum: u64, &value| sum.wrapping_add(to_u64(value)))
+}
+
+/// The error for an instruction the interpreter can't execute.
+fn unsupported(opcode: Opcode) -> InterpreterError {
+    InterpreterError::Unsupported(format!("instruction {}", opcode))
+}
+
+/// Is `value` a floating point number?
+fn is_float(value: DataValue) -> bool {
+    match value {
+        DataValue::F32(_) | DataValue::F64(_) => true,
+        _ => false,
+    }
+}
+
+/// Get an `f32` or `f64` value as an `f64`, which represents all `f32` values exactly.
+fn to_float(value: DataValue) -> f64 {
+    match value {
+        DataValue::F32(_) => f64::from(to_f32(value)),
+        _ => to_f64(value),
+    }
+}
+
+/// The `_imm` form of a binary instruction takes its second operand as an immediate; get the
+/// opcode of the form taking a value.
+fn without_imm(opcode: Opcode) -> Opcode {
+    match opcode {
+        Opcode::IaddImm => Opcode::Iadd,
+        Opcode::ImulImm => Opcode::Imul,
+        Opcode::UdivImm => Opcode::Udiv,
+        Opcode::SdivImm => Opcode::Sdiv,
+        Opcode::UremImm => Opcode::Urem,
+        Opcode::SremImm => Opcode::Srem,
+        Opcode::BandImm => Opcode::Band,
+        Opcode::BorImm => Opcode::Bor,
+        Opcode::BxorImm => Opcode::Bxor,
+        Opcode::RotlImm => Opcode::Rotl,
+        Opcode::RotrImm => Opcode::Rotr,
+        Opcode::IshlImm => Opcode::Ishl,
+        Opcode::UshrImm => Opcode::Ushr,
+        Opcode::SshrImm => Opcode::Sshr,
+        _ => opcode,
+    }
+}
+
+/// Execute a binary integer or boolean instruction on values of type `ty`.
+fn int_binary(
+    opcode: Opcode,
+    x: DataValue,
+    y: DataValue,
+    ty: Type,
+) -> InterpreterResult<DataValue> {
+    let bits = if ty.is_bool() { 1 } else { ty.bits() };
+    let (ux, uy) = (to_u64(x), to_u64(y));
+    let (sx, sy) = (to_i64(x), to_i64(y));
+    let (min, max) = (!(mask(bits) >> 1) as i64, (mask(bits) >> 1) as i64);
+    let shift = uy % u64::from(bits);
+    let result = match opcode {
+        Opcode::Iadd => ux.wrapping_add(uy),
+        Opcode::Isub => ux.wrapping_sub(uy),
+        Opcode::Imul => ux.wrapping_mul(uy),
+        Opcode::Umulhi => ((u128::from(ux) * u128::from(uy)) >> bits) as u64,
+        Opcode::Smulhi => ((i128::from(sx) * i128::from(sy)) >> bits) as u64,
+        Opcode::Udiv | Opcode::Urem | Opcode::Sdiv | Opcode::Srem if uy == 0 => {
+            return Err(TrapCode::IntegerDivisionByZero.into());
+        }
+        Opcode::Udiv => ux / uy,
+        Opcode::Urem => ux % uy,
+        Opcode::Sdiv if sx == min && sy == -1 => {
+            return Err(TrapCode::IntegerOverflow.into());
+        }
+        Opcode::Sdiv => sx.wrapping_div(sy) as u64,
+        Opcode::Srem => sx.wrapping_rem(sy) as u64,
+        Opcode::UaddSat => (ux + uy).min(mask(bits)),
+        Opcode::UsubSat => ux.saturating_sub(uy),
+        Opcode::SaddSat => (i128::from(sx) + i128::from(sy))
+            .max(i128::from(min))
+            .min(i128::from(max)) as u64,
+        Opcode::SsubSat => (i128::from(sx) - i128::from(sy))
+            .max(i128::from(min))
+            .min(i128::from(max)) as u64,
+        Opcode::Band => ux & uy,
+        Opcode::Bor => ux | uy,
+        Opcode::Bxor => ux ^ uy,
+        Opcode::BandNot => ux & !uy,
+        Opcode::BorNot => ux | !uy,
+        Opcode::BxorNot => ux ^ !uy,
+        Opcode::Ishl => ux << shift,
+        Opcode::Ushr => ux >> shift,
+        Opcode::Sshr => (sx >> shift) as u64,
+        Opcode::Rotl if shift != 0 => (ux << shift) | (ux >> (u64::from(bits) - shift)),
+        Opcode::Rotr if shift != 0 => (ux >> shift) | (ux << (u64::from(bits) - shift)),
+        Opcode::Rotl | Opcode::Rotr => ux,
+        _ => return Err(unsupported(opcode)),
+    };
+    Ok(from_u64(result & mask(bits), ty))
+}
+
+/// Does `opcode` read a carry or borrow flag?
+fn takes_carry(opcode: Opcode) -> bool {
+    match opcode {
+        Opcode::IaddCin | Opcode::IaddCarry | Opcode::IsubBin | Opcode::IsubBorrow => true,
+        _ => false,
+    }
+}
+
+/// Execute an add or subtract with a carry or borrow, returning the result and the carry or
+/// borrow out.
+fn carry(opcode: Opcode, x: DataValue, y: DataValue, c_in: bool, ty: Type) -> (DataValue, bool) {
+    let (x, y, c) = (
+        u128::from(to_u64(x)),
+        u128::from(to_u64(y)),
+        u128::from(c_in),
+    );
+    let (result, c_out) = match opcode {
+        Opcode::IaddCin | Opcode::IaddCout | Opcode::IaddCarry => {
+            let sum = x + y + c;
+            (sum, sum > u128::from(mask(ty.bits())))
+        }
+        _ => (x.wrapping_sub(y + c), x < y + c),
+    };
+    (from_u64(result as u64, ty), c_out)
+}
+
+/// Round `x` to the nearest integer, with ties to even.
+macro_rules! nearest {
+    ($x:expr) => {{
+        let x = $x;
+        let rounded = x.round();
+        if (rounded - x).abs() == 0.5 {
+            2.0 * (x / 2.0).round()
+        } else {
+            rounded
+        }
+    }};
+}
+
+/// Apply the floating point operation `opcode` to `x` and `y` of the same float type.
+macro_rules! float_binary {
+    ($opcode:expr, $x:expr, $y:expr) => {{
+        let (x, y) = ($x, $y);
+        match $opcode {
+            Opcode::Fadd => x + y,
+            Opcode::Fsub => x - y,
+            Opcode::Fmul => x * y,
+            Opcode::Fdiv => x / y,
+            Opcode::Fcopysign => x.copysign(y),
+            // `fmin` and `fmax` propagate NaNs, and order -0.0 before +0.0.
+            Opcode::Fmin if x.is_nan() || y.is_nan() => x + y,
+            Opcode::Fmax if x.is_nan() || y.is_nan() => x + y,
+            Opcode::Fmin if x == y && x.is_sign_negative() => x,
+            Opcode::Fmax if x == y && x.is_sign_positive() => x,
+            Opcode::Fmin if x < y => x,
+            Opcode::Fmax if x > y => x,
+            Opcode::Fmin | Opcode::Fmax => y,
+            _ => return Err(unsupported($opcode)),
+        }
+    }};
+}
+
+/// Execute a binary floating point instruction.
+fn float_binary(opcode: Opcode, x: DataValue, y: DataValue) -> InterpreterResult<DataValue> {
+    Ok(match x {
+        DataValue::F32(_) => from_f32(float_binary!(opcode, to_f32(x), to_f32(y))),
+        _ => from_f64(float_binary!(opcode, to_f64(x), to_f64(y))),
+    })
+}
+
+/// Apply the unary floating point operation `opcode` to `x`.
+macro_rules! float_unary {
+    ($opcode:expr, $x:expr) => {{
+        let x = $x;
+        match $opcode {
+            Opcode::Fneg => -x,
+            Opcode::Fabs => x.abs(),
+            Opcode::Sqrt => x.sqrt(),
+            Opcode::Ceil => x.ceil(),
+            Opcode::Floor => x.floor(),
+            Opcode::Trunc => x.trunc(),
+            Opcode::Nearest => nearest!(x),
+            _ => return Err(unsupported($opcode)),
+        }
+    }};
+}
+
+/// Execute a unary instruction producing a value of type `ty`.
+fn unary(opcode: Opcode, x: DataValue, ty: Type) -> InterpreterResult<DataValue> {
+    let bits = if ty.is_bool() { 1 } else { ty.bits() };
+    Ok(match opcode {
+        Opcode::Bnot => from_u64(!to_u64(x) & mask(bits), ty),
+        Opcode::Clz => from_u64(
+            u64::from(
+                (to_u64(x) << (64 - u32::from(bits)))
+                    .leading_zeros()
+                    .min(u32::from(bits)),
+            ),
+            ty,
+        ),
+        Opcode::Ctz => from_u64(u64::from((to_u64(x) | !mask(bits)).trailing_zeros()), ty),
+        Opcode::Cls => {
+            let x = to_i64(x) << (64 - u32::from(bits));
+            let sign_bits = if x < 0 {
+                x.leading_ones()
+            } else {
+                x.leading_zeros()
+            };
+            from_u64(u64::from(sign_bits.min(u32::from(bits)) - 1), ty)
+        }
+        Opcode::Popcnt => from_u64(u64::from(to_u64(x).count_ones()), ty),
+        Opcode::Bitrev => from_u64(to_u64(x).reverse_bits() >> (64 - u32::from(bits)), ty),
+        Opcode::Bint => from_u64(to_u64(x), ty),
+        Opcode::Bmask => from_i64(-to_i64(x), ty),
+        Opcode::Breduce | Opcode::Bextend => x,
+        Opcode::Ireduce | Opcode::Uextend => from_u64(to_u64(x), ty),
+        Opcode::Sextend => from_i64(to_i64(x), ty),
+        Opcode::Bitcast => from_bits(to_bits(x), ty),
+        Opcode::RawBitcast => match x {
+            DataValue::V128(_) if ty.is_vector() => x,
+            _ if !ty.is_vector() => from_bits(to_bits(x), ty),
+            _ => return Err(unsupported(opcode)),
+        },
+        Opcode::Fpromote => from_f64(f64::from(to_f32(x))),
+        Opcode::Fdemote => from_f32(to_f64(x) as f32),
+        Opcode::FcvtToUint | Opcode::FcvtToUintSat | Opcode::FcvtToSint | Opcode::FcvtToSintSat => {
+            fcvt_to_int(opcode, to_float(x), ty)?
+        }
+        Opcode::FcvtFromUint => match ty {
+            types::F32 => from_f32(to_u64(x) as f32),
+            _ => from_f64(to_u64(x) as f64),
+        },
+        Opcode::FcvtFromSint => match ty {
+            types::F32 => from_f32(to_i64(x) as f32),
+            _ => from_f64(to_i64(x) as f64),
+        },
+        _ => match x {
+            DataValue::F32(_) => from_f32(float_unary!(opcode, to_f32(x))),
+            DataValue::F64(_) => from_f64(float_unary!(opcode, to_f64(x))),
+            _ => return Err(unsupported(opcode)),
+        },
+    })
+}
+
+/// Convert the float `x` to an integer of type `ty`, trapping or saturating when it is NaN or
+/// out of range.
+fn fcvt_to_int(opcode: Opcode, x: f64, ty: Type) -> InterpreterResult<DataValue> {
+    let bits = i32::from(ty.bits());
+    let signed = opcode == Opcode::FcvtToSint || opcode == Opcode::FcvtToSintSat;
+    let saturating = opcode == Opcode::FcvtToUintSat || opcode == Opcode::FcvtToSintSat;
+    let (min, limit) = if signed {
+        (-(2f64.powi(bits - 1)), 2f64.powi(bits - 1))
+    } else {
+        (0.0, 2f64.powi(bits))
+    };
+    let x = x.trunc();
+    if x.is_nan() {
+        if saturating {
+            return Ok(from_u64(0, ty));
+        }
+        return Err(TrapCode::BadConversionToInteger.into());
+    }
+    if x < min || x >= limit {
+        if !saturating {
+            return Err(TrapCode::IntegerOverflow.into());
+        }
+        let max = if signed {
+            mask(ty.bits()) >> 1
+        } else {
+            mask(ty.bits())
+        };
+        return Ok(if x < min {
+            from_i64(min as i64, ty)
+        } else {
+            from_u64(max, ty)
+        });
+    }
+    Ok(if signed {
+        from_i64(x as i64, ty)
+    } else {
+        from_u64(x as u64, ty)
+    })
+}
+
+/// Evaluate an integer condition code on `x` and `y`.
+fn int_cc(cond: IntCC, x: DataValue, y: DataValue) -> bool {
+    let (ux, uy) = (to_u64(x), to_u64(y));
+    let (sx, sy) = (to_i64(x), to_i64(y));
+    match cond {
+        IntCC::Equal => ux == uy,
+        IntCC::NotEqual => ux != uy,
+        IntCC::SignedLessThan => sx < sy,
+        IntCC::SignedGreaterThanOrEqual => sx >= sy,
+        IntCC::SignedGreaterThan => sx > sy,
+        IntCC::SignedLessThanOrEqual => sx <= sy,
+        IntCC::UnsignedLessThan => ux < uy,
+        IntCC::UnsignedGreaterThanOrEqual => ux >= uy,
+        IntCC::UnsignedGreaterThan => ux > uy,
+        IntCC::UnsignedLessThanOrEqual => ux <= uy,
+    }
+}
+
+/// Evaluate a floating point condition code on `x` and `y`.
+fn float_cc(cond: FloatCC, x: DataValue, y: DataValue) -> bool {
+    let (x, y) = (to_float(x), to_float(y));
+    let unordered = x.is_nan() || y.is_nan();
+    match cond {
+        FloatCC::Ordered => !unordered,
+        FloatCC::Unordered => unordered,
+        FloatCC::Equal => x == y,
+        FloatCC::NotEqual => x != y,
+        FloatCC::OrderedNotEqual => !unordered && x != y,
+        FloatCC::UnorderedOrEqual => unordered || x == y,
+        FloatCC::LessThan => x < y,
+        FloatCC::LessThanOrEqual => x <= y,
+        FloatCC::GreaterThan => x > y,
+        FloatCC::GreaterThanOrEqual => x >= y,
+        FloatCC::UnorderedOrLessThan => unordered || x < y,
+        FloatCC::UnorderedOrLessThanOrEqual => unordered || x <= y,
+        FloatCC::UnorderedOrGreaterThan => unordered || x > y,
+        FloatCC::UnorderedOrGreaterThanOrEqual => unordered || x >= y,
+    }
+}
+
+/// A condition code tested on a flags value.
+enum Condition {
+    Int(IntCC),
+    Float(FloatCC),
+}
+
+/// Test the condition `cond` on the instruction which produced `flags`.
+fn flags_cc(flags: Flags, cond: Condition) -> InterpreterResult<bool> {
+    Ok(match (flags, cond) {
+        (Flags::Int(x, y), Condition::Int(cond)) => int_cc(cond, x, y),
+        (Flags::Float(x, y), Condition::Float(cond)) => float_cc(cond, x, y),
+        (Flags::Carry(c), Condition::Int(IntCC::UnsignedLessThan)) => c,
+        (Flags::Carry(c), Condition::Int(IntCC::UnsignedGreaterThanOrEqual)) => !c,
+        (Flags::Carry(_), Condition::Int(cond)) => {
+            return Err(InterpreterError::Unsupported(format!(
+                "testing carry flags with {}",
+                cond
+            )));
+        }
+        _ => panic!("flags tested with the wrong kind of condition code"),
+    })
+}
+
+/// Get the carry or borrow from integer flags. Flags from a comparison have the carry set if
+/// the first operand is less than the second, as if they were subtracted.
+fn carry_flag(flags: Flags) -> bool {
+    match flags {
+        Flags::Carry(c) => c,
+        Flags::Int(x, y) => int_cc(IntCC::UnsignedLessThan, x, y),
+        Flags::Float(..) => panic!("carry read from floating point flags"),
+    }
+}
+
+/// Does `value` have the type `ty`?
+fn has_type(value: DataValue, ty: Type) -> bool {
+    match value {
+        DataValue::B(_) => ty.is_bool(),
+        DataValue::I8(_) => ty == types::I8,
+        DataValue::I16(_) => ty == types::I16,
+        DataValue::I32(_) => ty == types::I32,
+        DataValue::I64(_) => ty == types::I64,
+        DataValue::F32(_) => ty == types::F32,
+        DataValue::F64(_) => ty == types::F64,
+        DataValue::V128(_) => ty.is_vector() && ty.bits() == 128,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cranelift_reader::parse_functions;
+
+    /// Interpret the first of the functions in `text`, with all of them in the store.
+    fn interpret(text: &str, args: &[DataValue]) -> InterpreterResult<Vec<DataValue>> {
+        let functions = parse_functions(text).unwrap();
+        let mut store = FunctionStore::new();
+        for function in &functions {
+            store.add(function);
+        }
+        Interpreter::new(store).call(&functions[0], args)
+    }
+
+    #[test]
+    fn arithmetic() {
+        let text = "
+            function %f(i32, i32) -> i32, i8 {
+            ebb0(v0: i32, v1: i32):
+                v2 = imul v0, v1
+                v3 = iadd_imm v2, -3
+                v4 = ireduce.i8 v3
+                return v3, v4
+            }";
+        let results = interpret(text, &[DataValue::I32(100), DataValue::I32(3)]).unwrap();
+        assert_eq!(results, [DataValue::I32(297), DataValue::I8(41)]);
+    }
+
+    #[test]
+    fn branches_and_traps() {
+        let text = "
+            function %f(i64, i64) -> i64 {
+            ebb0(v0: i64, v1: i64):
+                v2 = ifcmp v0, v1
+                brif sgt v2, ebb1(v1, v0)
+                jump ebb1(v0, v1)
+            ebb1(v3: i64, v4: i64):
+                v5 = sdiv v3, v4
+                return v5
+            }";
+        let call = |x, y| interpret(text, &[DataValue::I64(x), DataValue::I64(y)]);
+        assert_eq!(call(-9, 2).unwrap(), [DataValue::I64(-4)]);
+        assert_eq!(call(2, -9).unwrap(), [DataValue::I64(-4)]);
+        assert_eq!(
+            call(-5, 0),
+            Err(InterpreterError::Trap(TrapCode::IntegerDivisionByZero))
+        );
+        assert_eq!(
+            call(-1, i64::min_value()),
+            Err(InterpreterError::Trap(TrapCode::IntegerOverflow))
+        );
+    }
+
+    #[test]
+    fn floats() {
+        let text = "
+            function %f(f32, f32) -> f32, i32, b1 {
+            ebb0(v0: f32, v1: f32):
+                v2 = fmin v0, v1
+                v3 = nearest v2
+                v4 = fcvt_to_sint_sat.i32 v1
+                v5 = fcmp uno v0, v1
+                return v3, v4, v5
+            }";
+        let results = interpret(text, &[from_f32(2.5), from_f32(1e10)]).unwrap();
+        assert_eq!(
+            results,
+            [
+                from_f32(2.0),
+                DataValue::I32(i32::max_value()),
+                DataValue::B(false)
+            ]
+        );
+        let results = interpret(text, &[from_f32(-0.0), from_f32(std::f32::NAN)]).unwrap();
+        assert!(to_f32(results[0]).is_nan());
+        assert_eq!(results[1..], [DataValue::I32(0), DataValue::B(true)]);
+    }
+
+    #[test]
+    fn stack_slots() {
+        let text = "
+            function %f(i32) -> i64 {
+                ss0 = explicit_slot 8
+            ebb0(v0: i32):
+                stack_store v0, ss0+4
+                v1 = stack_addr.i64 ss0
+                istore8 v0, v1+1
+                v2 = load.i64 v1
+                return v2
+            }";
+        let results = interpret(text, &[DataValue::I32(0x1234_5678)]).unwrap();
+        assert_eq!(results, [DataValue::I64(0x1234_5678_0000_7800)]);
+    }
+
+    #[test]
+    fn calls() {
+        let text = "
+            function %fib(i32) -> i32 {
+                sig0 = (i32) -> i32
+                fn0 = %fib sig0
+            ebb0(v0: i32):
+                v1 = icmp_imm ult v0, 2
+                brz v1, ebb1
+                return v0
+            ebb1:
+                v2 = iadd_imm v0, -1
+                v3 = call fn0(v2)
+                v4 = iadd_imm v0, -2
+                v5 = func_addr.i64 fn0
+                v6 = call_indirect sig0, v5(v4)
+                v7 = iadd v3, v6
+                return v7
+            }";
+        let results = interpret(text, &[DataValue::I32(12)]).unwrap();
+        assert_eq!(results, [DataValue::I32(144)]);
+
+        let text = "
+            function %forever() {
+                fn0 = %forever()
+            ebb0:
+                call fn0()
+                return
+            }";
+        assert_eq!(
+            interpret(text, &[]),
+            Err(InterpreterError::Trap(TrapCode::StackOverflow))
+        );
+    }
+
+    #[test]
+    fn heaps() {
+        let text = "
+            function %f(i32, i64 vmctx) -> i32 {
+                gv0 = vmctx
+                gv1 = load.i64 notrap aligned gv0
+                gv2 = load.i64 notrap aligned gv0+8
+                heap0 = dynamic gv1, bound gv2, offset_guard 0, index_type i32
+            ebb0(v0: i32, v1: i64):
+                v2 = heap_addr.i64 heap0, v0, 4
+                v3 = load.i32 v2
+                return v3
+            }";
+        let functions = parse_functions(text).unwrap();
+        let mut interpreter = Interpreter::new(FunctionStore::new());
+        let memory = interpreter.memory_mut();
+        let heap = memory.allocate(16, 8);
+        memory.write(heap + 8, &[1, 2, 3, 4]).unwrap();
+        let vmctx = memory.allocate(16, 8);
+        memory.write(vmctx, &heap.to_le_bytes()).unwrap();
+        memory.write(vmctx + 8, &16u64.to_le_bytes()).unwrap();
+
+        let mut call = |index| {
+            let args = [DataValue::I32(index), DataValue::I64(vmctx as i64)];
+            interpreter.call(&functions[0], &args)
+        };
+        assert_eq!(call(8).unwrap(), [DataValue::I32(0x0403_0201)]);
+        assert_eq!(
+            call(13),
+            Err(InterpreterError::Trap(TrapCode::HeapOutOfBounds))
+        );
+    }
+
+    #[test]
+    fn bad_arguments() {
+        let text = "
+            function %f(i32) {
+            ebb0(v0: i32):
+                return
+            }";
+        assert!(interpret(text, &[DataValue::I64(0)]).is_err());
+        assert!(interpret(text, &[]).is_err());
+    }
+}
@@ -0,0 +1,39 @@
+//! Cranelift IR interpreter.
+//!
+//! The `cranelift_interpreter` library executes Cranelift IR functions directly, by evaluating
+//! each instruction over the values of the data flow graph. It doesn't need a target ISA or
+//! executable memory, so the behavior of a function can be observed on any host, and compared
+//! before and after a transformation.
+
+#![deny(
+    missing_docs,
+    trivial_numeric_casts,
+    unused_extern_crates,
+    unstable_features
+)]
+#![warn(unused_import_braces)]
+#![cfg_attr(feature = "clippy", plugin(clippy(conf_file = "../../clippy.toml")))]
+#![cfg_attr(feature = "cargo-clippy", allow(clippy::new_without_default))]
+#![cfg_attr(
+    feature = "cargo-clippy",
+    warn(
+        clippy::mut_mut,
+        clippy::nonminimal_bool,
+        clippy::option_map_unwrap_or,
+        clippy::option_map_unwrap_or_else,
+        clippy::print_stdout,
+        clippy::unicode_not_nfc,
+        clippy::use_self
+    )
+)]
+
+pub use crate::environment::FunctionStore;
+pub use crate::interpreter::{Interpreter, InterpreterError, InterpreterResult};
+pub use crate::memory::Memory;
+pub use cranelift_reader::DataValue;
+
+mod environment;
+mod frame;
+mod interpreter;
+mod memory;
+mod value;
@@ -0,0 +1,96 @@
+//! The memory of the interpreter.
+
+use cranelift_codegen::ir::TrapCode;
+
+/// The address of the first byte of memory. Lower addresses are never valid, so null pointers
+/// always trap, and the addresses of functions can't be confused with data.
+pub const MEMORY_BASE: u64 = 0x1_0000;
+
+/// A contiguous, little-endian byte-addressed memory.
+///
+/// The memory grows like a stack: each interpreted call allocates its stack slots at the end of
+/// the memory and releases them when it returns. Memory allocated with `allocate` while no
+/// function is running stays valid until it is explicitly released with `truncate`.
+///
+/// Addresses are 64 bits wide, and small enough to be used as 32-bit pointers too.
+#[derive(Default)]
+pub struct Memory {
+    bytes: Vec<u8>,
+}
+
+impl Memory {
+    /// Create an empty memory.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allocate `size` zeroed bytes aligned to `align`, returning their address.
+    pub fn allocate(&mut self, size: u64, align: u64) -> u64 {
+        debug_assert!(align.is_power_of_two());
+        let start = (self.bytes.len() as u64 + align - 1) & !(align - 1);
+        self.bytes.resize((start + size) as usize, 0);
+        MEMORY_BASE + start
+    }
+
+    /// The address one past the last allocated byte.
+    pub fn end(&self) -> u64 {
+        MEMORY_BASE + self.bytes.len() as u64
+    }
+
+    /// Release all the memory at and after the address `end`.
+    pub fn truncate(&mut self, end: u64) {
+        debug_assert!(end >= MEMORY_BASE);
+        self.bytes.truncate((end - MEMORY_BASE) as usize);
+    }
+
+    /// Get the `size` bytes at `address`, or trap if they aren't all allocated.
+    pub fn read(&self, address: u64, size: usize) -> Result<&[u8], TrapCode> {
+        let start = self.offset(address, size)?;
+        Ok(&self.bytes[start..start + size])
+    }
+
+    /// Write `bytes` at `address`, or trap if they don't fit in the allocated memory.
+    pub fn write(&mut self, address: u64, bytes: &[u8]) -> Result<(), TrapCode> {
+        let start = self.offset(address, bytes.len())?;
+        self.bytes[start..start + bytes.len()].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Get the offset in `bytes` of an access of `size` bytes at `address`.
+    fn offset(&self, address: u64, size: usize) -> Result<usize, TrapCode> {
+        address
+            .checked_sub(MEMORY_BASE)
+            .and_then(|start| start.checked_add(size as u64))
+            .filter(|&end| end <= self.bytes.len() as u64)
+            .map(|end| end as usize - size)
+            .ok_or(TrapCode::HeapOutOfBounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocate_and_access() {
+        let mut memory = Memory::new();
+        let a = memory.allocate(3, 1);
+        let b = memory.allocate(8, 8);
+        assert_eq!(a, MEMORY_BASE);
+        assert_eq!(b, MEMORY_BASE + 8);
+        assert_eq!(memory.end(), MEMORY_BASE + 16);
+
+        memory.write(b + 4, &[1, 2, 3, 4]).unwrap();
+        assert_eq!(memory.read(b + 3, 3).unwrap(), &[0, 1, 2]);
+        assert_eq!(memory.read(b + 5, 4), Err(TrapCode::HeapOutOfBounds));
+        assert_eq!(memory.read(0, 1), Err(TrapCode::HeapOutOfBounds));
+        assert_eq!(
+            memory.write(u64::max_value(), &[0]),
+            Err(TrapCode::HeapOutOfBounds)
+        );
+
+        memory.truncate(b);
+        assert_eq!(memory.read(b, 1), Err(TrapCode::HeapOutOfBounds));
+        assert_eq!(memory.read(a, 3).unwrap(), &[0, 0, 0]);
+    }
+}
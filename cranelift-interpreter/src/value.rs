@@ -0,0 +1,105 @@
+//! Conversions between `DataValue`s and the host types the interpreter computes with.
+
+use cranelift_codegen::ir::immediates::{Ieee32, Ieee64};
+use cranelift_codegen::ir::{types, Type};
+use cranelift_reader::DataValue;
+
+/// Get an integer or boolean value, sign-extended to 64 bits. Booleans are 0 or 1.
+pub fn to_i64(value: DataValue) -> i64 {
+    match value {
+        DataValue::B(b) => i64::from(b),
+        DataValue::I8(x) => i64::from(x),
+        DataValue::I16(x) => i64::from(x),
+        DataValue::I32(x) => i64::from(x),
+        DataValue::I64(x) => x,
+        _ => panic!("{:?} is not an integer", value),
+    }
+}
+
+/// Get an integer or boolean value, zero-extended to 64 bits. Booleans are 0 or 1.
+pub fn to_u64(value: DataValue) -> u64 {
+    match value {
+        DataValue::B(b) => u64::from(b),
+        DataValue::I8(x) => u64::from(x as u8),
+        DataValue::I16(x) => u64::from(x as u16),
+        DataValue::I32(x) => u64::from(x as u32),
+        DataValue::I64(x) => x as u64,
+        _ => panic!("{:?} is not an integer", value),
+    }
+}
+
+/// Make a value of the integer or boolean type `ty` from the low bits of `x`. Booleans are true
+/// when `x` is non-zero.
+pub fn from_i64(x: i64, ty: Type) -> DataValue {
+    match ty {
+        _ if ty.is_bool() => DataValue::B(x != 0),
+        types::I8 => DataValue::I8(x as i8),
+        types::I16 => DataValue::I16(x as i16),
+        types::I32 => DataValue::I32(x as i32),
+        types::I64 => DataValue::I64(x),
+        _ => panic!("{} is not an integer type", ty),
+    }
+}
+
+/// Make a value of the integer or boolean type `ty` from the low bits of `x`.
+pub fn from_u64(x: u64, ty: Type) -> DataValue {
+    from_i64(x as i64, ty)
+}
+
+/// Is `value` a true boolean, or a non-zero integer?
+pub fn is_true(value: DataValue) -> bool {
+    to_u64(value) != 0
+}
+
+/// Get an `f32` value.
+pub fn to_f32(value: DataValue) -> f32 {
+    match value {
+        DataValue::F32(x) => f32::from_bits(x.bits()),
+        _ => panic!("{:?} is not an f32", value),
+    }
+}
+
+/// Get an `f64` value.
+pub fn to_f64(value: DataValue) -> f64 {
+    match value {
+        DataValue::F64(x) => f64::from_bits(x.bits()),
+        _ => panic!("{:?} is not an f64", value),
+    }
+}
+
+/// Make an `f32` value.
+pub fn from_f32(x: f32) -> DataValue {
+    DataValue::F32(Ieee32::with_float(x))
+}
+
+/// Make an `f64` value.
+pub fn from_f64(x: f64) -> DataValue {
+    DataValue::F64(Ieee64::with_float(x))
+}
+
+/// Get the bits of a scalar value, zero-extended to 64 bits.
+pub fn to_bits(value: DataValue) -> u64 {
+    match value {
+        DataValue::F32(x) => u64::from(x.bits()),
+        DataValue::F64(x) => x.bits(),
+        _ => to_u64(value),
+    }
+}
+
+/// Make a scalar value of type `ty` from the low bits of `bits`.
+pub fn from_bits(bits: u64, ty: Type) -> DataValue {
+    match ty {
+        types::F32 => DataValue::F32(Ieee32::with_bits(bits as u32)),
+        types::F64 => DataValue::F64(Ieee64::with_bits(bits)),
+        _ => from_u64(bits, ty),
+    }
+}
+
+/// A mask of the low `bits` bits.
+pub fn mask(bits: u16) -> u64 {
+    if bits >= 64 {
+        !0
+    } else {
+        (1 << bits) - 1
+    }
+}
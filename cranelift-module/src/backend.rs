@@ -1,10 +1,15 @@
 //! Defines the `Backend` trait.
 
 use crate::DataContext;
+use crate::DataId;
+use crate::FuncId;
 use crate::Linkage;
+use crate::ModuleError;
 use crate::ModuleNamespace;
 use crate::ModuleResult;
 use core::marker;
+#[cfg(feature = "cache")]
+use cranelift_codegen::cache::CompilationCache;
 use cranelift_codegen::isa::TargetIsa;
 use cranelift_codegen::Context;
 use cranelift_codegen::{binemit, ir};
@@ -12,17 +17,22 @@ use cranelift_codegen::{binemit, ir};
 use std::borrow::ToOwned;
 use std::boxed::Box;
 use std::string::String;
+#[cfg(feature = "cache")]
+use std::sync::Arc;
 
 /// A `Backend` implements the functionality needed to support a `Module`.
 ///
-/// Two notable implementations of this trait are:
+/// Notable implementations of this trait are:
 ///  - `SimpleJITBackend`, defined in [cranelift-simplejit], which JITs
 ///    the contents of a `Module` to memory which can be directly executed.
 ///  - `FaerieBackend`, defined in [cranelift-faerie], which writes the
 ///    contents of a `Module` out as a native object file.
+///  - `CoffBackend`, defined in [cranelift-coff], which writes the
+///    contents of a `Module` out as a COFF object file for x86-64 Windows.
 ///
 /// [cranelift-simplejit]: https://docs.rs/cranelift-simplejit/
 /// [cranelift-faerie]: https://docs.rs/cranelift-faerie/
+/// [cranelift-coff]: https://docs.rs/cranelift-coff/
 pub trait Backend
 where
     Self: marker::Sized,
@@ -55,28 +65,79 @@ where
     /// Return the `TargetIsa` to compile for.
     fn isa(&self) -> &dyn TargetIsa;
 
+    /// Use `cache` when compiling the functions which the backend compiles by itself, such as
+    /// lazily compiled functions. This is called by `Module::set_compilation_cache`.
+    #[cfg(feature = "cache")]
+    fn set_compilation_cache(&mut self, _cache: Arc<CompilationCache>) {}
+
     /// Declare a function.
-    fn declare_function(&mut self, name: &str, linkage: Linkage);
+    fn declare_function(&mut self, id: FuncId, name: &str, linkage: Linkage);
 
     /// Declare a data object.
-    fn declare_data(&mut self, name: &str, linkage: Linkage, writable: bool, align: Option<u8>);
+    fn declare_data(
+        &mut self,
+        id: DataId,
+        name: &str,
+        linkage: Linkage,
+        writable: bool,
+        align: Option<u8>,
+    );
 
     /// Define a function, producing the function body from the given `Context`.
     ///
     /// Functions must be declared before being defined.
     fn define_function(
         &mut self,
+        id: FuncId,
         name: &str,
         ctx: &Context,
         namespace: &ModuleNamespace<Self>,
         code_size: u32,
     ) -> ModuleResult<Self::CompiledFunction>;
 
+    /// Define a function whose body isn't compiled until it's first called.
+    ///
+    /// Backends which can't compile code on demand, such as object files, keep the default
+    /// implementation, which returns a `ModuleError::Backend` error. Functions must be declared
+    /// before being defined.
+    fn define_function_lazily(
+        &mut self,
+        _id: FuncId,
+        name: &str,
+        _namespace: &ModuleNamespace<Self>,
+    ) -> ModuleResult<Self::CompiledFunction> {
+        Err(ModuleError::Backend(format!(
+            "can't compile function {} lazily in an object file",
+            name
+        )))
+    }
+
+    /// Replace the definition `old` of a function, producing the new function body from the
+    /// given `Context`.
+    ///
+    /// Backends which can't replace code keep the default implementation, which returns a
+    /// `ModuleError::Backend` error.
+    fn redefine_function(
+        &mut self,
+        _id: FuncId,
+        name: &str,
+        _ctx: &Context,
+        _namespace: &ModuleNamespace<Self>,
+        _code_size: u32,
+        _old: &Self::CompiledFunction,
+    ) -> ModuleResult<Self::CompiledFunction> {
+        Err(ModuleError::Backend(format!(
+            "can't redefine function {} in an object file",
+            name
+        )))
+    }
+
     /// Define a zero-initialized data object of the given size.
     ///
     /// Data objects must be declared before being defined.
     fn define_data(
         &mut self,
+        id: DataId,
         name: &str,
         writable: bool,
         align: Option<u8>,
@@ -103,6 +164,40 @@ where
         addend: binemit::Addend,
     );
 
+    /// Free the memory holding the definition of a function.
+    ///
+    /// Backends which can't free code keep the default implementation, which returns a
+    /// `ModuleError::Backend` error. This is unsafe because the function may not be used
+    /// afterwards.
+    unsafe fn free_function(
+        &mut self,
+        _id: FuncId,
+        name: &str,
+        _func: &Self::CompiledFunction,
+    ) -> ModuleResult<()> {
+        Err(ModuleError::Backend(format!(
+            "can't free function {} in an object file",
+            name
+        )))
+    }
+
+    /// Free the memory holding the definition of a data object.
+    ///
+    /// Backends which can't free data keep the default implementation, which returns a
+    /// `ModuleError::Backend` error. This is unsafe because the data object may not be used
+    /// afterwards.
+    unsafe fn free_data(
+        &mut self,
+        _id: DataId,
+        name: &str,
+        _data: &Self::CompiledData,
+    ) -> ModuleResult<()> {
+        Err(ModuleError::Backend(format!(
+            "can't free data object {} in an object file",
+            name
+        )))
+    }
+
     /// Perform all outstanding relocations on the given function. This requires all `Local`
     /// and `Export` entities referenced to be defined.
     fn finalize_function(
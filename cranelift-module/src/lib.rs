@@ -39,8 +39,8 @@ mod module;
 pub use crate::backend::{default_libcall_names, Backend};
 pub use crate::data_context::{DataContext, DataDescription, Init};
 pub use crate::module::{
-    DataId, FuncId, FuncOrDataId, Linkage, Module, ModuleError, ModuleFunction, ModuleNamespace,
-    ModuleResult,
+    CompiledFunctionBody, DataId, FuncId, FuncOrDataId, FunctionCompiler, Linkage, Module,
+    ModuleError, ModuleFunction, ModuleNamespace, ModuleResult,
 };
 
 /// Version number of this crate.
@@ -8,13 +8,20 @@
 use super::HashMap;
 use crate::data_context::DataContext;
 use crate::Backend;
+use core::mem;
 use cranelift_codegen::binemit::{self, CodeInfo};
-use cranelift_codegen::entity::{entity_impl, PrimaryMap};
+#[cfg(feature = "cache")]
+use cranelift_codegen::cache::CompilationCache;
+use cranelift_codegen::entity::{entity_impl, PrimaryMap, SecondaryMap};
 use cranelift_codegen::{ir, isa, CodegenError, Context};
 use failure::Fail;
 use log::info;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 use std::borrow::ToOwned;
 use std::string::String;
+#[cfg(feature = "cache")]
+use std::sync::Arc;
 use std::vec::Vec;
 
 /// A function identifier for use in the `Module` interface.
@@ -142,6 +149,9 @@ pub enum ModuleError {
     /// Indicates an identifier was defined, but was declared as an import
     #[fail(display = "Invalid to define identifier declared as an import: {}", _0)]
     InvalidImportDefinition(String),
+    /// Indicates a function body was compiled for a different target than the module's
+    #[fail(display = "Function {} was compiled for a different target", _0)]
+    IncompatibleTarget(String),
     /// Wraps a `cranelift-codegen` error
     #[fail(display = "Compilation error: {}", _0)]
     Compilation(CodegenError),
@@ -317,6 +327,89 @@ where
     }
 }
 
+/// Compiles function bodies for a `Module`, independently of the module and its backend.
+///
+/// A `FunctionCompiler` can be shared between threads, so that several functions are compiled at
+/// once, and the results handed to the module with `Module::define_compiled_function`.
+#[derive(Clone, Copy)]
+pub struct FunctionCompiler<'a> {
+    isa: &'a dyn isa::TargetIsa,
+    #[cfg(feature = "cache")]
+    cache: Option<&'a CompilationCache>,
+}
+
+impl<'a> FunctionCompiler<'a> {
+    /// Compile the body of the function `func` in `ctx`.
+    pub fn compile(&self, func: FuncId, mut ctx: Context) -> ModuleResult<CompiledFunctionBody> {
+        let total_size = self.compile_context(func, &mut ctx)?;
+        Ok(CompiledFunctionBody {
+            func,
+            ctx,
+            total_size,
+            target: describe_target(self.isa),
+        })
+    }
+
+    /// Compile the body of the function `func` in `ctx`, and return the size of its code.
+    fn compile_context(
+        &self,
+        func: FuncId,
+        ctx: &mut Context,
+    ) -> ModuleResult<binemit::CodeOffset> {
+        let isa = self.isa;
+        #[cfg(feature = "cache")]
+        let result = match self.cache {
+            Some(cache) => ctx.compile_with_cache(isa, cache),
+            None => ctx.compile(isa),
+        };
+        #[cfg(not(feature = "cache"))]
+        let result = ctx.compile(isa);
+        let CodeInfo { total_size, .. } = result.map_err(|e| {
+            info!("defining function {}: {}", func, ctx.func.display(isa));
+            ModuleError::Compilation(e)
+        })?;
+        Ok(total_size)
+    }
+}
+
+/// Describe the target `isa` compiles for, including its settings.
+fn describe_target(isa: &dyn isa::TargetIsa) -> String {
+    format!("{} {}\n{}", isa.name(), isa.triple(), isa)
+}
+
+/// The compiled body of a function, produced by `FunctionCompiler::compile`, which doesn't depend
+/// on the module's backend yet.
+pub struct CompiledFunctionBody {
+    func: FuncId,
+    ctx: Context,
+    total_size: binemit::CodeOffset,
+    /// The description of the target the body was compiled for.
+    target: String,
+}
+
+impl CompiledFunctionBody {
+    /// Get the function this is the body of.
+    pub fn func(&self) -> FuncId {
+        self.func
+    }
+
+    /// Get the size of the function's code and constant data.
+    pub fn total_size(&self) -> binemit::CodeOffset {
+        self.total_size
+    }
+
+    /// Get the `Context` containing the compiled function.
+    pub fn context(&self) -> &Context {
+        &self.ctx
+    }
+
+    /// Consume the body and return the `Context` containing the compiled function, so that it
+    /// can be reused.
+    pub fn into_context(self) -> Context {
+        self.ctx
+    }
+}
+
 /// A `Module` is a utility for collecting functions and data objects, and linking them together.
 pub struct Module<B>
 where
@@ -326,6 +419,8 @@ where
     contents: ModuleContents<B>,
     functions_to_finalize: Vec<FuncId>,
     data_objects_to_finalize: Vec<DataId>,
+    #[cfg(feature = "cache")]
+    cache: Option<Arc<CompilationCache>>,
     backend: B,
 }
 
@@ -343,6 +438,8 @@ where
             },
             functions_to_finalize: Vec::new(),
             data_objects_to_finalize: Vec::new(),
+            #[cfg(feature = "cache")]
+            cache: None,
             backend: B::new(backend_builder),
         }
     }
@@ -353,6 +450,17 @@ where
         self.names.get(name).cloned()
     }
 
+    /// Look up the functions defined from now on in `cache`, so that functions which were
+    /// compiled before with the same ISA and flags aren't compiled again.
+    ///
+    /// The cache is also used by the backend for the functions it compiles by itself, such as
+    /// lazily compiled functions.
+    #[cfg(feature = "cache")]
+    pub fn set_compilation_cache(&mut self, cache: Arc<CompilationCache>) {
+        self.backend.set_compilation_cache(Arc::clone(&cache));
+        self.cache = Some(cache);
+    }
+
     /// Return the target information needed by frontends to produce Cranelift IR
     /// for the current target.
     pub fn target_config(&self) -> isa::TargetFrontendConfig {
@@ -407,7 +515,8 @@ where
                 FuncOrDataId::Func(id) => {
                     let existing = &mut self.contents.functions[id];
                     existing.merge(linkage, signature)?;
-                    self.backend.declare_function(name, existing.decl.linkage);
+                    self.backend
+                        .declare_function(id, name, existing.decl.linkage);
                     Ok(id)
                 }
                 FuncOrDataId::Data(..) => {
@@ -424,7 +533,7 @@ where
                     compiled: None,
                 });
                 entry.insert(FuncOrDataId::Func(id));
-                self.backend.declare_function(name, linkage);
+                self.backend.declare_function(id, name, linkage);
                 Ok(id)
             }
         }
@@ -451,6 +560,7 @@ where
                     let existing = &mut self.contents.data_objects[id];
                     existing.merge(linkage, writable, align);
                     self.backend.declare_data(
+                        id,
                         name,
                         existing.decl.linkage,
                         existing.decl.writable,
@@ -474,7 +584,8 @@ where
                     compiled: None,
                 });
                 entry.insert(FuncOrDataId::Data(id));
-                self.backend.declare_data(name, linkage, writable, align);
+                self.backend
+                    .declare_data(id, name, linkage, writable, align);
                 Ok(id)
             }
         }
@@ -518,6 +629,15 @@ where
         ctx.import_global_value(ir::ExternalName::user(1, data.as_u32()))
     }
 
+    /// Return a `FunctionCompiler` compiling function bodies for this module.
+    pub fn compiler(&self) -> FunctionCompiler<'_> {
+        FunctionCompiler {
+            isa: self.backend.isa(),
+            #[cfg(feature = "cache")]
+            cache: self.cache.as_ref().map(|cache| &**cache),
+        }
+    }
+
     /// Define a function, producing the function body from the given `Context`.
     ///
     /// Returns the size of the function's code and constant data.
@@ -528,15 +648,69 @@ where
         func: FuncId,
         ctx: &mut Context,
     ) -> ModuleResult<binemit::CodeOffset> {
-        let CodeInfo { total_size, .. } = ctx.compile(self.backend.isa()).map_err(|e| {
-            info!(
-                "defining function {}: {}",
-                func,
-                ctx.func.display(self.backend.isa())
-            );
-            ModuleError::Compilation(e)
-        })?;
+        let total_size = self.compiler().compile_context(func, ctx)?;
+        self.define_compiled_context(func, ctx, total_size)?;
+        Ok(total_size)
+    }
 
+    /// Define a function with a body compiled by this module's `FunctionCompiler`.
+    ///
+    /// Returns the size of the function's code and constant data. A body compiled for a target
+    /// with a different ISA or different settings is rejected.
+    pub fn define_compiled_function(
+        &mut self,
+        body: &CompiledFunctionBody,
+    ) -> ModuleResult<binemit::CodeOffset> {
+        if body.target != describe_target(self.backend.isa()) {
+            let name = &self.contents.functions[body.func].decl.name;
+            return Err(ModuleError::IncompatibleTarget(name.clone()));
+        }
+        self.define_compiled_context(body.func, &body.ctx, body.total_size)?;
+        Ok(body.total_size)
+    }
+
+    /// Define several functions, producing the function bodies from the given `Context`s.
+    ///
+    /// The functions are compiled first, on the current rayon thread pool when the `parallel`
+    /// feature is enabled, and then defined in the order in which they're given, so the result
+    /// doesn't depend on the number of threads. If any function fails to compile, the error for
+    /// the first one is returned, and none of the functions are defined.
+    ///
+    /// Returns the compiled bodies, whose `Context`s can be reused.
+    pub fn define_functions(
+        &mut self,
+        defs: Vec<(FuncId, Context)>,
+    ) -> ModuleResult<Vec<CompiledFunctionBody>> {
+        let mut seen = SecondaryMap::new();
+        for &(func, _) in &defs {
+            self.check_definable(func)?;
+            if mem::replace(&mut seen[func], true) {
+                let name = &self.contents.functions[func].decl.name;
+                return Err(ModuleError::DuplicateDefinition(name.clone()));
+            }
+        }
+
+        let compiler = self.compiler();
+        #[cfg(feature = "parallel")]
+        let bodies: Vec<_> = defs
+            .into_par_iter()
+            .map(|(func, ctx)| compiler.compile(func, ctx))
+            .collect();
+        #[cfg(not(feature = "parallel"))]
+        let bodies: Vec<_> = defs
+            .into_iter()
+            .map(|(func, ctx)| compiler.compile(func, ctx))
+            .collect();
+        let bodies = bodies.into_iter().collect::<ModuleResult<Vec<_>>>()?;
+
+        for body in &bodies {
+            self.define_compiled_context(body.func, &body.ctx, body.total_size)?;
+        }
+        Ok(bodies)
+    }
+
+    /// Check that the function `func` can be defined.
+    fn check_definable(&self, func: FuncId) -> ModuleResult<()> {
         let info = &self.contents.functions[func];
         if info.compiled.is_some() {
             return Err(ModuleError::DuplicateDefinition(info.decl.name.clone()));
@@ -544,8 +718,21 @@ where
         if !info.decl.linkage.is_definable() {
             return Err(ModuleError::InvalidImportDefinition(info.decl.name.clone()));
         }
+        Ok(())
+    }
 
+    /// Define the function `func` with the body compiled in `ctx`.
+    fn define_compiled_context(
+        &mut self,
+        func: FuncId,
+        ctx: &Context,
+        total_size: binemit::CodeOffset,
+    ) -> ModuleResult<()> {
+        self.check_definable(func)?;
+
+        let info = &self.contents.functions[func];
         let compiled = Some(self.backend.define_function(
+            func,
             &info.decl.name,
             ctx,
             &ModuleNamespace::<B> {
@@ -556,9 +743,66 @@ where
 
         self.contents.functions[func].compiled = compiled;
         self.functions_to_finalize.push(func);
+        Ok(())
+    }
+
+    /// Replace the definition of a function, producing the new function body from the given
+    /// `Context`.
+    ///
+    /// A function which isn't defined yet is simply defined. Otherwise, the backend routes the
+    /// calls to the function through an indirection table, and the callers switch over to the
+    /// new body once the definitions are finalized. Not every backend supports this.
+    pub fn redefine_function(
+        &mut self,
+        func: FuncId,
+        ctx: &mut Context,
+    ) -> ModuleResult<binemit::CodeOffset> {
+        if self.contents.functions[func].compiled.is_none() {
+            return self.define_function(func, ctx);
+        }
+        let total_size = self.compiler().compile_context(func, ctx)?;
+
+        let info = &self.contents.functions[func];
+        let compiled = Some(self.backend.redefine_function(
+            func,
+            &info.decl.name,
+            ctx,
+            &ModuleNamespace::<B> {
+                contents: &self.contents,
+            },
+            total_size,
+            info.compiled.as_ref().unwrap(),
+        )?);
+
+        self.contents.functions[func].compiled = compiled;
+        if !self.functions_to_finalize.contains(&func) {
+            self.functions_to_finalize.push(func);
+        }
         Ok(total_size)
     }
 
+    /// Define a function whose body is only compiled when it's first called.
+    ///
+    /// Instead of code, the backend emits a small stub for the function, which compiles it the
+    /// first time it's called, and then jumps to the compiled code. How the function body is
+    /// produced is up to the backend, and not every backend supports this.
+    pub fn define_function_lazily(&mut self, func: FuncId) -> ModuleResult<()> {
+        self.check_definable(func)?;
+
+        let info = &self.contents.functions[func];
+        let compiled = Some(self.backend.define_function_lazily(
+            func,
+            &info.decl.name,
+            &ModuleNamespace::<B> {
+                contents: &self.contents,
+            },
+        )?);
+
+        self.contents.functions[func].compiled = compiled;
+        self.functions_to_finalize.push(func);
+        Ok(())
+    }
+
     /// Define a data object, producing the data contents from the given `DataContext`.
     pub fn define_data(&mut self, data: DataId, data_ctx: &DataContext) -> ModuleResult<()> {
         let compiled = {
@@ -570,6 +814,7 @@ where
                 return Err(ModuleError::InvalidImportDefinition(info.decl.name.clone()));
             }
             Some(self.backend.define_data(
+                data,
                 &info.decl.name,
                 info.decl.writable,
                 info.decl.align,
@@ -627,6 +872,44 @@ where
         );
     }
 
+    /// Free the memory holding the definition of a function, which becomes undefined again.
+    /// `func` must refer to a defined function. Not every backend supports this.
+    ///
+    /// This is unsafe because the function may not be used afterwards, including by the other
+    /// functions and data objects referring to it.
+    pub unsafe fn free_function(&mut self, func: FuncId) -> ModuleResult<()> {
+        let info = &self.contents.functions[func];
+        self.backend.free_function(
+            func,
+            &info.decl.name,
+            info.compiled
+                .as_ref()
+                .expect("`func` must refer to a defined function"),
+        )?;
+        self.contents.functions[func].compiled = None;
+        self.functions_to_finalize.retain(|&x| x != func);
+        Ok(())
+    }
+
+    /// Free the memory holding the definition of a data object, which becomes undefined again.
+    /// `data` must refer to a defined data object. Not every backend supports this.
+    ///
+    /// This is unsafe because the data object may not be used afterwards, including by the
+    /// functions and other data objects referring to it.
+    pub unsafe fn free_data(&mut self, data: DataId) -> ModuleResult<()> {
+        let info = &self.contents.data_objects[data];
+        self.backend.free_data(
+            data,
+            &info.decl.name,
+            info.compiled
+                .as_ref()
+                .expect("`data` must refer to a defined data object"),
+        )?;
+        self.contents.data_objects[data].compiled = None;
+        self.data_objects_to_finalize.retain(|&x| x != data);
+        Ok(())
+    }
+
     /// Finalize all functions and data objects that are defined but not yet finalized.
     /// All symbols referenced in their bodies that are declared as needing a definition
     /// must be defined by this point.
@@ -694,6 +977,11 @@ where
         self.backend.isa()
     }
 
+    /// Return the backend, for the functionality specific to it.
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
     /// Consume the module and return the resulting `Product`. Some `Backend`
     /// implementations may provide additional functionality available after
     /// a `Module` is complete.
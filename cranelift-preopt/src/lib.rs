@@ -21,13 +21,11 @@
 #![no_std]
 
 #[cfg(not(feature = "std"))]
-#[macro_use]
 extern crate alloc as std;
 #[cfg(feature = "std")]
-#[macro_use]
 extern crate std;
 
-mod constant_folding;
+mod sccp;
 
 use cranelift_codegen::{isa::TargetIsa, settings::FlagsOrIsa, CodegenResult, Context};
 
@@ -43,12 +41,16 @@ pub fn optimize(ctx: &mut Context, isa: &dyn TargetIsa) -> CodegenResult<()> {
     Ok(())
 }
 
-/// Fold constants
+/// Fold constants with sparse conditional constant propagation.
+///
+/// This propagates constants through EBB parameters and across branches, replaces the values
+/// which are always the same constant by that constant, and turns branches whose condition is
+/// constant into jumps.
 pub fn fold_constants<'a, FOI>(ctx: &mut Context, fisa: FOI) -> CodegenResult<()>
 where
     FOI: Into<FlagsOrIsa<'a>>,
 {
-    constant_folding::fold_constants(&mut ctx.func);
+    sccp::do_sccp(&mut ctx.func);
     ctx.verify_if(fisa)?;
     Ok(())
 }
@@ -0,0 +1,820 @@
+//! Sparse conditional constant propagation.
+//!
+//! The pass finds the values which are the same constant in every execution of the function, and
+//! the EBBs which may be executed at all. It starts out assuming that no EBB is executed and no
+//! value is defined, and only gives up on that for the EBBs reached by branches which may be taken
+//! and the values computed by instructions which may be executed. EBB parameters get the meet of
+//! the arguments passed by the branches which may be taken to their EBB, so a parameter is constant
+//! if every branch which may actually be taken passes it the same constant.
+//!
+//! Instructions with a constant result are then replaced by constants, constant EBB parameters
+//! are replaced by constants at the top of their EBB, and branches whose condition is constant are
+//! turned into jumps or removed. The EBBs which are never executed are left for the unreachable
+//! code elimination pass, and the unused constants for the DCE pass.
+//!
+//! Floating point operations are folded with the host's IEEE 754 arithmetic, which rounds the
+//! results of basic operations and conversions exactly. Operations producing a NaN aren't folded,
+//! since the bits of the NaN depend on the target.
+
+use core::cmp::Ordering;
+use cranelift_codegen::cursor::{Cursor, FuncCursor};
+use cranelift_codegen::ir::condcodes::{FloatCC, IntCC};
+use cranelift_codegen::ir::immediates::{Ieee32, Ieee64};
+use cranelift_codegen::ir::instructions::BranchInfo;
+use cranelift_codegen::ir::{
+    types, Ebb, Function, Inst, InstBuilder, InstructionData, Opcode, Type, Value,
+};
+use cranelift_entity::{EntitySet, SecondaryMap};
+use std::vec::Vec;
+
+/// The sign bit of an `f32`.
+const SIGN32: u32 = 1 << 31;
+
+/// The sign bit of an `f64`.
+const SIGN64: u64 = 1 << 63;
+
+/// A constant scalar value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Constant {
+    /// An integer, with the bits above the width of its type cleared.
+    Int(u64),
+    Bool(bool),
+    /// The bits of an `f32`.
+    Ieee32(u32),
+    /// The bits of an `f64`.
+    Ieee64(u64),
+}
+
+/// What is known about a value at some point of the analysis.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum LatticeValue {
+    /// The value hasn't been defined by any instruction which may be executed yet.
+    Undefined,
+    /// The value is always this constant.
+    Constant(Constant),
+    /// The value may not be a constant.
+    Overdefined,
+}
+
+impl Default for LatticeValue {
+    fn default() -> Self {
+        LatticeValue::Undefined
+    }
+}
+
+impl LatticeValue {
+    /// Get the value which may be either `self` or `other`.
+    fn meet(self, other: Self) -> Self {
+        match (self, other) {
+            (LatticeValue::Undefined, x) | (x, LatticeValue::Undefined) => x,
+            (LatticeValue::Constant(a), LatticeValue::Constant(b)) if a == b => self,
+            _ => LatticeValue::Overdefined,
+        }
+    }
+}
+
+/// How a conditional branch behaves, as far as is known.
+enum Decision {
+    /// The condition hasn't been defined yet.
+    Unknown,
+    /// The branch is never taken.
+    Never,
+    /// The branch is always taken.
+    Always,
+    /// The branch may or may not be taken.
+    Maybe,
+}
+
+/// Perform sparse conditional constant propagation on `func`.
+pub fn do_sccp(func: &mut Function) {
+    let entry = match func.layout.entry_block() {
+        Some(entry) => entry,
+        None => return,
+    };
+    let mut sccp = Sccp::new(func);
+    sccp.run(func, entry);
+    sccp.rewrite(func);
+}
+
+/// The state of the analysis.
+struct Sccp {
+    values: SecondaryMap<Value, LatticeValue>,
+    /// The EBBs which may be executed.
+    executable: EntitySet<Ebb>,
+    /// The branches which may be taken to each EBB.
+    incoming: SecondaryMap<Ebb, Vec<Inst>>,
+    /// The EBBs whose instructions, or whose parameters, depend on each value.
+    users: SecondaryMap<Value, Vec<Ebb>>,
+    worklist: Vec<Ebb>,
+    queued: SecondaryMap<Ebb, bool>,
+}
+
+impl Sccp {
+    fn new(func: &Function) -> Self {
+        let mut users: SecondaryMap<Value, Vec<Ebb>> = SecondaryMap::new();
+        for ebb in func.layout.ebbs() {
+            for inst in func.layout.ebb_insts(ebb) {
+                for &arg in func.dfg.inst_args(inst) {
+                    users[func.dfg.resolve_aliases(arg)].push(ebb);
+                }
+                if let BranchInfo::SingleDest(destination, args) = func.dfg.analyze_branch(inst) {
+                    for &arg in args {
+                        users[func.dfg.resolve_aliases(arg)].push(destination);
+                    }
+                }
+            }
+        }
+
+        Self {
+            values: SecondaryMap::new(),
+            executable: EntitySet::new(),
+            incoming: SecondaryMap::new(),
+            users,
+            worklist: Vec::new(),
+            queued: SecondaryMap::new(),
+        }
+    }
+
+    /// Propagate constants from the function entry until nothing changes.
+    fn run(&mut self, func: &Function, entry: Ebb) {
+        for &param in func.dfg.ebb_params(entry) {
+            self.values[param] = LatticeValue::Overdefined;
+        }
+        self.executable.insert(entry);
+        self.enqueue(entry);
+
+        while let Some(ebb) = self.worklist.pop() {
+            self.queued[ebb] = false;
+            self.visit_ebb(func, ebb, entry);
+        }
+    }
+
+    fn enqueue(&mut self, ebb: Ebb) {
+        if !self.queued[ebb] {
+            self.queued[ebb] = true;
+            self.worklist.push(ebb);
+        }
+    }
+
+    /// Get what is known about `value`.
+    fn value(&self, func: &Function, value: Value) -> LatticeValue {
+        self.values[func.dfg.resolve_aliases(value)]
+    }
+
+    /// Lower what is known about `value` to `new`, and revisit the EBBs depending on it if that
+    /// changed anything.
+    fn update(&mut self, value: Value, new: LatticeValue) {
+        let old = self.values[value];
+        let new = old.meet(new);
+        if new != old {
+            self.values[value] = new;
+            for i in 0..self.users[value].len() {
+                let ebb = self.users[value][i];
+                if self.executable.contains(ebb) {
+                    self.enqueue(ebb);
+                }
+            }
+        }
+    }
+
+    /// Record that the branch `inst` may be taken to `destination`.
+    fn mark_edge(&mut self, inst: Inst, destination: Ebb) {
+        self.executable.insert(destination);
+        if !self.incoming[destination].contains(&inst) {
+            self.incoming[destination].push(inst);
+            self.enqueue(destination);
+        }
+    }
+
+    fn visit_ebb(&mut self, func: &Function, ebb: Ebb, entry: Ebb) {
+        if ebb != entry {
+            for (i, &param) in func.dfg.ebb_params(ebb).iter().enumerate() {
+                let mut value = LatticeValue::Undefined;
+                for &branch in &self.incoming[ebb] {
+                    value = value.meet(self.value(func, func.dfg.inst_variable_args(branch)[i]));
+                }
+                self.update(param, value);
+            }
+        }
+
+        for inst in func.layout.ebb_insts(ebb) {
+            if !func.dfg[inst].opcode().is_branch() {
+                let value = if func.dfg.inst_results(inst).len() == 1 {
+                    self.evaluate(func, inst)
+                } else {
+                    LatticeValue::Overdefined
+                };
+                for &result in func.dfg.inst_results(inst) {
+                    self.update(result, value);
+                }
+                continue;
+            }
+
+            let decision = self.decide(func, inst);
+            match decision {
+                // The rest of the EBB isn't known to be executed yet.
+                Decision::Unknown => return,
+                Decision::Never => continue,
+                Decision::Always | Decision::Maybe => {}
+            }
+            match func.dfg.analyze_branch(inst) {
+                BranchInfo::NotABranch => {}
+                BranchInfo::SingleDest(destination, _) => self.mark_edge(inst, destination),
+                BranchInfo::Table(jt, default) => {
+                    for &destination in func.jump_tables[jt].iter() {
+                        self.mark_edge(inst, destination);
+                    }
+                    if let Some(destination) = default {
+                        self.mark_edge(inst, destination);
+                    }
+                }
+            }
+            if let Decision::Always = decision {
+                return;
+            }
+        }
+    }
+
+    /// Decide if the branch `inst` is taken.
+    fn decide(&self, func: &Function, inst: Inst) -> Decision {
+        let args = func.dfg.inst_args(inst);
+        match func.dfg[inst] {
+            InstructionData::Jump { .. } => Decision::Always,
+            InstructionData::Branch { opcode, .. } => match self.value(func, args[0]) {
+                LatticeValue::Undefined => Decision::Unknown,
+                LatticeValue::Constant(cond) => match truthiness(cond) {
+                    Some(cond) if cond == (opcode == Opcode::Brnz) => Decision::Always,
+                    Some(_) => Decision::Never,
+                    None => Decision::Maybe,
+                },
+                LatticeValue::Overdefined => Decision::Maybe,
+            },
+            InstructionData::BranchIcmp { cond, .. } => {
+                let width = u32::from(func.dfg.value_type(args[0]).bits());
+                match (self.value(func, args[0]), self.value(func, args[1])) {
+                    (LatticeValue::Undefined, _) | (_, LatticeValue::Undefined) => {
+                        Decision::Unknown
+                    }
+                    (
+                        LatticeValue::Constant(Constant::Int(a)),
+                        LatticeValue::Constant(Constant::Int(b)),
+                    ) => {
+                        if icmp(cond, a, b, width) {
+                            Decision::Always
+                        } else {
+                            Decision::Never
+                        }
+                    }
+                    _ => Decision::Maybe,
+                }
+            }
+            _ => Decision::Maybe,
+        }
+    }
+
+    /// Evaluate the single result of the non-branch instruction `inst`.
+    fn evaluate(&self, func: &Function, inst: Inst) -> LatticeValue {
+        let dfg = &func.dfg;
+        let ty = dfg.value_type(dfg.first_result(inst));
+        if ty.is_vector() || ty.bits() > 64 || !(ty.is_int() || ty.is_bool() || ty.is_float()) {
+            return LatticeValue::Overdefined;
+        }
+        let width = u32::from(ty.bits());
+
+        match dfg[inst] {
+            InstructionData::UnaryImm {
+                opcode: Opcode::Iconst,
+                imm,
+            } => {
+                let imm: i64 = imm.into();
+                return LatticeValue::Constant(Constant::Int(mask(imm as u64, width)));
+            }
+            InstructionData::UnaryIeee32 {
+                opcode: Opcode::F32const,
+                imm,
+            } => return LatticeValue::Constant(Constant::Ieee32(imm.bits())),
+            InstructionData::UnaryIeee64 {
+                opcode: Opcode::F64const,
+                imm,
+            } => return LatticeValue::Constant(Constant::Ieee64(imm.bits())),
+            InstructionData::UnaryBool {
+                opcode: Opcode::Bconst,
+                imm,
+            } => return LatticeValue::Constant(Constant::Bool(imm)),
+            InstructionData::Ternary {
+                opcode: Opcode::Select,
+                args,
+            } => {
+                return match self.value(func, args[0]) {
+                    LatticeValue::Undefined => LatticeValue::Undefined,
+                    LatticeValue::Constant(cond) => match truthiness(cond) {
+                        Some(true) => self.value(func, args[1]),
+                        Some(false) => self.value(func, args[2]),
+                        None => LatticeValue::Overdefined,
+                    },
+                    LatticeValue::Overdefined => {
+                        self.value(func, args[1]).meet(self.value(func, args[2]))
+                    }
+                };
+            }
+            _ => {}
+        }
+
+        let mut operands = Vec::new();
+        for &arg in dfg.inst_args(inst) {
+            match self.value(func, arg) {
+                LatticeValue::Undefined => return LatticeValue::Undefined,
+                LatticeValue::Constant(constant) => operands.push(constant),
+                LatticeValue::Overdefined => return LatticeValue::Overdefined,
+            }
+        }
+        if operands.is_empty() {
+            return LatticeValue::Overdefined;
+        }
+        let arg_ty = dfg.value_type(dfg.inst_args(inst)[0]);
+
+        let folded = match dfg[inst] {
+            InstructionData::Binary { opcode, .. } => {
+                fold_binary(opcode, operands[0], operands[1], width)
+            }
+            InstructionData::BinaryImm { opcode, imm, .. } => {
+                let imm: i64 = imm.into();
+                let imm = Constant::Int(mask(imm as u64, width));
+                match opcode {
+                    Opcode::IrsubImm => fold_binary(Opcode::Isub, imm, operands[0], width),
+                    _ => fold_binary(binary_opcode(opcode), operands[0], imm, width),
+                }
+            }
+            InstructionData::Unary { opcode, .. } => fold_unary(opcode, operands[0], ty, arg_ty),
+            InstructionData::IntCompare { cond, .. } => match (operands[0], operands[1]) {
+                (Constant::Int(a), Constant::Int(b)) => {
+                    Some(Constant::Bool(icmp(cond, a, b, u32::from(arg_ty.bits()))))
+                }
+                _ => None,
+            },
+            InstructionData::IntCompareImm { cond, imm, .. } => match operands[0] {
+                Constant::Int(a) => {
+                    let arg_width = u32::from(arg_ty.bits());
+                    let imm: i64 = imm.into();
+                    Some(Constant::Bool(icmp(
+                        cond,
+                        a,
+                        mask(imm as u64, arg_width),
+                        arg_width,
+                    )))
+                }
+                _ => None,
+            },
+            InstructionData::FloatCompare { cond, .. } => {
+                fcmp(cond, operands[0], operands[1]).map(Constant::Bool)
+            }
+            _ => None,
+        };
+        match folded {
+            Some(constant) => LatticeValue::Constant(constant),
+            None => LatticeValue::Overdefined,
+        }
+    }
+
+    /// Replace the constant values by constants, and the decided branches by jumps.
+    fn rewrite(&self, func: &mut Function) {
+        let mut replacements: SecondaryMap<Value, Option<Value>> = SecondaryMap::new();
+        let mut has_replacements = false;
+
+        let mut pos = FuncCursor::new(func);
+        while let Some(ebb) = pos.next_ebb() {
+            if !self.executable.contains(ebb) {
+                continue;
+            }
+
+            for i in 0..pos.func.dfg.num_ebb_params(ebb) {
+                let param = pos.func.dfg.ebb_params(ebb)[i];
+                if let LatticeValue::Constant(constant) = self.values[param] {
+                    let ty = pos.func.dfg.value_type(param);
+                    pos.goto_first_inst(ebb);
+                    replacements[param] = Some(build_constant(pos.ins(), ty, constant));
+                    has_replacements = true;
+                }
+            }
+
+            pos.goto_top(ebb);
+            while let Some(inst) = pos.next_inst() {
+                match pos.func.dfg[inst] {
+                    InstructionData::Branch { .. } | InstructionData::BranchIcmp { .. } => {
+                        match self.decide(pos.func, inst) {
+                            Decision::Never => {
+                                pos.remove_inst_and_step_back();
+                            }
+                            Decision::Always => {
+                                self.replace_with_jump(pos.func, inst);
+                                break;
+                            }
+                            Decision::Unknown | Decision::Maybe => {}
+                        }
+                        continue;
+                    }
+                    InstructionData::UnaryImm { .. }
+                    | InstructionData::UnaryIeee32 { .. }
+                    | InstructionData::UnaryIeee64 { .. }
+                    | InstructionData::UnaryBool { .. } => continue,
+                    _ => {}
+                }
+
+                if pos.func.dfg.inst_results(inst).len() != 1 {
+                    continue;
+                }
+                let result = pos.func.dfg.first_result(inst);
+                if let LatticeValue::Constant(constant) = self.values[result] {
+                    let ty = pos.func.dfg.value_type(result);
+                    build_constant(pos.func.dfg.replace(inst), ty, constant);
+                }
+            }
+        }
+
+        if has_replacements {
+            for ebb in func.layout.ebbs() {
+                for inst in func.layout.ebb_insts(ebb) {
+                    for arg in func.dfg.inst_args_mut(inst) {
+                        if let Some(replacement) = replacements[*arg] {
+                            *arg = replacement;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Replace the conditional branch `inst`, which is always taken, by a jump.
+    ///
+    /// The instructions after it are never executed. They are removed, or moved to a new EBB if
+    /// their results are used.
+    fn replace_with_jump(&self, func: &mut Function, inst: Inst) {
+        let destination = func.dfg[inst]
+            .branch_destination()
+            .expect("a conditional branch has a destination");
+        let args = func.dfg.inst_variable_args(inst).to_vec();
+        func.dfg.replace(inst).jump(destination, &args);
+
+        let tail: Vec<Inst> = {
+            let mut tail = Vec::new();
+            let mut next = func.layout.next_inst(inst);
+            while let Some(inst) = next {
+                tail.push(inst);
+                next = func.layout.next_inst(inst);
+            }
+            tail
+        };
+        let used = tail.iter().any(|&inst| {
+            func.dfg
+                .inst_results(inst)
+                .iter()
+                .any(|&result| !self.users[result].is_empty())
+        });
+        if used {
+            let ebb = func.dfg.make_ebb();
+            func.layout.split_ebb(ebb, tail[0]);
+        } else {
+            for inst in tail {
+                func.layout.remove_inst(inst);
+            }
+        }
+    }
+}
+
+/// Build an instruction computing `constant` as a value of type `ty`.
+fn build_constant<'f, B: InstBuilder<'f>>(builder: B, ty: Type, constant: Constant) -> Value {
+    match constant {
+        Constant::Int(bits) => builder.iconst(ty, sign_extend(bits, u32::from(ty.bits()))),
+        Constant::Bool(imm) => builder.bconst(ty, imm),
+        Constant::Ieee32(bits) => builder.f32const(Ieee32::with_bits(bits)),
+        Constant::Ieee64(bits) => builder.f64const(Ieee64::with_bits(bits)),
+    }
+}
+
+/// Get the truth value of a branch or `select` condition.
+fn truthiness(constant: Constant) -> Option<bool> {
+    match constant {
+        Constant::Int(imm) => Some(imm != 0),
+        Constant::Bool(imm) => Some(imm),
+        Constant::Ieee32(_) | Constant::Ieee64(_) => None,
+    }
+}
+
+/// Clear the bits of `bits` above `width`.
+fn mask(bits: u64, width: u32) -> u64 {
+    if width >= 64 {
+        bits
+    } else {
+        bits & ((1 << width) - 1)
+    }
+}
+
+/// Sign-extend the `width`-bit integer `bits`.
+fn sign_extend(bits: u64, width: u32) -> i64 {
+    let shift = 64 - width;
+    ((bits << shift) as i64) >> shift
+}
+
+/// Get the binary instruction computing the same as the immediate instruction `opcode`.
+fn binary_opcode(opcode: Opcode) -> Opcode {
+    match opcode {
+        Opcode::IaddImm => Opcode::Iadd,
+        Opcode::ImulImm => Opcode::Imul,
+        Opcode::UdivImm => Opcode::Udiv,
+        Opcode::SdivImm => Opcode::Sdiv,
+        Opcode::UremImm => Opcode::Urem,
+        Opcode::SremImm => Opcode::Srem,
+        Opcode::BandImm => Opcode::Band,
+        Opcode::BorImm => Opcode::Bor,
+        Opcode::BxorImm => Opcode::Bxor,
+        Opcode::RotlImm => Opcode::Rotl,
+        Opcode::RotrImm => Opcode::Rotr,
+        Opcode::IshlImm => Opcode::Ishl,
+        Opcode::UshrImm => Opcode::Ushr,
+        Opcode::SshrImm => Opcode::Sshr,
+        _ => opcode,
+    }
+}
+
+/// Fold the binary operation `opcode` on constants of width `width`.
+fn fold_binary(opcode: Opcode, a: Constant, b: Constant, width: u32) -> Option<Constant> {
+    match (a, b) {
+        (Constant::Int(a), Constant::Int(b)) => {
+            fold_int_binary(opcode, a, b, width).map(|result| Constant::Int(mask(result, width)))
+        }
+        (Constant::Bool(a), Constant::Bool(b)) => {
+            let result = match opcode {
+                Opcode::Band => a & b,
+                Opcode::Bor => a | b,
+                Opcode::Bxor => a ^ b,
+                Opcode::BandNot => a & !b,
+                Opcode::BorNot => a | !b,
+                Opcode::BxorNot => a ^ !b,
+                _ => return None,
+            };
+            Some(Constant::Bool(result))
+        }
+        (Constant::Ieee32(a), Constant::Ieee32(b)) => {
+            fold_f32_binary(opcode, a, b).map(Constant::Ieee32)
+        }
+        (Constant::Ieee64(a), Constant::Ieee64(b)) => {
+            fold_f64_binary(opcode, a, b).map(Constant::Ieee64)
+        }
+        _ => None,
+    }
+}
+
+/// Fold the binary operation `opcode` on `width`-bit integers.
+///
+/// The result may have bits set above `width`. Divisions which would trap aren't folded.
+fn fold_int_binary(opcode: Opcode, a: u64, b: u64, width: u32) -> Option<u64> {
+    let (sa, sb) = (sign_extend(a, width), sign_extend(b, width));
+    let min = sign_extend(1 << (width - 1), width);
+    let max = !min;
+    let umax = mask(!0, width);
+    let amount = (b & u64::from(width - 1)) as u32;
+    let result = match opcode {
+        Opcode::Iadd => a.wrapping_add(b),
+        Opcode::Isub => a.wrapping_sub(b),
+        Opcode::Imul => a.wrapping_mul(b),
+        Opcode::Umulhi => ((u128::from(a) * u128::from(b)) >> width) as u64,
+        Opcode::Smulhi => ((i128::from(sa) * i128::from(sb)) >> width) as u64,
+        Opcode::Udiv | Opcode::Urem if b == 0 => return None,
+        Opcode::Udiv => a / b,
+        Opcode::Urem => a % b,
+        Opcode::Sdiv | Opcode::Srem if b == 0 => return None,
+        Opcode::Sdiv if sa == min && sb == -1 => return None,
+        Opcode::Sdiv => sa.wrapping_div(sb) as u64,
+        Opcode::Srem => sa.wrapping_rem(sb) as u64,
+        Opcode::UaddSat => a.saturating_add(b).min(umax),
+        Opcode::UsubSat => a.saturating_sub(b),
+        Opcode::SaddSat => sa.saturating_add(sb).max(min).min(max) as u64,
+        Opcode::SsubSat => sa.saturating_sub(sb).max(min).min(max) as u64,
+        Opcode::Band => a & b,
+        Opcode::Bor => a | b,
+        Opcode::Bxor => a ^ b,
+        Opcode::BandNot => a & !b,
+        Opcode::BorNot => a | !b,
+        Opcode::BxorNot => a ^ !b,
+        Opcode::Ishl => a << amount,
+        Opcode::Ushr => a >> amount,
+        Opcode::Sshr => (sa >> amount) as u64,
+        Opcode::Rotl | Opcode::Rotr if amount == 0 => a,
+        Opcode::Rotl => (a << amount) | (a >> (width - amount)),
+        Opcode::Rotr => (a >> amount) | (a << (width - amount)),
+        _ => return None,
+    };
+    Some(result)
+}
+
+/// Fold the binary operation `opcode` on the `f32` values with the bits `a` and `b`.
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::float_arithmetic))]
+fn fold_f32_binary(opcode: Opcode, a: u32, b: u32) -> Option<u32> {
+    let (x, y) = (f32::from_bits(a), f32::from_bits(b));
+    let result = match opcode {
+        Opcode::Fadd => x + y,
+        Opcode::Fsub => x - y,
+        Opcode::Fmul => x * y,
+        Opcode::Fdiv => x / y,
+        Opcode::Fcopysign => return Some((a & !SIGN32) | (b & SIGN32)),
+        // Equal operands can only differ in the sign of a zero, and `-0.0` is the smaller one.
+        Opcode::Fmin if x == y => f32::from_bits(a | b),
+        Opcode::Fmax if x == y => f32::from_bits(a & b),
+        Opcode::Fmin => x.min(y),
+        Opcode::Fmax => x.max(y),
+        _ => return None,
+    };
+    if x.is_nan() || y.is_nan() || result.is_nan() {
+        None
+    } else {
+        Some(result.to_bits())
+    }
+}
+
+/// Fold the binary operation `opcode` on the `f64` values with the bits `a` and `b`.
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::float_arithmetic))]
+fn fold_f64_binary(opcode: Opcode, a: u64, b: u64) -> Option<u64> {
+    let (x, y) = (f64::from_bits(a), f64::from_bits(b));
+    let result = match opcode {
+        Opcode::Fadd => x + y,
+        Opcode::Fsub => x - y,
+        Opcode::Fmul => x * y,
+        Opcode::Fdiv => x / y,
+        Opcode::Fcopysign => return Some((a & !SIGN64) | (b & SIGN64)),
+        // Equal operands can only differ in the sign of a zero, and `-0.0` is the smaller one.
+        Opcode::Fmin if x == y => f64::from_bits(a | b),
+        Opcode::Fmax if x == y => f64::from_bits(a & b),
+        Opcode::Fmin => x.min(y),
+        Opcode::Fmax => x.max(y),
+        _ => return None,
+    };
+    if x.is_nan() || y.is_nan() || result.is_nan() {
+        None
+    } else {
+        Some(result.to_bits())
+    }
+}
+
+/// Fold the unary operation `opcode` on a constant of type `arg_ty`, producing a value of type
+/// `ty`.
+fn fold_unary(opcode: Opcode, a: Constant, ty: Type, arg_ty: Type) -> Option<Constant> {
+    let width = u32::from(ty.bits());
+    let arg_width = u32::from(arg_ty.bits());
+    let result = match (opcode, a) {
+        (Opcode::Copy, _) => a,
+        (Opcode::Bnot, Constant::Int(a)) => Constant::Int(mask(!a, width)),
+        (Opcode::Bnot, Constant::Bool(a)) => Constant::Bool(!a),
+        (Opcode::Clz, Constant::Int(a)) => {
+            Constant::Int(u64::from(a.leading_zeros() - (64 - arg_width)))
+        }
+        (Opcode::Cls, Constant::Int(a)) => {
+            let a = sign_extend(a, arg_width);
+            let sign_bits = if a < 0 { !a } else { a }.leading_zeros();
+            Constant::Int(u64::from(sign_bits - (64 - arg_width) - 1))
+        }
+        (Opcode::Ctz, Constant::Int(a)) => {
+            Constant::Int(u64::from(a.trailing_zeros().min(arg_width)))
+        }
+        (Opcode::Popcnt, Constant::Int(a)) => Constant::Int(u64::from(a.count_ones())),
+        (Opcode::Bitrev, Constant::Int(a)) => Constant::Int(a.reverse_bits() >> (64 - width)),
+        (Opcode::Uextend, Constant::Int(a)) => Constant::Int(a),
+        (Opcode::Sextend, Constant::Int(a)) => {
+            Constant::Int(mask(sign_extend(a, arg_width) as u64, width))
+        }
+        (Opcode::Ireduce, Constant::Int(a)) => Constant::Int(mask(a, width)),
+        (Opcode::Bint, Constant::Bool(a)) => Constant::Int(u64::from(a)),
+        (Opcode::Bmask, Constant::Bool(a)) => Constant::Int(if a { mask(!0, width) } else { 0 }),
+        (Opcode::Bextend, Constant::Bool(a)) | (Opcode::Breduce, Constant::Bool(a)) => {
+            Constant::Bool(a)
+        }
+        (Opcode::Fneg, Constant::Ieee32(a)) => Constant::Ieee32(a ^ SIGN32),
+        (Opcode::Fneg, Constant::Ieee64(a)) => Constant::Ieee64(a ^ SIGN64),
+        (Opcode::Fabs, Constant::Ieee32(a)) => Constant::Ieee32(a & !SIGN32),
+        (Opcode::Fabs, Constant::Ieee64(a)) => Constant::Ieee64(a & !SIGN64),
+        (Opcode::Bitcast, Constant::Int(a)) if ty == types::F32 => Constant::Ieee32(a as u32),
+        (Opcode::Bitcast, Constant::Int(a)) if ty == types::F64 => Constant::Ieee64(a),
+        (Opcode::Bitcast, Constant::Ieee32(a)) if ty.is_int() => Constant::Int(u64::from(a)),
+        (Opcode::Bitcast, Constant::Ieee64(a)) if ty.is_int() => Constant::Int(a),
+        (Opcode::Fpromote, Constant::Ieee32(a)) => {
+            return float_constant(ty, f64::from(f32::from_bits(a)))
+        }
+        (Opcode::Fdemote, Constant::Ieee64(a)) => {
+            return float_constant(ty, f64::from(f64::from_bits(a) as f32))
+        }
+        (Opcode::FcvtFromSint, Constant::Int(a)) => {
+            let a = sign_extend(a, arg_width);
+            if ty == types::F32 {
+                Constant::Ieee32((a as f32).to_bits())
+            } else {
+                Constant::Ieee64((a as f64).to_bits())
+            }
+        }
+        (Opcode::FcvtFromUint, Constant::Int(a)) => {
+            if ty == types::F32 {
+                Constant::Ieee32((a as f32).to_bits())
+            } else {
+                Constant::Ieee64((a as f64).to_bits())
+            }
+        }
+        (Opcode::FcvtToSint, Constant::Ieee32(a)) => {
+            return float_to_sint(f64::from(f32::from_bits(a)), width)
+        }
+        (Opcode::FcvtToSint, Constant::Ieee64(a)) => {
+            return float_to_sint(f64::from_bits(a), width)
+        }
+        (Opcode::FcvtToUint, Constant::Ieee32(a)) => {
+            return float_to_uint(f64::from(f32::from_bits(a)), width)
+        }
+        (Opcode::FcvtToUint, Constant::Ieee64(a)) => {
+            return float_to_uint(f64::from_bits(a), width)
+        }
+        _ => return None,
+    };
+    Some(result)
+}
+
+/// Get the constant of the float type `ty` equal to `x`, which is exactly representable in it,
+/// unless it's a NaN.
+fn float_constant(ty: Type, x: f64) -> Option<Constant> {
+    if x.is_nan() {
+        None
+    } else if ty == types::F32 {
+        Some(Constant::Ieee32((x as f32).to_bits()))
+    } else {
+        Some(Constant::Ieee64(x.to_bits()))
+    }
+}
+
+/// Convert `x` to a `width`-bit signed integer, rounding towards zero, unless it's out of range.
+fn float_to_sint(x: f64, width: u32) -> Option<Constant> {
+    // The bounds are exact, except for the lower bound of 64-bit integers which is rounded to
+    // -2^63, so that value isn't folded.
+    let lower = (-(1i128 << (width - 1)) - 1) as f64;
+    let upper = (1u128 << (width - 1)) as f64;
+    if x > lower && x < upper {
+        Some(Constant::Int(mask(x as i64 as u64, width)))
+    } else {
+        None
+    }
+}
+
+/// Convert `x` to a `width`-bit unsigned integer, rounding towards zero, unless it's out of range.
+fn float_to_uint(x: f64, width: u32) -> Option<Constant> {
+    let upper = (1u128 << width) as f64;
+    if x > -1.0 && x < upper {
+        Some(Constant::Int(x as u64))
+    } else {
+        None
+    }
+}
+
+/// Evaluate the integer comparison `cond` of the `width`-bit integers `a` and `b`.
+fn icmp(cond: IntCC, a: u64, b: u64, width: u32) -> bool {
+    let (sa, sb) = (sign_extend(a, width), sign_extend(b, width));
+    match cond {
+        IntCC::Equal => a == b,
+        IntCC::NotEqual => a != b,
+        IntCC::SignedLessThan => sa < sb,
+        IntCC::SignedGreaterThanOrEqual => sa >= sb,
+        IntCC::SignedGreaterThan => sa > sb,
+        IntCC::SignedLessThanOrEqual => sa <= sb,
+        IntCC::UnsignedLessThan => a < b,
+        IntCC::UnsignedGreaterThanOrEqual => a >= b,
+        IntCC::UnsignedGreaterThan => a > b,
+        IntCC::UnsignedLessThanOrEqual => a <= b,
+    }
+}
+
+/// Evaluate the floating point comparison `cond` of `a` and `b`.
+fn fcmp(cond: FloatCC, a: Constant, b: Constant) -> Option<bool> {
+    let order = match (a, b) {
+        (Constant::Ieee32(a), Constant::Ieee32(b)) => {
+            f32::from_bits(a).partial_cmp(&f32::from_bits(b))
+        }
+        (Constant::Ieee64(a), Constant::Ieee64(b)) => {
+            f64::from_bits(a).partial_cmp(&f64::from_bits(b))
+        }
+        _ => return None,
+    };
+    let result = match cond {
+        FloatCC::Ordered => order.is_some(),
+        FloatCC::Unordered => order.is_none(),
+        FloatCC::Equal => order == Some(Ordering::Equal),
+        FloatCC::NotEqual => order != Some(Ordering::Equal),
+        FloatCC::OrderedNotEqual => order.is_some() && order != Some(Ordering::Equal),
+        FloatCC::UnorderedOrEqual => {
+            order != Some(Ordering::Less) && order != Some(Ordering::Greater)
+        }
+        FloatCC::LessThan => order == Some(Ordering::Less),
+        FloatCC::LessThanOrEqual => order == Some(Ordering::Less) || order == Some(Ordering::Equal),
+        FloatCC::GreaterThan => order == Some(Ordering::Greater),
+        FloatCC::GreaterThanOrEqual => {
+            order == Some(Ordering::Greater) || order == Some(Ordering::Equal)
+        }
+        FloatCC::UnorderedOrLessThan => order.is_none() || order == Some(Ordering::Less),
+        FloatCC::UnorderedOrLessThanOrEqual => order != Some(Ordering::Greater),
+        FloatCC::UnorderedOrGreaterThan => order.is_none() || order == Some(Ordering::Greater),
+        FloatCC::UnorderedOrGreaterThanOrEqual => order != Some(Ordering::Less),
+    };
+    Some(result)
+}
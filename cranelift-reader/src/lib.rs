@@ -28,7 +28,8 @@
 
 pub use crate::error::{Location, ParseError, ParseResult};
 pub use crate::isaspec::{parse_options, IsaSpec};
-pub use crate::parser::{parse_functions, parse_test, ParseOptions};
+pub use crate::parser::{parse_functions, parse_run_command, parse_test, ParseOptions};
+pub use crate::run_command::{Comparison, DataValue, DisplayDataValues, Invocation, RunCommand};
 pub use crate::sourcemap::SourceMap;
 pub use crate::testcommand::{TestCommand, TestOption};
 pub use crate::testfile::{Comment, Details, Feature, TestFile};
@@ -37,6 +38,7 @@ mod error;
 mod isaspec;
 mod lexer;
 mod parser;
+mod run_command;
 mod sourcemap;
 mod testcommand;
 mod testfile;
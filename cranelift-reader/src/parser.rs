@@ -3,6 +3,7 @@
 use crate::error::{Location, ParseError, ParseResult};
 use crate::isaspec;
 use crate::lexer::{LexError, Lexer, LocatedError, LocatedToken, Token};
+use crate::run_command::{Comparison, DataValue, Invocation, RunCommand};
 use crate::sourcemap::SourceMap;
 use crate::testcommand::TestCommand;
 use crate::testfile::{Comment, Details, Feature, TestFile};
@@ -111,6 +112,23 @@ pub fn parse_test<'a>(text: &'a str, options: ParseOptions<'a>) -> ParseResult<T
     })
 }
 
+/// Parse a run command from the text of a comment, e.g. `; run: %add(1, 2) == 3`.
+///
+/// The arguments and the expected results are parsed according to `signature`. Returns `None` if
+/// the comment is not a run command.
+pub fn parse_run_command(text: &str, signature: &Signature) -> ParseResult<Option<RunCommand>> {
+    let text = text.trim_start_matches(';').trim_start();
+    if !text.starts_with("run:") {
+        return Ok(None);
+    }
+    let mut parser = Parser::new(&text["run:".len()..]);
+    let command = parser.parse_run_command(signature)?;
+    if parser.token().is_some() {
+        return err!(parser.loc, "unexpected text after the run command");
+    }
+    Ok(Some(command))
+}
+
 pub struct Parser<'a> {
     lex: Lexer<'a>,
 
@@ -867,6 +885,124 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parse a run command, following the `run:` prefix:
+    ///
+    /// run-command ::= invocation comparison results
+    /// invocation ::= Name "(" [data-value {"," data-value}] ")"
+    /// comparison ::= "==" | "!="
+    /// results ::= data-value | "[" [data-value {"," data-value}] "]"
+    ///
+    /// The results are only bracketed when the function doesn't return exactly one value.
+    fn parse_run_command(&mut self, sig: &Signature) -> ParseResult<RunCommand> {
+        let func = match self.token() {
+            Some(Token::Name(name)) => {
+                self.consume();
+                name.to_string()
+            }
+            _ => return err!(self.loc, "expected a function name, e.g. %fn"),
+        };
+
+        self.match_token(Token::LPar, "expected '(' before the arguments")?;
+        let mut args = Vec::with_capacity(sig.params.len());
+        for (i, param) in sig.params.iter().enumerate() {
+            if i > 0 {
+                self.match_token(Token::Comma, "expected ',' between the arguments")?;
+            }
+            args.push(self.parse_data_value(param.value_type)?);
+        }
+        self.match_token(
+            Token::RPar,
+            &format!("expected ')' after {} arguments", sig.params.len()),
+        )?;
+
+        let comparison = match self.token() {
+            Some(Token::Equal) => {
+                self.consume();
+                Comparison::Equals
+            }
+            Some(Token::Not) => {
+                self.consume();
+                Comparison::NotEquals
+            }
+            _ => return err!(self.loc, "expected a comparison, '==' or '!='"),
+        };
+        self.match_token(Token::Equal, "expected a comparison, '==' or '!='")?;
+
+        let mut expected = Vec::with_capacity(sig.returns.len());
+        if sig.returns.len() == 1 {
+            expected.push(self.parse_data_value(sig.returns[0].value_type)?);
+        } else {
+            self.match_token(Token::LBracket, "expected '[' before the results")?;
+            for (i, ret) in sig.returns.iter().enumerate() {
+                if i > 0 {
+                    self.match_token(Token::Comma, "expected ',' between the results")?;
+                }
+                expected.push(self.parse_data_value(ret.value_type)?);
+            }
+            self.match_token(
+                Token::RBracket,
+                &format!("expected ']' after {} results", sig.returns.len()),
+            )?;
+        }
+
+        Ok(RunCommand {
+            invocation: Invocation { func, args },
+            comparison,
+            expected,
+        })
+    }
+
+    /// Parse a value of type `ty` in a run command.
+    ///
+    /// Scalars use the syntax of the corresponding immediates. Vectors use either the hexadecimal
+    /// or the literal list syntax of `vconst`, e.g. `[1 2 3 4]`.
+    fn parse_data_value(&mut self, ty: Type) -> ParseResult<DataValue> {
+        if !DataValue::is_supported(ty) {
+            return err!(self.loc, "values of type {} are not supported", ty);
+        }
+
+        if ty.is_vector() {
+            if !self.optional(Token::LBracket) {
+                let uimm128 = self.match_uimm128("expected a vector value")?;
+                return Ok(DataValue::V128(uimm128.0));
+            }
+            let lane_type = ty.lane_type();
+            let lane_size = lane_type.bytes() as usize;
+            let mut bytes = [0; 16];
+            for lane in bytes.chunks_mut(lane_size) {
+                self.parse_data_value(lane_type)?.write_to_slice(lane);
+            }
+            self.match_token(
+                Token::RBracket,
+                &format!("expected ']' after {} lanes", ty.lane_count()),
+            )?;
+            return Ok(DataValue::V128(bytes));
+        }
+
+        match ty {
+            _ if ty.is_bool() => Ok(DataValue::B(self.match_bool("expected a boolean value")?)),
+            F32 => Ok(DataValue::F32(self.match_ieee32("expected an f32 value")?)),
+            F64 => Ok(DataValue::F64(self.match_ieee64("expected an f64 value")?)),
+            _ => {
+                let loc = self.loc;
+                let imm: i64 = self.match_imm64("expected an integer value")?.into();
+                // Accept both the signed and the unsigned interpretation of the bits, like `iconst`.
+                let bits = ty.bits();
+                let wrapped = (imm << (64 - bits)) >> (64 - bits);
+                let zero_extended = ((imm as u64) << (64 - bits)) >> (64 - bits);
+                if wrapped != imm && zero_extended != imm as u64 {
+                    return err!(loc, "integer {} does not fit in {}", imm, ty);
+                }
+                Ok(match ty {
+                    I8 => DataValue::I8(imm as i8),
+                    I16 => DataValue::I16(imm as i16),
+                    I32 => DataValue::I32(imm as i32),
+                    _ => DataValue::I64(imm),
+                })
+            }
+        }
+    }
+
     /// Parse a list of test command passes specified in command line.
     pub fn parse_cmdline_passes(&mut self, passes: &'a [String]) -> Vec<TestCommand<'a>> {
         let mut list = Vec::new();
@@ -2442,6 +2578,19 @@ impl<'a> Parser<'a> {
                 let lane = self.match_uimm8("expected lane number")?;
                 InstructionData::ExtractLane { opcode, lane, arg }
             }
+            InstructionFormat::Shuffle => {
+                let a = self.match_value("expected SSA value first operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let b = self.match_value("expected SSA value second operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let uimm128 = self.match_uimm128_or_literals(I8X16)?;
+                let mask = ctx.function.dfg.constants.insert(uimm128.0.to_vec());
+                InstructionData::Shuffle {
+                    opcode,
+                    mask,
+                    args: [a, b],
+                }
+            }
             InstructionFormat::IntCompare => {
                 let cond = self.match_enum("expected intcc condition code")?;
                 let lhs = self.match_value("expected SSA value first operand")?;
@@ -2617,6 +2766,19 @@ impl<'a> Parser<'a> {
                 }
             }
 
+            InstructionFormat::AtomicCas => {
+                let flags = self.optional_memflags();
+                let expected = self.match_value("expected SSA value operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let replacement = self.match_value("expected SSA value operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let addr = self.match_value("expected SSA value address")?;
+                InstructionData::AtomicCas {
+                    opcode,
+                    flags,
+                    args: [expected, replacement, addr],
+                }
+            }
             InstructionFormat::StoreComplex => {
                 let flags = self.optional_memflags();
                 let src = self.match_value("expected SSA value operand")?;
@@ -3237,4 +3399,71 @@ mod tests {
         cannot_parse_as_uimm128!("1 2 3", I32X4);
         cannot_parse_as_uimm128!(" ", F32X4);
     }
+
+    #[test]
+    fn run_commands() {
+        let sig = |params: &[Type], returns: &[Type]| {
+            let mut sig = Signature::new(CallConv::SystemV);
+            sig.params = params.iter().map(|&ty| AbiParam::new(ty)).collect();
+            sig.returns = returns.iter().map(|&ty| AbiParam::new(ty)).collect();
+            sig
+        };
+        let parse = |text: &str, sig: &Signature| {
+            parse_run_command(text, sig).map(|command| command.unwrap().to_string())
+        };
+
+        let add = sig(&[I32, I8], &[I64]);
+        assert_eq!(
+            parse("; run: %add(1, -2) == 0xffff_ffff_ffff_ffff", &add).unwrap(),
+            "%add(1, -2) == -1"
+        );
+        assert_eq!(
+            parse(";run: %add(0xffffffff, 255) != 3", &add).unwrap(),
+            "%add(-1, -1) != 3"
+        );
+        assert_eq!(parse_run_command("; run", &add).unwrap(), None);
+        assert_eq!(parse_run_command("; check: run:", &add).unwrap(), None);
+        assert_eq!(
+            parse("; run: %add(1, 256) == 3", &add).unwrap_err().message,
+            "integer 256 does not fit in i8"
+        );
+        assert_eq!(
+            parse("; run: %add(1) == 3", &add).unwrap_err().message,
+            "expected ',' between the arguments"
+        );
+        assert_eq!(
+            parse("; run: %add(1, 2) == 3 4", &add).unwrap_err().message,
+            "unexpected text after the run command"
+        );
+
+        let floats = sig(&[F32, B1], &[F64, B8, I32X4]);
+        let text = "; run: %floats(0x1.8p1, true) == [-0x1.0p-1, false, [1 2 3 -1]]";
+        let command = parse_run_command(text, &floats).unwrap().unwrap();
+        assert_eq!(
+            command.invocation.args,
+            [DataValue::F32(Ieee32::with_float(3.0)), DataValue::B(true)]
+        );
+        assert_eq!(
+            command.to_string(),
+            "%floats(0x1.800000p1, true) == [-0x1.0000000000000p-1, false, \
+             0xffffffff000000030000000200000001]"
+        );
+        assert!(command.matches(&command.expected));
+        assert_eq!(
+            parse("; run: %floats(3, true) == [0.0, true, 0]", &floats)
+                .unwrap_err()
+                .message,
+            "expected an f32 value"
+        );
+
+        let unsupported = sig(&[I128], &[]);
+        assert_eq!(
+            parse("; run: %f(0) == []", &unsupported)
+                .unwrap_err()
+                .message,
+            "values of type i128 are not supported"
+        );
+        let none = sig(&[], &[]);
+        assert_eq!(parse("; run: %f() == []", &none).unwrap(), "%f() == []");
+    }
 }
@@ -0,0 +1,199 @@
+//! Run commands.
+//!
+//! Functions in a `test run` file can be annotated with *run commands* which call the function
+//! with some arguments and compare its results against expected values:
+//!
+//! <pre>
+//! ; run: %add(1, 2) == 3
+//! ; run: %swap(1, 2) == [2, 1]
+//! ; run: %splat(1) != [0 0 0 0]
+//! </pre>
+//!
+//! The arguments and results are parsed according to the signature of the function, see
+//! `parse_run_command`.
+
+use cranelift_codegen::ir::immediates::{Ieee32, Ieee64, Uimm128};
+use cranelift_codegen::ir::{types, Type};
+use std::fmt::{self, Display, Formatter};
+
+/// A run command appearing in a comment: `run: %fn(args...) == results`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct RunCommand {
+    /// The function call to perform.
+    pub invocation: Invocation,
+    /// How to compare the results of the call with `expected`.
+    pub comparison: Comparison,
+    /// The expected results, one for each return value of the function.
+    pub expected: Vec<DataValue>,
+}
+
+impl RunCommand {
+    /// Check whether the `actual` results of the invocation satisfy this command.
+    pub fn matches(&self, actual: &[DataValue]) -> bool {
+        let equal = actual == &self.expected[..];
+        match self.comparison {
+            Comparison::Equals => equal,
+            Comparison::NotEquals => !equal,
+        }
+    }
+}
+
+impl Display for RunCommand {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{} {} ", self.invocation, self.comparison)?;
+        if self.expected.len() == 1 {
+            write!(f, "{}", self.expected[0])
+        } else {
+            write!(f, "{}", DisplayDataValues(&self.expected))
+        }
+    }
+}
+
+/// A call of the function named `func` with the given arguments: `%fn(args...)`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Invocation {
+    /// The name of the function to call, without the leading `%`.
+    pub func: String,
+    /// The arguments, one for each parameter of the function.
+    pub args: Vec<DataValue>,
+}
+
+impl Display for Invocation {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "%{}(", self.func)?;
+        for (i, arg) in self.args.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", arg)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// The comparison performed by a run command.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Comparison {
+    /// The results must be equal to the expected values: `==`.
+    Equals,
+    /// The results must differ from the expected values: `!=`.
+    NotEquals,
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            Comparison::Equals => write!(f, "=="),
+            Comparison::NotEquals => write!(f, "!="),
+        }
+    }
+}
+
+/// A value passed to or returned from a function in a run command.
+///
+/// Floats and vectors are compared by their bit patterns, so a NaN result can be matched exactly.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DataValue {
+    /// A boolean of any width.
+    B(bool),
+    /// An `i8` value.
+    I8(i8),
+    /// An `i16` value.
+    I16(i16),
+    /// An `i32` value.
+    I32(i32),
+    /// An `i64` value.
+    I64(i64),
+    /// An `f32` value.
+    F32(Ieee32),
+    /// An `f64` value.
+    F64(Ieee64),
+    /// A 128-bit vector value, in little-endian lane order.
+    V128([u8; 16]),
+}
+
+impl DataValue {
+    /// Is `ty` a type that can be represented by a `DataValue`?
+    pub fn is_supported(ty: Type) -> bool {
+        (ty.is_bool() || ty.is_int()) && ty.bits() <= 64
+            || ty.is_float()
+            || ty.is_vector() && ty.bits() == 128
+    }
+
+    /// Read a value of type `ty` from the little-endian bytes at the start of `src`.
+    ///
+    /// Any non-zero bit pattern is read as a `true` boolean.
+    pub fn read_from_slice(src: &[u8], ty: Type) -> Self {
+        debug_assert!(Self::is_supported(ty), "unsupported type {}", ty);
+        let mut bytes = [0; 16];
+        let size = ty.bytes() as usize;
+        bytes[..size].copy_from_slice(&src[..size]);
+        let bits = u64::from_le_bytes([
+            bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
+        ]);
+        match ty {
+            _ if ty.is_vector() => DataValue::V128(bytes),
+            _ if ty.is_bool() => DataValue::B(bits != 0),
+            types::I8 => DataValue::I8(bits as i8),
+            types::I16 => DataValue::I16(bits as i16),
+            types::I32 => DataValue::I32(bits as i32),
+            types::I64 => DataValue::I64(bits as i64),
+            types::F32 => DataValue::F32(Ieee32::with_bits(bits as u32)),
+            types::F64 => DataValue::F64(Ieee64::with_bits(bits)),
+            _ => panic!("unsupported type {}", ty),
+        }
+    }
+
+    /// Write this value in little-endian byte order to the start of `dst`, which must be exactly
+    /// the size of the value's type.
+    ///
+    /// A `true` boolean is written as 1, like the lanes of a boolean `vconst`.
+    pub fn write_to_slice(&self, dst: &mut [u8]) {
+        match *self {
+            DataValue::B(b) => {
+                for byte in dst.iter_mut() {
+                    *byte = 0;
+                }
+                dst[0] = b as u8;
+            }
+            DataValue::I8(x) => dst.copy_from_slice(&x.to_le_bytes()),
+            DataValue::I16(x) => dst.copy_from_slice(&x.to_le_bytes()),
+            DataValue::I32(x) => dst.copy_from_slice(&x.to_le_bytes()),
+            DataValue::I64(x) => dst.copy_from_slice(&x.to_le_bytes()),
+            DataValue::F32(x) => dst.copy_from_slice(&x.bits().to_le_bytes()),
+            DataValue::F64(x) => dst.copy_from_slice(&x.bits().to_le_bytes()),
+            DataValue::V128(x) => dst.copy_from_slice(&x),
+        }
+    }
+}
+
+impl Display for DataValue {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            DataValue::B(b) => write!(f, "{}", b),
+            DataValue::I8(x) => write!(f, "{}", x),
+            DataValue::I16(x) => write!(f, "{}", x),
+            DataValue::I32(x) => write!(f, "{}", x),
+            DataValue::I64(x) => write!(f, "{}", x),
+            DataValue::F32(x) => write!(f, "{}", x),
+            DataValue::F64(x) => write!(f, "{}", x),
+            DataValue::V128(x) => write!(f, "{}", Uimm128(x)),
+        }
+    }
+}
+
+/// Display a list of `DataValue`s the way a run command writes multiple results: `[1, 2]`.
+pub struct DisplayDataValues<'a>(pub &'a [DataValue]);
+
+impl<'a> Display for DisplayDataValues<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "[")?;
+        for (i, value) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", value)?;
+        }
+        write!(f, "]")
+    }
+}
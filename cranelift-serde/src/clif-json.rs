@@ -53,7 +53,13 @@ fn call_de(file: &File) -> Result<(), String> {
         Result::Ok(val) => val,
         Result::Err(err) => panic!("{}", err),
     };
-    println!("{:?}", de);
+    let funcs = de.to_functions()?;
+    for (i, func) in funcs.iter().enumerate() {
+        if i != 0 {
+            println!();
+        }
+        print!("{}", func.display(None));
+    }
     Ok(())
 }
 
@@ -73,7 +79,7 @@ fn main() {
         )
         .subcommand(
             SubCommand::with_name("deserialize")
-                .about("Deserializes Cranelift IR into JSON.")
+                .about("Deserializes JSON into Cranelift IR.")
                 .arg(
                     Arg::with_name("FILE")
                         .required(true)
@@ -1,6 +1,19 @@
-use cranelift_codegen::ir::immediates::Uimm128;
-use cranelift_codegen::ir::{Ebb, Function, Inst, InstructionData, Signature};
+use cranelift_codegen::entity::EntityRef;
+use cranelift_codegen::ir::immediates::{Imm64, Offset32, Uimm128, Uimm64};
+use cranelift_codegen::ir::instructions::InstructionFormat;
+use cranelift_codegen::ir::types::{self, Type};
+use cranelift_codegen::ir::{
+    AbiParam, ArgumentExtension, ArgumentLoc, Ebb, ExtFuncData, ExternalName, FuncRef, Function,
+    GlobalValue, GlobalValueData, Heap, HeapData, HeapStyle, Inst, InstructionData, JumpTable,
+    JumpTableData, MemFlags, Opcode, SigRef, Signature, SourceLoc, StackSlot, StackSlotData,
+    StackSlotKind, Table, TableData, Value, ValueList,
+};
+use cranelift_codegen::isa::CallConv;
+use cranelift_codegen::packed_option::ReservedValue;
 use serde_derive::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::str::FromStr;
 
 /// Serializable version of the original Cranelift IR
 #[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
@@ -59,6 +72,11 @@ pub enum SerInstData {
         arg: String,
         lane: String,
     },
+    Shuffle {
+        opcode: String,
+        args: [String; 2],
+        mask: String,
+    },
     IntCompare {
         opcode: String,
         args: [String; 2],
@@ -177,6 +195,11 @@ pub enum SerInstData {
         flags: String,
         offset: String,
     },
+    AtomicCas {
+        opcode: String,
+        args: [String; 3],
+        flags: String,
+    },
     StackLoad {
         opcode: String,
         stack_slot: String,
@@ -340,6 +363,16 @@ pub fn get_inst_data(inst_index: Inst, func: &Function) -> SerInstData {
             arg: arg.to_string(),
             lane: lane.to_string(),
         },
+        InstructionData::Shuffle { opcode, args, mask } => {
+            let data = func.dfg.constants.get(mask);
+            let uimm128 = Uimm128::from(&data[..]);
+            let hold_args = [args[0].to_string(), args[1].to_string()];
+            SerInstData::Shuffle {
+                opcode: opcode.to_string(),
+                args: hold_args,
+                mask: uimm128.to_string(),
+            }
+        }
         InstructionData::IntCompare { opcode, args, cond } => {
             let hold_args = [args[0].to_string(), args[1].to_string()];
             SerInstData::IntCompare {
@@ -588,6 +621,22 @@ pub fn get_inst_data(inst_index: Inst, func: &Function) -> SerInstData {
                 offset: offset.to_string(),
             }
         }
+        InstructionData::AtomicCas {
+            opcode,
+            args,
+            flags,
+        } => {
+            let hold_args = [
+                args[0].to_string(),
+                args[1].to_string(),
+                args[2].to_string(),
+            ];
+            SerInstData::AtomicCas {
+                opcode: opcode.to_string(),
+                args: hold_args,
+                flags: flags.to_string(),
+            }
+        }
         InstructionData::StoreComplex {
             opcode,
             ref args,
@@ -724,27 +773,815 @@ pub fn get_inst_data(inst_index: Inst, func: &Function) -> SerInstData {
     }
 }
 
+/// Parse a string produced by a `Display` implementation back with `FromStr`.
+fn parse<T: FromStr>(s: &str, what: &str) -> Result<T, String> {
+    s.parse().map_err(|_| format!("invalid {} '{}'", what, s))
+}
+
+/// Parse an entity reference such as `v12` or `ebb3`, as displayed with the given prefix.
+fn parse_entity<E: EntityRef>(s: &str, prefix: &str) -> Result<E, String> {
+    if s.starts_with(prefix) {
+        let digits = &s[prefix.len()..];
+        if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+            if let Ok(index) = digits.parse::<u32>() {
+                if index < u32::max_value() {
+                    return Ok(E::new(index as usize));
+                }
+            }
+        }
+    }
+    Err(format!(
+        "invalid entity reference '{}', expected {}N",
+        s, prefix
+    ))
+}
+
+/// Parse a value type, such as `i32`, `b1` or `f32x4`.
+fn parse_type(s: &str) -> Result<Type, String> {
+    let scalars = [
+        types::B1,
+        types::B8,
+        types::B16,
+        types::B32,
+        types::B64,
+        types::B128,
+        types::I8,
+        types::I16,
+        types::I32,
+        types::I64,
+        types::I128,
+        types::F32,
+        types::F64,
+        types::R32,
+        types::R64,
+        types::IFLAGS,
+        types::FFLAGS,
+    ];
+    let (lane, lanes) = match s.find('x') {
+        Some(pos) => (&s[..pos], Some(&s[pos + 1..])),
+        None => (s, None),
+    };
+    let ty = scalars
+        .iter()
+        .cloned()
+        .find(|ty| ty.to_string() == lane)
+        .ok_or_else(|| format!("invalid type '{}'", s))?;
+    match lanes {
+        None => Ok(ty),
+        Some(lanes) => parse::<u16>(lanes, "lane count")
+            .ok()
+            .and_then(|lanes| ty.by(lanes))
+            .ok_or_else(|| format!("invalid vector type '{}'", s)),
+    }
+}
+
+/// Parse an external name, in either the `u1:2` or the `%name` form.
+fn parse_external_name(s: &str) -> Result<ExternalName, String> {
+    if s.starts_with('%') {
+        // Try to parse as a libcall name, otherwise it's a test case.
+        Ok(match s[1..].parse() {
+            Ok(libcall) => ExternalName::LibCall(libcall),
+            Err(()) => ExternalName::testcase(&s[1..]),
+        })
+    } else if s.starts_with('u') {
+        let mut parts = s[1..].splitn(2, ':');
+        let namespace = parse(parts.next().unwrap_or(""), "external name namespace")?;
+        let index = parse(parts.next().unwrap_or(""), "external name index")?;
+        Ok(ExternalName::user(namespace, index))
+    } else {
+        Err(format!("invalid external name '{}'", s))
+    }
+}
+
+/// Parse memory flags, written as a list of flag names each preceded by a space.
+fn parse_memflags(s: &str) -> Result<MemFlags, String> {
+    let mut flags = MemFlags::new();
+    for name in s.split_whitespace() {
+        if !flags.set_by_name(name) {
+            return Err(format!("invalid memory flag '{}'", name));
+        }
+    }
+    Ok(flags)
+}
+
+/// Parse an `Offset32`, whose display is empty when it is zero.
+fn parse_offset32(s: &str) -> Result<Offset32, String> {
+    if s.is_empty() {
+        Ok(Offset32::new(0))
+    } else {
+        parse(s, "offset")
+    }
+}
+
+/// Parse a source location, such as `@0012`.
+fn parse_srcloc(s: &str) -> Result<SourceLoc, String> {
+    if s.starts_with('@') {
+        if &s[1..] == "-" {
+            return Ok(SourceLoc::default());
+        }
+        if let Ok(bits) = u32::from_str_radix(&s[1..], 16) {
+            return Ok(SourceLoc::new(bits));
+        }
+    }
+    Err(format!("invalid source location '{}'", s))
+}
+
+/// Parse a function parameter or return value, such as `i32 sext` or `i64 vmctx [%5]`.
+fn parse_abi_param(s: &str) -> Result<AbiParam, String> {
+    let mut words = s.split_whitespace();
+    let mut param = AbiParam::new(parse_type(words.next().unwrap_or(""))?);
+    for word in words {
+        match word {
+            "uext" => param.extension = ArgumentExtension::Uext,
+            "sext" => param.extension = ArgumentExtension::Sext,
+            _ if word.starts_with('[') && word.ends_with(']') => {
+                let loc = &word[1..word.len() - 1];
+                param.location = if loc.starts_with('%') {
+                    ArgumentLoc::Reg(parse(&loc[1..], "register unit")?)
+                } else {
+                    ArgumentLoc::Stack(parse(loc, "stack offset")?)
+                };
+            }
+            _ => param.purpose = parse(word, "argument purpose")?,
+        }
+    }
+    Ok(param)
+}
+
+fn parse_value(s: &str) -> Result<Value, String> {
+    parse_entity(s, "v")
+}
+
+fn parse_values(args: &[String]) -> Result<Vec<Value>, String> {
+    args.iter().map(|arg| parse_value(arg)).collect()
+}
+
+fn parse_value_list(args: &[String], func: &mut Function) -> Result<ValueList, String> {
+    let values = parse_values(args)?;
+    Ok(ValueList::from_slice(&values, &mut func.dfg.value_lists))
+}
+
+fn parse_ebb(s: &str) -> Result<Ebb, String> {
+    parse_entity(s, "ebb")
+}
+
+fn check_defined<E: Display>(entity: E, defined: bool, what: &str) -> Result<E, String> {
+    if defined {
+        Ok(entity)
+    } else {
+        Err(format!("undefined {} {}", what, entity))
+    }
+}
+
+fn parse_global_value(s: &str, func: &Function) -> Result<GlobalValue, String> {
+    let gv = parse_entity(s, "gv")?;
+    check_defined(gv, func.global_values.is_valid(gv), "global value")
+}
+
+fn parse_heap(s: &str, func: &Function) -> Result<Heap, String> {
+    let heap = parse_entity(s, "heap")?;
+    let defined = func.heaps.is_valid(heap) && !func.heaps[heap].index_type.is_invalid();
+    check_defined(heap, defined, "heap")
+}
+
+fn parse_table(s: &str, func: &Function) -> Result<Table, String> {
+    let table = parse_entity(s, "table")?;
+    let defined = func.tables.is_valid(table) && !func.tables[table].index_type.is_invalid();
+    check_defined(table, defined, "table")
+}
+
+fn parse_stack_slot(s: &str, func: &Function) -> Result<StackSlot, String> {
+    let ss = parse_entity(s, "ss")?;
+    check_defined(ss, func.stack_slots.is_valid(ss), "stack slot")
+}
+
+fn parse_jump_table(s: &str, func: &Function) -> Result<JumpTable, String> {
+    let jt = parse_entity(s, "jt")?;
+    check_defined(jt, func.jump_tables.is_valid(jt), "jump table")
+}
+
+fn parse_func_ref(s: &str, func: &Function) -> Result<FuncRef, String> {
+    let fn_ = parse_entity(s, "fn")?;
+    let defined = func.dfg.ext_funcs.is_valid(fn_)
+        && func.dfg.ext_funcs[fn_].signature != SigRef::reserved_value();
+    check_defined(fn_, defined, "function")
+}
+
+fn parse_sig_ref(s: &str, func: &Function) -> Result<SigRef, String> {
+    let sig = parse_entity(s, "sig")?;
+    check_defined(sig, func.dfg.signatures.is_valid(sig), "signature")
+}
+
+/// Convert instructions in JSON format back to Cranelift IR, creating the constants and value
+/// lists they refer to in `func`.
+pub fn to_inst_data(
+    ser_inst: &SerInstData,
+    func: &mut Function,
+) -> Result<InstructionData, String> {
+    let data = match *ser_inst {
+        SerInstData::Unary {
+            ref opcode,
+            ref arg,
+        } => InstructionData::Unary {
+            opcode: parse(opcode, "opcode")?,
+            arg: parse_value(arg)?,
+        },
+        SerInstData::UnaryImm {
+            ref opcode,
+            ref imm,
+        } => {
+            let opcode: Opcode = parse(opcode, "opcode")?;
+            if opcode.format() == InstructionFormat::UnaryImm128 {
+                let uimm128: Uimm128 = parse(imm, "128-bit immediate")?;
+                InstructionData::UnaryImm128 {
+                    opcode,
+                    imm: func.dfg.constants.insert(uimm128.0.to_vec()),
+                }
+            } else {
+                InstructionData::UnaryImm {
+                    opcode,
+                    imm: parse(imm, "immediate")?,
+                }
+            }
+        }
+        SerInstData::UnaryIeee32 {
+            ref opcode,
+            ref imm,
+        } => InstructionData::UnaryIeee32 {
+            opcode: parse(opcode, "opcode")?,
+            imm: parse(imm, "floating point immediate")?,
+        },
+        SerInstData::UnaryIeee64 {
+            ref opcode,
+            ref imm,
+        } => InstructionData::UnaryIeee64 {
+            opcode: parse(opcode, "opcode")?,
+            imm: parse(imm, "floating point immediate")?,
+        },
+        SerInstData::UnaryBool { ref opcode, imm } => InstructionData::UnaryBool {
+            opcode: parse(opcode, "opcode")?,
+            imm,
+        },
+        SerInstData::UnaryGlobalValue {
+            ref opcode,
+            ref global_value,
+        } => InstructionData::UnaryGlobalValue {
+            opcode: parse(opcode, "opcode")?,
+            global_value: parse_global_value(global_value, func)?,
+        },
+        SerInstData::Binary {
+            ref opcode,
+            ref args,
+        } => InstructionData::Binary {
+            opcode: parse(opcode, "opcode")?,
+            args: [parse_value(&args[0])?, parse_value(&args[1])?],
+        },
+        SerInstData::BinaryImm {
+            ref opcode,
+            ref arg,
+            ref imm,
+        } => InstructionData::BinaryImm {
+            opcode: parse(opcode, "opcode")?,
+            arg: parse_value(arg)?,
+            imm: parse(imm, "immediate")?,
+        },
+        SerInstData::Ternary {
+            ref opcode,
+            ref args,
+        } => InstructionData::Ternary {
+            opcode: parse(opcode, "opcode")?,
+            args: [
+                parse_value(&args[0])?,
+                parse_value(&args[1])?,
+                parse_value(&args[2])?,
+            ],
+        },
+        SerInstData::MultiAry {
+            ref opcode,
+            ref args,
+        } => InstructionData::MultiAry {
+            opcode: parse(opcode, "opcode")?,
+            args: parse_value_list(args, func)?,
+        },
+        SerInstData::NullAry { ref opcode } => InstructionData::NullAry {
+            opcode: parse(opcode, "opcode")?,
+        },
+        SerInstData::InsertLane {
+            ref opcode,
+            ref args,
+            ref lane,
+        } => InstructionData::InsertLane {
+            opcode: parse(opcode, "opcode")?,
+            args: [parse_value(&args[0])?, parse_value(&args[1])?],
+            lane: parse(lane, "lane")?,
+        },
+        SerInstData::ExtractLane {
+            ref opcode,
+            ref arg,
+            ref lane,
+        } => InstructionData::ExtractLane {
+            opcode: parse(opcode, "opcode")?,
+            arg: parse_value(arg)?,
+            lane: parse(lane, "lane")?,
+        },
+        SerInstData::Shuffle {
+            ref opcode,
+            ref args,
+            ref mask,
+        } => {
+            let mask: Uimm128 = parse(mask, "shuffle mask")?;
+            InstructionData::Shuffle {
+                opcode: parse(opcode, "opcode")?,
+                args: [parse_value(&args[0])?, parse_value(&args[1])?],
+                mask: func.dfg.constants.insert(mask.0.to_vec()),
+            }
+        }
+        SerInstData::IntCompare {
+            ref opcode,
+            ref args,
+            ref cond,
+        } => InstructionData::IntCompare {
+            opcode: parse(opcode, "opcode")?,
+            args: [parse_value(&args[0])?, parse_value(&args[1])?],
+            cond: parse(cond, "condition code")?,
+        },
+        SerInstData::IntCompareImm {
+            ref opcode,
+            ref arg,
+            ref cond,
+            ref imm,
+        } => InstructionData::IntCompareImm {
+            opcode: parse(opcode, "opcode")?,
+            arg: parse_value(arg)?,
+            cond: parse(cond, "condition code")?,
+            imm: parse(imm, "immediate")?,
+        },
+        SerInstData::IntCond {
+            ref opcode,
+            ref arg,
+            ref cond,
+        } => InstructionData::IntCond {
+            opcode: parse(opcode, "opcode")?,
+            arg: parse_value(arg)?,
+            cond: parse(cond, "condition code")?,
+        },
+        SerInstData::FloatCompare {
+            ref opcode,
+            ref args,
+            ref cond,
+        } => InstructionData::FloatCompare {
+            opcode: parse(opcode, "opcode")?,
+            args: [parse_value(&args[0])?, parse_value(&args[1])?],
+            cond: parse(cond, "condition code")?,
+        },
+        SerInstData::FloatCond {
+            ref opcode,
+            ref arg,
+            ref cond,
+        } => InstructionData::FloatCond {
+            opcode: parse(opcode, "opcode")?,
+            arg: parse_value(arg)?,
+            cond: parse(cond, "condition code")?,
+        },
+        SerInstData::IntSelect {
+            ref opcode,
+            ref args,
+            ref cond,
+        } => InstructionData::IntSelect {
+            opcode: parse(opcode, "opcode")?,
+            args: [
+                parse_value(&args[0])?,
+                parse_value(&args[1])?,
+                parse_value(&args[2])?,
+            ],
+            cond: parse(cond, "condition code")?,
+        },
+        SerInstData::Jump {
+            ref opcode,
+            ref args,
+            ref destination,
+        } => InstructionData::Jump {
+            opcode: parse(opcode, "opcode")?,
+            args: parse_value_list(args, func)?,
+            destination: parse_ebb(destination)?,
+        },
+        SerInstData::Branch {
+            ref opcode,
+            ref args,
+            ref destination,
+        } => InstructionData::Branch {
+            opcode: parse(opcode, "opcode")?,
+            args: parse_value_list(args, func)?,
+            destination: parse_ebb(destination)?,
+        },
+        SerInstData::BranchInt {
+            ref opcode,
+            ref args,
+            ref cond,
+            ref destination,
+        } => InstructionData::BranchInt {
+            opcode: parse(opcode, "opcode")?,
+            args: parse_value_list(args, func)?,
+            cond: parse(cond, "condition code")?,
+            destination: parse_ebb(destination)?,
+        },
+        SerInstData::BranchFloat {
+            ref opcode,
+            ref args,
+            ref cond,
+            ref destination,
+        } => InstructionData::BranchFloat {
+            opcode: parse(opcode, "opcode")?,
+            args: parse_value_list(args, func)?,
+            cond: parse(cond, "condition code")?,
+            destination: parse_ebb(destination)?,
+        },
+        SerInstData::BranchIcmp {
+            ref opcode,
+            ref args,
+            ref cond,
+            ref destination,
+        } => InstructionData::BranchIcmp {
+            opcode: parse(opcode, "opcode")?,
+            args: parse_value_list(args, func)?,
+            cond: parse(cond, "condition code")?,
+            destination: parse_ebb(destination)?,
+        },
+        SerInstData::BranchTable {
+            ref opcode,
+            ref arg,
+            ref destination,
+            ref table,
+        } => InstructionData::BranchTable {
+            opcode: parse(opcode, "opcode")?,
+            arg: parse_value(arg)?,
+            destination: parse_ebb(destination)?,
+            table: parse_jump_table(table, func)?,
+        },
+        SerInstData::BranchTableEntry {
+            ref opcode,
+            ref args,
+            ref imm,
+            ref table,
+        } => InstructionData::BranchTableEntry {
+            opcode: parse(opcode, "opcode")?,
+            args: [parse_value(&args[0])?, parse_value(&args[1])?],
+            imm: parse(imm, "immediate")?,
+            table: parse_jump_table(table, func)?,
+        },
+        SerInstData::BranchTableBase {
+            ref opcode,
+            ref table,
+        } => InstructionData::BranchTableBase {
+            opcode: parse(opcode, "opcode")?,
+            table: parse_jump_table(table, func)?,
+        },
+        SerInstData::IndirectJump {
+            ref opcode,
+            ref arg,
+            ref table,
+        } => InstructionData::IndirectJump {
+            opcode: parse(opcode, "opcode")?,
+            arg: parse_value(arg)?,
+            table: parse_jump_table(table, func)?,
+        },
+        SerInstData::Call {
+            ref opcode,
+            ref args,
+            ref func_ref,
+        } => InstructionData::Call {
+            opcode: parse(opcode, "opcode")?,
+            func_ref: parse_func_ref(func_ref, func)?,
+            args: parse_value_list(args, func)?,
+        },
+        SerInstData::CallIndirect {
+            ref opcode,
+            ref args,
+            ref sig_ref,
+        } => InstructionData::CallIndirect {
+            opcode: parse(opcode, "opcode")?,
+            sig_ref: parse_sig_ref(sig_ref, func)?,
+            args: parse_value_list(args, func)?,
+        },
+        SerInstData::FuncAddr {
+            ref opcode,
+            ref func_ref,
+        } => InstructionData::FuncAddr {
+            opcode: parse(opcode, "opcode")?,
+            func_ref: parse_func_ref(func_ref, func)?,
+        },
+        SerInstData::Load {
+            ref opcode,
+            ref arg,
+            ref flags,
+            ref offset,
+        } => InstructionData::Load {
+            opcode: parse(opcode, "opcode")?,
+            arg: parse_value(arg)?,
+            flags: parse_memflags(flags)?,
+            offset: parse_offset32(offset)?,
+        },
+        SerInstData::LoadComplex {
+            ref opcode,
+            ref args,
+            ref flags,
+            ref offset,
+        } => InstructionData::LoadComplex {
+            opcode: parse(opcode, "opcode")?,
+            args: parse_value_list(args, func)?,
+            flags: parse_memflags(flags)?,
+            offset: parse_offset32(offset)?,
+        },
+        SerInstData::Store {
+            ref opcode,
+            ref args,
+            ref flags,
+            ref offset,
+        } => InstructionData::Store {
+            opcode: parse(opcode, "opcode")?,
+            args: [parse_value(&args[0])?, parse_value(&args[1])?],
+            flags: parse_memflags(flags)?,
+            offset: parse_offset32(offset)?,
+        },
+        SerInstData::StoreComplex {
+            ref opcode,
+            ref args,
+            ref flags,
+            ref offset,
+        } => InstructionData::StoreComplex {
+            opcode: parse(opcode, "opcode")?,
+            args: parse_value_list(args, func)?,
+            flags: parse_memflags(flags)?,
+            offset: parse_offset32(offset)?,
+        },
+        SerInstData::AtomicCas {
+            ref opcode,
+            ref args,
+            ref flags,
+        } => InstructionData::AtomicCas {
+            opcode: parse(opcode, "opcode")?,
+            args: [
+                parse_value(&args[0])?,
+                parse_value(&args[1])?,
+                parse_value(&args[2])?,
+            ],
+            flags: parse_memflags(flags)?,
+        },
+        SerInstData::StackLoad {
+            ref opcode,
+            ref stack_slot,
+            ref offset,
+        } => InstructionData::StackLoad {
+            opcode: parse(opcode, "opcode")?,
+            stack_slot: parse_stack_slot(stack_slot, func)?,
+            offset: parse_offset32(offset)?,
+        },
+        SerInstData::StackStore {
+            ref opcode,
+            ref arg,
+            ref stack_slot,
+            ref offset,
+        } => InstructionData::StackStore {
+            opcode: parse(opcode, "opcode")?,
+            arg: parse_value(arg)?,
+            stack_slot: parse_stack_slot(stack_slot, func)?,
+            offset: parse_offset32(offset)?,
+        },
+        SerInstData::HeapAddr {
+            ref opcode,
+            ref arg,
+            ref heap,
+            ref imm,
+        } => InstructionData::HeapAddr {
+            opcode: parse(opcode, "opcode")?,
+            arg: parse_value(arg)?,
+            heap: parse_heap(heap, func)?,
+            imm: parse(imm, "immediate")?,
+        },
+        SerInstData::TableAddr {
+            ref opcode,
+            ref arg,
+            ref table,
+            ref offset,
+        } => InstructionData::TableAddr {
+            opcode: parse(opcode, "opcode")?,
+            arg: parse_value(arg)?,
+            table: parse_table(table, func)?,
+            offset: parse_offset32(offset)?,
+        },
+        SerInstData::RegMove {
+            ref opcode,
+            ref arg,
+            ref src,
+            ref dst,
+        } => InstructionData::RegMove {
+            opcode: parse(opcode, "opcode")?,
+            arg: parse_value(arg)?,
+            src: parse(src, "register unit")?,
+            dst: parse(dst, "register unit")?,
+        },
+        SerInstData::CopySpecial {
+            ref opcode,
+            ref src,
+            ref dst,
+        } => InstructionData::CopySpecial {
+            opcode: parse(opcode, "opcode")?,
+            src: parse(src, "register unit")?,
+            dst: parse(dst, "register unit")?,
+        },
+        SerInstData::CopyToSsa {
+            ref opcode,
+            ref src,
+        } => InstructionData::CopyToSsa {
+            opcode: parse(opcode, "opcode")?,
+            src: parse(src, "register unit")?,
+        },
+        SerInstData::RegSpill {
+            ref opcode,
+            ref arg,
+            ref src,
+            ref dst,
+        } => InstructionData::RegSpill {
+            opcode: parse(opcode, "opcode")?,
+            arg: parse_value(arg)?,
+            src: parse(src, "register unit")?,
+            dst: parse_stack_slot(dst, func)?,
+        },
+        SerInstData::RegFill {
+            ref opcode,
+            ref arg,
+            ref src,
+            ref dst,
+        } => InstructionData::RegFill {
+            opcode: parse(opcode, "opcode")?,
+            arg: parse_value(arg)?,
+            src: parse_stack_slot(src, func)?,
+            dst: parse(dst, "register unit")?,
+        },
+        SerInstData::Trap {
+            ref opcode,
+            ref code,
+        } => InstructionData::Trap {
+            opcode: parse(opcode, "opcode")?,
+            code: parse(code, "trap code")?,
+        },
+        SerInstData::CondTrap {
+            ref opcode,
+            ref arg,
+            ref code,
+        } => InstructionData::CondTrap {
+            opcode: parse(opcode, "opcode")?,
+            arg: parse_value(arg)?,
+            code: parse(code, "trap code")?,
+        },
+        SerInstData::IntCondTrap {
+            ref opcode,
+            ref arg,
+            ref cond,
+            ref code,
+        } => InstructionData::IntCondTrap {
+            opcode: parse(opcode, "opcode")?,
+            arg: parse_value(arg)?,
+            cond: parse(cond, "condition code")?,
+            code: parse(code, "trap code")?,
+        },
+        SerInstData::FloatCondTrap {
+            ref opcode,
+            ref arg,
+            ref cond,
+            ref code,
+        } => InstructionData::FloatCondTrap {
+            opcode: parse(opcode, "opcode")?,
+            arg: parse_value(arg)?,
+            cond: parse(cond, "condition code")?,
+            code: parse(code, "trap code")?,
+        },
+    };
+
+    let opcode = data.opcode();
+    if InstructionFormat::from(&data) != opcode.format() {
+        return Err(format!(
+            "opcode {} doesn't use the {:?} format",
+            opcode,
+            InstructionFormat::from(&data)
+        ));
+    }
+    Ok(data)
+}
+
+/// Make sure `value` can be referred to in `func`, creating placeholder values up to it.
+fn reserve_value(value: Value, func: &mut Function) {
+    while func.dfg.num_values() <= value.index() {
+        func.dfg.make_invalid_value_for_parser();
+    }
+}
+
+/// Prepare to define `value` in `func`, failing if it was already defined.
+fn define_value(value: Value, func: &mut Function) -> Result<(), String> {
+    reserve_value(value, func);
+    if func.dfg.value_is_valid_for_parser(value) {
+        Err(format!("value {} is defined more than once", value))
+    } else {
+        Ok(())
+    }
+}
+
 /// Serializable version of Cranelift IR instructions.
 #[derive(Clone, Deserialize, Serialize, Debug)]
 pub struct SerInst {
     pub inst_name: String,
     pub inst_data: SerInstData,
+    pub results: Vec<String>,
+    pub ctrl_typevar: Option<String>,
+    pub srcloc: Option<String>,
 }
 
 impl SerInst {
     pub fn new(inst: Inst, func: &Function) -> Self {
+        let ctrl_typevar = func.dfg.ctrl_typevar(inst);
+        let srcloc = func.srclocs[inst];
         Self {
             inst_name: inst.to_string(),
             inst_data: get_inst_data(inst, func),
+            results: func
+                .dfg
+                .inst_results(inst)
+                .iter()
+                .map(|result| result.to_string())
+                .collect(),
+            ctrl_typevar: if ctrl_typevar.is_invalid() {
+                None
+            } else {
+                Some(ctrl_typevar.to_string())
+            },
+            srcloc: if srcloc.is_default() {
+                None
+            } else {
+                Some(srcloc.to_string())
+            },
         }
     }
+
+    /// Append the instruction at the end of `ebb`, defining its results.
+    fn append_to(&self, ebb: Ebb, func: &mut Function) -> Result<(), String> {
+        let data = to_inst_data(&self.inst_data, func)?;
+        let opcode = data.opcode();
+        let constraints = opcode.constraints();
+        let ctrl_typevar = match self.ctrl_typevar {
+            Some(ref ty) => {
+                let ty = parse_type(ty)?;
+                match constraints.ctrl_typeset() {
+                    Some(typeset) if typeset.contains(ty) => ty,
+                    _ => return Err(format!("{} is not a valid type for {}", ty, opcode)),
+                }
+            }
+            None if constraints.is_polymorphic() => {
+                return Err(format!("missing controlling type for {}", opcode));
+            }
+            None => types::INVALID,
+        };
+
+        let results = parse_values(&self.results)?;
+        for &result in &results {
+            define_value(result, func)?;
+        }
+        let inst = func.dfg.make_inst(data);
+        let num_args = func.dfg.inst_args(inst).len();
+        if num_args < constraints.num_fixed_value_arguments() {
+            return Err(format!("{} needs more than {} arguments", opcode, num_args));
+        }
+        let num_results = func
+            .dfg
+            .make_inst_results_for_parser(inst, ctrl_typevar, &results);
+        if num_results != results.len() {
+            return Err(format!(
+                "{} has {} results, but {} were given",
+                opcode,
+                num_results,
+                results.len()
+            ));
+        }
+        func.layout.append_inst(inst, ebb);
+
+        if let Some(ref srcloc) = self.srcloc {
+            func.srclocs[inst] = parse_srcloc(srcloc)?;
+        }
+        Ok(())
+    }
+}
+
+/// Serializable version of Cranelift IR Ebb parameters.
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct SerEbbParam {
+    pub value: String,
+    pub value_type: String,
 }
 
 /// Serializable version of Cranelift IR Ebbs.
 #[derive(Clone, Deserialize, Serialize, Debug)]
 pub struct SerEbb {
     pub ebb: String,
-    pub params: Vec<String>,
+    pub params: Vec<SerEbbParam>,
     pub insts: Vec<SerInst>,
 }
 
@@ -769,19 +1606,30 @@ pub fn populate_inst(func: &Function, ebb: Ebb) -> Vec<SerInst> {
 }
 
 /// Translating Ebb parameters into serializable parameters.
-pub fn populate_params(func: &Function, ebb: Ebb) -> Vec<String> {
-    let mut ser_vec: Vec<String> = Vec::new();
+pub fn populate_params(func: &Function, ebb: Ebb) -> Vec<SerEbbParam> {
+    let mut ser_vec: Vec<SerEbbParam> = Vec::new();
     let parameters = func.dfg.ebb_params(ebb);
     for param in parameters {
-        ser_vec.push(param.to_string());
+        ser_vec.push(SerEbbParam {
+            value: param.to_string(),
+            value_type: func.dfg.value_type(*param).to_string(),
+        });
     }
     ser_vec
 }
 
+/// Serializable value alias, such as `v3 -> v1`.
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct SerValueAlias {
+    pub value: String,
+    pub original: String,
+}
+
 /// Serializable Data Flow Graph.
 #[derive(Deserialize, Serialize, Debug)]
 pub struct SerDataFlowGraph {
     ebbs: Vec<SerEbb>,
+    aliases: Vec<SerValueAlias>,
 }
 
 /// Serialize all parts of the Cranelift Ebb data structure, this includes name, parameters, and
@@ -797,17 +1645,92 @@ pub fn populate_ebbs(func: &Function) -> Vec<SerEbb> {
     ebb_vec
 }
 
+/// Serialize all the value aliases of the function.
+pub fn populate_aliases(func: &Function) -> Vec<SerValueAlias> {
+    let mut alias_vec: Vec<SerValueAlias> = Vec::new();
+    for value in func.dfg.values() {
+        if let Some(original) = func.dfg.value_alias_dest_for_serialization(value) {
+            alias_vec.push(SerValueAlias {
+                value: value.to_string(),
+                original: original.to_string(),
+            });
+        }
+    }
+    alias_vec
+}
+
 /// Serializable Cranelift IR data flow graph, including all ebbs.
 impl SerDataFlowGraph {
     pub fn create_new(func: &Function) -> Self {
         Self {
             ebbs: populate_ebbs(func),
+            aliases: populate_aliases(func),
         }
     }
 
     pub fn new(func: &Function) -> Self {
         Self::create_new(func)
     }
+
+    /// Add the ebbs, instructions and value aliases to the layout and data flow graph of `func`.
+    fn append_to(&self, func: &mut Function) -> Result<(), String> {
+        // Insert all the ebbs first, since branches can refer to the ebbs which follow them.
+        for ser_ebb in &self.ebbs {
+            let ebb = parse_ebb(&ser_ebb.ebb)?;
+            while func.dfg.num_ebbs() <= ebb.index() {
+                func.dfg.make_ebb();
+            }
+            if func.layout.is_ebb_inserted(ebb) {
+                return Err(format!("ebb {} is defined more than once", ebb));
+            }
+            func.layout.append_ebb(ebb);
+        }
+
+        for ser_ebb in &self.ebbs {
+            let ebb = parse_ebb(&ser_ebb.ebb)?;
+            for param in &ser_ebb.params {
+                let value = parse_value(&param.value)?;
+                let ty = parse_type(&param.value_type)?;
+                define_value(value, func)?;
+                func.dfg.append_ebb_param_for_parser(ebb, ty, value);
+            }
+            for ser_inst in &ser_ebb.insts {
+                ser_inst.append_to(ebb, func)?;
+            }
+        }
+
+        // Aliases can refer to aliases defined after them, so the original values are only
+        // checked once all the aliases exist.
+        let mut aliases = HashSet::new();
+        for alias in &self.aliases {
+            let value = parse_value(&alias.value)?;
+            let original = parse_value(&alias.original)?;
+            if !aliases.insert(value) {
+                return Err(format!("value {} is defined more than once", value));
+            }
+            define_value(value, func)?;
+            reserve_value(original, func);
+            func.dfg.make_value_alias_for_serialization(original, value);
+        }
+        for &value in &aliases {
+            let original = func
+                .dfg
+                .value_alias_dest_for_serialization(value)
+                .expect("value alias");
+            if !aliases.contains(&original) && !func.dfg.value_is_valid_for_parser(original) {
+                return Err(format!(
+                    "value {} is an alias of undefined {}",
+                    value, original
+                ));
+            }
+        }
+        for &value in &aliases {
+            if !func.dfg.set_alias_type_for_parser(value) {
+                return Err(format!("alias cycle involving {}", value));
+            }
+        }
+        Ok(())
+    }
 }
 
 /// Serializable signature including function parameters and returns.
@@ -815,6 +1738,7 @@ impl SerDataFlowGraph {
 pub struct SerSignature {
     pub func_params: Vec<String>,
     pub func_returns: Vec<String>,
+    pub call_conv: String,
 }
 
 impl SerSignature {
@@ -831,35 +1755,428 @@ impl SerSignature {
         Self {
             func_params: params_vec,
             func_returns: returns_vec,
+            call_conv: sig.call_conv.to_string(),
         }
     }
 
     pub fn new(func: &Function) -> Self {
         Self::create_new(&func.signature)
     }
+
+    /// Reconstruct the Cranelift IR signature.
+    pub fn to_signature(&self) -> Result<Signature, String> {
+        let call_conv: CallConv = parse(&self.call_conv, "calling convention")?;
+        let mut sig = Signature::new(call_conv);
+        for param in &self.func_params {
+            sig.params.push(parse_abi_param(param)?);
+        }
+        for ret in &self.func_returns {
+            sig.returns.push(parse_abi_param(ret)?);
+        }
+        Ok(sig)
+    }
+}
+
+/// Serializable stack slot declaration.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SerStackSlot {
+    pub name: String,
+    pub kind: String,
+    pub size: u32,
+    pub offset: Option<i32>,
+}
+
+impl SerStackSlot {
+    pub fn new(ss: StackSlot, data: &StackSlotData) -> Self {
+        Self {
+            name: ss.to_string(),
+            kind: data.kind.to_string(),
+            size: data.size,
+            offset: data.offset,
+        }
+    }
+
+    fn add_to(&self, func: &mut Function) -> Result<(), String> {
+        let ss: StackSlot = parse_entity(&self.name, "ss")?;
+        let kind: StackSlotKind = parse(&self.kind, "stack slot kind")?;
+        while func.stack_slots.next_key().index() <= ss.index() {
+            func.create_stack_slot(StackSlotData::new(StackSlotKind::SpillSlot, 0));
+        }
+        func.stack_slots[ss] = StackSlotData {
+            kind,
+            size: self.size,
+            offset: self.offset,
+        };
+        Ok(())
+    }
+}
+
+/// Serializable version of `GlobalValueData`.
+#[derive(Serialize, Deserialize, Debug)]
+pub enum SerGlobalValueData {
+    VMContext,
+    Load {
+        base: String,
+        offset: String,
+        global_type: String,
+        readonly: bool,
+    },
+    IAddImm {
+        base: String,
+        offset: String,
+        global_type: String,
+    },
+    Symbol {
+        name: String,
+        offset: String,
+        colocated: bool,
+    },
+}
+
+/// Serializable global value declaration.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SerGlobalValue {
+    pub name: String,
+    pub data: SerGlobalValueData,
+}
+
+impl SerGlobalValue {
+    pub fn new(gv: GlobalValue, data: &GlobalValueData) -> Self {
+        let data = match *data {
+            GlobalValueData::VMContext => SerGlobalValueData::VMContext,
+            GlobalValueData::Load {
+                base,
+                offset,
+                global_type,
+                readonly,
+            } => SerGlobalValueData::Load {
+                base: base.to_string(),
+                offset: offset.to_string(),
+                global_type: global_type.to_string(),
+                readonly,
+            },
+            GlobalValueData::IAddImm {
+                base,
+                offset,
+                global_type,
+            } => SerGlobalValueData::IAddImm {
+                base: base.to_string(),
+                offset: offset.to_string(),
+                global_type: global_type.to_string(),
+            },
+            GlobalValueData::Symbol {
+                ref name,
+                offset,
+                colocated,
+            } => SerGlobalValueData::Symbol {
+                name: name.to_string(),
+                offset: offset.to_string(),
+                colocated,
+            },
+        };
+        Self {
+            name: gv.to_string(),
+            data,
+        }
+    }
+
+    /// Reconstruct the global value data, whose base must already exist in `func`.
+    fn to_data(&self, func: &Function) -> Result<GlobalValueData, String> {
+        Ok(match self.data {
+            SerGlobalValueData::VMContext => GlobalValueData::VMContext,
+            SerGlobalValueData::Load {
+                ref base,
+                ref offset,
+                ref global_type,
+                readonly,
+            } => GlobalValueData::Load {
+                base: parse_global_value(base, func)?,
+                offset: parse_offset32(offset)?,
+                global_type: parse_type(global_type)?,
+                readonly,
+            },
+            SerGlobalValueData::IAddImm {
+                ref base,
+                ref offset,
+                ref global_type,
+            } => GlobalValueData::IAddImm {
+                base: parse_global_value(base, func)?,
+                offset: parse(offset, "offset")?,
+                global_type: parse_type(global_type)?,
+            },
+            SerGlobalValueData::Symbol {
+                ref name,
+                ref offset,
+                colocated,
+            } => GlobalValueData::Symbol {
+                name: parse_external_name(name)?,
+                offset: parse(offset, "offset")?,
+                colocated,
+            },
+        })
+    }
+}
+
+/// Serializable version of `HeapStyle`.
+#[derive(Serialize, Deserialize, Debug)]
+pub enum SerHeapStyle {
+    Dynamic { bound_gv: String },
+    Static { bound: String },
+}
+
+/// Serializable heap declaration.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SerHeap {
+    pub name: String,
+    pub base: String,
+    pub min_size: String,
+    pub offset_guard_size: String,
+    pub style: SerHeapStyle,
+    pub index_type: String,
+}
+
+impl SerHeap {
+    pub fn new(heap: Heap, data: &HeapData) -> Self {
+        Self {
+            name: heap.to_string(),
+            base: data.base.to_string(),
+            min_size: data.min_size.to_string(),
+            offset_guard_size: data.offset_guard_size.to_string(),
+            style: match data.style {
+                HeapStyle::Dynamic { bound_gv } => SerHeapStyle::Dynamic {
+                    bound_gv: bound_gv.to_string(),
+                },
+                HeapStyle::Static { bound } => SerHeapStyle::Static {
+                    bound: bound.to_string(),
+                },
+            },
+            index_type: data.index_type.to_string(),
+        }
+    }
+
+    fn add_to(&self, func: &mut Function) -> Result<(), String> {
+        let heap: Heap = parse_entity(&self.name, "heap")?;
+        let data = HeapData {
+            base: parse_entity(&self.base, "gv")?,
+            min_size: parse(&self.min_size, "heap size")?,
+            offset_guard_size: parse(&self.offset_guard_size, "offset guard size")?,
+            style: match self.style {
+                SerHeapStyle::Dynamic { ref bound_gv } => HeapStyle::Dynamic {
+                    bound_gv: parse_entity(bound_gv, "gv")?,
+                },
+                SerHeapStyle::Static { ref bound } => HeapStyle::Static {
+                    bound: parse(bound, "heap bound")?,
+                },
+            },
+            index_type: parse_type(&self.index_type)?,
+        };
+        while func.heaps.next_key().index() <= heap.index() {
+            func.create_heap(HeapData {
+                base: GlobalValue::reserved_value(),
+                min_size: Uimm64::new(0),
+                offset_guard_size: Uimm64::new(0),
+                style: HeapStyle::Static {
+                    bound: Uimm64::new(0),
+                },
+                index_type: types::INVALID,
+            });
+        }
+        func.heaps[heap] = data;
+        Ok(())
+    }
+}
+
+/// Serializable table declaration.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SerTable {
+    pub name: String,
+    pub base_gv: String,
+    pub min_size: String,
+    pub bound_gv: String,
+    pub element_size: String,
+    pub index_type: String,
+}
+
+impl SerTable {
+    pub fn new(table: Table, data: &TableData) -> Self {
+        Self {
+            name: table.to_string(),
+            base_gv: data.base_gv.to_string(),
+            min_size: data.min_size.to_string(),
+            bound_gv: data.bound_gv.to_string(),
+            element_size: data.element_size.to_string(),
+            index_type: data.index_type.to_string(),
+        }
+    }
+
+    fn add_to(&self, func: &mut Function) -> Result<(), String> {
+        let table: Table = parse_entity(&self.name, "table")?;
+        let data = TableData {
+            base_gv: parse_entity(&self.base_gv, "gv")?,
+            min_size: parse(&self.min_size, "table size")?,
+            bound_gv: parse_entity(&self.bound_gv, "gv")?,
+            element_size: parse(&self.element_size, "element size")?,
+            index_type: parse_type(&self.index_type)?,
+        };
+        while func.tables.next_key().index() <= table.index() {
+            func.create_table(TableData {
+                base_gv: GlobalValue::reserved_value(),
+                min_size: Uimm64::new(0),
+                bound_gv: GlobalValue::reserved_value(),
+                element_size: Uimm64::new(0),
+                index_type: types::INVALID,
+            });
+        }
+        func.tables[table] = data;
+        Ok(())
+    }
+}
+
+/// Serializable signature declaration, for indirect calls and external functions.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SerSigRef {
+    pub name: String,
+    pub signature: SerSignature,
+}
+
+/// Serializable external function declaration.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SerExtFunc {
+    pub name: String,
+    pub func_name: String,
+    pub signature: String,
+    pub colocated: bool,
+}
+
+impl SerExtFunc {
+    pub fn new(fn_: FuncRef, data: &ExtFuncData) -> Self {
+        Self {
+            name: fn_.to_string(),
+            func_name: data.name.to_string(),
+            signature: data.signature.to_string(),
+            colocated: data.colocated,
+        }
+    }
+
+    fn add_to(&self, func: &mut Function) -> Result<(), String> {
+        let fn_: FuncRef = parse_entity(&self.name, "fn")?;
+        let data = ExtFuncData {
+            name: parse_external_name(&self.func_name)?,
+            signature: parse_sig_ref(&self.signature, func)?,
+            colocated: self.colocated,
+        };
+        while func.dfg.ext_funcs.next_key().index() <= fn_.index() {
+            func.import_function(ExtFuncData {
+                name: ExternalName::testcase(""),
+                signature: SigRef::reserved_value(),
+                colocated: false,
+            });
+        }
+        func.dfg.ext_funcs[fn_] = data;
+        Ok(())
+    }
+}
+
+/// Serializable jump table declaration.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SerJumpTable {
+    pub name: String,
+    pub ebbs: Vec<String>,
+}
+
+impl SerJumpTable {
+    pub fn new(jt: JumpTable, data: &JumpTableData) -> Self {
+        Self {
+            name: jt.to_string(),
+            ebbs: data.iter().map(|ebb| ebb.to_string()).collect(),
+        }
+    }
+
+    fn add_to(&self, func: &mut Function) -> Result<(), String> {
+        let jt: JumpTable = parse_entity(&self.name, "jt")?;
+        let mut data = JumpTableData::with_capacity(self.ebbs.len());
+        for ebb in &self.ebbs {
+            data.push_entry(parse_ebb(ebb)?);
+        }
+        while func.jump_tables.next_key().index() <= jt.index() {
+            func.create_jump_table(JumpTableData::new());
+        }
+        func.jump_tables[jt] = data;
+        Ok(())
+    }
 }
 
-/// Serializable Function type, including name, signature, global values, and data flow graph.
+/// Serializable Function type, including name, signature, the entities declared in the preamble,
+/// and data flow graph.
+///
+/// Encodings and value locations depend on the target ISA, and aren't serialized.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SerFunction {
     pub name: String,
     pub signature: SerSignature,
-    pub globals: Vec<String>,
+    pub stack_slots: Vec<SerStackSlot>,
+    pub globals: Vec<SerGlobalValue>,
+    pub heaps: Vec<SerHeap>,
+    pub tables: Vec<SerTable>,
+    pub signatures: Vec<SerSigRef>,
+    pub ext_funcs: Vec<SerExtFunc>,
+    pub jump_tables: Vec<SerJumpTable>,
     pub dfg: SerDataFlowGraph,
 }
 
 impl SerFunction {
-    /// Creates serializable global values, as well as the functions signature, name, and data flow
-    /// graph.
+    /// Creates serializable preamble entities, as well as the functions signature, name, and data
+    /// flow graph.
     fn create_new(func: &Function) -> Self {
-        let mut global_vec: Vec<String> = Vec::new();
-        for (glob_name, _) in func.global_values.iter() {
-            global_vec.push(glob_name.to_string());
+        let mut stack_slot_vec: Vec<SerStackSlot> = Vec::new();
+        for (ss, data) in func.stack_slots.iter() {
+            stack_slot_vec.push(SerStackSlot::new(ss, data));
+        }
+        let mut global_vec: Vec<SerGlobalValue> = Vec::new();
+        for (gv, data) in func.global_values.iter() {
+            global_vec.push(SerGlobalValue::new(gv, data));
+        }
+        // Placeholder heaps and tables, which only exist to fill gaps in the numbering, have an
+        // invalid index type and aren't part of the function text.
+        let mut heap_vec: Vec<SerHeap> = Vec::new();
+        for (heap, data) in func.heaps.iter() {
+            if !data.index_type.is_invalid() {
+                heap_vec.push(SerHeap::new(heap, data));
+            }
+        }
+        let mut table_vec: Vec<SerTable> = Vec::new();
+        for (table, data) in func.tables.iter() {
+            if !data.index_type.is_invalid() {
+                table_vec.push(SerTable::new(table, data));
+            }
+        }
+        let mut sig_vec: Vec<SerSigRef> = Vec::new();
+        for (sig, data) in func.dfg.signatures.iter() {
+            sig_vec.push(SerSigRef {
+                name: sig.to_string(),
+                signature: SerSignature::create_new(data),
+            });
+        }
+        let mut ext_func_vec: Vec<SerExtFunc> = Vec::new();
+        for (fn_, data) in func.dfg.ext_funcs.iter() {
+            if data.signature != SigRef::reserved_value() {
+                ext_func_vec.push(SerExtFunc::new(fn_, data));
+            }
+        }
+        let mut jump_table_vec: Vec<SerJumpTable> = Vec::new();
+        for (jt, data) in func.jump_tables.iter() {
+            jump_table_vec.push(SerJumpTable::new(jt, data));
         }
         Self {
             name: func.name.to_string(),
             signature: SerSignature::new(&func),
+            stack_slots: stack_slot_vec,
             globals: global_vec,
+            heaps: heap_vec,
+            tables: table_vec,
+            signatures: sig_vec,
+            ext_funcs: ext_func_vec,
+            jump_tables: jump_table_vec,
             dfg: SerDataFlowGraph::new(&func),
         }
     }
@@ -867,6 +2184,75 @@ impl SerFunction {
     pub fn new(func: &Function) -> Self {
         Self::create_new(func)
     }
+
+    /// Reconstruct the Cranelift IR function.
+    pub fn to_function(&self) -> Result<Function, String> {
+        self.build_function()
+            .map_err(|err| format!("function {}: {}", self.name, err))
+    }
+
+    fn build_function(&self) -> Result<Function, String> {
+        let name = parse_external_name(&self.name)?;
+        let mut func = Function::with_name_signature(name, self.signature.to_signature()?);
+
+        for ss in &self.stack_slots {
+            ss.add_to(&mut func)?;
+        }
+
+        // Global values can refer to global values declared after them, so create them all
+        // before reconstructing their data.
+        let mut global_values = Vec::new();
+        for ser_gv in &self.globals {
+            let gv: GlobalValue = parse_entity(&ser_gv.name, "gv")?;
+            while func.global_values.next_key().index() <= gv.index() {
+                func.create_global_value(GlobalValueData::Symbol {
+                    name: ExternalName::testcase(""),
+                    offset: Imm64::new(0),
+                    colocated: false,
+                });
+            }
+            global_values.push(gv);
+        }
+        for (ser_gv, &gv) in self.globals.iter().zip(&global_values) {
+            func.global_values[gv] = ser_gv.to_data(&func)?;
+        }
+
+        for heap in &self.heaps {
+            heap.add_to(&mut func)?;
+        }
+        for table in &self.tables {
+            table.add_to(&mut func)?;
+        }
+        for ser_sig in &self.signatures {
+            let sig: SigRef = parse_entity(&ser_sig.name, "sig")?;
+            let data = ser_sig.signature.to_signature()?;
+            while func.dfg.signatures.next_key().index() <= sig.index() {
+                func.import_signature(Signature::new(data.call_conv));
+            }
+            func.dfg.signatures[sig] = data;
+        }
+        for ext_func in &self.ext_funcs {
+            ext_func.add_to(&mut func)?;
+        }
+        for jt in &self.jump_tables {
+            jt.add_to(&mut func)?;
+        }
+
+        self.dfg.append_to(&mut func)?;
+
+        // Now that all the values are defined, make sure the instructions only use those.
+        for ebb in func.layout.ebbs() {
+            for inst in func.layout.ebb_insts(ebb) {
+                for &arg in func.dfg.inst_args(inst) {
+                    if !func.dfg.value_is_valid_for_parser(arg) {
+                        return Err(format!("undefined operand value {}", arg));
+                    }
+                }
+            }
+        }
+
+        Ok(func)
+    }
 }
 
 /// Must have SerObj for deserialization, contains all of the functions from inside the file to be
@@ -889,4 +2275,261 @@ impl SerObj {
         }
         Self::create_new(func_vec)
     }
+
+    /// Reconstruct all the Cranelift IR functions.
+    pub fn to_functions(&self) -> Result<Vec<Function>, String> {
+        self.functions
+            .iter()
+            .map(SerFunction::to_function)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cranelift_codegen::ir::ArgumentPurpose;
+    use cranelift_codegen::write_function;
+    use cranelift_reader::parse_functions;
+
+    fn write(func: &Function) -> String {
+        let mut text = String::new();
+        write_function(&mut text, func, &None.into()).unwrap();
+        text
+    }
+
+    /// Serialize the functions in `text` to JSON and back, and check that they're printed the
+    /// same.
+    fn round_trip(text: &str) {
+        let funcs = parse_functions(text).unwrap();
+        let json = serde_json::to_string(&SerObj::new(&funcs)).unwrap();
+        let de: SerObj = serde_json::from_str(&json).unwrap();
+        let new_funcs = de.to_functions().unwrap();
+        assert_eq!(funcs.len(), new_funcs.len());
+        for (func, new_func) in funcs.iter().zip(&new_funcs) {
+            assert_eq!(write(func), write(new_func));
+        }
+    }
+
+    fn deserialize_error(text: &str, edit: fn(&mut SerFunction)) -> String {
+        let funcs = parse_functions(text).unwrap();
+        let mut ser = SerObj::new(&funcs);
+        edit(&mut ser.functions[0]);
+        ser.to_functions().unwrap_err()
+    }
+
+    #[test]
+    fn preamble() {
+        round_trip(
+            "function u0:3(i64 vmctx, i32 sext, i64 sret) -> i64 uext baldrdash_system_v {
+                ss0 = explicit_slot 8
+                ss1 = spill_slot 16, offset -32
+                ss5 = incoming_arg 4, offset 8
+                gv0 = vmctx
+                gv1 = load.i64 notrap aligned readonly gv0+16
+                gv2 = iadd_imm.i64 gv1, -4
+                gv3 = symbol colocated %memcpy+8
+                gv4 = symbol u1:2
+                heap0 = static gv1, min 0x0001_0000, bound 0x0001_0000_0000, offset_guard 0x8000_0000, index_type i32
+                heap2 = dynamic gv2, min 0, bound gv1, offset_guard 4096, index_type i64
+                table1 = dynamic gv1, min 10, bound gv2, element_size 8, index_type i32
+                sig0 = (i32, f64) -> b1 system_v
+                sig3 = () fast
+                fn0 = colocated %callee sig0
+                fn2 = u0:7 sig3
+                jt0 = jump_table [ebb1, ebb2, ebb1]
+
+            ebb0(v0: i64, v1: i32, v2: i64):
+                v3 = iconst.i32 0
+                v4 = heap_addr.i64 heap0, v1, 8
+                v5 = table_addr.i64 table1, v3, +4
+                v6 = global_value.i64 gv3
+                br_table v1, ebb2, jt0
+
+            ebb1:
+                v7 = stack_addr.i64 ss1
+                return v7
+
+            ebb2:
+                return v4
+            }",
+        );
+    }
+
+    #[test]
+    fn instructions() {
+        round_trip(
+            "function %insts(i32, i64, f32, f64) -> i32 system_v {
+                ss0 = explicit_slot 16
+                sig0 = (i32) -> i32 system_v
+                fn0 = %other sig0
+                jt0 = jump_table [ebb2]
+
+            ebb0(v0: i32, v1: i64, v2: f32, v3: f64):
+                v4 = iconst.i32 -7
+                v5 = iadd_imm v0, 0xffff_ffff
+                v6 = f32const 0x1.800000p1
+                v7 = f64const -0x1.0000000000000p0
+                v8 = bconst.b1 true
+                v9 = iadd v0, v4
+                v10 = select v8, v0, v9
+                v11 = icmp slt v0, v9
+                v12 = icmp_imm eq v0, 10
+                v13 = fcmp uno v2, v2
+                v14 = ifcmp v0, v9
+                v15 = selectif.i32 ult v14, v0, v9
+                v16 = trueif ugt v14
+                v17, v18 = isplit v1
+                v19 = vconst.i8x16 0x00010203_04050607_08090a0b_0c0d0e0f
+                v20 = shuffle v19, v19, 0x0f0e0d0c_0b0a0908_07060504_03020100
+                v21 = extractlane v20, 3
+                v22 = insertlane v20, 0, v21
+                v23 = load.i32 notrap aligned v1+8
+                store notrap v0, v1-4
+                v24 = load_complex.i32 v1+v1
+                store_complex v0, v1+v1+16
+                v25 = atomic_cas.i32 v1, v0, v9
+                v26 = stack_load.i32 ss0+4
+                stack_store v26, ss0
+                v27 = call fn0(v0)
+                v28 = func_addr.i64 fn0
+                v29 = call_indirect sig0, v28(v27)
+                v30 = fcvt_from_sint.f64 v0
+                trapnz v0, user3
+                trapif sgt v14, int_ovf
+                v31 = ffcmp v3, v30
+                trapff lt v31, heap_oob
+                v32 = copy v0
+                v33 = null.r64
+                brz v0, ebb1(v9)
+                brif eq v14, ebb1(v0)
+                brff ge v31, ebb1(v0)
+                br_icmp sle v0, v9, ebb1(v9)
+                jump ebb1(v29)
+
+            ebb1(v40: i32):
+                v41 = iconst.i64 0
+                v42 = jump_table_entry.i64 v41, v28, 4, jt0
+                v43 = jump_table_base.i64 jt0
+                indirect_jump_table_br v42, jt0
+
+            ebb2:
+                trap unreachable
+            }",
+        );
+    }
+
+    #[test]
+    fn aliases_and_srclocs() {
+        round_trip(
+            "function %aliases(i32) -> i32 {
+            ebb0(v0: i32):
+                @0012 v1 = iadd_imm v0, 1
+                v3 -> v1
+                v4 -> v3
+                @00ab v2 = iadd v4, v1
+                v5 = imul v2, v3
+                return v5
+            }",
+        );
+    }
+
+    #[test]
+    fn multiple_functions() {
+        round_trip(
+            "function %a() {
+            ebb0:
+                return
+            }
+
+            function %b(i8) -> i8 {
+            ebb3(v3: i8):
+                jump ebb1(v3)
+
+            ebb1(v7: i8):
+                return v7
+            }",
+        );
+    }
+
+    #[test]
+    fn abi_params() {
+        let mut param = AbiParam::special(types::I64, ArgumentPurpose::StructReturn);
+        param.location = ArgumentLoc::Reg(5);
+        assert_eq!(param.to_string(), "i64 sret [%5]");
+        assert_eq!(parse_abi_param(&param.to_string()), Ok(param));
+
+        let mut param = AbiParam::new(types::I8).uext();
+        param.location = ArgumentLoc::Stack(-8);
+        assert_eq!(param.to_string(), "i8 uext [-8]");
+        assert_eq!(parse_abi_param(&param.to_string()), Ok(param));
+
+        assert_eq!(
+            parse_abi_param("i32 bogus"),
+            Err("invalid argument purpose 'bogus'".to_string())
+        );
+    }
+
+    #[test]
+    fn undefined_operand() {
+        let err = deserialize_error(
+            "function %f(i32) -> i32 {
+            ebb0(v0: i32):
+                return v0
+            }",
+            |func| func.dfg.ebbs[0].params.clear(),
+        );
+        assert_eq!(err, "function %f: undefined operand value v0");
+    }
+
+    #[test]
+    fn duplicate_value() {
+        let err = deserialize_error(
+            "function %f(i32) -> i32 {
+            ebb0(v0: i32):
+                v1 = iadd v0, v0
+                return v1
+            }",
+            |func| func.dfg.ebbs[0].insts[0].results[0] = "v0".to_string(),
+        );
+        assert_eq!(err, "function %f: value v0 is defined more than once");
+    }
+
+    #[test]
+    fn alias_cycle() {
+        let err = deserialize_error(
+            "function %f(i32) -> i32 {
+            ebb0(v0: i32):
+                v1 = iadd v0, v0
+                v2 -> v1
+                return v2
+            }",
+            |func| {
+                func.dfg.ebbs[0].insts[0].results[0] = "v3".to_string();
+                func.dfg.aliases.push(SerValueAlias {
+                    value: "v1".to_string(),
+                    original: "v2".to_string(),
+                });
+            },
+        );
+        assert!(err.starts_with("function %f: alias cycle involving"));
+    }
+
+    #[test]
+    fn wrong_format() {
+        let err = deserialize_error(
+            "function %f(i32) -> i32 {
+            ebb0(v0: i32):
+                v1 = iadd v0, v0
+                return v1
+            }",
+            |func| {
+                func.dfg.ebbs[0].insts[0].inst_data = SerInstData::Unary {
+                    opcode: "iadd".to_string(),
+                    arg: "v0".to_string(),
+                }
+            },
+        );
+        assert_eq!(err, "function %f: opcode iadd doesn't use the Unary format");
+    }
 }
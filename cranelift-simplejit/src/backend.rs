@@ -1,13 +1,19 @@
 //! Defines `SimpleJITBackend`.
 
 use crate::memory::Memory;
+use crate::stubs;
+use crate::unwind::{host_unwind_kind, UnwindInfo, UnwindInfoSink, UnwindRegistry};
 use cranelift_codegen::binemit::{
     Addend, CodeOffset, NullTrapSink, Reloc, RelocSink, Stackmap, StackmapSink,
 };
+#[cfg(feature = "cache")]
+use cranelift_codegen::cache::CompilationCache;
+use cranelift_codegen::entity::SecondaryMap;
 use cranelift_codegen::isa::TargetIsa;
-use cranelift_codegen::{self, ir, settings};
+use cranelift_codegen::{self, ir, settings, Context};
 use cranelift_module::{
-    Backend, DataContext, DataDescription, Init, Linkage, ModuleNamespace, ModuleResult,
+    Backend, DataContext, DataDescription, DataId, FuncId, Init, Linkage, ModuleError,
+    ModuleNamespace, ModuleResult,
 };
 use cranelift_native;
 #[cfg(not(windows))]
@@ -15,20 +21,35 @@ use libc;
 use std::collections::HashMap;
 use std::ffi::CString;
 use std::io::Write;
+use std::mem;
 use std::ptr;
-use target_lexicon::PointerWidth;
+use std::rc::Rc;
+#[cfg(feature = "cache")]
+use std::sync::Arc;
+use std::sync::Mutex;
+use target_lexicon::{Architecture, PointerWidth};
 #[cfg(windows)]
 use winapi;
 
 const EXECUTABLE_DATA_ALIGNMENT: u8 = 0x10;
 const WRITABLE_DATA_ALIGNMENT: u8 = 0x8;
 const READONLY_DATA_ALIGNMENT: u8 = 0x1;
+const UNWIND_INFO_ALIGNMENT: usize = 0x8;
+
+/// Produces the body of a lazily compiled function.
+type LazyFunctionGenerator = Box<dyn FnMut(FuncId, &mut Context)>;
+
+/// Handles an error compiling a lazily compiled function, and returns the code to run instead.
+type LazyCompilationErrorHandler = Box<dyn FnMut(FuncId, ModuleError) -> *const u8>;
 
 /// A builder for `SimpleJITBackend`.
 pub struct SimpleJITBuilder {
     isa: Box<dyn TargetIsa>,
     symbols: HashMap<String, *const u8>,
     libcall_names: Box<dyn Fn(ir::LibCall) -> String>,
+    lazy_function_generator: Option<LazyFunctionGenerator>,
+    lazy_compilation_error_handler: Option<LazyCompilationErrorHandler>,
+    hotswap: bool,
 }
 
 impl SimpleJITBuilder {
@@ -69,6 +90,9 @@ impl SimpleJITBuilder {
             isa,
             symbols,
             libcall_names,
+            lazy_function_generator: None,
+            lazy_compilation_error_handler: None,
+            hotswap: false,
         }
     }
 
@@ -107,6 +131,50 @@ impl SimpleJITBuilder {
         }
         self
     }
+
+    /// Set the generator of the functions defined with `Module::define_function_lazily`.
+    ///
+    /// The first time such a function is called, the generator is given its id and a `Context`
+    /// whose function has the declared name and signature, and must fill in the function body.
+    /// The function is then compiled, linked against the rest of the module, and run.
+    ///
+    /// The first calls of lazily compiled functions must not happen concurrently, nor from the
+    /// generator itself.
+    pub fn lazy_function_generator<F>(&mut self, generator: F) -> &Self
+    where
+        F: FnMut(FuncId, &mut Context) + 'static,
+    {
+        self.lazy_function_generator = Some(Box::new(generator));
+        self
+    }
+
+    /// Set the handler of the errors compiling the bodies of lazily compiled functions.
+    ///
+    /// When the body of such a function fails to compile, the handler is given its id and the
+    /// error, and returns the address of code with the same signature, which is run in place of
+    /// the function. The function is compiled again the next time it's called. Without a handler,
+    /// the call traps.
+    pub fn lazy_compilation_error_handler<F>(&mut self, handler: F) -> &Self
+    where
+        F: FnMut(FuncId, ModuleError) -> *const u8 + 'static,
+    {
+        self.lazy_compilation_error_handler = Some(Box::new(handler));
+        self
+    }
+
+    /// Enable or disable hot swapping, which allows replacing the definitions of functions with
+    /// `Module::redefine_function`.
+    ///
+    /// With hot swapping, functions are called through stubs jumping to the addresses held in
+    /// their GOT entries, and the code of each function is placed in pages of its own. When the
+    /// definitions are finalized, the GOT entries of the redefined functions are updated, and the
+    /// memory holding their old code is freed, so the old code must not be running anymore.
+    ///
+    /// Hot swapping is only supported on x86-64.
+    pub fn hotswap(&mut self, enabled: bool) -> &Self {
+        self.hotswap = enabled;
+        self
+    }
 }
 
 /// A `SimpleJITBackend` implements `Backend` and emits code and data into memory where it can be
@@ -114,12 +182,67 @@ impl SimpleJITBuilder {
 ///
 /// See the `SimpleJITBuilder` for a convenient way to construct `SimpleJITBackend` instances.
 pub struct SimpleJITBackend {
+    /// The runtime, which is shared with the product and the stubs of the lazily compiled
+    /// functions.
+    runtime: Rc<SimpleJITRuntime>,
+    code_memory: Memory,
+    readonly_memory: Memory,
+    writable_memory: Memory,
+    unwind_registry: UnwindRegistry,
+    /// The reference to the runtime held by the stubs of the lazily compiled functions, made by
+    /// `Rc::into_raw` when the first stub is created.
+    stub_runtime: Option<*const SimpleJITRuntime>,
+    /// Whether functions are defined so that they can be redefined.
+    hotswap: bool,
+    /// The code of the redefined functions, which is freed once the new code is published.
+    retired: Vec<*mut u8>,
+}
+
+/// The parts of a SimpleJIT module which are needed to compile functions lazily, once the module
+/// is finalized.
+///
+/// The stubs of the lazily compiled functions hold a reference to the runtime, which is only
+/// released when the module's memory is freed.
+pub struct SimpleJITRuntime {
     isa: Box<dyn TargetIsa>,
     symbols: HashMap<String, *const u8>,
     libcall_names: Box<dyn Fn(ir::LibCall) -> String>,
+    state: Mutex<RuntimeState>,
+}
+
+/// The mutable part of a `SimpleJITRuntime`.
+struct RuntimeState {
+    /// The declared functions and data objects, indexed by their ids.
+    functions: SecondaryMap<FuncId, SymbolDefinition>,
+    data_objects: SecondaryMap<DataId, SymbolDefinition>,
+    /// The functions which are compiled lazily.
+    lazy_functions: HashMap<FuncId, LazyFunction>,
+    /// The generator of the lazily compiled functions, unless it's running.
+    generator: Option<LazyFunctionGenerator>,
+    /// The handler of the errors compiling them, unless it's running.
+    error_handler: Option<LazyCompilationErrorHandler>,
+    /// The code trapping in place of the functions which failed to compile, once it's needed.
+    trap_code: Option<*const u8>,
+    /// The memory holding the lazily compiled functions.
     code_memory: Memory,
-    readonly_memory: Memory,
-    writable_memory: Memory,
+    unwind_registry: UnwindRegistry,
+    /// The cache of the lazily compiled functions.
+    #[cfg(feature = "cache")]
+    cache: Option<Arc<CompilationCache>>,
+}
+
+/// The name of a declared function or data object, and its address if it's defined.
+#[derive(Clone, Default)]
+struct SymbolDefinition {
+    name: String,
+    address: Option<*const u8>,
+}
+
+/// A function which is compiled the first time it's called.
+struct LazyFunction {
+    signature: ir::Signature,
+    /// The GOT entry used by the function's stub.
+    got: *mut *const u8,
 }
 
 /// A record of a relocation to perform.
@@ -141,15 +264,53 @@ pub struct SimpleJITCompiledFunction {
     code: *mut u8,
     size: usize,
     relocs: Vec<RelocRecord>,
+    unwind_info: Option<UnwindInfo>,
+    /// The GOT entry and the stub through which a hot swappable function is called.
+    got: Option<*mut *const u8>,
+    stub: Option<*const u8>,
+}
+
+impl SimpleJITCompiledFunction {
+    /// Get the address through which the function is called.
+    fn entry(&self) -> *const u8 {
+        self.stub.unwrap_or(self.code)
+    }
 }
 
 pub struct SimpleJITCompiledData {
     storage: *mut u8,
     size: usize,
     relocs: Vec<RelocRecord>,
+    writable: bool,
 }
 
-impl SimpleJITBackend {
+/// The memory mapped by a SimpleJIT module, in bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// The memory mapped for code, including stubs and lazily compiled functions.
+    pub code: usize,
+    /// The memory mapped for data objects and GOT entries.
+    pub data: usize,
+    /// The part of the mapped memory which was freed, and is kept to be reused.
+    pub reusable: usize,
+}
+
+impl MemoryUsage {
+    /// Get the memory usage of the given memories.
+    fn of(code: &[&Memory], data: &[&Memory]) -> Self {
+        Self {
+            code: code.iter().map(|memory| memory.mapped()).sum(),
+            data: data.iter().map(|memory| memory.mapped()).sum(),
+            reusable: code
+                .iter()
+                .chain(data)
+                .map(|memory| memory.reusable())
+                .sum(),
+        }
+    }
+}
+
+impl SimpleJITRuntime {
     fn lookup_symbol(&self, name: &str) -> *const u8 {
         match self.symbols.get(name) {
             Some(&ptr) => ptr,
@@ -157,6 +318,144 @@ impl SimpleJITBackend {
         }
     }
 
+    /// Get the address of `name`, when it's referenced by a lazily compiled function.
+    fn get_definition(&self, state: &RuntimeState, name: &ir::ExternalName) -> *const u8 {
+        let symbol = match *name {
+            ir::ExternalName::User {
+                namespace: 0,
+                index,
+            } => &state.functions[FuncId::from_u32(index)],
+            ir::ExternalName::User {
+                namespace: 1,
+                index,
+            } => &state.data_objects[DataId::from_u32(index)],
+            ir::ExternalName::LibCall(ref libcall) => {
+                let sym = (self.libcall_names)(*libcall);
+                return self.lookup_symbol(&sym);
+            }
+            _ => panic!("invalid ExternalName {}", name),
+        };
+        match symbol.address {
+            Some(address) => address,
+            None => self.lookup_symbol(&symbol.name),
+        }
+    }
+
+    /// Generate and compile the lazily compiled function `id`, and return the address of its
+    /// code.
+    pub(crate) fn compile_function(&self, id: FuncId) -> ModuleResult<*const u8> {
+        // The generator runs without holding the lock.
+        let (mut ctx, mut generator) = {
+            let mut state = self.state.lock().unwrap();
+            let mut ctx = Context::new();
+            ctx.func.name = id.into();
+            ctx.func.signature = state.lazy_functions[&id].signature.clone();
+            let generator = match state.generator.take() {
+                Some(generator) => generator,
+                None => {
+                    return Err(ModuleError::Backend(format!(
+                        "lazily compiled function {} called while generating another one",
+                        state.functions[id].name
+                    )))
+                }
+            };
+            (ctx, generator)
+        };
+        generator(id, &mut ctx);
+
+        let mut state = self.state.lock().unwrap();
+        state.generator = Some(generator);
+        let name = state.functions[id].name.clone();
+        #[cfg(feature = "cache")]
+        let result = match state.cache {
+            Some(ref cache) => ctx.compile_with_cache(&*self.isa, cache),
+            None => ctx.compile(&*self.isa),
+        };
+        #[cfg(not(feature = "cache"))]
+        let result = ctx.compile(&*self.isa);
+        let code_info = result.map_err(ModuleError::Compilation)?;
+
+        let state = &mut *state;
+        let code_memory = &mut state.code_memory;
+        let func = emit_function(&*self.isa, &name, &ctx, code_info.total_size, |size| {
+            code_memory
+                .allocate(size, EXECUTABLE_DATA_ALIGNMENT)
+                .expect("TODO: handle OOM etc.")
+        });
+        perform_relocations(&func, |name| self.get_definition(state, name));
+        if let Some(ref unwind_info) = func.unwind_info {
+            unsafe {
+                state
+                    .unwind_registry
+                    .register(func.code, func.size, unwind_info)
+            };
+        }
+        state.code_memory.set_readable_and_executable();
+
+        // Send the following calls to the compiled code.
+        unsafe { *state.lazy_functions[&id].got = func.code };
+        Ok(func.code)
+    }
+
+    /// Get the address of the code to run in place of the lazily compiled function `id`, which
+    /// failed to compile with `error`.
+    pub(crate) fn compilation_error(&self, id: FuncId, error: ModuleError) -> *const u8 {
+        // The handler runs without holding the lock.
+        let handler = self.state.lock().unwrap().error_handler.take();
+        if let Some(mut handler) = handler {
+            let code = handler(id, error);
+            self.state.lock().unwrap().error_handler = Some(handler);
+            return code;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        if let Some(code) = state.trap_code {
+            return code;
+        }
+        // ud2
+        let trap = [0x0f, 0x0b];
+        let code = state
+            .code_memory
+            .allocate(trap.len(), EXECUTABLE_DATA_ALIGNMENT)
+            .expect("TODO: handle OOM etc.");
+        unsafe { ptr::copy_nonoverlapping(trap.as_ptr(), code, trap.len()) };
+        state.code_memory.set_readable_and_executable();
+        state.trap_code = Some(code);
+        code
+    }
+}
+
+impl SimpleJITBackend {
+    /// Get the memory currently mapped by the module.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let state = self.runtime.state.lock().unwrap();
+        MemoryUsage::of(
+            &[&self.code_memory, &state.code_memory],
+            &[&self.readonly_memory, &self.writable_memory],
+        )
+    }
+
+    /// Whether functions can be called through stubs, which are only implemented for x86-64.
+    fn supports_stubs(&self) -> bool {
+        cfg!(target_arch = "x86_64") && self.isa().triple().architecture == Architecture::X86_64
+    }
+
+    /// Emit the function compiled in `ctx` to pages of its own, which can be freed once the
+    /// function is redefined.
+    fn emit_function_pages(
+        &mut self,
+        name: &str,
+        ctx: &cranelift_codegen::Context,
+        code_size: u32,
+    ) -> SimpleJITCompiledFunction {
+        let code_memory = &mut self.code_memory;
+        emit_function(&*self.runtime.isa, name, ctx, code_size, |size| {
+            code_memory
+                .allocate_pages(size)
+                .expect("TODO: handle OOM etc.")
+        })
+    }
+
     fn get_definition(
         &self,
         namespace: &ModuleNamespace<Self>,
@@ -167,20 +466,20 @@ impl SimpleJITBackend {
                 if namespace.is_function(name) {
                     let (def, name_str, _signature) = namespace.get_function_definition(&name);
                     match def {
-                        Some(compiled) => compiled.code,
-                        None => self.lookup_symbol(name_str),
+                        Some(compiled) => compiled.entry(),
+                        None => self.runtime.lookup_symbol(name_str),
                     }
                 } else {
                     let (def, name_str, _writable) = namespace.get_data_definition(&name);
                     match def {
                         Some(compiled) => compiled.storage,
-                        None => self.lookup_symbol(name_str),
+                        None => self.runtime.lookup_symbol(name_str),
                     }
                 }
             }
             ir::ExternalName::LibCall(ref libcall) => {
-                let sym = (self.libcall_names)(*libcall);
-                self.lookup_symbol(&sym)
+                let sym = (self.runtime.libcall_names)(*libcall);
+                self.runtime.lookup_symbol(&sym)
             }
             _ => panic!("invalid ExternalName {}", name),
         }
@@ -202,87 +501,198 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
     type FinalizedFunction = *const u8;
     type FinalizedData = (*mut u8, usize);
 
-    /// SimpleJIT emits code and data into memory as it processes them, so it
-    /// doesn't need to provide anything after the `Module` is complete.
-    type Product = ();
+    /// SimpleJIT emits code and data into memory as it processes them. The
+    /// product owns that memory, so that it can be freed.
+    type Product = SimpleJITProduct;
 
     /// Create a new `SimpleJITBackend`.
     fn new(builder: SimpleJITBuilder) -> Self {
+        let state = RuntimeState {
+            functions: SecondaryMap::new(),
+            data_objects: SecondaryMap::new(),
+            lazy_functions: HashMap::new(),
+            generator: builder.lazy_function_generator,
+            error_handler: builder.lazy_compilation_error_handler,
+            trap_code: None,
+            code_memory: Memory::new(),
+            unwind_registry: UnwindRegistry::new(),
+            #[cfg(feature = "cache")]
+            cache: None,
+        };
         Self {
-            isa: builder.isa,
-            symbols: builder.symbols,
-            libcall_names: builder.libcall_names,
+            runtime: Rc::new(SimpleJITRuntime {
+                isa: builder.isa,
+                symbols: builder.symbols,
+                libcall_names: builder.libcall_names,
+                state: Mutex::new(state),
+            }),
             code_memory: Memory::new(),
             readonly_memory: Memory::new(),
             writable_memory: Memory::new(),
+            unwind_registry: UnwindRegistry::new(),
+            stub_runtime: None,
+            hotswap: builder.hotswap,
+            retired: Vec::new(),
         }
     }
 
     fn isa(&self) -> &dyn TargetIsa {
-        &*self.isa
+        &*self.runtime.isa
+    }
+
+    #[cfg(feature = "cache")]
+    fn set_compilation_cache(&mut self, cache: Arc<CompilationCache>) {
+        self.runtime.state.lock().unwrap().cache = Some(cache);
     }
 
-    fn declare_function(&mut self, _name: &str, _linkage: Linkage) {
-        // Nothing to do.
+    fn declare_function(&mut self, id: FuncId, name: &str, _linkage: Linkage) {
+        let mut state = self.runtime.state.lock().unwrap();
+        state.functions[id].name = name.to_owned();
     }
 
     fn declare_data(
         &mut self,
-        _name: &str,
+        id: DataId,
+        name: &str,
         _linkage: Linkage,
         _writable: bool,
         _align: Option<u8>,
     ) {
-        // Nothing to do.
+        let mut state = self.runtime.state.lock().unwrap();
+        state.data_objects[id].name = name.to_owned();
     }
 
     fn define_function(
         &mut self,
+        id: FuncId,
         name: &str,
         ctx: &cranelift_codegen::Context,
         _namespace: &ModuleNamespace<Self>,
         code_size: u32,
     ) -> ModuleResult<Self::CompiledFunction> {
-        let size = code_size as usize;
-        let ptr = self
+        if !self.hotswap {
+            let code_memory = &mut self.code_memory;
+            let func = emit_function(&*self.runtime.isa, name, ctx, code_size, |size| {
+                code_memory
+                    .allocate(size, EXECUTABLE_DATA_ALIGNMENT)
+                    .expect("TODO: handle OOM etc.")
+            });
+            let mut state = self.runtime.state.lock().unwrap();
+            state.functions[id].address = Some(func.code);
+            return Ok(func);
+        }
+
+        if !self.supports_stubs() {
+            return Err(ModuleError::Backend(format!(
+                "can't define function {} for hot swapping on this architecture",
+                name
+            )));
+        }
+        let mut func = self.emit_function_pages(name, ctx, code_size);
+
+        // The GOT entry is set when the function is finalized.
+        let got = self
+            .writable_memory
+            .allocate(mem::size_of::<*const u8>(), WRITABLE_DATA_ALIGNMENT)
+            .expect("TODO: handle OOM etc.") as *mut *const u8;
+        let stub_code = stubs::stub_code(got);
+        let stub = self
             .code_memory
-            .allocate(size, EXECUTABLE_DATA_ALIGNMENT)
+            .allocate(stub_code.len(), EXECUTABLE_DATA_ALIGNMENT)
             .expect("TODO: handle OOM etc.");
+        unsafe {
+            ptr::copy_nonoverlapping(stub_code.as_ptr(), stub, stub_code.len());
+            *got = ptr::null();
+        }
 
-        if cfg!(target_os = "linux") && ::std::env::var_os("PERF_BUILDID_DIR").is_some() {
-            let mut map_file = ::std::fs::OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open(format!("/tmp/perf-{}.map", ::std::process::id()))
-                .unwrap();
+        func.got = Some(got);
+        func.stub = Some(stub);
+        let mut state = self.runtime.state.lock().unwrap();
+        state.functions[id].address = Some(stub);
+        Ok(func)
+    }
 
-            let _ = writeln!(map_file, "{:x} {:x} {}", ptr as usize, code_size, name);
+    fn define_function_lazily(
+        &mut self,
+        id: FuncId,
+        name: &str,
+        namespace: &ModuleNamespace<Self>,
+    ) -> ModuleResult<Self::CompiledFunction> {
+        if !self.supports_stubs() {
+            return Err(ModuleError::Backend(format!(
+                "can't compile function {} lazily on this architecture",
+                name
+            )));
+        }
+        let mut state = self.runtime.state.lock().unwrap();
+        if state.generator.is_none() {
+            return Err(ModuleError::Backend(format!(
+                "no lazy function generator to define function {}",
+                name
+            )));
         }
 
-        let mut reloc_sink = SimpleJITRelocSink::new();
-        // Ignore traps for now. For now, frontends should just avoid generating code
-        // that traps.
-        let mut trap_sink = NullTrapSink {};
-        let mut stackmap_sink = SimpleJITStackmapSink::new();
+        let got = self
+            .writable_memory
+            .allocate(mem::size_of::<*const u8>(), WRITABLE_DATA_ALIGNMENT)
+            .expect("TODO: handle OOM etc.") as *mut *const u8;
+        // The stubs keep the runtime alive until the memory is freed.
+        if self.stub_runtime.is_none() {
+            self.stub_runtime = Some(Rc::into_raw(Rc::clone(&self.runtime)));
+        }
+        let stub_code = stubs::lazy_stub_code(got, self.stub_runtime.unwrap(), id);
+        let stub = self
+            .code_memory
+            .allocate(stub_code.len(), EXECUTABLE_DATA_ALIGNMENT)
+            .expect("TODO: handle OOM etc.");
         unsafe {
-            ctx.emit_to_memory(
-                &*self.isa,
-                ptr,
-                &mut reloc_sink,
-                &mut trap_sink,
-                &mut stackmap_sink,
-            )
-        };
+            ptr::copy_nonoverlapping(stub_code.as_ptr(), stub, stub_code.len());
+            *got = stub.add(stubs::COMPILE_ENTRY_OFFSET);
+        }
 
+        let signature = namespace.get_function_decl(&id.into()).signature.clone();
+        state.functions[id].address = Some(stub);
+        state
+            .lazy_functions
+            .insert(id, LazyFunction { signature, got });
         Ok(Self::CompiledFunction {
-            code: ptr,
-            size,
-            relocs: reloc_sink.relocs,
+            code: stub,
+            size: stub_code.len(),
+            relocs: Vec::new(),
+            unwind_info: None,
+            got: None,
+            stub: None,
         })
     }
 
+    fn redefine_function(
+        &mut self,
+        _id: FuncId,
+        name: &str,
+        ctx: &cranelift_codegen::Context,
+        _namespace: &ModuleNamespace<Self>,
+        code_size: u32,
+        old: &Self::CompiledFunction,
+    ) -> ModuleResult<Self::CompiledFunction> {
+        let (got, stub) = match (old.got, old.stub) {
+            (Some(got), Some(stub)) => (got, stub),
+            _ => {
+                return Err(ModuleError::Backend(format!(
+                    "can't redefine function {}, which isn't hot swappable",
+                    name
+                )))
+            }
+        };
+        let mut func = self.emit_function_pages(name, ctx, code_size);
+        func.got = Some(got);
+        func.stub = Some(stub);
+        self.retired.push(old.code);
+        Ok(func)
+    }
+
     fn define_data(
         &mut self,
+        id: DataId,
         _name: &str,
         writable: bool,
         align: Option<u8>,
@@ -307,6 +717,7 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
                 .allocate(size, align.unwrap_or(READONLY_DATA_ALIGNMENT))
                 .expect("TODO: handle OOM etc.")
         };
+        self.runtime.state.lock().unwrap().data_objects[id].address = Some(storage);
 
         match *init {
             Init::Uninitialized => {
@@ -321,7 +732,7 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
             }
         }
 
-        let reloc = match self.isa.triple().pointer_width().unwrap() {
+        let reloc = match self.runtime.isa.triple().pointer_width().unwrap() {
             PointerWidth::U16 => panic!(),
             PointerWidth::U32 => Reloc::Abs4,
             PointerWidth::U64 => Reloc::Abs8,
@@ -348,6 +759,7 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
             storage,
             size,
             relocs,
+            writable,
         })
     }
 
@@ -370,57 +782,75 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
         unimplemented!();
     }
 
+    unsafe fn free_function(
+        &mut self,
+        id: FuncId,
+        _name: &str,
+        func: &Self::CompiledFunction,
+    ) -> ModuleResult<()> {
+        let mut state = self.runtime.state.lock().unwrap();
+        state.functions[id].address = None;
+        if let Some(lazy) = state.lazy_functions.remove(&id) {
+            // The GOT entry points into the stub until the function is compiled.
+            let code = *lazy.got as *mut u8;
+            if code != func.code.add(stubs::COMPILE_ENTRY_OFFSET) {
+                state.unwind_registry.deregister_function(code);
+                state.code_memory.free(code);
+            }
+            self.writable_memory.free(lazy.got as *mut u8);
+            self.code_memory.free(func.code);
+            return Ok(());
+        }
+
+        self.unwind_registry.deregister_function(func.code);
+        self.code_memory.free(func.code);
+        if let (Some(got), Some(stub)) = (func.got, func.stub) {
+            self.writable_memory.free(got as *mut u8);
+            self.code_memory.free(stub as *mut u8);
+        }
+        Ok(())
+    }
+
+    unsafe fn free_data(
+        &mut self,
+        id: DataId,
+        _name: &str,
+        data: &Self::CompiledData,
+    ) -> ModuleResult<()> {
+        self.runtime.state.lock().unwrap().data_objects[id].address = None;
+        if data.writable {
+            self.writable_memory.free(data.storage);
+        } else {
+            self.readonly_memory.free(data.storage);
+        }
+        Ok(())
+    }
+
     fn finalize_function(
         &mut self,
         func: &Self::CompiledFunction,
         namespace: &ModuleNamespace<Self>,
     ) -> Self::FinalizedFunction {
-        use std::ptr::write_unaligned;
+        perform_relocations(func, |name| self.get_definition(namespace, name));
 
-        for &RelocRecord {
-            reloc,
-            offset,
-            ref name,
-            addend,
-        } in &func.relocs
-        {
-            let ptr = func.code;
-            debug_assert!((offset as usize) < func.size);
-            let at = unsafe { ptr.offset(offset as isize) };
-            let base = self.get_definition(namespace, name);
-            // TODO: Handle overflow.
-            let what = unsafe { base.offset(addend as isize) };
-            match reloc {
-                Reloc::Abs4 => {
-                    // TODO: Handle overflow.
-                    #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_ptr_alignment))]
-                    unsafe {
-                        write_unaligned(at as *mut u32, what as u32)
-                    };
-                }
-                Reloc::Abs8 => {
-                    #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_ptr_alignment))]
-                    unsafe {
-                        write_unaligned(at as *mut u64, what as u64)
-                    };
-                }
-                Reloc::X86PCRel4 | Reloc::X86CallPCRel4 => {
-                    // TODO: Handle overflow.
-                    let pcrel = ((what as isize) - (at as isize)) as i32;
-                    #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_ptr_alignment))]
-                    unsafe {
-                        write_unaligned(at as *mut i32, pcrel)
-                    };
-                }
-                Reloc::X86GOTPCRel4 | Reloc::X86CallPLTRel4 => panic!("unexpected PIC relocation"),
-                _ => unimplemented!(),
-            }
+        // Now that the function is in its final place, make it unwindable.
+        if let Some(ref unwind_info) = func.unwind_info {
+            unsafe {
+                self.unwind_registry
+                    .register(func.code, func.size, unwind_info)
+            };
+        }
+
+        // Send the calls through the stub to the new code.
+        if let Some(got) = func.got {
+            unsafe { *got = func.code };
         }
-        func.code
+
+        func.entry()
     }
 
     fn get_finalized_function(&self, func: &Self::CompiledFunction) -> Self::FinalizedFunction {
-        func.code
+        func.entry()
     }
 
     fn finalize_data(
@@ -475,11 +905,229 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
         // Now that we're done patching, prepare the memory for execution!
         self.readonly_memory.set_readonly();
         self.code_memory.set_readable_and_executable();
+
+        // The redefined functions are now called through their GOT entries, so their old code
+        // can be freed.
+        for code in self.retired.drain(..) {
+            unsafe {
+                self.unwind_registry.deregister_function(code);
+                self.code_memory.free(code);
+            }
+        }
+    }
+
+    /// SimpleJIT emits code and data into memory as it processes them. This
+    /// method hands the memory over to a `SimpleJITProduct`, which can free it.
+    fn finish(self) -> Self::Product {
+        SimpleJITProduct {
+            runtime: self.runtime,
+            code_memory: self.code_memory,
+            readonly_memory: self.readonly_memory,
+            writable_memory: self.writable_memory,
+            unwind_registry: self.unwind_registry,
+            stub_runtime: self.stub_runtime,
+        }
+    }
+}
+
+/// The memory holding the code and data of a finished SimpleJIT module.
+///
+/// The memory is leaked unless it is explicitly freed with `free_memory`, so
+/// that pointers to finalized functions and data stay valid.
+pub struct SimpleJITProduct {
+    runtime: Rc<SimpleJITRuntime>,
+    code_memory: Memory,
+    readonly_memory: Memory,
+    writable_memory: Memory,
+    unwind_registry: UnwindRegistry,
+    stub_runtime: Option<*const SimpleJITRuntime>,
+}
+
+impl SimpleJITProduct {
+    /// Get the memory mapped by the module.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let state = self.runtime.state.lock().unwrap();
+        MemoryUsage::of(
+            &[&self.code_memory, &state.code_memory],
+            &[&self.readonly_memory, &self.writable_memory],
+        )
+    }
+
+    /// Deregister the unwind information of the compiled functions, and free
+    /// the memory holding the compiled code and data, including the lazily
+    /// compiled functions.
+    ///
+    /// This is unsafe because none of the finalized functions and data may be
+    /// used afterwards.
+    pub unsafe fn free_memory(mut self) {
+        {
+            let mut state = self.runtime.state.lock().unwrap();
+            state.unwind_registry.deregister();
+            state.code_memory.free_memory();
+        }
+        self.unwind_registry.deregister();
+        self.code_memory.free_memory();
+        self.readonly_memory.free_memory();
+        self.writable_memory.free_memory();
+        if let Some(runtime) = self.stub_runtime {
+            // Release the reference held by the stubs.
+            drop(Rc::from_raw(runtime));
+        }
     }
+}
 
-    /// SimpleJIT emits code and data into memory as it processes them, so it
-    /// doesn't need to provide anything after the `Module` is complete.
-    fn finish(self) {}
+/// Perform the relocations of `func`, getting the address of each name with `get_definition`.
+fn perform_relocations<F>(func: &SimpleJITCompiledFunction, get_definition: F)
+where
+    F: Fn(&ir::ExternalName) -> *const u8,
+{
+    use std::ptr::{read_unaligned, write_unaligned};
+
+    for &RelocRecord {
+        reloc,
+        offset,
+        ref name,
+        addend,
+    } in &func.relocs
+    {
+        let ptr = func.code;
+        debug_assert!((offset as usize) < func.size);
+        let at = unsafe { ptr.offset(offset as isize) };
+        let base = get_definition(name);
+        // TODO: Handle overflow.
+        let what = unsafe { base.offset(addend as isize) };
+        match reloc {
+            Reloc::Abs4 => {
+                // TODO: Handle overflow.
+                #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_ptr_alignment))]
+                unsafe {
+                    write_unaligned(at as *mut u32, what as u32)
+                };
+            }
+            Reloc::Abs8 => {
+                #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_ptr_alignment))]
+                unsafe {
+                    write_unaligned(at as *mut u64, what as u64)
+                };
+            }
+            Reloc::X86PCRel4 | Reloc::X86CallPCRel4 => {
+                // TODO: Handle overflow.
+                let pcrel = ((what as isize) - (at as isize)) as i32;
+                #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_ptr_alignment))]
+                unsafe {
+                    write_unaligned(at as *mut i32, pcrel)
+                };
+            }
+            Reloc::X86GOTPCRel4 | Reloc::X86CallPLTRel4 => panic!("unexpected PIC relocation"),
+            Reloc::Arm64Call => {
+                // The `bl` immediate is a signed 26-bit word offset.
+                let pcrel = (what as isize) - (at as isize);
+                assert!(
+                    pcrel & 3 == 0 && pcrel >= -(1 << 27) && pcrel < (1 << 27),
+                    "call target out of range for Arm64Call relocation"
+                );
+                #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_ptr_alignment))]
+                unsafe {
+                    let inst = read_unaligned(at as *const u32);
+                    let imm26 = ((pcrel >> 2) as u32) & 0x03ff_ffff;
+                    write_unaligned(at as *mut u32, (inst & 0xfc00_0000) | imm26)
+                };
+            }
+            Reloc::RiscvCall => {
+                // The `jal` immediate is a signed 21-bit even offset, scattered as
+                // imm[20|10:1|11|19:12] in the upper 20 bits of the instruction.
+                let pcrel = (what as isize) - (at as isize);
+                assert!(
+                    pcrel & 1 == 0 && pcrel >= -(1 << 20) && pcrel < (1 << 20),
+                    "call target out of range for RiscvCall relocation"
+                );
+                let imm = pcrel as u32;
+                let imm20 = ((imm >> 20) & 0x1) << 31
+                    | ((imm >> 1) & 0x3ff) << 21
+                    | ((imm >> 11) & 0x1) << 20
+                    | ((imm >> 12) & 0xff) << 12;
+                #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_ptr_alignment))]
+                unsafe {
+                    let inst = read_unaligned(at as *const u32);
+                    write_unaligned(at as *mut u32, (inst & 0xfff) | imm20)
+                };
+            }
+            _ => unimplemented!(),
+        }
+    }
+}
+
+/// Emit the function compiled in `ctx` to memory obtained from `allocate`, along with its unwind
+/// information.
+fn emit_function<F>(
+    isa: &dyn TargetIsa,
+    name: &str,
+    ctx: &Context,
+    code_size: u32,
+    allocate: F,
+) -> SimpleJITCompiledFunction
+where
+    F: FnOnce(usize) -> *mut u8,
+{
+    let size = code_size as usize;
+    // The unwind information is placed right after the code, so that it can be addressed
+    // relative to the function.
+    let mut unwind_sink = UnwindInfoSink::default();
+    ctx.emit_unwind_info(isa, host_unwind_kind(), &mut unwind_sink);
+    let unwind_offset = (size + UNWIND_INFO_ALIGNMENT - 1) & !(UNWIND_INFO_ALIGNMENT - 1);
+    let alloc_size = if unwind_sink.bytes.is_empty() {
+        size
+    } else {
+        unwind_offset + unwind_sink.bytes.len()
+    };
+
+    let ptr = allocate(alloc_size);
+
+    if cfg!(target_os = "linux") && ::std::env::var_os("PERF_BUILDID_DIR").is_some() {
+        let mut map_file = ::std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(format!("/tmp/perf-{}.map", ::std::process::id()))
+            .unwrap();
+
+        let _ = writeln!(map_file, "{:x} {:x} {}", ptr as usize, code_size, name);
+    }
+
+    let mut reloc_sink = SimpleJITRelocSink::new();
+    // Ignore traps for now. For now, frontends should just avoid generating code
+    // that traps.
+    let mut trap_sink = NullTrapSink {};
+    let mut stackmap_sink = SimpleJITStackmapSink::new();
+    unsafe {
+        ctx.emit_to_memory(
+            isa,
+            ptr,
+            &mut reloc_sink,
+            &mut trap_sink,
+            &mut stackmap_sink,
+        )
+    };
+
+    let unwind_info = if unwind_sink.bytes.is_empty() {
+        None
+    } else {
+        let bytes = &unwind_sink.bytes;
+        unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), ptr.add(unwind_offset), bytes.len()) };
+        Some(UnwindInfo {
+            offset: unwind_offset,
+            entry: unwind_sink.entry,
+            relocs: unwind_sink.relocs,
+        })
+    };
+
+    SimpleJITCompiledFunction {
+        code: ptr,
+        size,
+        relocs: reloc_sink.relocs,
+        unwind_info,
+        got: None,
+        stub: None,
+    }
 }
 
 #[cfg(not(windows))]
@@ -25,8 +25,10 @@
 
 mod backend;
 mod memory;
+mod stubs;
+mod unwind;
 
-pub use crate::backend::{SimpleJITBackend, SimpleJITBuilder};
+pub use crate::backend::{MemoryUsage, SimpleJITBackend, SimpleJITBuilder, SimpleJITProduct};
 
 /// Version number of this crate.
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -16,13 +16,15 @@ fn round_up_to_page_size(size: usize, page_size: usize) -> usize {
     (size + (page_size - 1)) & !(page_size - 1)
 }
 
-/// A simple struct consisting of a pointer and length.
+/// A simple struct consisting of a pointer and length, along with the number of objects allocated
+/// there which weren't freed yet.
 struct PtrLen {
     #[cfg(feature = "selinux-fix")]
     map: Option<MmapMut>,
 
     ptr: *mut u8,
     len: usize,
+    live: usize,
 }
 
 impl PtrLen {
@@ -34,9 +36,15 @@ impl PtrLen {
 
             ptr: ptr::null_mut(),
             len: 0,
+            live: 0,
         }
     }
 
+    /// Whether `ptr` points into this memory.
+    fn contains(&self, ptr: *mut u8) -> bool {
+        ptr >= self.ptr && (ptr as usize) < self.ptr as usize + self.len
+    }
+
     /// Create a new `PtrLen` pointing to at least `size` bytes of memory,
     /// suitably sized and aligned for memory protection.
     #[cfg(all(not(target_os = "windows"), feature = "selinux-fix"))]
@@ -53,6 +61,7 @@ impl PtrLen {
                     ptr: map.as_mut_ptr(),
                     map: Some(map),
                     len: alloc_size,
+                    live: 0,
                 })
             }
             Err(e) => Err(e.to_string()),
@@ -71,6 +80,7 @@ impl PtrLen {
                 Ok(Self {
                     ptr: ptr as *mut u8,
                     len: alloc_size,
+                    live: 0,
                 })
             } else {
                 Err(errno::Errno(err).to_string())
@@ -98,6 +108,7 @@ impl PtrLen {
             Ok(Self {
                 ptr: ptr as *mut u8,
                 len: round_up_to_page_size(size, page_size),
+                live: 0,
             })
         } else {
             Err(errno::errno().to_string())
@@ -105,13 +116,46 @@ impl PtrLen {
     }
 }
 
+// `MmapMut` from `cfg(feature = "selinux-fix")` already deallocates properly.
+#[cfg(not(any(feature = "selinux-fix", windows)))]
+impl Drop for PtrLen {
+    fn drop(&mut self) {
+        if !self.ptr.is_null() {
+            unsafe {
+                region::protect(self.ptr, self.len, region::Protection::ReadWrite)
+                    .expect("unable to unprotect memory");
+                libc::free(self.ptr as _);
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+impl Drop for PtrLen {
+    fn drop(&mut self) {
+        use winapi::um::memoryapi::VirtualFree;
+        use winapi::um::winnt::MEM_RELEASE;
+
+        if !self.ptr.is_null() {
+            unsafe {
+                VirtualFree(self.ptr as _, 0, MEM_RELEASE);
+            }
+        }
+    }
+}
+
 /// JIT memory manager. This manages pages of suitably aligned and
 /// accessible memory.
+///
+/// Once all the objects allocated in some pages are freed, the pages are
+/// kept to be reused by later allocations.
 pub struct Memory {
     allocations: Vec<PtrLen>,
     executable: usize,
     current: PtrLen,
     position: usize,
+    /// The pages whose objects were all freed, which are readable and writable.
+    free: Vec<PtrLen>,
 }
 
 impl Memory {
@@ -121,17 +165,39 @@ impl Memory {
             executable: 0,
             current: PtrLen::new(),
             position: 0,
+            free: Vec::new(),
         }
     }
 
     fn finish_current(&mut self) {
-        self.allocations
-            .push(mem::replace(&mut self.current, PtrLen::new()));
+        let current = mem::replace(&mut self.current, PtrLen::new());
+        if current.live != 0 {
+            self.allocations.push(current);
+        } else if !current.ptr.is_null() {
+            self.free.push(current);
+        }
         self.position = 0;
     }
 
+    /// Get free pages holding at least `size` bytes, either reused or newly allocated.
+    fn take_pages(&mut self, size: usize) -> Result<PtrLen, String> {
+        let best_fit = self
+            .free
+            .iter()
+            .enumerate()
+            .filter(|&(_, pages)| pages.len >= size)
+            .min_by_key(|&(_, pages)| pages.len)
+            .map(|(index, _)| index);
+        match best_fit {
+            Some(index) => Ok(self.free.swap_remove(index)),
+            None => PtrLen::with_size(size),
+        }
+    }
+
     /// TODO: Use a proper error type.
     pub fn allocate(&mut self, size: usize, align: u8) -> Result<*mut u8, String> {
+        // Zero-sized objects take a byte, so that they can be freed like the others.
+        let size = size.max(1);
         if self.position % align as usize != 0 {
             self.position += align as usize - self.position % align as usize;
             debug_assert!(self.position % align as usize == 0);
@@ -141,24 +207,96 @@ impl Memory {
             // TODO: Ensure overflow is not possible.
             let ptr = unsafe { self.current.ptr.add(self.position) };
             self.position += size;
+            self.current.live += 1;
             return Ok(ptr);
         }
 
         self.finish_current();
 
         // TODO: Allocate more at a time.
-        self.current = PtrLen::with_size(size)?;
+        self.current = self.take_pages(size)?;
+        self.current.live = 1;
         self.position = size;
         Ok(self.current.ptr)
     }
 
+    /// Allocate `size` bytes in pages of their own, so that they're reused as soon as the object
+    /// is freed.
+    pub fn allocate_pages(&mut self, size: usize) -> Result<*mut u8, String> {
+        let mut allocation = self.take_pages(size.max(1))?;
+        allocation.live = 1;
+        let ptr = allocation.ptr;
+        self.allocations.push(allocation);
+        Ok(ptr)
+    }
+
+    /// Free the object allocated at `ptr`.
+    ///
+    /// This is unsafe because it invalidates all pointers into the object.
+    pub unsafe fn free(&mut self, ptr: *mut u8) {
+        if self.current.contains(ptr) {
+            self.current.live -= 1;
+            if self.current.live == 0 {
+                self.position = 0;
+            }
+            return;
+        }
+
+        let index = self
+            .allocations
+            .iter()
+            .position(|allocation| allocation.contains(ptr))
+            .expect("object not allocated in this memory");
+        self.allocations[index].live -= 1;
+        if self.allocations[index].live == 0 {
+            let allocation = self.allocations.remove(index);
+            if index < self.executable {
+                self.executable -= 1;
+            }
+            region::protect(
+                allocation.ptr,
+                allocation.len,
+                region::Protection::ReadWrite,
+            )
+            .expect("unable to unprotect memory");
+            self.free.push(allocation);
+        }
+    }
+
+    /// Get the number of bytes mapped by this memory, including the pages kept to be reused.
+    pub fn mapped(&self) -> usize {
+        self.allocations
+            .iter()
+            .chain(&self.free)
+            .map(|allocation| allocation.len)
+            .sum::<usize>()
+            + self.current.len
+    }
+
+    /// Get the number of bytes mapped by this memory which are kept to be reused.
+    pub fn reusable(&self) -> usize {
+        let current = if self.current.live == 0 {
+            self.current.len
+        } else {
+            0
+        };
+        self.free
+            .iter()
+            .map(|allocation| allocation.len)
+            .sum::<usize>()
+            + current
+    }
+
     /// Set all memory allocated in this `Memory` up to now as readable and executable.
     pub fn set_readable_and_executable(&mut self) {
         self.finish_current();
 
         #[cfg(feature = "selinux-fix")]
         {
-            for &PtrLen { ref map, ptr, len } in &self.allocations[self.executable..] {
+            for &PtrLen {
+                ref map, ptr, len, ..
+            } in &self.allocations[self.executable..]
+            {
                 if len != 0 && map.is_some() {
                     unsafe {
                         region::protect(ptr, len, region::Protection::ReadExecute)
@@ -170,7 +308,7 @@ impl Memory {
 
         #[cfg(not(feature = "selinux-fix"))]
         {
-            for &PtrLen { ptr, len } in &self.allocations[self.executable..] {
+            for &PtrLen { ptr, len, .. } in &self.allocations[self.executable..] {
                 if len != 0 {
                     unsafe {
                         region::protect(ptr, len, region::Protection::ReadExecute)
@@ -187,7 +325,10 @@ impl Memory {
 
         #[cfg(feature = "selinux-fix")]
         {
-            for &PtrLen { ref map, ptr, len } in &self.allocations[self.executable..] {
+            for &PtrLen {
+                ref map, ptr, len, ..
+            } in &self.allocations[self.executable..]
+            {
                 if len != 0 && map.is_some() {
                     unsafe {
                         region::protect(ptr, len, region::Protection::Read)
@@ -199,7 +340,7 @@ impl Memory {
 
         #[cfg(not(feature = "selinux-fix"))]
         {
-            for &PtrLen { ptr, len } in &self.allocations[self.executable..] {
+            for &PtrLen { ptr, len, .. } in &self.allocations[self.executable..] {
                 if len != 0 {
                     unsafe {
                         region::protect(ptr, len, region::Protection::Read)
@@ -209,9 +350,29 @@ impl Memory {
             }
         }
     }
+
+    /// Free all the memory allocated so far.
+    ///
+    /// This is unsafe because it invalidates all pointers into the memory, including pointers to
+    /// compiled functions.
+    pub unsafe fn free_memory(&mut self) {
+        self.finish_current();
+        self.allocations.clear();
+        self.free.clear();
+        self.executable = 0;
+    }
 }
 
-// TODO: Implement Drop to unprotect and deallocate the memory?
+impl Drop for Memory {
+    fn drop(&mut self) {
+        // Leak the memory, so that pointers to compiled functions stay valid. Only the pages
+        // kept to be reused are freed.
+        self.finish_current();
+        for allocation in self.allocations.drain(..) {
+            mem::forget(allocation);
+        }
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -224,4 +385,26 @@ mod tests {
         assert_eq!(round_up_to_page_size(4096, 4096), 4096);
         assert_eq!(round_up_to_page_size(4097, 4096), 8192);
     }
+
+    #[test]
+    fn test_reuse_freed_pages() {
+        let mut memory = Memory::new();
+        let a = memory.allocate(16, 8).unwrap();
+        let b = memory.allocate(16, 8).unwrap();
+        memory.set_readonly();
+        let mapped = memory.mapped();
+        assert_eq!(memory.reusable(), 0);
+
+        // The pages are only reused once all their objects are freed.
+        unsafe { memory.free(a) };
+        assert_eq!(memory.reusable(), 0);
+        unsafe { memory.free(b) };
+        assert_eq!(memory.reusable(), mapped);
+
+        let c = memory.allocate(16, 8).unwrap();
+        assert_eq!(c, a);
+        unsafe { *c = 1 };
+        assert_eq!(memory.reusable(), 0);
+        assert_eq!(memory.mapped(), mapped);
+    }
 }
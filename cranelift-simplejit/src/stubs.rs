@@ -0,0 +1,144 @@
+//! Stubs calling functions through their GOT entries.
+//!
+//! A function which can be redefined is called through a stub, which jumps to the address held in
+//! the function's GOT entry. Redefining the function only requires updating that entry.
+//!
+//! A lazily compiled function is called through a longer stub. Its GOT entry initially points back
+//! into the stub, at code which saves the argument registers, calls `compile_lazy_function`,
+//! restores the registers, and jumps to the compiled function. Since the GOT entry is then
+//! updated, the following calls go to the compiled function directly.
+
+use crate::backend::SimpleJITRuntime;
+use cranelift_module::FuncId;
+use std::panic::{self, AssertUnwindSafe};
+use std::process;
+
+/// The offset in a stub of the code compiling the function.
+pub const COMPILE_ENTRY_OFFSET: usize = 13;
+
+/// The general purpose registers holding arguments in the host calling convention.
+#[cfg(not(windows))]
+const ARG_GPRS: [u8; 6] = [7, 6, 2, 1, 8, 9];
+#[cfg(windows)]
+const ARG_GPRS: [u8; 4] = [1, 2, 8, 9];
+
+/// The number of XMM registers holding arguments in the host calling convention.
+#[cfg(not(windows))]
+const ARG_XMMS: u8 = 8;
+#[cfg(windows)]
+const ARG_XMMS: u8 = 4;
+
+/// The stack space reserved for the callee by the caller in the host calling convention.
+#[cfg(not(windows))]
+const SHADOW_SPACE: u8 = 0;
+#[cfg(windows)]
+const SHADOW_SPACE: u8 = 32;
+
+const RAX: u8 = 0;
+const R11: u8 = 11;
+
+/// Get the x86-64 code of a stub jumping to the address in the GOT entry at `got`.
+pub fn stub_code(got: *mut *const u8) -> Vec<u8> {
+    let mut code = Vec::new();
+    // movabs r11, got; jmp [r11]
+    movabs(&mut code, R11, got as u64);
+    code.extend_from_slice(&[0x41, 0xff, 0x23]);
+    code
+}
+
+/// Get the x86-64 code of the stub of the lazily compiled function `id`, whose GOT entry is at
+/// `got`.
+pub fn lazy_stub_code(
+    got: *mut *const u8,
+    runtime: *const SimpleJITRuntime,
+    id: FuncId,
+) -> Vec<u8> {
+    let mut code = stub_code(got);
+    debug_assert_eq!(code.len(), COMPILE_ENTRY_OFFSET);
+
+    // push rbp; mov rbp, rsp
+    code.extend_from_slice(&[0x55, 0x48, 0x89, 0xe5]);
+    for &reg in ARG_GPRS.iter() {
+        push(&mut code, reg);
+    }
+    // Keep the stack 16-byte aligned for the call.
+    let padding = if ARG_GPRS.len() % 2 == 0 { 0 } else { 8 };
+    let frame_size = u32::from(ARG_XMMS) * 16 + u32::from(SHADOW_SPACE) + padding;
+    // sub rsp, frame_size
+    code.extend_from_slice(&[0x48, 0x81, 0xec]);
+    code.extend_from_slice(&frame_size.to_le_bytes());
+    for xmm in 0..ARG_XMMS {
+        // movdqu [rsp + disp], xmm
+        movdqu(&mut code, 0x7f, xmm, SHADOW_SPACE + xmm * 16);
+    }
+
+    movabs(&mut code, ARG_GPRS[0], runtime as u64);
+    movabs(&mut code, ARG_GPRS[1], u64::from(id.as_u32()));
+    movabs(&mut code, RAX, compile_lazy_function as usize as u64);
+    // call rax
+    code.extend_from_slice(&[0xff, 0xd0]);
+
+    for xmm in 0..ARG_XMMS {
+        // movdqu xmm, [rsp + disp]
+        movdqu(&mut code, 0x6f, xmm, SHADOW_SPACE + xmm * 16);
+    }
+    // add rsp, frame_size
+    code.extend_from_slice(&[0x48, 0x81, 0xc4]);
+    code.extend_from_slice(&frame_size.to_le_bytes());
+    for &reg in ARG_GPRS.iter().rev() {
+        pop(&mut code, reg);
+    }
+    // pop rbp; jmp rax
+    code.extend_from_slice(&[0x5d, 0xff, 0xe0]);
+    code
+}
+
+fn rex_b(reg: u8) -> u8 {
+    if reg >= 8 {
+        0x41
+    } else {
+        0x40
+    }
+}
+
+fn movabs(code: &mut Vec<u8>, reg: u8, imm: u64) {
+    code.extend_from_slice(&[rex_b(reg) | 0x08, 0xb8 | (reg & 7)]);
+    code.extend_from_slice(&imm.to_le_bytes());
+}
+
+fn push(code: &mut Vec<u8>, reg: u8) {
+    if reg >= 8 {
+        code.push(rex_b(reg));
+    }
+    code.push(0x50 | (reg & 7));
+}
+
+fn pop(code: &mut Vec<u8>, reg: u8) {
+    if reg >= 8 {
+        code.push(rex_b(reg));
+    }
+    code.push(0x58 | (reg & 7));
+}
+
+/// Emit a `movdqu` between `xmm` and the stack at `rsp + disp`, loading with the opcode `0x6f`
+/// and storing with `0x7f`.
+fn movdqu(code: &mut Vec<u8>, opcode: u8, xmm: u8, disp: u8) {
+    code.extend_from_slice(&[0xf3, 0x0f, opcode, 0x44 | (xmm << 3), 0x24, disp]);
+}
+
+/// Compile the function `id` for its stub, and return the address of its code, or of the code to
+/// run instead if it fails to compile.
+///
+/// A panic can't unwind into the JIT code, so it aborts the process.
+extern "C" fn compile_lazy_function(runtime: *const SimpleJITRuntime, id: usize) -> *const u8 {
+    let id = FuncId::from_u32(id as u32);
+    match panic::catch_unwind(AssertUnwindSafe(|| {
+        let runtime = unsafe { &*runtime };
+        runtime
+            .compile_function(id)
+            .unwrap_or_else(|error| runtime.compilation_error(id, error))
+    })) {
+        Ok(code) => code,
+        Err(_) => process::abort(),
+    }
+}
@@ -0,0 +1,167 @@
+//! Registration of the unwind information of JIT-compiled functions with the system unwinder.
+//!
+//! The unwind information of each function is placed in code memory right after the function's
+//! code. On Windows, it is an `UNWIND_INFO` structure which is registered with
+//! `RtlAddFunctionTable`. Elsewhere, it is an `.eh_frame` section which is registered with
+//! `__register_frame`.
+
+use cranelift_codegen::binemit::{FrameUnwindKind, FrameUnwindOffset, FrameUnwindSink, Reloc};
+use std::ptr::write_unaligned;
+
+#[cfg(not(windows))]
+extern "C" {
+    // Provided by libgcc or libunwind.
+    fn __register_frame(fde: *const u8);
+    fn __deregister_frame(fde: *const u8);
+}
+
+/// The kind of unwind information understood by the system unwinder.
+pub fn host_unwind_kind() -> FrameUnwindKind {
+    if cfg!(windows) {
+        FrameUnwindKind::Fastcall
+    } else {
+        FrameUnwindKind::Libunwind
+    }
+}
+
+/// Unwind information emitted for a function, before it is copied into code memory.
+#[derive(Default)]
+pub struct UnwindInfoSink {
+    pub bytes: Vec<u8>,
+    pub relocs: Vec<FrameUnwindOffset>,
+    pub entry: FrameUnwindOffset,
+}
+
+impl FrameUnwindSink for UnwindInfoSink {
+    fn len(&self) -> FrameUnwindOffset {
+        self.bytes.len()
+    }
+
+    fn bytes(&mut self, b: &[u8]) {
+        self.bytes.extend_from_slice(b);
+    }
+
+    fn reserve(&mut self, len: usize) {
+        self.bytes.reserve(len);
+    }
+
+    fn reloc(&mut self, r: Reloc, off: FrameUnwindOffset) {
+        // The only relocations refer to the start of the function.
+        assert_eq!(r, Reloc::Abs8, "unexpected unwind information relocation");
+        self.relocs.push(off);
+    }
+
+    fn set_entry_offset(&mut self, off: FrameUnwindOffset) {
+        self.entry = off;
+    }
+}
+
+/// The location of a function's unwind information in code memory.
+pub struct UnwindInfo {
+    /// Offset of the unwind information from the start of the function.
+    pub offset: usize,
+    /// Offset of the entry to register, relative to the unwind information.
+    pub entry: FrameUnwindOffset,
+    /// Offsets of the function addresses to fill in, relative to the unwind information.
+    pub relocs: Vec<FrameUnwindOffset>,
+}
+
+/// The unwind information registered with the system unwinder, along with the code it describes.
+pub struct UnwindRegistry {
+    #[cfg(not(windows))]
+    frames: Vec<(*mut u8, *const u8)>,
+    #[cfg(windows)]
+    tables: Vec<(*mut u8, Box<winapi::um::winnt::RUNTIME_FUNCTION>)>,
+}
+
+impl UnwindRegistry {
+    pub fn new() -> Self {
+        Self {
+            #[cfg(not(windows))]
+            frames: Vec::new(),
+            #[cfg(windows)]
+            tables: Vec::new(),
+        }
+    }
+
+    /// Fill in the function address in the unwind information of the function at `code`, and
+    /// register it.
+    ///
+    /// This is unsafe because the unwind information must be in writable memory after the code,
+    /// and must stay there until `deregister` is called.
+    pub unsafe fn register(&mut self, code: *mut u8, code_size: usize, info: &UnwindInfo) {
+        let unwind_info = code.add(info.offset);
+        for &offset in &info.relocs {
+            #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_ptr_alignment))]
+            write_unaligned(unwind_info.add(offset) as *mut u64, code as u64);
+        }
+        self.register_info(code, code_size, info);
+    }
+
+    #[cfg(not(windows))]
+    unsafe fn register_info(&mut self, code: *mut u8, _code_size: usize, info: &UnwindInfo) {
+        // libgcc walks a whole `.eh_frame` section, up to the zero terminator, while libunwind
+        // expects a single FDE.
+        let frame = if cfg!(any(
+            all(target_os = "linux", target_env = "gnu"),
+            target_os = "freebsd"
+        )) {
+            code.add(info.offset)
+        } else {
+            code.add(info.offset + info.entry)
+        };
+        __register_frame(frame);
+        self.frames.push((code, frame));
+    }
+
+    #[cfg(windows)]
+    unsafe fn register_info(&mut self, code: *mut u8, code_size: usize, info: &UnwindInfo) {
+        use winapi::um::winnt::{RtlAddFunctionTable, RUNTIME_FUNCTION};
+
+        // The addresses in the function table are relative to the start of the function.
+        let mut table: Box<RUNTIME_FUNCTION> = Box::new(std::mem::zeroed());
+        table.BeginAddress = 0;
+        table.EndAddress = code_size as u32;
+        *table.u.UnwindInfoAddress_mut() = info.offset as u32;
+        if RtlAddFunctionTable(&mut *table, 1, code as u64) == 0 {
+            panic!("failed to register the function table");
+        }
+        self.tables.push((code, table));
+    }
+
+    /// Deregister the unwind information of the function at `code`, if it was registered.
+    ///
+    /// This must be done before the code memory holding it is freed.
+    pub unsafe fn deregister_function(&mut self, code: *mut u8) {
+        #[cfg(not(windows))]
+        {
+            if let Some(index) = self.frames.iter().position(|&(c, _)| c == code) {
+                let (_, frame) = self.frames.remove(index);
+                __deregister_frame(frame);
+            }
+        }
+
+        #[cfg(windows)]
+        {
+            if let Some(index) = self.tables.iter().position(|&(c, _)| c == code) {
+                let (_, mut table) = self.tables.remove(index);
+                winapi::um::winnt::RtlDeleteFunctionTable(&mut *table);
+            }
+        }
+    }
+
+    /// Deregister all the unwind information registered so far.
+    ///
+    /// This must be done before the code memory holding it is freed.
+    pub unsafe fn deregister(&mut self) {
+        #[cfg(not(windows))]
+        for (_, frame) in self.frames.drain(..).rev() {
+            __deregister_frame(frame);
+        }
+
+        #[cfg(windows)]
+        for (_, mut table) in self.tables.drain(..).rev() {
+            winapi::um::winnt::RtlDeleteFunctionTable(&mut *table);
+        }
+    }
+}
@@ -198,3 +198,810 @@ fn libcall_function() {
 
     module.finalize_definitions();
 }
+
+#[test]
+fn free_and_reuse_memory() {
+    let mut module: Module<SimpleJITBackend> =
+        Module::new(SimpleJITBuilder::new(default_libcall_names()));
+    let func_id = define_simple_function(&mut module);
+    let data_id = module
+        .declare_data("data", Linkage::Local, true, None)
+        .unwrap();
+    let mut data_ctx = DataContext::new();
+    data_ctx.define_zeroinit(16);
+    module.define_data(data_id, &data_ctx).unwrap();
+    module.finalize_definitions();
+    let usage = module.backend().memory_usage();
+    assert_eq!(usage.reusable, 0);
+
+    // The memory stays mapped, to be reused.
+    unsafe {
+        module.free_function(func_id).unwrap();
+        module.free_data(data_id).unwrap();
+    }
+    let freed = module.backend().memory_usage();
+    assert_eq!((freed.code, freed.data), (usage.code, usage.data));
+    assert_eq!(freed.reusable, usage.code + usage.data);
+
+    // The freed functions and data objects can be defined again.
+    assert_eq!(define_simple_function(&mut module), func_id);
+    module.define_data(data_id, &data_ctx).unwrap();
+    module.finalize_definitions();
+    assert_eq!(module.backend().memory_usage(), usage);
+
+    let code = module.get_finalized_function(func_id);
+    let func: extern "C" fn() = unsafe { std::mem::transmute(code) };
+    func();
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu", target_arch = "x86_64"))]
+mod unwind {
+    use super::*;
+    use std::cell::RefCell;
+    use std::mem;
+    use std::os::raw::c_void;
+
+    #[repr(C)]
+    struct UnwindContext {
+        _private: [u8; 0],
+    }
+
+    const URC_NO_REASON: i32 = 0;
+    const URC_END_OF_STACK: i32 = 5;
+
+    extern "C" {
+        fn _Unwind_Backtrace(
+            trace: extern "C" fn(*mut UnwindContext, *mut c_void) -> i32,
+            arg: *mut c_void,
+        ) -> i32;
+        fn _Unwind_GetIP(ctx: *mut UnwindContext) -> usize;
+    }
+
+    extern "C" fn trace(ctx: *mut UnwindContext, arg: *mut c_void) -> i32 {
+        let ips = unsafe { &mut *(arg as *mut Vec<usize>) };
+        ips.push(unsafe { _Unwind_GetIP(ctx) });
+        URC_NO_REASON
+    }
+
+    thread_local! {
+        static BACKTRACE: RefCell<(i32, Vec<usize>)> = RefCell::new((0, Vec::new()));
+    }
+
+    extern "C" fn record_backtrace() {
+        let mut ips = Vec::new();
+        let reason = unsafe { _Unwind_Backtrace(trace, &mut ips as *mut _ as *mut c_void) };
+        BACKTRACE.with(|bt| *bt.borrow_mut() = (reason, ips));
+    }
+
+    #[test]
+    fn unwind_through_jit_code() {
+        let mut builder = SimpleJITBuilder::new(default_libcall_names());
+        builder.symbol("record_backtrace", record_backtrace as *const u8);
+        let mut module: Module<SimpleJITBackend> = Module::new(builder);
+
+        let sig = Signature {
+            params: vec![],
+            returns: vec![],
+            call_conv: CallConv::SystemV,
+        };
+        let callee_id = module
+            .declare_function("record_backtrace", Linkage::Import, &sig)
+            .unwrap();
+        let func_id = module
+            .declare_function("caller", Linkage::Local, &sig)
+            .unwrap();
+
+        let mut ctx = Context::new();
+        ctx.func = {
+            let func = Function::with_name_signature(ExternalName::user(0, func_id.as_u32()), sig);
+            let mut bcx: FunctionBuilder = FunctionBuilder::new(func);
+            let ebb = bcx.create_ebb();
+            bcx.switch_to_block(ebb);
+            let callee = module.declare_func_in_func(callee_id, &mut bcx.func);
+            bcx.ins().call(callee, &[]);
+            bcx.ins().return_(&[]);
+            bcx.seal_all_blocks();
+            bcx.finalize()
+        };
+        module.define_function(func_id, &mut ctx).unwrap();
+        module.finalize_definitions();
+
+        let code = module.get_finalized_function(func_id);
+        let caller: extern "C" fn() = unsafe { mem::transmute(code) };
+        caller();
+
+        // The unwinder must find the JIT-compiled frame, and walk past it to the
+        // bottom of the stack.
+        let (reason, ips) = BACKTRACE.with(|bt| bt.borrow().clone());
+        let code = code as usize;
+        let jit_frame = ips
+            .iter()
+            .position(|&ip| ip > code && ip - code < 0x100)
+            .expect("no JIT-compiled frame in the backtrace");
+        assert!(jit_frame + 1 < ips.len());
+        assert_eq!(reason, URC_END_OF_STACK);
+
+        unsafe { module.finish().free_memory() };
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod lazy {
+    use super::*;
+    use cranelift_codegen::ir::immediates::Imm64;
+    use std::cell::RefCell;
+    use std::mem;
+    use std::rc::Rc;
+
+    fn signature(params: &[Type], returns: &[Type]) -> Signature {
+        Signature {
+            params: params.iter().map(|&ty| AbiParam::new(ty)).collect(),
+            returns: returns.iter().map(|&ty| AbiParam::new(ty)).collect(),
+            call_conv: CallConv::SystemV,
+        }
+    }
+
+    #[test]
+    fn compile_on_first_call() {
+        let generated = Rc::new(RefCell::new(Vec::new()));
+        let double_id = FuncId::new(0);
+        let quadruple_id = FuncId::new(1);
+        let factor_id = DataId::new(0);
+
+        let mut builder = SimpleJITBuilder::new(default_libcall_names());
+        let log = Rc::clone(&generated);
+        builder.lazy_function_generator(move |id, ctx: &mut Context| {
+            log.borrow_mut().push(id);
+            let func = mem::replace(&mut ctx.func, Function::new());
+            let mut bcx: FunctionBuilder = FunctionBuilder::new(func);
+            let ebb = bcx.create_ebb();
+            bcx.switch_to_block(ebb);
+            bcx.append_ebb_params_for_function_params(ebb);
+            let x = bcx.ebb_params(ebb)[0];
+            let result = if id == double_id {
+                let factor = bcx.create_global_value(GlobalValueData::Symbol {
+                    name: ExternalName::user(1, factor_id.as_u32()),
+                    offset: Imm64::new(0),
+                    colocated: true,
+                });
+                let factor = bcx.ins().global_value(types::I64, factor);
+                let factor = bcx.ins().load(types::I64, MemFlags::new(), factor, 0);
+                bcx.ins().imul(x, factor)
+            } else {
+                let sig = bcx.import_signature(signature(&[types::I64], &[types::I64]));
+                let double = bcx.import_function(ExtFuncData {
+                    name: ExternalName::user(0, double_id.as_u32()),
+                    signature: sig,
+                    colocated: true,
+                });
+                let call = bcx.ins().call(double, &[x]);
+                let y = bcx.inst_results(call)[0];
+                let call = bcx.ins().call(double, &[y]);
+                bcx.inst_results(call)[0]
+            };
+            bcx.ins().return_(&[result]);
+            bcx.seal_all_blocks();
+            ctx.func = bcx.finalize();
+        });
+        let mut module: Module<SimpleJITBackend> = Module::new(builder);
+
+        let sig = signature(&[types::I64], &[types::I64]);
+        let double = module
+            .declare_function("double", Linkage::Local, &sig)
+            .unwrap();
+        let quadruple = module
+            .declare_function("quadruple", Linkage::Local, &sig)
+            .unwrap();
+        let factor = module
+            .declare_data("factor", Linkage::Local, false, None)
+            .unwrap();
+        assert_eq!((double, quadruple), (double_id, quadruple_id));
+        assert_eq!(factor, factor_id);
+        let mut data_ctx = DataContext::new();
+        data_ctx.define(Box::new(2i64.to_ne_bytes()));
+        module.define_data(factor, &data_ctx).unwrap();
+        module.define_function_lazily(double).unwrap();
+        module.define_function_lazily(quadruple).unwrap();
+        module.finalize_definitions();
+        assert!(generated.borrow().is_empty());
+
+        let code = module.get_finalized_function(quadruple);
+        let quadruple: extern "C" fn(i64) -> i64 = unsafe { mem::transmute(code) };
+        assert_eq!(quadruple(5), 20);
+        assert_eq!(*generated.borrow(), [quadruple_id, double_id]);
+        assert_eq!(quadruple(-3), -12);
+        assert_eq!(generated.borrow().len(), 2);
+
+        unsafe { module.finish().free_memory() };
+    }
+
+    #[test]
+    fn preserve_arguments() {
+        // Eight integer and eight float arguments use every argument register, and the stack.
+        let params: Vec<Type> = [types::I64; 8]
+            .iter()
+            .chain([types::F64; 8].iter())
+            .cloned()
+            .collect();
+        let sig = signature(&params, &[types::F64]);
+
+        let mut builder = SimpleJITBuilder::new(default_libcall_names());
+        builder.lazy_function_generator(|_, ctx: &mut Context| {
+            let func = mem::replace(&mut ctx.func, Function::new());
+            let mut bcx: FunctionBuilder = FunctionBuilder::new(func);
+            let ebb = bcx.create_ebb();
+            bcx.switch_to_block(ebb);
+            bcx.append_ebb_params_for_function_params(ebb);
+            let params = bcx.ebb_params(ebb).to_vec();
+            // Weigh each argument differently, so that swapped arguments are noticed.
+            let mut sum = bcx.ins().f64const(0.0);
+            for (i, &param) in params.iter().enumerate() {
+                let value = if i < 8 {
+                    bcx.ins().fcvt_from_sint(types::F64, param)
+                } else {
+                    param
+                };
+                let weight = bcx.ins().f64const((i + 1) as f64);
+                let term = bcx.ins().fmul(value, weight);
+                sum = bcx.ins().fadd(sum, term);
+            }
+            bcx.ins().return_(&[sum]);
+            bcx.seal_all_blocks();
+            ctx.func = bcx.finalize();
+        });
+        let mut module: Module<SimpleJITBackend> = Module::new(builder);
+
+        let func_id = module
+            .declare_function("weighted_sum", Linkage::Export, &sig)
+            .unwrap();
+        module.define_function_lazily(func_id).unwrap();
+        module.finalize_definitions();
+
+        let code = module.get_finalized_function(func_id);
+        type WeightedSum = extern "C" fn(
+            i64,
+            i64,
+            i64,
+            i64,
+            i64,
+            i64,
+            i64,
+            i64,
+            f64,
+            f64,
+            f64,
+            f64,
+            f64,
+            f64,
+            f64,
+            f64,
+        ) -> f64;
+        let weighted_sum: WeightedSum = unsafe { mem::transmute(code) };
+        let expected: f64 = (1..=16).map(|i| f64::from(i * i)).sum();
+        for _ in 0..2 {
+            let result = weighted_sum(
+                1, 2, 3, 4, 5, 6, 7, 8, 9.0, 10.0, 11.0, 12.0, 13.0, 14.0, 15.0, 16.0,
+            );
+            assert_eq!(result, expected);
+        }
+
+        unsafe { module.finish().free_memory() };
+    }
+
+    #[test]
+    fn free_compiled_function() {
+        let mut builder = SimpleJITBuilder::new(default_libcall_names());
+        builder.lazy_function_generator(|_, ctx: &mut Context| {
+            let func = mem::replace(&mut ctx.func, Function::new());
+            let mut bcx: FunctionBuilder = FunctionBuilder::new(func);
+            let ebb = bcx.create_ebb();
+            bcx.switch_to_block(ebb);
+            let value = bcx.ins().iconst(types::I64, 42);
+            bcx.ins().return_(&[value]);
+            bcx.seal_all_blocks();
+            ctx.func = bcx.finalize();
+        });
+        let mut module: Module<SimpleJITBackend> = Module::new(builder);
+        let func_id = module
+            .declare_function("answer", Linkage::Local, &signature(&[], &[types::I64]))
+            .unwrap();
+        module.define_function_lazily(func_id).unwrap();
+        module.finalize_definitions();
+
+        let code = module.get_finalized_function(func_id);
+        let answer: extern "C" fn() -> i64 = unsafe { mem::transmute(code) };
+        assert_eq!(answer(), 42);
+        let usage = module.backend().memory_usage();
+        assert_eq!(usage.reusable, 0);
+
+        // The stub, its GOT entry and the compiled code are all freed.
+        unsafe { module.free_function(func_id).unwrap() };
+        let freed = module.backend().memory_usage();
+        assert_eq!(freed.reusable, usage.code + usage.data);
+
+        unsafe { module.finish().free_memory() };
+    }
+
+    #[test]
+    fn handle_compilation_error() {
+        extern "C" fn fallback() -> i64 {
+            -1
+        }
+
+        let errors = Rc::new(RefCell::new(Vec::new()));
+        let mut builder = SimpleJITBuilder::new(default_libcall_names());
+        builder.lazy_function_generator(|_, ctx: &mut Context| {
+            // Return an `i32` from a function returning an `i64`, which the verifier rejects.
+            let func = mem::replace(&mut ctx.func, Function::new());
+            let mut bcx: FunctionBuilder = FunctionBuilder::new(func);
+            let ebb = bcx.create_ebb();
+            bcx.switch_to_block(ebb);
+            let value = bcx.ins().iconst(types::I32, 42);
+            bcx.ins().return_(&[value]);
+            bcx.seal_all_blocks();
+            ctx.func = bcx.finalize();
+        });
+        let log = Rc::clone(&errors);
+        builder.lazy_compilation_error_handler(move |id, error| {
+            log.borrow_mut().push((id, error));
+            fallback as *const u8
+        });
+        let mut module: Module<SimpleJITBackend> = Module::new(builder);
+        let func_id = module
+            .declare_function("answer", Linkage::Local, &signature(&[], &[types::I64]))
+            .unwrap();
+        module.define_function_lazily(func_id).unwrap();
+        module.finalize_definitions();
+
+        let code = module.get_finalized_function(func_id);
+        let answer: extern "C" fn() -> i64 = unsafe { mem::transmute(code) };
+        // Every call compiles the function again, and runs the fallback.
+        assert_eq!(answer(), -1);
+        assert_eq!(answer(), -1);
+        let errors = errors.borrow();
+        assert_eq!(errors.len(), 2);
+        for (id, error) in errors.iter() {
+            assert_eq!(*id, func_id);
+            match error {
+                ModuleError::Compilation(_) => {}
+                _ => panic!("unexpected error: {}", error),
+            }
+        }
+
+        unsafe { module.finish().free_memory() };
+    }
+
+    #[test]
+    fn error_without_generator() {
+        let mut module: Module<SimpleJITBackend> =
+            Module::new(SimpleJITBuilder::new(default_libcall_names()));
+        let func_id = module
+            .declare_function("abc", Linkage::Local, &signature(&[], &[]))
+            .unwrap();
+        module.define_function_lazily(func_id).err().unwrap();
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod hotswap {
+    use super::*;
+    use std::mem;
+
+    fn signature() -> Signature {
+        Signature {
+            params: vec![],
+            returns: vec![AbiParam::new(types::I64)],
+            call_conv: CallConv::SystemV,
+        }
+    }
+
+    /// Build the function `id` returning `value`, plus the result of `callee` if any.
+    fn build_function(ctx: &mut Context, id: FuncId, value: i64, callee: Option<FuncId>) {
+        ctx.clear();
+        let func = Function::with_name_signature(ExternalName::user(0, id.as_u32()), signature());
+        let mut bcx: FunctionBuilder = FunctionBuilder::new(func);
+        let ebb = bcx.create_ebb();
+        bcx.switch_to_block(ebb);
+        let mut result = bcx.ins().iconst(types::I64, value);
+        if let Some(callee) = callee {
+            let sig = bcx.import_signature(signature());
+            let callee = bcx.import_function(ExtFuncData {
+                name: ExternalName::user(0, callee.as_u32()),
+                signature: sig,
+                colocated: true,
+            });
+            let call = bcx.ins().call(callee, &[]);
+            let callee_result = bcx.inst_results(call)[0];
+            result = bcx.ins().iadd(result, callee_result);
+        }
+        bcx.ins().return_(&[result]);
+        bcx.seal_all_blocks();
+        ctx.func = bcx.finalize();
+    }
+
+    #[test]
+    fn redefine_called_function() {
+        let mut builder = SimpleJITBuilder::new(default_libcall_names());
+        builder.hotswap(true);
+        let mut module: Module<SimpleJITBackend> = Module::new(builder);
+        let value = module
+            .declare_function("value", Linkage::Local, &signature())
+            .unwrap();
+        let caller = module
+            .declare_function("caller", Linkage::Local, &signature())
+            .unwrap();
+
+        let mut ctx = Context::new();
+        build_function(&mut ctx, value, 1, None);
+        module.define_function(value, &mut ctx).unwrap();
+        build_function(&mut ctx, caller, 10, Some(value));
+        module.define_function(caller, &mut ctx).unwrap();
+        module.finalize_definitions();
+
+        let code = module.get_finalized_function(caller);
+        let caller_fn: extern "C" fn() -> i64 = unsafe { mem::transmute(code) };
+        assert_eq!(caller_fn(), 11);
+
+        // The old definition is used until the new one is finalized.
+        build_function(&mut ctx, value, 2, None);
+        module.redefine_function(value, &mut ctx).unwrap();
+        assert_eq!(caller_fn(), 11);
+        module.finalize_definitions();
+        assert_eq!(caller_fn(), 12);
+
+        // The callers are redefined the same way, and keep their address.
+        build_function(&mut ctx, caller, 20, Some(value));
+        module.redefine_function(caller, &mut ctx).unwrap();
+        module.finalize_definitions();
+        assert_eq!(module.get_finalized_function(caller), code);
+        assert_eq!(caller_fn(), 22);
+
+        unsafe { module.finish().free_memory() };
+    }
+
+    #[test]
+    fn reuse_redefined_code() {
+        let mut builder = SimpleJITBuilder::new(default_libcall_names());
+        builder.hotswap(true);
+        let mut module: Module<SimpleJITBackend> = Module::new(builder);
+        let value = module
+            .declare_function("value", Linkage::Local, &signature())
+            .unwrap();
+
+        let mut ctx = Context::new();
+        build_function(&mut ctx, value, 0, None);
+        module.define_function(value, &mut ctx).unwrap();
+        module.finalize_definitions();
+        build_function(&mut ctx, value, 1, None);
+        module.redefine_function(value, &mut ctx).unwrap();
+        module.finalize_definitions();
+        let usage = module.backend().memory_usage();
+
+        // The code of each new definition goes where the code of the previous one was freed.
+        let code = module.get_finalized_function(value);
+        let value_fn: extern "C" fn() -> i64 = unsafe { mem::transmute(code) };
+        for i in 2..10 {
+            build_function(&mut ctx, value, i, None);
+            module.redefine_function(value, &mut ctx).unwrap();
+            module.finalize_definitions();
+            assert_eq!(module.backend().memory_usage(), usage);
+            assert_eq!(value_fn(), i);
+        }
+
+        unsafe { module.finish().free_memory() };
+    }
+
+    #[test]
+    fn redefine_undefined_function() {
+        let mut builder = SimpleJITBuilder::new(default_libcall_names());
+        builder.hotswap(true);
+        let mut module: Module<SimpleJITBackend> = Module::new(builder);
+        let value = module
+            .declare_function("value", Linkage::Local, &signature())
+            .unwrap();
+
+        let mut ctx = Context::new();
+        build_function(&mut ctx, value, 3, None);
+        module.redefine_function(value, &mut ctx).unwrap();
+        module.finalize_definitions();
+
+        let code = module.get_finalized_function(value);
+        let value_fn: extern "C" fn() -> i64 = unsafe { mem::transmute(code) };
+        assert_eq!(value_fn(), 3);
+    }
+
+    #[test]
+    fn error_without_hotswap() {
+        let mut module: Module<SimpleJITBackend> =
+            Module::new(SimpleJITBuilder::new(default_libcall_names()));
+        let value = module
+            .declare_function("value", Linkage::Local, &signature())
+            .unwrap();
+
+        let mut ctx = Context::new();
+        build_function(&mut ctx, value, 1, None);
+        module.define_function(value, &mut ctx).unwrap();
+        build_function(&mut ctx, value, 2, None);
+        module.redefine_function(value, &mut ctx).err().unwrap();
+    }
+}
+
+#[cfg(feature = "cache")]
+mod cache {
+    use super::*;
+    use cranelift_codegen::cache::CompilationCache;
+    use std::mem;
+    use std::sync::Arc;
+
+    fn signature() -> Signature {
+        Signature {
+            params: vec![types::I64].into_iter().map(AbiParam::new).collect(),
+            returns: vec![AbiParam::new(types::I64)],
+            call_conv: CallConv::SystemV,
+        }
+    }
+
+    /// Build the function `id` adding `value` to its argument, and passing the sum to `callee` if
+    /// any.
+    fn build_function(ctx: &mut Context, id: FuncId, value: i64, callee: Option<FuncId>) {
+        ctx.clear();
+        let func = Function::with_name_signature(ExternalName::user(0, id.as_u32()), signature());
+        let mut bcx: FunctionBuilder = FunctionBuilder::new(func);
+        let ebb = bcx.create_ebb();
+        bcx.switch_to_block(ebb);
+        bcx.append_ebb_params_for_function_params(ebb);
+        let x = bcx.ebb_params(ebb)[0];
+        let mut result = bcx.ins().iadd_imm(x, value);
+        if let Some(callee) = callee {
+            let sig = bcx.import_signature(signature());
+            let callee = bcx.import_function(ExtFuncData {
+                name: ExternalName::user(0, callee.as_u32()),
+                signature: sig,
+                colocated: false,
+            });
+            let call = bcx.ins().call(callee, &[result]);
+            result = bcx.inst_results(call)[0];
+        }
+        bcx.ins().return_(&[result]);
+        bcx.seal_all_blocks();
+        ctx.func = bcx.finalize();
+    }
+
+    /// Define and run a module where `add_three` calls `add_offset`, using `cache`.
+    fn run_module(cache: &Arc<CompilationCache>, offset: i64) -> i64 {
+        let mut module: Module<SimpleJITBackend> =
+            Module::new(SimpleJITBuilder::new(default_libcall_names()));
+        module.set_compilation_cache(Arc::clone(cache));
+        let add_offset = module
+            .declare_function("add_offset", Linkage::Local, &signature())
+            .unwrap();
+        let add_three = module
+            .declare_function("add_three", Linkage::Local, &signature())
+            .unwrap();
+
+        let mut ctx = Context::new();
+        build_function(&mut ctx, add_offset, offset, None);
+        let add_offset_size = module.define_function(add_offset, &mut ctx).unwrap();
+        build_function(&mut ctx, add_three, 3, Some(add_offset));
+        let add_three_size = module.define_function(add_three, &mut ctx).unwrap();
+        assert!(add_offset_size > 0 && add_three_size > 0);
+        module.finalize_definitions();
+
+        let code = module.get_finalized_function(add_three);
+        let add_three: extern "C" fn(i64) -> i64 = unsafe { mem::transmute(code) };
+        let result = add_three(4);
+        unsafe { module.finish().free_memory() };
+        result
+    }
+
+    #[test]
+    fn reuse_compiled_functions() {
+        let cache = Arc::new(CompilationCache::in_memory());
+        assert_eq!(run_module(&cache, 0), 7);
+        assert_eq!((cache.hits(), cache.misses()), (0, 2));
+
+        // The relocations of the cached code are applied in the new module.
+        assert_eq!(run_module(&cache, 0), 7);
+        assert_eq!((cache.hits(), cache.misses()), (2, 2));
+
+        // Changing a function only recompiles that function.
+        assert_eq!(run_module(&cache, 10), 17);
+        assert_eq!((cache.hits(), cache.misses()), (3, 3));
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod batch {
+    use super::*;
+    use std::mem;
+
+    fn signature() -> Signature {
+        Signature {
+            params: vec![],
+            returns: vec![AbiParam::new(types::I64)],
+            call_conv: CallConv::SystemV,
+        }
+    }
+
+    /// Build the function `id` returning `value`, plus the result of `callee` if any.
+    fn build_function(id: FuncId, value: i64, callee: Option<FuncId>) -> Context {
+        let mut ctx = Context::new();
+        let func = Function::with_name_signature(ExternalName::user(0, id.as_u32()), signature());
+        let mut bcx: FunctionBuilder = FunctionBuilder::new(func);
+        let ebb = bcx.create_ebb();
+        bcx.switch_to_block(ebb);
+        let mut result = bcx.ins().iconst(types::I64, value);
+        if let Some(callee) = callee {
+            let sig = bcx.import_signature(signature());
+            let callee = bcx.import_function(ExtFuncData {
+                name: ExternalName::user(0, callee.as_u32()),
+                signature: sig,
+                colocated: true,
+            });
+            let call = bcx.ins().call(callee, &[]);
+            let callee_result = bcx.inst_results(call)[0];
+            result = bcx.ins().iadd(result, callee_result);
+        }
+        bcx.ins().return_(&[result]);
+        bcx.seal_all_blocks();
+        ctx.func = bcx.finalize();
+        ctx
+    }
+
+    /// Declare `count` functions, where each function calls the previous one.
+    fn declare_chain(module: &mut Module<SimpleJITBackend>, count: usize) -> Vec<FuncId> {
+        (0..count)
+            .map(|i| {
+                module
+                    .declare_function(&format!("f{}", i), Linkage::Local, &signature())
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn define_many_functions() {
+        let mut module: Module<SimpleJITBackend> =
+            Module::new(SimpleJITBuilder::new(default_libcall_names()));
+        let funcs = declare_chain(&mut module, 50);
+        let defs = funcs
+            .iter()
+            .enumerate()
+            .map(|(i, &func)| {
+                let callee = if i == 0 { None } else { Some(funcs[i - 1]) };
+                (func, build_function(func, i as i64, callee))
+            })
+            .collect();
+
+        // The bodies come back in the order of the definitions.
+        let bodies = module.define_functions(defs).unwrap();
+        let order: Vec<_> = bodies.iter().map(CompiledFunctionBody::func).collect();
+        assert_eq!(order, funcs);
+        assert!(bodies.iter().all(|body| body.total_size() > 0));
+        module.finalize_definitions();
+
+        let code = module.get_finalized_function(funcs[49]);
+        let last: extern "C" fn() -> i64 = unsafe { mem::transmute(code) };
+        assert_eq!(last(), (0..50).sum());
+
+        unsafe { module.finish().free_memory() };
+    }
+
+    #[test]
+    fn define_compiled_bodies() {
+        let mut module: Module<SimpleJITBackend> =
+            Module::new(SimpleJITBuilder::new(default_libcall_names()));
+        let funcs = declare_chain(&mut module, 2);
+
+        // Compiling a body doesn't need the module, only its compiler.
+        let compiler = module.compiler();
+        let first = compiler
+            .compile(funcs[0], build_function(funcs[0], 1, None))
+            .unwrap();
+        let second = compiler
+            .compile(funcs[1], build_function(funcs[1], 2, Some(funcs[0])))
+            .unwrap();
+        module.define_compiled_function(&second).unwrap();
+        module.define_compiled_function(&first).unwrap();
+        module.finalize_definitions();
+
+        let code = module.get_finalized_function(funcs[1]);
+        let second_fn: extern "C" fn() -> i64 = unsafe { mem::transmute(code) };
+        assert_eq!(second_fn(), 3);
+    }
+
+    #[test]
+    fn incompatible_target() {
+        use cranelift_codegen::settings::{self, Configurable};
+
+        let mut module: Module<SimpleJITBackend> =
+            Module::new(SimpleJITBuilder::new(default_libcall_names()));
+        let funcs = declare_chain(&mut module, 1);
+
+        // The same ISA with different settings doesn't produce the same code.
+        let mut flag_builder = settings::builder();
+        flag_builder.set("opt_level", "best").unwrap();
+        let isa = cranelift_native::builder()
+            .unwrap()
+            .finish(settings::Flags::new(flag_builder));
+        let mut other: Module<SimpleJITBackend> =
+            Module::new(SimpleJITBuilder::with_isa(isa, default_libcall_names()));
+        declare_chain(&mut other, 1);
+
+        let body = other
+            .compiler()
+            .compile(funcs[0], build_function(funcs[0], 1, None))
+            .unwrap();
+        match module.define_compiled_function(&body) {
+            Err(ModuleError::IncompatibleTarget(name)) => assert_eq!(name, "f0"),
+            _ => panic!("expected an incompatible target error"),
+        }
+        assert!(module.declared_functions().all(|f| f.compiled.is_none()));
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn same_code_on_any_number_of_threads() {
+        use cranelift_codegen::binemit::{NullRelocSink, NullStackmapSink, NullTrapSink};
+
+        // Define a chain of functions on a pool of `threads` threads, and emit their code.
+        let emit = |threads| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            pool.install(|| {
+                let mut module: Module<SimpleJITBackend> =
+                    Module::new(SimpleJITBuilder::new(default_libcall_names()));
+                let funcs = declare_chain(&mut module, 50);
+                let defs = funcs
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &func)| {
+                        let callee = if i == 0 { None } else { Some(funcs[i - 1]) };
+                        (func, build_function(func, i as i64, callee))
+                    })
+                    .collect();
+                let bodies = module.define_functions(defs).unwrap();
+                let code: Vec<Vec<u8>> = bodies
+                    .iter()
+                    .map(|body| {
+                        let mut code = vec![0; body.total_size() as usize];
+                        unsafe {
+                            body.context().emit_to_memory(
+                                module.isa(),
+                                code.as_mut_ptr(),
+                                &mut NullRelocSink {},
+                                &mut NullTrapSink {},
+                                &mut NullStackmapSink {},
+                            )
+                        };
+                        code
+                    })
+                    .collect();
+                unsafe { module.finish().free_memory() };
+                code
+            })
+        };
+        assert_eq!(emit(1), emit(4));
+    }
+
+    #[test]
+    fn duplicate_definition_in_batch() {
+        let mut module: Module<SimpleJITBackend> =
+            Module::new(SimpleJITBuilder::new(default_libcall_names()));
+        let funcs = declare_chain(&mut module, 2);
+        let defs = vec![
+            (funcs[0], build_function(funcs[0], 1, None)),
+            (funcs[1], build_function(funcs[1], 2, None)),
+            (funcs[0], build_function(funcs[0], 3, None)),
+        ];
+        match module.define_functions(defs) {
+            Err(ModuleError::DuplicateDefinition(name)) => assert_eq!(name, "f0"),
+            _ => panic!("expected a duplicate definition error"),
+        }
+
+        // None of the functions were defined.
+        assert!(module.declared_functions().all(|f| f.compiled.is_none()));
+    }
+}
@@ -28,7 +28,9 @@ use crate::state::{ControlStackFrame, TranslationState};
 use crate::translation_utils::{
     blocktype_to_type, f32_translation, f64_translation, num_return_values,
 };
-use crate::translation_utils::{FuncIndex, MemoryIndex, SignatureIndex, TableIndex};
+use crate::translation_utils::{
+    DataIndex, ElemIndex, FuncIndex, MemoryIndex, SignatureIndex, TableIndex,
+};
 use crate::wasm_unsupported;
 use core::{i32, u32};
 use cranelift_codegen::ir::condcodes::{FloatCC, IntCC};
@@ -841,334 +843,1203 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
             let val = builder.ins().is_null(arg);
             state.push1(val);
         }
-        Operator::Wake { .. }
-        | Operator::I32Wait { .. }
-        | Operator::I64Wait { .. }
-        | Operator::I32AtomicLoad { .. }
-        | Operator::I64AtomicLoad { .. }
-        | Operator::I32AtomicLoad8U { .. }
-        | Operator::I32AtomicLoad16U { .. }
-        | Operator::I64AtomicLoad8U { .. }
-        | Operator::I64AtomicLoad16U { .. }
-        | Operator::I64AtomicLoad32U { .. }
-        | Operator::I32AtomicStore { .. }
-        | Operator::I64AtomicStore { .. }
-        | Operator::I32AtomicStore8 { .. }
-        | Operator::I32AtomicStore16 { .. }
-        | Operator::I64AtomicStore8 { .. }
-        | Operator::I64AtomicStore16 { .. }
-        | Operator::I64AtomicStore32 { .. }
-        | Operator::I32AtomicRmwAdd { .. }
-        | Operator::I64AtomicRmwAdd { .. }
-        | Operator::I32AtomicRmw8UAdd { .. }
-        | Operator::I32AtomicRmw16UAdd { .. }
-        | Operator::I64AtomicRmw8UAdd { .. }
-        | Operator::I64AtomicRmw16UAdd { .. }
-        | Operator::I64AtomicRmw32UAdd { .. }
-        | Operator::I32AtomicRmwSub { .. }
-        | Operator::I64AtomicRmwSub { .. }
-        | Operator::I32AtomicRmw8USub { .. }
-        | Operator::I32AtomicRmw16USub { .. }
-        | Operator::I64AtomicRmw8USub { .. }
-        | Operator::I64AtomicRmw16USub { .. }
-        | Operator::I64AtomicRmw32USub { .. }
-        | Operator::I32AtomicRmwAnd { .. }
-        | Operator::I64AtomicRmwAnd { .. }
-        | Operator::I32AtomicRmw8UAnd { .. }
-        | Operator::I32AtomicRmw16UAnd { .. }
-        | Operator::I64AtomicRmw8UAnd { .. }
-        | Operator::I64AtomicRmw16UAnd { .. }
-        | Operator::I64AtomicRmw32UAnd { .. }
-        | Operator::I32AtomicRmwOr { .. }
-        | Operator::I64AtomicRmwOr { .. }
-        | Operator::I32AtomicRmw8UOr { .. }
-        | Operator::I32AtomicRmw16UOr { .. }
-        | Operator::I64AtomicRmw8UOr { .. }
-        | Operator::I64AtomicRmw16UOr { .. }
-        | Operator::I64AtomicRmw32UOr { .. }
-        | Operator::I32AtomicRmwXor { .. }
-        | Operator::I64AtomicRmwXor { .. }
-        | Operator::I32AtomicRmw8UXor { .. }
-        | Operator::I32AtomicRmw16UXor { .. }
-        | Operator::I64AtomicRmw8UXor { .. }
-        | Operator::I64AtomicRmw16UXor { .. }
-        | Operator::I64AtomicRmw32UXor { .. }
-        | Operator::I32AtomicRmwXchg { .. }
-        | Operator::I64AtomicRmwXchg { .. }
-        | Operator::I32AtomicRmw8UXchg { .. }
-        | Operator::I32AtomicRmw16UXchg { .. }
-        | Operator::I64AtomicRmw8UXchg { .. }
-        | Operator::I64AtomicRmw16UXchg { .. }
-        | Operator::I64AtomicRmw32UXchg { .. }
-        | Operator::I32AtomicRmwCmpxchg { .. }
-        | Operator::I64AtomicRmwCmpxchg { .. }
-        | Operator::I32AtomicRmw8UCmpxchg { .. }
-        | Operator::I32AtomicRmw16UCmpxchg { .. }
-        | Operator::I64AtomicRmw8UCmpxchg { .. }
-        | Operator::I64AtomicRmw16UCmpxchg { .. }
-        | Operator::I64AtomicRmw32UCmpxchg { .. }
-        | Operator::Fence { .. } => {
-            wasm_unsupported!("proposed thread operator {:?}", op);
-        }
-        Operator::MemoryInit { .. }
-        | Operator::DataDrop { .. }
-        | Operator::MemoryCopy
-        | Operator::MemoryFill
-        | Operator::TableInit { .. }
-        | Operator::ElemDrop { .. }
-        | Operator::TableCopy
-        | Operator::TableGet { .. }
-        | Operator::TableSet { .. }
-        | Operator::TableGrow { .. }
-        | Operator::TableSize { .. } => {
-            wasm_unsupported!("proposed bulk memory operator {:?}", op);
+        /******************************* Atomic instructions *********************************
+         * Atomic accesses must be naturally aligned and trap otherwise. Narrow accesses operate
+         * on the low bits of their operands and zero-extend their results.
+         ************************************************************************************/
+        Operator::Wake {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            let heap_index = MemoryIndex::from_u32(0);
+            let heap = state.get_heap(&mut builder.func, 0, environ)?;
+            let count = state.pop1();
+            let addr = translate_atomic_addr(heap, *offset, I32, builder, state, environ)?;
+            state.push1(environ.translate_atomic_notify(
+                builder.cursor(),
+                heap_index,
+                heap,
+                addr,
+                count,
+            )?);
         }
-        Operator::V128Const { value } => {
-            let handle = builder.func.dfg.constants.insert(value.bytes().to_vec());
-            let value = builder.ins().vconst(I8X16, handle);
-            // the v128.const is typed in CLIF as a I8x16 but raw_bitcast to a different type before use
-            state.push1(value)
+        Operator::I32Wait {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            let heap_index = MemoryIndex::from_u32(0);
+            let heap = state.get_heap(&mut builder.func, 0, environ)?;
+            let (expected, timeout) = state.pop2();
+            let addr = translate_atomic_addr(heap, *offset, I32, builder, state, environ)?;
+            state.push1(environ.translate_atomic_wait(
+                builder.cursor(),
+                heap_index,
+                heap,
+                addr,
+                expected,
+                timeout,
+            )?);
+        }
+        Operator::I64Wait {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            let heap_index = MemoryIndex::from_u32(0);
+            let heap = state.get_heap(&mut builder.func, 0, environ)?;
+            let (expected, timeout) = state.pop2();
+            let addr = translate_atomic_addr(heap, *offset, I64, builder, state, environ)?;
+            state.push1(environ.translate_atomic_wait(
+                builder.cursor(),
+                heap_index,
+                heap,
+                addr,
+                expected,
+                timeout,
+            )?);
+        }
+        Operator::I32AtomicLoad {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_load(*offset, I32, I32, builder, state, environ)?;
         }
-        Operator::I8x16Splat
-        | Operator::I16x8Splat
-        | Operator::I32x4Splat
-        | Operator::I64x2Splat
-        | Operator::F32x4Splat
-        | Operator::F64x2Splat => {
-            let value_to_splat = state.pop1();
-            let ty = type_of(op);
-            let splatted = builder.ins().splat(ty, value_to_splat);
-            state.push1(splatted)
+        Operator::I64AtomicLoad {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_load(*offset, I64, I64, builder, state, environ)?;
         }
-        Operator::I32x4ExtractLane { lane }
-        | Operator::I64x2ExtractLane { lane }
-        | Operator::F32x4ExtractLane { lane }
-        | Operator::F64x2ExtractLane { lane } => {
-            let vector = optionally_bitcast_vector(state.pop1(), type_of(op), builder);
-            state.push1(builder.ins().extractlane(vector, lane.clone()))
+        Operator::I32AtomicLoad8U {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_load(*offset, I32, I8, builder, state, environ)?;
         }
-        Operator::I8x16ReplaceLane { lane }
-        | Operator::I16x8ReplaceLane { lane }
-        | Operator::I32x4ReplaceLane { lane }
-        | Operator::I64x2ReplaceLane { lane }
-        | Operator::F32x4ReplaceLane { lane }
-        | Operator::F64x2ReplaceLane { lane } => {
-            let (vector, replacement_value) = state.pop2();
-            let original_vector_type = builder.func.dfg.value_type(vector);
-            let vector = optionally_bitcast_vector(vector, type_of(op), builder);
-            let replaced_vector = builder
-                .ins()
-                .insertlane(vector, lane.clone(), replacement_value);
-            state.push1(optionally_bitcast_vector(
-                replaced_vector,
-                original_vector_type,
-                builder,
-            ))
+        Operator::I32AtomicLoad16U {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_load(*offset, I32, I16, builder, state, environ)?;
         }
-        Operator::V128Load { .. }
-        | Operator::V128Store { .. }
-        | Operator::I8x16ExtractLaneS { .. }
-        | Operator::I8x16ExtractLaneU { .. }
-        | Operator::I16x8ExtractLaneS { .. }
-        | Operator::I16x8ExtractLaneU { .. }
-        | Operator::V8x16Shuffle { .. }
-        | Operator::I8x16Eq
-        | Operator::I8x16Ne
-        | Operator::I8x16LtS
-        | Operator::I8x16LtU
-        | Operator::I8x16GtS
-        | Operator::I8x16GtU
-        | Operator::I8x16LeS
-        | Operator::I8x16LeU
-        | Operator::I8x16GeS
-        | Operator::I8x16GeU
-        | Operator::I16x8Eq
-        | Operator::I16x8Ne
-        | Operator::I16x8LtS
-        | Operator::I16x8LtU
-        | Operator::I16x8GtS
-        | Operator::I16x8GtU
-        | Operator::I16x8LeS
-        | Operator::I16x8LeU
-        | Operator::I16x8GeS
-        | Operator::I16x8GeU
-        | Operator::I32x4Eq
-        | Operator::I32x4Ne
-        | Operator::I32x4LtS
-        | Operator::I32x4LtU
-        | Operator::I32x4GtS
-        | Operator::I32x4GtU
-        | Operator::I32x4LeS
-        | Operator::I32x4LeU
-        | Operator::I32x4GeS
-        | Operator::I32x4GeU
-        | Operator::F32x4Eq
-        | Operator::F32x4Ne
-        | Operator::F32x4Lt
-        | Operator::F32x4Gt
-        | Operator::F32x4Le
-        | Operator::F32x4Ge
-        | Operator::F64x2Eq
-        | Operator::F64x2Ne
-        | Operator::F64x2Lt
-        | Operator::F64x2Gt
-        | Operator::F64x2Le
-        | Operator::F64x2Ge
-        | Operator::V128Not
-        | Operator::V128And
-        | Operator::V128Or
-        | Operator::V128Xor
-        | Operator::V128Bitselect
-        | Operator::I8x16Neg
-        | Operator::I8x16AnyTrue
-        | Operator::I8x16AllTrue
-        | Operator::I8x16Shl
-        | Operator::I8x16ShrS
-        | Operator::I8x16ShrU
-        | Operator::I8x16Add
-        | Operator::I8x16AddSaturateS
-        | Operator::I8x16AddSaturateU
-        | Operator::I8x16Sub
-        | Operator::I8x16SubSaturateS
-        | Operator::I8x16SubSaturateU
-        | Operator::I8x16Mul
-        | Operator::I16x8Neg
-        | Operator::I16x8AnyTrue
-        | Operator::I16x8AllTrue
-        | Operator::I16x8Shl
-        | Operator::I16x8ShrS
-        | Operator::I16x8ShrU
-        | Operator::I16x8Add
-        | Operator::I16x8AddSaturateS
-        | Operator::I16x8AddSaturateU
-        | Operator::I16x8Sub
-        | Operator::I16x8SubSaturateS
-        | Operator::I16x8SubSaturateU
-        | Operator::I16x8Mul
-        | Operator::I32x4Neg
-        | Operator::I32x4AnyTrue
-        | Operator::I32x4AllTrue
-        | Operator::I32x4Shl
-        | Operator::I32x4ShrS
-        | Operator::I32x4ShrU
-        | Operator::I32x4Add
-        | Operator::I32x4Sub
-        | Operator::I32x4Mul
-        | Operator::I64x2Neg
-        | Operator::I64x2AnyTrue
-        | Operator::I64x2AllTrue
-        | Operator::I64x2Shl
-        | Operator::I64x2ShrS
-        | Operator::I64x2ShrU
-        | Operator::I64x2Add
-        | Operator::I64x2Sub
-        | Operator::F32x4Abs
-        | Operator::F32x4Neg
-        | Operator::F32x4Sqrt
-        | Operator::F32x4Add
-        | Operator::F32x4Sub
-        | Operator::F32x4Mul
-        | Operator::F32x4Div
-        | Operator::F32x4Min
-        | Operator::F32x4Max
-        | Operator::F64x2Abs
-        | Operator::F64x2Neg
-        | Operator::F64x2Sqrt
-        | Operator::F64x2Add
-        | Operator::F64x2Sub
-        | Operator::F64x2Mul
-        | Operator::F64x2Div
-        | Operator::F64x2Min
-        | Operator::F64x2Max
-        | Operator::I32x4TruncSF32x4Sat
-        | Operator::I32x4TruncUF32x4Sat
-        | Operator::I64x2TruncSF64x2Sat
-        | Operator::I64x2TruncUF64x2Sat
-        | Operator::F32x4ConvertSI32x4
-        | Operator::F32x4ConvertUI32x4
-        | Operator::F64x2ConvertSI64x2
-        | Operator::F64x2ConvertUI64x2 { .. }
-        | Operator::V8x16Swizzle
-        | Operator::I8x16LoadSplat { .. }
-        | Operator::I16x8LoadSplat { .. }
-        | Operator::I32x4LoadSplat { .. }
-        | Operator::I64x2LoadSplat { .. } => {
-            wasm_unsupported!("proposed SIMD operator {:?}", op);
+        Operator::I64AtomicLoad8U {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_load(*offset, I64, I8, builder, state, environ)?;
         }
-    };
-    Ok(())
-}
-
-// Clippy warns us of some fields we are deliberately ignoring
-#[cfg_attr(feature = "cargo-clippy", allow(clippy::unneeded_field_pattern))]
-/// Deals with a Wasm instruction located in an unreachable portion of the code. Most of them
-/// are dropped but special ones like `End` or `Else` signal the potential end of the unreachable
-/// portion so the translation state must be updated accordingly.
-fn translate_unreachable_operator(
-    op: &Operator,
-    builder: &mut FunctionBuilder,
-    state: &mut TranslationState,
-) {
-    match *op {
-        Operator::If { ty: _ } => {
-            // Push a placeholder control stack entry. The if isn't reachable,
-            // so we don't have any branches anywhere.
-            state.push_if(ir::Inst::reserved_value(), ir::Ebb::reserved_value(), 0);
+        Operator::I64AtomicLoad16U {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_load(*offset, I64, I16, builder, state, environ)?;
         }
-        Operator::Loop { ty: _ } | Operator::Block { ty: _ } => {
-            state.push_block(ir::Ebb::reserved_value(), 0);
+        Operator::I64AtomicLoad32U {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_load(*offset, I64, I32, builder, state, environ)?;
         }
-        Operator::Else => {
-            let i = state.control_stack.len() - 1;
-            if let ControlStackFrame::If {
-                branch_inst,
-                ref mut reachable_from_top,
-                ..
-            } = state.control_stack[i]
-            {
-                if *reachable_from_top {
-                    // We have a branch from the top of the if to the else.
-                    state.reachable = true;
-                    // And because there's an else, there can no longer be a
-                    // branch from the top directly to the end.
-                    *reachable_from_top = false;
-
-                    // We change the target of the branch instruction
-                    let else_ebb = builder.create_ebb();
-                    builder.change_jump_destination(branch_inst, else_ebb);
-                    builder.seal_block(else_ebb);
-                    builder.switch_to_block(else_ebb);
-                }
-            }
+        Operator::I32AtomicStore {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_store(*offset, I32, builder, state, environ)?;
         }
-        Operator::End => {
-            let stack = &mut state.stack;
-            let control_stack = &mut state.control_stack;
-            let frame = control_stack.pop().unwrap();
-
-            // Now we have to split off the stack the values not used
-            // by unreachable code that hasn't been translated
-            stack.truncate(frame.original_stack_size());
-
-            let reachable_anyway = match frame {
-                // If it is a loop we also have to seal the body loop block
-                ControlStackFrame::Loop { header, .. } => {
-                    builder.seal_block(header);
-                    // And loops can't have branches to the end.
-                    false
-                }
-                ControlStackFrame::If {
-                    reachable_from_top, ..
-                } => {
-                    // A reachable if without an else has a branch from the top
-                    // directly to the bottom.
-                    reachable_from_top
-                }
-                // All other control constructs are already handled.
-                _ => false,
-            };
-
-            if frame.exit_is_branched_to() || reachable_anyway {
+        Operator::I64AtomicStore {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_store(*offset, I64, builder, state, environ)?;
+        }
+        Operator::I32AtomicStore8 {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_store(*offset, I8, builder, state, environ)?;
+        }
+        Operator::I32AtomicStore16 {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_store(*offset, I16, builder, state, environ)?;
+        }
+        Operator::I64AtomicStore8 {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_store(*offset, I8, builder, state, environ)?;
+        }
+        Operator::I64AtomicStore16 {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_store(*offset, I16, builder, state, environ)?;
+        }
+        Operator::I64AtomicStore32 {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_store(*offset, I32, builder, state, environ)?;
+        }
+        Operator::I32AtomicRmwAdd {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwAdd,
+                I32,
+                I32,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I64AtomicRmwAdd {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwAdd,
+                I64,
+                I64,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I32AtomicRmw8UAdd {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwAdd,
+                I32,
+                I8,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I32AtomicRmw16UAdd {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwAdd,
+                I32,
+                I16,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I64AtomicRmw8UAdd {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwAdd,
+                I64,
+                I8,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I64AtomicRmw16UAdd {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwAdd,
+                I64,
+                I16,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I64AtomicRmw32UAdd {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwAdd,
+                I64,
+                I32,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I32AtomicRmwSub {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwSub,
+                I32,
+                I32,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I64AtomicRmwSub {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwSub,
+                I64,
+                I64,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I32AtomicRmw8USub {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwSub,
+                I32,
+                I8,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I32AtomicRmw16USub {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwSub,
+                I32,
+                I16,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I64AtomicRmw8USub {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwSub,
+                I64,
+                I8,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I64AtomicRmw16USub {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwSub,
+                I64,
+                I16,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I64AtomicRmw32USub {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwSub,
+                I64,
+                I32,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I32AtomicRmwAnd {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwAnd,
+                I32,
+                I32,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I64AtomicRmwAnd {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwAnd,
+                I64,
+                I64,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I32AtomicRmw8UAnd {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwAnd,
+                I32,
+                I8,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I32AtomicRmw16UAnd {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwAnd,
+                I32,
+                I16,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I64AtomicRmw8UAnd {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwAnd,
+                I64,
+                I8,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I64AtomicRmw16UAnd {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwAnd,
+                I64,
+                I16,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I64AtomicRmw32UAnd {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwAnd,
+                I64,
+                I32,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I32AtomicRmwOr {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwOr,
+                I32,
+                I32,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I64AtomicRmwOr {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwOr,
+                I64,
+                I64,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I32AtomicRmw8UOr {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwOr,
+                I32,
+                I8,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I32AtomicRmw16UOr {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwOr,
+                I32,
+                I16,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I64AtomicRmw8UOr {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwOr,
+                I64,
+                I8,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I64AtomicRmw16UOr {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwOr,
+                I64,
+                I16,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I64AtomicRmw32UOr {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwOr,
+                I64,
+                I32,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I32AtomicRmwXor {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwXor,
+                I32,
+                I32,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I64AtomicRmwXor {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwXor,
+                I64,
+                I64,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I32AtomicRmw8UXor {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwXor,
+                I32,
+                I8,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I32AtomicRmw16UXor {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwXor,
+                I32,
+                I16,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I64AtomicRmw8UXor {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwXor,
+                I64,
+                I8,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I64AtomicRmw16UXor {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwXor,
+                I64,
+                I16,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I64AtomicRmw32UXor {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwXor,
+                I64,
+                I32,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I32AtomicRmwXchg {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwXchg,
+                I32,
+                I32,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I64AtomicRmwXchg {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwXchg,
+                I64,
+                I64,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I32AtomicRmw8UXchg {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwXchg,
+                I32,
+                I8,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I32AtomicRmw16UXchg {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwXchg,
+                I32,
+                I16,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I64AtomicRmw8UXchg {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwXchg,
+                I64,
+                I8,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I64AtomicRmw16UXchg {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwXchg,
+                I64,
+                I16,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I64AtomicRmw32UXchg {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_rmw(
+                *offset,
+                ir::Opcode::AtomicRmwXchg,
+                I64,
+                I32,
+                builder,
+                state,
+                environ,
+            )?;
+        }
+        Operator::I32AtomicRmwCmpxchg {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_cas(*offset, I32, I32, builder, state, environ)?;
+        }
+        Operator::I64AtomicRmwCmpxchg {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_cas(*offset, I64, I64, builder, state, environ)?;
+        }
+        Operator::I32AtomicRmw8UCmpxchg {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_cas(*offset, I32, I8, builder, state, environ)?;
+        }
+        Operator::I32AtomicRmw16UCmpxchg {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_cas(*offset, I32, I16, builder, state, environ)?;
+        }
+        Operator::I64AtomicRmw8UCmpxchg {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_cas(*offset, I64, I8, builder, state, environ)?;
+        }
+        Operator::I64AtomicRmw16UCmpxchg {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_cas(*offset, I64, I16, builder, state, environ)?;
+        }
+        Operator::I64AtomicRmw32UCmpxchg {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_atomic_cas(*offset, I64, I32, builder, state, environ)?;
+        }
+        Operator::Fence { flags: _ } => {
+            builder.ins().fence();
+        }
+        /**************************** Bulk memory instructions *******************************
+         * Bulk memory and table operations are handled by the environment. They are usually
+         * translated into calls to special functions.
+         ************************************************************************************/
+        Operator::MemoryInit { segment } => {
+            // We don't yet support multiple linear memories.
+            let heap_index = MemoryIndex::from_u32(0);
+            let heap = state.get_heap(&mut builder.func, 0, environ)?;
+            let (dst, src, len) = state.pop3();
+            environ.translate_memory_init(
+                builder.cursor(),
+                heap_index,
+                heap,
+                DataIndex::from_u32(*segment),
+                dst,
+                src,
+                len,
+            )?;
+        }
+        Operator::DataDrop { segment } => {
+            environ.translate_data_drop(builder.cursor(), DataIndex::from_u32(*segment))?;
+        }
+        Operator::MemoryCopy => {
+            // We don't yet support multiple linear memories.
+            let heap_index = MemoryIndex::from_u32(0);
+            let heap = state.get_heap(&mut builder.func, 0, environ)?;
+            let (dst, src, len) = state.pop3();
+            environ.translate_memory_copy(builder.cursor(), heap_index, heap, dst, src, len)?;
+        }
+        Operator::MemoryFill => {
+            // We don't yet support multiple linear memories.
+            let heap_index = MemoryIndex::from_u32(0);
+            let heap = state.get_heap(&mut builder.func, 0, environ)?;
+            let (dst, val, len) = state.pop3();
+            environ.translate_memory_fill(builder.cursor(), heap_index, heap, dst, val, len)?;
+        }
+        Operator::TableInit { segment } => {
+            // The bulk memory proposal only supports table 0 here.
+            let table_index = TableIndex::from_u32(0);
+            let table = state.get_table(&mut builder.func, 0, environ)?;
+            let (dst, src, len) = state.pop3();
+            environ.translate_table_init(
+                builder.cursor(),
+                ElemIndex::from_u32(*segment),
+                table_index,
+                table,
+                dst,
+                src,
+                len,
+            )?;
+        }
+        Operator::ElemDrop { segment } => {
+            environ.translate_elem_drop(builder.cursor(), ElemIndex::from_u32(*segment))?;
+        }
+        Operator::TableCopy => {
+            // The bulk memory proposal only supports table 0 here.
+            let table_index = TableIndex::from_u32(0);
+            let table = state.get_table(&mut builder.func, 0, environ)?;
+            let (dst, src, len) = state.pop3();
+            environ.translate_table_copy(
+                builder.cursor(),
+                table_index,
+                table,
+                table_index,
+                table,
+                dst,
+                src,
+                len,
+            )?;
+        }
+        /**************************** Reference types instructions ***************************
+         * Table accesses are handled by the environment, which knows the layout of tables.
+         ************************************************************************************/
+        Operator::TableGet { table } => {
+            let table_index = TableIndex::from_u32(*table);
+            let table = state.get_table(&mut builder.func, *table, environ)?;
+            let elem_index = state.pop1();
+            state.push1(environ.translate_table_get(
+                builder.cursor(),
+                table_index,
+                table,
+                elem_index,
+            )?);
+        }
+        Operator::TableSet { table } => {
+            let table_index = TableIndex::from_u32(*table);
+            let table = state.get_table(&mut builder.func, *table, environ)?;
+            let (elem_index, value) = state.pop2();
+            environ.translate_table_set(builder.cursor(), table_index, table, value, elem_index)?;
+        }
+        Operator::TableGrow { table } => {
+            let table_index = TableIndex::from_u32(*table);
+            let table = state.get_table(&mut builder.func, *table, environ)?;
+            let (init_value, delta) = state.pop2();
+            state.push1(environ.translate_table_grow(
+                builder.cursor(),
+                table_index,
+                table,
+                delta,
+                init_value,
+            )?);
+        }
+        Operator::TableSize { table } => {
+            let table_index = TableIndex::from_u32(*table);
+            let table = state.get_table(&mut builder.func, *table, environ)?;
+            state.push1(environ.translate_table_size(builder.cursor(), table_index, table)?);
+        }
+        Operator::V128Const { value } => {
+            let handle = builder.func.dfg.constants.insert(value.bytes().to_vec());
+            let value = builder.ins().vconst(I8X16, handle);
+            // the v128.const is typed in CLIF as a I8x16 but raw_bitcast to a different type before use
+            state.push1(value)
+        }
+        Operator::I8x16Splat
+        | Operator::I16x8Splat
+        | Operator::I32x4Splat
+        | Operator::I64x2Splat
+        | Operator::F32x4Splat
+        | Operator::F64x2Splat => {
+            let value_to_splat = state.pop1();
+            let ty = type_of(op);
+            let splatted = builder.ins().splat(ty, value_to_splat);
+            state.push1(splatted)
+        }
+        Operator::I32x4ExtractLane { lane }
+        | Operator::I64x2ExtractLane { lane }
+        | Operator::F32x4ExtractLane { lane }
+        | Operator::F64x2ExtractLane { lane } => {
+            let vector = optionally_bitcast_vector(state.pop1(), type_of(op), builder);
+            state.push1(builder.ins().extractlane(vector, lane.clone()))
+        }
+        Operator::I8x16ReplaceLane { lane }
+        | Operator::I16x8ReplaceLane { lane }
+        | Operator::I32x4ReplaceLane { lane }
+        | Operator::I64x2ReplaceLane { lane }
+        | Operator::F32x4ReplaceLane { lane }
+        | Operator::F64x2ReplaceLane { lane } => {
+            let (vector, replacement_value) = state.pop2();
+            let original_vector_type = builder.func.dfg.value_type(vector);
+            let vector = optionally_bitcast_vector(vector, type_of(op), builder);
+            let replaced_vector = builder
+                .ins()
+                .insertlane(vector, lane.clone(), replacement_value);
+            state.push1(optionally_bitcast_vector(
+                replaced_vector,
+                original_vector_type,
+                builder,
+            ))
+        }
+        Operator::V128Load {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_load(*offset, ir::Opcode::Load, I8X16, builder, state, environ)?;
+        }
+        Operator::V128Store {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            translate_store(*offset, ir::Opcode::Store, builder, state, environ)?;
+        }
+        Operator::I8x16LoadSplat {
+            memarg: MemoryImmediate { flags: _, offset },
+        }
+        | Operator::I16x8LoadSplat {
+            memarg: MemoryImmediate { flags: _, offset },
+        }
+        | Operator::I32x4LoadSplat {
+            memarg: MemoryImmediate { flags: _, offset },
+        }
+        | Operator::I64x2LoadSplat {
+            memarg: MemoryImmediate { flags: _, offset },
+        } => {
+            let ty = type_of(op);
+            translate_load(
+                *offset,
+                ir::Opcode::Load,
+                ty.lane_type(),
+                builder,
+                state,
+                environ,
+            )?;
+            let loaded = state.pop1();
+            state.push1(builder.ins().splat(ty, loaded))
+        }
+        Operator::I8x16ExtractLaneS { lane } | Operator::I16x8ExtractLaneS { lane } => {
+            let vector = optionally_bitcast_vector(state.pop1(), type_of(op), builder);
+            let extracted = builder.ins().extractlane(vector, lane.clone());
+            state.push1(builder.ins().sextend(I32, extracted))
+        }
+        Operator::I8x16ExtractLaneU { lane } | Operator::I16x8ExtractLaneU { lane } => {
+            let vector = optionally_bitcast_vector(state.pop1(), type_of(op), builder);
+            let extracted = builder.ins().extractlane(vector, lane.clone());
+            state.push1(builder.ins().uextend(I32, extracted))
+        }
+        Operator::V8x16Shuffle { lanes, .. } => {
+            let (vector_a, vector_b) = state.pop2();
+            let a = optionally_bitcast_vector(vector_a, I8X16, builder);
+            let b = optionally_bitcast_vector(vector_b, I8X16, builder);
+            let mask = builder.func.dfg.constants.insert(lanes.to_vec());
+            state.push1(builder.ins().shuffle(a, b, mask))
+        }
+        Operator::V8x16Swizzle => {
+            let (vector, indices) = state.pop2();
+            let vector = optionally_bitcast_vector(vector, I8X16, builder);
+            let indices = optionally_bitcast_vector(indices, I8X16, builder);
+            state.push1(builder.ins().swizzle(vector, indices))
+        }
+        Operator::I8x16Eq | Operator::I16x8Eq | Operator::I32x4Eq => {
+            translate_vector_icmp(IntCC::Equal, type_of(op), builder, state)
+        }
+        Operator::I8x16Ne | Operator::I16x8Ne | Operator::I32x4Ne => {
+            translate_vector_icmp(IntCC::NotEqual, type_of(op), builder, state)
+        }
+        Operator::I8x16GtS | Operator::I16x8GtS | Operator::I32x4GtS => {
+            translate_vector_icmp(IntCC::SignedGreaterThan, type_of(op), builder, state)
+        }
+        Operator::I8x16LtS | Operator::I16x8LtS | Operator::I32x4LtS => {
+            translate_vector_icmp(IntCC::SignedLessThan, type_of(op), builder, state)
+        }
+        Operator::I8x16GtU | Operator::I16x8GtU | Operator::I32x4GtU => {
+            translate_vector_icmp(IntCC::UnsignedGreaterThan, type_of(op), builder, state)
+        }
+        Operator::I8x16LtU | Operator::I16x8LtU | Operator::I32x4LtU => {
+            translate_vector_icmp(IntCC::UnsignedLessThan, type_of(op), builder, state)
+        }
+        Operator::I8x16GeS | Operator::I16x8GeS | Operator::I32x4GeS => {
+            translate_vector_icmp(IntCC::SignedGreaterThanOrEqual, type_of(op), builder, state)
+        }
+        Operator::I8x16LeS | Operator::I16x8LeS | Operator::I32x4LeS => {
+            translate_vector_icmp(IntCC::SignedLessThanOrEqual, type_of(op), builder, state)
+        }
+        Operator::I8x16GeU | Operator::I16x8GeU | Operator::I32x4GeU => translate_vector_icmp(
+            IntCC::UnsignedGreaterThanOrEqual,
+            type_of(op),
+            builder,
+            state,
+        ),
+        Operator::I8x16LeU | Operator::I16x8LeU | Operator::I32x4LeU => {
+            translate_vector_icmp(IntCC::UnsignedLessThanOrEqual, type_of(op), builder, state)
+        }
+        Operator::F32x4Eq | Operator::F64x2Eq => {
+            translate_vector_fcmp(FloatCC::Equal, type_of(op), builder, state)
+        }
+        Operator::F32x4Ne | Operator::F64x2Ne => {
+            translate_vector_fcmp(FloatCC::NotEqual, type_of(op), builder, state)
+        }
+        Operator::F32x4Lt | Operator::F64x2Lt => {
+            translate_vector_fcmp(FloatCC::LessThan, type_of(op), builder, state)
+        }
+        Operator::F32x4Gt | Operator::F64x2Gt => {
+            translate_vector_fcmp(FloatCC::GreaterThan, type_of(op), builder, state)
+        }
+        Operator::F32x4Le | Operator::F64x2Le => {
+            translate_vector_fcmp(FloatCC::LessThanOrEqual, type_of(op), builder, state)
+        }
+        Operator::F32x4Ge | Operator::F64x2Ge => {
+            translate_vector_fcmp(FloatCC::GreaterThanOrEqual, type_of(op), builder, state)
+        }
+        Operator::V128Not => {
+            let a = optionally_bitcast_vector(state.pop1(), type_of(op), builder);
+            state.push1(builder.ins().bnot(a))
+        }
+        Operator::V128And => {
+            let (a, b) = pop2_with_bitcast(state, type_of(op), builder);
+            state.push1(builder.ins().band(a, b))
+        }
+        Operator::V128Or => {
+            let (a, b) = pop2_with_bitcast(state, type_of(op), builder);
+            state.push1(builder.ins().bor(a, b))
+        }
+        Operator::V128Xor => {
+            let (a, b) = pop2_with_bitcast(state, type_of(op), builder);
+            state.push1(builder.ins().bxor(a, b))
+        }
+        Operator::V128Bitselect => {
+            let (v1, v2, c) = state.pop3();
+            let ty = type_of(op);
+            let v1 = optionally_bitcast_vector(v1, ty, builder);
+            let v2 = optionally_bitcast_vector(v2, ty, builder);
+            let c = optionally_bitcast_vector(c, ty, builder);
+            // Take the bits of `v1` where `c` is set and the bits of `v2` elsewhere.
+            let selected_v1 = builder.ins().band(v1, c);
+            let selected_v2 = builder.ins().band_not(v2, c);
+            state.push1(builder.ins().bor(selected_v1, selected_v2))
+        }
+        Operator::I8x16AnyTrue
+        | Operator::I16x8AnyTrue
+        | Operator::I32x4AnyTrue
+        | Operator::I64x2AnyTrue => {
+            let a = optionally_bitcast_vector(state.pop1(), type_of(op), builder);
+            let bool_result = builder.ins().vany_true(a);
+            state.push1(builder.ins().bint(I32, bool_result))
+        }
+        Operator::I8x16AllTrue
+        | Operator::I16x8AllTrue
+        | Operator::I32x4AllTrue
+        | Operator::I64x2AllTrue => {
+            let a = optionally_bitcast_vector(state.pop1(), type_of(op), builder);
+            let bool_result = builder.ins().vall_true(a);
+            state.push1(builder.ins().bint(I32, bool_result))
+        }
+        Operator::I8x16Neg | Operator::I16x8Neg | Operator::I32x4Neg | Operator::I64x2Neg => {
+            let ty = type_of(op);
+            let a = optionally_bitcast_vector(state.pop1(), ty, builder);
+            let zero_handle = builder.func.dfg.constants.insert(vec![0; 16]);
+            let zero = builder.ins().vconst(ty, zero_handle);
+            state.push1(builder.ins().isub(zero, a))
+        }
+        Operator::I8x16Shl | Operator::I16x8Shl | Operator::I32x4Shl | Operator::I64x2Shl => {
+            let (a, b) = state.pop2();
+            let a = optionally_bitcast_vector(a, type_of(op), builder);
+            state.push1(builder.ins().ishl(a, b))
+        }
+        Operator::I8x16ShrS | Operator::I16x8ShrS | Operator::I32x4ShrS | Operator::I64x2ShrS => {
+            let (a, b) = state.pop2();
+            let a = optionally_bitcast_vector(a, type_of(op), builder);
+            state.push1(builder.ins().sshr(a, b))
+        }
+        Operator::I8x16ShrU | Operator::I16x8ShrU | Operator::I32x4ShrU | Operator::I64x2ShrU => {
+            let (a, b) = state.pop2();
+            let a = optionally_bitcast_vector(a, type_of(op), builder);
+            state.push1(builder.ins().ushr(a, b))
+        }
+        Operator::I8x16Add | Operator::I16x8Add | Operator::I32x4Add | Operator::I64x2Add => {
+            let (a, b) = pop2_with_bitcast(state, type_of(op), builder);
+            state.push1(builder.ins().iadd(a, b))
+        }
+        Operator::I8x16Sub | Operator::I16x8Sub | Operator::I32x4Sub | Operator::I64x2Sub => {
+            let (a, b) = pop2_with_bitcast(state, type_of(op), builder);
+            state.push1(builder.ins().isub(a, b))
+        }
+        Operator::I8x16Mul | Operator::I16x8Mul | Operator::I32x4Mul => {
+            let (a, b) = pop2_with_bitcast(state, type_of(op), builder);
+            state.push1(builder.ins().imul(a, b))
+        }
+        Operator::I8x16AddSaturateS | Operator::I16x8AddSaturateS => {
+            let (a, b) = pop2_with_bitcast(state, type_of(op), builder);
+            state.push1(builder.ins().sadd_sat(a, b))
+        }
+        Operator::I8x16AddSaturateU | Operator::I16x8AddSaturateU => {
+            let (a, b) = pop2_with_bitcast(state, type_of(op), builder);
+            state.push1(builder.ins().uadd_sat(a, b))
+        }
+        Operator::I8x16SubSaturateS | Operator::I16x8SubSaturateS => {
+            let (a, b) = pop2_with_bitcast(state, type_of(op), builder);
+            state.push1(builder.ins().ssub_sat(a, b))
+        }
+        Operator::I8x16SubSaturateU | Operator::I16x8SubSaturateU => {
+            let (a, b) = pop2_with_bitcast(state, type_of(op), builder);
+            state.push1(builder.ins().usub_sat(a, b))
+        }
+        Operator::F32x4Abs | Operator::F64x2Abs => {
+            let a = optionally_bitcast_vector(state.pop1(), type_of(op), builder);
+            state.push1(builder.ins().fabs(a))
+        }
+        Operator::F32x4Neg | Operator::F64x2Neg => {
+            let a = optionally_bitcast_vector(state.pop1(), type_of(op), builder);
+            state.push1(builder.ins().fneg(a))
+        }
+        Operator::F32x4Sqrt | Operator::F64x2Sqrt => {
+            let a = optionally_bitcast_vector(state.pop1(), type_of(op), builder);
+            state.push1(builder.ins().sqrt(a))
+        }
+        Operator::F32x4Add | Operator::F64x2Add => {
+            let (a, b) = pop2_with_bitcast(state, type_of(op), builder);
+            state.push1(builder.ins().fadd(a, b))
+        }
+        Operator::F32x4Sub | Operator::F64x2Sub => {
+            let (a, b) = pop2_with_bitcast(state, type_of(op), builder);
+            state.push1(builder.ins().fsub(a, b))
+        }
+        Operator::F32x4Mul | Operator::F64x2Mul => {
+            let (a, b) = pop2_with_bitcast(state, type_of(op), builder);
+            state.push1(builder.ins().fmul(a, b))
+        }
+        Operator::F32x4Div | Operator::F64x2Div => {
+            let (a, b) = pop2_with_bitcast(state, type_of(op), builder);
+            state.push1(builder.ins().fdiv(a, b))
+        }
+        Operator::F32x4Min | Operator::F64x2Min => {
+            let (a, b) = pop2_with_bitcast(state, type_of(op), builder);
+            state.push1(builder.ins().fmin(a, b))
+        }
+        Operator::F32x4Max | Operator::F64x2Max => {
+            let (a, b) = pop2_with_bitcast(state, type_of(op), builder);
+            state.push1(builder.ins().fmax(a, b))
+        }
+        Operator::I32x4TruncSF32x4Sat => {
+            let a = optionally_bitcast_vector(state.pop1(), type_of(op), builder);
+            state.push1(builder.ins().fcvt_to_sint_sat(I32X4, a))
+        }
+        Operator::I32x4TruncUF32x4Sat => {
+            let a = optionally_bitcast_vector(state.pop1(), type_of(op), builder);
+            state.push1(builder.ins().fcvt_to_uint_sat(I32X4, a))
+        }
+        Operator::I64x2TruncSF64x2Sat => {
+            let a = optionally_bitcast_vector(state.pop1(), type_of(op), builder);
+            state.push1(builder.ins().fcvt_to_sint_sat(I64X2, a))
+        }
+        Operator::I64x2TruncUF64x2Sat => {
+            let a = optionally_bitcast_vector(state.pop1(), type_of(op), builder);
+            state.push1(builder.ins().fcvt_to_uint_sat(I64X2, a))
+        }
+        Operator::F32x4ConvertSI32x4 => {
+            let a = optionally_bitcast_vector(state.pop1(), type_of(op), builder);
+            state.push1(builder.ins().fcvt_from_sint(F32X4, a))
+        }
+        Operator::F32x4ConvertUI32x4 => {
+            let a = optionally_bitcast_vector(state.pop1(), type_of(op), builder);
+            state.push1(builder.ins().fcvt_from_uint(F32X4, a))
+        }
+        Operator::F64x2ConvertSI64x2 => {
+            let a = optionally_bitcast_vector(state.pop1(), type_of(op), builder);
+            state.push1(builder.ins().fcvt_from_sint(F64X2, a))
+        }
+        Operator::F64x2ConvertUI64x2 => {
+            let a = optionally_bitcast_vector(state.pop1(), type_of(op), builder);
+            state.push1(builder.ins().fcvt_from_uint(F64X2, a))
+        }
+    };
+    Ok(())
+}
+
+// Clippy warns us of some fields we are deliberately ignoring
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::unneeded_field_pattern))]
+/// Deals with a Wasm instruction located in an unreachable portion of the code. Most of them
+/// are dropped but special ones like `End` or `Else` signal the potential end of the unreachable
+/// portion so the translation state must be updated accordingly.
+fn translate_unreachable_operator(
+    op: &Operator,
+    builder: &mut FunctionBuilder,
+    state: &mut TranslationState,
+) {
+    match *op {
+        Operator::If { ty: _ } => {
+            // Push a placeholder control stack entry. The if isn't reachable,
+            // so we don't have any branches anywhere.
+            state.push_if(ir::Inst::reserved_value(), ir::Ebb::reserved_value(), 0);
+        }
+        Operator::Loop { ty: _ } | Operator::Block { ty: _ } => {
+            state.push_block(ir::Ebb::reserved_value(), 0);
+        }
+        Operator::Else => {
+            let i = state.control_stack.len() - 1;
+            if let ControlStackFrame::If {
+                branch_inst,
+                ref mut reachable_from_top,
+                ..
+            } = state.control_stack[i]
+            {
+                if *reachable_from_top {
+                    // We have a branch from the top of the if to the else.
+                    state.reachable = true;
+                    // And because there's an else, there can no longer be a
+                    // branch from the top directly to the end.
+                    *reachable_from_top = false;
+
+                    // We change the target of the branch instruction
+                    let else_ebb = builder.create_ebb();
+                    builder.change_jump_destination(branch_inst, else_ebb);
+                    builder.seal_block(else_ebb);
+                    builder.switch_to_block(else_ebb);
+                }
+            }
+        }
+        Operator::End => {
+            let stack = &mut state.stack;
+            let control_stack = &mut state.control_stack;
+            let frame = control_stack.pop().unwrap();
+
+            // Now we have to split off the stack the values not used
+            // by unreachable code that hasn't been translated
+            stack.truncate(frame.original_stack_size());
+
+            let reachable_anyway = match frame {
+                // If it is a loop we also have to seal the body loop block
+                ControlStackFrame::Loop { header, .. } => {
+                    builder.seal_block(header);
+                    // And loops can't have branches to the end.
+                    false
+                }
+                ControlStackFrame::If {
+                    reachable_from_top, ..
+                } => {
+                    // A reachable if without an else has a branch from the top
+                    // directly to the bottom.
+                    reachable_from_top
+                }
+                // All other control constructs are already handled.
+                _ => false,
+            };
+
+            if frame.exit_is_branched_to() || reachable_anyway {
                 builder.switch_to_block(frame.following_code());
                 builder.seal_block(frame.following_code());
 
@@ -1267,6 +2138,148 @@ fn translate_store<FE: FuncEnvironment + ?Sized>(
     Ok(())
 }
 
+/// Compute the native address of an atomic access of type `access_ty` at `offset` from the
+/// address on top of the value stack, which is popped.
+///
+/// This traps if the effective address is not naturally aligned or out of bounds.
+fn translate_atomic_addr<FE: FuncEnvironment + ?Sized>(
+    heap: ir::Heap,
+    offset: u32,
+    access_ty: Type,
+    builder: &mut FunctionBuilder,
+    state: &mut TranslationState,
+    environ: &mut FE,
+) -> WasmResult<ir::Value> {
+    let addr32 = state.pop1();
+    let (base, native_offset) =
+        get_heap_addr(heap, addr32, offset, environ.pointer_type(), builder);
+
+    let access_size = i64::from(access_ty.bytes());
+    if access_size > 1 {
+        // The effective address is computed modulo 2^32 here, which doesn't affect its low bits.
+        let effective = builder.ins().iadd_imm(addr32, i64::from(offset));
+        let misalignment = builder.ins().band_imm(effective, access_size - 1);
+        builder
+            .ins()
+            .trapnz(misalignment, ir::TrapCode::HeapMisaligned);
+    }
+
+    // `atomic_cas` doesn't take an offset, so fold it into the address for all atomic accesses.
+    if native_offset == 0 {
+        Ok(base)
+    } else {
+        Ok(builder.ins().iadd_imm(base, i64::from(native_offset)))
+    }
+}
+
+/// The flags of an atomic access whose address was computed by `translate_atomic_addr`.
+fn atomic_flags() -> MemFlags {
+    let mut flags = MemFlags::new();
+    flags.set_aligned();
+    flags
+}
+
+/// Translate an atomic load of type `access_ty`, zero-extended to `widened_ty`.
+fn translate_atomic_load<FE: FuncEnvironment + ?Sized>(
+    offset: u32,
+    widened_ty: Type,
+    access_ty: Type,
+    builder: &mut FunctionBuilder,
+    state: &mut TranslationState,
+    environ: &mut FE,
+) -> WasmResult<()> {
+    // We don't yet support multiple linear memories.
+    let heap = state.get_heap(&mut builder.func, 0, environ)?;
+    let addr = translate_atomic_addr(heap, offset, access_ty, builder, state, environ)?;
+    let mut val = builder
+        .ins()
+        .atomic_load(access_ty, atomic_flags(), addr, 0);
+    if access_ty != widened_ty {
+        val = builder.ins().uextend(widened_ty, val);
+    }
+    state.push1(val);
+    Ok(())
+}
+
+/// Translate an atomic store of the low `access_ty` bits of a value.
+fn translate_atomic_store<FE: FuncEnvironment + ?Sized>(
+    offset: u32,
+    access_ty: Type,
+    builder: &mut FunctionBuilder,
+    state: &mut TranslationState,
+    environ: &mut FE,
+) -> WasmResult<()> {
+    let mut val = state.pop1();
+    if builder.func.dfg.value_type(val) != access_ty {
+        val = builder.ins().ireduce(access_ty, val);
+    }
+
+    // We don't yet support multiple linear memories.
+    let heap = state.get_heap(&mut builder.func, 0, environ)?;
+    let addr = translate_atomic_addr(heap, offset, access_ty, builder, state, environ)?;
+    builder.ins().atomic_store(atomic_flags(), val, addr, 0);
+    Ok(())
+}
+
+/// Translate an atomic read-modify-write instruction of type `access_ty`. The old value is
+/// zero-extended to `widened_ty`.
+fn translate_atomic_rmw<FE: FuncEnvironment + ?Sized>(
+    offset: u32,
+    opcode: ir::Opcode,
+    widened_ty: Type,
+    access_ty: Type,
+    builder: &mut FunctionBuilder,
+    state: &mut TranslationState,
+    environ: &mut FE,
+) -> WasmResult<()> {
+    let mut val = state.pop1();
+    if access_ty != widened_ty {
+        val = builder.ins().ireduce(access_ty, val);
+    }
+
+    // We don't yet support multiple linear memories.
+    let heap = state.get_heap(&mut builder.func, 0, environ)?;
+    let addr = translate_atomic_addr(heap, offset, access_ty, builder, state, environ)?;
+    let (inst, dfg) = builder
+        .ins()
+        .Store(opcode, access_ty, atomic_flags(), 0.into(), val, addr);
+    let mut old = dfg.first_result(inst);
+    if access_ty != widened_ty {
+        old = builder.ins().uextend(widened_ty, old);
+    }
+    state.push1(old);
+    Ok(())
+}
+
+/// Translate an atomic compare-and-exchange instruction of type `access_ty`. The old value is
+/// zero-extended to `widened_ty`.
+fn translate_atomic_cas<FE: FuncEnvironment + ?Sized>(
+    offset: u32,
+    widened_ty: Type,
+    access_ty: Type,
+    builder: &mut FunctionBuilder,
+    state: &mut TranslationState,
+    environ: &mut FE,
+) -> WasmResult<()> {
+    let (mut expected, mut replacement) = state.pop2();
+    if access_ty != widened_ty {
+        expected = builder.ins().ireduce(access_ty, expected);
+        replacement = builder.ins().ireduce(access_ty, replacement);
+    }
+
+    // We don't yet support multiple linear memories.
+    let heap = state.get_heap(&mut builder.func, 0, environ)?;
+    let addr = translate_atomic_addr(heap, offset, access_ty, builder, state, environ)?;
+    let mut old = builder
+        .ins()
+        .atomic_cas(atomic_flags(), expected, replacement, addr);
+    if access_ty != widened_ty {
+        old = builder.ins().uextend(widened_ty, old);
+    }
+    state.push1(old);
+    Ok(())
+}
+
 fn translate_icmp(cc: IntCC, builder: &mut FunctionBuilder, state: &mut TranslationState) {
     let (arg0, arg1) = state.pop2();
     let val = builder.ins().icmp(cc, arg0, arg1);
@@ -1279,6 +2292,29 @@ fn translate_fcmp(cc: FloatCC, builder: &mut FunctionBuilder, state: &mut Transl
     state.push1(builder.ins().bint(I32, val));
 }
 
+fn translate_vector_icmp(
+    cc: IntCC,
+    needed_type: Type,
+    builder: &mut FunctionBuilder,
+    state: &mut TranslationState,
+) {
+    let (a, b) = pop2_with_bitcast(state, needed_type, builder);
+    // The boolean lanes are all ones or all zeros, which is the wasm representation of the result.
+    let bool_result = builder.ins().icmp(cc, a, b);
+    state.push1(builder.ins().raw_bitcast(needed_type, bool_result))
+}
+
+fn translate_vector_fcmp(
+    cc: FloatCC,
+    needed_type: Type,
+    builder: &mut FunctionBuilder,
+    state: &mut TranslationState,
+) {
+    let (a, b) = pop2_with_bitcast(state, needed_type, builder);
+    let bool_result = builder.ins().fcmp(cc, a, b);
+    state.push1(builder.ins().raw_bitcast(needed_type, bool_result))
+}
+
 fn translate_br_if(
     relative_depth: u32,
     builder: &mut FunctionBuilder,
@@ -1331,6 +2367,8 @@ fn type_of(operator: &Operator) -> Type {
         | Operator::V128Bitselect => I8X16, // default type representing V128
 
         Operator::V8x16Shuffle { .. }
+        | Operator::V8x16Swizzle
+        | Operator::I8x16LoadSplat { .. }
         | Operator::I8x16Splat
         | Operator::I8x16ExtractLaneS { .. }
         | Operator::I8x16ExtractLaneU { .. }
@@ -1360,6 +2398,7 @@ fn type_of(operator: &Operator) -> Type {
         | Operator::I8x16Mul => I8X16,
 
         Operator::I16x8Splat
+        | Operator::I16x8LoadSplat { .. }
         | Operator::I16x8ExtractLaneS { .. }
         | Operator::I16x8ExtractLaneU { .. }
         | Operator::I16x8ReplaceLane { .. }
@@ -1388,6 +2427,7 @@ fn type_of(operator: &Operator) -> Type {
         | Operator::I16x8Mul => I16X8,
 
         Operator::I32x4Splat
+        | Operator::I32x4LoadSplat { .. }
         | Operator::I32x4ExtractLane { .. }
         | Operator::I32x4ReplaceLane { .. }
         | Operator::I32x4Eq
@@ -1413,6 +2453,7 @@ fn type_of(operator: &Operator) -> Type {
         | Operator::F32x4ConvertUI32x4 => I32X4,
 
         Operator::I64x2Splat
+        | Operator::I64x2LoadSplat { .. }
         | Operator::I64x2ExtractLane { .. }
         | Operator::I64x2ReplaceLane { .. }
         | Operator::I64x2Neg
@@ -1488,3 +2529,15 @@ fn optionally_bitcast_vector(
         value
     }
 }
+
+/// Pop two values from the stack and convert both to `needed_type` if necessary.
+fn pop2_with_bitcast(
+    state: &mut TranslationState,
+    needed_type: Type,
+    builder: &mut FunctionBuilder,
+) -> (Value, Value) {
+    let (a, b) = state.pop2();
+    let bitcast_a = optionally_bitcast_vector(a, needed_type, builder);
+    let bitcast_b = optionally_bitcast_vector(b, needed_type, builder);
+    (bitcast_a, bitcast_b)
+}
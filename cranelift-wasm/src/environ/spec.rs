@@ -8,7 +8,8 @@
 
 use crate::state::VisibleTranslationState;
 use crate::translation_utils::{
-    FuncIndex, Global, GlobalIndex, Memory, MemoryIndex, SignatureIndex, Table, TableIndex,
+    DataIndex, ElemIndex, FuncIndex, Global, GlobalIndex, Memory, MemoryIndex, SignatureIndex,
+    Table, TableIndex,
 };
 use core::convert::From;
 use cranelift_codegen::cursor::FuncCursor;
@@ -266,6 +267,194 @@ pub trait FuncEnvironment {
         heap: ir::Heap,
     ) -> WasmResult<ir::Value>;
 
+    /// Translates an `i32.atomic.wait` or `i64.atomic.wait` WebAssembly instruction.
+    ///
+    /// The `index` provided identifies the linear memory containing the value to wait on, and
+    /// `heap` is the heap reference returned by `make_heap` for the same index. The `addr` value
+    /// is the native address of the value; it has already been bounds checked and is known to be
+    /// naturally aligned.
+    ///
+    /// The `expected` value is an `i32` or `i64` depending on the instruction, and `timeout` is
+    /// the relative timeout in nanoseconds as an `i64`, where a negative value means no timeout.
+    ///
+    /// Returns 0 ("ok"), 1 ("not-equal") or 2 ("timed-out") as an `i32`.
+    fn translate_atomic_wait(
+        &mut self,
+        pos: FuncCursor,
+        index: MemoryIndex,
+        heap: ir::Heap,
+        addr: ir::Value,
+        expected: ir::Value,
+        timeout: ir::Value,
+    ) -> WasmResult<ir::Value>;
+
+    /// Translates an `atomic.notify` WebAssembly instruction.
+    ///
+    /// The `index`, `heap` and `addr` arguments are as for `translate_atomic_wait`. The `count`
+    /// value is the maximum number of waiters to wake up, as an `i32`.
+    ///
+    /// Returns the number of waiters that were woken up as an `i32`.
+    fn translate_atomic_notify(
+        &mut self,
+        pos: FuncCursor,
+        index: MemoryIndex,
+        heap: ir::Heap,
+        addr: ir::Value,
+        count: ir::Value,
+    ) -> WasmResult<ir::Value>;
+
+    /// Translates a `memory.copy` WebAssembly instruction.
+    ///
+    /// The `index` provided identifies the linear memory to copy within, and `heap` is the heap
+    /// reference returned by `make_heap` for the same index.
+    ///
+    /// The `dst`, `src` and `len` values are the `i32` operands of the instruction.
+    fn translate_memory_copy(
+        &mut self,
+        pos: FuncCursor,
+        index: MemoryIndex,
+        heap: ir::Heap,
+        dst: ir::Value,
+        src: ir::Value,
+        len: ir::Value,
+    ) -> WasmResult<()>;
+
+    /// Translates a `memory.fill` WebAssembly instruction.
+    ///
+    /// The `index` provided identifies the linear memory to fill, and `heap` is the heap reference
+    /// returned by `make_heap` for the same index.
+    ///
+    /// The `dst`, `val` and `len` values are the `i32` operands of the instruction.
+    fn translate_memory_fill(
+        &mut self,
+        pos: FuncCursor,
+        index: MemoryIndex,
+        heap: ir::Heap,
+        dst: ir::Value,
+        val: ir::Value,
+        len: ir::Value,
+    ) -> WasmResult<()>;
+
+    /// Translates a `memory.init` WebAssembly instruction.
+    ///
+    /// The `index` provided identifies the linear memory to initialize, and `heap` is the heap
+    /// reference returned by `make_heap` for the same index. The `seg_index` identifies the
+    /// passive data segment to copy from.
+    ///
+    /// The `dst`, `src` and `len` values are the `i32` operands of the instruction.
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::too_many_arguments))]
+    fn translate_memory_init(
+        &mut self,
+        pos: FuncCursor,
+        index: MemoryIndex,
+        heap: ir::Heap,
+        seg_index: DataIndex,
+        dst: ir::Value,
+        src: ir::Value,
+        len: ir::Value,
+    ) -> WasmResult<()>;
+
+    /// Translates a `data.drop` WebAssembly instruction.
+    fn translate_data_drop(&mut self, pos: FuncCursor, seg_index: DataIndex) -> WasmResult<()>;
+
+    /// Translates a `table.size` WebAssembly instruction.
+    ///
+    /// The `index` provided identifies the table to query, and `table` is the table reference
+    /// returned by `make_table` for the same index.
+    ///
+    /// Returns the number of elements in the table as an `i32`.
+    fn translate_table_size(
+        &mut self,
+        pos: FuncCursor,
+        index: TableIndex,
+        table: ir::Table,
+    ) -> WasmResult<ir::Value>;
+
+    /// Translates a `table.grow` WebAssembly instruction.
+    ///
+    /// The `index` provided identifies the table to grow, and `table` is the table reference
+    /// returned by `make_table` for the same index. The table is grown by `delta` elements, which
+    /// are initialized to `init_value`.
+    ///
+    /// Returns the old size of the table, or -1 if it could not be grown, as an `i32`.
+    fn translate_table_grow(
+        &mut self,
+        pos: FuncCursor,
+        index: TableIndex,
+        table: ir::Table,
+        delta: ir::Value,
+        init_value: ir::Value,
+    ) -> WasmResult<ir::Value>;
+
+    /// Translates a `table.get` WebAssembly instruction.
+    ///
+    /// The `index` provided identifies the table to read from, and `table` is the table reference
+    /// returned by `make_table` for the same index.
+    ///
+    /// Returns the reference stored at `elem_index`.
+    fn translate_table_get(
+        &mut self,
+        pos: FuncCursor,
+        index: TableIndex,
+        table: ir::Table,
+        elem_index: ir::Value,
+    ) -> WasmResult<ir::Value>;
+
+    /// Translates a `table.set` WebAssembly instruction.
+    ///
+    /// The `index` provided identifies the table to write to, and `table` is the table reference
+    /// returned by `make_table` for the same index. The reference `value` is stored at
+    /// `elem_index`.
+    fn translate_table_set(
+        &mut self,
+        pos: FuncCursor,
+        index: TableIndex,
+        table: ir::Table,
+        value: ir::Value,
+        elem_index: ir::Value,
+    ) -> WasmResult<()>;
+
+    /// Translates a `table.copy` WebAssembly instruction.
+    ///
+    /// The table indexes identify the tables to copy between, and the table references are the
+    /// ones returned by `make_table` for the same indexes.
+    ///
+    /// The `dst`, `src` and `len` values are the `i32` operands of the instruction.
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::too_many_arguments))]
+    fn translate_table_copy(
+        &mut self,
+        pos: FuncCursor,
+        dst_table_index: TableIndex,
+        dst_table: ir::Table,
+        src_table_index: TableIndex,
+        src_table: ir::Table,
+        dst: ir::Value,
+        src: ir::Value,
+        len: ir::Value,
+    ) -> WasmResult<()>;
+
+    /// Translates a `table.init` WebAssembly instruction.
+    ///
+    /// The `table_index` provided identifies the table to initialize, and `table` is the table
+    /// reference returned by `make_table` for the same index. The `seg_index` identifies the
+    /// passive element segment to copy from.
+    ///
+    /// The `dst`, `src` and `len` values are the `i32` operands of the instruction.
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::too_many_arguments))]
+    fn translate_table_init(
+        &mut self,
+        pos: FuncCursor,
+        seg_index: ElemIndex,
+        table_index: TableIndex,
+        table: ir::Table,
+        dst: ir::Value,
+        src: ir::Value,
+        len: ir::Value,
+    ) -> WasmResult<()>;
+
+    /// Translates an `elem.drop` WebAssembly instruction.
+    fn translate_elem_drop(&mut self, pos: FuncCursor, seg_index: ElemIndex) -> WasmResult<()>;
+
     /// Emit code at the beginning of every wasm loop.
     ///
     /// This can be used to insert explicit interrupt or safepoint checking at
@@ -443,6 +632,15 @@ pub trait ModuleEnvironment<'data> {
         elements: Box<[FuncIndex]>,
     ) -> WasmResult<()>;
 
+    /// Declares a passive element segment, which is only used by `table.init` instructions.
+    ///
+    /// The `elem_index` is the index of the segment among all the element segments of the module.
+    fn declare_passive_element(
+        &mut self,
+        elem_index: ElemIndex,
+        elements: Box<[FuncIndex]>,
+    ) -> WasmResult<()>;
+
     /// Provides the contents of a function body.
     ///
     /// Note there's no `reserve_function_bodies` function because the number of
@@ -468,6 +666,11 @@ pub trait ModuleEnvironment<'data> {
         data: &'data [u8],
     ) -> WasmResult<()>;
 
+    /// Declares a passive data segment, which is only used by `memory.init` instructions.
+    ///
+    /// The `data_index` is the index of the segment among all the data segments of the module.
+    fn declare_passive_data(&mut self, data_index: DataIndex, data: &'data [u8]) -> WasmResult<()>;
+
     /// Indicates that a custom section has been found in the wasm file
     fn custom_section(&mut self, name: &'data str, data: &'data [u8]) -> WasmResult<()> {
         drop((name, data));
@@ -9,8 +9,8 @@
 //! interpreted on the fly.
 use crate::environ::{ModuleEnvironment, WasmResult};
 use crate::translation_utils::{
-    tabletype_to_type, type_to_type, FuncIndex, Global, GlobalIndex, GlobalInit, Memory,
-    MemoryIndex, SignatureIndex, Table, TableElementType, TableIndex,
+    tabletype_to_type, type_to_type, DataIndex, ElemIndex, FuncIndex, Global, GlobalIndex,
+    GlobalInit, Memory, MemoryIndex, SignatureIndex, Table, TableElementType, TableIndex,
 };
 use crate::wasm_unsupported;
 use core::convert::TryFrom;
@@ -266,8 +266,14 @@ pub fn parse_element_section<'data>(
 ) -> WasmResult<()> {
     environ.reserve_table_elements(elements.get_count())?;
 
-    for entry in elements {
+    for (index, entry) in elements.into_iter().enumerate() {
         let Element { kind, items } = entry?;
+        let items_reader = items.get_items_reader()?;
+        let mut elems = Vec::with_capacity(usize::try_from(items_reader.get_count()).unwrap());
+        for item in items_reader {
+            let x = item?;
+            elems.push(FuncIndex::from_u32(x));
+        }
         if let ElementKind::Active {
             table_index,
             init_expr,
@@ -283,12 +289,6 @@ pub fn parse_element_section<'data>(
                     wasm_unsupported!("unsupported init expr in element section: {:?}", s);
                 }
             };
-            let items_reader = items.get_items_reader()?;
-            let mut elems = Vec::with_capacity(usize::try_from(items_reader.get_count()).unwrap());
-            for item in items_reader {
-                let x = item?;
-                elems.push(FuncIndex::from_u32(x));
-            }
             environ.declare_table_elements(
                 TableIndex::from_u32(table_index),
                 base,
@@ -296,7 +296,7 @@ pub fn parse_element_section<'data>(
                 elems.into_boxed_slice(),
             )?
         } else {
-            wasm_unsupported!("unsupported passive elements section: {:?}", kind);
+            environ.declare_passive_element(ElemIndex::new(index), elems.into_boxed_slice())?
         }
     }
     Ok(())
@@ -323,7 +323,7 @@ pub fn parse_data_section<'data>(
 ) -> WasmResult<()> {
     environ.reserve_data_initializers(data.get_count())?;
 
-    for entry in data {
+    for (index, entry) in data.into_iter().enumerate() {
         let Data { kind, data } = entry?;
         if let DataKind::Active {
             memory_index,
@@ -345,7 +345,7 @@ pub fn parse_data_section<'data>(
                 data,
             )?;
         } else {
-            wasm_unsupported!("unsupported passive data section: {:?}", kind);
+            environ.declare_passive_data(DataIndex::new(index), data)?;
         }
     }
 
@@ -0,0 +1,16 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate cranelift_codegen;
+
+use cranelift_codegen::binary_format::{decode_function, encode_function};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok((func, info)) = decode_function(data) {
+        let text = func.display(None).to_string();
+        let bytes = encode_function(&func, info.as_ref());
+        let (decoded, decoded_info) = decode_function(&bytes).unwrap();
+        assert_eq!(decoded.display(None).to_string(), text);
+        assert_eq!(decoded_info, info);
+    }
+});
@@ -0,0 +1,19 @@
+#![no_main]
+
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate cranelift_codegen;
+extern crate cranelift_filetests;
+extern crate cranelift_native;
+
+use cranelift_codegen::settings;
+use cranelift_filetests::{check_opt_levels, FunctionGenerator};
+
+fuzz_target!(|data: &[u8]| {
+    let flags = settings::Flags::new(settings::builder());
+    let isa = cranelift_native::builder().unwrap().finish(flags);
+    let (function, runs) = FunctionGenerator::new(data).generate(isa.default_call_conv());
+    if let Err(message) = check_opt_levels(&function, &runs) {
+        panic!("{}\n{}", message, function.display(isa.as_ref()));
+    }
+});
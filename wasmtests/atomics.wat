@@ -0,0 +1,52 @@
+(module
+  (memory 1 1 shared)
+
+  (func $load_store (param i32 i64) (result i64)
+    local.get 0
+    local.get 1
+    i64.atomic.store offset=8
+    local.get 0
+    i32.atomic.load8_u
+    drop
+    local.get 0
+    i64.atomic.load offset=8
+  )
+
+  (func $rmw (param i32 i32) (result i32)
+    local.get 0
+    local.get 1
+    i32.atomic.rmw.add
+    local.get 0
+    local.get 1
+    i32.atomic.rmw16.sub_u offset=2
+    i32.add
+    local.get 0
+    i64.const 1
+    i64.atomic.rmw8.xor_u
+    i32.wrap_i64
+    i32.add
+  )
+
+  (func $cmpxchg (param i32 i64 i64) (result i64)
+    local.get 0
+    local.get 1
+    local.get 2
+    i64.atomic.rmw32.cmpxchg_u
+    local.get 0
+    local.get 1
+    local.get 2
+    i64.atomic.rmw.cmpxchg
+    i64.add
+  )
+
+  (func $wait_notify (param i32 i32 i64) (result i32)
+    local.get 0
+    local.get 1
+    local.get 2
+    i32.atomic.wait
+    local.get 0
+    i32.const 1
+    atomic.notify
+    i32.add
+  )
+)
@@ -0,0 +1,15 @@
+(module
+  (memory 1)
+  (table 2 anyfunc)
+  (func $f)
+  (func $main
+    (memory.init 0 (i32.const 0) (i32.const 0) (i32.const 4))
+    (data.drop 0)
+    (memory.copy (i32.const 8) (i32.const 0) (i32.const 4))
+    (memory.fill (i32.const 16) (i32.const 0xff) (i32.const 4))
+    (table.copy (i32.const 1) (i32.const 0) (i32.const 1))
+  )
+  (start $main)
+  (elem (i32.const 0) $f)
+  (data passive "0000")
+)
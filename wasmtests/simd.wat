@@ -1,4 +1,6 @@
 (module
+  (memory 1)
+
   (func $test_splat (result i32)
     i32.const 42
     i32x4.splat
@@ -17,7 +19,47 @@
     i32x4.extract_lane 3
   )
 
+  (func $test_load_store (param i32)
+    local.get 0
+    local.get 0
+    v128.load offset=16
+    i32.const 3
+    i32x4.shl
+    v128.store
+  )
+
+  (func $test_shuffle (param v128 v128) (result v128)
+    local.get 0
+    local.get 1
+    v8x16.shuffle 0 17 2 19 4 21 6 23 8 25 10 27 12 29 14 31
+  )
+
+  (func $test_compare (param v128 v128) (result i32)
+    local.get 0
+    local.get 1
+    i16x8.lt_u
+    local.get 0
+    local.get 1
+    f32x4.ge
+    v128.and
+    i8x16.any_true
+  )
+
+  (func $test_arith (param v128 v128) (result i32)
+    local.get 0
+    local.get 1
+    i8x16.add_saturate_u
+    local.get 1
+    i8x16.sub
+    i8x16.neg
+    i8x16.extract_lane_s 15
+  )
+
   (export "test_splat" (func $test_splat))
   (export "test_insert_lane" (func $test_insert_lane))
   (export "test_const" (func $test_const))
+  (export "test_load_store" (func $test_load_store))
+  (export "test_shuffle" (func $test_shuffle))
+  (export "test_compare" (func $test_compare))
+  (export "test_arith" (func $test_arith))
 )